@@ -1,11 +1,45 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use crate::lib::cli_interface::self_update::ReleaseChannel;
+use crate::lib::cpp_indexer::adaptive_depth::{DEFAULT_PROMOTE_HITS, DEFAULT_WINDOW_DAYS};
+use crate::lib::cpp_indexer::dialect::{DialectRule, DialectRules};
+use crate::lib::cpp_indexer::symbol_filter::SymbolFilter;
+use crate::lib::cpp_indexer::vendored::VendoredDedup;
+use crate::lib::cpp_indexer::vfs::pattern_matches;
+use crate::lib::mcp_server::failover::DEFAULT_FAILOVER_TIMEOUT_SECS;
+use crate::lib::mcp_server::freshness::{StaleCheck, DEFAULT_STALE_REINDEX_MAX_KB};
+use crate::lib::mcp_server::scheduler::ScheduledTask;
+use crate::lib::storage::embeddings::EmbeddingConfig;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Name of the project configuration file at the root of a codebase
+pub const PROJECT_CONFIG_FILE: &str = ".cppindex.toml";
+
+/// Environment variable naming a configuration file to read instead of the user's
+pub const CONFIG_ENV_VAR: &str = "CPP_INDEX_CONFIG";
+
+/// Directory of the user's configuration and data directories this tool uses
+const APP_DIR: &str = "cpp-index-mcp";
+
+/// Database of the storage location used by commands that name no index
+const SHARED_DATABASE_FILE: &str = "cpp-index.db";
+
+/// Options whose value is a path, resolved against the project root
+const PATH_FLAGS: &[&str] = &["-I", "-isystem", "-iquote", "-include"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    /// Database file path
-    pub database_path: PathBuf,
+    /// Database every command uses, instead of one per index in the storage location
+    pub database_path: Option<PathBuf>,
+
+    /// Where index databases are kept
+    pub storage: StorageConfig,
+
+    /// Indices the server answers for from databases of their own, by index name
+    pub index_databases: BTreeMap<String, PathBuf>,
     
     /// Log level
     pub log_level: String,
@@ -14,6 +48,9 @@ pub struct Config {
     pub max_concurrent_tasks: usize,
     
     /// Memory limit for indexing operations (in MB)
+    ///
+    /// The incremental indexer moves its file state to `spill_path()` past
+    /// this limit; 0 keeps everything in memory.
     pub memory_limit_mb: usize,
     
     /// File extensions to index
@@ -21,12 +58,130 @@ pub struct Config {
     
     /// Directories to ignore during indexing
     pub ignore_patterns: Vec<String>,
+
+    /// Seconds between scheduled WAL checkpoints (0 = only size triggered)
+    pub wal_checkpoint_interval_seconds: u64,
+
+    /// WAL size in MB that forces a truncating checkpoint (0 = unlimited)
+    pub wal_size_limit_mb: u64,
+
+    /// Repository queries slower than this are logged (in ms, 0 = disabled)
+    pub slow_query_threshold_ms: u64,
+
+    /// Index only one copy of byte-identical vendored directories
+    pub dedupe_vendored_copies: bool,
+
+    /// Smallest directory, in source files, treated as a vendored copy
+    pub vendored_min_files: usize,
+
+    /// Directories whose copy is indexed when duplicates are found (e.g. "third_party")
+    pub vendored_preferred_roots: Vec<String>,
+
+    /// Encrypt the database at rest (needs a build with the `encryption` feature)
+    pub encrypt_database: bool,
+
+    /// Command printing the database key, e.g. a keyring lookup (CPP_INDEX_DB_KEY takes precedence)
+    pub encryption_key_command: Option<String>,
+
+    /// Collect anonymous usage statistics (opt-in, off by default)
+    pub telemetry_enabled: bool,
+
+    /// URL spooled telemetry reports are posted to by `telemetry send`
+    pub telemetry_endpoint: Option<String>,
+
+    /// Where telemetry reports are spooled (default: next to the database)
+    pub telemetry_spool_path: Option<PathBuf>,
+
+    /// Releases `self-update` installs: stable, or nightly to include prereleases
+    pub update_channel: ReleaseChannel,
+
+    /// Minisign public key release checksums must be signed with
+    pub update_public_key: Option<String>,
+
+    /// Maintenance the server runs on its own, as `[[schedule]]` tables of task and cron expression
+    pub schedule: Vec<ScheduledTask>,
+
+    /// Heartbeat file a failover primary writes and its replica reads (default: next to the database)
+    ///
+    /// A replica serving a streamed copy points this at the primary's file.
+    pub heartbeat_path: Option<PathBuf>,
+
+    /// Seconds without a primary heartbeat before its replica takes over
+    pub failover_timeout_seconds: u64,
+
+    /// Backend embedding symbols for semantic_search, e.g. `{ backend = "hashing", dimensions = 512 }` (default: disabled)
+    pub embeddings: EmbeddingConfig,
+
+    /// Directory of the sqlite-vss `vector0` and `vss0` extensions, for approximate nearest-neighbour search
+    ///
+    /// Without it semantic_search compares the query with every vector.
+    pub embeddings_vss_path: Option<PathBuf>,
+
+    /// Learn indexing depth per directory from the paths clients query, and index at that depth
+    pub adaptive_depth: bool,
+
+    /// Queries within the window that promote a directory to deep indexing
+    pub adaptive_depth_promote_hits: u64,
+
+    /// Days of query history adaptive depth looks at
+    pub adaptive_depth_window_days: u32,
+
+    /// Whether server calls naming a file check it against the index: off, flag, or reindex stale files inline
+    pub stale_check: StaleCheck,
+
+    /// Largest stale file, in KB, the server re-indexes inline; larger ones are only flagged
+    pub stale_reindex_max_kb: u64,
+
+    /// Conventions of the codebase being indexed, from its `.cppindex.toml`
+    #[serde(skip)]
+    pub project: Option<ProjectConfig>,
+}
+
+/// Location of index databases, the `[storage]` table of the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Directory holding a `<name>.db` per index (default: `indices` in the user's data directory)
+    pub path: Option<PathBuf>,
+}
+
+/// Project conventions read from `.cppindex.toml` at the root of a codebase
+///
+/// ```toml
+/// include = ["src/", "include/"]
+/// exclude = ["*.pb.h", "src/generated/"]
+/// compile_flags = ["-Iinclude", "-DPLATFORM_LINUX"]
+/// compile_commands = "build/compile_commands.json"
+/// database_path = ".cppindex/index.db"
+///
+/// [symbols]
+/// exclude_scopes = ["*::detail"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Gitignore-style patterns of files to index; empty indexes every source file
+    pub include: Vec<String>,
+    /// Patterns of files to skip, applied after `include`
+    pub exclude: Vec<String>,
+    /// Compiler flags added to the defaults; relative include paths are resolved against the project root
+    pub compile_flags: Vec<String>,
+    /// compile_commands.json, relative to the project root
+    pub compile_commands: Option<PathBuf>,
+    /// Symbols to leave out of the index
+    pub symbols: SymbolFilter,
+    /// Parse options of files matching a pattern, as `[[dialects]]` tables
+    pub dialects: Vec<DialectRule>,
+    /// Database the index is stored in, relative to the project root
+    pub database_path: Option<PathBuf>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            database_path: PathBuf::from("./cpp-index.db"),
+            database_path: None,
+            storage: StorageConfig::default(),
+            index_databases: BTreeMap::new(),
             log_level: "info".to_string(),
             max_concurrent_tasks: num_cpus::get(),
             memory_limit_mb: 1024,
@@ -36,11 +191,17 @@ impl Default for Config {
                 ".cxx".to_string(),
                 ".c++".to_string(),
                 ".c".to_string(),
+                ".m".to_string(),
+                ".mm".to_string(),
+                ".cu".to_string(),
+                ".ixx".to_string(),
+                ".cppm".to_string(),
                 ".h".to_string(),
                 ".hpp".to_string(),
                 ".hh".to_string(),
                 ".hxx".to_string(),
                 ".h++".to_string(),
+                ".cuh".to_string(),
             ],
             ignore_patterns: vec![
                 "build/".to_string(),
@@ -53,22 +214,454 @@ impl Default for Config {
                 "*.dll".to_string(),
                 "*.dylib".to_string(),
             ],
+            wal_checkpoint_interval_seconds: 300,
+            wal_size_limit_mb: 64,
+            slow_query_threshold_ms: 200,
+            dedupe_vendored_copies: true,
+            vendored_min_files: 3,
+            vendored_preferred_roots: vec!["third_party".to_string()],
+            encrypt_database: false,
+            encryption_key_command: None,
+            telemetry_enabled: false,
+            telemetry_endpoint: None,
+            telemetry_spool_path: None,
+            update_channel: ReleaseChannel::Stable,
+            update_public_key: None,
+            schedule: Vec::new(),
+            heartbeat_path: None,
+            failover_timeout_seconds: DEFAULT_FAILOVER_TIMEOUT_SECS,
+            embeddings: EmbeddingConfig::Disabled,
+            embeddings_vss_path: None,
+            adaptive_depth: false,
+            adaptive_depth_promote_hits: DEFAULT_PROMOTE_HITS,
+            adaptive_depth_window_days: DEFAULT_WINDOW_DAYS,
+            stale_check: StaleCheck::Off,
+            stale_reindex_max_kb: DEFAULT_STALE_REINDEX_MAX_KB,
+            project: None,
         }
     }
 }
 
 impl Config {
-    /// Load configuration from file or create default
-    #[allow(dead_code)]
+    /// Database commands that name no index use, defaulting to `cpp-index.db` in the storage location
+    pub fn database_path(&self) -> PathBuf {
+        self.database_path.clone().unwrap_or_else(|| self.storage_path().join(SHARED_DATABASE_FILE))
+    }
+
+    /// Directory index databases are kept in
+    pub fn storage_path(&self) -> PathBuf {
+        self.storage.path.clone().unwrap_or_else(|| data_home(|name| std::env::var_os(name)).join(APP_DIR).join("indices"))
+    }
+
+    /// Database of the index `name`: the one configured for it, else the
+    /// configured `database_path`, else `<name>.db` in the storage location
+    pub fn index_database_path(&self, name: &str) -> PathBuf {
+        if let Some(path) = self.index_databases.get(name).or(self.database_path.as_ref()) {
+            return path.clone();
+        }
+        self.storage_path().join(format!("{}.db", database_file_stem(name)))
+    }
+
+    /// How vendored copies are detected while indexing; None when they are indexed like other files
+    pub fn vendored_dedup(&self) -> Option<VendoredDedup> {
+        if !self.dedupe_vendored_copies {
+            return None;
+        }
+        let dedup = VendoredDedup::new().with_min_files(self.vendored_min_files);
+        Some(self.vendored_preferred_roots.iter().fold(dedup, |dedup, root| dedup.prefer(root)))
+    }
+
+    /// This configuration with the database of the index `name`
+    pub fn for_index(mut self, name: &str) -> Self {
+        self.database_path = Some(self.index_database_path(name));
+        self
+    }
+
+    /// Index databases in the storage location, by index name
+    pub fn stored_index_databases(&self) -> Result<BTreeMap<String, PathBuf>> {
+        let storage = self.storage_path();
+        let mut databases = BTreeMap::new();
+        let entries = match std::fs::read_dir(&storage) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(databases),
+            Err(e) => return Err(e).with_context(|| format!("Failed to list {}", storage.display())),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let is_database = path.extension().is_some_and(|extension| extension == "db");
+            if !is_database || path.file_name().is_some_and(|name| name == SHARED_DATABASE_FILE) {
+                continue;
+            }
+            if let Some(stem) = path.file_stem() {
+                databases.insert(index_name_from_stem(&stem.to_string_lossy()), path.clone());
+            }
+        }
+        Ok(databases)
+    }
+
+    /// Configurations of every database a command naming no index acts on
+    ///
+    /// That is the configured `database_path` alone if there is one, else
+    /// those of the shared database, the index databases in the storage
+    /// location and the ones configured in `index_databases` that exist.
+    pub fn databases(&self) -> Result<Vec<Self>> {
+        if self.database_path.is_some() {
+            return Ok(vec![self.clone()]);
+        }
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let shared = self.database_path();
+        if shared.exists() {
+            paths.push(shared);
+        }
+        let mut databases = self.stored_index_databases()?;
+        databases.extend(self.index_databases.clone());
+        for path in databases.into_values() {
+            if path.exists() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        Ok(paths
+            .into_iter()
+            .map(|path| Self { database_path: Some(path), ..self.clone() })
+            .collect())
+    }
+
+    /// The configuration file read: CPP_INDEX_CONFIG, else `config.toml` in the user's configuration directory
+    pub fn file_path() -> PathBuf {
+        std::env::var_os(CONFIG_ENV_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| config_home(|name| std::env::var_os(name)).join(APP_DIR).join("config.toml"))
+    }
+
+    /// Telemetry spool location, defaulting to `<database>.telemetry.jsonl`
+    pub fn telemetry_spool_path(&self) -> PathBuf {
+        self.telemetry_spool_path.clone().unwrap_or_else(|| {
+            let mut path = self.database_path().into_os_string();
+            path.push(".telemetry.jsonl");
+            PathBuf::from(path)
+        })
+    }
+
+    /// Failover heartbeat location, defaulting to `<database>.heartbeat`
+    pub fn heartbeat_path(&self) -> PathBuf {
+        self.heartbeat_path.clone().unwrap_or_else(|| {
+            let mut path = self.database_path().into_os_string();
+            path.push(".heartbeat");
+            PathBuf::from(path)
+        })
+    }
+
+    /// Scratch database for indexing state over the memory limit, `<database>.spill`
+    pub fn spill_path(&self) -> PathBuf {
+        let mut path = self.database_path().into_os_string();
+        path.push(".spill");
+        PathBuf::from(path)
+    }
+
+    /// Reads the configuration file, or returns the defaults if there is none
     pub fn load() -> Result<Self> {
-        // TODO: Implement configuration loading from file
-        Ok(Self::default())
+        Self::load_from(&Self::file_path())
     }
-    
-    /// Save configuration to file
-    #[allow(dead_code)]
-    pub fn save(&self) -> Result<()> {
-        // TODO: Implement configuration saving
-        Ok(())
+
+    /// Reads a configuration file; options it leaves out keep their defaults
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    /// Loads configuration for the codebase at `root`, applying its `.cppindex.toml` if it has one
+    pub fn load_for_project(root: &Path) -> Result<Self> {
+        let mut config = Self::load()?;
+        if let Some(project) = ProjectConfig::load(root)? {
+            if let Some(database_path) = &project.database_path {
+                config.database_path = Some(root.join(database_path));
+            }
+            config.project = Some(project);
+        }
+        Ok(config)
+    }
+
+    /// Sets an option in a configuration file, like `storage.path`, creating the file if needed
+    ///
+    /// `value` is read as a TOML value, or taken as a string if it isn't
+    /// one. The other options of the file are kept as written, and nothing
+    /// is written unless the result is a valid configuration.
+    pub fn set_in_file(path: &Path, key: &str, value: &str) -> Result<()> {
+        let mut table: toml::Table = match std::fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        let (parents, leaf) = match key.rsplit_once('.') {
+            Some((parents, leaf)) => (parents.split('.').collect(), leaf),
+            None => (Vec::new(), key),
+        };
+        if leaf.is_empty() || parents.iter().any(|part: &&str| part.is_empty()) {
+            anyhow::bail!("Invalid option name '{}'", key);
+        }
+        let mut current = &mut table;
+        for part in parents {
+            current = current
+                .entry(part)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow::anyhow!("'{}' in '{}' is not a table", part, key))?;
+        }
+        current.insert(leaf.to_string(), value);
+
+        let content = toml::to_string(&table)?;
+        toml::from_str::<Self>(&content).with_context(|| format!("Can't set {}", key))?;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// The user's data directory: LOCALAPPDATA on Windows, else XDG_DATA_HOME or `~/.local/share`
+fn data_home(env: impl Fn(&str) -> Option<OsString>) -> PathBuf {
+    if cfg!(windows) {
+        return env("LOCALAPPDATA").or_else(|| env("APPDATA")).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+    }
+    xdg_home(&env, "XDG_DATA_HOME", ".local/share")
+}
+
+/// The user's configuration directory: APPDATA on Windows, else XDG_CONFIG_HOME or `~/.config`
+fn config_home(env: impl Fn(&str) -> Option<OsString>) -> PathBuf {
+    if cfg!(windows) {
+        return env("APPDATA").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+    }
+    xdg_home(&env, "XDG_CONFIG_HOME", ".config")
+}
+
+/// An XDG base directory; relative values are invalid per the specification and ignored
+fn xdg_home(env: &impl Fn(&str) -> Option<OsString>, variable: &str, default: &str) -> PathBuf {
+    match env(variable).map(PathBuf::from).filter(|path| path.is_absolute()) {
+        Some(path) => path,
+        None => env("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(".")).join(default),
+    }
+}
+
+/// Index name as a file name, with characters that have a meaning in paths percent-encoded
+///
+/// `%` is encoded too, so every name has its own file and
+/// [`index_name_from_stem`] gets the name back: `engine/core` is stored as
+/// `engine%2Fcore.db`, apart from `engine_core.db`.
+fn database_file_stem(name: &str) -> String {
+    let mut stem = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+            stem.push(c);
+        } else {
+            for byte in c.to_string().bytes() {
+                stem.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    stem
+}
+
+/// Index name of a database file stem written by [`database_file_stem`]
+fn index_name_from_stem(stem: &str) -> String {
+    let mut bytes = Vec::with_capacity(stem.len());
+    let mut rest = stem.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl ProjectConfig {
+    /// Reads `.cppindex.toml` at `root`; None if there is none
+    pub fn load(root: &Path) -> Result<Option<Self>> {
+        let path = root.join(PROJECT_CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let project: Self = toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?;
+        project.symbols.validate().map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        for rule in &project.dialects {
+            rule.validate().map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        }
+        Ok(Some(project))
+    }
+
+    /// Returns true if the file at `path`, relative to the project root, is indexed
+    pub fn selects(&self, path: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|pattern| pattern_matches(pattern, path));
+        included && !self.exclude.iter().any(|pattern| pattern_matches(pattern, path))
+    }
+
+    /// Compiler flags with relative include paths resolved against `root`
+    pub fn compile_flags(&self, root: &Path) -> Vec<String> {
+        anchor_path_flags(&self.compile_flags, root)
+    }
+
+    /// Dialect rules for files under `root`, with their relative include paths resolved against it
+    pub fn dialect_rules(&self, root: &Path) -> DialectRules {
+        let rules = self
+            .dialects
+            .iter()
+            .map(|rule| DialectRule { flags: anchor_path_flags(&rule.flags, root), ..rule.clone() })
+            .collect();
+        DialectRules::new(root, rules)
+    }
+
+    /// The project's compile_commands.json, if it names one
+    pub fn compile_commands(&self, root: &Path) -> Option<PathBuf> {
+        self.compile_commands.as_ref().map(|path| root.join(path))
+    }
+}
+
+/// Resolves the relative paths of include options against `root`
+fn anchor_path_flags(compile_flags: &[String], root: &Path) -> Vec<String> {
+    let anchor = |value: &str| {
+        if Path::new(value).is_absolute() {
+            value.to_string()
+        } else {
+            root.join(value).to_string_lossy().to_string()
+        }
+    };
+    let mut flags = Vec::new();
+    let mut arguments = compile_flags.iter();
+    while let Some(flag) = arguments.next() {
+        match PATH_FLAGS.iter().find(|option| flag.starts_with(**option)) {
+            Some(option) if flag == option => {
+                flags.push(flag.clone());
+                if let Some(value) = arguments.next() {
+                    flags.push(anchor(value));
+                }
+            }
+            Some(option) => flags.push(format!("{}{}", option, anchor(&flag[option.len()..]))),
+            None => flags.push(flag.clone()),
+        }
+    }
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_config() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Config::load_for_project(dir.path()).unwrap().project.is_none());
+
+        std::fs::write(
+            dir.path().join(PROJECT_CONFIG_FILE),
+            r#"
+include = ["src/", "include/"]
+exclude = ["*.pb.h"]
+compile_flags = ["-Iinclude", "-isystem", "/opt/sdk/include", "-DPLATFORM_LINUX"]
+compile_commands = "build/compile_commands.json"
+database_path = ".cppindex/index.db"
+
+[symbols]
+exclude_scopes = ["*::detail"]
+
+[[dialects]]
+pattern = "legacy/**"
+std = "c++03"
+
+[[dialects]]
+pattern = "*.cu"
+language = "cuda"
+flags = ["-Icuda/include"]
+"#,
+        )
+        .unwrap();
+        let config = Config::load_for_project(dir.path()).unwrap();
+        assert_eq!(config.database_path(), dir.path().join(".cppindex/index.db"));
+        let project = config.project.unwrap();
+        assert!(project.selects("src/codec/decoder.cpp"));
+        assert!(!project.selects("src/codec/frame.pb.h"));
+        assert!(!project.selects("tests/decoder_test.cpp"));
+        assert_eq!(
+            project.compile_flags(dir.path()),
+            [
+                format!("-I{}", dir.path().join("include").display()),
+                "-isystem".to_string(),
+                "/opt/sdk/include".to_string(),
+                "-DPLATFORM_LINUX".to_string(),
+            ]
+        );
+        assert_eq!(project.symbols.exclude_scopes, ["*::detail"]);
+        let dialects = project.dialect_rules(dir.path());
+        assert_eq!(
+            dialects.apply(&dir.path().join("kernels/blur.cu"), vec!["-std=c++17".to_string()]),
+            ["-x".to_string(), "cuda".to_string(), "-std=c++17".to_string(), format!("-I{}", dir.path().join("cuda/include").display())]
+        );
+
+        std::fs::write(dir.path().join(PROJECT_CONFIG_FILE), "exclde = [\"*.pb.h\"]\n").unwrap();
+        assert!(Config::load_for_project(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_storage_location() {
+        let env = |variables: &'static [(&'static str, &'static str)]| {
+            move |name: &str| variables.iter().find(|(variable, _)| *variable == name).map(|(_, value)| OsString::from(*value))
+        };
+        if !cfg!(windows) {
+            assert_eq!(data_home(env(&[("HOME", "/home/ada")])), PathBuf::from("/home/ada/.local/share"));
+            assert_eq!(data_home(env(&[("HOME", "/home/ada"), ("XDG_DATA_HOME", "/data")])), PathBuf::from("/data"));
+            assert_eq!(data_home(env(&[("HOME", "/home/ada"), ("XDG_DATA_HOME", "relative")])), PathBuf::from("/home/ada/.local/share"));
+            assert_eq!(config_home(env(&[("HOME", "/home/ada")])), PathBuf::from("/home/ada/.config"));
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("cpp-index-mcp/config.toml");
+        Config::set_in_file(&file, "storage.path", &dir.path().join("ssd").display().to_string()).unwrap();
+        Config::set_in_file(&file, "wal_size_limit_mb", "128").unwrap();
+        Config::set_in_file(&file, "stale_check", "reindex").unwrap();
+        assert!(Config::set_in_file(&file, "stale_check", "sometimes").is_err());
+        assert!(Config::set_in_file(&file, "storage.pth", "/tmp").is_err());
+        assert!(Config::set_in_file(&file, "wal_size_limit_mb", "lots").is_err());
+
+        let mut config = Config::load_from(&file).unwrap();
+        assert_eq!((config.wal_size_limit_mb, config.stale_check), (128, StaleCheck::Reindex));
+        assert_eq!(config.storage_path(), dir.path().join("ssd"));
+        assert_eq!(config.database_path(), dir.path().join("ssd/cpp-index.db"));
+        assert_eq!(config.index_database_path("engine/core"), dir.path().join("ssd/engine%2Fcore.db"));
+        assert_eq!(config.index_database_path("engine_core"), dir.path().join("ssd/engine_core.db"));
+        assert_eq!(config.index_database_path("50%"), dir.path().join("ssd/50%25.db"));
+
+        std::fs::create_dir_all(dir.path().join("ssd")).unwrap();
+        for file in ["engine.db", "engine%2Fcore.db", "cpp-index.db", "notes.txt"] {
+            std::fs::write(dir.path().join("ssd").join(file), "").unwrap();
+        }
+        assert_eq!(config.stored_index_databases().unwrap().into_keys().collect::<Vec<_>>(), ["engine", "engine/core"]);
+        let databases: Vec<PathBuf> = config.databases().unwrap().iter().map(Config::database_path).collect();
+        assert_eq!(databases, ["cpp-index.db", "engine.db", "engine%2Fcore.db"].map(|file| dir.path().join("ssd").join(file)));
+
+        // A database of the index's own wins over the shared one, which wins over the storage location
+        config.index_databases.insert("engine".to_string(), PathBuf::from("/fast/engine.db"));
+        config.database_path = Some(PathBuf::from("/shared.db"));
+        assert_eq!(config.index_database_path("engine"), PathBuf::from("/fast/engine.db"));
+        assert_eq!(config.clone().for_index("renderer").database_path(), PathBuf::from("/shared.db"));
     }
-}
\ No newline at end of file
+}
@@ -3,6 +3,8 @@
 // This module provides interactive menu systems and command-line
 // argument parsing for user interaction with the indexing system.
 
+pub mod self_update;
+
 // TODO: Implement these modules in later tasks
 // pub mod menu;
 // pub mod cli_args;
@@ -0,0 +1,322 @@
+// Self-update
+//
+// Releases are published on GitHub with one executable per platform, named
+// `cpp-index-mcp-<arch>-<os>`, a `SHA256SUMS` file listing their hashes and
+// `SHA256SUMS.minisig`, its minisign signature. An update downloads the
+// three, checks the signature, checks the executable against its listed
+// hash and only then moves it over the running binary.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// GitHub repository releases are published in
+pub const RELEASE_REPOSITORY: &str = "dannyowelch/code_index_mcp";
+
+/// Checksum file published with every release
+pub const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// Minisign signature of the checksum file
+pub const SIGNATURE_ASSET: &str = "SHA256SUMS.minisig";
+
+/// Which releases an installation follows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseChannel {
+    /// Tagged releases only
+    #[default]
+    Stable,
+    /// Tagged releases and prereleases, such as the nightly builds
+    Nightly,
+}
+
+/// A GitHub release, as the releases API lists it
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// A file attached to a release
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+/// A release version: `major.minor.patch`, optionally followed by `-prerelease`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseVersion {
+    numbers: [u64; 3],
+    prerelease: Option<String>,
+}
+
+impl ReleaseChannel {
+    /// Parses "stable" or "nightly"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "stable" => Some(Self::Stable),
+            "nightly" => Some(Self::Nightly),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Nightly => "nightly",
+        }
+    }
+
+    /// Returns true if installations on this channel take `release`
+    fn accepts(&self, release: &Release) -> bool {
+        !release.draft && (*self == Self::Nightly || !release.prerelease)
+    }
+}
+
+impl ReleaseVersion {
+    /// Parses a tag such as "v1.4.0" or "v1.5.0-nightly.20261014"
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.strip_prefix('v').unwrap_or(tag);
+        let (numbers, prerelease) = match tag.split_once('-') {
+            Some((numbers, prerelease)) => (numbers, Some(prerelease.to_string())),
+            None => (tag, None),
+        };
+        let parts: Vec<u64> = numbers.split('.').map(str::parse).collect::<Result<_, _>>().ok()?;
+        let [major, minor, patch] = parts.as_slice() else {
+            return None;
+        };
+        Some(Self { numbers: [*major, *minor, *patch], prerelease })
+    }
+}
+
+impl Ord for ReleaseVersion {
+    /// Orders by the numbers, then a prerelease before the release it leads up to
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numbers.cmp(&other.numbers).then_with(|| match (&self.prerelease, &other.prerelease) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => a.cmp(b),
+        })
+    }
+}
+
+impl PartialOrd for ReleaseVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Release {
+    /// The asset named `name`, if the release has one
+    pub fn asset(&self, name: &str) -> Option<&ReleaseAsset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+/// Name of the release executable for the platform this binary was built for
+pub fn platform_asset_name() -> String {
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    format!("cpp-index-mcp-{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, suffix)
+}
+
+/// The newest release on `channel` that is newer than `current` and has an executable named `asset_name`
+///
+/// Releases with tags that aren't versions are skipped.
+pub fn select_update<'a>(releases: &'a [Release], channel: ReleaseChannel, current: &str, asset_name: &str) -> Option<&'a Release> {
+    let current = ReleaseVersion::parse(current)?;
+    releases
+        .iter()
+        .filter(|release| channel.accepts(release) && release.asset(asset_name).is_some())
+        .filter_map(|release| ReleaseVersion::parse(&release.tag_name).map(|version| (version, release)))
+        .filter(|(version, _)| *version > current)
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, release)| release)
+}
+
+/// Parses `sha256sum` output into hex digests by file name
+pub fn parse_checksums(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let (digest, name) = line.trim().split_once(char::is_whitespace)?;
+            let name = name.trim_start().trim_start_matches('*');
+            Some((name.to_string(), digest.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Hex SHA-256 digest of a file
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Lists the repository's releases, newest first
+///
+/// Uses the system `curl`, like telemetry uploads.
+pub fn fetch_releases(repository: &str) -> Result<Vec<Release>> {
+    let url = format!("https://api.github.com/repos/{}/releases?per_page=30", repository);
+    let output = curl()
+        .args(["-H", "Accept: application/vnd.github+json"])
+        .arg(&url)
+        .output()
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        bail!("Failed to list releases: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    serde_json::from_slice(&output.stdout).context("Unreadable release list")
+}
+
+/// Downloads, verifies and installs `release` over the executable at `executable`
+///
+/// Nothing is replaced unless the checksum file carries a valid signature by
+/// `public_key` and the downloaded executable matches its listed hash.
+pub fn install_release(release: &Release, public_key: &str, executable: &Path) -> Result<()> {
+    let asset_name = platform_asset_name();
+    let directory = executable.parent().ok_or_else(|| anyhow!("{} has no parent directory", executable.display()))?;
+    let staging = directory.join(format!(".{}.update", asset_name));
+    fs::create_dir_all(&staging).with_context(|| format!("Failed to create {}", staging.display()))?;
+
+    let result = (|| {
+        let mut downloaded = BTreeMap::new();
+        for name in [asset_name.as_str(), CHECKSUMS_ASSET, SIGNATURE_ASSET] {
+            let asset = release.asset(name).ok_or_else(|| anyhow!("Release {} has no {}", release.tag_name, name))?;
+            let path = staging.join(name);
+            download(&asset.browser_download_url, &path)?;
+            downloaded.insert(name, path);
+        }
+
+        verify_signature(&downloaded[CHECKSUMS_ASSET], &downloaded[SIGNATURE_ASSET], public_key)?;
+        let checksums = parse_checksums(&fs::read_to_string(&downloaded[CHECKSUMS_ASSET])?);
+        let expected = checksums.get(&asset_name).ok_or_else(|| anyhow!("{} doesn't list {}", CHECKSUMS_ASSET, asset_name))?;
+        let actual = sha256_file(&downloaded[asset_name.as_str()])?;
+        if *expected != actual {
+            bail!("Checksum mismatch for {}: expected {}, got {}", asset_name, expected, actual);
+        }
+
+        replace_executable(&downloaded[asset_name.as_str()], executable)
+    })();
+
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Moves `new` over `executable`, keeping it runnable
+///
+/// `new` must be on the same filesystem so the rename is atomic: the path
+/// always holds either the old or the new binary. Windows refuses to
+/// overwrite a running executable but lets it be renamed, so there the old
+/// binary is moved aside to `<name>.old` first.
+pub fn replace_executable(new: &Path, executable: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(new, fs::Permissions::from_mode(0o755))?;
+    }
+    if cfg!(windows) && executable.exists() {
+        let mut old = executable.as_os_str().to_owned();
+        old.push(".old");
+        fs::rename(executable, PathBuf::from(old)).with_context(|| format!("Failed to move {} aside", executable.display()))?;
+    }
+    fs::rename(new, executable).with_context(|| format!("Failed to replace {}", executable.display()))
+}
+
+/// curl failing on HTTP errors and following redirects to release storage
+fn curl() -> Command {
+    let mut command = Command::new("curl");
+    command.args(["--fail", "--silent", "--show-error", "--location", "--max-time", "300"]);
+    command
+}
+
+fn download(url: &str, path: &Path) -> Result<()> {
+    let output = curl().arg("--output").arg(path).arg(url).output().context("Failed to run curl")?;
+    if !output.status.success() {
+        bail!("Failed to download {}: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Checks the checksum file's signature with the system `minisign`
+fn verify_signature(checksums: &Path, signature: &Path, public_key: &str) -> Result<()> {
+    let output = Command::new("minisign")
+        .args(["-V", "-q", "-P", public_key, "-m"])
+        .arg(checksums)
+        .arg("-x")
+        .arg(signature)
+        .output()
+        .context("Failed to run minisign; it is needed to verify release signatures")?;
+    if !output.status.success() {
+        bail!("Release signature is invalid: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str, prerelease: bool, assets: &[&str]) -> Release {
+        Release {
+            tag_name: tag.to_string(),
+            draft: false,
+            prerelease,
+            assets: assets
+                .iter()
+                .map(|name| ReleaseAsset { name: name.to_string(), browser_download_url: format!("https://example.invalid/{}", name) })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_update() {
+        let asset = "cpp-index-mcp-x86_64-linux";
+        let releases = [
+            release("v0.3.0-nightly.20261014", true, &[asset]),
+            release("v0.2.1", false, &["cpp-index-mcp-aarch64-macos"]),
+            release("v0.2.0", false, &[asset]),
+            release("nightly", true, &[asset]),
+            release("v0.1.0", false, &[asset]),
+        ];
+
+        let stable = select_update(&releases, ReleaseChannel::Stable, "0.1.0", asset);
+        assert_eq!(stable.map(|release| release.tag_name.as_str()), Some("v0.2.0"));
+        let nightly = select_update(&releases, ReleaseChannel::Nightly, "0.1.0", asset);
+        assert_eq!(nightly.map(|release| release.tag_name.as_str()), Some("v0.3.0-nightly.20261014"));
+        assert!(select_update(&releases, ReleaseChannel::Stable, "0.2.0", asset).is_none());
+
+        assert!(ReleaseVersion::parse("v1.0.0-rc.1") < ReleaseVersion::parse("1.0.0"));
+        assert!(ReleaseVersion::parse("v1.10.0") > ReleaseVersion::parse("v1.9.3"));
+        assert_eq!(ReleaseChannel::parse("Nightly"), Some(ReleaseChannel::Nightly));
+    }
+
+    #[test]
+    fn test_checksums_and_replacement() {
+        let dir = tempfile::tempdir().unwrap();
+        let new = dir.path().join("download");
+        fs::write(&new, b"new build").unwrap();
+        let digest = sha256_file(&new).unwrap();
+
+        let checksums = parse_checksums(&format!("{}  cpp-index-mcp-x86_64-linux\n{} *cpp-index-mcp-x86_64-windows.exe\n", digest, "0".repeat(64)));
+        assert_eq!(checksums["cpp-index-mcp-x86_64-linux"], digest);
+        assert!(checksums.contains_key("cpp-index-mcp-x86_64-windows.exe"));
+
+        let executable = dir.path().join("cpp-index-mcp");
+        fs::write(&executable, b"old build").unwrap();
+        replace_executable(&new, &executable).unwrap();
+        assert_eq!(fs::read(&executable).unwrap(), b"new build");
+        assert!(!new.exists());
+    }
+}
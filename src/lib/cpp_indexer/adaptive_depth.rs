@@ -0,0 +1,170 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+use crate::lib::cpp_indexer::detail_tiers::{DetailPolicy, DetailTier, SymbolDetail};
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::directory_depth::{directory_of, DirectoryDepth, IndexDepth};
+use crate::lib::storage::repository::Repository;
+
+/// Queries within the window that promote a directory to deep indexing
+pub const DEFAULT_PROMOTE_HITS: u64 = 10;
+
+/// Days of query history a plan looks at
+pub const DEFAULT_WINDOW_DAYS: u32 = 30;
+
+/// Hours between re-plans of an index's depths as queries come in
+pub const REPLAN_INTERVAL_HOURS: i64 = 24;
+
+/// Assigns indexing depth to directories from how often they are queried
+///
+/// Directories queried at least `promote_hits` times within the window are
+/// indexed deep, directories not queried at all are demoted to shallow and
+/// the rest stay at standard depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthPlanner {
+    promote_hits: u64,
+    window_days: u32,
+}
+
+impl Default for DepthPlanner {
+    fn default() -> Self {
+        Self {
+            promote_hits: DEFAULT_PROMOTE_HITS,
+            window_days: DEFAULT_WINDOW_DAYS,
+        }
+    }
+}
+
+impl DepthPlanner {
+    /// Creates the default planner
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Promotes directories queried at least `hits` times within the window
+    pub fn with_promote_hits(mut self, hits: u64) -> Self {
+        self.promote_hits = hits.max(1);
+        self
+    }
+
+    /// Looks at the last `days` days of queries
+    pub fn with_window_days(mut self, days: u32) -> Self {
+        self.window_days = days.max(1);
+        self
+    }
+
+    pub fn promote_hits(&self) -> u64 {
+        self.promote_hits
+    }
+
+    pub fn window_days(&self) -> u32 {
+        self.window_days
+    }
+
+    /// First day of query history counted by a plan made on `today`
+    pub fn window_start(&self, today: NaiveDate) -> NaiveDate {
+        today - Duration::days(i64::from(self.window_days) - 1)
+    }
+
+    /// Depth for a directory queried `hits` times within the window
+    pub fn depth_for(&self, hits: u64) -> IndexDepth {
+        match hits {
+            0 => IndexDepth::Shallow,
+            hits if hits >= self.promote_hits => IndexDepth::Deep,
+            _ => IndexDepth::Standard,
+        }
+    }
+
+    /// Depth of every directory holding indexed files
+    ///
+    /// Hits on directories that no longer hold files are ignored.
+    pub fn plan(&self, index_id: Uuid, directories: &BTreeSet<String>, hits: &BTreeMap<String, u64>, now: DateTime<Utc>) -> Vec<DirectoryDepth> {
+        directories
+            .iter()
+            .map(|directory| {
+                let hits = hits.get(directory).copied().unwrap_or(0);
+                DirectoryDepth {
+                    index_id,
+                    directory: directory.clone(),
+                    depth: self.depth_for(hits),
+                    hits,
+                    assigned_at: now,
+                }
+            })
+            .collect()
+    }
+
+    /// Plans an index from its files and recent query hits, without storing the plan
+    pub fn plan_index(&self, repository: &Repository, index_id: &Uuid, now: DateTime<Utc>) -> Result<Vec<DirectoryDepth>> {
+        let directories: BTreeSet<String> = repository
+            .list_file_metadata(index_id)?
+            .iter()
+            .map(|metadata| directory_of(&metadata.file_path).to_string())
+            .collect();
+        let hits = repository.directory_hits_since(index_id, self.window_start(now.date_naive()))?;
+        Ok(self.plan(*index_id, &directories, &hits, now))
+    }
+
+    /// Plans an index and stores the result, dropping hits that fell out of the window
+    pub fn replan(&self, repository: &Repository, index_id: &Uuid, now: DateTime<Utc>) -> Result<Vec<DirectoryDepth>> {
+        let depths = self.plan_index(repository, index_id, now)?;
+        repository.replace_directory_depths(index_id, &depths)?;
+        repository.prune_directory_hits(index_id, self.window_start(now.date_naive()))?;
+        Ok(depths)
+    }
+}
+
+/// Whether depths assigned at `assigned_at` are due to be planned again
+pub fn needs_replan(assigned_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    assigned_at.map_or(true, |assigned_at| now - assigned_at >= Duration::hours(REPLAN_INTERVAL_HOURS))
+}
+
+/// Detail stored for the symbols of files indexed at `depth`
+pub fn detail_policy(depth: IndexDepth) -> DetailPolicy {
+    match depth {
+        IndexDepth::Deep => DetailPolicy::full(),
+        IndexDepth::Standard => DetailPolicy::tiered(),
+        IndexDepth::Shallow => DetailPolicy::tiered()
+            .with_detail(DetailTier::PublicApi, SymbolDetail::Outline)
+            .with_detail(DetailTier::Internal, SymbolDetail::Omit),
+    }
+}
+
+/// Depth assigned to the directory of a stored file, if any
+pub fn depth_of_file(depths: &[DirectoryDepth], file_path: &str) -> Option<IndexDepth> {
+    let directory = directory_of(file_path);
+    depths.iter().find(|depth| depth.directory == directory).map(|depth| depth.depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_promotes_and_demotes() {
+        let planner = DepthPlanner::new().with_promote_hits(3);
+        let directories: BTreeSet<String> = ["", "src/audio", "src/ui", "third_party/zlib"].iter().map(|d| d.to_string()).collect();
+        let hits = BTreeMap::from([("src/audio".to_string(), 5), ("src/ui".to_string(), 1), ("removed".to_string(), 9)]);
+
+        let now = Utc::now();
+        let plan = planner.plan(Uuid::new_v4(), &directories, &hits, now);
+        let depths: Vec<(&str, IndexDepth)> = plan.iter().map(|depth| (depth.directory.as_str(), depth.depth)).collect();
+        assert_eq!(
+            depths,
+            [("", IndexDepth::Shallow), ("src/audio", IndexDepth::Deep), ("src/ui", IndexDepth::Standard), ("third_party/zlib", IndexDepth::Shallow)]
+        );
+
+        assert_eq!(depth_of_file(&plan, "src/audio/mixer.cpp"), Some(IndexDepth::Deep));
+        assert_eq!(depth_of_file(&plan, "main.cpp"), Some(IndexDepth::Shallow));
+        assert_eq!(depth_of_file(&plan, "src/audio/dsp/fft.cpp"), None);
+        assert_eq!(detail_policy(IndexDepth::Deep), DetailPolicy::full());
+        assert_eq!(detail_policy(IndexDepth::Shallow).internal, SymbolDetail::Omit);
+
+        let today = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(planner.with_window_days(7).window_start(today), NaiveDate::from_ymd_opt(2024, 3, 25).unwrap());
+        assert!(needs_replan(None, now));
+        assert!(!needs_replan(Some(now - Duration::hours(1)), now));
+        assert!(needs_replan(Some(now - Duration::hours(25)), now));
+    }
+}
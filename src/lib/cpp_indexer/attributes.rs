@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use super::callbacks::{identifiers, mask_non_code};
+
+/// Placement macros that expand to a section attribute, with the section they select
+///
+/// These are the ESP-IDF memory placement macros plus the `__ramfunc`
+/// keyword of the IAR and Atmel toolchains.
+pub const DEFAULT_SECTION_MACROS: &[(&str, &str)] = &[
+    ("IRAM_ATTR", ".iram1"),
+    ("DRAM_ATTR", ".dram1"),
+    ("RTC_IRAM_ATTR", ".rtc.text"),
+    ("RTC_DATA_ATTR", ".rtc.data"),
+    ("RTC_RODATA_ATTR", ".rtc.rodata"),
+    ("RTC_NOINIT_ATTR", ".rtc_noinit"),
+    ("EXT_RAM_ATTR", ".ext_ram.bss"),
+    ("EXT_RAM_BSS_ATTR", ".ext_ram.bss"),
+    ("NOINIT_ATTR", ".noinit"),
+    ("__ramfunc", ".ramfunc"),
+];
+
+/// Attribute names whose string argument is a section name
+///
+/// Covers `__attribute__((section("...")))`, `[[gnu::section("...")]]` and
+/// MSVC's `__declspec(allocate("..."))`.
+const SECTION_ATTRIBUTES: &[&str] = &["section", "__section__", "allocate"];
+
+/// Lines searched for the end of a declaration header
+const MAX_HEADER_LINES: usize = 8;
+
+/// Macros that place a symbol in a section when they appear in its declaration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionMacros {
+    macros: BTreeMap<String, String>,
+}
+
+impl Default for SectionMacros {
+    fn default() -> Self {
+        Self {
+            macros: DEFAULT_SECTION_MACROS
+                .iter()
+                .map(|(name, section)| (name.to_string(), section.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl SectionMacros {
+    /// Creates the default macro table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces a project-specific placement macro (e.g. `ITCM_FUNC` -> ".itcm")
+    pub fn with_macro(mut self, name: impl Into<String>, section: impl Into<String>) -> Self {
+        self.macros.insert(name.into(), section.into());
+        self
+    }
+
+    /// Section a macro selects, if it is a placement macro
+    pub fn section_of(&self, name: &str) -> Option<&str> {
+        self.macros.get(name).map(String::as_str)
+    }
+}
+
+/// Finds the section a declaration is placed in
+///
+/// `header` is the declaration text before its body or initializer. An
+/// explicit section attribute wins over a placement macro.
+pub fn find_memory_section(header: &str, macros: &SectionMacros) -> Option<String> {
+    let code = mask_non_code(header);
+    let mut from_macro = None;
+
+    for (start, end) in identifiers(&code) {
+        let name = &code[start..end];
+        if SECTION_ATTRIBUTES.contains(&name) {
+            // Literals are masked in `code`, so the string is read from the original text
+            let rest = header[end..].trim_start();
+            if let Some(section) = rest.strip_prefix('(').and_then(|args| string_literal(args.trim_start())) {
+                return Some(section.to_string());
+            }
+        } else if from_macro.is_none() {
+            from_macro = macros.section_of(name).map(str::to_string);
+        }
+    }
+
+    from_macro
+}
+
+/// Section of the symbol declared at the 1-based `line` of `content`
+///
+/// The header is read from the line before (for attributes written on their
+/// own line) through the first `{`, `;` or `=` at parenthesis depth zero.
+pub fn declaration_section(content: &str, line: u32, macros: &SectionMacros) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let index = (line as usize).checked_sub(1).filter(|&index| index < lines.len())?;
+
+    let mut header = String::new();
+    if let Some(previous) = index.checked_sub(1).map(|previous| lines[previous].trim()) {
+        let attribute_only = !previous.is_empty()
+            && !previous.starts_with('#')
+            && !previous.contains([';', '{', '}'])
+            && find_memory_section(previous, macros).is_some();
+        if attribute_only {
+            header.push_str(previous);
+            header.push('\n');
+        }
+    }
+
+    let mut depth = 0i32;
+    for text in lines[index..].iter().take(MAX_HEADER_LINES) {
+        let code = mask_non_code(text);
+        let mut end = None;
+        for (offset, c) in code.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '{' | ';' | '=' if depth <= 0 => {
+                    end = Some(offset);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        match end {
+            Some(end) => {
+                header.push_str(&text[..end]);
+                break;
+            }
+            None => {
+                header.push_str(text);
+                header.push('\n');
+            }
+        }
+    }
+
+    find_memory_section(&header, macros)
+}
+
+/// Doc comment directly above the symbol declared at the 1-based `line`
+///
+/// Accepts a run of `//`, `///` or `//!` lines, or one `/* */` block, ending
+/// on the line before the declaration or before its `template <...>` line.
+/// Comment markers and the leading `*` of block lines are removed.
+pub fn declaration_documentation(content: &str, line: u32) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut index = (line as usize).checked_sub(1).filter(|&index| index < lines.len())?;
+    if index > 0 && lines[index - 1].trim_start().starts_with("template") {
+        index -= 1;
+    }
+
+    let mut comment = Vec::new();
+    let above = |index: usize| index.checked_sub(1).map(|previous| lines[previous].trim());
+    if above(index).is_some_and(|text| text.ends_with("*/")) {
+        while let Some(text) = above(index) {
+            index -= 1;
+            comment.push(text);
+            if text.starts_with("/*") {
+                break;
+            }
+        }
+        if !comment.last()?.starts_with("/*") {
+            return None;
+        }
+    } else {
+        while let Some(text) = above(index).filter(|text| text.starts_with("//")) {
+            index -= 1;
+            comment.push(text);
+        }
+    }
+    comment.reverse();
+
+    let text: Vec<&str> = comment
+        .iter()
+        .map(|text| {
+            let text = text.trim_start_matches("/**").trim_start_matches("/*!").trim_start_matches("/*");
+            let text = text.strip_suffix("*/").unwrap_or(text);
+            let text = text.trim_start_matches(['/', '!']);
+            let text = text.trim();
+            text.strip_prefix('*').map_or(text, str::trim_start).trim_end()
+        })
+        .collect();
+    let documentation = text.join("\n").trim().to_string();
+    (!documentation.is_empty()).then_some(documentation)
+}
+
+/// Contents of a leading `"..."` literal
+fn string_literal(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix('"')?;
+    rest.find('"').map(|end| &rest[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_memory_section() {
+        let macros = SectionMacros::new().with_macro("ITCM_FUNC", ".itcm");
+        let section = |header| find_memory_section(header, &macros);
+
+        assert_eq!(section("__attribute__((section(\".itcm\"))) void fir_filter(int n)").as_deref(), Some(".itcm"));
+        assert_eq!(section("__attribute__((used, __section__ (\".dtcm.bss\"))) int buffer[64]").as_deref(), Some(".dtcm.bss"));
+        assert_eq!(section("[[gnu::section(\".fast\")]] void tick()").as_deref(), Some(".fast"));
+        assert_eq!(section("__declspec(allocate(\".shared\")) int counter").as_deref(), Some(".shared"));
+        assert_eq!(section("void IRAM_ATTR gpio_isr(void* arg)").as_deref(), Some(".iram1"));
+        assert_eq!(section("ITCM_FUNC void dsp_kernel()").as_deref(), Some(".itcm"));
+        assert_eq!(section("void plain(const char* section)"), None);
+        assert_eq!(section("void commented() /* IRAM_ATTR */"), None);
+    }
+
+    #[test]
+    fn test_declaration_section() {
+        let content = "\
+__attribute__((section(\".itcm\")))
+void fir_filter(const int* taps,
+                int n) {
+    run();
+}
+
+DRAM_ATTR static int lookup[4] = { IRAM_ATTR };
+
+void ordinary() {
+    int x = 0; // IRAM_ATTR
+}
+";
+        let macros = SectionMacros::new();
+        assert_eq!(declaration_section(content, 2, &macros).as_deref(), Some(".itcm"));
+        assert_eq!(declaration_section(content, 7, &macros).as_deref(), Some(".dram1"));
+        assert_eq!(declaration_section(content, 9, &macros), None);
+        assert_eq!(declaration_section(content, 99, &macros), None);
+    }
+
+    #[test]
+    fn test_declaration_documentation() {
+        let content = "\
+#include <cstddef>
+
+/// Copies buffered samples into `out`.
+/// Never blocks.
+size_t drain(float* out);
+
+/**
+ * Ring buffer of audio frames.
+ */
+template <typename T>
+class RingBuffer;
+
+int undocumented;
+// trailing comment
+
+void after_blank();
+/* unterminated */ int on_same_line;
+";
+        assert_eq!(declaration_documentation(content, 5).as_deref(), Some("Copies buffered samples into `out`.\nNever blocks."));
+        assert_eq!(declaration_documentation(content, 11).as_deref(), Some("Ring buffer of audio frames."));
+        assert_eq!(declaration_documentation(content, 13), None);
+        assert_eq!(declaration_documentation(content, 16), None);
+        assert_eq!(declaration_documentation(content, 99), None);
+    }
+}
@@ -0,0 +1,205 @@
+// Compilation database capture by build wrapping
+//
+// Projects whose build system can't export compile_commands.json still run a
+// compiler for every source file. A wrapped build puts shim scripts named
+// like the usual compilers first on PATH and in CC/CXX; each shim records its
+// arguments and working directory to a log, then runs the real compiler. The
+// log becomes a compile_commands.json that `index create --compile-commands`
+// reads like any other.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::lib::cpp_indexer::vfs::is_source_file;
+
+/// Environment variable naming the log shims append invocations to
+pub const CAPTURE_LOG_ENV: &str = "CPP_INDEX_CAPTURE_LOG";
+
+/// Compiler names shimmed during a wrapped build
+pub const SHIMMED_COMPILERS: &[&str] = &["cc", "c++", "gcc", "g++", "clang", "clang++"];
+
+/// Options whose value is the next argument, so it can't be mistaken for a source file
+const OPTIONS_WITH_VALUE: &[&str] = &[
+    "-o", "-x", "-D", "-U", "-I", "-include", "-imacros", "-isystem", "-iquote", "-idirafter", "-isysroot", "--sysroot", "-F",
+    "-MF", "-MT", "-MQ", "-arch", "-target", "-Xclang", "-Xpreprocessor", "-Xassembler", "-Xlinker",
+];
+
+/// One compiler run recorded by a shim
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturedInvocation {
+    /// Working directory of the compiler
+    pub directory: PathBuf,
+    /// Command line, starting with the real compiler
+    pub arguments: Vec<String>,
+}
+
+/// One entry of compile_commands.json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompileCommandEntry {
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    pub arguments: Vec<String>,
+}
+
+/// Appends an invocation to the capture log
+///
+/// Each invocation is one JSON line written with a single call, so shims of
+/// a parallel build don't interleave.
+pub fn record_invocation(log: &Path, invocation: &CapturedInvocation) -> io::Result<()> {
+    let mut line = serde_json::to_string(invocation)?;
+    line.push('\n');
+    OpenOptions::new().create(true).append(true).open(log)?.write_all(line.as_bytes())
+}
+
+/// Reads the invocations of a capture log, skipping lines a crashed shim left incomplete
+pub fn read_invocations(log: &Path) -> io::Result<Vec<CapturedInvocation>> {
+    let mut invocations = Vec::new();
+    for line in BufReader::new(fs::File::open(log)?).lines() {
+        if let Ok(invocation) = serde_json::from_str(&line?) {
+            invocations.push(invocation);
+        }
+    }
+    Ok(invocations)
+}
+
+/// Source files an invocation compiles; empty for link, preprocess and dependency-only runs
+pub fn compiled_sources(arguments: &[String]) -> Vec<&str> {
+    let compiles = arguments.iter().any(|argument| argument == "-c");
+    let preprocesses_only = arguments.iter().any(|argument| argument == "-E" || argument == "-M" || argument == "-MM");
+    if !compiles || preprocesses_only {
+        return Vec::new();
+    }
+
+    let mut sources = Vec::new();
+    let mut arguments = arguments.iter().skip(1);
+    while let Some(argument) = arguments.next() {
+        if OPTIONS_WITH_VALUE.contains(&argument.as_str()) {
+            arguments.next();
+        } else if !argument.starts_with('-') && is_source_file(argument) && !is_header(argument) {
+            sources.push(argument.as_str());
+        }
+    }
+    sources
+}
+
+/// compile_commands.json entries of a build's invocations
+///
+/// An invocation compiling several sources yields one entry per source,
+/// without the other sources. A file compiled more than once keeps its last
+/// invocation. Entries are sorted by path.
+pub fn compile_commands(invocations: &[CapturedInvocation]) -> Vec<CompileCommandEntry> {
+    let mut entries: BTreeMap<PathBuf, CompileCommandEntry> = BTreeMap::new();
+    for invocation in invocations {
+        let sources = compiled_sources(&invocation.arguments);
+        for source in &sources {
+            let arguments = invocation
+                .arguments
+                .iter()
+                .filter(|argument| argument.as_str() == *source || !sources.contains(&argument.as_str()))
+                .cloned()
+                .collect();
+            entries.insert(
+                invocation.directory.join(source),
+                CompileCommandEntry {
+                    directory: invocation.directory.clone(),
+                    file: PathBuf::from(source),
+                    arguments,
+                },
+            );
+        }
+    }
+    entries.into_values().collect()
+}
+
+/// Writes entries as compile_commands.json
+pub fn write_compile_commands(path: &Path, entries: &[CompileCommandEntry]) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(entries)?)
+}
+
+/// First executable named `name` on `path`, skipping the directory `skip`
+pub fn find_executable(name: &str, path: &std::ffi::OsStr, skip: &Path) -> Option<PathBuf> {
+    env::split_paths(path)
+        .filter(|directory| directory != skip)
+        .map(|directory| directory.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Writes a shim named `name` that records through `recorder` then runs `compiler`
+///
+/// `recorder` is this program; the shim calls its `record-compile` command.
+#[cfg(unix)]
+pub fn install_shim(shim_dir: &Path, name: &str, recorder: &Path, compiler: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let recorder = shell_quote(&recorder.to_string_lossy());
+    let compiler = shell_quote(&compiler.to_string_lossy());
+    let script = format!("#!/bin/sh\n{} record-compile -- {} \"$@\"\nexec {} \"$@\"\n", recorder, compiler, compiler);
+    let shim = shim_dir.join(name);
+    fs::write(&shim, script)?;
+    fs::set_permissions(&shim, fs::Permissions::from_mode(0o755))?;
+    Ok(shim)
+}
+
+/// Quotes a word for /bin/sh
+pub fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+fn is_header(path: &str) -> bool {
+    matches!(
+        Path::new(path).extension().and_then(|extension| extension.to_str()),
+        Some("h") | Some("hpp") | Some("hxx")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+
+    fn invocation(directory: &str, arguments: &[&str]) -> CapturedInvocation {
+        CapturedInvocation {
+            directory: PathBuf::from(directory),
+            arguments: arguments.iter().map(|argument| argument.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_compile_commands_from_invocations() {
+        let invocations = [
+            invocation("/work/build", &["/usr/bin/g++", "-DOLD", "-c", "../src/mixer.cpp", "-o", "mixer.o"]),
+            invocation("/work/build", &["/usr/bin/g++", "-I", "../include", "-DNEW", "-c", "../src/mixer.cpp", "-o", "mixer.o"]),
+            invocation("/work/build", &["/usr/bin/gcc", "-c", "../src/a.c", "../src/b.c", "-x", "c"]),
+            // Link and dependency-only runs compile nothing
+            invocation("/work/build", &["/usr/bin/g++", "mixer.o", "a.o", "-o", "player"]),
+            invocation("/work/build", &["/usr/bin/g++", "-M", "-c", "../src/mixer.cpp"]),
+        ];
+        let entries = compile_commands(&invocations);
+        let files: Vec<&Path> = entries.iter().map(|entry| entry.file.as_path()).collect();
+        assert_eq!(files, [Path::new("../src/a.c"), Path::new("../src/b.c"), Path::new("../src/mixer.cpp")]);
+        assert_eq!(entries[0].arguments, ["/usr/bin/gcc", "-c", "../src/a.c", "-x", "c"]);
+        assert!(entries[2].arguments.contains(&"-DNEW".to_string()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("compile_commands.json");
+        write_compile_commands(&out, &entries).unwrap();
+        let database = CompilationDatabase::load(&out).unwrap();
+        assert_eq!(database.flags_for(Path::new("/work/src/mixer.cpp")).unwrap(), ["-I", "/work/include", "-DNEW"]);
+    }
+
+    #[test]
+    fn test_capture_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("invocations.jsonl");
+        let first = invocation("/work", &["cc", "-c", "a.c"]);
+        record_invocation(&log, &first).unwrap();
+        fs::OpenOptions::new().append(true).open(&log).unwrap().write_all(b"{\"directory\":").unwrap();
+        assert_eq!(read_invocations(&log).unwrap(), [first]);
+
+        assert_eq!(shell_quote("/opt/it's/g++"), r"'/opt/it'\''s/g++'");
+    }
+}
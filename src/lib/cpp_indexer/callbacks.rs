@@ -0,0 +1,393 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+use crate::lib::storage::repository::Repository;
+
+/// Identifiers that can appear in value position but never name a function
+const NON_FUNCTION_WORDS: &[&str] = &[
+    "nullptr", "NULL", "true", "false", "this", "void", "bool", "char", "short", "int", "long",
+    "float", "double", "signed", "unsigned", "auto", "const", "volatile", "return", "sizeof",
+];
+
+/// How a function ended up stored for a later indirect call
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackVia {
+    /// Assigned to or initializing a function pointer (`fp = &f`, `void (*fp)() = f`, `{&f, &g}`)
+    FunctionPointer,
+    /// Stored in a `std::function`
+    StdFunction,
+    /// Bound with `std::bind`
+    Bind,
+    /// Passed as an argument (`qsort(..., compare)`, `register_handler(&on_tick)`)
+    Argument,
+    /// Assigned without a visible function pointer type (`timer.on_expire = handle_timeout`)
+    Assignment,
+}
+
+/// A place where a name is used as a value rather than called
+///
+/// Sites are found lexically and are only candidates: the name may just as
+/// well be a variable. [`resolve_callback_sites`] keeps the ones that resolve
+/// to indexed functions.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CallbackSite {
+    /// Unqualified name
+    pub name: String,
+    /// Qualifier written before the name (e.g. "Widget" for `&Widget::draw`)
+    pub qualifier: Option<String>,
+    /// 1-based line
+    pub line: u32,
+    /// 1-based column of the name
+    pub column: u32,
+    pub via: CallbackVia,
+}
+
+/// Finds names used as function values: address-of, std::function and std::bind
+/// targets, function pointer initializers and plain names passed or assigned
+pub fn find_callback_sites(content: &str) -> Vec<CallbackSite> {
+    let code = mask_non_code(content);
+    let mut sites = Vec::new();
+
+    for (line_index, line) in code.lines().enumerate() {
+        let bind_start = line.find("bind(").map(|i| i + "bind(".len());
+        let marker = if line.contains("function<") {
+            Some(CallbackVia::StdFunction)
+        } else if line.contains("(*") {
+            Some(CallbackVia::FunctionPointer)
+        } else {
+            None
+        };
+
+        for (start, end) in identifiers(line) {
+            let name = &line[start..end];
+            if NON_FUNCTION_WORDS.contains(&name) {
+                continue;
+            }
+
+            let after = line[end..].trim_start();
+            // Calls and qualifiers of a longer name are not values
+            if after.starts_with('(') || after.starts_with("::") {
+                continue;
+            }
+
+            let qualified_start = qualifier_start(line, start);
+            let before = line[..qualified_start].trim_end();
+            if before.ends_with('.') || before.ends_with("->") {
+                continue;
+            }
+
+            let address_of = takes_address(before);
+            let prev = before.chars().next_back();
+            let next = after.chars().next();
+            let plain_value = matches!(prev, Some('=' | '{' | '(' | ',')) && matches!(next, Some(';' | '}' | ')' | ','));
+            if !address_of && !plain_value {
+                continue;
+            }
+            // `a == b`, `a <= b` and friends compare, they don't assign
+            if prev == Some('=') && before[..before.len() - 1].ends_with(['=', '!', '<', '>']) {
+                continue;
+            }
+
+            let in_parens = paren_depth(&line[..qualified_start]) > 0;
+            let via = match (bind_start, marker) {
+                (Some(bind_start), _) if start >= bind_start => CallbackVia::Bind,
+                (_, Some(marker)) => marker,
+                _ if in_parens => CallbackVia::Argument,
+                _ if address_of => CallbackVia::FunctionPointer,
+                _ => CallbackVia::Assignment,
+            };
+
+            let qualifier = line[qualified_start..start].trim_end_matches("::").trim();
+            sites.push(CallbackSite {
+                name: name.to_string(),
+                qualifier: (!qualifier.is_empty()).then(|| qualifier.to_string()),
+                line: line_index as u32 + 1,
+                column: start as u32 + 1,
+                via,
+            });
+        }
+    }
+
+    sites
+}
+
+/// Turns callback sites into ReferencedAsCallback relationships
+///
+/// `file_elements` are the elements of the file the sites came from; the
+/// referencing symbol is the function (or, for global tables of handlers, the
+/// element) enclosing each site. `lookup` returns candidate elements for a
+/// name; only function-like candidates, matching the qualifier if one was
+/// written, become targets.
+pub fn resolve_callback_sites(
+    sites: &[CallbackSite],
+    file_path: &str,
+    file_elements: &[CodeElement],
+    mut lookup: impl FnMut(&str) -> Vec<CodeElement>,
+) -> Vec<SymbolRelationship> {
+    let mut relationships = Vec::new();
+    let mut seen = HashSet::new();
+
+    for site in sites {
+        let Some(from_id) = enclosing_element(file_elements, site.line).and_then(|element| element.id) else { continue };
+
+        for target in lookup(&site.name) {
+            let Some(to_id) = target.id else { continue };
+            if !target.is_callable() || to_id == from_id {
+                continue;
+            }
+            if let Some(qualifier) = &site.qualifier {
+                let scope = target.scope.as_deref().unwrap_or("");
+                if scope != qualifier && !scope.ends_with(&format!("::{}", qualifier)) {
+                    continue;
+                }
+            }
+
+            if seen.insert((from_id, to_id, site.line)) {
+                relationships.push(SymbolRelationship::new(
+                    from_id,
+                    to_id,
+                    RelationshipType::ReferencedAsCallback,
+                    file_path.to_string(),
+                    site.line,
+                ));
+            }
+        }
+    }
+
+    relationships
+}
+
+/// Resolves and stores the callback sites of indexed files, given by stored path
+///
+/// Run once every file is stored, like the calls, so references to
+/// functions of later batches resolve too. Returns the number of
+/// relationships stored.
+pub fn store_callback_relationships(repository: &Repository, index: &CodeIndex, files: Vec<(String, Vec<CallbackSite>)>) -> Result<usize> {
+    let mut candidates: HashMap<String, Vec<CodeElement>> = HashMap::new();
+    let mut stored = 0;
+    for (file_path, sites) in files {
+        for site in &sites {
+            if !candidates.contains_key(&site.name) {
+                let found = repository.find_code_elements_by_name(&index.id, &site.name)?;
+                candidates.insert(site.name.clone(), found);
+            }
+        }
+        let file_elements = repository.list_code_elements_by_file(&index.id, &file_path)?;
+        let relationships = resolve_callback_sites(&sites, &file_path, &file_elements, |name| {
+            candidates.get(name).cloned().unwrap_or_default()
+        });
+        if !relationships.is_empty() {
+            stored += repository.create_symbol_relationships_batch(relationships)?.len();
+        }
+    }
+    Ok(stored)
+}
+
+/// Nearest function starting at or before `line`, else the nearest element of any kind
+pub(crate) fn enclosing_element(elements: &[CodeElement], line: u32) -> Option<&CodeElement> {
+    let preceding = || elements.iter().filter(move |element| element.line_number <= line);
+    preceding()
+        .filter(|element| element.is_callable())
+        .max_by_key(|element| element.line_number)
+        .or_else(|| preceding().max_by_key(|element| element.line_number))
+}
+
+/// Replaces comments and string/char literals with spaces, keeping newlines and offsets
+pub(crate) fn mask_non_code(content: &str) -> String {
+    #[derive(PartialEq)]
+    enum State {
+        Code,
+        LineComment,
+        BlockComment,
+        Literal(u8),
+    }
+
+    let bytes = content.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut state = State::Code;
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        match state {
+            State::Code => match (byte, next) {
+                (b'/', Some(b'/')) => state = State::LineComment,
+                (b'/', Some(b'*')) => state = State::BlockComment,
+                (b'"' | b'\'', _) => state = State::Literal(byte),
+                _ => {}
+            },
+            State::LineComment if byte == b'\n' => state = State::Code,
+            State::BlockComment if byte == b'*' && next == Some(b'/') => {
+                out[i] = b' ';
+                out[i + 1] = b' ';
+                i += 2;
+                state = State::Code;
+                continue;
+            }
+            State::Literal(_) if byte == b'\\' => {
+                out[i] = b' ';
+                if next.is_some_and(|b| b != b'\n') {
+                    out[i + 1] = b' ';
+                }
+                i += 2;
+                continue;
+            }
+            State::Literal(quote) if byte == quote => {
+                out[i] = b' ';
+                i += 1;
+                state = State::Code;
+                continue;
+            }
+            _ => {}
+        }
+        if state != State::Code && byte != b'\n' {
+            out[i] = b' ';
+        }
+        i += 1;
+    }
+
+    // Only ASCII bytes were replaced, and whole characters are masked at once
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// Byte ranges of the identifiers in a line
+pub(crate) fn identifiers(line: &str) -> Vec<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if byte.is_ascii_alphabetic() || byte == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            ranges.push((start, i));
+        } else if byte.is_ascii_digit() {
+            // Skip numeric literals such as 0x1f so their tails aren't read as names
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'.') {
+                i += 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    ranges
+}
+
+/// Start of the `A::B::` qualifier chain ending at `start`
+fn qualifier_start(line: &str, start: usize) -> usize {
+    let mut qualified = start;
+    loop {
+        let before = line[..qualified].trim_end();
+        let Some(rest) = before.strip_suffix("::") else { return qualified };
+        let rest = rest.trim_end();
+        let ident_start = rest.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_').len();
+        if ident_start == rest.len() {
+            // Leading `::` for the global namespace
+            return rest.len();
+        }
+        qualified = ident_start;
+    }
+}
+
+/// Returns true if `before` ends with a unary `&` (not `&&` or a reference declarator)
+fn takes_address(before: &str) -> bool {
+    let Some(rest) = before.strip_suffix('&') else { return false };
+    if rest.ends_with('&') {
+        return false;
+    }
+    match rest.trim_end().chars().next_back() {
+        None => true,
+        Some(c) => !(c.is_alphanumeric() || c == '_' || c == ')' || c == ']' || c == '>'),
+    }
+}
+
+fn paren_depth(text: &str) -> i32 {
+    text.chars().fold(0, |depth, c| match c {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    fn sites(content: &str) -> Vec<(String, CallbackVia)> {
+        find_callback_sites(content).into_iter().map(|site| (site.name, site.via)).collect()
+    }
+
+    #[test]
+    fn test_find_callback_sites() {
+        let content = r#"
+void setup() {
+    void (*fp)(int) = on_tick;
+    std::function<void()> cb = handle_timeout;
+    auto bound = std::bind(&Widget::draw, this, _1);
+    register_handler(&on_click);
+    std::sort(v.begin(), v.end(), compare_by_name);
+    timer.on_expire = handle_expiry;
+    run(tick());
+    if (mode == fast) { reset(); }
+    // register_handler(&not_code);
+    log("&in_string");
+}
+static const Handler handlers[] = {&on_a, &on_b};
+"#;
+        let found = sites(content);
+        assert_eq!(
+            found,
+            vec![
+                ("on_tick".to_string(), CallbackVia::FunctionPointer),
+                ("handle_timeout".to_string(), CallbackVia::StdFunction),
+                ("draw".to_string(), CallbackVia::Bind),
+                ("_1".to_string(), CallbackVia::Bind),
+                ("on_click".to_string(), CallbackVia::Argument),
+                ("compare_by_name".to_string(), CallbackVia::Argument),
+                ("handle_expiry".to_string(), CallbackVia::Assignment),
+                ("on_a".to_string(), CallbackVia::FunctionPointer),
+                ("on_b".to_string(), CallbackVia::FunctionPointer),
+            ]
+        );
+
+        let draw = find_callback_sites(content).into_iter().find(|site| site.name == "draw").unwrap();
+        assert_eq!(draw.qualifier.as_deref(), Some("Widget"));
+        assert_eq!((draw.line, draw.column), (5, 37));
+    }
+
+    #[test]
+    fn test_resolve_callback_sites() {
+        let index_id = Uuid::new_v4();
+        let element = |id: i64, name: &str, symbol_type, line, scope: Option<&str>| {
+            let mut element = CodeElement::new(index_id, name.to_string(), symbol_type, "src/app.cpp".to_string(), line, 1, "a".repeat(64));
+            element.id = Some(id);
+            element.scope = scope.map(str::to_string);
+            element
+        };
+        let file_elements = vec![element(1, "setup", SymbolType::Function, 2, None)];
+        let candidates = [
+            element(2, "draw", SymbolType::Function, 40, Some("Widget")),
+            element(3, "draw", SymbolType::Function, 50, Some("Canvas")),
+            element(4, "on_tick", SymbolType::Variable, 60, None),
+        ];
+        let lookup = |name: &str| candidates.iter().filter(|c| c.symbol_name == name).cloned().collect();
+
+        let content = "\nvoid setup() {\n    auto bound = std::bind(&Widget::draw, this);\n    void (*fp)(int) = on_tick;\n}\n";
+        let relationships = resolve_callback_sites(&find_callback_sites(content), "src/app.cpp", &file_elements, lookup);
+
+        assert_eq!(relationships.len(), 1);
+        assert_eq!(relationships[0].from_symbol_id, 1);
+        assert_eq!(relationships[0].to_symbol_id, 2);
+        assert_eq!(relationships[0].relationship_type, RelationshipType::ReferencedAsCallback);
+        assert_eq!(relationships[0].line_number, 3);
+    }
+}
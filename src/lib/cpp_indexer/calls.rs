@@ -0,0 +1,155 @@
+// Call relationships
+//
+// LibClang resolves every call expression to the function it calls; the
+// parser records those as call sites. Once the symbols of every file are
+// stored, each site becomes a Calls relationship from the function the
+// call is written in to the function called, which find_references and
+// get_call_graph read.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::lib::cpp_indexer::callbacks::enclosing_element;
+use crate::lib::cpp_indexer::clang_parser::CallSite;
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+use crate::lib::storage::repository::Repository;
+
+/// Turns call sites into Calls relationships
+///
+/// `file_elements` are the stored elements of the file the sites came from.
+/// The caller is the element with the site's caller USR, else the function
+/// enclosing the call. `lookup` returns candidate elements for a name; those
+/// with the callee's USR are its targets, else the callable ones declared in
+/// the callee's scope. Definitions are preferred over declarations, and
+/// recursive calls are left out.
+pub fn resolve_call_sites(
+    sites: &[CallSite],
+    file_path: &str,
+    file_elements: &[CodeElement],
+    mut lookup: impl FnMut(&str) -> Vec<CodeElement>,
+) -> Vec<SymbolRelationship> {
+    let mut relationships = Vec::new();
+    let mut seen = HashSet::new();
+
+    for site in sites {
+        let caller = site
+            .caller_usr
+            .as_ref()
+            .and_then(|usr| file_elements.iter().find(|element| element.usr.as_ref() == Some(usr) && !element.is_declaration))
+            .or_else(|| enclosing_element(file_elements, site.line));
+        let Some(from_id) = caller.and_then(|element| element.id) else { continue };
+
+        for to_id in callee_ids(site, lookup(&site.callee)) {
+            if to_id != from_id && seen.insert((from_id, to_id, site.line)) {
+                relationships.push(SymbolRelationship::new(
+                    from_id,
+                    to_id,
+                    RelationshipType::Calls,
+                    file_path.to_string(),
+                    site.line,
+                ));
+            }
+        }
+    }
+
+    relationships
+}
+
+/// Resolves and stores the calls of indexed files, given by stored path
+///
+/// Run once every file is stored, so calls into files of later batches
+/// resolve too. Returns the number of relationships stored.
+pub fn store_call_relationships(repository: &Repository, index: &CodeIndex, files: Vec<(String, Vec<CallSite>)>) -> Result<usize> {
+    let mut candidates: HashMap<String, Vec<CodeElement>> = HashMap::new();
+    let mut stored = 0;
+    for (file_path, sites) in files {
+        for site in &sites {
+            if !candidates.contains_key(&site.callee) {
+                let found = repository.find_code_elements_by_name(&index.id, &site.callee)?;
+                candidates.insert(site.callee.clone(), found);
+            }
+        }
+        let file_elements = repository.list_code_elements_by_file(&index.id, &file_path)?;
+        let relationships = resolve_call_sites(&sites, &file_path, &file_elements, |name| {
+            candidates.get(name).cloned().unwrap_or_default()
+        });
+        if !relationships.is_empty() {
+            stored += repository.create_symbol_relationships_batch(relationships)?.len();
+        }
+    }
+    Ok(stored)
+}
+
+/// Ids of the candidates a call site targets
+fn callee_ids(site: &CallSite, candidates: Vec<CodeElement>) -> Vec<i64> {
+    let by_usr: Vec<&CodeElement> = match &site.callee_usr {
+        Some(usr) => candidates.iter().filter(|element| element.usr.as_ref() == Some(usr)).collect(),
+        None => Vec::new(),
+    };
+    let targets = if by_usr.is_empty() {
+        candidates
+            .iter()
+            .filter(|element| element.is_callable() && element.scope == site.callee_scope)
+            .collect()
+    } else {
+        by_usr
+    };
+    let definitions: Vec<&CodeElement> = targets.iter().copied().filter(|element| !element.is_declaration).collect();
+    let chosen = if definitions.is_empty() { targets } else { definitions };
+    chosen.into_iter().filter_map(|element| element.id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_resolve_call_sites() {
+        let index_id = Uuid::new_v4();
+        let element = |id: i64, name: &str, file: &str, line, scope: Option<&str>, usr: Option<&str>, declaration: bool| {
+            let mut element = CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64));
+            element.id = Some(id);
+            element.scope = scope.map(str::to_string);
+            element.usr = usr.map(str::to_string);
+            element.is_declaration = declaration;
+            element
+        };
+        let file_elements = vec![
+            element(1, "render", "app.cpp", 2, None, Some("c:@F@render#"), false),
+            element(2, "helper", "app.cpp", 10, None, None, false),
+        ];
+        let candidates = [
+            element(3, "draw", "widget.h", 4, Some("ui::Widget"), Some("c:@N@ui@S@Widget@F@draw#"), true),
+            element(4, "draw", "widget.cpp", 8, Some("ui::Widget"), Some("c:@N@ui@S@Widget@F@draw#"), false),
+            element(5, "draw", "canvas.cpp", 3, Some("ui::Canvas"), Some("c:@N@ui@S@Canvas@F@draw#"), false),
+            element(6, "log", "log.cpp", 1, None, None, false),
+        ];
+        let lookup = |name: &str| candidates.iter().chain(&file_elements).filter(|c| c.symbol_name == name).cloned().collect();
+        let site = |callee: &str, scope: Option<&str>, usr: Option<&str>, caller: Option<&str>, line| CallSite {
+            callee: callee.to_string(),
+            callee_scope: scope.map(str::to_string),
+            callee_usr: usr.map(str::to_string),
+            caller_usr: caller.map(str::to_string),
+            line,
+            column: 5,
+        };
+        let sites = vec![
+            // By USR, to the definition rather than the declaration
+            site("draw", Some("ui::Widget"), Some("c:@N@ui@S@Widget@F@draw#"), Some("c:@F@render#"), 3),
+            // By scope when the stored symbols have no USR; the caller is found by line
+            site("log", None, Some("c:@F@log#"), None, 11),
+            // Recursion and unknown functions are left out
+            site("render", None, Some("c:@F@render#"), Some("c:@F@render#"), 4),
+            site("printf", None, None, Some("c:@F@render#"), 5),
+        ];
+
+        let relationships = resolve_call_sites(&sites, "app.cpp", &file_elements, lookup);
+        let edges: Vec<(i64, i64, u32)> = relationships.iter().map(|r| (r.from_symbol_id, r.to_symbol_id, r.line_number)).collect();
+        assert_eq!(edges, vec![(1, 4, 3), (2, 6, 11)]);
+        assert!(relationships.iter().all(|r| r.relationship_type == RelationshipType::Calls && r.file_path == "app.cpp"));
+    }
+}
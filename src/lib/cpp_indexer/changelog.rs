@@ -0,0 +1,303 @@
+// API changelog between two revisions
+//
+// Extracts the public symbols of the files that changed between two git
+// revisions and reports what was added, removed or modified, grouped by
+// module (the directory a symbol is declared in).
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::lib::cpp_indexer::git::{changed_files, GitFs};
+use crate::lib::cpp_indexer::symbol_extractor::SymbolExtractor;
+use crate::lib::cpp_indexer::vfs::{is_source_file, SourceFs};
+use crate::lib::storage::models::code_element::{AccessModifier, CodeElement, SymbolType};
+use crate::lib::storage::models::directory_depth::directory_of;
+
+/// Symbol types that make up a public API
+pub const API_SYMBOL_TYPES: &[SymbolType] = &[
+    SymbolType::Class,
+    SymbolType::Struct,
+    SymbolType::Union,
+    SymbolType::Enum,
+    SymbolType::Typedef,
+    SymbolType::Function,
+    SymbolType::Constructor,
+    SymbolType::Destructor,
+    SymbolType::Operator,
+    SymbolType::Template,
+    SymbolType::Macro,
+];
+
+/// What happened to a symbol between the two revisions
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Same name and type, but a different signature or documentation
+    Modified,
+}
+
+/// One public symbol that changed
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct SymbolChange {
+    pub kind: ChangeKind,
+    /// Name qualified with its scope
+    pub name: String,
+    pub symbol_type: SymbolType,
+    /// Where the symbol is declared; the old location for removed symbols
+    pub file_path: String,
+    pub signature: Option<String>,
+    /// Signature before the change, for modified symbols whose signature changed
+    pub previous_signature: Option<String>,
+    pub documentation: Option<String>,
+}
+
+/// Public symbol changes between two revisions, grouped by module
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ChangeReport {
+    pub from: String,
+    pub to: String,
+    /// Module -> changes, sorted by kind then name
+    pub modules: BTreeMap<String, Vec<SymbolChange>>,
+    /// Changed files that could not be parsed at one of the revisions, with the error
+    pub failures: Vec<(String, String)>,
+}
+
+/// Returns true if a symbol belongs to the public API
+pub fn is_public_api(element: &CodeElement) -> bool {
+    matches!(element.access_modifier, None | Some(AccessModifier::Public)) && API_SYMBOL_TYPES.contains(&element.symbol_type)
+}
+
+/// Module a stored file path belongs to: its directory, "." at the root
+pub fn module_of(file_path: &str) -> &str {
+    match directory_of(file_path) {
+        "" => ".",
+        directory => directory,
+    }
+}
+
+impl ChangeReport {
+    /// Compares the public symbols of the files changed between two revisions of a repository
+    pub fn between_revisions(repository: &Path, from: &str, to: &str, extractor: &mut SymbolExtractor) -> std::io::Result<Self> {
+        let before = GitFs::open(repository, from)?;
+        let after = GitFs::open(repository, to)?;
+        let files: Vec<String> = changed_files(&before, &after)?.into_iter().filter(|file| is_source_file(file)).collect();
+
+        let mut failures = Vec::new();
+        let old_symbols = extract_revision(&before, &files, extractor, &mut failures)?;
+        let new_symbols = extract_revision(&after, &files, extractor, &mut failures)?;
+        let mut report = Self::compare(from, to, &old_symbols, &new_symbols);
+        report.failures = failures;
+        Ok(report)
+    }
+
+    /// Diffs two sets of symbols, keeping only public API symbols
+    ///
+    /// Symbols are matched by qualified name and type, then by signature
+    /// among overloads. When exactly one overload is left unmatched on each
+    /// side it is reported as modified; otherwise leftovers are added or
+    /// removed. Changes to a body alone don't show up: only the signature
+    /// and documentation are compared.
+    pub fn compare(from: &str, to: &str, before: &[CodeElement], after: &[CodeElement]) -> Self {
+        let old = api_symbols(before);
+        let new = api_symbols(after);
+        let keys: BTreeSet<&(String, &str)> = old.keys().chain(new.keys()).collect();
+
+        let mut changes = Vec::new();
+        for key in keys {
+            let mut removed: Vec<&CodeElement> = old.get(key).cloned().unwrap_or_default();
+            let mut added: Vec<&CodeElement> = new.get(key).cloned().unwrap_or_default();
+
+            // Same signature on both sides: unchanged unless the documentation moved on
+            removed.retain(|previous| match added.iter().position(|current| current.signature == previous.signature) {
+                Some(position) => {
+                    let current = added.remove(position);
+                    if current.documentation != previous.documentation {
+                        changes.push(change(ChangeKind::Modified, &key.0, current, None));
+                    }
+                    false
+                }
+                None => true,
+            });
+
+            if let ([previous], [current]) = (removed.as_slice(), added.as_slice()) {
+                changes.push(change(ChangeKind::Modified, &key.0, current, previous.signature.clone()));
+                continue;
+            }
+            changes.extend(removed.into_iter().map(|previous| change(ChangeKind::Removed, &key.0, previous, None)));
+            changes.extend(added.into_iter().map(|current| change(ChangeKind::Added, &key.0, current, None)));
+        }
+
+        let mut modules: BTreeMap<String, Vec<SymbolChange>> = BTreeMap::new();
+        for change in changes {
+            modules.entry(module_of(&change.file_path).to_string()).or_default().push(change);
+        }
+        for changes in modules.values_mut() {
+            changes.sort_by(|a, b| (a.kind, &a.name, &a.signature).cmp(&(b.kind, &b.name, &b.signature)));
+        }
+
+        Self { from: from.to_string(), to: to.to_string(), modules, failures: Vec::new() }
+    }
+
+    /// Total number of changed symbols
+    pub fn len(&self) -> usize {
+        self.modules.values().map(Vec::len).sum()
+    }
+
+    /// Returns true if no public symbol changed
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// Draft changelog in Markdown, one section per module
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("# API changes from {} to {}\n", self.from, self.to);
+        if self.is_empty() {
+            markdown.push_str("\nNo public symbols changed.\n");
+        }
+
+        for (module, changes) in &self.modules {
+            markdown.push_str(&format!("\n## {}\n", module));
+            for kind in [ChangeKind::Added, ChangeKind::Modified, ChangeKind::Removed] {
+                let of_kind: Vec<&SymbolChange> = changes.iter().filter(|change| change.kind == kind).collect();
+                if of_kind.is_empty() {
+                    continue;
+                }
+                let heading = match kind {
+                    ChangeKind::Added => "Added",
+                    ChangeKind::Modified => "Changed",
+                    ChangeKind::Removed => "Removed",
+                };
+                markdown.push_str(&format!("\n### {}\n\n", heading));
+                for change in of_kind {
+                    markdown.push_str(&format!("- `{}`", change.signature.as_deref().unwrap_or(&change.name)));
+                    if let Some(previous) = &change.previous_signature {
+                        markdown.push_str(&format!(" (was `{}`)", previous));
+                    }
+                    let summary = change.documentation.as_deref().and_then(|doc| doc.lines().map(str::trim).find(|line| !line.is_empty()));
+                    if let Some(summary) = summary {
+                        markdown.push_str(&format!(" — {}", summary));
+                    }
+                    markdown.push('\n');
+                }
+            }
+        }
+        markdown
+    }
+
+    /// The report as a JSON document
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("change report is always serializable")
+    }
+}
+
+/// Public symbols keyed by (qualified name, type name), one per distinct signature
+///
+/// A declaration and its definition share a signature; the one carrying
+/// documentation is kept.
+fn api_symbols(elements: &[CodeElement]) -> BTreeMap<(String, &'static str), Vec<&CodeElement>> {
+    let mut symbols: BTreeMap<(String, &'static str), Vec<&CodeElement>> = BTreeMap::new();
+    for element in elements.iter().filter(|element| is_public_api(element)) {
+        let overloads = symbols.entry((element.fully_qualified_name(), element.symbol_type.as_str())).or_default();
+        match overloads.iter_mut().find(|existing| existing.signature == element.signature) {
+            Some(existing) if existing.documentation.is_none() && element.documentation.is_some() => *existing = element,
+            Some(_) => {}
+            None => overloads.push(element),
+        }
+    }
+    symbols
+}
+
+fn change(kind: ChangeKind, name: &str, element: &CodeElement, previous_signature: Option<String>) -> SymbolChange {
+    SymbolChange {
+        kind,
+        name: name.to_string(),
+        symbol_type: element.symbol_type,
+        file_path: element.file_path.clone(),
+        previous_signature: previous_signature.filter(|previous| Some(previous) != element.signature.as_ref()),
+        signature: element.signature.clone(),
+        documentation: element.documentation.clone(),
+    }
+}
+
+/// Symbols of the given files at one revision; files missing at that revision contribute none
+fn extract_revision(
+    tree: &GitFs,
+    files: &[String],
+    extractor: &mut SymbolExtractor,
+    failures: &mut Vec<(String, String)>,
+) -> std::io::Result<Vec<CodeElement>> {
+    let present: BTreeSet<String> = tree.list_files()?.into_iter().collect();
+    let mut elements = Vec::new();
+    for file in files.iter().filter(|file| present.contains(*file)) {
+        let content = String::from_utf8_lossy(&tree.read(file)?).into_owned();
+        match extractor.extract_symbols_from_content(&content, Path::new(file)) {
+            Ok(extraction) => {
+                elements.extend(extraction.symbols.iter().map(|symbol| symbol.to_code_element(Uuid::nil(), &tree.stored_path(file))))
+            }
+            Err(e) => failures.push((format!("{}@{}", file, tree.commit().get(..12).unwrap_or(tree.commit())), e.to_string())),
+        }
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, file: &str, signature: &str) -> CodeElement {
+        CodeElement::new(Uuid::nil(), name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64))
+            .with_scope("net".to_string())
+            .with_signature(signature.to_string())
+    }
+
+    #[test]
+    fn test_compare_reports_added_removed_and_modified() {
+        let before = vec![
+            function("connect", "include/net/socket.h", "int connect(const char *host)"),
+            function("close", "include/net/socket.h", "void close(int fd)"),
+            function("resolve", "include/net/dns.h", "int resolve(const char *name)"),
+            function("resolve", "include/net/dns.h", "int resolve(const char *name, int family)"),
+        ];
+        let after = vec![
+            function("connect", "include/net/socket.h", "int connect(const char *host, int port)"),
+            function("resolve", "include/net/dns.h", "int resolve(const char *name)")
+                .with_documentation("Looks up a host name.\nBlocks until done.".to_string()),
+            function("send", "include/net/socket.h", "long send(int fd, const void *data, size_t size)"),
+            function("connect", "src/net/socket.cpp", "int connect(const char *host, int port)"),
+        ];
+
+        let report = ChangeReport::compare("v1.2", "HEAD", &before, &after);
+        assert_eq!(report.modules.keys().collect::<Vec<_>>(), ["include/net"]);
+        let changes = &report.modules["include/net"];
+        let summary: Vec<(ChangeKind, &str)> = changes.iter().map(|change| (change.kind, change.name.as_str())).collect();
+        assert_eq!(
+            summary,
+            [
+                (ChangeKind::Added, "net::send"),
+                (ChangeKind::Removed, "net::close"),
+                (ChangeKind::Removed, "net::resolve"),
+                (ChangeKind::Modified, "net::connect"),
+                (ChangeKind::Modified, "net::resolve"),
+            ]
+        );
+        let connect = changes.iter().find(|change| change.name == "net::connect").unwrap();
+        assert_eq!(connect.previous_signature.as_deref(), Some("int connect(const char *host)"));
+
+        let markdown = report.to_markdown();
+        assert!(markdown.starts_with("# API changes from v1.2 to HEAD\n"));
+        assert!(markdown.contains("- `int connect(const char *host, int port)` (was `int connect(const char *host)`)\n"));
+        assert!(markdown.contains("- `int resolve(const char *name)` — Looks up a host name.\n"));
+    }
+
+    #[test]
+    fn test_private_members_are_not_api() {
+        let private = function("flush", "include/net/socket.h", "void flush()").with_access_modifier(AccessModifier::Private);
+        let report = ChangeReport::compare("a", "b", &[], &[private]);
+        assert!(report.is_empty());
+        assert!(report.to_markdown().contains("No public symbols changed."));
+    }
+}
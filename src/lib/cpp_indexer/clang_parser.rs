@@ -1,6 +1,27 @@
 use clang::{Clang, EntityKind, Index};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+use crate::lib::cpp_indexer::dialect::DialectRules;
+use crate::lib::cpp_indexer::language::SourceLanguage;
+
+/// Taken while a `Clang` exists; the clang crate allows one per process
+static CLANG_TURN: Mutex<()> = Mutex::new(());
+
+/// Runs `parse` with the process's one `Clang`, waiting while another thread has it
+///
+/// Parser threads take turns instead of failing with "an instance of
+/// `Clang` already exists".
+pub fn with_clang<T, E: From<String>>(parse: impl FnOnce(&Clang) -> Result<T, E>) -> Result<T, E> {
+    let _turn = CLANG_TURN.lock().unwrap_or_else(PoisonError::into_inner);
+    let clang = Clang::new().map_err(|e| format!("Failed to initialize Clang: {:?}", e))?;
+    parse(&clang)
+}
 
 #[derive(Debug, Clone)]
 pub struct SemanticInfo {
@@ -15,6 +36,8 @@ pub struct SemanticInfo {
     pub references: Vec<SourceLocation>,
     pub template_info: Option<TemplateInfo>,
     pub inheritance_info: Option<InheritanceInfo>,
+    /// Unified Symbol Resolution id, the same for every declaration of a symbol
+    pub usr: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +48,22 @@ pub struct SourceLocation {
     pub offset: u32,
 }
 
+/// A call written in the parsed file, with the function it calls
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallSite {
+    /// Unqualified name of the called function
+    pub callee: String,
+    /// Scope the called function is declared in, like `audio::Mixer`
+    pub callee_scope: Option<String>,
+    pub callee_usr: Option<String>,
+    /// USR of the named function the call is written in; None at file scope and in lambdas
+    pub caller_usr: Option<String>,
+    /// 1-based line of the call
+    pub line: u32,
+    /// 1-based column of the call
+    pub column: u32,
+}
+
 #[derive(Debug, Clone)]
 pub enum AccessSpecifier {
     Public,
@@ -50,54 +89,159 @@ pub struct InheritanceInfo {
 #[derive(Debug)]
 pub struct ClangParser {
     compile_flags: Vec<String>,
+    compilation_database: Option<CompilationDatabase>,
+    dialects: DialectRules,
+    header_cache: Option<HeaderCache>,
+}
+
+/// A header's path, content hash and the flags of the translation unit that included it
+type HeaderKey = (PathBuf, String, String);
+
+/// A header's key and its symbols, if they were cached before the current parse
+type SeenHeader = (HeaderKey, Option<Arc<HeaderSymbols>>);
+
+/// What visiting one header yielded
+#[derive(Debug, Clone, Default)]
+struct HeaderSymbols {
+    symbols: Vec<SemanticInfo>,
+    type_hierarchy: Vec<(String, InheritanceInfo)>,
+}
+
+/// Semantic results of headers, shared by the parsers of one process
+///
+/// Every translation unit that includes `<vector>` yields the same symbols
+/// for it. With a cache attached, a parser visits each header once per
+/// content and flags, and later translation units take its symbols from the
+/// cache instead of walking its declarations again. This saves the walk,
+/// not the parse: libclang still parses every header each translation unit
+/// includes, which remains most of the indexing time. A header whose
+/// meaning depends on macros defined before it is included keeps the
+/// symbols of its first visit.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderCache {
+    entries: Arc<Mutex<HashMap<HeaderKey, Arc<HeaderSymbols>>>>,
+    hits: Arc<AtomicUsize>,
+    misses: Arc<AtomicUsize>,
+}
+
+/// The headers met while visiting one translation unit
+struct HeaderVisit<'a> {
+    cache: &'a HeaderCache,
+    fingerprint: String,
+    /// Each header met so far; None if unreadable
+    headers: HashMap<PathBuf, Option<SeenHeader>>,
 }
 
 impl ClangParser {
     pub fn new(compile_flags: Option<Vec<String>>) -> Result<Self, Box<dyn std::error::Error>> {
-        let default_flags = vec![
-            "-std=c++17".to_string(),
-        ];
-        
-        let flags = compile_flags.unwrap_or(default_flags);
+        let flags = compile_flags.unwrap_or_else(Self::default_flags);
         
         Ok(Self {
             compile_flags: flags,
+            compilation_database: None,
+            dialects: DialectRules::default(),
+            header_cache: None,
         })
     }
 
+    /// Flags used when none are given
+    pub fn default_flags() -> Vec<String> {
+        vec![
+            "-std=c++17".to_string(),
+        ]
+    }
+
+    /// Uses per-file flags from a compilation database, falling back to the
+    /// parser's own flags for files it doesn't cover
+    pub fn with_compilation_database(mut self, database: CompilationDatabase) -> Self {
+        self.compilation_database = Some(database);
+        self
+    }
+
+    /// Rewrites the flags of files matching a dialect rule
+    pub fn with_dialect_rules(mut self, dialects: DialectRules) -> Self {
+        self.dialects = dialects;
+        self
+    }
+
+    /// Takes the symbols of headers already visited from `cache`
+    pub fn with_header_cache(mut self, cache: HeaderCache) -> Self {
+        self.header_cache = Some(cache);
+        self
+    }
+
+    /// Flags libclang receives for a file
+    ///
+    /// The parser's own flags are written for C++ and adapted to the file's
+    /// language; a compilation database's flags are used as recorded. Dialect
+    /// rules apply to either.
+    pub fn flags_for(&self, file_path: &Path) -> Vec<String> {
+        let flags = match self.compilation_database.as_ref().and_then(|database| database.flags_for(file_path)) {
+            Some(flags) => flags.to_vec(),
+            None => SourceLanguage::from_path(file_path).adapt_flags(&self.compile_flags),
+        };
+        self.dialects.apply(file_path, flags)
+    }
+
     pub fn parse_file(&self, file_path: &Path) -> Result<SemanticParseResult, Box<dyn std::error::Error>> {
-        let clang = Clang::new().map_err(|e| format!("Failed to initialize Clang: {:?}", e))?;
-        let index = Index::new(&clang, false, false);
-        
+        with_clang(|clang| self.parse_with(clang, file_path))
+    }
+
+    fn parse_with(&self, clang: &Clang, file_path: &Path) -> Result<SemanticParseResult, Box<dyn std::error::Error>> {
+        let index = Index::new(clang, false, false);
+
+        let flags = self.flags_for(file_path);
         let translation_unit = index
             .parser(file_path)
-            .arguments(&self.compile_flags)
+            .arguments(&flags)
             .parse()
             .map_err(|e| format!("Failed to parse file: {:?}", e))?;
 
         let mut symbols = Vec::new();
         let mut references = HashMap::new();
         let mut type_hierarchy = HashMap::new();
+        let mut headers = self.header_cache.as_ref().map(|cache| HeaderVisit::new(cache, &flags));
 
         let entity = translation_unit.get_entity();
-        self.visit_entity_recursive(&entity, &mut symbols, &mut references, &mut type_hierarchy)?;
+        self.visit_entity_recursive(&entity, &[], &mut symbols, &mut references, &mut type_hierarchy, &mut headers)?;
+        let mut calls = Vec::new();
+        self.collect_call_sites(&entity, None, &mut calls);
 
-        Ok(SemanticParseResult {
+        let mut result = SemanticParseResult {
             file_path: file_path.to_path_buf(),
             symbols,
             references,
             type_hierarchy,
-        })
+            calls,
+        };
+        if let Some(headers) = headers {
+            headers.finish(&mut result);
+        }
+        Ok(result)
     }
 
+    /// Visits `entity` and its children; `scope` names the entities lexically enclosing it
     fn visit_entity_recursive(
         &self,
         entity: &clang::Entity,
+        scope: &[String],
         symbols: &mut Vec<SemanticInfo>,
         references: &mut HashMap<String, Vec<SourceLocation>>,
         type_hierarchy: &mut HashMap<String, InheritanceInfo>,
+        headers: &mut Option<HeaderVisit>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(location_info) = self.get_location_info(entity) {
+        let location_info = self.get_location_info(entity);
+        let in_main_file = entity.get_location().is_some_and(|location| location.is_in_main_file());
+        let cached = match (headers.as_mut(), &location_info) {
+            (Some(visit), Some(location)) if !in_main_file => visit.is_cached(&location.file_path),
+            _ => false,
+        };
+        // Namespaces are reopened by other headers, whose declarations may not be cached yet
+        if cached && !matches!(entity.get_kind(), EntityKind::Namespace | EntityKind::LinkageSpec) {
+            return Ok(());
+        }
+
+        if let Some(location_info) = location_info.filter(|_| !cached) {
             match entity.get_kind() {
                 EntityKind::ClassDecl | 
                 EntityKind::StructDecl | 
@@ -111,8 +255,9 @@ impl ClangParser {
                 EntityKind::EnumDecl |
                 EntityKind::EnumConstantDecl |
                 EntityKind::Namespace |
-                EntityKind::TypedefDecl => {
-                    let semantic_info = self.extract_semantic_info(entity, location_info)?;
+                EntityKind::TypedefDecl |
+                EntityKind::LambdaExpr => {
+                    let semantic_info = self.extract_semantic_info(entity, scope, location_info)?;
                     
                     if let Some(ref name) = entity.get_name() {
                         references.entry(name.clone()).or_insert_with(Vec::new);
@@ -132,13 +277,61 @@ impl ClangParser {
             }
         }
 
+        let qualified = self.qualified_path(entity, scope);
+        let child_scope = match &qualified {
+            Some(path) => path.as_slice(),
+            None => scope,
+        };
         for child in entity.get_children() {
-            self.visit_entity_recursive(&child, symbols, references, type_hierarchy)?;
+            self.visit_entity_recursive(&child, child_scope, symbols, references, type_hierarchy, headers)?;
         }
 
         Ok(())
     }
 
+    /// Collects the calls written in the main file below `entity`
+    ///
+    /// `caller` is the USR of the named function being walked. Calls through
+    /// function pointers have no function to point at and are left out.
+    fn collect_call_sites(&self, entity: &clang::Entity, caller: Option<&str>, calls: &mut Vec<CallSite>) {
+        for child in entity.get_children() {
+            if !child.get_location().is_some_and(|location| location.is_in_main_file()) {
+                continue;
+            }
+            let usr = child.get_usr().and_then(|usr| normalize_usr(&usr.0));
+            let caller = match child.get_kind() {
+                EntityKind::LambdaExpr => None,
+                kind if is_function_kind(kind) => usr.as_deref(),
+                EntityKind::CallExpr => {
+                    if let Some(call) = self.call_site(&child, caller) {
+                        calls.push(call);
+                    }
+                    caller
+                }
+                _ => caller,
+            };
+            self.collect_call_sites(&child, caller, calls);
+        }
+    }
+
+    /// The call a CallExpr makes, if it names a function
+    fn call_site(&self, call: &clang::Entity, caller: Option<&str>) -> Option<CallSite> {
+        let callee = call.get_reference()?;
+        if !is_function_kind(callee.get_kind()) {
+            return None;
+        }
+        let location = self.get_location_info(call)?;
+        let scope = self.semantic_scope(&callee);
+        Some(CallSite {
+            callee: self.entity_name(&callee),
+            callee_scope: (!scope.is_empty()).then(|| scope.join("::")),
+            callee_usr: callee.get_usr().and_then(|usr| normalize_usr(&usr.0)),
+            caller_usr: caller.map(str::to_string),
+            line: location.line,
+            column: location.column,
+        })
+    }
+
     fn get_location_info(&self, entity: &clang::Entity) -> Option<SourceLocation> {
         if let Some(location) = entity.get_location() {
             let file_location = location.get_file_location();
@@ -154,16 +347,94 @@ impl ClangParser {
         None
     }
 
+    /// Name of an entity, or a synthetic one if it has none in the source
+    fn entity_name(&self, entity: &clang::Entity) -> String {
+        let name = entity.get_name().unwrap_or_default();
+        if !is_unnamed(&name) {
+            return name;
+        }
+        self.get_location_info(entity)
+            .and_then(|location| synthetic_name(entity.get_kind(), &location))
+            .unwrap_or(name)
+    }
+
+    /// Names of the scopes enclosing `entity`, outermost first
+    ///
+    /// The entities visited around it, unless it is a member defined out of
+    /// line, whose scope is that of its class rather than where it's written.
+    /// libclang gives expressions such as lambdas no lexical parent, and
+    /// they are always where they're written.
+    fn enclosing_scope(&self, entity: &clang::Entity, scope: &[String]) -> Vec<String> {
+        let semantic_parent = entity.get_semantic_parent();
+        let lexical_parent = entity.get_lexical_parent();
+        if semantic_parent.is_none() || lexical_parent.is_none() || semantic_parent == lexical_parent {
+            return scope.to_vec();
+        }
+        self.semantic_scope(entity)
+    }
+
+    /// Names of the semantic parents of `entity`, outermost first
+    fn semantic_scope(&self, entity: &clang::Entity) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut parent = entity.get_semantic_parent();
+        while let Some(current) = parent {
+            match current.get_kind() {
+                EntityKind::TranslationUnit => break,
+                EntityKind::LinkageSpec => {}
+                _ => path.push(self.entity_name(&current)),
+            }
+            parent = current.get_semantic_parent();
+        }
+        path.reverse();
+        path
+    }
+
+    /// Scope the children of `entity` are in, if it opens one
+    fn qualified_path(&self, entity: &clang::Entity, scope: &[String]) -> Option<Vec<String>> {
+        match entity.get_kind() {
+            EntityKind::Namespace
+            | EntityKind::ClassDecl
+            | EntityKind::StructDecl
+            | EntityKind::UnionDecl
+            | EntityKind::EnumDecl
+            | EntityKind::ClassTemplate
+            | EntityKind::ClassTemplatePartialSpecialization
+            | EntityKind::FunctionDecl
+            | EntityKind::FunctionTemplate
+            | EntityKind::Method
+            | EntityKind::Constructor
+            | EntityKind::Destructor
+            | EntityKind::ConversionFunction
+            | EntityKind::LambdaExpr => {
+                let mut path = self.enclosing_scope(entity, scope);
+                path.push(self.entity_name(entity));
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+
     fn extract_semantic_info(
         &self,
         entity: &clang::Entity,
+        scope: &[String],
         location: SourceLocation,
     ) -> Result<SemanticInfo, Box<dyn std::error::Error>> {
-        let symbol_name = entity.get_name().unwrap_or_default();
+        let symbol_name = self.entity_name(entity);
         let symbol_kind = entity.get_kind();
-        let fully_qualified_name = entity.get_display_name().unwrap_or(symbol_name.clone());
-        
-        let type_info = entity.get_type().map(|t| t.get_display_name());
+        let mut qualified = self.enclosing_scope(entity, scope);
+        qualified.push(symbol_name.clone());
+        let fully_qualified_name = qualified.join("::");
+
+        let type_info = if symbol_kind == EntityKind::LambdaExpr {
+            let tokens: Vec<String> = entity
+                .get_range()
+                .map(|range| range.tokenize().iter().map(|token| token.get_spelling()).collect())
+                .unwrap_or_default();
+            lambda_signature(&tokens)
+        } else {
+            entity.get_type().map(|t| without_paths(&t.get_display_name()))
+        };
         
         let access_specifier = match entity.get_accessibility() {
             Some(clang::Accessibility::Public) => Some(AccessSpecifier::Public),
@@ -172,10 +443,12 @@ impl ClangParser {
             _ => None,
         };
 
-        let is_definition = entity.is_definition();
+        // A lambda expression is its own definition
+        let is_definition = symbol_kind == EntityKind::LambdaExpr || entity.is_definition();
         let is_declaration = !is_definition;
 
         let template_info = self.extract_template_info(entity)?;
+        let usr = entity.get_usr().and_then(|usr| normalize_usr(&usr.0));
 
         Ok(SemanticInfo {
             symbol_name,
@@ -189,6 +462,7 @@ impl ClangParser {
             references: Vec::new(),
             template_info,
             inheritance_info: None,
+            usr,
         })
     }
 
@@ -284,12 +558,102 @@ impl ClangParser {
     }
 }
 
+impl HeaderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Header visits served from the cache, and headers visited and stored
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+
+    /// Number of header versions cached
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|entries| entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, key: &HeaderKey) -> Option<Arc<HeaderSymbols>> {
+        self.entries.lock().ok()?.get(key).cloned()
+    }
+
+    fn insert(&self, key: HeaderKey, symbols: HeaderSymbols) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, Arc::new(symbols));
+        }
+    }
+}
+
+impl<'a> HeaderVisit<'a> {
+    fn new(cache: &'a HeaderCache, flags: &[String]) -> Self {
+        Self {
+            cache,
+            fingerprint: flags.join("\u{1f}"),
+            headers: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the symbols of `header` come from the cache
+    ///
+    /// The answer is fixed at the header's first declaration, so a header
+    /// another parser caches meanwhile is still visited in full here.
+    fn is_cached(&mut self, header: &Path) -> bool {
+        let (cache, fingerprint) = (self.cache, &self.fingerprint);
+        let entry = self.headers.entry(header.to_path_buf()).or_insert_with(|| {
+            // Hashing the content keeps a header edited during the run from being reused
+            let content = std::fs::read(header).ok()?;
+            let key = (header.to_path_buf(), format!("{:x}", Sha256::digest(&content)), fingerprint.clone());
+            let cached = cache.get(&key);
+            Some((key, cached))
+        });
+        matches!(entry, Some((_, Some(_))))
+    }
+
+    /// Adds the cached headers' symbols to `result` and caches the headers visited in full
+    fn finish(self, result: &mut SemanticParseResult) {
+        let mut visited: HashMap<PathBuf, HeaderSymbols> = HashMap::new();
+        for symbol in &result.symbols {
+            if let Some(Some((_, None))) = self.headers.get(&symbol.location.file_path) {
+                let header = visited.entry(symbol.location.file_path.clone()).or_default();
+                if let Some(inheritance) = result.type_hierarchy.get(&symbol.symbol_name) {
+                    header.type_hierarchy.push((symbol.symbol_name.clone(), inheritance.clone()));
+                }
+                header.symbols.push(symbol.clone());
+            }
+        }
+
+        for (path, entry) in self.headers {
+            match entry {
+                Some((_, Some(cached))) => {
+                    self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                    for symbol in &cached.symbols {
+                        result.references.entry(symbol.symbol_name.clone()).or_default();
+                        result.symbols.push(symbol.clone());
+                    }
+                    result.type_hierarchy.extend(cached.type_hierarchy.iter().cloned());
+                }
+                Some((key, None)) => {
+                    self.cache.misses.fetch_add(1, Ordering::Relaxed);
+                    self.cache.insert(key, visited.remove(&path).unwrap_or_default());
+                }
+                None => {}
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SemanticParseResult {
     pub file_path: PathBuf,
     pub symbols: Vec<SemanticInfo>,
     pub references: HashMap<String, Vec<SourceLocation>>,
     pub type_hierarchy: HashMap<String, InheritanceInfo>,
+    /// Calls written in the file itself, in source order
+    pub calls: Vec<CallSite>,
 }
 
 impl SemanticParseResult {
@@ -322,6 +686,127 @@ impl SemanticParseResult {
     }
 }
 
+/// Returns true for names libclang gives entities without one: nothing, or
+/// a description like `(unnamed struct at /src/mixer.h:4:5)`
+fn is_unnamed(name: &str) -> bool {
+    name.is_empty() || name.starts_with('(')
+}
+
+/// Returns true for the kinds of entity a call can target
+fn is_function_kind(kind: EntityKind) -> bool {
+    matches!(
+        kind,
+        EntityKind::FunctionDecl
+            | EntityKind::FunctionTemplate
+            | EntityKind::Method
+            | EntityKind::Constructor
+            | EntityKind::Destructor
+            | EntityKind::ConversionFunction
+    )
+}
+
+/// Stable name of an entity that has none in the source
+///
+/// Spelled like clang spells them in diagnostics, such as
+/// `(lambda at mixer.cpp:120:5)`, but with the file name instead of the
+/// path, so the name is the same in every checkout. All anonymous
+/// namespaces of a translation unit are one namespace, so theirs has no
+/// location. None for kinds that are always named.
+pub fn synthetic_name(kind: EntityKind, location: &SourceLocation) -> Option<String> {
+    let what = match kind {
+        EntityKind::Namespace => return Some("(anonymous namespace)".to_string()),
+        EntityKind::LambdaExpr => "lambda",
+        EntityKind::ClassDecl => "unnamed class",
+        EntityKind::StructDecl => "unnamed struct",
+        EntityKind::UnionDecl => "unnamed union",
+        EntityKind::EnumDecl => "unnamed enum",
+        _ => return None,
+    };
+    let file_name = location.file_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    Some(format!("({} at {}:{}:{})", what, file_name, location.line, location.column))
+}
+
+/// Type spelling with the paths in clang's `(lambda at /src/mixer.cpp:12:5)`
+/// descriptions cut down to file names, like `synthetic_name` writes them
+pub fn without_paths(spelling: &str) -> String {
+    let mut result = String::with_capacity(spelling.len());
+    let mut rest = spelling;
+    while let Some(start) = rest.find(" at ") {
+        let (before, after) = rest.split_at(start + " at ".len());
+        result.push_str(before);
+        let end = after.find(')').unwrap_or(after.len());
+        let location = &after[..end];
+        // The path is what precedes ":line:column"
+        let path_end = location.rmatch_indices(':').nth(1).map_or(location.len(), |(index, _)| index);
+        let path = &location[..path_end];
+        result.push_str(path.rsplit(['/', '\\']).next().unwrap_or(path));
+        result.push_str(&location[path_end..]);
+        rest = &after[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Signature of a lambda from its tokens: captures, parameters and
+/// specifiers up to the body, like `[this, &gain](float x) mutable -> float`
+///
+/// None if the tokens don't start with a capture list.
+pub fn lambda_signature(tokens: &[String]) -> Option<String> {
+    if tokens.first().map(String::as_str) != Some("[") {
+        return None;
+    }
+    let mut signature = String::new();
+    let mut depth = 0usize;
+    let mut in_captures = true;
+    // `&` or `*` opening a capture, as in `&gain` or `*this`
+    let mut sigil = false;
+    let mut previous = "";
+    for token in tokens.iter().map(String::as_str) {
+        if depth == 0 && token == "{" {
+            break;
+        }
+        let tight_before = matches!(token, "," | ")" | "]" | "::" | "<" | ">" | "...")
+            || (token == "(" && !matches!(previous, "," | "->"))
+            || (!in_captures && matches!(token, "&" | "&&" | "*") && !matches!(previous, "," | "("));
+        let tight_after = sigil || matches!(previous, "[" | "(" | "::" | "<");
+        if !signature.is_empty() && !tight_before && !tight_after {
+            signature.push(' ');
+        }
+        signature.push_str(token);
+        sigil = in_captures && matches!(token, "&" | "*") && matches!(previous, "[" | ",");
+        match token {
+            "[" | "(" => depth += 1,
+            "]" | ")" => {
+                depth = depth.saturating_sub(1);
+                in_captures &= depth > 0;
+            }
+            _ => {}
+        }
+        previous = token;
+    }
+    Some(signature)
+}
+
+/// Normalizes a USR for storage
+///
+/// Symbols with internal linkage get USRs starting with the path of their
+/// file as the compiler was given it, which differs between checkouts, so
+/// only the file name is kept. Entities libclang can't name get an empty
+/// USR, returned as None.
+pub fn normalize_usr(usr: &str) -> Option<String> {
+    let usr = usr.trim();
+    if usr.is_empty() {
+        return None;
+    }
+    match usr.strip_prefix("c:").and_then(|rest| rest.split_once('@')) {
+        Some((file, rest)) if file.contains(['/', '\\']) => {
+            let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+            Some(format!("c:{}@{}", name, rest))
+        }
+        _ => Some(usr.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -342,4 +827,115 @@ mod tests {
         let parser = parser.unwrap();
         assert_eq!(parser.compile_flags, flags);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parser_threads_take_turns_with_clang() {
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| {
+                with_clang(|_| {
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                    Ok::<_, String>(())
+                })
+            }))
+            .collect();
+        assert!(threads.into_iter().all(|thread| thread.join().unwrap().is_ok()));
+    }
+
+    #[test]
+    fn test_compilation_database_flags() {
+        let database = CompilationDatabase::from_json(
+            r#"[{"directory": "/work", "file": "main.cpp", "arguments": ["c++", "-DMAIN", "-c", "main.cpp"]}]"#,
+        )
+        .unwrap();
+        let parser = ClangParser::new(None).unwrap().with_compilation_database(database);
+
+        assert_eq!(parser.flags_for(&PathBuf::from("/work/main.cpp")), ["-DMAIN"]);
+        assert_eq!(parser.flags_for(&PathBuf::from("/elsewhere/lib.cpp")), ["-DMAIN"]);
+        assert_eq!(ClangParser::new(None).unwrap().flags_for(&PathBuf::from("/work/main.cpp")), ["-std=c++17"]);
+        assert_eq!(ClangParser::new(None).unwrap().flags_for(&PathBuf::from("/work/codec.c")), ["-x", "c"]);
+    }
+
+    #[test]
+    fn test_header_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = dir.path().join("vector.h");
+        std::fs::write(&header, "template <class T> class vector {};").unwrap();
+        let symbol = |name: &str, file_path: &Path| SemanticInfo {
+            symbol_name: name.to_string(),
+            symbol_kind: EntityKind::ClassDecl,
+            fully_qualified_name: name.to_string(),
+            location: SourceLocation { file_path: file_path.to_path_buf(), line: 1, column: 1, offset: 0 },
+            type_info: None,
+            access_specifier: None,
+            is_definition: true,
+            is_declaration: false,
+            references: Vec::new(),
+            template_info: None,
+            inheritance_info: None,
+            usr: None,
+        };
+        let result = |symbols: Vec<SemanticInfo>| SemanticParseResult {
+            file_path: PathBuf::from("main.cpp"),
+            symbols,
+            references: HashMap::new(),
+            type_hierarchy: HashMap::new(),
+            calls: Vec::new(),
+        };
+        let flags = ["-std=c++17".to_string()];
+
+        // The first translation unit visits the header and caches what it yields
+        let cache = HeaderCache::new();
+        let mut first = HeaderVisit::new(&cache, &flags);
+        assert!(!first.is_cached(&header));
+        let mut parsed = result(vec![symbol("vector", &header), symbol("main", Path::new("main.cpp"))]);
+        first.finish(&mut parsed);
+        assert_eq!((cache.len(), cache.stats()), (1, (0, 1)));
+
+        // The next one skips it and gets its symbols from the cache
+        let mut second = HeaderVisit::new(&cache, &flags);
+        assert!(second.is_cached(&header));
+        let mut parsed = result(vec![symbol("other", Path::new("other.cpp"))]);
+        second.finish(&mut parsed);
+        let names: Vec<&str> = parsed.symbols.iter().map(|symbol| symbol.symbol_name.as_str()).collect();
+        assert_eq!(names, ["other", "vector"]);
+        assert!(parsed.references.contains_key("vector"));
+        assert_eq!(cache.stats(), (1, 1));
+
+        // Other flags or an edited header are parsed again
+        assert!(!HeaderVisit::new(&cache, &["-std=c++20".to_string()]).is_cached(&header));
+        std::fs::write(&header, "template <class T, class A> class vector {};").unwrap();
+        assert!(!HeaderVisit::new(&cache, &flags).is_cached(&header));
+    }
+
+    #[test]
+    fn test_normalize_usr() {
+        assert_eq!(normalize_usr("c:@N@audio@S@Mixer@F@mix#f#").as_deref(), Some("c:@N@audio@S@Mixer@F@mix#f#"));
+        assert_eq!(normalize_usr("c:/home/ci/src/mixer.cpp@F@clamp#I#").as_deref(), Some("c:mixer.cpp@F@clamp#I#"));
+        assert_eq!(normalize_usr("c:mixer.cpp@F@clamp#I#").as_deref(), Some("c:mixer.cpp@F@clamp#I#"));
+        assert_eq!(normalize_usr(""), None);
+    }
+
+    #[test]
+    fn test_anonymous_entity_names() {
+        let location = SourceLocation { file_path: PathBuf::from("/home/ci/src/mixer.cpp"), line: 120, column: 5, offset: 0 };
+        assert_eq!(synthetic_name(EntityKind::LambdaExpr, &location).as_deref(), Some("(lambda at mixer.cpp:120:5)"));
+        assert_eq!(synthetic_name(EntityKind::StructDecl, &location).as_deref(), Some("(unnamed struct at mixer.cpp:120:5)"));
+        assert_eq!(synthetic_name(EntityKind::Namespace, &location).as_deref(), Some("(anonymous namespace)"));
+        assert_eq!(synthetic_name(EntityKind::FunctionDecl, &location), None);
+        assert!(is_unnamed("(unnamed union at /home/ci/src/mixer.h:4:5)") && !is_unnamed("operator()"));
+
+        assert_eq!(
+            without_paths("std::function<void ()> (lambda at /home/ci/src/mixer.cpp:12:19)"),
+            "std::function<void ()> (lambda at mixer.cpp:12:19)"
+        );
+        assert_eq!(without_paths("const float *"), "const float *");
+
+        let tokens = |source: &str| source.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            lambda_signature(&tokens("[ this , & gain , n = std :: move ( n ) ] ( const std :: vector < float > & in , int * out ) mutable -> float { return gain ; }")).as_deref(),
+            Some("[this, &gain, n = std::move(n)](const std::vector<float>& in, int* out) mutable -> float")
+        );
+        assert_eq!(lambda_signature(&tokens("[ & ] { tick ( ) ; }")).as_deref(), Some("[&]"));
+        assert_eq!(lambda_signature(&tokens("mix ( )")), None);
+    }
+}
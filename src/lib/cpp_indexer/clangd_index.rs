@@ -0,0 +1,832 @@
+// clangd background index import
+//
+// Teams already running clangd have a complete symbol index of their tree in
+// `.cache/clangd/index`. Importing those shards bootstraps a new index in
+// seconds; only files changed since clangd indexed them, or that clangd never
+// saw, are left for our own parsers.
+//
+// A shard is a RIFF container of type `CdIx` holding the chunks `meta`
+// (format version), `stri` (string table, optionally zlib-compressed),
+// `symb` (symbols), `refs` (references) and `rela` (relations). Integers are
+// little-endian varints of 7-bit groups; strings are varint indices into the
+// string table.
+
+use chrono::{DateTime, Utc};
+use flate2::read::ZlibDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::Read;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::lib::cpp_indexer::vfs::{is_source_file, LocalFs, SourceFs};
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::file_metadata::FileMetadata;
+use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+use crate::lib::storage::ordering::path_key;
+use crate::lib::storage::recovery::io_error;
+use crate::lib::storage::repository::{IndexContents, Repository};
+
+/// Extension of clangd's background index shards
+pub const CLANGD_SHARD_EXTENSION: &str = "idx";
+
+/// Where clangd keeps its background index, relative to the project root
+pub const CLANGD_INDEX_DIR: &str = ".cache/clangd/index";
+
+/// Shard format versions this reader understands
+pub const SUPPORTED_CLANGD_VERSIONS: RangeInclusive<u32> = 17..=19;
+
+/// `RefKind` bit of a plain reference, as opposed to a declaration or definition
+const REF_KIND_REFERENCE: u8 = 1 << 2;
+
+/// `RefKind` bit of a reference that is a call
+const REF_KIND_CALL: u8 = 1 << 4;
+
+/// `RelationKind` of "subject is a base class of object"
+const RELATION_BASE_OF: u8 = 0;
+
+/// `RelationKind` of "subject is overridden by object"
+const RELATION_OVERRIDDEN_BY: u8 = 1;
+
+/// `SymbolKind` of a C++20 concept, which has no symbol type of ours
+const SYMBOL_KIND_CONCEPT: u8 = 30;
+
+/// clangd's 8-byte symbol identifier
+pub type ClangdSymbolId = [u8; 8];
+
+/// A source position as clangd records it: file URI and zero-based line and column
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClangdLocation {
+    pub uri: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// One symbol of a shard
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClangdSymbol {
+    pub id: ClangdSymbolId,
+    /// clang's `index::SymbolKind`
+    pub kind: u8,
+    pub name: String,
+    /// Enclosing scope with a trailing `::`, e.g. `audio::Mixer::`
+    pub scope: String,
+    pub definition: Option<ClangdLocation>,
+    pub declaration: Option<ClangdLocation>,
+    /// Parameter list, e.g. `(float *out, size_t frames)`
+    pub signature: String,
+    pub documentation: String,
+    pub return_type: String,
+}
+
+/// One reference to a symbol
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClangdRef {
+    /// Bits of clangd's `RefKind`
+    pub kind: u8,
+    pub location: ClangdLocation,
+    /// Symbol whose body holds the reference; all zeroes if none
+    pub container: ClangdSymbolId,
+}
+
+/// A relation between two symbols
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClangdRelation {
+    pub subject: ClangdSymbolId,
+    /// clangd's `RelationKind`
+    pub predicate: u8,
+    pub object: ClangdSymbolId,
+}
+
+/// The decoded content of one shard
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClangdShard {
+    pub version: u32,
+    pub symbols: Vec<ClangdSymbol>,
+    pub refs: Vec<(ClangdSymbolId, ClangdRef)>,
+    pub relations: Vec<ClangdRelation>,
+}
+
+/// Symbols, references and relations of a set of shards, merged by symbol
+#[derive(Debug, Default)]
+pub struct ClangdIndex {
+    symbols: BTreeMap<ClangdSymbolId, ClangdSymbol>,
+    refs: BTreeSet<(ClangdSymbolId, ClangdRef)>,
+    relations: BTreeSet<ClangdRelation>,
+    /// Write time of the newest shard mentioning each file, by URI
+    indexed_at: HashMap<String, SystemTime>,
+    shards: usize,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone)]
+pub struct ClangdImport {
+    pub index: CodeIndex,
+    pub symbols: usize,
+    pub relationships: usize,
+    /// Files whose symbols were taken from clangd
+    pub files_imported: usize,
+    /// Symbols left out because they lie outside the base path or in a changed file, or are concepts
+    pub skipped_symbols: usize,
+    /// Source files to parse ourselves, sorted: changed since clangd indexed them or not indexed by it
+    pub delta_files: Vec<String>,
+}
+
+impl ClangdIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads every shard in clangd's index directory
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| io_error(&format!("Failed to read {}", dir.display()), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map(|extension| extension == CLANGD_SHARD_EXTENSION).unwrap_or(false))
+            .collect();
+        paths.sort();
+
+        let mut index = Self::new();
+        for path in paths {
+            let context = format!("Failed to read {}", path.display());
+            let bytes = fs::read(&path).map_err(|e| io_error(&context, e))?;
+            let written = fs::metadata(&path).and_then(|metadata| metadata.modified()).map_err(|e| io_error(&context, e))?;
+            let shard = parse_shard(&bytes)
+                .map_err(|e| StorageError::Corruption(format!("Invalid clangd shard {}: {}", path.display(), e)))?;
+            index.add_shard(shard, written);
+        }
+        Ok(index)
+    }
+
+    /// Merges a shard written at `written`
+    ///
+    /// A symbol seen in several shards keeps the first non-empty value of
+    /// each field, so a header's declaration and a source file's definition
+    /// end up on one symbol.
+    pub fn add_shard(&mut self, shard: ClangdShard, written: SystemTime) {
+        let mut uris: Vec<&str> = Vec::new();
+        for symbol in &shard.symbols {
+            uris.extend(symbol.definition.iter().chain(&symbol.declaration).map(|location| location.uri.as_str()));
+        }
+        uris.extend(shard.refs.iter().map(|(_, reference)| reference.location.uri.as_str()));
+        for uri in uris {
+            let entry = self.indexed_at.entry(uri.to_string()).or_insert(written);
+            *entry = (*entry).max(written);
+        }
+
+        for symbol in shard.symbols {
+            match self.symbols.get_mut(&symbol.id) {
+                Some(merged) => merged.merge(symbol),
+                None => {
+                    self.symbols.insert(symbol.id, symbol);
+                }
+            }
+        }
+        self.refs.extend(shard.refs);
+        self.relations.extend(shard.relations);
+        self.shards += 1;
+    }
+
+    /// Number of shards merged
+    pub fn shard_count(&self) -> usize {
+        self.shards
+    }
+
+    /// Number of distinct symbols
+    pub fn symbol_count(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Stores the merged symbols as a new index named `name` rooted at `base_path`
+    ///
+    /// Files changed on disk since their shard was written are left out, as
+    /// are symbols outside `base_path` such as system headers. Those files,
+    /// and source files clangd never indexed, are returned as `delta_files`
+    /// for the caller to parse.
+    pub fn import(&self, repository: &Repository, name: &str, base_path: &Path) -> Result<ClangdImport> {
+        if repository.get_code_index_by_name(name)?.is_some() {
+            return Err(StorageError::Conflict(format!("Index {} already exists", name)));
+        }
+
+        // Stored path -> metadata of each file clangd's view of is still current
+        let mut fresh: BTreeMap<String, FileMetadata> = BTreeMap::new();
+        let mut stale: BTreeSet<String> = BTreeSet::new();
+        for (uri, written) in &self.indexed_at {
+            let Some(stored_path) = stored_path(uri, base_path) else {
+                continue;
+            };
+            match current_metadata(base_path, &stored_path, *written) {
+                Some(metadata) => {
+                    fresh.insert(stored_path, metadata);
+                }
+                None => {
+                    stale.insert(stored_path);
+                }
+            }
+        }
+        let location_of = |location: &ClangdLocation| {
+            let stored_path = stored_path(&location.uri, base_path)?;
+            fresh.contains_key(&stored_path).then_some((stored_path, location.line + 1, location.column + 1))
+        };
+
+        let mut elements: Vec<CodeElement> = Vec::new();
+        // clangd symbol -> element standing for it in relationships, the definition if imported
+        let mut element_ids: HashMap<ClangdSymbolId, i64> = HashMap::new();
+        let mut skipped_symbols = 0;
+        for symbol in self.symbols.values() {
+            let definition = symbol.definition.as_ref().and_then(location_of);
+            let declaration = symbol.declaration.as_ref().and_then(location_of).filter(|declaration| {
+                definition.as_ref().map(|definition| (&definition.0, definition.1) != (&declaration.0, declaration.1)).unwrap_or(true)
+            });
+            if symbol.kind == SYMBOL_KIND_CONCEPT || (definition.is_none() && declaration.is_none()) {
+                skipped_symbols += 1;
+                continue;
+            }
+
+            for (location, is_declaration) in [(definition, false), (declaration, true)] {
+                let Some((file_path, line, column)) = location else {
+                    continue;
+                };
+                let id = elements.len() as i64 + 1;
+                let mut element = symbol.to_element(file_path, line, column).with_declaration(is_declaration);
+                element.id = Some(id);
+                elements.push(element);
+                element_ids.entry(symbol.id).or_insert(id);
+            }
+        }
+
+        let element_of = |id: &ClangdSymbolId| element_ids.get(id).map(|&element_id| (element_id, &elements[element_id as usize - 1]));
+        // Keyed so a reference recorded by several shards is stored once
+        let mut relationships: BTreeMap<(i64, i64, u8, String, u32), SymbolRelationship> = BTreeMap::new();
+        let mut relate = |from: i64, to: i64, relationship_type: RelationshipType, file_path: String, line: u32| {
+            if from != to {
+                relationships
+                    .entry((from, to, relationship_type as u8, file_path.clone(), line))
+                    .or_insert_with(|| SymbolRelationship::new(from, to, relationship_type, file_path, line));
+            }
+        };
+        for (target, reference) in &self.refs {
+            if reference.kind & REF_KIND_REFERENCE == 0 {
+                continue;
+            }
+            let (Some((from, _)), Some((to, _)), Some((file_path, line, _))) =
+                (element_of(&reference.container), element_of(target), location_of(&reference.location))
+            else {
+                continue;
+            };
+            let relationship_type = if reference.kind & REF_KIND_CALL != 0 { RelationshipType::Calls } else { RelationshipType::Uses };
+            relate(from, to, relationship_type, file_path, line);
+        }
+        for relation in &self.relations {
+            let (from, to, relationship_type) = match relation.predicate {
+                RELATION_BASE_OF => (relation.object, relation.subject, RelationshipType::Inherits),
+                RELATION_OVERRIDDEN_BY => (relation.object, relation.subject, RelationshipType::Overrides),
+                _ => continue,
+            };
+            let (Some((from, element)), Some((to, _))) = (element_of(&from), element_of(&to)) else {
+                continue;
+            };
+            relate(from, to, relationship_type, element.file_path.clone(), element.line_number);
+        }
+        let relationships: Vec<SymbolRelationship> = relationships.into_values().collect();
+
+        let mut symbol_counts: HashMap<&str, u32> = HashMap::new();
+        for element in &elements {
+            *symbol_counts.entry(element.file_path.as_str()).or_default() += 1;
+        }
+        let files: Vec<FileMetadata> = fresh
+            .values()
+            .cloned()
+            .map(|mut metadata| {
+                metadata.symbol_count = symbol_counts.get(metadata.file_path.as_str()).copied().unwrap_or(0);
+                metadata
+            })
+            .collect();
+
+        let mut delta_files: Vec<String> = LocalFs::new(base_path)
+            .list_files()
+            .map_err(|e| io_error(&format!("Failed to list {}", base_path.display()), e))?
+            .into_iter()
+            .filter(|path| is_source_file(path) && !fresh.contains_key(path))
+            .collect();
+        delta_files.extend(stale.into_iter().filter(|path| base_path.join(path).is_file()));
+        delta_files.sort();
+        delta_files.dedup();
+
+        let mut index = CodeIndex::new(name.to_string(), base_path.to_string_lossy().to_string());
+        index.update_stats(files.len() as u32, elements.len() as u32);
+        let (symbols, relationship_count, files_imported) = (elements.len(), relationships.len(), files.len());
+        let index = repository.restore_index(index, IndexContents { files, elements, relationships, ..IndexContents::default() })?;
+
+        Ok(ClangdImport {
+            index,
+            symbols,
+            relationships: relationship_count,
+            files_imported,
+            skipped_symbols,
+            delta_files,
+        })
+    }
+}
+
+impl ClangdSymbol {
+    fn merge(&mut self, other: ClangdSymbol) {
+        if self.definition.is_none() {
+            self.definition = other.definition;
+        }
+        if self.declaration.is_none() {
+            self.declaration = other.declaration;
+        }
+        for (field, value) in [
+            (&mut self.signature, other.signature),
+            (&mut self.documentation, other.documentation),
+            (&mut self.return_type, other.return_type),
+        ] {
+            if field.is_empty() {
+                *field = value;
+            }
+        }
+    }
+
+    /// Our symbol type for clang's symbol kind
+    pub fn symbol_type(&self) -> SymbolType {
+        match self.kind {
+            2 | 3 => SymbolType::Namespace,
+            4 => SymbolType::Macro,
+            5 => SymbolType::Enum,
+            6 => SymbolType::Struct,
+            7 => SymbolType::Class,
+            10 => SymbolType::Union,
+            11 | 26 => SymbolType::Typedef,
+            12 | 16..=18 if self.name.starts_with("operator") => SymbolType::Operator,
+            12 | 16..=18 => SymbolType::Function,
+            13 | 19..=21 => SymbolType::Variable,
+            14 => SymbolType::Field,
+            15 => SymbolType::EnumConstant,
+            22 => SymbolType::Constructor,
+            23 => SymbolType::Destructor,
+            24 => SymbolType::Operator,
+            _ => SymbolType::Unknown,
+        }
+    }
+
+    fn to_element(&self, file_path: String, line: u32, column: u32) -> CodeElement {
+        // Keyed by clangd's id, so the declaration and definition pair up
+        let mut hasher = Sha256::new();
+        hasher.update(self.id);
+        let mut element = CodeElement::new(
+            Default::default(),
+            self.name.clone(),
+            self.symbol_type(),
+            file_path,
+            line,
+            column,
+            format!("{:x}", hasher.finalize()),
+        );
+
+        let scope = self.scope.trim_end_matches("::");
+        if !scope.is_empty() {
+            element = element.with_scope(scope.to_string());
+        }
+        if !self.signature.is_empty() {
+            let signature = format!("{} {}{}", self.return_type, self.name, self.signature);
+            element = element.with_signature(signature.trim().to_string());
+        }
+        if !self.documentation.is_empty() {
+            element = element.with_documentation(self.documentation.clone());
+        }
+        element
+    }
+}
+
+/// Path stored in the index for a `file://` URI, or None if it lies outside `base_path`
+pub fn stored_path(uri: &str, base_path: &Path) -> Option<String> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // Windows paths are written as file:///C:/src/...
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+        _ => path.as_str(),
+    };
+
+    let relative = Path::new(path).strip_prefix(base_path).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| path_key(relative))
+}
+
+/// Metadata of a file unchanged since `written`, or None if it changed or is gone
+fn current_metadata(base_path: &Path, stored_path: &str, written: SystemTime) -> Option<FileMetadata> {
+    let path = base_path.join(stored_path);
+    let disk = fs::metadata(&path).ok()?;
+    let modified = disk.modified().ok()?;
+    if modified > written {
+        return None;
+    }
+    let content = fs::read(&path).ok()?;
+    let mut hasher = Sha256::new();
+    hasher.update(&content);
+    Some(FileMetadata::new(
+        Default::default(),
+        stored_path.to_string(),
+        format!("{:x}", hasher.finalize()),
+        DateTime::<Utc>::from(modified),
+        disk.len(),
+    ))
+}
+
+/// Decodes one shard
+pub fn parse_shard(bytes: &[u8]) -> std::result::Result<ClangdShard, String> {
+    let mut riff = Reader::new(bytes, "RIFF");
+    if riff.take(4)? != b"RIFF" {
+        return Err("not a RIFF file".to_string());
+    }
+    let length = riff.u32()? as usize;
+    if length > riff.remaining() {
+        return Err(format!("RIFF length {} exceeds file size", length));
+    }
+    let mut body = Reader::new(riff.take(length)?, "RIFF");
+    if body.take(4)? != b"CdIx" {
+        return Err("not a clangd index".to_string());
+    }
+
+    let mut chunks: HashMap<&[u8], &[u8]> = HashMap::new();
+    while body.remaining() > 0 {
+        let id = body.take(4)?;
+        let size = body.u32()? as usize;
+        chunks.insert(id, body.take(size)?);
+        if size % 2 == 1 && body.remaining() > 0 {
+            body.take(1)?;
+        }
+    }
+    let chunk = |id: &[u8]| chunks.get(id).copied().ok_or_else(|| format!("missing {} chunk", String::from_utf8_lossy(id)));
+
+    let version = Reader::new(chunk(b"meta")?, "meta").u32()?;
+    if !SUPPORTED_CLANGD_VERSIONS.contains(&version) {
+        return Err(format!(
+            "unsupported format version {} (supported: {}-{})",
+            version,
+            SUPPORTED_CLANGD_VERSIONS.start(),
+            SUPPORTED_CLANGD_VERSIONS.end()
+        ));
+    }
+    let strings = read_string_table(chunk(b"stri")?)?;
+
+    let mut shard = ClangdShard { version, ..Default::default() };
+    if let Some(data) = chunks.get(&b"symb"[..]) {
+        let mut reader = Reader::new(data, "symb");
+        while reader.remaining() > 0 {
+            shard.symbols.push(reader.symbol(&strings)?);
+        }
+    }
+    if let Some(data) = chunks.get(&b"refs"[..]) {
+        let mut reader = Reader::new(data, "refs");
+        while reader.remaining() > 0 {
+            let target = reader.id()?;
+            for _ in 0..reader.var()? {
+                let kind = reader.u8()?;
+                let location = reader.location(&strings)?.ok_or("reference without a location")?;
+                let container = reader.id()?;
+                shard.refs.push((target, ClangdRef { kind, location, container }));
+            }
+        }
+    }
+    if let Some(data) = chunks.get(&b"rela"[..]) {
+        let mut reader = Reader::new(data, "rela");
+        while reader.remaining() > 0 {
+            let subject = reader.id()?;
+            let predicate = reader.u8()?;
+            let object = reader.id()?;
+            shard.relations.push(ClangdRelation { subject, predicate, object });
+        }
+    }
+    Ok(shard)
+}
+
+/// Decodes the `stri` chunk: uncompressed size (0 if stored raw), then null-terminated strings
+fn read_string_table(data: &[u8]) -> std::result::Result<Vec<String>, String> {
+    let mut reader = Reader::new(data, "stri");
+    let uncompressed_size = reader.u32()? as usize;
+    let rest = reader.take(reader.remaining())?;
+    let table = if uncompressed_size == 0 {
+        rest.to_vec()
+    } else {
+        let mut table = Vec::with_capacity(uncompressed_size);
+        ZlibDecoder::new(rest)
+            .read_to_end(&mut table)
+            .map_err(|e| format!("failed to decompress string table: {}", e))?;
+        if table.len() != uncompressed_size {
+            return Err(format!("string table is {} bytes, expected {}", table.len(), uncompressed_size));
+        }
+        table
+    };
+
+    let table = table.strip_suffix(&[0]).unwrap_or(&table);
+    if table.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(table.split(|&byte| byte == 0).map(|string| String::from_utf8_lossy(string).into_owned()).collect())
+}
+
+/// Cursor over the bytes of one chunk
+struct Reader<'a> {
+    data: &'a [u8],
+    chunk: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], chunk: &'static str) -> Self {
+        Self { data, chunk }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    fn take(&mut self, n: usize) -> std::result::Result<&'a [u8], String> {
+        if n > self.data.len() {
+            return Err(format!("truncated {} chunk", self.chunk));
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> std::result::Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn var(&mut self) -> std::result::Result<u32, String> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("overlong varint in {} chunk", self.chunk))
+    }
+
+    fn id(&mut self) -> std::result::Result<ClangdSymbolId, String> {
+        let mut id = [0; 8];
+        id.copy_from_slice(self.take(8)?);
+        Ok(id)
+    }
+
+    fn string(&mut self, strings: &[String]) -> std::result::Result<String, String> {
+        let index = self.var()? as usize;
+        strings
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("string {} out of range in {} chunk", index, self.chunk))
+    }
+
+    /// A location: URI, start line and column, end line and column; None if the URI is empty
+    fn location(&mut self, strings: &[String]) -> std::result::Result<Option<ClangdLocation>, String> {
+        let uri = self.string(strings)?;
+        let (line, column) = (self.var()?, self.var()?);
+        let _end = (self.var()?, self.var()?);
+        Ok((!uri.is_empty()).then_some(ClangdLocation { uri, line, column }))
+    }
+
+    fn symbol(&mut self, strings: &[String]) -> std::result::Result<ClangdSymbol, String> {
+        let id = self.id()?;
+        let kind = self.u8()?;
+        let _language = self.u8()?;
+        let name = self.string(strings)?;
+        let scope = self.string(strings)?;
+        let _template_arguments = self.string(strings)?;
+        let definition = self.location(strings)?;
+        let declaration = self.location(strings)?;
+        let _references = self.var()?;
+        let _flags = self.u8()?;
+        let signature = self.string(strings)?;
+        let _completion_snippet = self.string(strings)?;
+        let documentation = self.string(strings)?;
+        let return_type = self.string(strings)?;
+        let _type = self.string(strings)?;
+        for _ in 0..self.var()? {
+            let _header = self.string(strings)?;
+            let _header_references = self.var()?;
+        }
+        Ok(ClangdSymbol { id, kind, name, scope, definition, declaration, signature, documentation, return_type })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::time::Duration;
+
+    /// Writes shards the way clangd's serializer lays them out
+    #[derive(Default)]
+    struct ShardWriter {
+        strings: Vec<String>,
+        symb: Vec<u8>,
+        refs: Vec<u8>,
+        rela: Vec<u8>,
+    }
+
+    fn var(out: &mut Vec<u8>, mut value: u32) {
+        while value >= 0x80 {
+            out.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    impl ShardWriter {
+        fn string(&mut self, out: &mut Vec<u8>, string: &str) {
+            let index = match self.strings.iter().position(|s| s == string) {
+                Some(index) => index,
+                None => {
+                    self.strings.push(string.to_string());
+                    self.strings.len() - 1
+                }
+            };
+            var(out, index as u32);
+        }
+
+        fn location(&mut self, location: Option<(&str, u32, u32)>) -> Vec<u8> {
+            let (uri, line, column) = location.unwrap_or(("", 0, 0));
+            let mut out = Vec::new();
+            self.string(&mut out, uri);
+            for value in [line, column, line, column + 4] {
+                var(&mut out, value);
+            }
+            out
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn symbol(&mut self, id: u8, kind: u8, name: &str, scope: &str, definition: Option<(&str, u32, u32)>, declaration: Option<(&str, u32, u32)>, signature: &str) {
+            let mut out = vec![id; 8];
+            out.extend([kind, 0]);
+            for string in [name, scope, ""] {
+                self.string(&mut out, string);
+            }
+            out.extend(self.location(definition));
+            out.extend(self.location(declaration));
+            var(&mut out, 1);
+            out.push(0);
+            for string in [signature, "", "", "void", ""] {
+                self.string(&mut out, string);
+            }
+            var(&mut out, 0);
+            self.symb.extend(out);
+        }
+
+        fn reference(&mut self, target: u8, kind: u8, location: (&str, u32, u32), container: u8) {
+            let mut out = vec![target; 8];
+            var(&mut out, 1);
+            out.push(kind);
+            out.extend(self.location(Some(location)));
+            out.extend([container; 8]);
+            self.refs.extend(out);
+        }
+
+        fn relation(&mut self, subject: u8, predicate: u8, object: u8) {
+            self.rela.extend([subject; 8]);
+            self.rela.push(predicate);
+            self.rela.extend([object; 8]);
+        }
+
+        fn finish(self, compress: bool) -> Vec<u8> {
+            let mut table: Vec<u8> = self.strings.iter().flat_map(|s| s.bytes().chain([0])).collect();
+            let mut stri = Vec::new();
+            if compress {
+                stri.extend((table.len() as u32).to_le_bytes());
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&table).unwrap();
+                table = encoder.finish().unwrap();
+            } else {
+                stri.extend(0u32.to_le_bytes());
+            }
+            stri.extend(table);
+
+            let mut body = b"CdIx".to_vec();
+            for (id, data) in [(b"meta", 19u32.to_le_bytes().to_vec()), (b"stri", stri), (b"symb", self.symb), (b"refs", self.refs), (b"rela", self.rela)] {
+                body.extend(id);
+                body.extend((data.len() as u32).to_le_bytes());
+                body.extend(&data);
+                if data.len() % 2 == 1 {
+                    body.push(0);
+                }
+            }
+            let mut riff = b"RIFF".to_vec();
+            riff.extend((body.len() as u32).to_le_bytes());
+            riff.extend(body);
+            riff
+        }
+    }
+
+    #[test]
+    fn test_parse_shard() {
+        let mut writer = ShardWriter::default();
+        writer.symbol(1, 12, "drain", "audio::RingBuffer::", Some(("file:///src/ring.cpp", 2, 19)), None, "(float *out)");
+        writer.reference(1, REF_KIND_REFERENCE | REF_KIND_CALL, ("file:///src/mix.cpp", 8, 4), 2);
+        writer.relation(3, RELATION_BASE_OF, 4);
+
+        for compress in [false, true] {
+            let writer = ShardWriter { strings: writer.strings.clone(), symb: writer.symb.clone(), refs: writer.refs.clone(), rela: writer.rela.clone() };
+            let shard = parse_shard(&writer.finish(compress)).unwrap();
+            assert_eq!(shard.version, 19);
+            let drain = &shard.symbols[0];
+            assert_eq!((drain.name.as_str(), drain.scope.as_str(), drain.symbol_type()), ("drain", "audio::RingBuffer::", SymbolType::Function));
+            assert_eq!(drain.definition, Some(ClangdLocation { uri: "file:///src/ring.cpp".to_string(), line: 2, column: 19 }));
+            assert_eq!(drain.declaration, None);
+            assert_eq!(shard.refs[0].1.container, [2; 8]);
+            assert_eq!(shard.relations, [ClangdRelation { subject: [3; 8], predicate: RELATION_BASE_OF, object: [4; 8] }]);
+        }
+
+        assert!(parse_shard(b"RIFF\x04\0\0\0WAVE").is_err());
+        assert_eq!(stored_path("file:///C:/src/fw/a%20b.cpp", Path::new("C:/src/fw")), Some("a b.cpp".to_string()));
+        assert_eq!(stored_path("file:///usr/include/stdio.h", Path::new("/src/fw")), None);
+    }
+
+    #[test]
+    fn test_import_and_delta() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("fw");
+        fs::create_dir_all(base.join("src")).unwrap();
+        for file in ["src/ring.h", "src/ring.cpp", "src/new.cpp"] {
+            fs::write(base.join(file), "// source\n").unwrap();
+        }
+        // Edited after clangd indexed it
+        fs::File::options()
+            .write(true)
+            .open(base.join("src/ring.cpp"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(3600))
+            .unwrap();
+
+        let header = format!("file://{}/src/ring.h", base.display());
+        let source = format!("file://{}/src/ring.cpp", base.display());
+        let mut writer = ShardWriter::default();
+        writer.symbol(1, 7, "RingBuffer", "audio::", Some((&header, 1, 6)), Some((&header, 1, 6)), "");
+        writer.symbol(2, 16, "drain", "audio::RingBuffer::", Some((&source, 2, 19)), Some((&header, 3, 9)), "(float *out)");
+        writer.symbol(3, 12, "fill", "audio::", Some((&header, 7, 5)), None, "()");
+        writer.symbol(4, 7, "SpscRing", "audio::", Some((&header, 11, 6)), None, "");
+        writer.symbol(5, 11, "size_t", "", Some(("file:///usr/include/stddef.h", 40, 23)), None, "");
+        writer.reference(2, REF_KIND_REFERENCE | REF_KIND_CALL, (&header, 8, 4), 3);
+        writer.reference(5, REF_KIND_REFERENCE, (&header, 8, 10), 3);
+        writer.relation(1, RELATION_BASE_OF, 4);
+        let index_dir = dir.path().join("index");
+        fs::create_dir_all(&index_dir).unwrap();
+        fs::write(index_dir.join("ring.h.0123456789ABCDEF.idx"), writer.finish(true)).unwrap();
+        fs::write(index_dir.join("notes.txt"), "not a shard").unwrap();
+
+        let clangd = ClangdIndex::load_dir(&index_dir).unwrap();
+        assert_eq!((clangd.shard_count(), clangd.symbol_count()), (1, 5));
+
+        let repository = Repository::new(DatabaseManager::new(DatabaseConfig::in_memory()).unwrap().connect().unwrap());
+        let import = clangd.import(&repository, "fw", &base).unwrap();
+        // The system typedef and nothing of drain's definition in the edited file
+        assert_eq!((import.symbols, import.skipped_symbols, import.files_imported), (4, 1, 1));
+        assert_eq!(import.delta_files, ["src/new.cpp", "src/ring.cpp"]);
+        assert_eq!(import.relationships, 2);
+
+        let elements = repository.list_code_elements_by_file(&import.index.id, "src/ring.h").unwrap();
+        let drain = elements.iter().find(|element| element.symbol_name == "drain").unwrap();
+        assert!(drain.is_declaration);
+        assert_eq!((drain.line_number, drain.column_number), (4, 10));
+        assert_eq!(drain.scope.as_deref(), Some("audio::RingBuffer"));
+        assert_eq!(drain.signature.as_deref(), Some("void drain(float *out)"));
+        let relationships = repository.list_index_relationships(&import.index.id).unwrap();
+        assert!(relationships.iter().any(|r| r.relationship_type == RelationshipType::Calls && r.line_number == 9));
+        assert!(relationships.iter().any(|r| r.relationship_type == RelationshipType::Inherits));
+
+        assert!(matches!(clangd.import(&repository, "fw", &base), Err(StorageError::Conflict(_))));
+    }
+}
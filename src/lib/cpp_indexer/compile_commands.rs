@@ -0,0 +1,282 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Options followed by a value that only matter to the build, not to parsing
+const DROPPED_WITH_VALUE: &[&str] = &["-o", "-MF", "-MT", "-MQ", "-Xclang-dependency-file"];
+
+/// Build-only options without a value
+const DROPPED: &[&str] = &["-c", "-MD", "-MMD", "-M", "-MM", "-MP", "-MG", "-pipe", "-fsyntax-only", "-Winvalid-pch"];
+
+/// Options whose value is a path relative to the entry's directory
+const PATH_OPTIONS: &[&str] = &["-I", "-isystem", "-iquote", "-idirafter", "-include", "-imacros", "-F", "-isysroot", "--sysroot"];
+
+/// One entry of compile_commands.json
+#[derive(Debug, Clone, Deserialize)]
+struct CompileCommand {
+    directory: PathBuf,
+    file: PathBuf,
+    #[serde(default)]
+    arguments: Option<Vec<String>>,
+    #[serde(default)]
+    command: Option<String>,
+}
+
+/// Per-file compiler flags from a JSON compilation database
+///
+/// Flags are reduced to what libclang needs to parse a file: the compiler,
+/// the source file and output/dependency options are removed, and relative
+/// include paths are made absolute against the entry's directory. Headers
+/// rarely have entries of their own; they borrow the flags of the source
+/// file with the same stem in the same directory, or failing that the entry
+/// in the nearest directory.
+#[derive(Debug, Clone, Default)]
+pub struct CompilationDatabase {
+    /// Absolute, normalized source path -> parse flags
+    flags: HashMap<PathBuf, Vec<String>>,
+}
+
+impl CompilationDatabase {
+    /// Reads a compile_commands.json file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&content).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Parses the contents of a compile_commands.json file
+    pub fn from_json(content: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let commands: Vec<CompileCommand> = serde_json::from_str(content)?;
+        let mut flags = HashMap::new();
+
+        for command in commands {
+            let arguments = match (command.arguments, command.command) {
+                (Some(arguments), _) => arguments,
+                (None, Some(command)) => split_command(&command)?,
+                (None, None) => return Err(format!("Entry for {} has neither arguments nor command", command.file.display()).into()),
+            };
+            let file = normalize(&command.directory.join(&command.file));
+            let parse_flags = parse_flags(&arguments, &command.directory, &command.file);
+            // The first entry wins when a file is compiled several times
+            flags.entry(file).or_insert(parse_flags);
+        }
+
+        Ok(Self { flags })
+    }
+
+    /// Number of files with their own entry
+    pub fn len(&self) -> usize {
+        self.flags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// Parse flags for a file, inferred from a neighbouring entry for headers
+    pub fn flags_for(&self, file_path: &Path) -> Option<&[String]> {
+        let file_path = normalize(&absolute(file_path));
+        if let Some(flags) = self.flags.get(&file_path) {
+            return Some(flags);
+        }
+
+        let directory = file_path.parent()?;
+        let stem = file_path.file_stem();
+        // Same directory and stem (foo.h -> foo.cpp), then the deepest shared directory
+        self.flags
+            .iter()
+            .max_by_key(|(candidate, _)| {
+                let same_stem = candidate.parent() == Some(directory) && candidate.file_stem() == stem;
+                let shared = candidate.components().zip(file_path.components()).take_while(|(a, b)| a == b).count();
+                // Ties go to the lexicographically first path, for stable results
+                (same_stem, shared, std::cmp::Reverse((*candidate).clone()))
+            })
+            .map(|(_, flags)| flags.as_slice())
+    }
+}
+
+/// Drops the compiler, the source and build-only options; anchors relative paths
+fn parse_flags(arguments: &[String], directory: &Path, file: &Path) -> Vec<String> {
+    let file_name = file.to_string_lossy();
+    let absolute_file = directory.join(file);
+    let mut flags = Vec::new();
+    let mut arguments = arguments.iter().skip(1);
+
+    while let Some(argument) = arguments.next() {
+        let argument = argument.as_str();
+        if argument == file_name || Path::new(argument) == absolute_file {
+            continue;
+        }
+        if DROPPED.contains(&argument) {
+            continue;
+        }
+        if DROPPED_WITH_VALUE.contains(&argument) {
+            arguments.next();
+            continue;
+        }
+        if DROPPED_WITH_VALUE.iter().any(|option| option.len() == 2 && argument.starts_with(option) && argument.len() > 2) {
+            // Joined form, e.g. -ofoo.o or -MFfoo.d
+            continue;
+        }
+
+        match PATH_OPTIONS.iter().find(|option| argument == **option || joined_value(argument, option).is_some()) {
+            Some(option) if argument == *option => {
+                flags.push(argument.to_string());
+                if let Some(value) = arguments.next() {
+                    flags.push(anchor(directory, value));
+                }
+            }
+            Some(option) => {
+                let value = joined_value(argument, option).unwrap_or_default();
+                flags.push(format!("{}{}{}", option, if option.starts_with("--") { "=" } else { "" }, anchor(directory, value)));
+            }
+            None => flags.push(argument.to_string()),
+        }
+    }
+
+    flags
+}
+
+/// Value of a joined option: `-Iinclude` for `-I`, `--sysroot=/sdk` for `--sysroot`
+fn joined_value<'a>(argument: &'a str, option: &str) -> Option<&'a str> {
+    let rest = argument.strip_prefix(option)?;
+    if option.starts_with("--") {
+        return rest.strip_prefix('=');
+    }
+    // -I is joinable, but -include/-isystem must not match -I
+    if option.len() == 2 && !rest.is_empty() {
+        return Some(rest);
+    }
+    None
+}
+
+fn anchor(directory: &Path, value: &str) -> String {
+    let path = Path::new(value);
+    if path.is_absolute() {
+        value.to_string()
+    } else {
+        normalize(&directory.join(path)).to_string_lossy().to_string()
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+/// Removes `.` and resolves `..` lexically, without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Splits a `command` string the way a POSIX shell would word-split it
+fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => word.push(c),
+            (Some('"'), '"') => quote = None,
+            (Some('"'), '\\') => match chars.next() {
+                Some(next @ ('"' | '\\' | '$' | '`')) => word.push(next),
+                Some(next) => {
+                    word.push('\\');
+                    word.push(next);
+                }
+                None => return Err("Unterminated escape in command".to_string()),
+            },
+            (Some(_), _) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                word.push(chars.next().ok_or("Unterminated escape in command")?);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("Unterminated quote in command".to_string());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = r#"[
+        {
+            "directory": "/work/build",
+            "file": "../src/audio/engine.cpp",
+            "command": "/usr/bin/c++ -DAUDIO=1 -I../include -isystem /opt/sdk/include -std=c++20 -o engine.o -c ../src/audio/engine.cpp"
+        },
+        {
+            "directory": "/work/build",
+            "file": "/work/src/net/socket.cpp",
+            "arguments": ["clang++", "-Inet", "-DNAME=\"a b\"", "-MD", "-MF", "socket.d", "-c", "/work/src/net/socket.cpp"]
+        }
+    ]"#;
+
+    #[test]
+    fn test_per_file_flags() {
+        let database = CompilationDatabase::from_json(DATABASE).unwrap();
+        assert_eq!(database.len(), 2);
+
+        let engine = database.flags_for(Path::new("/work/src/audio/engine.cpp")).unwrap();
+        assert_eq!(engine, ["-DAUDIO=1", "-I/work/include", "-isystem", "/opt/sdk/include", "-std=c++20"]);
+
+        let socket = database.flags_for(Path::new("/work/src/net/socket.cpp")).unwrap();
+        assert_eq!(socket, ["-I/work/build/net", "-DNAME=\"a b\""]);
+
+        // Headers borrow the flags of the matching or nearest source file
+        assert_eq!(database.flags_for(Path::new("/work/src/audio/engine.h")).unwrap(), engine);
+        assert_eq!(database.flags_for(Path::new("/work/src/net/detail/buffer.hpp")).unwrap(), socket);
+        assert!(CompilationDatabase::default().flags_for(Path::new("/work/a.cpp")).is_none());
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command(r#"cc -DMSG="hello world" -DQ='it''s' a\ b"#).unwrap(),
+            vec!["cc", "-DMSG=hello world", "-DQ=its", "a b"]
+        );
+        assert!(split_command("cc \"unterminated").is_err());
+        assert!(CompilationDatabase::from_json(r#"[{"directory": "/", "file": "a.cpp"}]"#).is_err());
+    }
+}
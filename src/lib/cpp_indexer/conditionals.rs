@@ -0,0 +1,798 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use super::callbacks::mask_non_code;
+use super::vfs::read_source;
+use crate::config::ProjectConfig;
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::build_configuration::BuildConfiguration;
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::repository::Repository;
+
+/// Macro expansions followed when evaluating a condition, so `#define A B` chains terminate
+const MAX_EXPANSION_DEPTH: usize = 8;
+
+/// A named set of predefined macros, as passed with `-D` on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroConfiguration {
+    pub name: String,
+    pub defines: BTreeMap<String, String>,
+}
+
+/// Per-line activity of a file under one configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveLines {
+    lines: Vec<bool>,
+    /// True if any active line holds code other than preprocessor directives
+    has_active_code: bool,
+}
+
+/// Totals for one configuration
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ConfigurationSummary {
+    pub name: String,
+    pub file_count: usize,
+    pub symbol_count: usize,
+    /// Files compiled only in this configuration
+    pub unique_files: usize,
+    /// Symbols defined only in this configuration
+    pub unique_symbols: usize,
+}
+
+/// A file or symbol that is not present in every configuration
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Presence {
+    /// File path, or the symbol's qualified name
+    pub name: String,
+    pub file_path: String,
+    pub line_number: Option<u32>,
+    /// Configurations the entry is present in, in configuration order
+    pub present_in: Vec<String>,
+}
+
+/// Which files and symbols exist under each of a set of configurations
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ConfigurationMatrix {
+    pub configurations: Vec<ConfigurationSummary>,
+    /// Files missing from at least one configuration
+    pub divergent_files: Vec<Presence>,
+    /// Symbols missing from at least one configuration
+    pub divergent_symbols: Vec<Presence>,
+    /// Files and symbols present in every configuration
+    pub common_files: usize,
+    pub common_symbols: usize,
+}
+
+impl MacroConfiguration {
+    /// Creates a configuration with no macros defined
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            defines: BTreeMap::new(),
+        }
+    }
+
+    /// Defines a macro; `-DNAME` style definitions have the value "1"
+    pub fn with_define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    /// Parses a `NAME` or `NAME=VALUE` definition, with or without a leading `-D`
+    pub fn parse_define(definition: &str) -> Result<(String, String), String> {
+        let definition = definition.trim();
+        let definition = definition.strip_prefix("-D").unwrap_or(definition);
+        let (name, value) = definition.split_once('=').unwrap_or((definition, "1"));
+        let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("Invalid macro definition: {}", definition));
+        }
+        Ok((name.to_string(), value.to_string()))
+    }
+}
+
+impl MacroConfiguration {
+    /// Removes a macro's definition, as `-U` does
+    pub fn with_undefine(mut self, name: &str) -> Self {
+        self.defines.remove(name);
+        self
+    }
+
+    /// Applies the `-D` and `-U` options among compiler flags, in order
+    ///
+    /// Other flags and malformed definitions are skipped.
+    pub fn with_flags(mut self, flags: &[String]) -> Self {
+        let mut flags = flags.iter();
+        while let Some(flag) = flags.next() {
+            let (undefine, inline) = if let Some(rest) = flag.strip_prefix("-D") {
+                (false, rest)
+            } else if let Some(rest) = flag.strip_prefix("-U") {
+                (true, rest)
+            } else {
+                continue;
+            };
+            // `-D NAME` takes its argument from the next flag
+            let Some(argument) = (if inline.is_empty() { flags.next().map(String::as_str) } else { Some(inline) }) else { break };
+            if undefine {
+                self = self.with_undefine(argument.trim());
+            } else if let Ok((name, value)) = Self::parse_define(argument) {
+                self = self.with_define(name, value);
+            }
+        }
+        self
+    }
+
+    /// The macros of a build configuration, on top of the ones every file is parsed with
+    ///
+    /// Its defines are added and its undefines remove what the base defined.
+    pub fn with_configuration(self, configuration: &BuildConfiguration) -> Self {
+        let mut macros = configuration.undefines.iter().fold(self, |macros, name| macros.with_undefine(name));
+        macros.name = configuration.name.clone();
+        macros.defines.extend(configuration.defines.iter().map(|(name, value)| (name.clone(), value.clone())));
+        macros
+    }
+}
+
+impl ActiveLines {
+    /// Returns true if the 1-based line is compiled; lines past the end count as active
+    pub fn is_active(&self, line: u32) -> bool {
+        match (line as usize).checked_sub(1) {
+            Some(index) => self.lines.get(index).copied().unwrap_or(true),
+            None => false,
+        }
+    }
+
+    /// Returns true if the file contributes any code
+    pub fn has_active_code(&self) -> bool {
+        self.has_active_code
+    }
+}
+
+/// One level of `#if` nesting
+struct Branch {
+    parent_active: bool,
+    /// A branch of this conditional has already been taken
+    taken: bool,
+    active: bool,
+}
+
+/// Evaluates the preprocessor conditionals of a file under a configuration
+///
+/// `#define` and `#undef` in active regions update the macro set as the file
+/// is read, so include guards and locally derived feature macros behave as
+/// they would when compiling the file on its own. Includes are not followed.
+pub fn active_lines(content: &str, config: &MacroConfiguration) -> ActiveLines {
+    let code = mask_non_code(content);
+    let mut macros = config.defines.clone();
+    let mut stack: Vec<Branch> = Vec::new();
+    let mut lines = Vec::new();
+    let mut has_active_code = false;
+    let mut continued: Option<String> = None;
+
+    for line in code.lines() {
+        let current = stack.last().map(|branch| branch.active).unwrap_or(true);
+
+        // Directives continue onto the next line after a trailing backslash
+        if let Some(mut directive) = continued.take() {
+            lines.push(current);
+            directive.push_str(line.trim_end_matches('\\'));
+            if line.trim_end().ends_with('\\') {
+                continued = Some(directive);
+            } else {
+                apply_directive(&directive, &mut stack, &mut macros);
+            }
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        let Some(directive) = trimmed.strip_prefix('#') else {
+            lines.push(current);
+            has_active_code |= current && !trimmed.is_empty();
+            continue;
+        };
+
+        let enclosing = if is_conditional(directive) {
+            stack.last().map(|branch| branch.parent_active).unwrap_or(true)
+        } else {
+            current
+        };
+        lines.push(enclosing);
+        if trimmed.trim_end().ends_with('\\') {
+            continued = Some(directive.trim_end().trim_end_matches('\\').to_string());
+        } else {
+            apply_directive(directive, &mut stack, &mut macros);
+        }
+    }
+
+    ActiveLines { lines, has_active_code }
+}
+
+/// Returns true for directives that open, switch or close a conditional
+fn is_conditional(directive: &str) -> bool {
+    let keyword = directive.trim_start().split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or("");
+    matches!(keyword, "if" | "ifdef" | "ifndef" | "elif" | "elifdef" | "elifndef" | "else" | "endif")
+}
+
+fn apply_directive(directive: &str, stack: &mut Vec<Branch>, macros: &mut BTreeMap<String, String>) {
+    let directive = directive.trim_start();
+    let keyword_end = directive.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(directive.len());
+    let (keyword, rest) = directive.split_at(keyword_end);
+    let rest = rest.trim();
+    let current = stack.last().map(|branch| branch.active).unwrap_or(true);
+    let first_word = || rest.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).next().unwrap_or("");
+
+    match keyword {
+        "if" | "ifdef" | "ifndef" => {
+            let condition = current
+                && match keyword {
+                    "if" => evaluate(rest, macros),
+                    "ifdef" => macros.contains_key(first_word()),
+                    _ => !macros.contains_key(first_word()),
+                };
+            stack.push(Branch {
+                parent_active: current,
+                taken: condition,
+                active: condition,
+            });
+        }
+        "elif" | "elifdef" | "elifndef" => {
+            if let Some(branch) = stack.last_mut() {
+                let condition = branch.parent_active
+                    && !branch.taken
+                    && match keyword {
+                        "elif" => evaluate(rest, macros),
+                        "elifdef" => macros.contains_key(first_word()),
+                        _ => !macros.contains_key(first_word()),
+                    };
+                branch.active = condition;
+                branch.taken |= condition;
+            }
+        }
+        "else" => {
+            if let Some(branch) = stack.last_mut() {
+                branch.active = branch.parent_active && !branch.taken;
+                branch.taken = true;
+            }
+        }
+        "endif" => {
+            stack.pop();
+        }
+        "define" if current => {
+            let name = first_word();
+            if !name.is_empty() {
+                // Function-like macros are recorded as defined; their bodies aren't expanded
+                let body = &rest[name.len()..];
+                let value = if body.starts_with('(') { "1" } else { body.trim() };
+                macros.insert(name.to_string(), value.to_string());
+            }
+        }
+        "undef" if current => {
+            macros.remove(first_word());
+        }
+        _ => {}
+    }
+}
+
+/// Evaluates an `#if` expression; malformed expressions are false
+fn evaluate(expression: &str, macros: &BTreeMap<String, String>) -> bool {
+    let tokens = tokenize(expression);
+    let mut parser = ExpressionParser {
+        tokens: &tokens,
+        position: 0,
+        macros,
+        depth: 0,
+    };
+    match parser.ternary() {
+        Some(value) if parser.position == tokens.len() => value != 0,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+/// Operators longest first so `<=` is not read as `<`
+const OPERATORS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "(", ")", "!", "~", "+", "-", "*", "/", "%",
+    "<", ">", "&", "|", "^", "?", ":", ",",
+];
+
+fn tokenize(expression: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            let literal = rest[..end].trim_end_matches(['u', 'U', 'l', 'L']);
+            let value = if let Some(hex) = literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+                i64::from_str_radix(hex, 16).ok()
+            } else if literal.len() > 1 && literal.starts_with('0') {
+                i64::from_str_radix(&literal[1..], 8).ok()
+            } else {
+                literal.parse().ok()
+            };
+            tokens.push(Token::Number(value.unwrap_or(0)));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            // Characters such as `<` `>` in __has_include(<x>) are skipped with their call
+            rest = &rest[c.len_utf8()..];
+        }
+        rest = rest.trim_start();
+    }
+    tokens
+}
+
+struct ExpressionParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    macros: &'a BTreeMap<String, String>,
+    depth: usize,
+}
+
+impl ExpressionParser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ternary(&mut self) -> Option<i64> {
+        let condition = self.binary(0)?;
+        if self.eat("?") {
+            let then = self.ternary()?;
+            if !self.eat(":") {
+                return None;
+            }
+            let otherwise = self.ternary()?;
+            return Some(if condition != 0 { then } else { otherwise });
+        }
+        Some(condition)
+    }
+
+    /// Binary operators by precedence level, loosest first
+    fn binary(&mut self, level: usize) -> Option<i64> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        let Some(operators) = LEVELS.get(level) else { return self.unary() };
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| operators.contains(op)) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = match op {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left.checked_shl(right as u32).unwrap_or(0),
+                ">>" => left.checked_shr(right as u32).unwrap_or(0),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" => left.checked_div(right)?,
+                _ => left.checked_rem(right)?,
+            };
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        if self.eat("!") {
+            return Some((self.unary()? == 0) as i64);
+        }
+        if self.eat("-") {
+            return Some(self.unary()?.wrapping_neg());
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        if self.eat("~") {
+            return Some(!self.unary()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<i64> {
+        if self.eat("(") {
+            let value = self.ternary()?;
+            return self.eat(")").then_some(value);
+        }
+
+        let token = self.tokens.get(self.position)?.clone();
+        self.position += 1;
+        match token {
+            Token::Number(value) => Some(value),
+            Token::Ident(name) if name == "defined" => {
+                let parenthesized = self.eat("(");
+                let Some(Token::Ident(name)) = self.tokens.get(self.position).cloned() else { return None };
+                self.position += 1;
+                if parenthesized && !self.eat(")") {
+                    return None;
+                }
+                Some(self.macros.contains_key(&name) as i64)
+            }
+            Token::Ident(name) => {
+                // Function-like checks (__has_include, __has_feature, ...) count as 0
+                if self.peek_op() == Some("(") {
+                    self.skip_arguments();
+                    return Some(0);
+                }
+                match name.as_str() {
+                    "true" => Some(1),
+                    "false" => Some(0),
+                    _ => Some(self.expand(&name)),
+                }
+            }
+            Token::Op(_) => None,
+        }
+    }
+
+    /// Value of a macro used in an expression; undefined identifiers are 0
+    fn expand(&self, name: &str) -> i64 {
+        let Some(value) = self.macros.get(name) else { return 0 };
+        if self.depth >= MAX_EXPANSION_DEPTH {
+            return 0;
+        }
+        let tokens = tokenize(value);
+        if tokens.is_empty() {
+            return 0;
+        }
+        let mut parser = ExpressionParser {
+            tokens: &tokens,
+            position: 0,
+            macros: self.macros,
+            depth: self.depth + 1,
+        };
+        parser.ternary().filter(|_| parser.position == tokens.len()).unwrap_or(0)
+    }
+
+    fn skip_arguments(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.position) {
+            self.position += 1;
+            match token {
+                Token::Op("(") => depth += 1,
+                Token::Op(")") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ConfigurationMatrix {
+    /// Evaluates every file under every configuration
+    ///
+    /// `files` yields each file's path, content and indexed elements. A file
+    /// is present in a configuration if any of its code outside preprocessor
+    /// directives is compiled; an element is present if its line is.
+    pub fn build<'a, I>(configurations: &[MacroConfiguration], files: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a str, &'a [CodeElement])>,
+    {
+        let mut summaries: Vec<ConfigurationSummary> = configurations
+            .iter()
+            .map(|config| ConfigurationSummary {
+                name: config.name.clone(),
+                file_count: 0,
+                symbol_count: 0,
+                unique_files: 0,
+                unique_symbols: 0,
+            })
+            .collect();
+        let mut divergent_files = Vec::new();
+        let mut divergent_symbols = Vec::new();
+        let (mut common_files, mut common_symbols) = (0, 0);
+
+        let record = |present: &BTreeSet<usize>, summaries: &mut Vec<ConfigurationSummary>, is_file: bool| -> bool {
+            for &index in present {
+                if is_file {
+                    summaries[index].file_count += 1;
+                } else {
+                    summaries[index].symbol_count += 1;
+                }
+            }
+            if present.len() == 1 {
+                let index = *present.iter().next().unwrap_or(&0);
+                if is_file {
+                    summaries[index].unique_files += 1;
+                } else {
+                    summaries[index].unique_symbols += 1;
+                }
+            }
+            present.len() == configurations.len()
+        };
+        let names = |present: &BTreeSet<usize>| present.iter().map(|&index| configurations[index].name.clone()).collect();
+
+        for (file_path, content, elements) in files {
+            let activity: Vec<ActiveLines> = configurations.iter().map(|config| active_lines(content, config)).collect();
+
+            let present: BTreeSet<usize> = (0..configurations.len()).filter(|&index| activity[index].has_active_code()).collect();
+            if record(&present, &mut summaries, true) {
+                common_files += 1;
+            } else {
+                divergent_files.push(Presence {
+                    name: file_path.to_string(),
+                    file_path: file_path.to_string(),
+                    line_number: None,
+                    present_in: names(&present),
+                });
+            }
+
+            for element in elements {
+                let present: BTreeSet<usize> = (0..configurations.len())
+                    .filter(|&index| activity[index].is_active(element.line_number))
+                    .collect();
+                if record(&present, &mut summaries, false) {
+                    common_symbols += 1;
+                } else {
+                    divergent_symbols.push(Presence {
+                        name: element.fully_qualified_name(),
+                        file_path: file_path.to_string(),
+                        line_number: Some(element.line_number),
+                        present_in: names(&present),
+                    });
+                }
+            }
+        }
+
+        Self {
+            configurations: summaries,
+            divergent_files,
+            divergent_symbols,
+            common_files,
+            common_symbols,
+        }
+    }
+}
+
+/// Records which symbols each build configuration of an index compiles out
+///
+/// Each configuration is evaluated on top of the `-D` and `-U` flags of the
+/// project file, which every file is parsed with. Files are read from the
+/// index base path; symbols of unreadable files are kept in every
+/// configuration. Symbols stored after this runs, e.g. by the watcher,
+/// belong to every configuration until it runs again. Returns the number of
+/// exclusions recorded.
+pub fn assign_configurations(repository: &Repository, index: &CodeIndex) -> Result<usize> {
+    let configurations = repository.list_build_configurations(&index.id)?;
+    if configurations.is_empty() {
+        return Ok(0);
+    }
+    let base_path = Path::new(&index.base_path);
+    let project = ProjectConfig::load(base_path).map_err(|e| StorageError::Validation(e.to_string()))?.unwrap_or_default();
+    let base = MacroConfiguration::new("").with_flags(&project.compile_flags);
+    let macros: Vec<MacroConfiguration> = configurations.iter().map(|configuration| base.clone().with_configuration(configuration)).collect();
+
+    let mut exclusions = Vec::new();
+    for file in repository.list_file_metadata(&index.id)? {
+        let Ok(content) = read_source(base_path, &file.file_path) else { continue };
+        let elements = repository.list_code_elements_by_file(&index.id, &file.file_path)?;
+        for (configuration, macros) in configurations.iter().zip(&macros) {
+            let activity = active_lines(&content, macros);
+            let Some(configuration_id) = configuration.id else { continue };
+            exclusions.extend(
+                elements
+                    .iter()
+                    .filter(|element| !activity.is_active(element.line_number))
+                    .filter_map(|element| element.id.map(|id| (configuration_id, id))),
+            );
+        }
+    }
+
+    repository.replace_configuration_exclusions(&index.id, &exclusions)?;
+    Ok(exclusions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    const PLATFORM: &str = "\
+#ifndef PLATFORM_H
+#define PLATFORM_H
+#if defined(_WIN32) && !defined(__MINGW32__)
+void win_only();
+#elif defined(__linux__) || \\
+      defined(__APPLE__)
+void posix_only();
+#else
+void other_only();
+#endif
+#define HAS_SIMD (ARCH_LEVEL >= 2)
+#if HAS_SIMD
+void simd_kernel();
+#endif
+void everywhere();
+#endif
+";
+
+    fn config(name: &str, defines: &[&str]) -> MacroConfiguration {
+        defines.iter().fold(MacroConfiguration::new(name), |config, definition| {
+            let (name, value) = MacroConfiguration::parse_define(definition).unwrap();
+            config.with_define(name, value)
+        })
+    }
+
+    #[test]
+    fn test_active_lines() {
+        let windows = active_lines(PLATFORM, &config("windows", &["_WIN32", "ARCH_LEVEL=2"]));
+        let linux = active_lines(PLATFORM, &config("linux", &["-D__linux__"]));
+        let mingw = active_lines(PLATFORM, &config("mingw", &["_WIN32", "__MINGW32__"]));
+
+        assert!(windows.is_active(4) && !windows.is_active(7) && windows.is_active(13));
+        assert!(!linux.is_active(4) && linux.is_active(7) && !linux.is_active(13));
+        assert!(!mingw.is_active(4) && !mingw.is_active(7) && mingw.is_active(9));
+        assert!(windows.is_active(15) && linux.is_active(15) && mingw.is_active(15));
+        assert!(linux.has_active_code());
+
+        // Everything behind a disabled guard is inactive
+        let guarded = "#ifdef ENABLE_GPU\nvoid gpu();\n#endif\n";
+        assert!(!active_lines(guarded, &config("cpu", &[])).has_active_code());
+        assert!(MacroConfiguration::parse_define("1BAD").is_err());
+    }
+
+    #[test]
+    fn test_expression_evaluation() {
+        let macros = BTreeMap::from([
+            ("VERSION".to_string(), "0x0203".to_string()),
+            ("ALIAS".to_string(), "VERSION".to_string()),
+        ]);
+        assert!(evaluate("VERSION >= 0x0200 && VERSION < 0x0300", &macros));
+        assert!(evaluate("ALIAS == 515", &macros));
+        assert!(evaluate("(UNDEFINED + 1) * 2 == 2", &macros));
+        assert!(evaluate("defined VERSION ? 1 : 0", &macros));
+        assert!(!evaluate("__has_include(<optional>)", &macros));
+        assert!(!evaluate("1 / 0", &macros));
+        assert!(!evaluate("VERSION >", &macros));
+    }
+
+    #[test]
+    fn test_configuration_matrix() {
+        let index_id = Uuid::new_v4();
+        let element = |name: &str, line| {
+            CodeElement::new(index_id, name.to_string(), SymbolType::Function, "platform.h".to_string(), line, 1, "a".repeat(64))
+        };
+        let elements = vec![element("win_only", 4), element("posix_only", 7), element("simd_kernel", 13), element("everywhere", 15)];
+        let gpu = vec![element("gpu", 2)];
+        let configurations = [config("windows", &["_WIN32", "ARCH_LEVEL=2"]), config("linux", &["__linux__"])];
+
+        let matrix = ConfigurationMatrix::build(
+            &configurations,
+            [
+                ("platform.h", PLATFORM, elements.as_slice()),
+                ("gpu.cpp", "#ifdef _WIN32\nvoid gpu();\n#endif\n", gpu.as_slice()),
+            ],
+        );
+
+        assert_eq!(matrix.common_files, 1);
+        assert_eq!(matrix.common_symbols, 1);
+        assert_eq!(matrix.divergent_files[0].present_in, vec!["windows"]);
+        let symbols: Vec<(&str, Vec<String>)> =
+            matrix.divergent_symbols.iter().map(|presence| (presence.name.as_str(), presence.present_in.clone())).collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("win_only", vec!["windows".to_string()]),
+                ("posix_only", vec!["linux".to_string()]),
+                ("simd_kernel", vec!["windows".to_string()]),
+                ("gpu", vec!["windows".to_string()]),
+            ]
+        );
+        assert_eq!(matrix.configurations[0].unique_symbols, 3);
+        assert_eq!(matrix.configurations[0].symbol_count, 4);
+        assert_eq!(matrix.configurations[1].file_count, 1);
+    }
+
+    #[test]
+    fn test_assign_configurations() {
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+        use crate::lib::storage::models::file_metadata::FileMetadata;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("platform.h"), PLATFORM).unwrap();
+        let repository = Repository::new(DatabaseManager::new(DatabaseConfig::in_memory()).unwrap().connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("platform".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        assert_eq!(assign_configurations(&repository, &index).unwrap(), 0);
+
+        let metadata = FileMetadata::new(index.id, "platform.h".to_string(), "b".repeat(64), chrono::Utc::now(), 100);
+        repository.create_file_metadata(metadata).unwrap();
+        let element = |name: &str, line| {
+            repository
+                .create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "platform.h".to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let win_only = element("win_only", 4);
+        let posix_only = element("posix_only", 7);
+        let everywhere = element("everywhere", 15);
+
+        let save = |name: &str, define: &str| {
+            repository
+                .save_build_configuration(BuildConfiguration::new(index.id, name.to_string()).with_define(define, "1"))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let windows = save("windows", "_WIN32");
+        let linux = save("linux", "__linux__");
+
+        assert_eq!(assign_configurations(&repository, &index).unwrap(), 2);
+        assert_eq!(repository.list_configuration_exclusions(windows).unwrap(), vec![posix_only]);
+        assert_eq!(repository.list_configuration_exclusions(linux).unwrap(), vec![win_only]);
+        assert_eq!(repository.get_symbol_configurations(&index.id, everywhere).unwrap(), vec!["linux", "windows"]);
+
+        // A configuration can undefine what the project file defines for every file
+        std::fs::write(dir.path().join(".cppindex.toml"), "compile_flags = [\"-D_WIN32\"]\n").unwrap();
+        let native = repository
+            .save_build_configuration(BuildConfiguration::new(index.id, "native".to_string()).with_undefine("_WIN32"))
+            .unwrap()
+            .id
+            .unwrap();
+        assign_configurations(&repository, &index).unwrap();
+        assert_eq!(repository.list_configuration_exclusions(native).unwrap(), vec![win_only, posix_only]);
+        assert_eq!(repository.list_configuration_exclusions(linux).unwrap(), vec![posix_only]);
+    }
+
+    #[test]
+    fn test_with_flags() {
+        let flags: Vec<String> = ["-DA", "-D", "B=2", "-Iinclude", "-UA", "-DC=x"].iter().map(|flag| flag.to_string()).collect();
+        let macros = MacroConfiguration::new("flags").with_flags(&flags);
+        assert_eq!(macros.defines, BTreeMap::from([("B".to_string(), "2".to_string()), ("C".to_string(), "x".to_string())]));
+
+        let configuration = BuildConfiguration::new(Uuid::new_v4(), "release".to_string()).with_undefine("B").with_define("NDEBUG", "1");
+        let macros = macros.with_configuration(&configuration);
+        assert_eq!(macros.name, "release");
+        assert_eq!(macros.defines.keys().collect::<Vec<_>>(), ["C", "NDEBUG"]);
+    }
+}
@@ -0,0 +1,255 @@
+use serde::Serialize;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::lib::cpp_indexer::symbol_extractor::ExtractedSymbol;
+use crate::lib::storage::models::code_element::{AccessModifier, CodeElement, SymbolType};
+
+/// How much of a symbol is worth storing
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DetailTier {
+    /// Tier 1: declared in a header and not private
+    PublicApi,
+    /// Tier 2: defined in a source file, or a private member
+    Internal,
+    /// Tier 3: declared inside a function body
+    Local,
+}
+
+/// What is stored for the symbols of a tier
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolDetail {
+    /// Every extracted field
+    Full,
+    /// Name, scope and location; signature, documentation, access and
+    /// section are dropped
+    Outline,
+    /// Not stored at all
+    Omit,
+}
+
+/// Per-tier storage settings applied at index time
+///
+/// The default stores everything in full. [`DetailPolicy::tiered`] trades
+/// fidelity for size on gigantic indices; files indexed that way can be
+/// promoted back to full detail one at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetailPolicy {
+    pub public_api: SymbolDetail,
+    pub internal: SymbolDetail,
+    pub locals: SymbolDetail,
+    /// Store the source text of symbols kept in full (off by default)
+    pub bodies: bool,
+}
+
+/// Symbols of one file after the policy was applied
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TieredElements {
+    pub elements: Vec<CodeElement>,
+    /// Symbols stored as outlines
+    pub outlined: usize,
+    /// Symbols left out
+    pub omitted: usize,
+}
+
+impl Default for DetailPolicy {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+impl DetailPolicy {
+    /// Stores every symbol in full
+    pub fn full() -> Self {
+        Self {
+            public_api: SymbolDetail::Full,
+            internal: SymbolDetail::Full,
+            locals: SymbolDetail::Full,
+            bodies: false,
+        }
+    }
+
+    /// Public API in full, internal symbols as outlines, locals omitted
+    pub fn tiered() -> Self {
+        Self {
+            public_api: SymbolDetail::Full,
+            internal: SymbolDetail::Outline,
+            locals: SymbolDetail::Omit,
+            bodies: false,
+        }
+    }
+
+    /// Sets what is stored for one tier
+    pub fn with_detail(mut self, tier: DetailTier, detail: SymbolDetail) -> Self {
+        match tier {
+            DetailTier::PublicApi => self.public_api = detail,
+            DetailTier::Internal => self.internal = detail,
+            DetailTier::Local => self.locals = detail,
+        }
+        self
+    }
+
+    /// Stores the source text of symbols kept in full
+    pub fn with_bodies(mut self, bodies: bool) -> Self {
+        self.bodies = bodies;
+        self
+    }
+
+    pub fn detail(&self, tier: DetailTier) -> SymbolDetail {
+        match tier {
+            DetailTier::PublicApi => self.public_api,
+            DetailTier::Internal => self.internal,
+            DetailTier::Local => self.locals,
+        }
+    }
+
+    /// True if the policy stores some symbol with less than full detail
+    pub fn is_reduced(&self) -> bool {
+        [self.public_api, self.internal, self.locals].iter().any(|detail| *detail != SymbolDetail::Full)
+    }
+
+    /// Converts the symbols extracted from one file, stored as `stored_path`
+    pub fn apply(&self, symbols: &[ExtractedSymbol], index_id: Uuid, stored_path: &str) -> TieredElements {
+        let mut tiered = TieredElements::default();
+        for (symbol, tier) in symbols.iter().zip(classify(symbols)) {
+            match self.detail(tier) {
+                SymbolDetail::Full => {
+                    let mut element = symbol.to_code_element(index_id, stored_path);
+                    if self.bodies && !symbol.content.is_empty() {
+                        element = element.with_body(symbol.content.clone());
+                    }
+                    tiered.elements.push(element);
+                }
+                SymbolDetail::Outline => {
+                    tiered.elements.push(outline(symbol.to_code_element(index_id, stored_path)));
+                    tiered.outlined += 1;
+                }
+                SymbolDetail::Omit => tiered.omitted += 1,
+            }
+        }
+        tiered
+    }
+}
+
+/// Tier of each symbol extracted from one file, in order
+///
+/// A symbol is local when it lies within the extent of a function of the
+/// same file; nested functions (lambdas, local classes' methods) count as
+/// locals too.
+pub fn classify(symbols: &[ExtractedSymbol]) -> Vec<DetailTier> {
+    let bodies: Vec<&ExtractedSymbol> = symbols
+        .iter()
+        .filter(|symbol| is_function(symbol.symbol_type) && symbol.is_definition && symbol.end_line > symbol.start_line)
+        .collect();
+
+    symbols
+        .iter()
+        .map(|symbol| {
+            let inside_body = bodies.iter().any(|body| {
+                !std::ptr::eq(*body, symbol)
+                    && body.file_path == symbol.file_path
+                    && symbol.start_line > body.start_line
+                    && symbol.end_line <= body.end_line
+            });
+            if inside_body {
+                DetailTier::Local
+            } else if is_header(&symbol.file_path) && symbol.visibility != Some(AccessModifier::Private) {
+                DetailTier::PublicApi
+            } else {
+                DetailTier::Internal
+            }
+        })
+        .collect()
+}
+
+fn is_function(symbol_type: SymbolType) -> bool {
+    matches!(symbol_type, SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor | SymbolType::Operator)
+}
+
+fn is_header(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("h") | Some("hpp") | Some("hxx") | Some("h++") | Some("H")
+    )
+}
+
+fn outline(mut element: CodeElement) -> CodeElement {
+    element.signature = None;
+    element.documentation = None;
+    element.access_modifier = None;
+    element.memory_section = None;
+    element
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn symbol(name: &str, symbol_type: SymbolType, file: &str, lines: (u32, u32), visibility: Option<AccessModifier>) -> ExtractedSymbol {
+        ExtractedSymbol {
+            name: name.to_string(),
+            symbol_type,
+            visibility,
+            file_path: PathBuf::from(file),
+            start_line: lines.0,
+            end_line: lines.1,
+            start_column: 1,
+            end_column: 1,
+            content: name.to_string(),
+            fully_qualified_name: name.to_string(),
+            namespace_path: Vec::new(),
+            dependencies: Vec::new(),
+            template_parameters: Vec::new(),
+            base_classes: Vec::new(),
+            member_functions: Vec::new(),
+            member_variables: Vec::new(),
+            signature: Some(format!("void {}()", name)),
+            documentation: Some("Docs".to_string()),
+            is_definition: true,
+            is_declaration: false,
+            memory_section: None,
+            usr: None,
+        }
+    }
+
+    #[test]
+    fn test_classify_and_apply() {
+        let symbols = vec![
+            symbol("Mixer", SymbolType::Class, "src/mixer.h", (1, 20), None),
+            symbol("state", SymbolType::Field, "src/mixer.h", (18, 18), Some(AccessModifier::Private)),
+            symbol("mix", SymbolType::Function, "src/mixer.h", (5, 12), Some(AccessModifier::Public)),
+            symbol("sum", SymbolType::Variable, "src/mixer.h", (6, 6), None),
+            symbol("clamp", SymbolType::Function, "src/mixer.cpp", (3, 8), None),
+        ];
+        assert_eq!(
+            classify(&symbols),
+            [DetailTier::PublicApi, DetailTier::Internal, DetailTier::PublicApi, DetailTier::Local, DetailTier::Internal]
+        );
+
+        let index_id = Uuid::new_v4();
+        let full = DetailPolicy::default().apply(&symbols, index_id, "src/mixer.h");
+        assert_eq!((full.elements.len(), full.outlined, full.omitted), (5, 0, 0));
+        assert!(!DetailPolicy::default().is_reduced());
+
+        let tiered = DetailPolicy::tiered().apply(&symbols, index_id, "src/mixer.h");
+        assert_eq!((tiered.elements.len(), tiered.outlined, tiered.omitted), (4, 2, 1));
+        let clamp = tiered.elements.iter().find(|element| element.symbol_name == "clamp").unwrap();
+        assert!(clamp.signature.is_none() && clamp.documentation.is_none());
+        assert_eq!(clamp.line_number, 3);
+        let mix = tiered.elements.iter().find(|element| element.symbol_name == "mix").unwrap();
+        assert_eq!(mix.signature.as_deref(), Some("void mix()"));
+
+        let no_internal = DetailPolicy::tiered().with_detail(DetailTier::Internal, SymbolDetail::Omit);
+        assert_eq!(no_internal.apply(&symbols, index_id, "src/mixer.h").elements.len(), 2);
+
+        // Bodies are kept only for symbols stored in full
+        assert!(full.elements.iter().all(|element| element.body.is_none()));
+        let bodies = DetailPolicy::tiered().with_bodies(true).apply(&symbols, index_id, "src/mixer.h");
+        let mix = bodies.elements.iter().find(|element| element.symbol_name == "mix").unwrap();
+        assert_eq!(mix.body.as_deref(), Some("mix"));
+        assert!(bodies.elements.iter().find(|element| element.symbol_name == "clamp").unwrap().body.is_none());
+    }
+}
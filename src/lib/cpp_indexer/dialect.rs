@@ -0,0 +1,124 @@
+// Per-path parse options
+//
+// One set of flags rarely fits a whole tree: a legacy directory only builds
+// as C++03, CUDA sources need `-x cuda`, module interfaces need
+// `-x c++-module`. Dialect rules map path patterns to the language standard,
+// language and extra flags libclang gets for matching files, on top of the
+// flags every file gets.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::lib::cpp_indexer::vfs::pattern_matches;
+
+/// Parse options for the files matching a pattern
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DialectRule {
+    /// Gitignore-style pattern, e.g. "legacy/**" or "*.cu"
+    pub pattern: String,
+    /// Language standard replacing any `-std=` flag, e.g. "c++03"
+    pub std: Option<String>,
+    /// Language libclang reads the files as, replacing any `-x` flag, e.g. "cuda"
+    pub language: Option<String>,
+    /// Flags appended for matching files
+    pub flags: Vec<String>,
+}
+
+/// Dialect rules of a tree, applied in order so later rules win
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DialectRules {
+    root: PathBuf,
+    rules: Vec<DialectRule>,
+}
+
+impl DialectRule {
+    /// Fails on a rule that can match nothing or changes nothing
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pattern.trim().is_empty() {
+            return Err("Dialect rule needs a pattern".to_string());
+        }
+        if self.std.is_none() && self.language.is_none() && self.flags.is_empty() {
+            return Err(format!("Dialect rule '{}' sets neither std, language nor flags", self.pattern));
+        }
+        Ok(())
+    }
+
+    /// Rewrites `flags` for a matching file
+    fn apply(&self, flags: &mut Vec<String>) {
+        if let Some(std) = &self.std {
+            flags.retain(|flag| !flag.starts_with("-std="));
+            flags.push(format!("-std={}", std));
+        }
+        if let Some(language) = &self.language {
+            let mut kept = Vec::with_capacity(flags.len());
+            let mut arguments = std::mem::take(flags).into_iter();
+            while let Some(flag) = arguments.next() {
+                match flag.as_str() {
+                    "-x" => {
+                        arguments.next();
+                    }
+                    _ if flag.starts_with("-x") => {}
+                    _ => kept.push(flag),
+                }
+            }
+            *flags = ["-x".to_string(), language.clone()].into_iter().chain(kept).collect();
+        }
+        flags.extend(self.flags.iter().cloned());
+    }
+}
+
+impl DialectRules {
+    /// Rules matched against paths relative to `root`
+    pub fn new(root: impl Into<PathBuf>, rules: Vec<DialectRule>) -> Self {
+        Self { root: root.into(), rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Applies every rule matching `file` to its flags
+    pub fn apply(&self, file: &Path, mut flags: Vec<String>) -> Vec<String> {
+        let path = file.strip_prefix(&self.root).unwrap_or(file).to_string_lossy().replace('\\', "/");
+        for rule in self.rules.iter().filter(|rule| pattern_matches(&rule.pattern, &path)) {
+            rule.apply(&mut flags);
+        }
+        flags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dialect_rules() {
+        let rules = DialectRules::new(
+            "/work/engine",
+            vec![
+                DialectRule { pattern: "legacy/**".to_string(), std: Some("c++03".to_string()), ..Default::default() },
+                DialectRule {
+                    pattern: "*.cu".to_string(),
+                    language: Some("cuda".to_string()),
+                    flags: vec!["--cuda-gpu-arch=sm_80".to_string(), "-nocudalib".to_string()],
+                    ..Default::default()
+                },
+                DialectRule { pattern: "*.ixx".to_string(), std: Some("c++20".to_string()), language: Some("c++-module".to_string()), ..Default::default() },
+            ],
+        );
+        let flags = vec!["-std=c++17".to_string(), "-DNDEBUG".to_string()];
+
+        assert_eq!(rules.apply(Path::new("/work/engine/src/render.cpp"), flags.clone()), flags);
+        assert_eq!(rules.apply(Path::new("/work/engine/legacy/io/file.cpp"), flags.clone()), ["-DNDEBUG", "-std=c++03"]);
+        assert_eq!(
+            rules.apply(Path::new("/work/engine/kernels/blur.cu"), flags.clone()),
+            ["-x", "cuda", "-std=c++17", "-DNDEBUG", "--cuda-gpu-arch=sm_80", "-nocudalib"]
+        );
+        let c_flags = vec!["-x".to_string(), "c".to_string(), "-std=c11".to_string()];
+        assert_eq!(rules.apply(Path::new("/work/engine/modules/math.ixx"), c_flags), ["-x", "c++-module", "-std=c++20"]);
+
+        assert!(DialectRule { pattern: "*.cu".to_string(), ..Default::default() }.validate().is_err());
+        assert!(DialectRule { std: Some("c++03".to_string()), ..Default::default() }.validate().is_err());
+    }
+}
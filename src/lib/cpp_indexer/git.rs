@@ -0,0 +1,96 @@
+// Git revisions as source trees
+//
+// Reads files as they were at a revision straight from the object database,
+// so reports can compare two revisions without checking either out.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::lib::cpp_indexer::vfs::SourceFs;
+
+/// A repository's tree at one revision
+#[derive(Debug, Clone)]
+pub struct GitFs {
+    repository: PathBuf,
+    /// Commit id the revision resolved to when opened
+    commit: String,
+}
+
+impl GitFs {
+    /// Resolves `revision` (a tag, branch, commit or expression like HEAD~3) in the repository at `repository`
+    pub fn open<P: AsRef<Path>>(repository: P, revision: &str) -> io::Result<Self> {
+        let repository = repository.as_ref().to_path_buf();
+        let output = git(&repository, &["rev-parse", "--verify", "--end-of-options", &format!("{}^{{commit}}", revision)])?;
+        let commit = String::from_utf8_lossy(&output).trim().to_string();
+        Ok(Self { repository, commit })
+    }
+
+    /// Full id of the commit this tree belongs to
+    pub fn commit(&self) -> &str {
+        &self.commit
+    }
+}
+
+impl SourceFs for GitFs {
+    fn list_files(&self) -> io::Result<Vec<String>> {
+        let output = git(&self.repository, &["ls-tree", "-r", "-z", "--name-only", &self.commit])?;
+        let mut files = split_nul(&output);
+        files.sort();
+        Ok(files)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        git(&self.repository, &["cat-file", "blob", &format!("{}:{}", self.commit, path)])
+    }
+
+    fn stored_path(&self, path: &str) -> String {
+        path.to_string()
+    }
+}
+
+/// Paths that differ between two revisions, including both sides of renames
+pub fn changed_files(from: &GitFs, to: &GitFs) -> io::Result<Vec<String>> {
+    let output = git(&to.repository, &["diff", "--name-only", "-z", "--no-renames", from.commit(), to.commit()])?;
+    let mut files = split_nul(&output);
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Branch checked out in the working tree at `repository`; None when HEAD is detached
+pub fn current_branch(repository: &Path) -> io::Result<Option<String>> {
+    let output = git(repository, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let branch = String::from_utf8_lossy(&output).trim().to_string();
+    Ok(Some(branch).filter(|branch| branch != "HEAD"))
+}
+
+/// Runs git in `repository`, returning its stdout or its stderr as the error
+fn git(repository: &Path, args: &[&str]) -> io::Result<Vec<u8>> {
+    let output = Command::new("git").arg("-C").arg(repository).args(args).output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("git {} failed: {}", args[0], message)));
+    }
+    Ok(output.stdout)
+}
+
+/// Splits NUL-terminated `-z` output into paths
+fn split_nul(output: &[u8]) -> Vec<String> {
+    output
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_nul() {
+        assert_eq!(split_nul(b"src/a b.cpp\0include/x.h\0"), ["src/a b.cpp", "include/x.h"]);
+        assert!(split_nul(b"").is_empty());
+    }
+}
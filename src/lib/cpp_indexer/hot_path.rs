@@ -0,0 +1,256 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use super::callbacks::{identifiers, mask_non_code};
+
+/// Calls that may allocate or free heap memory
+pub const DEFAULT_ALLOCATION_APIS: &[&str] = &[
+    "new", "delete", "malloc", "calloc", "realloc", "free", "aligned_alloc", "posix_memalign",
+    "strdup", "make_shared", "make_unique", "allocate_shared",
+];
+
+/// Calls that may block on a lock held by another thread
+pub const DEFAULT_LOCKING_APIS: &[&str] = &[
+    "lock", "try_lock", "lock_guard", "unique_lock", "scoped_lock", "shared_lock",
+    "pthread_mutex_lock", "pthread_mutex_trylock", "pthread_rwlock_rdlock", "pthread_rwlock_wrlock",
+    "pthread_cond_wait", "sem_wait", "EnterCriticalSection", "WaitForSingleObject",
+    "xSemaphoreTake", "osMutexAcquire",
+];
+
+/// Calls that block or are not async-signal-safe: stdio, file I/O, sleeping, exceptions
+pub const DEFAULT_FORBIDDEN_APIS: &[&str] = &[
+    "throw", "printf", "fprintf", "puts", "cout", "cerr", "syslog", "fopen", "fread", "fwrite",
+    "fclose", "fflush", "sleep", "usleep", "nanosleep", "sleep_for", "sleep_until",
+];
+
+/// Keywords that are hazards on their own, without a following `(` or `<`
+const KEYWORD_APIS: &[&str] = &["new", "delete", "throw"];
+
+/// Why a call is unsafe on a hot path
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardCategory {
+    Allocation,
+    Locking,
+    Forbidden,
+}
+
+/// APIs that must not be reached from a hot-path root, by category
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotPathRules {
+    allocation: BTreeSet<String>,
+    locking: BTreeSet<String>,
+    forbidden: BTreeSet<String>,
+}
+
+/// A call to a disallowed API inside a function body
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Hazard {
+    pub category: HazardCategory,
+    /// The API as written, without qualifiers
+    pub api: String,
+    /// 1-based line in the file
+    pub line: u32,
+    /// 1-based column of the API name
+    pub column: u32,
+}
+
+impl HazardCategory {
+    /// Returns string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HazardCategory::Allocation => "allocation",
+            HazardCategory::Locking => "locking",
+            HazardCategory::Forbidden => "forbidden",
+        }
+    }
+}
+
+impl Default for HotPathRules {
+    fn default() -> Self {
+        let set = |apis: &[&str]| apis.iter().map(|api| api.to_string()).collect();
+        Self {
+            allocation: set(DEFAULT_ALLOCATION_APIS),
+            locking: set(DEFAULT_LOCKING_APIS),
+            forbidden: set(DEFAULT_FORBIDDEN_APIS),
+        }
+    }
+}
+
+impl HotPathRules {
+    /// Creates rules with the default API lists
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds project-specific APIs (e.g. a logging macro) to a category
+    pub fn forbid<I, S>(mut self, category: HazardCategory, apis: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let set = match category {
+            HazardCategory::Allocation => &mut self.allocation,
+            HazardCategory::Locking => &mut self.locking,
+            HazardCategory::Forbidden => &mut self.forbidden,
+        };
+        set.extend(apis.into_iter().map(Into::into));
+        self
+    }
+
+    /// Removes APIs known to be safe in this codebase from every category
+    pub fn allow<I, S>(mut self, apis: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for api in apis {
+            let api = api.as_ref();
+            self.allocation.remove(api);
+            self.locking.remove(api);
+            self.forbidden.remove(api);
+        }
+        self
+    }
+
+    /// Category of an API name, if it is disallowed; allocation wins over locking over forbidden
+    pub fn category_of(&self, api: &str) -> Option<HazardCategory> {
+        if self.allocation.contains(api) {
+            Some(HazardCategory::Allocation)
+        } else if self.locking.contains(api) {
+            Some(HazardCategory::Locking)
+        } else if self.forbidden.contains(api) {
+            Some(HazardCategory::Forbidden)
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds disallowed calls in the body of the function defined at `start_line`
+///
+/// The body is the first `{ ... }` block after `start_line`; if a `;` comes
+/// first the element is a declaration and has no body. Matching is lexical:
+/// a listed name counts when it is called, instantiated (`lock_guard<...>`)
+/// or streamed to (`cout <<`), and `new`, `delete` and `throw` count as
+/// keywords. Comments and string literals are ignored.
+pub fn find_body_hazards(content: &str, start_line: u32, rules: &HotPathRules) -> Vec<Hazard> {
+    let code = mask_non_code(content);
+    let mut hazards = Vec::new();
+    let mut depth = 0usize;
+    let mut in_body = false;
+
+    for (line_index, line) in code.lines().enumerate().skip(start_line.saturating_sub(1) as usize) {
+        let mut body_start = 0;
+        if !in_body {
+            let Some(open) = line.find(['{', ';']) else { continue };
+            if line.as_bytes()[open] == b';' {
+                return hazards;
+            }
+            in_body = true;
+            body_start = open;
+        }
+
+        // Identifiers are only checked up to the brace closing the body
+        let mut body_end = line.len();
+        for (offset, byte) in line.bytes().enumerate().skip(body_start) {
+            match byte {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        body_end = offset;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (start, end) in identifiers(line) {
+            if start < body_start || end > body_end {
+                continue;
+            }
+            let api = &line[start..end];
+            let Some(category) = rules.category_of(api) else { continue };
+            let after = line[end..].trim_start();
+            let used = KEYWORD_APIS.contains(&api) || after.starts_with('(') || after.starts_with('<') || after.starts_with('{');
+            if used {
+                hazards.push(Hazard {
+                    category,
+                    api: api.to_string(),
+                    line: line_index as u32 + 1,
+                    column: start as u32 + 1,
+                });
+            }
+        }
+
+        if depth == 0 {
+            break;
+        }
+    }
+
+    hazards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUDIO: &str = r#"
+void process(float* out, int frames) {
+    std::lock_guard<std::mutex> guard(state_mutex);   // locks
+    auto* scratch = new float[frames];
+    printf("frames=%d\n", frames);
+    // malloc(frames) in a comment is fine
+    log_message("not a hazard");
+    if (frames < 0) { throw std::runtime_error("bad"); }
+}
+
+void mix(float* out);
+
+void after() { free(buffer); }
+"#;
+
+    fn apis(hazards: &[Hazard]) -> Vec<(&str, HazardCategory)> {
+        hazards.iter().map(|hazard| (hazard.api.as_str(), hazard.category)).collect()
+    }
+
+    #[test]
+    fn test_find_body_hazards() {
+        let hazards = find_body_hazards(AUDIO, 2, &HotPathRules::new());
+        assert_eq!(
+            apis(&hazards),
+            vec![
+                ("lock_guard", HazardCategory::Locking),
+                ("new", HazardCategory::Allocation),
+                ("printf", HazardCategory::Forbidden),
+                ("throw", HazardCategory::Forbidden),
+            ]
+        );
+        assert_eq!((hazards[1].line, hazards[1].column), (4, 21));
+
+        // Declarations have no body; one-line bodies end at their closing brace
+        assert!(find_body_hazards(AUDIO, 11, &HotPathRules::new()).is_empty());
+        assert_eq!(apis(&find_body_hazards(AUDIO, 13, &HotPathRules::new())), vec![("free", HazardCategory::Allocation)]);
+    }
+
+    #[test]
+    fn test_rules_are_configurable() {
+        let rules = HotPathRules::new()
+            .forbid(HazardCategory::Forbidden, ["log_message"])
+            .allow(["printf", "throw"]);
+
+        assert_eq!(rules.category_of("log_message"), Some(HazardCategory::Forbidden));
+        assert_eq!(rules.category_of("printf"), None);
+        let found = find_body_hazards(AUDIO, 2, &rules);
+        assert_eq!(
+            apis(&found),
+            vec![
+                ("lock_guard", HazardCategory::Locking),
+                ("new", HazardCategory::Allocation),
+                ("log_message", HazardCategory::Forbidden),
+            ]
+        );
+    }
+}
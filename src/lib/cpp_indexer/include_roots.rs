@@ -0,0 +1,235 @@
+// Include path inference
+//
+// Without a compilation database, libclang can't find a project's own headers
+// unless it is told where the include roots are. A header-only library
+// usually keeps them in a directory such as `include/` whose layout matches
+// the `#include` lines. Inference probes every file for includes that don't
+// resolve, adds the directories under which those paths exist, and probes
+// again until no new directory helps.
+
+use clang::{Clang, Index};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::lib::cpp_indexer::clang_parser::with_clang;
+use crate::lib::cpp_indexer::language::SourceLanguage;
+
+/// Probing rounds before inference gives up on stabilizing
+pub const MAX_INFERENCE_ROUNDS: usize = 8;
+
+/// Finds the includes of a file that don't resolve with the given include directories
+pub trait IncludeProbe {
+    fn missing_includes(&mut self, file: &Path, include_dirs: &[PathBuf]) -> Vec<String>;
+}
+
+/// Reads `#include` lines and checks each path against the file's directory and the include directories
+///
+/// Works without libclang, but sees includes inside inactive `#if` blocks
+/// too.
+#[derive(Debug)]
+pub struct ScanProbe {
+    include: Regex,
+}
+
+/// Parses each file with libclang and collects its "file not found" diagnostics
+///
+/// clang stops at the first missing include, so a file may need several
+/// rounds before all of its includes are seen.
+#[derive(Debug, Clone)]
+pub struct ClangProbe {
+    flags: Vec<String>,
+}
+
+/// Include directories inferred for a tree
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IncludeInference {
+    /// Absolute include directories, in the order they were found
+    pub include_dirs: Vec<PathBuf>,
+    /// Probing rounds run
+    pub rounds: usize,
+    /// Includes no directory of the tree provides, such as system headers
+    pub unresolved: BTreeSet<String>,
+}
+
+impl Default for ScanProbe {
+    fn default() -> Self {
+        Self {
+            include: Regex::new(r#"(?m)^\s*#\s*include\s*["<]([^">]+)[">]"#).expect("include pattern is valid"),
+        }
+    }
+}
+
+impl IncludeProbe for ScanProbe {
+    fn missing_includes(&mut self, file: &Path, include_dirs: &[PathBuf]) -> Vec<String> {
+        let Ok(content) = std::fs::read_to_string(file) else {
+            return Vec::new();
+        };
+        let directory = file.parent().unwrap_or(Path::new(""));
+        self.include
+            .captures_iter(&content)
+            .map(|captures| captures[1].to_string())
+            .filter(|include| !std::iter::once(directory).chain(include_dirs.iter().map(PathBuf::as_path)).any(|dir| dir.join(include).is_file()))
+            .collect()
+    }
+}
+
+impl ClangProbe {
+    /// Probes with `flags` (e.g. macro definitions) plus the include directories of each round
+    pub fn new(flags: Vec<String>) -> Self {
+        Self { flags }
+    }
+
+    /// Parses `file` without function bodies and lists the includes libclang couldn't find
+    fn probe(&self, clang: &Clang, file: &Path, include_dirs: &[PathBuf]) -> Vec<String> {
+        let index = Index::new(clang, false, false);
+        let language = SourceLanguage::from_path(file);
+        let mut flags = language.adapt_flags(&self.flags);
+        if language == SourceLanguage::Cpp {
+            // Headers would otherwise be read as C
+            flags.splice(0..0, ["-x".to_string(), "c++".to_string()]);
+        }
+        flags.extend(include_dirs.iter().map(|dir| format!("-I{}", dir.display())));
+
+        let Ok(translation_unit) = index.parser(file).arguments(&flags).skip_function_bodies(true).parse() else {
+            return Vec::new();
+        };
+        translation_unit
+            .get_diagnostics()
+            .iter()
+            .filter_map(|diagnostic| missing_include(&diagnostic.get_text()))
+            .collect()
+    }
+}
+
+impl IncludeProbe for ClangProbe {
+    fn missing_includes(&mut self, file: &Path, include_dirs: &[PathBuf]) -> Vec<String> {
+        with_clang(|clang| Ok::<_, String>(self.probe(clang, file, include_dirs))).unwrap_or_default()
+    }
+}
+
+/// The path of a "'foo/bar.h' file not found" diagnostic
+pub fn missing_include(diagnostic: &str) -> Option<String> {
+    let path = diagnostic.strip_prefix('\'')?.strip_suffix("' file not found")?;
+    Some(path.to_string())
+}
+
+impl IncludeInference {
+    /// `-I` flags of the inferred directories
+    pub fn flags(&self) -> Vec<String> {
+        self.include_dirs.iter().map(|dir| format!("-I{}", dir.display())).collect()
+    }
+}
+
+/// Infers include directories for `files`, relative paths under `base_path`
+///
+/// Each round probes the files that still have missing includes. A missing
+/// include `a/b.h` is provided by every directory `d` of the tree holding
+/// `d/a/b.h`; of those, the directory providing the most missing includes
+/// is added (ties go to the shallowest path). Inference stops when a round
+/// adds nothing.
+pub fn infer_include_dirs<P: IncludeProbe>(base_path: &Path, files: &[String], probe: &mut P) -> IncludeInference {
+    // File name -> paths of the tree ending in it, for suffix matching
+    let mut by_name: HashMap<&str, Vec<&str>> = HashMap::new();
+    for file in files {
+        let name = file.rsplit('/').next().unwrap_or(file);
+        by_name.entry(name).or_default().push(file);
+    }
+    let providers = |include: &str| -> Vec<String> {
+        let include = include.trim_start_matches("./");
+        let name = include.rsplit('/').next().unwrap_or(include);
+        by_name
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter_map(|path| match path.strip_suffix(include) {
+                Some("") => Some(String::new()),
+                Some(root) => root.strip_suffix('/').map(str::to_string),
+                None => None,
+            })
+            .collect()
+    };
+
+    let mut inference = IncludeInference::default();
+    let mut pending: Vec<&String> = files.iter().collect();
+    while !pending.is_empty() && inference.rounds < MAX_INFERENCE_ROUNDS {
+        inference.rounds += 1;
+        let mut missing: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+        for file in pending {
+            let includes = probe.missing_includes(&base_path.join(file), &inference.include_dirs);
+            if !includes.is_empty() {
+                missing.insert(file, includes);
+            }
+        }
+
+        // Directory -> missing includes it provides
+        let mut provided: BTreeMap<String, BTreeSet<&str>> = BTreeMap::new();
+        let mut unprovided: BTreeSet<&str> = BTreeSet::new();
+        for include in missing.values().flatten() {
+            let roots = providers(include);
+            if roots.is_empty() {
+                unprovided.insert(include);
+            }
+            for root in roots {
+                provided.entry(root).or_default().insert(include);
+            }
+        }
+        inference.unresolved = unprovided.iter().map(|include| include.to_string()).collect();
+
+        let mut covered: BTreeSet<&str> = BTreeSet::new();
+        let mut added = false;
+        let mut ranked: Vec<(&String, &BTreeSet<&str>)> = provided.iter().collect();
+        ranked.sort_by_key(|(root, includes)| (std::cmp::Reverse(includes.len()), root.matches('/').count() + usize::from(!root.is_empty()), (*root).clone()));
+        for (root, includes) in ranked {
+            if includes.iter().all(|include| covered.contains(include)) {
+                continue;
+            }
+            covered.extend(includes.iter().copied());
+            let dir = base_path.join(root);
+            if !inference.include_dirs.contains(&dir) {
+                inference.include_dirs.push(dir);
+                added = true;
+            }
+        }
+        if !added {
+            break;
+        }
+        pending = missing.into_keys().collect();
+    }
+    inference
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_infer_include_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        for (path, content) in [
+            ("include/dsp/filter.hpp", "#include \"dsp/detail/math.hpp\"\n#include <vector>\n"),
+            ("include/dsp/detail/math.hpp", "#include <cmath>\n"),
+            ("include/dsp/biquad.hpp", "#include \"filter.hpp\"\n"),
+            ("third_party/fmt/include/fmt/core.h", ""),
+            ("tests/filter_test.cpp", "#include <dsp/filter.hpp>\n#include \"fmt/core.h\"\n"),
+        ] {
+            fs::create_dir_all(base.join(path).parent().unwrap()).unwrap();
+            fs::write(base.join(path), content).unwrap();
+        }
+        let files: Vec<String> = ["include/dsp/biquad.hpp", "include/dsp/detail/math.hpp", "include/dsp/filter.hpp", "tests/filter_test.cpp", "third_party/fmt/include/fmt/core.h"]
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+
+        let inference = infer_include_dirs(base, &files, &mut ScanProbe::default());
+        assert_eq!(inference.include_dirs, [base.join("include"), base.join("third_party/fmt/include")]);
+        assert_eq!(inference.unresolved, ["cmath", "vector"].iter().map(|include| include.to_string()).collect());
+        assert_eq!(inference.rounds, 2);
+        assert_eq!(inference.flags()[0], format!("-I{}", base.join("include").display()));
+
+        assert_eq!(missing_include("'dsp/filter.hpp' file not found").as_deref(), Some("dsp/filter.hpp"));
+        assert_eq!(missing_include("use of undeclared identifier 'x'"), None);
+    }
+}
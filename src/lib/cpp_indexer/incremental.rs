@@ -1,11 +1,28 @@
-use crate::lib::cpp_indexer::symbol_extractor::{SymbolExtractor, ExtractedSymbol};
-use crate::lib::storage::models::file_metadata::FileMetadata;
+use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+use crate::lib::cpp_indexer::parse_worker::SubprocessWorker;
+use crate::lib::cpp_indexer::pipeline::{ExtractError, FileExtractor};
+use crate::lib::cpp_indexer::spill::{estimated_size, SpillStore};
+use crate::lib::cpp_indexer::symbol_extractor::{SymbolExtractor, ExtractedSymbol, ExtractionResult};
+use crate::lib::cpp_indexer::vendored::{is_aliased, DuplicateTree, VendoredDedup};
+use crate::lib::cpp_indexer::vfs::{is_source_file, SourceFs};
+use crate::lib::cpp_indexer::index_settings::{FileSelection, IndexSettings};
+use crate::lib::mcp_server::freshness::{check_file, store_reindexed, Freshness, ReparsedFile};
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::batch_writer::{PriorityGate, DEFAULT_MAX_YIELD_MS};
+use crate::lib::storage::disk_space::SizeEstimate;
+use crate::lib::storage::error::StorageError;
+use crate::lib::storage::models::file_metadata::{FileDetail, FileMetadata};
+use crate::lib::storage::models::index_error::{IndexError, IndexErrorKind};
+use crate::lib::storage::ordering::path_key;
+use crate::lib::storage::repository::Repository;
 use sha2::{Sha256, Digest};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::fs;
 use tokio::time::Instant;
+use tracing::debug;
 
 #[derive(Debug, Clone)]
 pub struct FileNode {
@@ -56,7 +73,9 @@ impl MerkleTree {
         };
         
         self.nodes.insert(hash.clone(), merkle_node);
-        self.file_to_hash.insert(file_node.path, hash);
+        if let Some(previous) = self.file_to_hash.insert(file_node.path, hash) {
+            self.nodes.remove(&previous);
+        }
         
         self.recompute_root()?;
         Ok(())
@@ -85,11 +104,13 @@ impl MerkleTree {
     }
 
     fn recompute_root(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let leaf_hashes: Vec<String> = self.nodes
-            .iter()
-            .filter(|(_, node)| node.is_leaf)
-            .map(|(hash, _)| hash.clone())
-            .collect();
+        // Inner nodes of the previous root are rebuilt below; keeping them would grow the tree on every change
+        self.nodes.retain(|_, node| node.is_leaf);
+
+        // Leaves are taken from the path map, which still has the hashes of dropped leaves
+        let mut leaves: Vec<(&PathBuf, &String)> = self.file_to_hash.iter().collect();
+        leaves.sort();
+        let leaf_hashes: Vec<String> = leaves.into_iter().map(|(_, hash)| hash.clone()).collect();
         
         if leaf_hashes.is_empty() {
             self.root_hash = None;
@@ -140,6 +161,16 @@ impl MerkleTree {
         Ok(format!("{:x}", hasher.finalize()))
     }
 
+    /// Drops the leaf nodes to save memory, returning how many there were
+    ///
+    /// Only the path-to-hash map stays in memory, which is all computing the
+    /// root and comparing trees needs.
+    pub fn drop_leaves(&mut self) -> usize {
+        let count = self.nodes.len();
+        self.nodes.retain(|_, node| !node.is_leaf);
+        count - self.nodes.len()
+    }
+
     pub fn get_root_hash(&self) -> Option<&String> {
         self.root_hash.as_ref()
     }
@@ -175,6 +206,81 @@ pub struct IncrementalIndexer {
     current_tree: MerkleTree,
     file_cache: HashMap<PathBuf, FileNode>,
     dependency_graph: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// Where file nodes and Merkle leaves go once `memory_budget` is exceeded
+    spill: Option<SpillStore>,
+    /// Bytes of file state kept in memory before spilling (0 = unlimited)
+    memory_budget: usize,
+    /// Estimated bytes of the file nodes in `file_cache`
+    cached_bytes: usize,
+    /// Which files the directory walks select
+    selection: FileSelection,
+    /// Where indexed and removed files are stored, if anywhere
+    store: Option<IndexStore>,
+    /// Parses files in a process of its own instead of with `symbol_extractor`
+    parse_worker: Option<SubprocessWorker>,
+    /// Queries that stored and removed files wait for, if any
+    priority_gate: Option<PriorityGate>,
+}
+
+/// The index an indexer keeps up to date
+struct IndexStore {
+    repository: Arc<Mutex<Repository>>,
+    index: CodeIndex,
+    settings: IndexSettings,
+    /// Canonical base path of the index, which stored paths are relative to
+    base_path: PathBuf,
+}
+
+impl IndexStore {
+    /// Path of `file_path` as the index stores it, or None outside the base path
+    fn stored_path(&self, file_path: &Path) -> Option<String> {
+        let file_path = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+        let relative = file_path.strip_prefix(&self.base_path).ok()?;
+        Some(relative.to_string_lossy().replace('\\', "/"))
+    }
+
+    fn store_file(&self, file_path: &Path, content_hash: &str, extraction: &ExtractionResult) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(stored_path) = self.stored_path(file_path) else { return Ok(()) };
+        let repository = self.repository.lock().map_err(|_| "Repository lock poisoned")?;
+        let mut report = check_file(&repository, &self.index, &stored_path)?;
+        if report.status == Freshness::Fresh {
+            return Ok(());
+        }
+        report.current_hash = Some(content_hash.to_string());
+        // A full disk stops the update instead of failing file after file
+        if let Some(guard) = repository.disk_space_guard()? {
+            let size = std::fs::metadata(file_path).map_or(0, |metadata| metadata.len());
+            guard.ensure_available(SizeEstimate::database_bytes_for(size, 1)).map_err(StorageError::from)?;
+        }
+        let policy = self.settings.selection.detail_policy();
+        let reparsed = ReparsedFile::from_extraction(extraction, &self.index, &self.settings, &stored_path, &policy);
+        store_reindexed(&repository, &self.index, &mut report, reparsed)?;
+        repository.recount_index_totals(&self.index.id)?;
+        Ok(())
+    }
+
+    /// Records why `file_path` couldn't be indexed; storage errors aren't the file's fault and are returned instead
+    fn record_error(&self, file_path: &Path, error: Box<dyn std::error::Error>) -> Result<(), Box<dyn std::error::Error>> {
+        let kind = match error.downcast_ref::<ExtractError>() {
+            Some(error) => error.kind,
+            None if error.is::<std::io::Error>() => IndexErrorKind::Read,
+            None if error.is::<StorageError>() => return Err(error),
+            None => IndexErrorKind::Parse,
+        };
+        let Some(stored_path) = self.stored_path(file_path) else { return Ok(()) };
+        let repository = self.repository.lock().map_err(|_| "Repository lock poisoned")?;
+        repository.record_index_error(&IndexError::new(self.index.id, stored_path, kind, error.to_string()))?;
+        Ok(())
+    }
+
+    fn remove_file(&self, file_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(stored_path) = self.stored_path(file_path) else { return Ok(()) };
+        let repository = self.repository.lock().map_err(|_| "Repository lock poisoned")?;
+        if repository.get_file_metadata_by_path(&self.index.id, &stored_path)?.is_some() {
+            repository.remove_file(&self.index.id, &stored_path)?;
+        }
+        Ok(())
+    }
 }
 
 impl IncrementalIndexer {
@@ -186,16 +292,82 @@ impl IncrementalIndexer {
             current_tree: MerkleTree::new(),
             file_cache: HashMap::new(),
             dependency_graph: HashMap::new(),
+            spill: None,
+            memory_budget: 0,
+            cached_bytes: 0,
+            selection: FileSelection::default(),
+            store: None,
+            parse_worker: None,
+            priority_gate: None,
         })
     }
 
+    /// Keeps at most about `budget_bytes` of file state in memory
+    ///
+    /// Past the budget every cached file node and Merkle leaf is written to
+    /// a scratch database at `spill_path` and memory starts over; spilled
+    /// files are read back when they are looked up. A budget of 0 keeps
+    /// everything in memory.
+    pub fn with_memory_budget(mut self, budget_bytes: usize, spill_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if budget_bytes > 0 {
+            self.spill = Some(SpillStore::create(spill_path)?);
+        }
+        self.memory_budget = budget_bytes;
+        Ok(self)
+    }
+
+    /// Parses each file with its flags from a compilation database
+    pub fn with_compilation_database(mut self, database: CompilationDatabase) -> Self {
+        self.symbol_extractor = self.symbol_extractor.with_compilation_database(database);
+        self
+    }
+
+    /// Walks directories selecting files like `selection`, e.g. the one saved for an index
+    pub fn with_selection(mut self, selection: FileSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Stores indexed and removed files in `index`, parsing and selecting them with its saved settings
+    ///
+    /// Files are stored the way an inline re-index stores them: unchanged
+    /// files are left alone, changed ones replace their symbols, includes
+    /// and calls, and new ones are added.
+    pub fn with_repository(mut self, repository: Arc<Mutex<Repository>>, index: CodeIndex, settings: IndexSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        self.symbol_extractor = settings.extractor()?;
+        self.selection = settings.selection.clone();
+        let base_path = Path::new(&index.base_path);
+        let base_path = base_path.canonicalize().unwrap_or_else(|_| base_path.to_path_buf());
+        self.store = Some(IndexStore { repository, index, settings, base_path });
+        Ok(self)
+    }
+
+    /// Parses files with `worker`, so a file that crashes or hangs libclang only fails itself
+    pub fn with_parse_worker(mut self, worker: SubprocessWorker) -> Self {
+        self.parse_worker = Some(worker);
+        self
+    }
+
+    /// Holds each store and removal back while queries on `gate` are in flight, up to the longest yield
+    pub fn with_priority_gate(mut self, gate: PriorityGate) -> Self {
+        self.priority_gate = Some(gate);
+        self
+    }
+
+    /// Pauses while queries on the priority gate are in flight, if there is one
+    fn yield_to_queries(&self) {
+        if let Some(gate) = &self.priority_gate {
+            gate.wait_idle(Duration::from_millis(DEFAULT_MAX_YIELD_MS));
+        }
+    }
+
     pub async fn index_file(&mut self, file_path: &Path) -> Result<IncrementalResult, Box<dyn std::error::Error>> {
         let start_time = Instant::now();
         
         let file_metadata = self.get_file_metadata(file_path).await?;
         let content_hash = self.compute_content_hash(file_path).await?;
         
-        let needs_reindex = if let Some(cached_node) = self.file_cache.get(file_path) {
+        let needs_reindex = if let Some(cached_node) = self.cached_node(file_path)? {
             cached_node.content_hash != content_hash || 
             cached_node.last_modified != file_metadata.last_modified.timestamp() as u64
         } else {
@@ -212,7 +384,23 @@ impl IncrementalIndexer {
             });
         }
         
-        let extraction_result = self.symbol_extractor.extract_symbols(file_path).await?;
+        let extraction_result = match &mut self.parse_worker {
+            Some(worker) => {
+                let started = Instant::now();
+                let extraction = worker.extract(file_path)?;
+                ExtractionResult {
+                    file_path: file_path.to_path_buf(),
+                    extraction_time_ms: started.elapsed().as_millis() as u32,
+                    // The worker doesn't say which parser found a symbol
+                    tree_sitter_symbols: 0,
+                    clang_symbols: 0,
+                    symbols: extraction.symbols,
+                    includes: extraction.includes,
+                    calls: extraction.calls,
+                }
+            }
+            None => self.symbol_extractor.extract_symbols(file_path).await?,
+        };
         let symbols_hash = self.compute_symbols_hash(&extraction_result.symbols)?;
         
         let dependencies = self.extract_file_dependencies(&extraction_result.includes).await?;
@@ -230,8 +418,12 @@ impl IncrementalIndexer {
         self.update_dependency_graph(file_path, &dependencies)?;
         let affected_files = self.get_affected_files(file_path)?;
         
-        self.file_cache.insert(file_path.to_path_buf(), file_node.clone());
-        self.current_tree.add_file_node(file_node)?;
+        self.current_tree.add_file_node(file_node.clone())?;
+        if let Some(store) = &self.store {
+            self.yield_to_queries();
+            store.store_file(file_path, &file_node.content_hash, &extraction_result)?;
+        }
+        self.cache_node(file_node)?;
         
         let processing_time = start_time.elapsed();
         
@@ -244,18 +436,52 @@ impl IncrementalIndexer {
         })
     }
 
+    /// Indexes a file like `index_file`, recording a failure in the stored index's errors instead of failing
+    ///
+    /// Only storage errors are returned.
+    pub async fn index_file_or_record(&mut self, file_path: &Path) -> Result<IncrementalResult, Box<dyn std::error::Error>> {
+        let start_time = Instant::now();
+        let error = match self.index_file(file_path).await {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+        let message = error.to_string();
+        debug!("Failed to index {}: {}", file_path.display(), message);
+        if let Some(store) = &self.store {
+            store.record_error(file_path, error)?;
+        } else if error.is::<StorageError>() {
+            return Err(error);
+        }
+        Ok(IncrementalResult {
+            file_path: file_path.to_path_buf(),
+            action: IndexAction::Failed(message),
+            affected_files: Vec::new(),
+            symbols_extracted: 0,
+            processing_time_ms: start_time.elapsed().as_millis() as u32,
+        })
+    }
+
     pub async fn remove_file(&mut self, file_path: &Path) -> Result<IncrementalResult, Box<dyn std::error::Error>> {
         let start_time = Instant::now();
         
         let affected_files = self.get_affected_files(file_path)?;
         
-        self.file_cache.remove(file_path);
+        if let Some(node) = self.file_cache.remove(file_path) {
+            self.cached_bytes = self.cached_bytes.saturating_sub(estimated_size(&node));
+        }
+        if let Some(spill) = &self.spill {
+            spill.remove_node(file_path)?;
+        }
         self.current_tree.remove_file_node(file_path)?;
         self.dependency_graph.remove(file_path);
         
         for (_, deps) in self.dependency_graph.iter_mut() {
             deps.remove(file_path);
         }
+        if let Some(store) = &self.store {
+            self.yield_to_queries();
+            store.remove_file(file_path)?;
+        }
         
         let processing_time = start_time.elapsed();
         
@@ -268,38 +494,134 @@ impl IncrementalIndexer {
         })
     }
 
-    pub async fn update_directory(&mut self, directory_path: &Path) -> Result<Vec<IncrementalResult>, Box<dyn std::error::Error>> {
+    /// Indexes a directory, skipping byte-identical copies of vendored trees
+    ///
+    /// All source files are hashed first; files in a copy that duplicates
+    /// another directory are skipped and reported as aliases of the indexed copy.
+    pub async fn update_directory_deduplicated(
+        &mut self,
+        directory_path: &Path,
+        dedup: &VendoredDedup,
+    ) -> Result<DeduplicatedResult, Box<dyn std::error::Error>> {
+        let mut files = self.collect_source_files(directory_path).await?;
+        files.sort_by_key(|path| path_key(path));
+
+        let mut hashes = BTreeMap::new();
+        for path in &files {
+            let relative = path.strip_prefix(directory_path).unwrap_or(path).to_path_buf();
+            hashes.insert(relative, self.compute_content_hash(path).await?);
+        }
+        let duplicates = dedup.find_duplicate_trees(&hashes);
+
         let mut results = Vec::new();
-        let cpp_extensions = [".cpp", ".cxx", ".cc", ".c", ".hpp", ".hxx", ".h"];
-        
-        let mut entries = fs::read_dir(directory_path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            
-            if path.is_file() {
-                if let Some(extension) = path.extension() {
-                    if cpp_extensions.iter().any(|&ext| extension == &ext[1..]) {
-                        let result = self.index_file(&path).await?;
-                        results.push(result);
-                    }
-                }
-            } else if path.is_dir() {
-                let sub_results = Box::pin(self.update_directory(&path)).await?;
-                results.extend(sub_results);
+        let mut skipped_files = 0;
+        for path in &files {
+            let relative = path.strip_prefix(directory_path).unwrap_or(path);
+            if is_aliased(&duplicates, relative) {
+                skipped_files += 1;
+                continue;
             }
+            results.push(self.index_file_or_record(path).await?);
+        }
+
+        Ok(DeduplicatedResult {
+            results,
+            duplicates,
+            skipped_files,
+        })
+    }
+
+    /// Indexes the source files of a virtual filesystem, such as a tar archive
+    ///
+    /// Files are read and hashed through `source_fs` and cached under their
+    /// stored path, so unchanged files are skipped on the next run. Only the
+    /// syntactic pass runs, since LibClang needs files on disk.
+    pub async fn index_source_fs(&mut self, source_fs: &dyn SourceFs) -> Result<Vec<IncrementalResult>, Box<dyn std::error::Error>> {
+        let mut results = Vec::new();
+        for path in source_fs.list_files()?.iter().filter(|path| is_source_file(path)) {
+            let start_time = Instant::now();
+            let stored_path = PathBuf::from(source_fs.stored_path(path));
+            let content = source_fs.read(path)?;
+
+            let mut hasher = Sha256::new();
+            hasher.update(&content);
+            let content_hash = format!("{:x}", hasher.finalize());
+
+            if self.cached_node(&stored_path)?.is_some_and(|cached| cached.content_hash == content_hash) {
+                results.push(IncrementalResult {
+                    file_path: stored_path,
+                    action: IndexAction::Skipped,
+                    affected_files: Vec::new(),
+                    symbols_extracted: 0,
+                    processing_time_ms: start_time.elapsed().as_millis() as u32,
+                });
+                continue;
+            }
+
+            let text = String::from_utf8_lossy(&content);
+            let extraction_result = self.symbol_extractor.extract_symbols_from_content(&text, &stored_path)?;
+            let file_node = FileNode {
+                path: stored_path.clone(),
+                metadata_hash: content_hash.clone(),
+                content_hash,
+                last_modified: 0,
+                size: content.len() as u64,
+                dependencies: Vec::new(),
+                dependents: Vec::new(),
+                symbols_hash: self.compute_symbols_hash(&extraction_result.symbols)?,
+            };
+
+            self.current_tree.add_file_node(file_node.clone())?;
+            self.cache_node(file_node)?;
+
+            results.push(IncrementalResult {
+                file_path: stored_path,
+                action: IndexAction::Indexed,
+                affected_files: Vec::new(),
+                symbols_extracted: extraction_result.symbols.len(),
+                processing_time_ms: start_time.elapsed().as_millis() as u32,
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Source files under a directory that the selection includes
+    async fn collect_source_files(&self, directory_path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let selection = self.selection.clone();
+        let root = directory_path.to_path_buf();
+        let files = tokio::task::spawn_blocking(move || selection.source_files(&root)).await??;
+        Ok(files.into_iter().map(|file| directory_path.join(file)).collect())
+    }
+
+    /// Indexes the source files under a directory that the selection includes
+    ///
+    /// A file that fails to read or parse is reported as failed, and
+    /// recorded in the stored index's errors; the other files are still
+    /// indexed.
+    pub async fn update_directory(&mut self, directory_path: &Path) -> Result<Vec<IncrementalResult>, Box<dyn std::error::Error>> {
+        let mut results = Vec::new();
+        for path in self.collect_source_files(directory_path).await? {
+            results.push(self.index_file_or_record(&path).await?);
         }
-        
         Ok(results)
     }
 
     pub fn get_index_status(&self) -> IndexStatus {
-        let total_files = self.file_cache.len();
-        let total_dependencies = self.dependency_graph.values().map(|deps| deps.len()).sum();
+        let spilled_paths = match &self.spill {
+            Some(spill) => spill.node_paths().unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let spilled_dependencies = self.spill.as_ref().and_then(|spill| spill.dependency_count().ok()).unwrap_or(0);
+        // Nodes are in memory or spilled, never both, and each carries its own includes
+        let total_files = self.file_cache.len() + spilled_paths.len();
+        let total_dependencies = self.file_cache.values().map(|node| node.dependencies.len()).sum::<usize>() + spilled_dependencies;
         
         let file_types = self.file_cache
             .keys()
+            .chain(&spilled_paths)
             .filter_map(|path| path.extension())
-            .fold(HashMap::new(), |mut acc, ext| {
+            .fold(BTreeMap::new(), |mut acc, ext| {
                 *acc.entry(ext.to_string_lossy().to_string()).or_insert(0) += 1;
                 acc
             });
@@ -326,6 +648,7 @@ impl IncrementalIndexer {
             size_bytes: metadata.len(),
             symbol_count: 0,
             indexed_at: chrono::Utc::now(),
+            detail: FileDetail::Full,
         })
     }
 
@@ -378,17 +701,62 @@ impl IncrementalIndexer {
                 if !cached_node.dependents.contains(&file_path.to_path_buf()) {
                     cached_node.dependents.push(file_path.to_path_buf());
                 }
+            } else if let Some(mut spilled_node) = self.cached_node(dep)? {
+                if !spilled_node.dependents.contains(&file_path.to_path_buf()) {
+                    spilled_node.dependents.push(file_path.to_path_buf());
+                    self.cache_node(spilled_node)?;
+                }
             }
         }
         
         Ok(())
     }
 
+    /// The node of a file, from memory or the spill store
+    fn cached_node(&self, file_path: &Path) -> Result<Option<FileNode>, Box<dyn std::error::Error>> {
+        if let Some(node) = self.file_cache.get(file_path) {
+            return Ok(Some(node.clone()));
+        }
+        match &self.spill {
+            Some(spill) => Ok(spill.get_node(file_path)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Keeps a file node in memory, spilling everything once over the memory budget
+    ///
+    /// A node is either in memory or in the spill store, never both, so
+    /// counting files never sees one twice.
+    fn cache_node(&mut self, node: FileNode) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(spill) = self.spill.as_mut() else {
+            self.file_cache.insert(node.path.clone(), node);
+            return Ok(());
+        };
+        spill.remove_node(&node.path)?;
+        self.cached_bytes += estimated_size(&node);
+        if let Some(previous) = self.file_cache.insert(node.path.clone(), node) {
+            self.cached_bytes = self.cached_bytes.saturating_sub(estimated_size(&previous));
+        }
+        if self.cached_bytes <= self.memory_budget {
+            return Ok(());
+        }
+        spill.put_nodes(self.file_cache.values())?;
+        let leaves = self.current_tree.drop_leaves();
+        debug!("Spilled {} file nodes and dropped {} Merkle leaves ({} bytes)", self.file_cache.len(), leaves, self.cached_bytes);
+        // The spilled nodes carry their includes, so the in-memory graph of their dependencies goes too
+        for path in self.file_cache.keys() {
+            self.dependency_graph.remove(path);
+        }
+        self.file_cache.clear();
+        self.cached_bytes = 0;
+        Ok(())
+    }
+
     fn get_affected_files(&self, file_path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
         let mut affected = Vec::new();
         let mut visited = HashSet::new();
         
-        self.collect_dependents_recursive(file_path, &mut affected, &mut visited);
+        self.collect_dependents_recursive(file_path, &mut affected, &mut visited)?;
         
         Ok(affected)
     }
@@ -398,21 +766,22 @@ impl IncrementalIndexer {
         file_path: &Path,
         affected: &mut Vec<PathBuf>,
         visited: &mut HashSet<PathBuf>,
-    ) {
+    ) -> Result<(), Box<dyn std::error::Error>> {
         if visited.contains(file_path) {
-            return;
+            return Ok(());
         }
         
         visited.insert(file_path.to_path_buf());
         
-        if let Some(file_node) = self.file_cache.get(file_path) {
+        if let Some(file_node) = self.cached_node(file_path)? {
             for dependent in &file_node.dependents {
                 if !affected.contains(dependent) {
                     affected.push(dependent.clone());
                 }
-                self.collect_dependents_recursive(dependent, affected, visited);
+                self.collect_dependents_recursive(dependent, affected, visited)?;
             }
         }
+        Ok(())
     }
 
     pub fn compare_with_previous(&self, previous_tree: &MerkleTree) -> ComparisonResult {
@@ -435,6 +804,8 @@ pub enum IndexAction {
     Indexed,
     Skipped,
     Removed,
+    /// The file couldn't be indexed, for this reason
+    Failed(String),
 }
 
 #[derive(Debug)]
@@ -446,11 +817,19 @@ pub struct IncrementalResult {
     pub processing_time_ms: u32,
 }
 
+#[derive(Debug)]
+pub struct DeduplicatedResult {
+    pub results: Vec<IncrementalResult>,
+    /// Vendored trees found, with the copies that were skipped
+    pub duplicates: Vec<DuplicateTree>,
+    pub skipped_files: usize,
+}
+
 #[derive(Debug)]
 pub struct IndexStatus {
     pub total_files: usize,
     pub total_dependencies: usize,
-    pub file_types: HashMap<String, usize>,
+    pub file_types: BTreeMap<String, usize>,
     pub merkle_root: Option<String>,
     pub last_updated: u64,
 }
@@ -497,7 +876,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_file_hash_computation() {
-        let indexer = IncrementalIndexer::new(None).expect("Failed to create indexer");
+        let _indexer = IncrementalIndexer::new(None).expect("Failed to create indexer");
         
         let file_node = FileNode {
             path: PathBuf::from("test.cpp"),
@@ -532,4 +911,176 @@ mod tests {
         assert!(indexer.dependency_graph.contains_key(&file_path));
         assert_eq!(indexer.dependency_graph[&file_path].len(), 2);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_memory_budget_spills_file_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("src");
+        std::fs::create_dir(&source).unwrap();
+        for name in ["mixer", "player", "format"] {
+            std::fs::write(source.join(format!("{}.cpp", name)), format!("int {}_gain() {{ return 1; }}\n", name)).unwrap();
+        }
+
+        // A one-byte budget spills after every file
+        let mut indexer = IncrementalIndexer::new(None)
+            .unwrap()
+            .with_memory_budget(1, &dir.path().join("index.spill"))
+            .unwrap();
+        let first = indexer.update_directory(&source).await.unwrap();
+        assert!(first.iter().all(|result| matches!(result.action, IndexAction::Indexed)));
+        assert!(indexer.file_cache.is_empty());
+        let root = indexer.current_tree.get_root_hash().cloned();
+
+        let status = indexer.get_index_status();
+        assert_eq!(status.total_files, 3);
+        assert_eq!(status.file_types.get("cpp"), Some(&3));
+
+        // Unchanged files are recognised from the spilled nodes
+        let second = indexer.update_directory(&source).await.unwrap();
+        assert!(second.iter().all(|result| matches!(result.action, IndexAction::Skipped)));
+        assert_eq!(indexer.current_tree.get_root_hash().cloned(), root);
+
+        indexer.remove_file(&source.join("player.cpp")).await.unwrap();
+        assert_eq!(indexer.get_index_status().total_files, 2);
+    }
+
+    #[test]
+    fn test_spilled_node_read_back_counts_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let node = |name: &str| FileNode {
+            path: PathBuf::from(name),
+            content_hash: "c".repeat(64),
+            metadata_hash: "m".repeat(64),
+            last_modified: 1,
+            size: 10,
+            dependencies: vec![PathBuf::from("mixer.h")],
+            dependents: Vec::new(),
+            symbols_hash: "s".repeat(64),
+        };
+        // Room for one node: the second spills both
+        let budget = estimated_size(&node("a.cpp")) * 3 / 2;
+        let mut indexer = IncrementalIndexer::new(None)
+            .unwrap()
+            .with_memory_budget(budget, &dir.path().join("index.spill"))
+            .unwrap();
+        indexer.cache_node(node("a.cpp")).unwrap();
+        indexer.cache_node(node("b.cpp")).unwrap();
+        assert!(indexer.file_cache.is_empty());
+
+        // A node brought back into memory leaves the spill store
+        let mut a = indexer.cached_node(Path::new("a.cpp")).unwrap().unwrap();
+        a.dependents.push(PathBuf::from("c.cpp"));
+        indexer.cache_node(a).unwrap();
+        assert_eq!(indexer.file_cache.len(), 1);
+        let status = indexer.get_index_status();
+        assert_eq!((status.total_files, status.total_dependencies), (2, 2));
+    }
+
+    #[test]
+    fn test_store_keeps_index_current() {
+        use crate::lib::storage::models::code_element::SymbolType;
+        use crate::lib::mcp_server::freshness::content_hash;
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("mixer.cpp");
+        std::fs::write(&path, "void mix() {}").unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("watched".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        let settings = IndexSettings::load(&repository, &index).unwrap();
+        let store = IndexStore {
+            repository: Arc::new(Mutex::new(repository)),
+            index: index.clone(),
+            settings,
+            base_path: dir.path().canonicalize().unwrap(),
+        };
+
+        let symbol = ExtractedSymbol {
+            name: "mix".to_string(),
+            symbol_type: SymbolType::Function,
+            visibility: None,
+            file_path: path.clone(),
+            start_line: 1,
+            end_line: 1,
+            start_column: 1,
+            end_column: 14,
+            content: "void mix() {}".to_string(),
+            fully_qualified_name: "mix".to_string(),
+            namespace_path: Vec::new(),
+            dependencies: Vec::new(),
+            template_parameters: Vec::new(),
+            base_classes: Vec::new(),
+            member_functions: Vec::new(),
+            member_variables: Vec::new(),
+            signature: None,
+            documentation: None,
+            is_definition: true,
+            is_declaration: false,
+            memory_section: None,
+            usr: None,
+        };
+        let extraction = ExtractionResult {
+            file_path: path.clone(),
+            symbols: vec![symbol],
+            includes: Vec::new(),
+            extraction_time_ms: 0,
+            tree_sitter_symbols: 1,
+            clang_symbols: 0,
+            calls: Vec::new(),
+        };
+        store.store_file(&path, &content_hash(b"void mix() {}"), &extraction).unwrap();
+        {
+            let repository = store.repository.lock().unwrap();
+            let metadata = repository.get_file_metadata_by_path(&index.id, "mixer.cpp").unwrap().unwrap();
+            assert_eq!(metadata.file_hash, content_hash(b"void mix() {}"));
+            assert_eq!(metadata.symbol_count, 1);
+            assert_eq!(repository.get_code_index(&index.id).unwrap().unwrap().total_files, 1);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        store.remove_file(&path).unwrap();
+        let repository = store.repository.lock().unwrap();
+        assert!(repository.get_file_metadata_by_path(&index.id, "mixer.cpp").unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_update_directory_records_failed_files() {
+        use crate::lib::cpp_indexer::parse_worker::{fake_worker, SubprocessWorker, WorkerSetup};
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        for name in ["crash", "mixer", "player"] {
+            std::fs::write(dir.path().join(format!("src/{}.cpp", name)), "int gain;\n").unwrap();
+        }
+        let script = "while read path; do\n  case \"$path\" in\n    *crash*) kill -SEGV $$ ;;\n    *) echo '{\"Ok\":{\"symbols\":[],\"includes\":[],\"calls\":[]}}' ;;\n  esac\ndone\n";
+        let worker = SubprocessWorker::new(fake_worker(dir.path(), script), WorkerSetup::default());
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("crashy".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        let settings = IndexSettings::load(&repository, &index).unwrap();
+        let repository = Arc::new(Mutex::new(repository));
+        let mut indexer = IncrementalIndexer::new(None)
+            .unwrap()
+            .with_repository(repository.clone(), index.clone(), settings)
+            .unwrap()
+            .with_parse_worker(worker);
+
+        // The crash only fails its own file
+        let results = indexer.update_directory(dir.path()).await.unwrap();
+        let actions: Vec<bool> = results.iter().map(|result| matches!(result.action, IndexAction::Failed(_))).collect();
+        assert_eq!(actions, [true, false, false]);
+        let repository = repository.lock().unwrap();
+        let errors = repository.list_index_errors(&index.id, None).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].file_path.as_str(), errors[0].kind), ("src/crash.cpp", IndexErrorKind::Crash));
+        assert!(repository.get_file_metadata_by_path(&index.id, "src/player.cpp").unwrap().is_some());
+    }
+}
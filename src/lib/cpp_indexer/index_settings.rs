@@ -0,0 +1,256 @@
+// Index parse settings
+//
+// What `index create` decided about an index is spread over its walk
+// rules, project file, preset tag, default build configuration and parse
+// settings. Updates load it all here, so a file parsed again is listed and
+// parsed the way it was when the index was built.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::ProjectConfig;
+use crate::lib::cpp_indexer::clang_parser::{ClangParser, HeaderCache};
+use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+use crate::lib::cpp_indexer::detail_tiers::DetailPolicy;
+use crate::lib::cpp_indexer::dialect::DialectRules;
+use crate::lib::cpp_indexer::parse_worker::{SubprocessWorker, WorkerSetup};
+use crate::lib::cpp_indexer::pipeline::{FileExtractor, ParserWorker};
+use crate::lib::cpp_indexer::presets::{self, IndexPreset, PRESET_TAG};
+use crate::lib::cpp_indexer::symbol_extractor::SymbolExtractor;
+use crate::lib::cpp_indexer::symbol_filter::SymbolFilter;
+use crate::lib::cpp_indexer::walk_filter::WalkFilter;
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::build_configuration::DEFAULT_CONFIGURATION_NAME;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::repository::Repository;
+
+/// Which files under the base path of an index are indexed
+///
+/// A file is selected if the walk rules, the project file and the preset
+/// all select it. The default selects every source file.
+#[derive(Debug, Clone, Default)]
+pub struct FileSelection {
+    walk_filter: WalkFilter,
+    project: ProjectConfig,
+    preset: Option<IndexPreset>,
+}
+
+impl FileSelection {
+    pub fn new(walk_filter: WalkFilter, project: ProjectConfig, preset: Option<IndexPreset>) -> Self {
+        Self { walk_filter, project, preset }
+    }
+
+    /// Walks with `walk_filter` instead of the index's saved walk rules
+    pub fn with_walk_filter(mut self, walk_filter: WalkFilter) -> Self {
+        self.walk_filter = walk_filter;
+        self
+    }
+
+    /// Returns true if the file at `path`, relative to the base path, is indexed
+    ///
+    /// Ignore files aren't consulted; they are only read while walking.
+    pub fn selects(&self, path: &str) -> bool {
+        self.walk_filter.selects(path) && self.project.selects(path) && self.preset.as_ref().map_or(true, |preset| preset.selects(path))
+    }
+
+    /// Source files under `root` the selection includes, relative to `root` and sorted
+    pub fn source_files(&self, root: &Path) -> io::Result<Vec<String>> {
+        let files = self.walk_filter.source_files(root)?;
+        Ok(files.into_iter().filter(|file| self.selects(file)).collect())
+    }
+
+    /// Source files below `directory` the selection includes, relative to `root` and sorted
+    pub fn source_files_under(&self, root: &Path, directory: &str) -> io::Result<Vec<String>> {
+        let files = self.walk_filter.source_files_under(root, directory)?;
+        Ok(files.into_iter().filter(|file| self.selects(file)).collect())
+    }
+
+    /// How much detail the preset keeps, or the default without one
+    pub fn detail_policy(&self) -> DetailPolicy {
+        self.preset.as_ref().map(|preset| preset.detail_policy).unwrap_or_default()
+    }
+}
+
+/// How the files of an index are selected and parsed
+#[derive(Debug, Clone)]
+pub struct IndexSettings {
+    pub selection: FileSelection,
+    /// Flags every file is parsed with; None for the parser's defaults
+    pub compile_flags: Option<Vec<String>>,
+    /// Where the compilation database was loaded from
+    pub compile_commands: Option<PathBuf>,
+    pub database: Option<CompilationDatabase>,
+    pub symbol_filter: SymbolFilter,
+    pub dialects: DialectRules,
+}
+
+impl IndexSettings {
+    /// Settings of a new index at `base_path`
+    ///
+    /// `macro_flags` are the `-D` and `-U` flags of its default build configuration.
+    pub fn new(
+        base_path: &Path,
+        project: ProjectConfig,
+        preset: Option<IndexPreset>,
+        walk_filter: WalkFilter,
+        macro_flags: Vec<String>,
+        compile_commands: Option<PathBuf>,
+    ) -> Result<Self> {
+        let database = match &compile_commands {
+            Some(path) => Some(CompilationDatabase::load(path).map_err(|e| {
+                StorageError::Validation(format!("Invalid compilation database {}: {}", path.display(), e))
+            })?),
+            None => None,
+        };
+        let project_flags = project.compile_flags(base_path);
+        let compile_flags = if macro_flags.is_empty() && project_flags.is_empty() {
+            None
+        } else {
+            Some(ClangParser::default_flags().into_iter().chain(project_flags).chain(macro_flags).collect())
+        };
+        let symbol_filter = match &preset {
+            Some(preset) => preset.merge_symbol_filter(project.symbols.clone()),
+            None => project.symbols.clone(),
+        };
+        Ok(Self {
+            dialects: project.dialect_rules(base_path),
+            selection: FileSelection::new(walk_filter, project, preset),
+            compile_flags,
+            compile_commands,
+            database,
+            symbol_filter,
+        })
+    }
+
+    /// Settings an index was created with, read from storage and its project file
+    ///
+    /// Indices without stored walk rules walk every source file; without
+    /// stored parse settings they use the project file's compilation
+    /// database, if it names one.
+    pub fn load(repository: &Repository, index: &CodeIndex) -> Result<Self> {
+        let base_path = Path::new(&index.base_path);
+        let project = ProjectConfig::load(base_path)
+            .map_err(|e| StorageError::Validation(e.to_string()))?
+            .unwrap_or_default();
+        let preset = repository.get_index_tags(&index.id)?.get(PRESET_TAG).and_then(|preset| presets::preset(preset));
+        let walk_filter = repository.get_walk_rules(&index.id)?.map(|rules| WalkFilter::from_rules(&rules)).unwrap_or_default();
+        let macro_flags = repository
+            .get_build_configuration(&index.id, DEFAULT_CONFIGURATION_NAME)?
+            .map(|configuration| configuration.compile_flags())
+            .unwrap_or_default();
+        let settings = repository.get_parse_settings(&index.id)?;
+        let (compile_commands, include_flags) = match settings {
+            Some(settings) => (settings.compile_commands.map(PathBuf::from), settings.include_flags),
+            None => (project.compile_commands(base_path), Vec::new()),
+        };
+        Ok(Self::new(base_path, project, preset, walk_filter, macro_flags, compile_commands)?.with_include_flags(include_flags))
+    }
+
+    /// Adds inferred `-I` flags after the other flags
+    pub fn with_include_flags(mut self, include_flags: Vec<String>) -> Self {
+        if !include_flags.is_empty() {
+            let flags = self.compile_flags.take().unwrap_or_else(ClangParser::default_flags);
+            self.compile_flags = Some(flags.into_iter().chain(include_flags).collect());
+        }
+        self
+    }
+
+    /// Returns true if the file at `path`, relative to the base path, is indexed
+    pub fn selects(&self, path: &str) -> bool {
+        self.selection.selects(path)
+    }
+
+    /// Source files under `root` the index selects, relative to `root` and sorted
+    pub fn source_files(&self, root: &Path) -> io::Result<Vec<String>> {
+        self.selection.source_files(root)
+    }
+
+    /// A pipeline worker parsing with these settings
+    ///
+    /// With `program`, files are parsed in `parse-worker` processes of it,
+    /// so a file that crashes or hangs libclang only fails itself. Without,
+    /// they are parsed on threads of this process, sharing `headers` with
+    /// the other workers.
+    pub fn worker(&self, headers: &HeaderCache, program: Option<&Path>) -> std::result::Result<Box<dyn FileExtractor>, String> {
+        if let Some(program) = program {
+            return Ok(Box::new(self.subprocess_worker(program)));
+        }
+        let worker = ParserWorker::new(self.compile_flags.clone(), self.database.clone())?;
+        Ok(Box::new(
+            worker
+                .with_symbol_filter(self.symbol_filter.clone())
+                .with_dialect_rules(self.dialects.clone())
+                .with_header_cache(headers.clone()),
+        ))
+    }
+
+    /// A worker parsing with these settings in `parse-worker` processes of `program`
+    pub fn subprocess_worker(&self, program: &Path) -> SubprocessWorker {
+        let setup = WorkerSetup {
+            compile_flags: self.compile_flags.clone(),
+            compile_commands: self.compile_commands.clone(),
+            dialects: self.dialects.clone(),
+        };
+        SubprocessWorker::new(program.to_path_buf(), setup).with_symbol_filter(self.symbol_filter.clone())
+    }
+
+    /// An extractor parsing with these settings; its symbols still need `symbol_filter`
+    pub fn extractor(&self) -> std::result::Result<SymbolExtractor, Box<dyn std::error::Error>> {
+        let mut extractor = SymbolExtractor::new(self.compile_flags.clone())?.with_dialect_rules(self.dialects.clone());
+        if let Some(database) = &self.database {
+            extractor = extractor.with_compilation_database(database.clone());
+        }
+        Ok(extractor)
+    }
+
+    /// Identifies the settings, so builds only share header parses when they parse alike
+    pub fn fingerprint(&self) -> String {
+        format!("{:?}|{:?}|{:?}|{:?}", self.compile_flags, self.compile_commands, self.symbol_filter, self.dialects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::build_configuration::BuildConfiguration;
+    use crate::lib::storage::models::index_tag::IndexTag;
+    use crate::lib::storage::models::parse_settings::ParseSettings;
+    use crate::lib::storage::models::walk_rules::WalkRules;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_restores_create_settings() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".cppindex.toml"), "exclude = [\"gen/\"]\ncompile_flags = [\"-DPROJECT\"]\n\n[symbols]\nexclude_scopes = [\"*::detail\"]\n").unwrap();
+        for file in ["src/a.cpp", "gen/b.cpp", "third_party/c.cpp", "Source/d.cpp"] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "int x;\n").unwrap();
+        }
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("settings".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        let fresh = IndexSettings::load(&repository, &index).unwrap();
+        assert_eq!(fresh.compile_flags, Some(vec!["-std=c++17".to_string(), "-DPROJECT".to_string()]));
+        assert_eq!(fresh.source_files(dir.path()).unwrap(), ["Source/d.cpp", "src/a.cpp", "third_party/c.cpp"]);
+
+        repository.save_walk_rules(&WalkRules::new(index.id, Vec::new(), vec!["third_party/**".to_string()], true)).unwrap();
+        repository.set_index_tag(&IndexTag::new(index.id, PRESET_TAG.to_string(), "unreal".to_string())).unwrap();
+        repository
+            .save_build_configuration(BuildConfiguration::new(index.id, DEFAULT_CONFIGURATION_NAME.to_string()).with_define("FAST", "1"))
+            .unwrap();
+        repository.save_parse_settings(&ParseSettings::new(index.id, None, vec!["-I/usr/include/extra".to_string()])).unwrap();
+
+        let settings = IndexSettings::load(&repository, &index).unwrap();
+        let flags = settings.compile_flags.clone().unwrap();
+        assert!(flags.contains(&"-DFAST=1".to_string()) && flags.ends_with(&["-I/usr/include/extra".to_string()]));
+        // Walk rules, the project file and the preset all have a say
+        assert_eq!(settings.source_files(dir.path()).unwrap(), ["Source/d.cpp"]);
+        assert!(!settings.selects("gen/b.cpp") && !settings.selects("third_party/c.cpp"));
+        assert_eq!(settings.symbol_filter.exclude_scopes, ["*::detail"]);
+    }
+}
@@ -0,0 +1,82 @@
+// Source languages of indexed files
+//
+// Mixed codebases put C, C++, Objective-C and Objective-C++ side by side.
+// The language follows from the file extension and decides which tree-sitter
+// grammar parses a file and how libclang is told to read it.
+
+use std::path::Path;
+
+/// Language a source file is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceLanguage {
+    C,
+    Cpp,
+    ObjectiveC,
+    ObjectiveCpp,
+}
+
+impl SourceLanguage {
+    /// Language of a file by its extension
+    ///
+    /// Headers are read as C++, which accepts nearly all C headers.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("c") => SourceLanguage::C,
+            Some("m") => SourceLanguage::ObjectiveC,
+            Some("mm") => SourceLanguage::ObjectiveCpp,
+            _ => SourceLanguage::Cpp,
+        }
+    }
+
+    /// Name libclang's `-x` option takes
+    pub fn clang_name(&self) -> &'static str {
+        match self {
+            SourceLanguage::C => "c",
+            SourceLanguage::Cpp => "c++",
+            SourceLanguage::ObjectiveC => "objective-c",
+            SourceLanguage::ObjectiveCpp => "objective-c++",
+        }
+    }
+
+    /// Returns true for languages built on C++
+    pub fn is_cpp(&self) -> bool {
+        matches!(self, SourceLanguage::Cpp | SourceLanguage::ObjectiveCpp)
+    }
+
+    /// Adapts flags written for C++ to this language
+    ///
+    /// C and Objective-C files get `-x <language>` and lose any C++ `-std=`
+    /// option, which clang rejects for them; Objective-C++ files get `-x
+    /// objective-c++`. C++ flags are returned unchanged.
+    pub fn adapt_flags(&self, flags: &[String]) -> Vec<String> {
+        if *self == SourceLanguage::Cpp {
+            return flags.to_vec();
+        }
+        let mut adapted = vec!["-x".to_string(), self.clang_name().to_string()];
+        adapted.extend(
+            flags
+                .iter()
+                .filter(|flag| self.is_cpp() || !(flag.starts_with("-std=c++") || flag.starts_with("-std=gnu++")))
+                .cloned(),
+        );
+        adapted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_flags() {
+        assert_eq!(SourceLanguage::from_path(Path::new("src/codec.c")), SourceLanguage::C);
+        assert_eq!(SourceLanguage::from_path(Path::new("ios/View.m")), SourceLanguage::ObjectiveC);
+        assert_eq!(SourceLanguage::from_path(Path::new("ios/Bridge.mm")), SourceLanguage::ObjectiveCpp);
+        assert_eq!(SourceLanguage::from_path(Path::new("include/codec.h")), SourceLanguage::Cpp);
+
+        let flags = vec!["-std=c++17".to_string(), "-DNDEBUG".to_string()];
+        assert_eq!(SourceLanguage::Cpp.adapt_flags(&flags), flags);
+        assert_eq!(SourceLanguage::C.adapt_flags(&flags), ["-x", "c", "-DNDEBUG"]);
+        assert_eq!(SourceLanguage::ObjectiveCpp.adapt_flags(&flags), ["-x", "objective-c++", "-std=c++17", "-DNDEBUG"]);
+    }
+}
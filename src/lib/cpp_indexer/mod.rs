@@ -3,12 +3,39 @@
 // This module provides C++ code parsing and symbol extraction capabilities
 // using Tree-sitter for syntax parsing and LibClang for semantic analysis.
 
+pub mod language;
+pub mod dialect;
 pub mod tree_sitter_parser;
 pub mod clang_parser;
 pub mod symbol_extractor;
+pub mod symbol_filter;
 pub mod incremental;
+pub mod spill;
+pub mod attributes;
+pub mod callbacks;
+pub mod calls;
+pub mod conditionals;
+pub mod detail_tiers;
+pub mod adaptive_depth;
+pub mod hot_path;
+pub mod vendored;
+pub mod vfs;
+pub mod walk_filter;
+pub mod index_settings;
+pub mod git;
+pub mod changelog;
+pub mod compile_commands;
+pub mod build_capture;
+pub mod include_roots;
+pub mod watcher;
+pub mod pipeline;
+pub mod parse_worker;
+pub mod multi_build;
+pub mod presets;
+pub mod clangd_index;
 
 pub use tree_sitter_parser::{TreeSitterParser, ParseResult, ParsedNode};
 pub use clang_parser::{ClangParser, SemanticParseResult, SemanticInfo, SourceLocation};
 pub use symbol_extractor::{SymbolExtractor, ExtractionResult, ExtractedSymbol};
+pub use compile_commands::CompilationDatabase;
 pub use incremental::{IncrementalIndexer, IncrementalResult, IndexStatus, IndexAction};
\ No newline at end of file
@@ -0,0 +1,423 @@
+// Several index builds at once
+//
+// A manifest lists the indices to create; they are built concurrently, each
+// on its own indexing pipeline, while a shared budget caps how many files
+// are parsed at any moment across all of them. Headers seen by more than
+// one build with the same parse settings are parsed once, as long as their
+// extraction is still among the most recently used ones the cache keeps.
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+use crate::lib::cpp_indexer::pipeline::{ExtractError, FileExtraction, FileExtractor};
+
+/// Header extractions kept for reuse across builds; past it the least recently used are dropped
+pub const DEFAULT_HEADER_CACHE_ENTRIES: usize = 4096;
+
+/// The indices `index create-all` builds
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildManifest {
+    /// Files parsed at once across all builds (default: number of CPUs)
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    #[serde(rename = "index", default)]
+    pub indices: Vec<IndexSpec>,
+}
+
+/// One index of a manifest, with the settings of `index create`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexSpec {
+    pub name: String,
+    /// Codebase root; relative paths are taken from the manifest's directory
+    pub path: String,
+    #[serde(default)]
+    pub compile_commands: Option<String>,
+    #[serde(default)]
+    pub define: Vec<String>,
+    #[serde(default)]
+    pub undefine: Vec<String>,
+    #[serde(default)]
+    pub store_bodies: bool,
+    #[serde(default)]
+    pub infer_includes: bool,
+    #[serde(default)]
+    pub preset: Option<String>,
+}
+
+/// State shared by the builds of one manifest
+///
+/// Each build holds a slot, the position of its index in the manifest,
+/// through which its progress is reported.
+pub struct MultiBuild {
+    budget: WorkerBudget,
+    headers: HeaderParseCache,
+    builds: Vec<BuildProgress>,
+}
+
+/// Progress of one build
+pub struct BuildProgress {
+    pub name: String,
+    total: AtomicUsize,
+    parsed: AtomicUsize,
+    state: Mutex<BuildState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+/// Counting semaphore limiting parses across builds
+struct WorkerBudget {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A permit of the worker budget, returned on drop
+struct BudgetPermit<'a> {
+    budget: &'a WorkerBudget,
+}
+
+/// Header extractions keyed by file, content and parse settings
+///
+/// Two builds missing the same header at the same moment both parse it;
+/// the cache only saves the parses after the first one is stored. At most
+/// `capacity` extractions are kept, evicting the least recently used.
+struct HeaderParseCache {
+    entries: Mutex<HeaderEntries>,
+    capacity: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// Cached extractions with the tick of their last use
+#[derive(Default)]
+struct HeaderEntries {
+    extractions: HashMap<(PathBuf, String, String), (FileExtraction, u64)>,
+    clock: u64,
+}
+
+/// A build's extractor, parsing within the worker budget and through the header cache
+pub struct SharedExtractor<'a, E> {
+    inner: E,
+    build: &'a MultiBuild,
+    slot: usize,
+    fingerprint: String,
+}
+
+impl BuildManifest {
+    /// Reads a manifest, resolving relative index paths against its directory
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut manifest = Self::from_toml(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        for index in &mut manifest.indices {
+            for relative in std::iter::once(&mut index.path).chain(index.compile_commands.as_mut()) {
+                if Path::new(relative.as_str()).is_relative() {
+                    *relative = directory.join(&*relative).to_string_lossy().into_owned();
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Parses a manifest, checking that it names each index once
+    pub fn from_toml(content: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let manifest: Self = toml::from_str(content)?;
+        if manifest.indices.is_empty() {
+            return Err("Manifest lists no [[index]]".into());
+        }
+        let mut names = std::collections::HashSet::new();
+        for index in &manifest.indices {
+            if index.name.trim().is_empty() {
+                return Err(format!("Index at {} has no name", index.path).into());
+            }
+            if !names.insert(index.name.as_str()) {
+                return Err(format!("Index '{}' is listed twice", index.name).into());
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+impl MultiBuild {
+    /// Shares `workers` parses between the builds of `names`
+    pub fn new(workers: usize, names: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            budget: WorkerBudget { available: Mutex::new(workers.max(1)), released: Condvar::new() },
+            headers: HeaderParseCache::new(DEFAULT_HEADER_CACHE_ENTRIES),
+            builds: names
+                .into_iter()
+                .map(|name| BuildProgress {
+                    name,
+                    total: AtomicUsize::new(0),
+                    parsed: AtomicUsize::new(0),
+                    state: Mutex::new(BuildState::Pending),
+                })
+                .collect(),
+        }
+    }
+
+    /// Keeps at most `entries` header extractions for reuse (at least one)
+    pub fn with_header_cache_entries(mut self, entries: usize) -> Self {
+        self.headers = HeaderParseCache::new(entries);
+        self
+    }
+
+    pub fn builds(&self) -> &[BuildProgress] {
+        &self.builds
+    }
+
+    /// Marks a build as running with `total_files` to parse
+    pub fn start(&self, slot: usize, total_files: usize) {
+        self.builds[slot].total.store(total_files, Ordering::Relaxed);
+        self.builds[slot].set_state(BuildState::Running);
+    }
+
+    pub fn finish(&self, slot: usize, succeeded: bool) {
+        self.builds[slot].set_state(if succeeded { BuildState::Done } else { BuildState::Failed });
+    }
+
+    /// True once no build is pending or running
+    pub fn is_finished(&self) -> bool {
+        self.builds.iter().all(|build| matches!(build.state(), BuildState::Done | BuildState::Failed))
+    }
+
+    /// Wraps a build's extractor
+    ///
+    /// `fingerprint` identifies everything besides a header's content that
+    /// changes what parsing it yields: flags, filters and dialect rules.
+    /// Builds share a header's extraction only if their fingerprints match.
+    pub fn extractor<E: FileExtractor>(&self, slot: usize, fingerprint: &str, inner: E) -> SharedExtractor<'_, E> {
+        SharedExtractor { inner, build: self, slot, fingerprint: fingerprint.to_string() }
+    }
+
+    /// Header parses reused from another build, and header parses done
+    pub fn header_stats(&self) -> (usize, usize) {
+        (self.headers.hits.load(Ordering::Relaxed), self.headers.misses.load(Ordering::Relaxed))
+    }
+
+    /// One line with the progress of every build
+    pub fn status_line(&self) -> String {
+        let builds: Vec<String> = self.builds.iter().map(|build| build.to_string()).collect();
+        builds.join(" | ")
+    }
+}
+
+impl BuildProgress {
+    pub fn parsed(&self) -> usize {
+        self.parsed.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn state(&self) -> BuildState {
+        *self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn set_state(&self, state: BuildState) {
+        *self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = state;
+    }
+}
+
+impl std::fmt::Display for BuildProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.state() {
+            BuildState::Pending => write!(f, "{} pending", self.name),
+            BuildState::Running => write!(f, "{} {}/{}", self.name, self.parsed(), self.total()),
+            BuildState::Done => write!(f, "{} done", self.name),
+            BuildState::Failed => write!(f, "{} failed", self.name),
+        }
+    }
+}
+
+impl WorkerBudget {
+    fn acquire(&self) -> BudgetPermit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        *available -= 1;
+        BudgetPermit { budget: self }
+    }
+}
+
+impl Drop for BudgetPermit<'_> {
+    fn drop(&mut self) {
+        *self.budget.available.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) += 1;
+        self.budget.released.notify_one();
+    }
+}
+
+impl HeaderParseCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HeaderEntries::default()),
+            capacity: capacity.max(1),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    fn get_or_parse(
+        &self,
+        path: &Path,
+        fingerprint: &str,
+        parse: impl FnOnce() -> Result<FileExtraction, ExtractError>,
+    ) -> Result<FileExtraction, ExtractError> {
+        // Hashing the content keeps a header edited between two builds from being reused
+        let content = std::fs::read(path).map_err(|e| e.to_string())?;
+        let key = (path.to_path_buf(), format!("{:x}", Sha256::digest(&content)), fingerprint.to_string());
+        {
+            let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            entries.clock += 1;
+            let now = entries.clock;
+            if let Some((extraction, used)) = entries.extractions.get_mut(&key) {
+                *used = now;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(extraction.clone());
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let extraction = parse()?;
+
+        let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if entries.extractions.len() >= self.capacity && !entries.extractions.contains_key(&key) {
+            let oldest = entries.extractions.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.extractions.remove(&oldest);
+            }
+        }
+        entries.clock += 1;
+        let now = entries.clock;
+        entries.extractions.insert(key, (extraction.clone(), now));
+        Ok(extraction)
+    }
+}
+
+impl<E: FileExtractor> FileExtractor for SharedExtractor<'_, E> {
+    fn extract(&mut self, path: &Path) -> Result<FileExtraction, ExtractError> {
+        let result = {
+            let _permit = self.build.budget.acquire();
+            if is_header(path) {
+                let inner = &mut self.inner;
+                self.build.headers.get_or_parse(path, &self.fingerprint, || inner.extract(path))
+            } else {
+                self.inner.extract(path)
+            }
+        };
+        self.build.builds[self.slot].parsed.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+}
+
+fn is_header(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("h") | Some("hpp") | Some("hxx") | Some("h++") | Some("H")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingExtractor {
+        parses: usize,
+    }
+
+    impl FileExtractor for CountingExtractor {
+        fn extract(&mut self, path: &Path) -> Result<FileExtraction, ExtractError> {
+            self.parses += 1;
+            Ok(FileExtraction { symbols: Vec::new(), includes: vec![path.display().to_string()], calls: Vec::new() })
+        }
+    }
+
+    #[test]
+    fn test_manifest_and_shared_headers() {
+        let manifest = BuildManifest::from_toml(
+            r#"
+            jobs = 4
+
+            [[index]]
+            name = "engine"
+            path = "engine"
+            compile_commands = "engine/build/compile_commands.json"
+
+            [[index]]
+            name = "tools"
+            path = "/src/tools"
+            define = ["TOOLS=1"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.jobs, Some(4));
+        assert_eq!(manifest.indices.len(), 2);
+        assert_eq!(manifest.indices[1].define, ["TOOLS=1"]);
+        assert!(BuildManifest::from_toml("[[index]]\nname = \"a\"\npath = \"x\"\n[[index]]\nname = \"a\"\npath = \"y\"").is_err());
+        assert!(BuildManifest::from_toml("jobs = 2").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let header = dir.path().join("common.h");
+        let source = dir.path().join("main.cpp");
+        std::fs::write(&header, "int shared();\n").unwrap();
+        std::fs::write(&source, "int main() { return shared(); }\n").unwrap();
+
+        let build = MultiBuild::new(2, ["engine".to_string(), "tools".to_string()]);
+        build.start(0, 2);
+        build.start(1, 2);
+        let mut engine = build.extractor(0, "flags", CountingExtractor { parses: 0 });
+        let mut tools = build.extractor(1, "flags", CountingExtractor { parses: 0 });
+        for extractor in [&mut engine, &mut tools] {
+            extractor.extract(&header).unwrap();
+            extractor.extract(&source).unwrap();
+        }
+        // The second build reuses the header, but parses its own source file
+        assert_eq!((engine.inner.parses, tools.inner.parses), (2, 1));
+        assert_eq!(build.header_stats(), (1, 1));
+
+        // Different settings never share
+        let mut other = build.extractor(1, "other flags", CountingExtractor { parses: 0 });
+        other.extract(&header).unwrap();
+        assert_eq!(other.inner.parses, 1);
+
+        assert_eq!(build.status_line(), "engine 2/2 | tools 3/2");
+        build.finish(0, true);
+        assert!(!build.is_finished());
+        build.finish(1, false);
+        assert!(build.is_finished());
+        assert_eq!(build.status_line(), "engine done | tools failed");
+    }
+
+    #[test]
+    fn test_header_cache_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let headers: Vec<PathBuf> = ["a.h", "b.h", "c.h"].iter().map(|name| dir.path().join(name)).collect();
+        for header in &headers {
+            std::fs::write(header, "int shared();\n").unwrap();
+        }
+
+        let build = MultiBuild::new(1, ["engine".to_string()]).with_header_cache_entries(2);
+        let mut extractor = build.extractor(0, "flags", CountingExtractor { parses: 0 });
+        for header in [&headers[0], &headers[1], &headers[0], &headers[2]] {
+            extractor.extract(header).unwrap();
+        }
+        // c.h evicted b.h, used longer ago than a.h
+        extractor.extract(&headers[0]).unwrap();
+        assert_eq!(extractor.inner.parses, 3);
+        extractor.extract(&headers[1]).unwrap();
+        assert_eq!(extractor.inner.parses, 4);
+        assert_eq!(build.headers.entries.lock().unwrap().extractions.len(), 2);
+    }
+}
@@ -0,0 +1,248 @@
+// Parsing in a process of its own
+//
+// libclang is native code. A file that crashes it takes the process down,
+// and a parse stuck inside it can't be interrupted, only abandoned on a
+// thread that keeps its turn with libclang. A subprocess worker hands files
+// to a `parse-worker` child of the running binary instead: a child that
+// crashes only loses the file it was parsing, and one that runs past the
+// timeout is killed. Either way the next file starts a new child.
+//
+// The child reads its settings from the first line of stdin, then one JSON
+// path per line, and answers each path with one JSON line holding the
+// file's extraction or why it failed.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::lib::cpp_indexer::clang_parser::HeaderCache;
+use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+use crate::lib::cpp_indexer::dialect::DialectRules;
+use crate::lib::cpp_indexer::pipeline::{ExtractError, FileExtraction, FileExtractor, ParserWorker, DEFAULT_PARSE_TIMEOUT};
+use crate::lib::cpp_indexer::symbol_filter::SymbolFilter;
+use crate::lib::storage::models::index_error::IndexErrorKind;
+
+/// Subcommand of the binary that runs `serve`
+pub const PARSE_WORKER_COMMAND: &str = "parse-worker";
+
+/// How a worker process parses, sent as the first line of its stdin
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkerSetup {
+    /// Flags every file is parsed with; None for the parser's defaults
+    pub compile_flags: Option<Vec<String>>,
+    /// compile_commands.json the process loads per-file flags from
+    pub compile_commands: Option<PathBuf>,
+    pub dialects: DialectRules,
+}
+
+/// A file extractor parsing in `parse-worker` processes of `program`
+///
+/// One process parses file after file; a new one is started for the first
+/// file and after a process crashed or was killed for taking too long.
+pub struct SubprocessWorker {
+    program: PathBuf,
+    setup: WorkerSetup,
+    filter: SymbolFilter,
+    timeout: Duration,
+    /// None before the first file, and after a process was lost
+    process: Option<WorkerProcess>,
+}
+
+/// A running worker process; dropping it kills the process
+struct WorkerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines the process writes, read on a thread so waiting for one can time out
+    replies: Receiver<io::Result<String>>,
+}
+
+impl SubprocessWorker {
+    pub fn new(program: PathBuf, setup: WorkerSetup) -> Self {
+        Self {
+            program,
+            setup,
+            filter: SymbolFilter::default(),
+            timeout: DEFAULT_PARSE_TIMEOUT,
+            process: None,
+        }
+    }
+
+    /// Drops the symbols `filter` excludes
+    pub fn with_symbol_filter(mut self, filter: SymbolFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Kills the process parsing a file for longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn start(&self) -> io::Result<WorkerProcess> {
+        let mut child = Command::new(&self.program)
+            .arg(PARSE_WORKER_COMMAND)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(io::Error::other("Parse worker has no stdin or stdout"));
+        };
+        let (sender, replies) = channel();
+        let mut process = WorkerProcess { child, stdin, replies };
+        thread::Builder::new().name("parse-worker".to_string()).spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })?;
+        writeln!(process.stdin, "{}", serde_json::to_string(&self.setup)?)?;
+        Ok(process)
+    }
+}
+
+impl FileExtractor for SubprocessWorker {
+    fn extract(&mut self, path: &Path) -> Result<FileExtraction, ExtractError> {
+        let request = serde_json::to_string(path).map_err(|e| ExtractError::new(IndexErrorKind::Read, format!("Unusable path: {}", e)))?;
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => self
+                .start()
+                .map_err(|e| ExtractError::new(IndexErrorKind::Crash, format!("Failed to start a parse worker: {}", e)))?,
+        };
+        if writeln!(process.stdin, "{}", request).and_then(|()| process.stdin.flush()).is_err() {
+            return Err(process.lost());
+        }
+
+        let reply = match process.replies.recv_timeout(self.timeout) {
+            Ok(Ok(line)) => line,
+            // The process is killed as it's dropped
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(ExtractError::new(IndexErrorKind::Timeout, format!("Parsing took longer than {}s", self.timeout.as_secs_f64())));
+            }
+            Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => return Err(process.lost()),
+        };
+        let extraction: Result<FileExtraction, ExtractError> = serde_json::from_str(&reply)
+            .map_err(|e| ExtractError::new(IndexErrorKind::Crash, format!("Unreadable reply from the parse worker: {}", e)))?;
+        self.process = Some(process);
+        let extraction = extraction?;
+        Ok(FileExtraction {
+            symbols: extraction.symbols.into_iter().filter(|symbol| self.filter.keeps(symbol)).collect(),
+            ..extraction
+        })
+    }
+}
+
+impl WorkerProcess {
+    /// Why the process stopped answering, once it has exited
+    fn lost(mut self) -> ExtractError {
+        let message = match self.child.wait() {
+            Ok(status) => format!("Parse worker exited ({})", status),
+            Err(e) => format!("Parse worker was lost: {}", e),
+        };
+        ExtractError::new(IndexErrorKind::Crash, message)
+    }
+}
+
+impl Drop for WorkerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Answers the paths read from `input` on `output` until `input` ends; the `parse-worker` subcommand
+///
+/// Parses have no timeout of their own: the parent kills a process that
+/// takes too long.
+pub fn serve(input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut lines = input.lines();
+    let Some(setup) = lines.next() else { return Ok(()) };
+    let setup: WorkerSetup = serde_json::from_str(&setup?)?;
+    let database = match &setup.compile_commands {
+        Some(path) => Some(CompilationDatabase::load(path).map_err(|e| io::Error::other(e.to_string()))?),
+        None => None,
+    };
+    let mut worker = ParserWorker::new(setup.compile_flags, database)
+        .map_err(io::Error::other)?
+        .with_dialect_rules(setup.dialects)
+        .with_header_cache(HeaderCache::new())
+        .with_timeout(Duration::MAX);
+
+    for line in lines {
+        let path: PathBuf = serde_json::from_str(&line?)?;
+        let reply = worker.extract(&path);
+        writeln!(output, "{}", serde_json::to_string(&reply)?)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// A script in `dir` standing in for the binary, answering each path read like `parse-worker` would
+#[cfg(all(test, unix))]
+pub(crate) fn fake_worker(dir: &Path, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.join("worker.sh");
+    std::fs::write(&path, format!("#!/bin/sh\nread setup\n{}", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cpp_indexer::dialect::DialectRule;
+
+    #[test]
+    fn test_setup_round_trips() {
+        let setup = WorkerSetup {
+            compile_flags: Some(vec!["-std=c++20".to_string()]),
+            compile_commands: Some(PathBuf::from("build/compile_commands.json")),
+            dialects: DialectRules::new(
+                "/src",
+                vec![DialectRule { pattern: "*.cu".to_string(), language: Some("cuda".to_string()), ..DialectRule::default() }],
+            ),
+        };
+        let line = serde_json::to_string(&setup).unwrap();
+        assert_eq!(serde_json::from_str::<WorkerSetup>(&line).unwrap(), setup);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subprocess_worker_survives_crashes_and_hangs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let parsed = r#"{"Ok":{"symbols":[],"includes":["mixer.h"],"calls":[]}}"#;
+        let script = format!(
+            "while read path; do\n  case \"$path\" in\n    *crash*) kill -SEGV $$ ;;\n    *hang*) sleep 30 ;;\n    *) echo '{}' ;;\n  esac\ndone\n",
+            parsed
+        );
+        let mut worker = SubprocessWorker::new(fake_worker(dir.path(), &script), WorkerSetup::default()).with_timeout(Duration::from_millis(500));
+
+        assert_eq!(worker.extract(Path::new("mixer.cpp")).unwrap().includes, ["mixer.h"]);
+        let crashed = worker.extract(Path::new("crash.cpp")).unwrap_err();
+        assert_eq!(crashed.kind, IndexErrorKind::Crash);
+        assert!(crashed.message.contains("signal"), "{}", crashed.message);
+        // A new process takes the next file
+        assert_eq!(worker.extract(Path::new("player.cpp")).unwrap().includes, ["mixer.h"]);
+        assert_eq!(worker.extract(Path::new("hang.cpp")).unwrap_err().kind, IndexErrorKind::Timeout);
+        assert_eq!(worker.extract(Path::new("format.cpp")).unwrap().includes, ["mixer.h"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subprocess_worker_passes_parse_errors_on() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = "while read path; do echo '{\"Err\":{\"kind\":\"parse\",\"message\":\"expected ;\"}}'; done\n";
+        let mut worker = SubprocessWorker::new(fake_worker(dir.path(), script), WorkerSetup::default());
+        let error = worker.extract(Path::new("mixer.cpp")).unwrap_err();
+        assert_eq!(error, ExtractError::new(IndexErrorKind::Parse, "expected ;".to_string()));
+        // Parse errors keep the process
+        assert!(worker.process.is_some());
+    }
+}
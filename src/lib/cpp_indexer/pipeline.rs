@@ -0,0 +1,842 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, sync_channel, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::lib::cpp_indexer::adaptive_depth::{depth_of_file, detail_policy};
+use crate::lib::cpp_indexer::callbacks::{find_callback_sites, store_callback_relationships, CallbackSite};
+use crate::lib::cpp_indexer::calls::store_call_relationships;
+use crate::lib::cpp_indexer::clang_parser::{CallSite, HeaderCache};
+use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+use crate::lib::cpp_indexer::conditionals::assign_configurations;
+use crate::lib::cpp_indexer::detail_tiers::{DetailPolicy, TieredElements};
+use crate::lib::cpp_indexer::dialect::DialectRules;
+use crate::lib::cpp_indexer::symbol_extractor::{ExtractedSymbol, SymbolExtractor};
+use crate::lib::cpp_indexer::symbol_filter::SymbolFilter;
+use crate::lib::storage::batch_writer::{BatchWriter, PriorityGate};
+use crate::lib::storage::disk_space::{DiskSpaceGuard, SizeEstimate, DEFAULT_SAMPLE_SIZE};
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::file_metadata::{FileDetail, FileMetadata};
+use crate::lib::storage::models::index_error::{IndexError, IndexErrorKind};
+use crate::lib::storage::repository::Repository;
+
+/// Symbols buffered by the writer before it commits a batch
+pub const DEFAULT_BATCH_SIZE: usize = 5000;
+
+/// Time a worker gives one file before abandoning its parse
+pub const DEFAULT_PARSE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Parses one file into symbols; each pipeline worker owns one
+pub trait FileExtractor {
+    fn extract(&mut self, path: &Path) -> std::result::Result<FileExtraction, ExtractError>;
+}
+
+/// Why a file yielded no symbols
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractError {
+    pub kind: IndexErrorKind,
+    pub message: String,
+}
+
+/// What a parse of one file yields
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileExtraction {
+    pub symbols: Vec<ExtractedSymbol>,
+    /// Paths the file `#include`s, as written
+    pub includes: Vec<String>,
+    /// Calls the file makes, resolved once every file is stored
+    pub calls: Vec<CallSite>,
+}
+
+/// The Tree-sitter and LibClang extractor, driven from a worker thread
+///
+/// Every file is parsed on a thread of its own. A parse that runs past the
+/// timeout is abandoned to finish in the background, and a parse that
+/// panics only fails its file; either way the worker carries on with a new
+/// extractor. An abandoned parse keeps its turn with libclang, so later
+/// parses in the process wait for it, and a crash of libclang itself takes
+/// the process down; `SubprocessWorker` parses in a process of its own to
+/// contain both.
+pub struct ParserWorker {
+    /// None while a file is being parsed, or after a parse was lost
+    parser: Option<Parser>,
+    settings: ParserSettings,
+    filter: SymbolFilter,
+    timeout: Duration,
+}
+
+/// An extractor and the runtime driving it, lent to the thread parsing a file
+struct Parser {
+    extractor: SymbolExtractor,
+    runtime: tokio::runtime::Runtime,
+}
+
+/// What a worker builds its extractor from, again after losing one
+#[derive(Default)]
+struct ParserSettings {
+    compile_flags: Option<Vec<String>>,
+    database: Option<CompilationDatabase>,
+    dialects: Option<DialectRules>,
+    header_cache: Option<HeaderCache>,
+}
+
+/// Settings for a parallel indexing run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    jobs: usize,
+    batch_size: usize,
+    detail_policy: DetailPolicy,
+    adaptive_depth: bool,
+}
+
+/// Parses files on a pool of worker threads and stores them through one writer
+///
+/// Parsers are not shared: every worker builds its own extractor. Parsed
+/// files flow to the calling thread over a bounded channel, so a slow
+/// database applies back-pressure instead of buffering the whole codebase.
+/// The calling thread is the only one touching the repository and commits
+/// a batch whenever enough symbols are buffered.
+#[derive(Debug, Clone, Default)]
+pub struct IndexingPipeline {
+    config: PipelineConfig,
+    /// Queries the batches yield to, if any
+    priority_gate: Option<PriorityGate>,
+}
+
+/// Outcome of an indexing run
+#[derive(Debug, Clone, Default)]
+pub struct PipelineReport {
+    pub files_indexed: usize,
+    /// Files that failed to read or parse, with the error
+    pub failures: Vec<(String, String)>,
+    pub symbols_stored: usize,
+    /// Calls relationships stored between symbols
+    pub calls_stored: usize,
+    /// ReferencedAsCallback relationships stored between symbols
+    pub callbacks_stored: usize,
+    /// Transactions the writer committed
+    pub batches: usize,
+    pub elapsed: Duration,
+    /// True if the run stopped before every file was parsed
+    pub cancelled: bool,
+}
+
+/// A file as a worker hands it to the writer
+struct ParsedFile {
+    metadata: FileMetadata,
+    /// Stored symbols, the file's includes and its calls
+    result: std::result::Result<(TieredElements, Vec<String>, Vec<CallSite>), ExtractError>,
+    /// Names the file uses as function values
+    callback_sites: Vec<CallbackSite>,
+}
+
+enum WorkerMessage {
+    Parsed(Box<ParsedFile>),
+    /// The worker's extractor could not be created
+    Unavailable(String),
+}
+
+impl ExtractError {
+    pub fn new(kind: IndexErrorKind, message: String) -> Self {
+        Self { kind, message }
+    }
+}
+
+impl From<String> for ExtractError {
+    fn from(message: String) -> Self {
+        Self::new(IndexErrorKind::Parse, message)
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl<E: FileExtractor + ?Sized> FileExtractor for Box<E> {
+    fn extract(&mut self, path: &Path) -> std::result::Result<FileExtraction, ExtractError> {
+        (**self).extract(path)
+    }
+}
+
+impl ParserWorker {
+    pub fn new(compile_flags: Option<Vec<String>>, database: Option<CompilationDatabase>) -> std::result::Result<Self, String> {
+        let settings = ParserSettings { compile_flags, database, ..ParserSettings::default() };
+        Ok(Self {
+            parser: Some(settings.build()?),
+            settings,
+            filter: SymbolFilter::default(),
+            timeout: DEFAULT_PARSE_TIMEOUT,
+        })
+    }
+
+    /// Drops the symbols `filter` excludes
+    pub fn with_symbol_filter(mut self, filter: SymbolFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Parses files matching a dialect rule with its options
+    pub fn with_dialect_rules(mut self, dialects: DialectRules) -> Self {
+        self.settings.dialects = Some(dialects.clone());
+        self.parser = self.parser.map(|parser| Parser { extractor: parser.extractor.with_dialect_rules(dialects), ..parser });
+        self
+    }
+
+    /// Visits each header once across the workers sharing `cache`
+    pub fn with_header_cache(mut self, cache: HeaderCache) -> Self {
+        self.settings.header_cache = Some(cache.clone());
+        self.parser = self.parser.map(|parser| Parser { extractor: parser.extractor.with_header_cache(cache), ..parser });
+        self
+    }
+
+    /// Gives up on a file whose parse takes longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl ParserSettings {
+    fn build(&self) -> std::result::Result<Parser, String> {
+        let mut extractor = SymbolExtractor::new(self.compile_flags.clone()).map_err(|e| e.to_string())?;
+        if let Some(database) = &self.database {
+            extractor = extractor.with_compilation_database(database.clone());
+        }
+        if let Some(dialects) = &self.dialects {
+            extractor = extractor.with_dialect_rules(dialects.clone());
+        }
+        if let Some(cache) = &self.header_cache {
+            extractor = extractor.with_header_cache(cache.clone());
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Parser { extractor, runtime })
+    }
+}
+
+impl FileExtractor for ParserWorker {
+    fn extract(&mut self, path: &Path) -> std::result::Result<FileExtraction, ExtractError> {
+        let mut parser = match self.parser.take() {
+            Some(parser) => parser,
+            None => self.settings.build()?,
+        };
+        let (sender, receiver) = channel();
+        let file = path.to_path_buf();
+        thread::Builder::new()
+            .name("parser".to_string())
+            .spawn(move || {
+                let parsed = panic::catch_unwind(AssertUnwindSafe(|| {
+                    parser.runtime.block_on(parser.extractor.extract_symbols(&file)).map_err(|e| e.to_string())
+                }));
+                // The receiver is gone if the parse timed out
+                let _ = sender.send(parsed.map(|result| (parser, result)).map_err(panic_message));
+            })
+            .map_err(|e| format!("Failed to start a parser thread: {}", e))?;
+
+        let extraction = match receiver.recv_timeout(self.timeout) {
+            Ok(Ok((parser, result))) => {
+                self.parser = Some(parser);
+                result?
+            }
+            Ok(Err(message)) => return Err(ExtractError::new(IndexErrorKind::Panic, format!("Parser panicked: {}", message))),
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(ExtractError::new(IndexErrorKind::Timeout, format!("Parsing took longer than {}s", self.timeout.as_secs_f64())));
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(ExtractError::new(IndexErrorKind::Panic, "Parser thread exited".to_string())),
+        };
+        Ok(FileExtraction {
+            symbols: extraction.symbols.into_iter().filter(|symbol| self.filter.keeps(symbol)).collect(),
+            includes: extraction.includes,
+            calls: extraction.calls,
+        })
+    }
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            jobs: thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            batch_size: DEFAULT_BATCH_SIZE,
+            detail_policy: DetailPolicy::default(),
+            adaptive_depth: false,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Parses at most `jobs` files at once
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Commits once `batch_size` symbols are buffered
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Stores symbols with the given detail tiers
+    pub fn with_detail_policy(mut self, policy: DetailPolicy) -> Self {
+        self.detail_policy = policy;
+        self
+    }
+
+    /// Stores files in directories with a learned depth at that depth's detail instead
+    pub fn with_adaptive_depth(mut self, adaptive_depth: bool) -> Self {
+        self.adaptive_depth = adaptive_depth;
+        self
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    pub fn detail_policy(&self) -> DetailPolicy {
+        self.detail_policy
+    }
+}
+
+impl IndexingPipeline {
+    pub fn new(config: PipelineConfig) -> Self {
+        Self { config, priority_gate: None }
+    }
+
+    /// Stores batches in short chunks that pause while queries on `gate` wait
+    pub fn with_priority_gate(mut self, gate: PriorityGate) -> Self {
+        self.priority_gate = Some(gate);
+        self
+    }
+
+    /// Indexes `files`, given relative to the index base path
+    ///
+    /// `new_extractor` runs once on each worker thread, and again after an
+    /// extractor panicked. A file that fails to read or parse, or whose parse
+    /// panics, is recorded in the error state with its error and the run
+    /// continues; the run only fails on storage errors or if no worker could
+    /// create its extractor. Once every file is stored, calls and functions
+    /// used as values are resolved into relationships, and symbols are
+    /// assigned to the index's build configurations.
+    ///
+    /// A database on disk is checked for room for the estimated size of the
+    /// files first and before each batch, and a full disk fails the run
+    /// with [`StorageError::DiskSpace`]; the batches stored so far are kept,
+    /// so an update picks up where the run stopped.
+    pub fn run<E, F>(&self, repository: &Repository, index: &CodeIndex, files: Vec<String>, new_extractor: F) -> Result<PipelineReport>
+    where
+        E: FileExtractor,
+        F: Fn() -> std::result::Result<E, String> + Sync,
+    {
+        self.run_with_progress(repository, index, files, new_extractor, |_, _| true)
+    }
+
+    /// Indexes `files` like `run`, calling `on_progress` with the files parsed so far and the total
+    ///
+    /// Returning false from `on_progress` cancels the run: workers stop
+    /// taking files, the files already parsed are still stored and the
+    /// report is marked cancelled.
+    pub fn run_with_progress<E, F, P>(
+        &self,
+        repository: &Repository,
+        index: &CodeIndex,
+        files: Vec<String>,
+        new_extractor: F,
+        mut on_progress: P,
+    ) -> Result<PipelineReport>
+    where
+        E: FileExtractor,
+        F: Fn() -> std::result::Result<E, String> + Sync,
+        P: FnMut(usize, usize) -> bool,
+    {
+        let started = Instant::now();
+        let total = files.len();
+        let jobs = self.config.jobs.min(files.len()).max(1);
+        let queue = Mutex::new(files.into_iter());
+        let (sender, receiver) = sync_channel(jobs * 2);
+        let base_path = Path::new(&index.base_path);
+        let new_extractor = &new_extractor;
+        let queue = &queue;
+        let policy = self.config.detail_policy;
+        let depths = if self.config.adaptive_depth { repository.list_directory_depths(&index.id)? } else { Vec::new() };
+        let depths = &depths;
+        let disk_space = match repository.disk_space_guard()? {
+            Some(guard) => {
+                let paths: Vec<PathBuf> = queue.lock().map(|queue| queue.as_slice().iter().map(|file| base_path.join(file)).collect()).unwrap_or_default();
+                let estimate = SizeEstimate::from_files(&paths, DEFAULT_SAMPLE_SIZE);
+                guard.preflight(&estimate)?;
+                Some((guard, estimate.database_bytes / estimate.file_count.max(1) as u64))
+            }
+            None => None,
+        };
+
+        thread::scope(|scope| {
+            for _ in 0..jobs {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let mut extractor = match new_extractor() {
+                        Ok(extractor) => extractor,
+                        Err(e) => {
+                            let _ = sender.send(WorkerMessage::Unavailable(e));
+                            return;
+                        }
+                    };
+                    loop {
+                        let next = queue.lock().map(|mut queue| queue.next()).unwrap_or(None);
+                        let Some(stored_path) = next else { break };
+                        let policy = depth_of_file(depths, &stored_path).map_or(policy, detail_policy);
+                        let parsed = panic::catch_unwind(AssertUnwindSafe(|| parse_file(&mut extractor, base_path, index, stored_path.clone(), &policy)));
+                        let (parsed, replacement) = match parsed {
+                            Ok(parsed) => (parsed, None),
+                            Err(panic) => {
+                                let error = ExtractError::new(IndexErrorKind::Panic, format!("Parser panicked: {}", panic_message(panic)));
+                                // The extractor may have been left half way through the file
+                                (failed_file(index, stored_path, error), Some(new_extractor()))
+                            }
+                        };
+                        // The writer stops receiving only after a storage error
+                        if sender.send(WorkerMessage::Parsed(Box::new(parsed))).is_err() {
+                            break;
+                        }
+                        match replacement {
+                            Some(Ok(replacement)) => extractor = replacement,
+                            Some(Err(e)) => {
+                                let _ = sender.send(WorkerMessage::Unavailable(e));
+                                return;
+                            }
+                            None => {}
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut writer = BatchState::new(self.config.batch_size, disk_space, self.priority_gate.clone(), total);
+            let mut unavailable = Vec::new();
+            let mut parsed_files = 0;
+            // Leaving the loop drops the receiver, which stops the workers
+            for message in receiver {
+                match message {
+                    WorkerMessage::Parsed(parsed) => {
+                        writer.push(repository, *parsed)?;
+                        parsed_files += 1;
+                        if !on_progress(parsed_files, total) {
+                            writer.report.cancelled = parsed_files < total;
+                            break;
+                        }
+                    }
+                    WorkerMessage::Unavailable(e) => unavailable.push(e),
+                }
+            }
+            writer.flush(repository)?;
+
+            if unavailable.len() == jobs {
+                return Err(StorageError::Validation(format!("No indexing worker could start: {}", unavailable[0])));
+            }
+            // Resolved and counted once every file is stored, so references across batches are included
+            writer.report.calls_stored = store_call_relationships(repository, index, std::mem::take(&mut writer.calls))?;
+            writer.report.callbacks_stored = store_callback_relationships(repository, index, std::mem::take(&mut writer.callbacks))?;
+            repository.refresh_symbol_popularity(&index.id)?;
+            repository.resolve_file_includes(&index.id)?;
+            assign_configurations(repository, index)?;
+            let mut report = writer.report;
+            report.elapsed = started.elapsed();
+            Ok(report)
+        })
+    }
+}
+
+/// Files buffered by the writer between commits
+struct BatchState {
+    batch_size: usize,
+    /// Checks room for the files left, at the estimated bytes per file, before each commit
+    disk_space: Option<(DiskSpaceGuard, u64)>,
+    priority_gate: Option<PriorityGate>,
+    /// Files not stored yet
+    remaining: usize,
+    indexed: Vec<(FileMetadata, Vec<CodeElement>, Vec<String>)>,
+    failed: Vec<(FileMetadata, IndexError)>,
+    /// Call sites of the files stored, by stored path
+    calls: Vec<(String, Vec<CallSite>)>,
+    /// Callback sites of the files stored, by stored path
+    callbacks: Vec<(String, Vec<CallbackSite>)>,
+    buffered_symbols: usize,
+    report: PipelineReport,
+}
+
+impl BatchState {
+    fn new(batch_size: usize, disk_space: Option<(DiskSpaceGuard, u64)>, priority_gate: Option<PriorityGate>, files: usize) -> Self {
+        Self {
+            batch_size,
+            disk_space,
+            priority_gate,
+            remaining: files,
+            indexed: Vec::new(),
+            failed: Vec::new(),
+            calls: Vec::new(),
+            callbacks: Vec::new(),
+            buffered_symbols: 0,
+            report: PipelineReport::default(),
+        }
+    }
+
+    fn push(&mut self, repository: &Repository, parsed: ParsedFile) -> Result<()> {
+        match parsed.result {
+            Ok((tiered, includes, calls)) => {
+                self.buffered_symbols += tiered.elements.len();
+                if !calls.is_empty() {
+                    self.calls.push((parsed.metadata.file_path.clone(), calls));
+                }
+                if !parsed.callback_sites.is_empty() {
+                    self.callbacks.push((parsed.metadata.file_path.clone(), parsed.callback_sites));
+                }
+                self.indexed.push((parsed.metadata, tiered.elements, includes));
+            }
+            Err(e) => {
+                warn!("Failed to index {}: {}", parsed.metadata.file_path, e);
+                let error = IndexError::new(parsed.metadata.index_id, parsed.metadata.file_path.clone(), e.kind, e.message);
+                self.report.failures.push((error.file_path.clone(), error.message.clone()));
+                self.failed.push((parsed.metadata, error));
+            }
+        }
+        if self.buffered_symbols >= self.batch_size {
+            self.flush(repository)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, repository: &Repository) -> Result<()> {
+        if self.indexed.is_empty() && self.failed.is_empty() {
+            return Ok(());
+        }
+        let files = self.indexed.len();
+        let failed = self.failed.len();
+        if let Some((guard, bytes_per_file)) = &self.disk_space {
+            guard.ensure_available(bytes_per_file * self.remaining as u64)?;
+        }
+        let (indexed, failed_files) = (std::mem::take(&mut self.indexed), std::mem::take(&mut self.failed));
+        let stored = match &self.priority_gate {
+            Some(gate) => BatchWriter::new(repository, gate.clone()).write_file_batch(indexed, failed_files).map(|(stored, _)| stored),
+            None => repository.store_file_batch(indexed, failed_files),
+        };
+        self.report.symbols_stored += stored
+            .map_err(|e| match self.disk_space.as_ref().and_then(|(guard, _)| guard.classify(&e)) {
+                Some(full) => full.into(),
+                None => e,
+            })?;
+        self.remaining = self.remaining.saturating_sub(files + failed);
+        self.report.files_indexed += files;
+        self.report.batches += 1;
+        self.buffered_symbols = 0;
+        Ok(())
+    }
+}
+
+/// Reads, hashes and parses one file on a worker thread
+fn parse_file<E: FileExtractor>(extractor: &mut E, base_path: &Path, index: &CodeIndex, stored_path: String, policy: &DetailPolicy) -> ParsedFile {
+    let path: PathBuf = base_path.join(&stored_path);
+    let (hash, size, modified, callback_sites) = match std::fs::read(&path).and_then(|content| Ok((content, std::fs::metadata(&path)?))) {
+        Ok((content, disk)) => {
+            let mut hasher = Sha256::new();
+            hasher.update(&content);
+            let modified = disk.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now());
+            (format!("{:x}", hasher.finalize()), disk.len(), modified, find_callback_sites(&String::from_utf8_lossy(&content)))
+        }
+        Err(e) => return failed_file(index, stored_path, ExtractError::new(IndexErrorKind::Read, format!("Failed to read: {}", e))),
+    };
+
+    let result = extractor.extract(&path).map(|extraction| {
+        // Skip symbols the parser reported for included headers
+        let symbols: Vec<ExtractedSymbol> = extraction.symbols.into_iter().filter(|symbol| symbol.file_path.ends_with(&stored_path)).collect();
+        (policy.apply(&symbols, index.id, &stored_path), extraction.includes, extraction.calls)
+    });
+    let detail = match &result {
+        Ok((tiered, _, _)) if tiered.outlined + tiered.omitted > 0 => FileDetail::Reduced,
+        _ => FileDetail::Full,
+    };
+    let mut metadata = FileMetadata::new(index.id, stored_path, hash, modified, size).with_detail(detail);
+    if let Ok((tiered, _, _)) = &result {
+        metadata.symbol_count = tiered.elements.len() as u32;
+    }
+    ParsedFile { metadata, result, callback_sites }
+}
+
+/// A file that failed without its contents being hashed
+///
+/// It is still recorded, with a placeholder hash that never matches, so
+/// the next run tries it again.
+fn failed_file(index: &CodeIndex, stored_path: String, error: ExtractError) -> ParsedFile {
+    ParsedFile {
+        metadata: FileMetadata::new(index.id, stored_path, "0".repeat(64), Utc::now(), 0),
+        result: Err(error),
+        callback_sites: Vec::new(),
+    }
+}
+
+/// The message a panic was raised with
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => panic.downcast_ref::<&str>().map_or_else(|| "unknown cause".to_string(), |message| message.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::disk_space::DiskSpaceError;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+    /// Reports one function per line of the file; files containing "!" fail and "crash" panic
+    struct LineExtractor;
+
+    impl FileExtractor for LineExtractor {
+        fn extract(&mut self, path: &Path) -> std::result::Result<FileExtraction, ExtractError> {
+            let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            if content.contains('!') {
+                return Err("syntax error".to_string().into());
+            }
+            if content.contains("crash") {
+                panic!("parser state corrupted");
+            }
+            let symbols = content
+                .lines()
+                .zip(1..)
+                .map(|(name, line)| ExtractedSymbol {
+                    name: name.to_string(),
+                    symbol_type: SymbolType::Function,
+                    visibility: None,
+                    file_path: path.to_path_buf(),
+                    start_line: line,
+                    end_line: line,
+                    start_column: 1,
+                    end_column: 1,
+                    content: name.to_string(),
+                    fully_qualified_name: name.to_string(),
+                    namespace_path: Vec::new(),
+                    dependencies: Vec::new(),
+                    template_parameters: Vec::new(),
+                    base_classes: Vec::new(),
+                    member_functions: Vec::new(),
+                    member_variables: Vec::new(),
+                    signature: None,
+                    documentation: None,
+                    is_definition: true,
+                    is_declaration: false,
+                    memory_section: None,
+                    usr: None,
+                })
+                .collect();
+            Ok(FileExtraction { symbols, includes: Vec::new(), calls: Vec::new() })
+        }
+    }
+
+    #[test]
+    fn test_pipeline_indexes_in_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = Vec::new();
+        for i in 0..20 {
+            let name = format!("src/file{}.cpp", i);
+            std::fs::create_dir_all(dir.path().join("src")).unwrap();
+            std::fs::write(dir.path().join(&name), "alpha\nbeta\ngamma").unwrap();
+            files.push(name);
+        }
+        std::fs::write(dir.path().join("src/broken.cpp"), "oops!").unwrap();
+        files.push("src/broken.cpp".to_string());
+        files.push("src/missing.cpp".to_string());
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("big".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+
+        let config = PipelineConfig::default().with_jobs(4).with_batch_size(10);
+        let report = IndexingPipeline::new(config).run(&repository, &index, files, || Ok(LineExtractor)).unwrap();
+        assert_eq!(report.files_indexed, 20);
+        assert_eq!(report.symbols_stored, 60);
+        assert!(report.batches >= 6);
+        let mut failed: Vec<&str> = report.failures.iter().map(|(path, _)| path.as_str()).collect();
+        failed.sort_unstable();
+        assert_eq!(failed, ["src/broken.cpp", "src/missing.cpp"]);
+
+        assert_eq!(repository.list_code_elements_by_file(&index.id, "src/file7.cpp").unwrap().len(), 3);
+        assert!(repository.is_file_indexed(&index.id, "src/file7.cpp").unwrap());
+        assert!(!repository.is_file_indexed(&index.id, "src/broken.cpp").unwrap());
+        let coverage = repository.get_index_coverage(&index.id).unwrap();
+        assert_eq!((coverage.indexed_files, coverage.failed_files), (20, 2));
+
+        // Re-running replaces symbols instead of duplicating them
+        let report = IndexingPipeline::new(config).run(&repository, &index, vec!["src/file7.cpp".to_string()], || Ok(LineExtractor)).unwrap();
+        assert_eq!(report.symbols_stored, 3);
+        assert_eq!(repository.list_code_elements_by_file(&index.id, "src/file7.cpp").unwrap().len(), 3);
+
+        // Batches that yield to a waiting query still store every file
+        let gate = PriorityGate::new();
+        let _query = gate.enter();
+        let rerun = vec!["src/file1.cpp".to_string(), "src/file2.cpp".to_string()];
+        let report = IndexingPipeline::new(config).with_priority_gate(gate.clone()).run(&repository, &index, rerun, || Ok(LineExtractor)).unwrap();
+        assert_eq!(report.symbols_stored, 6);
+        assert_eq!(repository.list_code_elements_by_file(&index.id, "src/file2.cpp").unwrap().len(), 3);
+
+        let unavailable = IndexingPipeline::new(config).run(&repository, &index, vec!["src/file1.cpp".to_string()], || {
+            Err::<LineExtractor, _>("no libclang".to_string())
+        });
+        assert!(unavailable.is_err());
+    }
+
+    #[test]
+    fn test_pipeline_indexes_at_learned_depth() {
+        use crate::lib::storage::models::directory_depth::{DirectoryDepth, IndexDepth};
+
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec!["busy/a.cpp".to_string(), "quiet/b.cpp".to_string()];
+        for file in &files {
+            std::fs::create_dir_all(dir.path().join(file).parent().unwrap()).unwrap();
+            std::fs::write(dir.path().join(file), "alpha\nbeta").unwrap();
+        }
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("depths".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        let depth = |directory: &str, depth| DirectoryDepth { index_id: index.id, directory: directory.to_string(), depth, hits: 0, assigned_at: Utc::now() };
+        repository.replace_directory_depths(&index.id, &[depth("busy", IndexDepth::Deep), depth("quiet", IndexDepth::Shallow)]).unwrap();
+
+        // Learned depths are ignored unless the run asks for them
+        let config = PipelineConfig::default().with_jobs(1);
+        IndexingPipeline::new(config).run(&repository, &index, files.clone(), || Ok(LineExtractor)).unwrap();
+        assert_eq!(repository.list_code_elements_by_file(&index.id, "quiet/b.cpp").unwrap().len(), 2);
+
+        IndexingPipeline::new(config.with_adaptive_depth(true)).run(&repository, &index, files, || Ok(LineExtractor)).unwrap();
+        assert_eq!(repository.list_code_elements_by_file(&index.id, "busy/a.cpp").unwrap().len(), 2);
+        // Shallow directories keep no internal symbols
+        assert!(repository.list_code_elements_by_file(&index.id, "quiet/b.cpp").unwrap().is_empty());
+        assert_eq!(repository.get_file_metadata_by_path(&index.id, "quiet/b.cpp").unwrap().unwrap().detail, FileDetail::Reduced);
+    }
+
+    #[test]
+    fn test_pipeline_stores_callback_references() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("timer.cpp"), "on_tick\nstart_timer = &on_tick;").unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("timer".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+
+        let pipeline = IndexingPipeline::new(PipelineConfig::default().with_jobs(1));
+        let report = pipeline.run(&repository, &index, vec!["timer.cpp".to_string()], || Ok(LineExtractor)).unwrap();
+        assert_eq!(report.callbacks_stored, 1);
+        let on_tick = repository.find_code_elements_by_name(&index.id, "on_tick").unwrap().remove(0);
+        let (_, incoming) = repository.get_symbol_relationships(on_tick.id.unwrap()).unwrap();
+        assert_eq!(incoming.len(), 1);
+        assert_eq!((incoming[0].relationship_type, incoming[0].line_number), (RelationshipType::ReferencedAsCallback, 2));
+
+        // Indexing the file again replaces its references instead of adding to them
+        pipeline.run(&repository, &index, vec!["timer.cpp".to_string()], || Ok(LineExtractor)).unwrap();
+        assert_eq!(repository.get_symbol_relationships(on_tick.id.unwrap()).unwrap().1.len(), 1);
+    }
+
+    #[test]
+    fn test_pipeline_reports_progress_and_cancels() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<String> = (0..10).map(|i| format!("file{}.cpp", i)).collect();
+        for file in &files {
+            std::fs::write(dir.path().join(file), "alpha\nbeta").unwrap();
+        }
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("big".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+
+        let pipeline = IndexingPipeline::new(PipelineConfig::default().with_jobs(2));
+        let mut seen = Vec::new();
+        let report = pipeline
+            .run_with_progress(&repository, &index, files.clone(), || Ok(LineExtractor), |done, total| {
+                seen.push((done, total));
+                done < 3
+            })
+            .unwrap();
+        assert_eq!(seen, [(1, 10), (2, 10), (3, 10)]);
+        assert!(report.cancelled);
+        // Files parsed before the cancellation are kept
+        assert_eq!((report.files_indexed, report.symbols_stored), (3, 6));
+        assert_eq!(repository.get_index_coverage(&index.id).unwrap().indexed_files, 3);
+
+        let report = pipeline.run_with_progress(&repository, &index, files, || Ok(LineExtractor), |_, _| true).unwrap();
+        assert!(!report.cancelled);
+        assert_eq!(report.files_indexed, 10);
+    }
+
+    #[test]
+    fn test_pipeline_isolates_failing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<String> = ["a.cpp", "broken.cpp", "crash.cpp", "missing.cpp", "z.cpp"].iter().map(|file| file.to_string()).collect();
+        for (file, content) in [("a.cpp", "alpha"), ("broken.cpp", "oops!"), ("crash.cpp", "crash"), ("z.cpp", "omega")] {
+            std::fs::write(dir.path().join(file), content).unwrap();
+        }
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("partial".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+
+        // A panicking parse fails its file only; its worker goes on with a new extractor
+        let pipeline = IndexingPipeline::new(PipelineConfig::default().with_jobs(1));
+        let report = pipeline.run(&repository, &index, files, || Ok(LineExtractor)).unwrap();
+        assert_eq!((report.files_indexed, report.failures.len()), (2, 3));
+        let errors: Vec<(String, IndexErrorKind)> = repository
+            .list_index_errors(&index.id, None)
+            .unwrap()
+            .into_iter()
+            .map(|error| (error.file_path, error.kind))
+            .collect();
+        assert_eq!(errors, [
+            ("broken.cpp".to_string(), IndexErrorKind::Parse),
+            ("crash.cpp".to_string(), IndexErrorKind::Panic),
+            ("missing.cpp".to_string(), IndexErrorKind::Read),
+        ]);
+        assert!(report.failures.iter().any(|(file, message)| file == "crash.cpp" && message.ends_with("parser state corrupted")));
+
+        // Fixed files lose their errors
+        std::fs::write(dir.path().join("crash.cpp"), "gamma").unwrap();
+        pipeline.run(&repository, &index, vec!["crash.cpp".to_string()], || Ok(LineExtractor)).unwrap();
+        assert_eq!(repository.list_index_errors(&index.id, Some(IndexErrorKind::Panic)).unwrap(), []);
+        assert_eq!(repository.list_index_errors(&index.id, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_pipeline_stops_when_the_database_would_outgrow_its_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("generated.cpp"), "alpha\n".repeat(200_000)).unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(dir.path().join("index.db")).with_max_size_mb(1)).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("generated".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+
+        let error = IndexingPipeline::default().run(&repository, &index, vec!["generated.cpp".to_string()], || Ok(LineExtractor)).unwrap_err();
+        assert!(matches!(error, StorageError::DiskSpace(DiskSpaceError::Insufficient { .. })), "{}", error);
+        assert_eq!(error.kind(), "disk_space");
+        assert!(repository.list_file_metadata(&index.id).unwrap().is_empty());
+    }
+}
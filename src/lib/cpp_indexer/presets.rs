@@ -0,0 +1,125 @@
+// Index creation presets
+//
+// Common ecosystems need the same setup every time: which directories hold
+// the code, which are generated or vendored, which macros a default build
+// defines and how much detail a tree that size can afford. A preset bundles
+// those settings under one name for `index create --preset`.
+
+use crate::lib::cpp_indexer::detail_tiers::DetailPolicy;
+use crate::lib::cpp_indexer::symbol_filter::SymbolFilter;
+use crate::lib::cpp_indexer::vfs::pattern_matches;
+
+/// Index tag recording the preset an index was created with
+pub const PRESET_TAG: &str = "preset";
+
+/// Settings bundled for one kind of codebase
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Gitignore-style patterns of files to index; empty indexes every source file
+    pub include: &'static [&'static str],
+    /// Patterns of files to skip, applied after `include`
+    pub exclude: &'static [&'static str],
+    /// Macros of the default build configuration, as NAME or NAME=VALUE
+    pub defines: &'static [&'static str],
+    /// Scopes whose symbols are left out
+    pub exclude_scopes: &'static [&'static str],
+    /// What is stored per detail tier
+    pub detail_policy: DetailPolicy,
+}
+
+/// All presets, by name
+pub fn presets() -> Vec<IndexPreset> {
+    vec![
+        IndexPreset {
+            name: "unreal",
+            description: "Unreal Engine project: game and plugin sources, without generated headers",
+            include: &["Source/", "Plugins/"],
+            exclude: &["Intermediate/", "Binaries/", "ThirdParty/", "*.gen.cpp", "*.generated.h"],
+            defines: &["WITH_EDITOR=1", "UE_BUILD_DEVELOPMENT=1", "WITH_ENGINE=1"],
+            exclude_scopes: &[],
+            detail_policy: DetailPolicy::tiered(),
+        },
+        IndexPreset {
+            name: "ros2",
+            description: "ROS 2 workspace: package sources, without build, install and log trees",
+            include: &["src/"],
+            exclude: &["build/", "install/", "log/", "test/"],
+            defines: &[],
+            exclude_scopes: &[],
+            detail_policy: DetailPolicy::full(),
+        },
+        IndexPreset {
+            name: "chromium-subset",
+            description: "Core Chromium libraries (base, url, net, public content API), without tests and vendored code",
+            include: &["base/", "url/", "net/", "content/public/"],
+            exclude: &["third_party/", "out/", "*_unittest.cc", "*_browsertest.cc", "*_fuzzer.cc", "*_perftest.cc"],
+            defines: &["NDEBUG"],
+            exclude_scopes: &["*::internal"],
+            detail_policy: DetailPolicy::tiered(),
+        },
+        IndexPreset {
+            name: "embedded",
+            description: "Firmware tree: everything but build outputs and vendor HAL/CMSIS packs, with symbol bodies",
+            include: &[],
+            exclude: &["build/", "Debug/", "Release/", "Drivers/CMSIS/", "*_hal_*.c"],
+            defines: &["__arm__", "__ARM_ARCH_7EM__"],
+            exclude_scopes: &[],
+            detail_policy: DetailPolicy::full().with_bodies(true),
+        },
+    ]
+}
+
+/// The preset named `name`, if there is one
+pub fn preset(name: &str) -> Option<IndexPreset> {
+    presets().into_iter().find(|preset| preset.name == name)
+}
+
+impl IndexPreset {
+    /// Returns true if the file at `path`, relative to the codebase root, is indexed
+    pub fn selects(&self, path: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|pattern| pattern_matches(pattern, path));
+        included && !self.exclude.iter().any(|pattern| pattern_matches(pattern, path))
+    }
+
+    /// Macro definitions as `-D` takes them
+    pub fn defines(&self) -> Vec<String> {
+        self.defines.iter().map(|define| define.to_string()).collect()
+    }
+
+    /// The preset's symbol filter, merged into `filter`
+    pub fn merge_symbol_filter(&self, mut filter: SymbolFilter) -> SymbolFilter {
+        filter.exclude_scopes.extend(self.exclude_scopes.iter().map(|scope| scope.to_string()));
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let names: Vec<&str> = presets().iter().map(|preset| preset.name).collect();
+        assert_eq!(names, ["unreal", "ros2", "chromium-subset", "embedded"]);
+        assert!(preset("qt").is_none());
+
+        let unreal = preset("unreal").unwrap();
+        assert!(unreal.selects("Source/Shooter/Weapon.cpp"));
+        assert!(unreal.selects("Plugins/Inventory/Source/Inventory/Bag.h"));
+        assert!(!unreal.selects("Source/Shooter/Weapon.generated.h"));
+        assert!(!unreal.selects("Intermediate/Build/Module.Shooter.cpp"));
+        assert!(unreal.detail_policy.is_reduced());
+
+        let chromium = preset("chromium-subset").unwrap();
+        assert!(chromium.selects("base/strings/string_util.cc"));
+        assert!(!chromium.selects("base/strings/string_util_unittest.cc"));
+        assert!(!chromium.selects("chrome/browser/ui/browser.cc"));
+        let filter = chromium.merge_symbol_filter(SymbolFilter { exclude_names: vec!["_*".to_string()], ..Default::default() });
+        assert_eq!((filter.exclude_names.len(), filter.exclude_scopes.as_slice()), (1, ["*::internal".to_string()].as_slice()));
+
+        assert!(preset("embedded").unwrap().selects("Core/Src/main.c"));
+        assert!(!preset("embedded").unwrap().selects("Drivers/STM32F4xx_HAL_Driver/Src/stm32f4xx_hal_gpio.c"));
+    }
+}
@@ -0,0 +1,164 @@
+// Spill store for incremental indexing state
+//
+// The incremental indexer remembers every file it has seen: its hashes, its
+// includes and the files including it, plus a Merkle leaf per file. On a
+// tree of 100k+ files that outgrows a developer machine, so past a memory
+// budget the indexer moves the file nodes into a scratch SQLite database and
+// reads them back as it needs them. Merkle leaves are dropped rather than
+// stored: the tree's path-to-hash map is all it reads.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+use crate::lib::cpp_indexer::incremental::{FileNode, MerkleNode};
+
+/// Scratch database holding file nodes moved out of memory
+pub struct SpillStore {
+    connection: Connection,
+}
+
+/// Approximate memory held for a file: its node and its Merkle leaf
+pub fn estimated_size(node: &FileNode) -> usize {
+    let paths: usize = node.dependencies.iter().chain(&node.dependents).map(|path| path.as_os_str().len() + 24).sum();
+    let node_size = std::mem::size_of::<FileNode>()
+        + node.path.as_os_str().len()
+        + node.content_hash.len()
+        + node.metadata_hash.len()
+        + node.symbols_hash.len()
+        + paths;
+    // The leaf holds its hash and the path, and the tree maps the path to the hash once more
+    let leaf_size = std::mem::size_of::<MerkleNode>() + 2 * (64 + node.path.as_os_str().len());
+    node_size + leaf_size
+}
+
+impl SpillStore {
+    /// Creates the store at `path`, replacing what a previous run left there
+    pub fn create(path: &Path) -> rusqlite::Result<Self> {
+        let _ = std::fs::remove_file(path);
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            r#"
+            PRAGMA journal_mode = OFF;
+            PRAGMA synchronous = OFF;
+            CREATE TABLE file_nodes (
+                path TEXT PRIMARY KEY,
+                content_hash TEXT NOT NULL,
+                metadata_hash TEXT NOT NULL,
+                last_modified INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                dependencies TEXT NOT NULL,
+                dependents TEXT NOT NULL,
+                symbols_hash TEXT NOT NULL
+            );
+            "#,
+        )?;
+        Ok(Self { connection })
+    }
+
+    /// Stores file nodes, replacing earlier copies
+    pub fn put_nodes<'a>(&mut self, nodes: impl IntoIterator<Item = &'a FileNode>) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO file_nodes
+                 (path, content_hash, metadata_hash, last_modified, size, dependencies, dependents, symbols_hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for node in nodes {
+                statement.execute(params![
+                    node.path.to_string_lossy(),
+                    node.content_hash,
+                    node.metadata_hash,
+                    node.last_modified as i64,
+                    node.size as i64,
+                    encode_paths(&node.dependencies),
+                    encode_paths(&node.dependents),
+                    node.symbols_hash,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// The stored node of a file
+    pub fn get_node(&self, path: &Path) -> rusqlite::Result<Option<FileNode>> {
+        self.connection
+            .query_row(
+                "SELECT content_hash, metadata_hash, last_modified, size, dependencies, dependents, symbols_hash
+                 FROM file_nodes WHERE path = ?1",
+                [path.to_string_lossy()],
+                |row| {
+                    Ok(FileNode {
+                        path: path.to_path_buf(),
+                        content_hash: row.get(0)?,
+                        metadata_hash: row.get(1)?,
+                        last_modified: row.get::<_, i64>(2)? as u64,
+                        size: row.get::<_, i64>(3)? as u64,
+                        dependencies: decode_paths(&row.get::<_, String>(4)?),
+                        dependents: decode_paths(&row.get::<_, String>(5)?),
+                        symbols_hash: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn remove_node(&self, path: &Path) -> rusqlite::Result<()> {
+        self.connection.execute("DELETE FROM file_nodes WHERE path = ?1", [path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Paths of the stored nodes
+    pub fn node_paths(&self) -> rusqlite::Result<Vec<PathBuf>> {
+        let mut statement = self.connection.prepare("SELECT path FROM file_nodes")?;
+        let paths = statement.query_map([], |row| row.get::<_, String>(0))?;
+        paths.map(|path| path.map(PathBuf::from)).collect()
+    }
+
+    /// Total number of includes recorded by the stored nodes
+    pub fn dependency_count(&self) -> rusqlite::Result<usize> {
+        let mut statement = self.connection.prepare("SELECT dependencies FROM file_nodes")?;
+        let dependencies = statement.query_map([], |row| row.get::<_, String>(0))?;
+        dependencies.map(|dependencies| dependencies.map(|dependencies| decode_paths(&dependencies).len())).sum()
+    }
+}
+
+/// Paths as a JSON array of strings
+fn encode_paths(paths: &[PathBuf]) -> String {
+    let paths: Vec<std::borrow::Cow<str>> = paths.iter().map(|path| path.to_string_lossy()).collect();
+    serde_json::to_string(&paths).expect("strings are always serializable")
+}
+
+fn decode_paths(encoded: &str) -> Vec<PathBuf> {
+    serde_json::from_str::<Vec<String>>(encoded).unwrap_or_default().into_iter().map(PathBuf::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SpillStore::create(&dir.path().join("index.spill")).unwrap();
+        let node = FileNode {
+            path: PathBuf::from("/work/audio/src/mixer.cpp"),
+            content_hash: "c".repeat(64),
+            metadata_hash: "m".repeat(64),
+            last_modified: 1_700_000_000,
+            size: 2048,
+            dependencies: vec![PathBuf::from("mixer.h"), PathBuf::from("audio/format.h")],
+            dependents: Vec::new(),
+            symbols_hash: "s".repeat(64),
+        };
+        store.put_nodes([&node]).unwrap();
+
+        assert_eq!(store.get_node(&node.path).unwrap().as_ref().map(|stored| &stored.dependencies), Some(&node.dependencies));
+        assert_eq!(store.dependency_count().unwrap(), 2);
+        assert!(estimated_size(&node) > 3 * 64);
+
+        store.remove_node(&node.path).unwrap();
+        assert!(store.get_node(&node.path).unwrap().is_none());
+        assert!(store.node_paths().unwrap().is_empty());
+    }
+}
@@ -1,12 +1,18 @@
 use crate::lib::cpp_indexer::tree_sitter_parser::{TreeSitterParser, ParseResult, ParsedNode};
-use crate::lib::cpp_indexer::clang_parser::{ClangParser, SemanticParseResult, SemanticInfo};
-use crate::lib::storage::models::code_element::{SymbolType, AccessModifier};
+use crate::lib::cpp_indexer::clang_parser::{CallSite, ClangParser, HeaderCache, SemanticParseResult, SemanticInfo};
+use crate::lib::cpp_indexer::compile_commands::CompilationDatabase;
+use crate::lib::cpp_indexer::dialect::DialectRules;
+use crate::lib::cpp_indexer::attributes::{declaration_documentation, declaration_section, SectionMacros};
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType, AccessModifier};
 use clang::EntityKind;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use tokio::time::Instant;
+use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtractedSymbol {
     pub name: String,
     pub symbol_type: SymbolType,
@@ -28,6 +34,48 @@ pub struct ExtractedSymbol {
     pub documentation: Option<String>,
     pub is_definition: bool,
     pub is_declaration: bool,
+    /// Linker section from a section attribute or placement macro
+    pub memory_section: Option<String>,
+    /// Unified Symbol Resolution id, for symbols parsed with libclang
+    pub usr: Option<String>,
+}
+
+impl ExtractedSymbol {
+    /// Storage form of the symbol, for its file stored as `stored_path`
+    pub fn to_code_element(&self, index_id: Uuid, stored_path: &str) -> CodeElement {
+        let mut hasher = Sha256::new();
+        hasher.update(self.content.as_bytes());
+        let mut element = CodeElement::new(
+            index_id,
+            self.name.clone(),
+            self.symbol_type,
+            stored_path.to_string(),
+            self.start_line.max(1),
+            self.start_column.max(1),
+            format!("{:x}", hasher.finalize()),
+        )
+        .with_declaration(self.is_declaration && !self.is_definition);
+
+        if !self.namespace_path.is_empty() {
+            element = element.with_scope(self.namespace_path.join("::"));
+        }
+        if let Some(visibility) = self.visibility {
+            element = element.with_access_modifier(visibility);
+        }
+        if let Some(signature) = &self.signature {
+            element = element.with_signature(signature.clone());
+        }
+        if let Some(section) = &self.memory_section {
+            element = element.with_memory_section(section.clone());
+        }
+        if let Some(documentation) = &self.documentation {
+            element = element.with_documentation(documentation.clone());
+        }
+        if let Some(usr) = &self.usr {
+            element = element.with_usr(usr.clone());
+        }
+        element
+    }
 }
 
 pub struct SymbolExtractor {
@@ -46,13 +94,38 @@ impl SymbolExtractor {
         })
     }
 
+    /// Parses each file with its flags from a compilation database
+    pub fn with_compilation_database(mut self, database: CompilationDatabase) -> Self {
+        self.clang_parser = self.clang_parser.with_compilation_database(database);
+        self
+    }
+
+    /// Parses files matching a dialect rule with its options
+    pub fn with_dialect_rules(mut self, dialects: DialectRules) -> Self {
+        self.clang_parser = self.clang_parser.with_dialect_rules(dialects);
+        self
+    }
+
+    /// Shares header results with the other extractors of an indexing run
+    pub fn with_header_cache(mut self, cache: HeaderCache) -> Self {
+        self.clang_parser = self.clang_parser.with_header_cache(cache);
+        self
+    }
+
     pub async fn extract_symbols(&mut self, file_path: &Path) -> Result<ExtractionResult, Box<dyn std::error::Error>> {
         let start_time = Instant::now();
         
         let tree_sitter_result = self.tree_sitter_parser.parse_file(file_path).await?;
         let clang_result = self.clang_parser.parse_file(file_path)?;
         
-        let symbols = self.merge_parser_results(&tree_sitter_result, &clang_result)?;
+        let mut symbols = self.merge_parser_results(&tree_sitter_result, &clang_result)?;
+        if let Ok(content) = std::fs::read_to_string(file_path) {
+            let macros = SectionMacros::default();
+            for symbol in &mut symbols {
+                symbol.memory_section = declaration_section(&content, symbol.start_line, &macros);
+                symbol.documentation = declaration_documentation(&content, symbol.start_line);
+            }
+        }
         
         let extraction_time = start_time.elapsed();
         
@@ -63,6 +136,41 @@ impl SymbolExtractor {
             extraction_time_ms: extraction_time.as_millis() as u32,
             tree_sitter_symbols: tree_sitter_result.symbols.len(),
             clang_symbols: clang_result.symbols.len(),
+            calls: clang_result.calls,
+        })
+    }
+
+    /// Extracts symbols from in-memory source, e.g. a file read from an archive
+    ///
+    /// LibClang needs the file on disk, so only the syntactic (Tree-sitter)
+    /// pass runs; symbols carry no semantic relationships.
+    pub fn extract_symbols_from_content(&mut self, content: &str, file_path: &Path) -> Result<ExtractionResult, Box<dyn std::error::Error>> {
+        let start_time = Instant::now();
+
+        let tree_sitter_result = self.tree_sitter_parser.parse_content(content, file_path)?;
+        let no_semantics = SemanticParseResult {
+            file_path: file_path.to_path_buf(),
+            symbols: Vec::new(),
+            references: HashMap::new(),
+            type_hierarchy: HashMap::new(),
+            calls: Vec::new(),
+        };
+
+        let mut symbols = self.merge_parser_results(&tree_sitter_result, &no_semantics)?;
+        let macros = SectionMacros::default();
+        for symbol in &mut symbols {
+            symbol.memory_section = declaration_section(content, symbol.start_line, &macros);
+            symbol.documentation = declaration_documentation(content, symbol.start_line);
+        }
+
+        Ok(ExtractionResult {
+            file_path: file_path.to_path_buf(),
+            symbols,
+            includes: tree_sitter_result.includes,
+            extraction_time_ms: start_time.elapsed().as_millis() as u32,
+            tree_sitter_symbols: tree_sitter_result.symbols.len(),
+            clang_symbols: 0,
+            calls: Vec::new(),
         })
     }
 
@@ -157,6 +265,8 @@ impl SymbolExtractor {
             documentation: None,
             is_definition: semantic_info.is_definition,
             is_declaration: semantic_info.is_declaration,
+            memory_section: None,
+            usr: semantic_info.usr.clone(),
         })
     }
 
@@ -188,6 +298,8 @@ impl SymbolExtractor {
             documentation: None,
             is_definition: true,
             is_declaration: false,
+            memory_section: None,
+            usr: None,
         })
     }
 
@@ -205,6 +317,7 @@ impl SymbolExtractor {
             EntityKind::EnumConstantDecl => SymbolType::EnumConstant,
             EntityKind::Namespace => SymbolType::Namespace,
             EntityKind::TypedefDecl | EntityKind::TypeAliasDecl => SymbolType::Typedef,
+            EntityKind::LambdaExpr => SymbolType::Lambda,
             _ => SymbolType::Unknown,
         }
     }
@@ -214,6 +327,8 @@ impl SymbolExtractor {
             SymbolType::Class
         } else if parse_kind.contains("struct") {
             SymbolType::Struct
+        } else if parse_kind.contains("union") {
+            SymbolType::Union
         } else if parse_kind.contains("function") {
             SymbolType::Function
         } else if parse_kind.contains("field") {
@@ -279,9 +394,11 @@ impl SymbolExtractor {
         let mut member_variables = Vec::new();
         
         for symbol in &clang_result.symbols {
-            if symbol.fully_qualified_name.starts_with(&semantic_info.fully_qualified_name) &&
-               symbol.fully_qualified_name != semantic_info.fully_qualified_name {
-                
+            let member = symbol
+                .fully_qualified_name
+                .strip_prefix(&semantic_info.fully_qualified_name)
+                .is_some_and(|rest| rest.starts_with("::"));
+            if member {
                 match symbol.symbol_kind {
                     EntityKind::Method | EntityKind::Constructor | EntityKind::Destructor => {
                         member_functions.push(symbol.symbol_name.clone());
@@ -374,6 +491,8 @@ pub struct ExtractionResult {
     pub extraction_time_ms: u32,
     pub tree_sitter_symbols: usize,
     pub clang_symbols: usize,
+    /// Calls made in the file, found by LibClang
+    pub calls: Vec<CallSite>,
 }
 
 impl ExtractionResult {
@@ -455,5 +574,44 @@ mod tests {
         
         let path = extractor.extract_namespace_path("MyClass");
         assert_eq!(path, Vec::<String>::new());
+
+        let path = extractor.extract_namespace_path("audio::(anonymous namespace)::start::(lambda at mixer.cpp:12:5)");
+        assert_eq!(path, vec!["audio", "(anonymous namespace)", "start"]);
+    }
+    #[test]
+    fn test_to_code_element() {
+        let symbol = ExtractedSymbol {
+            name: "drain".to_string(),
+            symbol_type: SymbolType::Function,
+            visibility: Some(AccessModifier::Public),
+            file_path: PathBuf::from("/work/src/ring.h"),
+            start_line: 12,
+            end_line: 14,
+            start_column: 0,
+            end_column: 1,
+            content: "void drain();".to_string(),
+            fully_qualified_name: "audio::Ring::drain".to_string(),
+            namespace_path: vec!["audio".to_string(), "Ring".to_string()],
+            dependencies: Vec::new(),
+            template_parameters: Vec::new(),
+            base_classes: Vec::new(),
+            member_functions: Vec::new(),
+            member_variables: Vec::new(),
+            signature: Some("void drain()".to_string()),
+            documentation: Some("Empties the ring".to_string()),
+            is_definition: false,
+            is_declaration: true,
+            memory_section: None,
+            usr: None,
+        };
+
+        let index_id = Uuid::new_v4();
+        let element = symbol.to_code_element(index_id, "src/ring.h");
+        assert!(element.validate().is_ok());
+        assert_eq!(element.file_path, "src/ring.h");
+        assert_eq!((element.line_number, element.column_number), (12, 1));
+        assert_eq!(element.scope.as_deref(), Some("audio::Ring"));
+        assert!(element.is_declaration);
+        assert_eq!(element.documentation.as_deref(), Some("Empties the ring"));
     }
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+// Project symbol filters
+//
+// Projects often carry symbols nobody wants to search: generated code,
+// `detail` namespaces, reserved names. A filter drops them at extraction so
+// they never reach the index.
+
+use serde::Deserialize;
+
+use crate::lib::cpp_indexer::symbol_extractor::ExtractedSymbol;
+use crate::lib::cpp_indexer::vfs::wildcard_match;
+use crate::lib::storage::models::code_element::SymbolType;
+
+/// Symbols to leave out of an index
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SymbolFilter {
+    /// Name globs of symbols to skip, e.g. "_*" or "*_impl"
+    pub exclude_names: Vec<String>,
+    /// Scope globs whose symbols are skipped, e.g. "*::detail" or "google::protobuf*"
+    pub exclude_scopes: Vec<String>,
+    /// Symbol types to keep, e.g. ["function", "class"]; empty keeps all
+    pub types: Vec<String>,
+}
+
+impl SymbolFilter {
+    /// Returns true if the filter keeps every symbol
+    pub fn is_empty(&self) -> bool {
+        self.exclude_names.is_empty() && self.exclude_scopes.is_empty() && self.types.is_empty()
+    }
+
+    /// Fails on a type name no symbol has, which would silently drop everything of it
+    pub fn validate(&self) -> Result<(), String> {
+        match self.types.iter().find(|name| !SymbolType::all().iter().any(|symbol_type| symbol_type.as_str() == name.as_str())) {
+            Some(name) => Err(format!("Unknown symbol type '{}' in symbol filter", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns true if `symbol` belongs in the index
+    pub fn keeps(&self, symbol: &ExtractedSymbol) -> bool {
+        if !self.types.is_empty() && !self.types.iter().any(|name| name == symbol.symbol_type.as_str()) {
+            return false;
+        }
+        if self.exclude_names.iter().any(|pattern| wildcard_match(pattern, &symbol.name)) {
+            return false;
+        }
+        let scope = symbol.namespace_path.join("::");
+        !self.exclude_scopes.iter().any(|pattern| wildcard_match(pattern, &scope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn symbol(name: &str, symbol_type: SymbolType, scope: &[&str]) -> ExtractedSymbol {
+        ExtractedSymbol {
+            name: name.to_string(),
+            symbol_type,
+            visibility: None,
+            file_path: PathBuf::from("src/codec.cpp"),
+            start_line: 1,
+            end_line: 1,
+            start_column: 1,
+            end_column: 1,
+            content: name.to_string(),
+            fully_qualified_name: name.to_string(),
+            namespace_path: scope.iter().map(|part| part.to_string()).collect(),
+            dependencies: Vec::new(),
+            template_parameters: Vec::new(),
+            base_classes: Vec::new(),
+            member_functions: Vec::new(),
+            member_variables: Vec::new(),
+            signature: None,
+            documentation: None,
+            is_definition: true,
+            is_declaration: false,
+            memory_section: None,
+            usr: None,
+        }
+    }
+
+    #[test]
+    fn test_symbol_filter() {
+        let filter = SymbolFilter {
+            exclude_names: vec!["_*".to_string()],
+            exclude_scopes: vec!["*::detail".to_string()],
+            types: vec!["function".to_string(), "class".to_string()],
+        };
+        assert!(filter.validate().is_ok());
+        assert!(filter.keeps(&symbol("decode", SymbolType::Function, &["codec"])));
+        assert!(!filter.keeps(&symbol("_decode", SymbolType::Function, &["codec"])));
+        assert!(!filter.keeps(&symbol("unpack", SymbolType::Function, &["codec", "detail"])));
+        assert!(!filter.keeps(&symbol("frames", SymbolType::Variable, &["codec"])));
+        assert!(SymbolFilter::default().is_empty());
+
+        let typo = SymbolFilter { types: vec!["fucntion".to_string()], ..Default::default() };
+        assert!(typo.validate().is_err());
+    }
+}
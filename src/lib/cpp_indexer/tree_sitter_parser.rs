@@ -1,11 +1,127 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::path::Path;
 use tokio::fs;
 use tree_sitter::{Language, Parser, Query, QueryCursor, Tree};
 
-extern "C" {
-    fn tree_sitter_cpp() -> Language;
-}
+use crate::lib::cpp_indexer::language::SourceLanguage;
+
+const CPP_SYMBOLS_QUERY: &str = r#"
+    (class_specifier
+      name: (type_identifier) @class.name) @class.definition
+
+    (struct_specifier
+      name: (type_identifier) @struct.name) @struct.definition
+
+    (function_definition
+      declarator: [
+        (function_declarator
+          declarator: (identifier) @function.name)
+        (function_declarator
+          declarator: (qualified_identifier
+            name: (identifier) @function.name))
+      ]) @function.definition
+
+    (declaration
+      declarator: [
+        (function_declarator
+          declarator: (identifier) @function.name)
+        (function_declarator
+          declarator: (qualified_identifier
+            name: (identifier) @function.name))
+      ]) @function.declaration
+
+    (field_declaration
+      declarator: (field_identifier) @field.name) @field.definition
+
+    (declaration
+      declarator: (init_declarator
+        declarator: (identifier) @variable.name)) @variable.definition
+
+    (enum_specifier
+      name: (type_identifier) @enum.name) @enum.definition
+
+    (enumerator
+      name: (identifier) @enum.member.name) @enum.member.definition
+
+    (namespace_definition
+      name: (namespace_identifier) @namespace.name) @namespace.definition
+
+    (using_declaration
+      (qualified_identifier
+        name: (identifier) @using.name)) @using.declaration
+
+    (type_definition
+      declarator: (type_identifier) @typedef.name) @typedef.definition
+
+    (template_declaration
+      [
+        (class_specifier
+          name: (type_identifier) @template.class.name)
+        (function_definition
+          declarator: (function_declarator
+            declarator: (identifier) @template.function.name))
+      ]) @template.definition
+    "#;
+
+/// C has no classes, namespaces or templates, but has unions
+const C_SYMBOLS_QUERY: &str = r#"
+    (struct_specifier
+      name: (type_identifier) @struct.name) @struct.definition
+
+    (union_specifier
+      name: (type_identifier) @union.name) @union.definition
+
+    (function_definition
+      declarator: (function_declarator
+        declarator: (identifier) @function.name)) @function.definition
+
+    (declaration
+      declarator: (function_declarator
+        declarator: (identifier) @function.name)) @function.declaration
+
+    (field_declaration
+      declarator: (field_identifier) @field.name) @field.definition
+
+    (declaration
+      declarator: (init_declarator
+        declarator: (identifier) @variable.name)) @variable.definition
+
+    (enum_specifier
+      name: (type_identifier) @enum.name) @enum.definition
+
+    (enumerator
+      name: (identifier) @enum.member.name) @enum.member.definition
+
+    (type_definition
+      declarator: (type_identifier) @typedef.name) @typedef.definition
+    "#;
+
+/// Objective-C interfaces, implementations, protocols and methods, on top of C
+const OBJC_SYMBOLS_QUERY: &str = r#"
+    (class_interface
+      . (identifier) @class.name) @class.definition
+
+    (class_implementation
+      . (identifier) @class.name) @class.definition
+
+    (protocol_declaration
+      . (identifier) @class.name) @class.definition
+
+    (method_declaration
+      (identifier) @function.name) @function.declaration
+
+    (method_definition
+      (identifier) @function.name) @function.definition
+    "#;
+
+const INCLUDES_QUERY: &str = r#"
+    (preproc_include
+      path: [
+        (string_literal) @include.path
+        (system_lib_string) @include.system_path
+      ]) @include.directive
+    "#;
 
 #[derive(Debug, Clone)]
 pub struct ParsedNode {
@@ -20,97 +136,57 @@ pub struct ParsedNode {
     pub text: String,
 }
 
+/// A tree-sitter grammar and the queries run over its trees
+struct Grammar {
+    language: Language,
+    symbols_query: Query,
+    includes_query: Query,
+}
+
+impl Grammar {
+    /// Loads the grammar parsing `language`
+    ///
+    /// No grammar covers Objective-C++, so it is parsed as C++: its C++ half
+    /// is indexed syntactically and libclang fills in the rest.
+    fn load(language: SourceLanguage) -> Result<Self, Box<dyn std::error::Error>> {
+        let (grammar, symbols) = match language {
+            SourceLanguage::Cpp | SourceLanguage::ObjectiveCpp => (tree_sitter_cpp::language(), CPP_SYMBOLS_QUERY.to_string()),
+            SourceLanguage::C => (tree_sitter_c::language(), C_SYMBOLS_QUERY.to_string()),
+            SourceLanguage::ObjectiveC => (tree_sitter_objc::language(), format!("{}{}", C_SYMBOLS_QUERY, OBJC_SYMBOLS_QUERY)),
+        };
+        Ok(Self {
+            language: grammar,
+            symbols_query: Query::new(grammar, &symbols)?,
+            includes_query: Query::new(grammar, INCLUDES_QUERY)?,
+        })
+    }
+}
+
+/// Grammar shared by a language: Objective-C++ uses C++'s
+fn grammar_key(language: SourceLanguage) -> SourceLanguage {
+    match language {
+        SourceLanguage::ObjectiveCpp => SourceLanguage::Cpp,
+        language => language,
+    }
+}
+
 pub struct TreeSitterParser {
     parser: Parser,
     query_cursor: QueryCursor,
-    symbols_query: Query,
-    includes_query: Query,
+    /// Grammars loaded so far: C++ up front, the others on their first file
+    grammars: HashMap<SourceLanguage, Grammar>,
 }
 
 impl TreeSitterParser {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let language = unsafe { tree_sitter_cpp() };
+        let cpp = Grammar::load(SourceLanguage::Cpp)?;
         let mut parser = Parser::new();
-        parser.set_language(language)?;
-
-        let symbols_query = Query::new(
-            language,
-            r#"
-            (class_specifier
-              name: (type_identifier) @class.name) @class.definition
-
-            (struct_specifier
-              name: (type_identifier) @struct.name) @struct.definition
-
-            (function_definition
-              declarator: [
-                (function_declarator
-                  declarator: (identifier) @function.name)
-                (function_declarator
-                  declarator: (qualified_identifier
-                    name: (identifier) @function.name))
-              ]) @function.definition
-
-            (declaration
-              declarator: [
-                (function_declarator
-                  declarator: (identifier) @function.name)
-                (function_declarator
-                  declarator: (qualified_identifier
-                    name: (identifier) @function.name))
-              ]) @function.declaration
-
-            (field_declaration
-              declarator: (field_declarator
-                declarator: (identifier) @field.name)) @field.definition
-
-            (declaration
-              declarator: (init_declarator
-                declarator: (identifier) @variable.name)) @variable.definition
-
-            (enum_specifier
-              name: (type_identifier) @enum.name) @enum.definition
-
-            (enumerator
-              name: (identifier) @enum.member.name) @enum.member.definition
-
-            (namespace_definition
-              name: (identifier) @namespace.name) @namespace.definition
-
-            (using_declaration
-              (qualified_identifier
-                name: (identifier) @using.name)) @using.declaration
-
-            (type_definition
-              declarator: (type_identifier) @typedef.name) @typedef.definition
-
-            (template_declaration
-              [
-                (class_specifier
-                  name: (type_identifier) @template.class.name)
-                (function_definition
-                  declarator: (function_declarator
-                    declarator: (identifier) @template.function.name))
-              ]) @template.definition
-            "#,
-        )?;
-
-        let includes_query = Query::new(
-            language,
-            r#"
-            (preproc_include
-              path: [
-                (string_literal) @include.path
-                (system_lib_string) @include.system_path
-              ]) @include.directive
-            "#,
-        )?;
+        parser.set_language(cpp.language)?;
 
         Ok(Self {
             parser,
             query_cursor: QueryCursor::new(),
-            symbols_query,
-            includes_query,
+            grammars: HashMap::from([(SourceLanguage::Cpp, cpp)]),
         })
     }
 
@@ -119,11 +195,18 @@ impl TreeSitterParser {
         self.parse_content(&content, file_path)
     }
 
+    /// Parses `content` with the grammar of `file_path`'s language
     pub fn parse_content(&mut self, content: &str, file_path: &Path) -> Result<ParseResult, Box<dyn std::error::Error>> {
+        let key = grammar_key(SourceLanguage::from_path(file_path));
+        if let Entry::Vacant(entry) = self.grammars.entry(key) {
+            entry.insert(Grammar::load(key)?);
+        }
+        let grammar = &self.grammars[&key];
+        self.parser.set_language(grammar.language)?;
         let tree = self.parser.parse(content, None).ok_or("Failed to parse content")?;
         
-        let symbols = self.extract_symbols(&tree, content)?;
-        let includes = self.extract_includes(&tree, content)?;
+        let symbols = Self::extract_symbols(&mut self.query_cursor, &grammar.symbols_query, &tree, content);
+        let includes = Self::extract_includes(&mut self.query_cursor, &grammar.includes_query, &tree, content);
         
         Ok(ParseResult {
             file_path: file_path.to_path_buf(),
@@ -134,14 +217,14 @@ impl TreeSitterParser {
         })
     }
 
-    fn extract_symbols(&mut self, tree: &Tree, content: &str) -> Result<Vec<ParsedNode>, Box<dyn std::error::Error>> {
+    fn extract_symbols(query_cursor: &mut QueryCursor, symbols_query: &Query, tree: &Tree, content: &str) -> Vec<ParsedNode> {
         let mut symbols = Vec::new();
-        let captures = self.query_cursor.matches(&self.symbols_query, tree.root_node(), content.as_bytes());
+        let captures = query_cursor.matches(symbols_query, tree.root_node(), content.as_bytes());
 
         for match_ in captures {
             for capture in match_.captures {
                 let node = capture.node;
-                let capture_name = &self.symbols_query.capture_names()[capture.index as usize];
+                let capture_name = &symbols_query.capture_names()[capture.index as usize];
                 
                 let text = node.utf8_text(content.as_bytes()).unwrap_or("");
                 
@@ -161,15 +244,20 @@ impl TreeSitterParser {
             }
         }
         
-        Ok(symbols)
+        symbols
     }
 
-    fn extract_includes(&mut self, tree: &Tree, content: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    fn extract_includes(query_cursor: &mut QueryCursor, includes_query: &Query, tree: &Tree, content: &str) -> Vec<String> {
         let mut includes = Vec::new();
-        let captures = self.query_cursor.matches(&self.includes_query, tree.root_node(), content.as_bytes());
+        let captures = query_cursor.matches(includes_query, tree.root_node(), content.as_bytes());
 
+        let names = includes_query.capture_names();
         for match_ in captures {
             for capture in match_.captures {
+                // The whole directive is captured too; only its path is wanted
+                if names[capture.index as usize] == "include.directive" {
+                    continue;
+                }
                 let node = capture.node;
                 let text = node.utf8_text(content.as_bytes()).unwrap_or("");
                 
@@ -178,7 +266,7 @@ impl TreeSitterParser {
             }
         }
         
-        Ok(includes)
+        includes
     }
 
     pub fn get_node_at_position(&self, tree: &Tree, content: &str, line: usize, column: usize) -> Option<ParsedNode> {
@@ -308,4 +396,34 @@ int main() {
         assert!(parse_result.includes.contains(&"iostream".to_string()));
         assert!(parse_result.includes.contains(&"local_header.h".to_string()));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_parse_by_language() {
+        let mut parser = TreeSitterParser::new().expect("Failed to create parser");
+        let c = r#"
+#include <stdint.h>
+
+union sample { int16_t s16; float f32; };
+typedef struct codec codec_t;
+int codec_decode(codec_t *codec, const uint8_t *data);
+"#;
+        let result = parser.parse_content(c, &PathBuf::from("src/codec.c")).unwrap();
+        assert_eq!(result.includes, ["stdint.h"]);
+        assert!(!result.get_symbols_by_type("union").is_empty());
+        assert!(result.get_symbols_by_type("function").iter().any(|symbol| symbol.text == "codec_decode"));
+
+        let objc = r#"
+#include "codec.h"
+
+@interface Player : NSObject
+- (void)play;
+@end
+"#;
+        let result = parser.parse_content(objc, &PathBuf::from("ios/Player.m")).unwrap();
+        assert!(result.get_symbols_by_type("class").iter().any(|symbol| symbol.text == "Player"));
+
+        // Objective-C++ goes through the C++ grammar
+        let result = parser.parse_content("class Bridge {};", &PathBuf::from("ios/Bridge.mm")).unwrap();
+        assert!(result.get_symbols_by_type("class").iter().any(|symbol| symbol.text == "Bridge"));
+    }
+}
@@ -0,0 +1,338 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::path_alias::PathAlias;
+use crate::lib::storage::repository::Repository;
+use crate::lib::storage::ordering::path_key;
+
+/// Smallest directory, in source files, considered a vendored copy by default
+pub const DEFAULT_MIN_DUPLICATE_FILES: usize = 3;
+
+/// A directory tree present at several paths with byte-identical contents
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct DuplicateTree {
+    /// Copy that is indexed
+    pub canonical: PathBuf,
+    /// Copies that are skipped and resolve to `canonical`
+    pub aliases: Vec<PathBuf>,
+    pub file_count: usize,
+    /// Content hash of the tree, independent of where it lives
+    pub tree_hash: String,
+}
+
+/// Settings for detecting vendored copies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VendoredDedup {
+    min_files: usize,
+    preferred_roots: Vec<PathBuf>,
+}
+
+/// Content hash and file count of one directory
+#[derive(Debug, Clone)]
+struct DirectoryHash {
+    hash: String,
+    file_count: usize,
+}
+
+impl Default for VendoredDedup {
+    fn default() -> Self {
+        Self {
+            min_files: DEFAULT_MIN_DUPLICATE_FILES,
+            preferred_roots: Vec::new(),
+        }
+    }
+}
+
+impl VendoredDedup {
+    /// Creates the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignores duplicate directories with fewer than `min_files` source files
+    pub fn with_min_files(mut self, min_files: usize) -> Self {
+        self.min_files = min_files.max(1);
+        self
+    }
+
+    /// Prefers copies under `root` (e.g. "third_party") as the indexed copy
+    pub fn prefer(mut self, root: impl Into<PathBuf>) -> Self {
+        self.preferred_roots.push(root.into());
+        self
+    }
+
+    /// Finds directories whose source files are byte-identical
+    ///
+    /// `files` maps paths relative to the index root to content hashes. A
+    /// directory's hash covers the names and hashes of everything below it,
+    /// so two directories match only if their whole subtrees do. Only the
+    /// outermost duplicates are reported; their subdirectories resolve
+    /// through them.
+    pub fn find_duplicate_trees(&self, files: &BTreeMap<PathBuf, String>) -> Vec<DuplicateTree> {
+        let directories = directory_hashes(files);
+
+        let mut groups: HashMap<&str, Vec<&Path>> = HashMap::new();
+        for (directory, hashed) in &directories {
+            if hashed.file_count >= self.min_files {
+                groups.entry(hashed.hash.as_str()).or_default().push(directory.as_path());
+            }
+        }
+
+        // Outer directories first, so copies nested in an aliased tree are skipped
+        let mut groups: Vec<Vec<&Path>> = groups.into_values().filter(|members| members.len() > 1).collect();
+        for members in &mut groups {
+            members.sort_by_key(|path| (path.components().count(), path_key(path)));
+        }
+        groups.sort_by_key(|members| (members[0].components().count(), path_key(members[0])));
+
+        let mut duplicates: Vec<DuplicateTree> = Vec::new();
+        for members in groups {
+            let is_aliased = |path: &Path| duplicates.iter().any(|d| d.aliases.iter().any(|alias| path.starts_with(alias)));
+            let in_canonical = |path: &Path| duplicates.iter().any(|d| path.starts_with(&d.canonical));
+
+            let mut remaining: Vec<&Path> = members.into_iter().filter(|path| !is_aliased(path)).collect();
+            if remaining.len() < 2 {
+                continue;
+            }
+
+            // Preferred roots, then copies already indexed as part of another tree, then the shortest path
+            remaining.sort_by_key(|path| {
+                (
+                    !self.preferred_roots.iter().any(|root| path.starts_with(root)),
+                    !in_canonical(path),
+                    path.components().count(),
+                    path_key(path),
+                )
+            });
+            let hashed = &directories[remaining[0]];
+            duplicates.push(DuplicateTree {
+                canonical: remaining[0].to_path_buf(),
+                aliases: remaining[1..].iter().map(|path| path.to_path_buf()).collect(),
+                file_count: hashed.file_count,
+                tree_hash: hashed.hash.clone(),
+            });
+        }
+
+        duplicates
+    }
+}
+
+impl DuplicateTree {
+    /// Alias rows to store for this tree
+    pub fn to_path_aliases(&self, index_id: Uuid) -> Vec<PathAlias> {
+        self.aliases
+            .iter()
+            .map(|alias| PathAlias {
+                index_id,
+                alias_path: path_string(alias),
+                canonical_path: path_string(&self.canonical),
+                file_count: self.file_count as u64,
+                tree_hash: self.tree_hash.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Returns true if `path` lies in a skipped copy of a duplicate tree
+pub fn is_aliased(duplicates: &[DuplicateTree], path: &Path) -> bool {
+    duplicates.iter().any(|d| d.aliases.iter().any(|alias| path.starts_with(alias)))
+}
+
+/// Leaves the files of skipped vendored copies out of `files` and stores the copies as path aliases
+///
+/// `files` are the source files of `index`, relative to its base path. The
+/// aliases stored before are replaced, and files indexed earlier inside a
+/// copy that is now skipped are removed. A file that can't be read is kept,
+/// so indexing records why it failed, and matches no other copy.
+pub fn skip_vendored_copies(
+    repository: &Repository,
+    index: &CodeIndex,
+    files: Vec<String>,
+    dedup: &VendoredDedup,
+) -> Result<(Vec<String>, Vec<DuplicateTree>)> {
+    let base_path = Path::new(&index.base_path);
+    let hashes: BTreeMap<PathBuf, String> = files
+        .iter()
+        .map(|file| {
+            let hash = match std::fs::read(base_path.join(file)) {
+                Ok(content) => format!("{:x}", Sha256::digest(&content)),
+                Err(_) => format!("unreadable:{}", file),
+            };
+            (PathBuf::from(file), hash)
+        })
+        .collect();
+    let duplicates = dedup.find_duplicate_trees(&hashes);
+
+    let aliases: Vec<PathAlias> = duplicates.iter().flat_map(|duplicate| duplicate.to_path_aliases(index.id)).collect();
+    repository.replace_path_aliases(&index.id, &aliases)?;
+    for metadata in repository.list_file_metadata(&index.id)? {
+        if is_aliased(&duplicates, Path::new(&metadata.file_path)) {
+            repository.remove_file(&index.id, &metadata.file_path)?;
+        }
+    }
+
+    let files = files.into_iter().filter(|file| !is_aliased(&duplicates, Path::new(file))).collect();
+    Ok((files, duplicates))
+}
+
+/// Content hashes of every directory containing a file, except the root
+fn directory_hashes(files: &BTreeMap<PathBuf, String>) -> BTreeMap<PathBuf, DirectoryHash> {
+    // Entries of each directory by name: ('f', content hash) for files, ('d', "") for subdirectories
+    let mut entries: BTreeMap<PathBuf, BTreeMap<String, (char, String)>> = BTreeMap::new();
+    for (path, hash) in files {
+        let mut child = path.as_path();
+        let mut entry = ('f', hash.clone());
+        while let Some(parent) = child.parent() {
+            let name = child.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let siblings = entries.entry(parent.to_path_buf()).or_default();
+            let known = siblings.contains_key(&name);
+            siblings.insert(name, entry);
+            if known {
+                break;
+            }
+            child = parent;
+            entry = ('d', String::new());
+        }
+    }
+
+    // Deepest directories first, so subdirectory hashes are known when their parent is hashed
+    let mut order: Vec<PathBuf> = entries.keys().cloned().collect();
+    order.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+
+    let mut hashes: BTreeMap<PathBuf, DirectoryHash> = BTreeMap::new();
+    for directory in order {
+        let mut hasher = Sha256::new();
+        let mut file_count = 0;
+        for (name, (kind, hash)) in &entries[&directory] {
+            let hash = match kind {
+                'd' => {
+                    let sub = &hashes[&directory.join(name)];
+                    file_count += sub.file_count;
+                    sub.hash.clone()
+                }
+                _ => {
+                    file_count += 1;
+                    hash.clone()
+                }
+            };
+            hasher.update(kind.to_string().as_bytes());
+            hasher.update(name.as_bytes());
+            hasher.update([0u8]);
+            hasher.update(hash.as_bytes());
+            hasher.update([0u8]);
+        }
+        hashes.insert(directory, DirectoryHash {
+            hash: format!("{:x}", hasher.finalize()),
+            file_count,
+        });
+    }
+
+    hashes.remove(Path::new(""));
+    hashes
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(files: &[(&str, &str)]) -> BTreeMap<PathBuf, String> {
+        files.iter().map(|(path, hash)| (PathBuf::from(path), hash.to_string())).collect()
+    }
+
+    #[test]
+    fn test_find_duplicate_trees() {
+        let files = tree(&[
+            ("third_party/zlib/zlib.h", "h1"),
+            ("third_party/zlib/inflate.c", "c1"),
+            ("third_party/zlib/contrib/minizip.c", "c2"),
+            ("third_party/json/json.hpp", "j"),
+            ("external/zlib/zlib.h", "h1"),
+            ("external/zlib/inflate.c", "c1"),
+            ("external/zlib/contrib/minizip.c", "c2"),
+            // Same names, one different file
+            ("vendor/zlib/zlib.h", "h1"),
+            ("vendor/zlib/inflate.c", "patched"),
+            ("vendor/zlib/contrib/minizip.c", "c2"),
+            ("src/main.cpp", "m"),
+        ]);
+
+        let duplicates = VendoredDedup::new().with_min_files(2).find_duplicate_trees(&files);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].canonical, PathBuf::from("external/zlib"));
+        assert_eq!(duplicates[0].aliases, vec![PathBuf::from("third_party/zlib")]);
+        assert_eq!(duplicates[0].file_count, 3);
+        assert!(is_aliased(&duplicates, Path::new("third_party/zlib/contrib/minizip.c")));
+        assert!(!is_aliased(&duplicates, Path::new("vendor/zlib/contrib/minizip.c")));
+
+        // Preference picks the indexed copy; small trees are ignored
+        let preferred = VendoredDedup::new().with_min_files(2).prefer("third_party").find_duplicate_trees(&files);
+        assert_eq!(preferred[0].canonical, PathBuf::from("third_party/zlib"));
+        assert!(VendoredDedup::new().with_min_files(4).find_duplicate_trees(&files).is_empty());
+    }
+
+    #[test]
+    fn test_nested_copies_resolve_through_outer_tree() {
+        let files = tree(&[
+            ("a/lib/x.h", "x"),
+            ("a/lib/y.h", "y"),
+            ("a/z.h", "z"),
+            ("b/lib/x.h", "x"),
+            ("b/lib/y.h", "y"),
+            ("b/z.h", "z"),
+            ("c/lib/x.h", "x"),
+            ("c/lib/y.h", "y"),
+        ]);
+
+        let duplicates = VendoredDedup::new().with_min_files(2).find_duplicate_trees(&files);
+        // a and b are whole copies; c/lib matches a/lib, which is indexed as part of a
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!((duplicates[0].canonical.as_path(), duplicates[0].aliases[0].as_path()), (Path::new("a"), Path::new("b")));
+        assert_eq!((duplicates[1].canonical.as_path(), duplicates[1].aliases[0].as_path()), (Path::new("a/lib"), Path::new("c/lib")));
+    }
+
+    #[test]
+    fn test_skip_vendored_copies() {
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+        use crate::lib::storage::models::file_metadata::FileMetadata;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut files = Vec::new();
+        for root in ["third_party/zlib", "external/zlib"] {
+            for (name, content) in [("zlib.h", "int inflate();"), ("inflate.c", "int inflate() { return 0; }"), ("deflate.c", "int deflate();")] {
+                let path = dir.path().join(root).join(name);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+                files.push(format!("{}/{}", root, name));
+            }
+        }
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.cpp"), "int main() {}").unwrap();
+        files.push("src/main.cpp".to_string());
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("vendored".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        // Indexed before the copy was recognized
+        repository
+            .create_file_metadata(FileMetadata::new(index.id, "external/zlib/zlib.h".to_string(), "a".repeat(64), chrono::Utc::now(), 14))
+            .unwrap();
+
+        let dedup = VendoredDedup::new().prefer("third_party");
+        let (kept, duplicates) = skip_vendored_copies(&repository, &index, files, &dedup).unwrap();
+        assert_eq!(kept, ["third_party/zlib/zlib.h", "third_party/zlib/inflate.c", "third_party/zlib/deflate.c", "src/main.cpp"]);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(repository.resolve_path_alias(&index.id, "external/zlib/inflate.c").unwrap(), "third_party/zlib/inflate.c");
+        assert!(repository.list_file_metadata(&index.id).unwrap().is_empty());
+    }
+}
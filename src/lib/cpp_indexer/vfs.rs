@@ -0,0 +1,552 @@
+// Virtual source filesystems
+//
+// Lets the indexer walk and hash sources that don't live in a checked-out
+// directory, such as an SDK shipped as a tarball. Files inside an archive are
+// stored in the index as `tar:<archive path>!/<entry path>` so snippets can be
+// read back from the archive on demand.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// URI scheme of files stored inside a tar archive
+pub const TAR_SCHEME: &str = "tar:";
+
+/// Extensions of files the indexer parses
+pub const SOURCE_EXTENSIONS: &[&str] = &["cpp", "cxx", "cc", "c", "m", "mm", "cu", "ixx", "cppm", "hpp", "hxx", "h", "cuh"];
+
+const BLOCK_SIZE: u64 = 512;
+
+/// A read-only tree of source files
+pub trait SourceFs: Send + Sync + Debug {
+    /// Paths of all regular files, relative to the root, sorted
+    fn list_files(&self) -> io::Result<Vec<String>>;
+
+    /// Contents of a file listed by `list_files`
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Path stored in the index for a file listed by `list_files`
+    fn stored_path(&self, path: &str) -> String;
+}
+
+/// Sources in a directory on disk
+#[derive(Debug, Clone)]
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+/// Sources in an uncompressed tar archive
+///
+/// Only the headers are read when the archive is opened; file contents are
+/// read from their offsets as requested.
+#[derive(Debug, Clone)]
+pub struct TarFs {
+    archive: PathBuf,
+    /// Entry path -> (data offset, size)
+    entries: BTreeMap<String, (u64, u64)>,
+}
+
+impl LocalFs {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl SourceFs for LocalFs {
+    fn list_files(&self) -> io::Result<Vec<String>> {
+        let mut files = Vec::new();
+        for entry in WalkDir::new(&self.root).follow_links(false) {
+            let entry = entry.map_err(io::Error::from)?;
+            if entry.file_type().is_file() {
+                let relative = entry.path().strip_prefix(&self.root).unwrap_or(entry.path());
+                files.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.root.join(path))
+    }
+
+    fn stored_path(&self, path: &str) -> String {
+        path.to_string()
+    }
+}
+
+impl TarFs {
+    /// Reads the entry table of a tar archive
+    pub fn open<P: AsRef<Path>>(archive: P) -> io::Result<Self> {
+        let archive = archive.as_ref().to_path_buf();
+        let mut entries = BTreeMap::new();
+        scan_tar(&archive, |path, offset, size| {
+            entries.insert(path, (offset, size));
+            false
+        })?;
+        Ok(Self { archive, entries })
+    }
+
+    /// Path of the archive on disk
+    pub fn archive(&self) -> &Path {
+        &self.archive
+    }
+}
+
+impl SourceFs for TarFs {
+    fn list_files(&self) -> io::Result<Vec<String>> {
+        Ok(self.entries.keys().cloned().collect())
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let (offset, size) = self.entries.get(path).copied().ok_or_else(|| missing_entry(&self.archive, path))?;
+        read_range(&self.archive, offset, size)
+    }
+
+    fn stored_path(&self, path: &str) -> String {
+        archive_uri(&self.archive, path)
+    }
+}
+
+/// Opens a directory or archive as a source filesystem
+///
+/// Compressed archives aren't supported; decompress them first
+/// (e.g. `gunzip sdk.tar.gz`) and index the `.tar`.
+pub fn open_source_fs<P: AsRef<Path>>(location: P) -> io::Result<Box<dyn SourceFs>> {
+    let location = location.as_ref();
+    if location.is_dir() {
+        return Ok(Box::new(LocalFs::new(location)));
+    }
+
+    let name = location.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.ends_with(".tar") {
+        return Ok(Box::new(TarFs::open(location)?));
+    }
+    if [".tar.gz", ".tgz", ".tar.xz", ".tar.bz2", ".tar.zst", ".zip"].iter().any(|ext| name.ends_with(ext)) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Compressed archive {} is not supported; decompress it to a .tar first", location.display()),
+        ));
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is neither a directory nor a .tar archive", location.display()),
+    ))
+}
+
+/// Returns true if the indexer parses files with this path's extension
+pub fn is_source_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .map(|extension| SOURCE_EXTENSIONS.iter().any(|ext| extension == *ext))
+        .unwrap_or(false)
+}
+
+/// Matches a gitignore-style pattern, as in CODEOWNERS, against a relative path
+///
+/// A pattern containing a '/' other than a trailing one is anchored at the
+/// root; otherwise it may match at any depth. A pattern matching a directory
+/// covers everything below it.
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    let directory_only = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.is_empty() {
+        return false;
+    }
+
+    let components: Vec<&str> = path.split('/').collect();
+    // Prefixes of the path that a pattern may match: the file itself and every directory above it
+    let mut candidates = (1..=components.len()).filter(|&end| !directory_only || end < components.len());
+    if anchored {
+        candidates.any(|end| wildcard_match(pattern, &components[..end].join("/")))
+    } else {
+        candidates.any(|end| wildcard_match(pattern, components[end - 1]))
+    }
+}
+
+/// Wildcard match where '*' stays within a path component, '**' crosses them and '?' matches one character
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    // matches[j]: pattern[..i] matches text[..j]
+    let mut matches = vec![false; text.len() + 1];
+    matches[0] = true;
+    let mut i = 0;
+    while i < pattern.len() {
+        let mut next = vec![false; text.len() + 1];
+        match pattern[i] {
+            '*' if pattern.get(i + 1) == Some(&'*') => {
+                let mut reachable = false;
+                for j in 0..=text.len() {
+                    reachable |= matches[j];
+                    next[j] = reachable;
+                }
+                i += 1;
+            }
+            '*' => {
+                let mut reachable = false;
+                for j in 0..=text.len() {
+                    if j > 0 && text[j - 1] == '/' {
+                        reachable = false;
+                    }
+                    reachable |= matches[j];
+                    next[j] = reachable;
+                }
+            }
+            '?' => {
+                for j in 1..=text.len() {
+                    next[j] = matches[j - 1] && text[j - 1] != '/';
+                }
+            }
+            c => {
+                for j in 1..=text.len() {
+                    next[j] = matches[j - 1] && text[j - 1] == c;
+                }
+            }
+        }
+        matches = next;
+        i += 1;
+    }
+    matches[text.len()]
+}
+
+/// Stored path of an entry inside a tar archive
+pub fn archive_uri(archive: &Path, entry: &str) -> String {
+    format!("{}{}!/{}", TAR_SCHEME, archive.to_string_lossy().replace('\\', "/"), entry)
+}
+
+/// Splits a stored `tar:` path into the archive path and entry path
+pub fn parse_archive_uri(stored_path: &str) -> Option<(PathBuf, &str)> {
+    let (archive, entry) = stored_path.strip_prefix(TAR_SCHEME)?.split_once("!/")?;
+    if archive.is_empty() || entry.is_empty() {
+        return None;
+    }
+    Some((PathBuf::from(archive), entry))
+}
+
+/// Reads a file by its stored path
+///
+/// `tar:` paths are read from their archive; anything else is relative to
+/// the index base path.
+pub fn read_source(base_path: &Path, stored_path: &str) -> io::Result<String> {
+    let bytes = match parse_archive_uri(stored_path) {
+        Some((archive, entry)) => {
+            let mut found = None;
+            scan_tar(&archive, |path, offset, size| {
+                if path == entry {
+                    found = Some((offset, size));
+                }
+                found.is_some()
+            })?;
+            let (offset, size) = found.ok_or_else(|| missing_entry(&archive, entry))?;
+            read_range(&archive, offset, size)?
+        }
+        None => std::fs::read(base_path.join(stored_path))?,
+    };
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Walks the headers of a tar archive, calling `visit(path, data offset, size)`
+/// for each regular file until it returns true
+///
+/// Handles ustar prefixes, GNU long names and pax `path` records.
+fn scan_tar(archive: &Path, mut visit: impl FnMut(String, u64, u64) -> bool) -> io::Result<()> {
+    let mut file = File::open(archive)?;
+    let length = file.metadata()?.len();
+    let mut header = [0u8; BLOCK_SIZE as usize];
+    let mut offset = 0;
+    let mut long_name: Option<String> = None;
+
+    while offset + BLOCK_SIZE <= length {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if !checksum_matches(&header) {
+            return Err(invalid_archive(archive, offset));
+        }
+
+        let size = header_size(&header).ok_or_else(|| invalid_archive(archive, offset))?;
+        let data_offset = offset + BLOCK_SIZE;
+        if data_offset + size > length {
+            return Err(invalid_archive(archive, offset));
+        }
+
+        match header[156] {
+            b'L' => {
+                let name = read_range(archive, data_offset, size)?;
+                long_name = Some(c_string(&name));
+            }
+            b'x' => {
+                let records = read_range(archive, data_offset, size)?;
+                if let Some(path) = pax_path(&records) {
+                    long_name = Some(path);
+                }
+            }
+            b'0' | b'\0' | b'7' => {
+                let path = long_name.take().unwrap_or_else(|| header_path(&header));
+                let path = path.trim_start_matches("./").to_string();
+                if !path.is_empty() && visit(path, data_offset, size) {
+                    return Ok(());
+                }
+            }
+            _ => long_name = None,
+        }
+
+        offset = data_offset + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    }
+
+    Ok(())
+}
+
+fn read_range(archive: &Path, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(archive)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0u8; size as usize];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Name of a ustar header, joined with its prefix
+fn header_path(header: &[u8]) -> String {
+    let name = c_string(&header[0..100]);
+    // GNU tar uses the prefix field for other data and marks itself with "ustar  "
+    if &header[257..263] == b"ustar\0" {
+        let prefix = c_string(&header[345..500]);
+        if !prefix.is_empty() {
+            return format!("{}/{}", prefix, name);
+        }
+    }
+    name
+}
+
+/// Entry size: octal text, or big-endian base-256 when the high bit is set
+fn header_size(header: &[u8]) -> Option<u64> {
+    let field = &header[124..136];
+    if field[0] & 0x80 != 0 {
+        return Some(field[1..].iter().fold(0u64, |size, &b| (size << 8) | b as u64));
+    }
+    let text = c_string(field);
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Header checksum: the byte sum with the checksum field read as spaces
+fn checksum_matches(header: &[u8]) -> bool {
+    let expected = c_string(&header[148..156]);
+    let Ok(expected) = u64::from_str_radix(expected.trim(), 8) else { return false };
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 })
+        .sum();
+    sum == expected
+}
+
+/// `path` value of pax extended header records ("<length> <key>=<value>\n")
+fn pax_path(records: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(records);
+    text.lines().find_map(|record| {
+        let (_, pair) = record.split_once(' ')?;
+        pair.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+fn invalid_archive(archive: &Path, offset: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is not a valid tar archive (bad header at byte {})", archive.display(), offset),
+    )
+}
+
+fn missing_entry(archive: &Path, entry: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} has no entry {}", archive.display(), entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_header(name: &str, typeflag: u8, size: usize) -> Vec<u8> {
+        let mut header = vec![0u8; BLOCK_SIZE as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header
+    }
+
+    fn append(tar: &mut Vec<u8>, name: &str, typeflag: u8, data: &[u8]) {
+        tar.extend(tar_header(name, typeflag, data.len()));
+        tar.extend_from_slice(data);
+        tar.resize(tar.len().div_ceil(BLOCK_SIZE as usize) * BLOCK_SIZE as usize, 0);
+    }
+
+    fn write_sdk_archive(dir: &Path) -> PathBuf {
+        let long_name = format!("sdk/include/{}/deep.h", "nested".repeat(20));
+        let mut tar = Vec::new();
+        append(&mut tar, "./sdk/", b'5', b"");
+        append(&mut tar, "./sdk/include/audio.h", b'0', b"#pragma once\nvoid play();\n");
+        append(&mut tar, "././@LongLink", b'L', format!("{}\0", long_name).as_bytes());
+        append(&mut tar, "ignored", b'0', b"int deep;\n");
+        append(&mut tar, "sdk/src/audio.cpp", b'0', b"#include \"audio.h\"\nvoid play() {}\n");
+        tar.extend(vec![0u8; 2 * BLOCK_SIZE as usize]);
+
+        let path = dir.join("sdk.tar");
+        std::fs::write(&path, tar).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_tar_fs_lists_and_reads_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = write_sdk_archive(dir.path());
+
+        let fs = open_source_fs(&archive).unwrap();
+        let files = fs.list_files().unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.contains(&"sdk/include/audio.h".to_string()));
+        assert!(files.iter().any(|file| file.ends_with("/deep.h")));
+        assert_eq!(fs.read("sdk/src/audio.cpp").unwrap(), b"#include \"audio.h\"\nvoid play() {}\n");
+        assert!(fs.read("sdk/missing.h").is_err());
+
+        // Stored paths read back through the archive
+        let stored = fs.stored_path("sdk/include/audio.h");
+        assert!(stored.starts_with("tar:") && stored.ends_with("sdk.tar!/sdk/include/audio.h"));
+        assert_eq!(parse_archive_uri(&stored).unwrap().1, "sdk/include/audio.h");
+        assert_eq!(read_source(Path::new("/unused"), &stored).unwrap(), "#pragma once\nvoid play();\n");
+    }
+
+    #[test]
+    fn test_open_source_fs_rejects_other_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.cpp"), "int main() {}\n").unwrap();
+        std::fs::write(dir.path().join("sdk.tgz"), b"\x1f\x8b").unwrap();
+        std::fs::write(dir.path().join("bad.tar"), vec![1u8; 1024]).unwrap();
+
+        let local = open_source_fs(dir.path()).unwrap();
+        assert!(local.list_files().unwrap().contains(&"src/main.cpp".to_string()));
+        assert_eq!(read_source(dir.path(), "src/main.cpp").unwrap(), "int main() {}\n");
+
+        let compressed = open_source_fs(dir.path().join("sdk.tgz")).unwrap_err();
+        assert_eq!(compressed.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(open_source_fs(dir.path().join("bad.tar")).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(is_source_file("a/b.hpp") && !is_source_file("README.md"));
+    }
+
+    #[test]
+    fn test_tar_fs_reads_pax_and_prefixed_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tar = Vec::new();
+        let pax_name = "sdk/include/a very long directory name/mixer.h";
+        let record = format!("path={}\n", pax_name);
+        let record = format!("{} {}", record.len() + 3, record);
+        append(&mut tar, "PaxHeaders/mixer.h", b'x', record.as_bytes());
+        append(&mut tar, "mixer.h", b'0', b"void mix();\n");
+        // ustar splits long paths into a prefix and a name
+        let mut header = tar_header("player.h", b'0', 12);
+        header[345..355].copy_from_slice(b"sdk/player");
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        tar.extend(header);
+        tar.extend_from_slice(b"void play();");
+        tar.resize(tar.len().div_ceil(BLOCK_SIZE as usize) * BLOCK_SIZE as usize, 0);
+        tar.extend(vec![0u8; 2 * BLOCK_SIZE as usize]);
+        let archive = dir.path().join("sdk.tar");
+        std::fs::write(&archive, &tar).unwrap();
+
+        let fs = TarFs::open(&archive).unwrap();
+        assert_eq!(fs.list_files().unwrap(), [pax_name, "sdk/player/player.h"]);
+        assert_eq!(fs.read("sdk/player/player.h").unwrap(), b"void play();");
+        assert_eq!(fs.archive(), archive);
+
+        // An entry running past the end of the file
+        std::fs::write(&archive, &tar[..BLOCK_SIZE as usize * 5 + 5]).unwrap();
+        assert_eq!(TarFs::open(&archive).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_header_size_reads_octal_and_base_256() {
+        let mut header = tar_header("big.h", b'0', 0o17);
+        assert_eq!(header_size(&header), Some(0o17));
+        header[124..136].copy_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(header_size(&header), Some(2 << 24));
+        header[124..136].copy_from_slice(b"12x4\0\0\0\0\0\0\0\0");
+        assert_eq!(header_size(&header), None);
+    }
+
+    #[test]
+    fn test_archive_uris_round_trip() {
+        let uri = archive_uri(Path::new("/sdk/sdk.tar"), "include/audio.h");
+        assert_eq!(uri, "tar:/sdk/sdk.tar!/include/audio.h");
+        assert_eq!(parse_archive_uri(&uri), Some((PathBuf::from("/sdk/sdk.tar"), "include/audio.h")));
+        assert_eq!(parse_archive_uri("src/audio.cpp"), None);
+        assert_eq!(parse_archive_uri("tar:/sdk/sdk.tar"), None);
+        assert_eq!(parse_archive_uri("tar:!/include/audio.h"), None);
+    }
+
+    #[test]
+    fn test_local_fs_lists_sorted_relative_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["src/b.cpp", "src/a.cpp", "include/a.h"] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+        let fs = LocalFs::new(dir.path());
+        assert_eq!(fs.list_files().unwrap(), ["include/a.h", "src/a.cpp", "src/b.cpp"]);
+        assert_eq!(fs.read("src/b.cpp").unwrap(), b"src/b.cpp");
+        assert_eq!(fs.stored_path("src/b.cpp"), "src/b.cpp");
+        assert_eq!(read_source(dir.path(), "src/missing.cpp").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        // Unanchored patterns match a component at any depth, and everything below it
+        assert!(pattern_matches("*.h", "include/audio/mixer.h"));
+        assert!(pattern_matches("audio", "include/audio/mixer.h"));
+        assert!(!pattern_matches("audio", "include/audiox/mixer.h"));
+        // Anchored patterns match from the root
+        assert!(pattern_matches("/include/audio", "include/audio/mixer.h"));
+        assert!(!pattern_matches("audio/*.h", "include/audio/mixer.h"));
+        assert!(pattern_matches("include/**/*.h", "include/audio/dsp/mixer.h"));
+        // Directory patterns don't match files
+        assert!(pattern_matches("audio/", "include/audio/mixer.h"));
+        assert!(!pattern_matches("mixer.h/", "include/audio/mixer.h"));
+        assert!(!pattern_matches("/", "include/audio/mixer.h"));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("src/*.cpp", "src/main.cpp"));
+        assert!(!wildcard_match("src/*.cpp", "src/audio/main.cpp"));
+        assert!(wildcard_match("src/**.cpp", "src/audio/main.cpp"));
+        assert!(wildcard_match("mix?r.h", "mixer.h"));
+        assert!(!wildcard_match("a?b", "a/b"));
+        assert!(wildcard_match("**", ""));
+        assert!(!wildcard_match("main.cpp", "main.cc"));
+    }
+}
@@ -0,0 +1,300 @@
+// Directory walk filtering
+//
+// Decides which files under an index's base path are indexed: include and
+// exclude globs, plus the `.gitignore` and `.cppindexignore` files of the
+// tree when they are honored. Ignored and excluded directories are pruned
+// during the walk, so a build tree of generated code is never listed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::lib::cpp_indexer::vfs::{is_source_file, pattern_matches, wildcard_match};
+use crate::lib::storage::models::walk_rules::WalkRules;
+
+/// Ignore files read in each directory; later ones override earlier ones
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".cppindexignore"];
+
+/// Paths a new index skips unless its creator passes its own exclude patterns
+pub const DEFAULT_EXCLUDE_PATTERNS: &[&str] = &["**/build/**", "**/target/**", "**/.git/**"];
+
+/// Walk rules of an index created without any: skip the default paths and honor ignore files
+pub fn default_rules(index_id: Uuid) -> WalkRules {
+    let exclude = DEFAULT_EXCLUDE_PATTERNS.iter().map(|pattern| pattern.to_string()).collect();
+    WalkRules::new(index_id, Vec::new(), exclude, true)
+}
+
+/// Which files a directory walk selects
+///
+/// The default selects every source file and reads no ignore files.
+#[derive(Debug, Clone, Default)]
+pub struct WalkFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignore_files: bool,
+}
+
+/// One line of an ignore file
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    /// Directory of the ignore file, relative to the walk root ("" for the root)
+    base: String,
+    pattern: String,
+    negated: bool,
+    anchored: bool,
+    directory_only: bool,
+}
+
+impl WalkFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter applying the stored rules of an index
+    pub fn from_rules(rules: &WalkRules) -> Self {
+        Self::new()
+            .with_include(rules.include_patterns.clone())
+            .with_exclude(rules.exclude_patterns.clone())
+            .with_ignore_files(rules.respect_ignore_files)
+    }
+
+    /// Selects only files matching one of `patterns` (empty = every source file)
+    pub fn with_include(mut self, patterns: Vec<String>) -> Self {
+        self.include = patterns;
+        self
+    }
+
+    /// Skips files and directories matching any of `patterns`
+    pub fn with_exclude(mut self, patterns: Vec<String>) -> Self {
+        self.exclude = patterns;
+        self
+    }
+
+    /// Honors the `.gitignore` and `.cppindexignore` files found during the walk
+    pub fn with_ignore_files(mut self, honor: bool) -> Self {
+        self.ignore_files = honor;
+        self
+    }
+
+    /// Returns true if the include and exclude globs select a relative file path
+    ///
+    /// Ignore files aren't consulted; they are only read while walking.
+    pub fn selects(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| glob_selects(pattern, path)))
+            && !self.exclude.iter().any(|pattern| glob_selects(pattern, path))
+    }
+
+    /// Source files under `root` the filter selects, relative to `root` and sorted
+    ///
+    /// Symbolic links aren't followed.
+    pub fn source_files(&self, root: &Path) -> io::Result<Vec<String>> {
+        let mut files = Vec::new();
+        let mut rules = Vec::new();
+        self.walk(root, "", &mut rules, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    /// Source files below `directory` of `root` the filter selects, relative to `root` and sorted
+    ///
+    /// Only `directory` is walked; ignore files and excludes of the
+    /// directories above it apply as they do in a walk of `root`. A file
+    /// path lists just that file, if it is selected.
+    pub fn source_files_under(&self, root: &Path, directory: &str) -> io::Result<Vec<String>> {
+        let directory = directory.trim_matches('/');
+        if directory.is_empty() {
+            return self.source_files(root);
+        }
+        let mut rules = Vec::new();
+        let mut parent = String::new();
+        for component in directory.split('/') {
+            self.read_ignore_files(root, &parent, &mut rules)?;
+            let path = if parent.is_empty() { component.to_string() } else { format!("{}/{}", parent, component) };
+            let absolute = root.join(&path);
+            if absolute.is_file() {
+                let selected = path == directory && is_source_file(&path) && self.selects(&path) && !is_ignored(&rules, &path, false);
+                return Ok(if selected { vec![path] } else { Vec::new() });
+            }
+            let excluded = self.exclude.iter().any(|pattern| glob_selects(pattern, &format!("{}/", path)));
+            if !absolute.is_dir() || excluded || is_ignored(&rules, &path, true) {
+                return Ok(Vec::new());
+            }
+            parent = path;
+        }
+
+        let mut files = Vec::new();
+        self.walk(root, &parent, &mut rules, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    fn read_ignore_files(&self, root: &Path, directory: &str, rules: &mut Vec<IgnoreRule>) -> io::Result<()> {
+        if self.ignore_files {
+            for name in IGNORE_FILES {
+                match fs::read_to_string(root.join(directory).join(name)) {
+                    Ok(contents) => rules.extend(parse_ignore_file(&contents, directory)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn walk(&self, root: &Path, directory: &str, rules: &mut Vec<IgnoreRule>, files: &mut Vec<String>) -> io::Result<()> {
+        let absolute = root.join(directory);
+        let inherited = rules.len();
+        self.read_ignore_files(root, directory, rules)?;
+
+        for entry in fs::read_dir(&absolute)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = if directory.is_empty() { name } else { format!("{}/{}", directory, name) };
+
+            if file_type.is_dir() {
+                // A directory matches an exclude glob if everything below it would
+                let excluded = self.exclude.iter().any(|pattern| glob_selects(pattern, &format!("{}/", path)));
+                if !excluded && !is_ignored(rules, &path, true) {
+                    self.walk(root, &path, rules, files)?;
+                }
+            } else if file_type.is_file() && is_source_file(&path) && self.selects(&path) && !is_ignored(rules, &path, false) {
+                files.push(path);
+            }
+        }
+
+        rules.truncate(inherited);
+        Ok(())
+    }
+}
+
+/// Matches an index_codebase glob against a path relative to the base path
+///
+/// A leading `**/` also matches at the root, so `**/*.cpp` selects
+/// `main.cpp` as well as `src/main.cpp`.
+pub fn glob_selects(pattern: &str, path: &str) -> bool {
+    pattern_matches(pattern, path) || pattern.strip_prefix("**/").is_some_and(|rest| pattern_matches(rest, path))
+}
+
+/// Rules of an ignore file in `directory`, in file order
+///
+/// Follows the gitignore format: blank lines and `#` comments are skipped,
+/// `!` re-includes what an earlier rule ignored and a trailing `/` matches
+/// directories only.
+fn parse_ignore_file(contents: &str, directory: &str) -> Vec<IgnoreRule> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let directory_only = line.ends_with('/');
+            let line = line.trim_end_matches('/');
+            let anchored = line.contains('/');
+            let pattern = line.trim_start_matches('/');
+            (!pattern.is_empty()).then(|| IgnoreRule {
+                base: directory.to_string(),
+                pattern: pattern.to_string(),
+                negated,
+                anchored,
+                directory_only,
+            })
+        })
+        .collect()
+}
+
+/// Returns true if the last rule matching `path` ignores it
+///
+/// Directories are checked before they are entered, so a file under an
+/// ignored directory can't be re-included, as with git.
+fn is_ignored(rules: &[IgnoreRule], path: &str, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+impl IgnoreRule {
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path.strip_prefix(self.base.as_str()).and_then(|rest| rest.strip_prefix('/')) {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+
+        if self.anchored {
+            wildcard_match(&self.pattern, relative)
+                || self.pattern.strip_prefix("**/").is_some_and(|rest| wildcard_match(rest, relative))
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildcard_match(&self.pattern, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_filter_honors_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        for file in ["main.cpp", "build/gen.cpp", "src/a.cpp", "src/a.pb.cc", "src/keep.pb.cc", "third_party/zlib/z.c", "docs/notes.txt"] {
+            write(file, "");
+        }
+        write(".gitignore", "# generated\nbuild/\n*.pb.cc\n!keep.pb.cc\n");
+        write("src/.cppindexignore", "/a.cpp\n");
+
+        let all = WalkFilter::new().source_files(dir.path()).unwrap();
+        assert_eq!(all, ["build/gen.cpp", "main.cpp", "src/a.cpp", "src/a.pb.cc", "src/keep.pb.cc", "third_party/zlib/z.c"]);
+
+        let ignoring = WalkFilter::new().with_ignore_files(true);
+        assert_eq!(ignoring.source_files(dir.path()).unwrap(), ["main.cpp", "src/keep.pb.cc", "third_party/zlib/z.c"]);
+
+        let globs = ignoring
+            .with_include(vec!["**/*.cpp".to_string(), "third_party/**".to_string()])
+            .with_exclude(vec!["third_party/**".to_string()]);
+        assert_eq!(globs.source_files(dir.path()).unwrap(), ["main.cpp"]);
+        assert!(glob_selects("**/build/**", "build/") && !glob_selects("**/build/**", "builder/x.cpp"));
+    }
+
+    #[test]
+    fn test_source_files_under_applies_parent_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["main.cpp", "src/dsp/fft.cpp", "src/dsp/fft.pb.cc", "src/dsp/gen/table.cpp", "src/io/file.cpp"] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::write(dir.path().join(".gitignore"), "*.pb.cc
+").unwrap();
+        fs::write(dir.path().join("src/.gitignore"), "dsp/gen/
+").unwrap();
+
+        let filter = WalkFilter::new().with_ignore_files(true);
+        assert_eq!(filter.source_files_under(dir.path(), "src/dsp/").unwrap(), ["src/dsp/fft.cpp"]);
+        assert_eq!(filter.source_files_under(dir.path(), "src/io/file.cpp").unwrap(), ["src/io/file.cpp"]);
+        assert!(filter.source_files_under(dir.path(), "src/dsp/fft.pb.cc").unwrap().is_empty());
+        assert!(filter.source_files_under(dir.path(), "src/dsp/gen").unwrap().is_empty());
+        assert!(filter.source_files_under(dir.path(), "src/missing").unwrap().is_empty());
+        assert_eq!(filter.source_files_under(dir.path(), "").unwrap(), filter.source_files(dir.path()).unwrap());
+    }
+}
@@ -0,0 +1,250 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tracing::warn;
+
+use crate::lib::cpp_indexer::incremental::{IncrementalIndexer, IncrementalResult, IndexAction};
+use crate::lib::cpp_indexer::index_settings::FileSelection;
+use crate::lib::cpp_indexer::vfs::is_source_file;
+
+/// Quiet period after a file's last event before it is re-indexed
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// What happened to a file once its events settled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    /// Created or modified; re-index it
+    Changed(PathBuf),
+    /// Deleted or renamed away; drop it from the index
+    Removed(PathBuf),
+}
+
+/// Outcome of applying one batch of changes
+#[derive(Debug, Default)]
+pub struct WatchBatch {
+    pub results: Vec<IncrementalResult>,
+    /// Files that failed to index, with the error
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+/// Coalesces bursts of events per path
+///
+/// Editors and build tools touch a file several times per save (write,
+/// chmod, rename over); a path is only reported once no event arrived for it
+/// during the debounce delay.
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    delay: Duration,
+    pending: BTreeMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Notes an event for `path` at `now`, restarting its quiet period
+    pub fn record(&mut self, path: PathBuf, now: Instant) {
+        self.pending.insert(path, now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// When the next pending path becomes ready
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().min().map(|last_seen| *last_seen + self.delay)
+    }
+
+    /// Removes and returns the paths that have been quiet for the delay, in path order
+    pub fn take_ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, last_seen)| now.duration_since(**last_seen) >= self.delay)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &ready {
+            self.pending.remove(path);
+        }
+        ready
+    }
+}
+
+/// Watches an index's base path and reports settled source file changes
+pub struct FileWatcher {
+    base_path: PathBuf,
+    /// Files outside it are ignored
+    selection: FileSelection,
+    debouncer: Debouncer,
+    events: UnboundedReceiver<notify::Result<Event>>,
+    // Dropping the watcher stops the event stream
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    /// Starts watching `base_path` recursively
+    pub fn new<P: AsRef<Path>>(base_path: P, debounce: Duration) -> notify::Result<Self> {
+        // Events carry resolved paths, which the base path must prefix
+        let base_path = std::fs::canonicalize(base_path.as_ref()).unwrap_or_else(|_| base_path.as_ref().to_path_buf());
+        let (sender, events) = unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver is gone once the FileWatcher is dropped
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(&base_path, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            base_path,
+            selection: FileSelection::default(),
+            debouncer: Debouncer::new(debounce),
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Only reports the files `selection` includes, e.g. the ones an index was built from
+    pub fn with_selection(mut self, selection: FileSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    /// Waits for the next batch of settled changes
+    ///
+    /// Whether a file changed or was removed is decided when its events
+    /// settle, by checking whether it still exists. Returns None when the
+    /// event stream ends.
+    pub async fn next_changes(&mut self) -> Option<Vec<FileChange>> {
+        loop {
+            let event = match self.debouncer.next_deadline() {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(tokio::time::Instant::from_std(deadline), self.events.recv()).await {
+                        Ok(event) => event,
+                        Err(_) => {
+                            let ready = self.debouncer.take_ready(Instant::now());
+                            if !ready.is_empty() {
+                                return Some(ready.into_iter().map(classify).collect());
+                            }
+                            continue;
+                        }
+                    }
+                }
+                None => self.events.recv().await,
+            };
+
+            match event? {
+                Ok(event) => self.record(event),
+                Err(e) => warn!("File watcher error: {}", e),
+            }
+        }
+    }
+
+    fn record(&mut self, event: Event) {
+        if !matches!(event.kind, EventKind::Any | EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            return;
+        }
+        let now = Instant::now();
+        for path in event.paths {
+            let Ok(relative) = path.strip_prefix(&self.base_path) else { continue };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if is_source_file(&relative) && self.selection.selects(&relative) {
+                self.debouncer.record(path, now);
+            }
+        }
+    }
+}
+
+fn classify(path: PathBuf) -> FileChange {
+    if path.is_file() {
+        FileChange::Changed(path)
+    } else {
+        FileChange::Removed(path)
+    }
+}
+
+/// Re-indexes changed files and drops removed ones
+///
+/// A file that fails to parse is reported, recorded in the index's errors
+/// and skipped; the rest of the batch is still applied.
+pub async fn apply_changes(indexer: &mut IncrementalIndexer, changes: &[FileChange]) -> WatchBatch {
+    let mut batch = WatchBatch::default();
+    for change in changes {
+        let (path, result) = match change {
+            FileChange::Changed(path) => (path, indexer.index_file_or_record(path).await),
+            FileChange::Removed(path) => (path, indexer.remove_file(path).await),
+        };
+        match result {
+            Ok(IncrementalResult { action: IndexAction::Failed(message), .. }) => {
+                warn!("Failed to re-index {}: {}", path.display(), message);
+                batch.failures.push((path.clone(), message));
+            }
+            Ok(result) => batch.results.push(result),
+            Err(e) => {
+                warn!("Failed to re-index {}: {}", path.display(), e);
+                batch.failures.push((path.clone(), e.to_string()));
+            }
+        }
+    }
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cpp_indexer::walk_filter::WalkFilter;
+
+    #[test]
+    fn test_debouncer_waits_for_quiet_period() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(100));
+        debouncer.record(PathBuf::from("src/b.cpp"), start);
+        debouncer.record(PathBuf::from("src/a.h"), start + Duration::from_millis(30));
+        // A second save restarts b.cpp's quiet period
+        debouncer.record(PathBuf::from("src/b.cpp"), start + Duration::from_millis(60));
+
+        assert_eq!(debouncer.next_deadline(), Some(start + Duration::from_millis(130)));
+        assert!(debouncer.take_ready(start + Duration::from_millis(120)).is_empty());
+        assert_eq!(debouncer.take_ready(start + Duration::from_millis(130)), vec![PathBuf::from("src/a.h")]);
+        assert_eq!(debouncer.take_ready(start + Duration::from_millis(500)), vec![PathBuf::from("src/b.cpp")]);
+        assert!(debouncer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reports_settled_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut watcher = FileWatcher::new(dir.path(), Duration::from_millis(50)).unwrap();
+
+        let source = dir.path().join("engine.cpp");
+        std::fs::write(&source, "int main() {}").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+        let changes = tokio::time::timeout(Duration::from_secs(5), watcher.next_changes()).await.unwrap().unwrap();
+        assert!(changes.iter().all(|change| matches!(change, FileChange::Changed(path) if path.ends_with("engine.cpp"))));
+
+        std::fs::remove_file(&source).unwrap();
+        let changes = tokio::time::timeout(Duration::from_secs(5), watcher.next_changes()).await.unwrap().unwrap();
+        assert!(changes.contains(&FileChange::Removed(source)));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_ignores_unselected_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("gen")).unwrap();
+        let selection = FileSelection::default().with_walk_filter(WalkFilter::new().with_exclude(vec!["gen/**".to_string()]));
+        let mut watcher = FileWatcher::new(dir.path(), Duration::from_millis(50)).unwrap().with_selection(selection);
+
+        std::fs::write(dir.path().join("gen/table.cpp"), "int table[4];").unwrap();
+        std::fs::write(dir.path().join("engine.cpp"), "int main() {}").unwrap();
+        let changes = tokio::time::timeout(Duration::from_secs(5), watcher.next_changes()).await.unwrap().unwrap();
+        assert!(changes.iter().all(|change| matches!(change, FileChange::Changed(path) if path.ends_with("engine.cpp"))));
+    }
+}
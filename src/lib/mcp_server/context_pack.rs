@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::lib::storage::models::code_element::CodeElement;
+
+/// Default maximum number of items in a context pack
+pub const DEFAULT_MAX_ITEMS: usize = 25;
+
+/// Most items a context pack may be asked for
+pub const MAX_ITEMS: usize = 200;
+
+/// A code element as presented to the assistant
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ContextItem {
+    pub symbol_name: String,
+    pub symbol_type: String,
+    pub file_path: String,
+    pub line_number: u32,
+    pub column_number: u32,
+    pub scope: Option<String>,
+    pub signature: Option<String>,
+    pub is_declaration: bool,
+}
+
+/// Named group of related items within a context pack
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ContextSection {
+    /// Section identifier (e.g., "types")
+    pub name: String,
+    /// Why these items are relevant
+    pub description: String,
+    pub items: Vec<ContextItem>,
+}
+
+/// Bounded bundle of index context assembled for one question
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ContextPack {
+    /// What the pack describes (e.g., the diagnostic message)
+    pub subject: String,
+    pub sections: Vec<ContextSection>,
+    /// Total number of items across all sections
+    pub item_count: usize,
+    /// True if items were dropped to stay within the budget
+    pub truncated: bool,
+}
+
+/// Builds context packs from index elements
+///
+/// Sections are kept in insertion order, elements already present in an
+/// earlier section are skipped, and the total item count is capped so packs
+/// stay small enough to hand to an assistant.
+#[derive(Debug, Clone)]
+pub struct ContextPackBuilder {
+    subject: String,
+    sections: Vec<ContextSection>,
+    max_items: usize,
+    item_count: usize,
+    truncated: bool,
+    seen: HashSet<(String, u32, u32, String)>,
+}
+
+impl From<&CodeElement> for ContextItem {
+    fn from(element: &CodeElement) -> Self {
+        Self {
+            symbol_name: element.symbol_name.clone(),
+            symbol_type: element.symbol_type.as_str().to_string(),
+            file_path: element.file_path.clone(),
+            line_number: element.line_number,
+            column_number: element.column_number,
+            scope: element.scope.clone(),
+            signature: element.signature.clone(),
+            is_declaration: element.is_declaration,
+        }
+    }
+}
+
+impl ContextPackBuilder {
+    /// Creates a builder for a pack about `subject`
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            sections: Vec::new(),
+            max_items: DEFAULT_MAX_ITEMS,
+            item_count: 0,
+            truncated: false,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Sets the maximum number of items across all sections
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = max_items;
+        self
+    }
+
+    /// Appends a section; empty sections are omitted from the pack
+    pub fn add_section(&mut self, name: &str, description: &str, elements: &[CodeElement]) -> &mut Self {
+        let mut items = Vec::new();
+
+        for element in elements {
+            let key = (
+                element.file_path.clone(),
+                element.line_number,
+                element.column_number,
+                element.symbol_name.clone(),
+            );
+            if self.seen.contains(&key) {
+                continue;
+            }
+            if self.item_count >= self.max_items {
+                self.truncated = true;
+                break;
+            }
+            self.seen.insert(key);
+            self.item_count += 1;
+            items.push(ContextItem::from(element));
+        }
+
+        if !items.is_empty() {
+            self.sections.push(ContextSection {
+                name: name.to_string(),
+                description: description.to_string(),
+                items,
+            });
+        }
+
+        self
+    }
+
+    /// Finishes the pack
+    pub fn build(self) -> ContextPack {
+        ContextPack {
+            subject: self.subject,
+            sections: self.sections,
+            item_count: self.item_count,
+            truncated: self.truncated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    fn element(name: &str, line: u32) -> CodeElement {
+        CodeElement::new(
+            Uuid::new_v4(),
+            name.to_string(),
+            SymbolType::Function,
+            "src/test.cpp".to_string(),
+            line,
+            1,
+            "a".repeat(64),
+        )
+    }
+
+    #[test]
+    fn test_sections_skip_duplicates_and_empty() {
+        let mut builder = ContextPackBuilder::new("subject");
+        builder
+            .add_section("location", "Symbol at location", &[element("run", 10)])
+            .add_section("overloads", "Overloads", &[element("run", 10), element("run", 20)])
+            .add_section("types", "Types", &[]);
+        let pack = builder.build();
+
+        assert_eq!(pack.sections.len(), 2);
+        assert_eq!(pack.sections[1].items.len(), 1);
+        assert_eq!(pack.sections[1].items[0].line_number, 20);
+        assert_eq!(pack.item_count, 2);
+        assert!(!pack.truncated);
+    }
+
+    #[test]
+    fn test_max_items_truncates() {
+        let elements: Vec<CodeElement> = (1..=5).map(|line| element("f", line)).collect();
+        let mut builder = ContextPackBuilder::new("subject").with_max_items(3);
+        builder.add_section("overloads", "Overloads", &elements);
+        let pack = builder.build();
+
+        assert_eq!(pack.item_count, 3);
+        assert!(pack.truncated);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long an unused cursor stays valid
+pub const DEFAULT_CURSOR_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of open cursors; the least recently used is dropped first
+pub const DEFAULT_MAX_CURSORS: usize = 64;
+
+/// Server-side state for paginated tool results
+///
+/// Tools that can return very large result sets send one page at a time and
+/// hand out an opaque cursor for the next page. Only the query position is
+/// kept here, never the results themselves, so memory stays bounded
+/// regardless of how many rows a query matches.
+#[derive(Debug)]
+pub struct CursorStore<S> {
+    cursors: HashMap<String, (S, Instant)>,
+    ttl: Duration,
+    max_cursors: usize,
+}
+
+impl<S> Default for CursorStore<S> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CURSOR_TTL, DEFAULT_MAX_CURSORS)
+    }
+}
+
+impl<S> CursorStore<S> {
+    /// Creates a store with the given expiry and capacity
+    pub fn new(ttl: Duration, max_cursors: usize) -> Self {
+        Self {
+            cursors: HashMap::new(),
+            ttl,
+            max_cursors: max_cursors.max(1),
+        }
+    }
+
+    /// Stores the state for the next page and returns its cursor
+    pub fn insert(&mut self, state: S) -> String {
+        self.expire();
+        while self.cursors.len() >= self.max_cursors {
+            let oldest = self
+                .cursors
+                .iter()
+                .min_by_key(|(_, (_, touched))| *touched)
+                .map(|(cursor, _)| cursor.clone());
+            match oldest {
+                Some(cursor) => self.cursors.remove(&cursor),
+                None => break,
+            };
+        }
+
+        let cursor = Uuid::new_v4().simple().to_string();
+        self.cursors.insert(cursor.clone(), (state, Instant::now()));
+        cursor
+    }
+
+    /// Removes and returns the state for a cursor; None if unknown or expired
+    pub fn take(&mut self, cursor: &str) -> Option<S> {
+        self.expire();
+        self.cursors.remove(cursor).map(|(state, _)| state)
+    }
+
+    /// Returns the state for a cursor without removing it, restarting its expiry
+    pub fn get(&mut self, cursor: &str) -> Option<&S> {
+        self.expire();
+        self.cursors.get_mut(cursor).map(|(state, touched)| {
+            *touched = Instant::now();
+            &*state
+        })
+    }
+
+    /// Number of open cursors
+    pub fn len(&self) -> usize {
+        self.cursors.len()
+    }
+
+    /// Returns true if no cursors are open
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+
+    /// Drops cursors unused for longer than the expiry, returning how many
+    pub fn expire(&mut self) -> usize {
+        let ttl = self.ttl;
+        let open = self.cursors.len();
+        self.cursors.retain(|_, (_, touched)| touched.elapsed() < ttl);
+        open - self.cursors.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip_and_capacity() {
+        let mut store = CursorStore::new(DEFAULT_CURSOR_TTL, 2);
+        let first = store.insert(1);
+        let second = store.insert(2);
+        let third = store.insert(3);
+
+        // The oldest cursor was evicted to make room
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.take(&first), None);
+        // get leaves the cursor in place
+        assert_eq!(store.get(&second), Some(&2));
+        assert_eq!(store.take(&second), Some(2));
+        assert_eq!(store.take(&third), Some(3));
+
+        // Cursors are single use
+        assert_eq!(store.take(&third), None);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_cursor_expiry() {
+        let mut store = CursorStore::new(Duration::ZERO, 8);
+        let cursor = store.insert("page");
+        assert_eq!(store.take(&cursor), None);
+    }
+}
@@ -0,0 +1,733 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Build files that mark a directory as the root of a build target
+const BUILD_FILES: &[&str] = &[
+    "CMakeLists.txt",
+    "BUILD",
+    "BUILD.bazel",
+    "meson.build",
+    "Makefile",
+    "premake5.lua",
+];
+
+/// Linker that produced a diagnostic
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkerFlavor {
+    /// GNU ld, gold and lld
+    Gnu,
+    /// MSVC link.exe (LNK2019/LNK2001)
+    Msvc,
+    /// Apple ld64
+    Apple,
+}
+
+/// Kind of entity the linker could not resolve
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnresolvedKind {
+    /// Function or variable
+    Symbol,
+    /// Virtual table of a class
+    Vtable,
+    /// RTTI type information of a class
+    Typeinfo,
+}
+
+/// A single unresolved symbol extracted from linker output
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UnresolvedSymbol {
+    /// Linker that reported the error
+    pub linker: LinkerFlavor,
+    /// What kind of entity is missing
+    pub kind: UnresolvedKind,
+    /// Symbol as printed by the linker
+    pub raw_symbol: String,
+    /// Qualified name without parameters (e.g., "ns::Widget::draw")
+    pub qualified_name: String,
+    /// Unqualified name used for index lookups (e.g., "draw")
+    pub name: String,
+    /// Enclosing scope, if any (e.g., "ns::Widget")
+    pub scope: Option<String>,
+    /// Object file or function that references the symbol, when reported
+    pub referenced_from: Option<String>,
+}
+
+/// Lookup names derived from a symbol printed by a compiler or linker
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct SymbolName {
+    /// Qualified name without parameters (e.g., "ns::Widget::draw")
+    pub qualified_name: String,
+    /// Enclosing scope, if any (e.g., "ns::Widget")
+    pub scope: Option<String>,
+    /// Unqualified name without template arguments (e.g., "draw")
+    pub name: String,
+}
+
+/// Build target that owns a file, identified by its nearest build file
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct BuildTarget {
+    /// Directory containing the build file, relative to the codebase root
+    pub directory: String,
+    /// Name of the build file (e.g., "CMakeLists.txt")
+    pub build_file: String,
+}
+
+/// Severity of a compiler diagnostic
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A compiler diagnostic with its location and the symbols it mentions
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CompilerDiagnostic {
+    /// File as printed by the compiler
+    pub file_path: String,
+    /// Line number in file (1-based)
+    pub line_number: u32,
+    /// Column number in file (1-based), when reported
+    pub column_number: Option<u32>,
+    /// Diagnostic severity
+    pub severity: Severity,
+    /// Compiler-specific code (e.g., MSVC "C2664")
+    pub code: Option<String>,
+    /// Diagnostic text without location and severity
+    pub message: String,
+    /// Symbols quoted in the message and its notes
+    pub symbols: Vec<SymbolName>,
+    /// Messages of notes attached to this diagnostic
+    pub notes: Vec<String>,
+}
+
+/// Extracts every unresolved symbol from a block of linker output
+///
+/// Recognizes GNU ld/lld "undefined reference"/"undefined symbol" lines,
+/// MSVC LNK2019/LNK2001 errors and Apple ld "Undefined symbols" blocks.
+/// Duplicate symbols are reported once.
+pub fn parse_linker_errors(message: &str) -> Vec<UnresolvedSymbol> {
+    let lines: Vec<&str> = message.lines().collect();
+    let mut symbols: Vec<UnresolvedSymbol> = Vec::new();
+    let mut in_apple_block = false;
+    // GNU ld prints "in function `caller':" on the line before the error
+    let mut current_function: Option<String> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("Undefined symbols for architecture") {
+            in_apple_block = true;
+            continue;
+        }
+
+        if let Some(function) = between(trimmed, "in function `", "'") {
+            current_function = Some(function.to_string());
+            continue;
+        }
+
+        let parsed = if let Some(pos) = trimmed.find("undefined reference to ") {
+            let rest = &trimmed[pos + "undefined reference to ".len()..];
+            if names_object(&trimmed[..pos]) {
+                // An error naming its own object file ends the last object's "in function" block
+                current_function = None;
+            }
+            let referenced_from = current_function
+                .clone()
+                .or_else(|| object_prefix(&trimmed[..pos]));
+            strip_quotes(rest).map(|s| (LinkerFlavor::Gnu, s.to_string(), referenced_from))
+        } else if let Some(pos) = trimmed.find("undefined symbol: ") {
+            // lld reports callers on following ">>> referenced by" lines
+            let rest = &trimmed[pos + "undefined symbol: ".len()..];
+            let referenced_from = lines[i + 1..]
+                .iter()
+                .take_while(|l| l.trim_start().starts_with(">>>"))
+                .find_map(|l| l.trim().strip_prefix(">>> referenced by "))
+                .map(|s| s.trim().to_string());
+            Some((LinkerFlavor::Gnu, rest.trim().to_string(), referenced_from))
+        } else if trimmed.contains("LNK2019") || trimmed.contains("LNK2001") {
+            between(trimmed, "unresolved external symbol ", " referenced in function")
+                .or_else(|| trimmed.split("unresolved external symbol ").nth(1))
+                .and_then(|rest| {
+                    let symbol = between(rest, "\"", "\"").unwrap_or_else(|| rest.split(' ').next().unwrap_or(rest));
+                    let referenced_from = trimmed
+                        .split(" referenced in function ")
+                        .nth(1)
+                        .map(|s| s.trim().to_string());
+                    (!symbol.is_empty()).then(|| (LinkerFlavor::Msvc, symbol.to_string(), referenced_from))
+                })
+        } else if in_apple_block && trimmed.ends_with("referenced from:") {
+            let referenced_from = lines
+                .get(i + 1)
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty());
+            between(trimmed, "\"", "\"").map(|s| (LinkerFlavor::Apple, s.to_string(), referenced_from))
+        } else {
+            if in_apple_block && trimmed.starts_with("ld:") {
+                in_apple_block = false;
+            }
+            None
+        };
+
+        if let Some((linker, raw_symbol, referenced_from)) = parsed {
+            if symbols.iter().any(|s| s.raw_symbol == raw_symbol) {
+                continue;
+            }
+            if let Some(symbol) = normalize_symbol(linker, &raw_symbol, referenced_from) {
+                symbols.push(symbol);
+            }
+        }
+    }
+
+    symbols
+}
+
+/// Parses GCC/Clang and MSVC diagnostics out of compiler output
+///
+/// Notes are folded into the preceding error or warning so that candidate
+/// declarations they mention become part of that diagnostic's symbols.
+pub fn parse_compiler_diagnostics(output: &str) -> Vec<CompilerDiagnostic> {
+    let mut diagnostics: Vec<CompilerDiagnostic> = Vec::new();
+
+    for line in output.lines() {
+        let Some(diagnostic) = parse_diagnostic_line(line.trim()) else {
+            continue;
+        };
+
+        match diagnostics.last_mut() {
+            Some(previous) if diagnostic.severity == Severity::Note => {
+                for symbol in diagnostic.symbols {
+                    if !previous.symbols.contains(&symbol) {
+                        previous.symbols.push(symbol);
+                    }
+                }
+                previous.notes.push(diagnostic.message);
+            }
+            _ => diagnostics.push(diagnostic),
+        }
+    }
+
+    diagnostics
+}
+
+/// Makes a compiler-printed path relative to the codebase root
+pub fn relative_to_base(base_path: &Path, file_path: &str) -> String {
+    let normalized = file_path.replace('\\', "/");
+    let base = base_path.to_string_lossy().replace('\\', "/");
+    let base = base.trim_end_matches('/');
+
+    let relative = if !base.is_empty() && normalized.starts_with(&format!("{}/", base)) {
+        &normalized[base.len() + 1..]
+    } else {
+        normalized.as_str()
+    };
+    relative.trim_start_matches("./").to_string()
+}
+
+/// Finds the build target owning `file_path` by walking up to `base_path`
+///
+/// Returns `None` when no directory between the file and the codebase root
+/// contains a recognized build file.
+pub fn find_build_target(base_path: &Path, file_path: &str) -> Option<BuildTarget> {
+    let relative = Path::new(file_path);
+    let mut directory: Option<PathBuf> = relative.parent().map(Path::to_path_buf);
+
+    while let Some(dir) = directory {
+        let absolute = base_path.join(&dir);
+        if let Some(build_file) = BUILD_FILES.iter().find(|f| absolute.join(f).is_file()) {
+            let display = dir.to_string_lossy().replace('\\', "/");
+            return Some(BuildTarget {
+                directory: if display.is_empty() { ".".to_string() } else { display },
+                build_file: (*build_file).to_string(),
+            });
+        }
+        directory = dir.parent().map(Path::to_path_buf);
+    }
+
+    None
+}
+
+/// Turns a linker-printed symbol into lookup names
+fn normalize_symbol(linker: LinkerFlavor, raw: &str, referenced_from: Option<String>) -> Option<UnresolvedSymbol> {
+    let mut text = raw.trim();
+    let mut kind = UnresolvedKind::Symbol;
+
+    for (prefix, prefix_kind) in [
+        ("vtable for ", UnresolvedKind::Vtable),
+        ("typeinfo for ", UnresolvedKind::Typeinfo),
+        ("typeinfo name for ", UnresolvedKind::Typeinfo),
+    ] {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            text = rest;
+            kind = prefix_kind;
+        }
+    }
+
+    if linker == LinkerFlavor::Msvc {
+        // "public: void __cdecl ns::Widget::draw(int)" -> "ns::Widget::draw(int)"
+        for access in ["public: ", "protected: ", "private: "] {
+            if let Some(rest) = text.strip_prefix(access) {
+                text = rest;
+            }
+        }
+        text = text.trim_start_matches("static ").trim_start_matches("virtual ");
+    }
+
+    if linker == LinkerFlavor::Apple && !text.contains("::") && !text.contains('(') {
+        // ld64 prints C symbols with their leading underscore
+        text = text.strip_prefix('_').unwrap_or(text);
+    }
+
+    // For vtables and RTTI the trailing component is the class to look up
+    let symbol_name = split_symbol_name(text)?;
+
+    Some(UnresolvedSymbol {
+        linker,
+        kind,
+        raw_symbol: raw.trim().to_string(),
+        qualified_name: symbol_name.qualified_name,
+        name: symbol_name.name,
+        scope: symbol_name.scope,
+        referenced_from,
+    })
+}
+
+/// Splits a demangled symbol like "void ns::Widget::draw(int) const" into lookup names
+///
+/// Return types, parameters and template arguments of the final component
+/// are dropped. Returns `None` when no name remains.
+pub fn split_symbol_name(text: &str) -> Option<SymbolName> {
+    let without_params = strip_parameters(text.trim());
+    // Drop return types and calling conventions that precede the name
+    let qualified = last_top_level_token(without_params);
+    if qualified.is_empty() {
+        return None;
+    }
+
+    let (scope, name) = match rsplit_scope(qualified) {
+        Some((scope, name)) => (Some(scope.to_string()), name),
+        None => (None, qualified),
+    };
+
+    Some(SymbolName {
+        qualified_name: qualified.to_string(),
+        scope,
+        name: strip_template_arguments(name).to_string(),
+    })
+}
+
+/// Parses one "file:line:col: error: ..." or "file(line,col): error C1234: ..." line
+fn parse_diagnostic_line(line: &str) -> Option<CompilerDiagnostic> {
+    for (marker, severity) in [
+        (": fatal error", Severity::Error),
+        (": error", Severity::Error),
+        (": warning", Severity::Warning),
+        (": note", Severity::Note),
+    ] {
+        let Some(pos) = line.find(marker) else {
+            continue;
+        };
+        let rest = &line[pos + marker.len()..];
+        // MSVC puts the code between severity and colon: "error C2664: ..."
+        let (code, message) = if let Some(message) = rest.strip_prefix(": ") {
+            (None, message)
+        } else {
+            match rest.trim_start().split_once(": ") {
+                Some((code, message)) if is_msvc_code(code) => (Some(code.to_string()), message),
+                _ => continue,
+            }
+        };
+
+        let (file_path, line_number, column_number) = parse_location(&line[..pos])?;
+        return Some(CompilerDiagnostic {
+            file_path,
+            line_number,
+            column_number,
+            severity,
+            code,
+            message: message.trim().to_string(),
+            symbols: quoted_symbols(message),
+            notes: Vec::new(),
+        });
+    }
+
+    None
+}
+
+/// Parses "file:line[:col]" (GCC/Clang) or "file(line[,col])" (MSVC)
+fn parse_location(location: &str) -> Option<(String, u32, Option<u32>)> {
+    let location = location.trim();
+
+    if let Some(inner) = location.strip_suffix(')') {
+        let open = inner.rfind('(')?;
+        let mut numbers = inner[open + 1..].split(',').map(|n| n.trim().parse::<u32>());
+        let line = numbers.next()?.ok()?;
+        let column = numbers.next().and_then(|c| c.ok());
+        return Some((inner[..open].to_string(), line, column));
+    }
+
+    let (head, last) = location.rsplit_once(':')?;
+    let last: u32 = last.parse().ok()?;
+    match head.rsplit_once(':') {
+        Some((file, line)) if line.parse::<u32>().is_ok() => {
+            Some((file.to_string(), line.parse().ok()?, Some(last)))
+        }
+        _ => Some((head.to_string(), last, None)),
+    }
+}
+
+/// Returns true for MSVC diagnostic codes such as "C2664" or "LNK2019"
+fn is_msvc_code(text: &str) -> bool {
+    let digits = text.trim_start_matches(|c: char| c.is_ascii_uppercase());
+    digits.len() != text.len() && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Collects symbol names quoted with '...' or ‘...’ in a message
+fn quoted_symbols(message: &str) -> Vec<SymbolName> {
+    let mut symbols: Vec<SymbolName> = Vec::new();
+    let mut rest = message;
+
+    while let Some(start) = rest.find(['\'', '\u{2018}']) {
+        let after = &rest[start..];
+        let after = &after[after.chars().next().map_or(1, char::len_utf8)..];
+        let Some(end) = after.find(['\'', '\u{2019}']) else {
+            break;
+        };
+        let quoted = &after[..end];
+        let closing = &after[end..];
+        rest = &closing[closing.chars().next().map_or(1, char::len_utf8)..];
+
+        let parameter_types: Vec<SymbolName> = parameter_list(quoted)
+            .map(|params| split_top_level(params, ',').into_iter().filter_map(parameter_type).collect())
+            .unwrap_or_default();
+
+        for symbol in split_symbol_name(quoted).into_iter().chain(parameter_types) {
+            if is_identifier(&symbol.name) && !is_builtin(&symbol.name) && !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+    }
+
+    symbols
+}
+
+/// Returns the text inside the outermost parameter list of a signature
+fn parameter_list(signature: &str) -> Option<&str> {
+    let name = strip_parameters(signature);
+    let rest = signature[name.len()..].trim_start().strip_prefix('(')?;
+    let mut depth = 0i32;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' | '<' => depth += 1,
+            ')' if depth == 0 => return Some(&rest[..i]),
+            ')' | '>' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits `text` on `separator` outside of brackets
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '<' | '[' => depth += 1,
+            ')' | '>' | ']' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Extracts the type named by a parameter such as "const ns::Canvas&"
+fn parameter_type(parameter: &str) -> Option<SymbolName> {
+    let cleaned: String = parameter
+        .split('=')
+        .next()
+        .unwrap_or(parameter)
+        .replace(['&', '*'], " ");
+    let type_text: Vec<&str> = cleaned
+        .split_whitespace()
+        .filter(|word| !matches!(*word, "const" | "volatile" | "struct" | "class" | "enum" | "union" | "typename"))
+        .collect();
+    split_symbol_name(type_text.first()?)
+}
+
+/// Returns true if `text` is a C++ identifier
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns true for keywords and builtin types that are never indexed
+fn is_builtin(name: &str) -> bool {
+    matches!(
+        name,
+        "void" | "bool" | "char" | "short" | "int" | "long" | "float" | "double" | "signed"
+            | "unsigned" | "auto" | "const" | "volatile" | "size_t" | "wchar_t" | "char8_t"
+            | "char16_t" | "char32_t" | "this" | "operator" | "virtual" | "static"
+    )
+}
+
+/// Cuts the parameter list (and trailing qualifiers) off a signature
+fn strip_parameters(text: &str) -> &str {
+    let mut depth = 0i32;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            '(' if depth == 0 && !text[..i].ends_with("operator") => return text[..i].trim_end(),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Returns the last whitespace-separated token outside template brackets
+fn last_top_level_token(text: &str) -> &str {
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ' ' if depth == 0 => start = i + 1,
+            _ => {}
+        }
+    }
+    text[start..].trim_start_matches(['*', '&'])
+}
+
+/// Splits "a::b::c" into ("a::b", "c"), ignoring "::" inside template arguments
+fn rsplit_scope(qualified: &str) -> Option<(&str, &str)> {
+    let mut depth = 0i32;
+    let mut split = None;
+    let bytes = qualified.as_bytes();
+    for (i, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'<' => depth += 1,
+            b'>' => depth -= 1,
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => split = Some(i),
+            _ => {}
+        }
+    }
+    split.map(|i| (&qualified[..i], &qualified[i + 2..]))
+}
+
+/// Removes template arguments from a name ("vector<int>" -> "vector")
+fn strip_template_arguments(name: &str) -> &str {
+    if name.starts_with("operator") {
+        return name;
+    }
+    name.split('<').next().unwrap_or(name)
+}
+
+/// Strips GNU-style `sym' or 'sym' quoting
+fn strip_quotes(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let inner = text
+        .strip_prefix('`')
+        .or_else(|| text.strip_prefix('\''))
+        .or_else(|| text.strip_prefix('\u{2018}'))?;
+    let end = inner.rfind(['\'', '\u{2019}'])?;
+    Some(&inner[..end])
+}
+
+/// Returns the text between the first `open` and the next `close`
+fn between<'a>(text: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let start = text.find(open)? + open.len();
+    let end = text[start..].find(close)? + start;
+    Some(&text[start..end])
+}
+
+/// Extracts the object or source file from "main.o:(.text+0x1f): "
+fn object_prefix(prefix: &str) -> Option<String> {
+    let object = prefix.split(":(").next()?.trim().trim_end_matches(':');
+    let object = object.rsplit(": ").next().unwrap_or(object);
+    (!object.is_empty()).then(|| object.to_string())
+}
+
+/// Returns true if the text before a GNU ld error is the linker naming an object file
+fn names_object(prefix: &str) -> bool {
+    prefix.split(":(").next().is_some_and(|object| object.contains(": "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_gnu_undefined_reference() {
+        let message = "/usr/bin/ld: main.o: in function `main':\n\
+                       main.cpp:(.text+0x1f): undefined reference to `geometry::Shape::area(double) const'\n\
+                       collect2: error: ld returned 1 exit status";
+        let symbols = parse_linker_errors(message);
+
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].linker, LinkerFlavor::Gnu);
+        assert_eq!(symbols[0].qualified_name, "geometry::Shape::area");
+        assert_eq!(symbols[0].name, "area");
+        assert_eq!(symbols[0].scope.as_deref(), Some("geometry::Shape"));
+        assert_eq!(symbols[0].referenced_from.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_parse_gnu_errors_of_two_objects() {
+        let message = "/usr/bin/ld: main.o: in function `main':\n\
+                       main.cpp:(.text+0x1f): undefined reference to `render()'\n\
+                       /usr/bin/ld: widget.o:(.data.rel.ro+0x10): undefined reference to `vtable for ui::Widget'\n\
+                       collect2: error: ld returned 1 exit status";
+        let symbols = parse_linker_errors(message);
+
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0].referenced_from.as_deref(), Some("main"));
+        assert_eq!(symbols[1].kind, UnresolvedKind::Vtable);
+        assert_eq!(symbols[1].referenced_from.as_deref(), Some("widget.o"));
+    }
+
+    #[test]
+    fn test_parse_gnu_vtable() {
+        let symbols = parse_linker_errors("widget.cpp:(.text+0x8): undefined reference to `vtable for ui::Widget'");
+
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].kind, UnresolvedKind::Vtable);
+        assert_eq!(symbols[0].name, "Widget");
+        assert_eq!(symbols[0].scope.as_deref(), Some("ui"));
+    }
+
+    #[test]
+    fn test_parse_lld_undefined_symbol() {
+        let message = "ld.lld: error: undefined symbol: std::vector<int, std::allocator<int> >::check(int)\n\
+                       >>> referenced by app.cpp:12\n\
+                       >>>               app.o:(main)";
+        let symbols = parse_linker_errors(message);
+
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "check");
+        assert_eq!(symbols[0].scope.as_deref(), Some("std::vector<int, std::allocator<int> >"));
+        assert_eq!(symbols[0].referenced_from.as_deref(), Some("app.cpp:12"));
+    }
+
+    #[test]
+    fn test_parse_msvc_lnk2019() {
+        let message = "main.obj : error LNK2019: unresolved external symbol \
+                       \"public: int __cdecl Engine::Renderer::draw(int)\" (?draw@Renderer@Engine@@QEAAHH@Z) \
+                       referenced in function main";
+        let symbols = parse_linker_errors(message);
+
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].linker, LinkerFlavor::Msvc);
+        assert_eq!(symbols[0].qualified_name, "Engine::Renderer::draw");
+        assert_eq!(symbols[0].referenced_from.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_parse_msvc_lnk2001_variable() {
+        let symbols = parse_linker_errors("util.obj : error LNK2001: unresolved external symbol \"int g_counter\" (?g_counter@@3HA)");
+
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "g_counter");
+        assert!(symbols[0].scope.is_none());
+    }
+
+    #[test]
+    fn test_parse_apple_block() {
+        let message = "Undefined symbols for architecture arm64:\n\
+                       \x20 \"_compute_checksum\", referenced from:\n\
+                       \x20     _main in main.o\n\
+                       \x20 \"Logger::flush()\", referenced from:\n\
+                       \x20     run() in app.o\n\
+                       ld: symbol(s) not found for architecture arm64";
+        let symbols = parse_linker_errors(message);
+
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0].linker, LinkerFlavor::Apple);
+        assert_eq!(symbols[0].name, "compute_checksum");
+        assert_eq!(symbols[0].referenced_from.as_deref(), Some("_main in main.o"));
+        assert_eq!(symbols[1].qualified_name, "Logger::flush");
+    }
+
+    #[test]
+    fn test_duplicate_symbols_reported_once() {
+        let message = "a.o: undefined reference to `foo()'\nb.o: undefined reference to `foo()'";
+        assert_eq!(parse_linker_errors(message).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_gcc_diagnostic_with_notes() {
+        let output = "src/app.cpp: In function 'int main()':\n\
+                      src/app.cpp:14:17: error: no matching function for call to 'Widget::draw(const char [6])'\n\
+                      include/widget.h:8:10: note: candidate: 'void Widget::draw(int)'\n\
+                      include/widget.h:9:10: note: candidate: 'void Widget::draw(const Canvas&)'";
+        let diagnostics = parse_compiler_diagnostics(output);
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.file_path, "src/app.cpp");
+        assert_eq!(diagnostic.line_number, 14);
+        assert_eq!(diagnostic.column_number, Some(17));
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.notes.len(), 2);
+        assert_eq!(diagnostic.symbols.len(), 2);
+        assert_eq!(diagnostic.symbols[0].qualified_name, "Widget::draw");
+        assert_eq!(diagnostic.symbols[1].name, "Canvas");
+    }
+
+    #[test]
+    fn test_parse_clang_diagnostic() {
+        let diagnostics = parse_compiler_diagnostics(
+            "/home/dev/proj/src/a.cpp:3:5: error: unknown type name \u{2018}Buffer\u{2019}",
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].symbols[0].name, "Buffer");
+        assert_eq!(
+            relative_to_base(Path::new("/home/dev/proj"), &diagnostics[0].file_path),
+            "src/a.cpp"
+        );
+    }
+
+    #[test]
+    fn test_parse_msvc_diagnostic() {
+        let diagnostics = parse_compiler_diagnostics(
+            "C:\\proj\\src\\main.cpp(42,9): error C2664: 'void Engine::run(int)': cannot convert argument 1 from 'const char [4]' to 'int'",
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.file_path, "C:\\proj\\src\\main.cpp");
+        assert_eq!(diagnostic.line_number, 42);
+        assert_eq!(diagnostic.column_number, Some(9));
+        assert_eq!(diagnostic.code.as_deref(), Some("C2664"));
+        assert_eq!(diagnostic.symbols.len(), 1);
+        assert_eq!(diagnostic.symbols[0].scope.as_deref(), Some("Engine"));
+    }
+
+    #[test]
+    fn test_find_build_target() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("libs/core/src")).unwrap();
+        std::fs::write(dir.path().join("CMakeLists.txt"), "").unwrap();
+        std::fs::write(dir.path().join("libs/core/CMakeLists.txt"), "").unwrap();
+
+        let target = find_build_target(dir.path(), "libs/core/src/engine.cpp").unwrap();
+        assert_eq!(target.directory, "libs/core");
+        assert_eq!(target.build_file, "CMakeLists.txt");
+
+        let root = find_build_target(dir.path(), "main.cpp").unwrap();
+        assert_eq!(root.directory, ".");
+    }
+}
@@ -0,0 +1,192 @@
+// Warm replica failover
+//
+// A primary server writes a heartbeat file next to the shard it serves:
+// which process it is, whether it is serving or busy rebuilding, and when it
+// last checked in. A replica runs read-only against the same shard, or a
+// copy streamed from it, with its database open and its handlers ready. It
+// reads the primary's heartbeat and answers tool calls only while the
+// primary is rebuilding or has stopped checking in, so clients configured
+// with both servers always have one of them answering.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Seconds without a heartbeat after which the primary is presumed gone
+pub const DEFAULT_FAILOVER_TIMEOUT_SECS: u64 = 30;
+
+/// Part a server plays in failover
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerRole {
+    /// Serves and writes the heartbeat
+    Primary,
+    /// Stands by, serving while the primary can't
+    Replica,
+}
+
+/// What the primary is doing, as last reported
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrimaryState {
+    Serving,
+    /// Running maintenance that writes the shard, e.g. a refresh
+    Rebuilding,
+}
+
+/// Contents of the heartbeat file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub pid: u32,
+    pub host: String,
+    pub state: PrimaryState,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One side of a primary/replica pair sharing a heartbeat file
+#[derive(Debug, Clone)]
+pub struct Failover {
+    role: ServerRole,
+    heartbeat_path: PathBuf,
+    timeout: Duration,
+    /// When this primary last wrote its heartbeat
+    last_beat: Option<DateTime<Utc>>,
+}
+
+impl ServerRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerRole::Primary => "primary",
+            ServerRole::Replica => "replica",
+        }
+    }
+}
+
+impl Heartbeat {
+    /// A heartbeat of this process
+    pub fn current(state: PrimaryState) -> Self {
+        let host = std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")).unwrap_or_default();
+        Self { pid: std::process::id(), host, state, updated_at: Utc::now() }
+    }
+
+    /// Writes the heartbeat to `path`, replacing the previous one atomically
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let mut staging = path.as_os_str().to_owned();
+        staging.push(".tmp");
+        std::fs::write(&staging, serde_json::to_vec(self)?)?;
+        std::fs::rename(&staging, path)
+    }
+
+    /// Reads the heartbeat at `path`; None if there is none or it can't be parsed
+    pub fn read(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+}
+
+impl Failover {
+    /// The primary, writing its heartbeat to `heartbeat_path`
+    pub fn primary(heartbeat_path: impl Into<PathBuf>, timeout: std::time::Duration) -> Self {
+        Self::new(ServerRole::Primary, heartbeat_path.into(), timeout)
+    }
+
+    /// A replica, watching the primary's heartbeat at `heartbeat_path`
+    pub fn replica(heartbeat_path: impl Into<PathBuf>, timeout: std::time::Duration) -> Self {
+        Self::new(ServerRole::Replica, heartbeat_path.into(), timeout)
+    }
+
+    fn new(role: ServerRole, heartbeat_path: PathBuf, timeout: std::time::Duration) -> Self {
+        let timeout = Duration::from_std(timeout).unwrap_or_else(|_| Duration::seconds(DEFAULT_FAILOVER_TIMEOUT_SECS as i64));
+        Self { role, heartbeat_path, timeout, last_beat: None }
+    }
+
+    pub fn role(&self) -> ServerRole {
+        self.role
+    }
+
+    pub fn heartbeat_path(&self) -> &Path {
+        &self.heartbeat_path
+    }
+
+    /// When the primary is next due to check in; None for a replica
+    ///
+    /// The primary checks in three times per timeout, so one late write
+    /// doesn't fail it over.
+    pub fn next_beat(&self) -> Option<DateTime<Utc>> {
+        match (self.role, self.last_beat) {
+            (ServerRole::Replica, _) => None,
+            (ServerRole::Primary, None) => Some(Utc::now()),
+            (ServerRole::Primary, Some(last_beat)) => Some(last_beat + self.timeout / 3),
+        }
+    }
+
+    /// Writes the primary's heartbeat; does nothing for a replica
+    pub fn beat(&mut self, state: PrimaryState) -> std::io::Result<()> {
+        if self.role != ServerRole::Primary {
+            return Ok(());
+        }
+        let heartbeat = Heartbeat::current(state);
+        heartbeat.write(&self.heartbeat_path)?;
+        self.last_beat = Some(heartbeat.updated_at);
+        Ok(())
+    }
+
+    /// Removes the primary's heartbeat so the replica takes over at once
+    pub fn resign(&mut self) -> std::io::Result<()> {
+        if self.role != ServerRole::Primary {
+            return Ok(());
+        }
+        self.last_beat = None;
+        match std::fs::remove_file(&self.heartbeat_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// The primary's heartbeat while this replica should stand by
+    ///
+    /// None means this server should answer: it is the primary, or the
+    /// primary is rebuilding, has stopped, or hasn't checked in within the
+    /// timeout.
+    pub fn standby_for(&self, now: DateTime<Utc>) -> Option<Heartbeat> {
+        if self.role != ServerRole::Replica {
+            return None;
+        }
+        Heartbeat::read(&self.heartbeat_path)
+            .filter(|heartbeat| heartbeat.state == PrimaryState::Serving && now - heartbeat.updated_at <= self.timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replica_takes_over_from_busy_or_silent_primary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.db.heartbeat");
+        let timeout = std::time::Duration::from_secs(30);
+        let mut primary = Failover::primary(&path, timeout);
+        let replica = Failover::replica(&path, timeout);
+        let now = Utc::now();
+
+        // No primary yet
+        assert!(replica.standby_for(now).is_none());
+        assert!(replica.next_beat().is_none());
+
+        primary.beat(PrimaryState::Serving).unwrap();
+        assert_eq!(replica.standby_for(now).map(|heartbeat| heartbeat.pid), Some(std::process::id()));
+        assert!(primary.standby_for(now).is_none());
+        assert_eq!(primary.next_beat(), primary.last_beat.map(|last_beat| last_beat + Duration::seconds(10)));
+
+        // Silent for longer than the timeout
+        assert!(replica.standby_for(now + Duration::seconds(31)).is_none());
+
+        primary.beat(PrimaryState::Rebuilding).unwrap();
+        assert!(replica.standby_for(now).is_none());
+
+        primary.beat(PrimaryState::Serving).unwrap();
+        primary.resign().unwrap();
+        assert!(!path.exists());
+        assert!(replica.standby_for(now).is_none());
+    }
+}
@@ -0,0 +1,586 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+use tracing::warn;
+
+use crate::lib::cpp_indexer::callbacks::{find_callback_sites, store_callback_relationships};
+use crate::lib::cpp_indexer::calls::store_call_relationships;
+use crate::lib::cpp_indexer::clang_parser::CallSite;
+use crate::lib::cpp_indexer::detail_tiers::{DetailPolicy, TieredElements};
+use crate::lib::cpp_indexer::index_settings::IndexSettings;
+use crate::lib::cpp_indexer::symbol_extractor::{ExtractedSymbol, ExtractionResult};
+use crate::lib::cpp_indexer::vfs::{parse_archive_uri, read_source, LocalFs, SourceFs};
+use crate::lib::cpp_indexer::walk_filter::glob_selects;
+use crate::lib::storage::file_moves::{match_moves, FileMove, FileOutline, DEFAULT_MOVE_SIMILARITY};
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::file_metadata::{FileDetail, FileMetadata};
+use crate::lib::storage::ordering::path_key;
+use crate::lib::storage::repository::Repository;
+
+/// Largest stale file, in KB, re-indexed inline before answering; larger ones are only flagged
+pub const DEFAULT_STALE_REINDEX_MAX_KB: u64 = 1024;
+
+/// Whether file-scoped tool calls check the index against the file on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleCheck {
+    /// Trust the index, e.g. because the watcher keeps it current
+    #[default]
+    Off,
+    /// Hash the file and flag answers from an outdated index
+    Flag,
+    /// Re-index a stale file before answering
+    Reindex,
+}
+
+impl StaleCheck {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "off" => Some(StaleCheck::Off),
+            "flag" => Some(StaleCheck::Flag),
+            "reindex" => Some(StaleCheck::Reindex),
+            _ => None,
+        }
+    }
+}
+
+/// How a file on disk compares with its indexed version
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Freshness {
+    /// Content hash matches the index
+    Fresh,
+    /// The file changed since it was indexed
+    Stale,
+    /// The file was deleted since it was indexed
+    Missing,
+    /// The index has no record of the file
+    NotIndexed,
+}
+
+/// Result of checking one file, attached to tool results as `freshness`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct FreshnessReport {
+    pub file_path: String,
+    pub status: Freshness,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indexed_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_hash: Option<String>,
+    /// Symbols stored by an inline re-index; the answer reflects the file on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reindexed_symbols: Option<usize>,
+}
+
+/// SHA-256 of file content, as stored in file_metadata.file_hash
+pub fn content_hash(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Files an index keeps when pruning: the `listed` ones and every indexed one still on disk
+///
+/// A file the walk no longer selects wasn't deleted, so it isn't pruned.
+pub fn files_to_keep(repository: &Repository, index: &CodeIndex, listed: &[String]) -> Result<BTreeSet<String>> {
+    let root = Path::new(&index.base_path);
+    let mut keep: BTreeSet<String> = listed.iter().cloned().collect();
+    for metadata in repository.list_file_metadata(&index.id)? {
+        if root.join(&metadata.file_path).exists() {
+            keep.insert(metadata.file_path);
+        }
+    }
+    Ok(keep)
+}
+
+/// Compares the on-disk content of a stored file with the hash it was indexed with
+pub fn check_file(repository: &Repository, index: &CodeIndex, stored_path: &str) -> Result<FreshnessReport> {
+    let metadata = repository.get_file_metadata_by_path(&index.id, stored_path)?;
+    let mut report = FreshnessReport {
+        file_path: stored_path.to_string(),
+        status: Freshness::NotIndexed,
+        indexed_hash: metadata.as_ref().map(|metadata| metadata.file_hash.clone()),
+        current_hash: None,
+        reindexed_symbols: None,
+    };
+    if metadata.is_none() {
+        return Ok(report);
+    }
+
+    match read_source(Path::new(&index.base_path), stored_path) {
+        Ok(content) => {
+            let current_hash = content_hash(content.as_bytes());
+            report.status = if report.indexed_hash.as_deref() == Some(current_hash.as_str()) {
+                Freshness::Fresh
+            } else {
+                Freshness::Stale
+            };
+            report.current_hash = Some(current_hash);
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => report.status = Freshness::Missing,
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", stored_path, e)),
+    }
+    Ok(report)
+}
+
+/// A file parsed again: the symbols to store, its includes and its calls
+#[derive(Debug, Clone, Default)]
+pub struct ReparsedFile {
+    pub tiered: TieredElements,
+    /// Paths the file `#include`s, as written
+    pub includes: Vec<String>,
+    pub calls: Vec<CallSite>,
+}
+
+/// Extracts the symbols of a stale file again, stored as `policy` says
+///
+/// Parses with the index's `settings`, as the file was parsed when the
+/// index was built. Runs without touching storage, so no repository lock
+/// is held while parsing; [`store_reindexed`] then swaps the result in.
+/// Files inside archives are never re-indexed inline.
+pub async fn extract_file(index: &CodeIndex, settings: &IndexSettings, stored_path: &str, policy: &DetailPolicy) -> Result<ReparsedFile> {
+    if parse_archive_uri(stored_path).is_some() {
+        return Err(anyhow!("Files inside archives are not re-indexed inline: {}", stored_path));
+    }
+
+    let file_path = Path::new(&index.base_path).join(stored_path);
+    let mut extractor = settings.extractor().map_err(|e| anyhow!("Failed to start symbol extraction: {}", e))?;
+    let extraction = extractor
+        .extract_symbols(&file_path)
+        .await
+        .map_err(|e| anyhow!("Failed to re-index {}: {}", stored_path, e))?;
+
+    Ok(ReparsedFile::from_extraction(&extraction, index, settings, stored_path, policy))
+}
+
+impl ReparsedFile {
+    /// What to store of a file's extraction: its own symbols the index keeps, its includes and calls
+    pub fn from_extraction(extraction: &ExtractionResult, index: &CodeIndex, settings: &IndexSettings, stored_path: &str, policy: &DetailPolicy) -> Self {
+        // Skip symbols the parser reported for included headers
+        let symbols: Vec<ExtractedSymbol> = extraction
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.file_path.ends_with(stored_path) && settings.symbol_filter.keeps(symbol))
+            .cloned()
+            .collect();
+        Self {
+            tiered: policy.apply(&symbols, index.id, stored_path),
+            includes: extraction.includes.clone(),
+            calls: extraction.calls.clone(),
+        }
+    }
+}
+
+/// Replaces a file's indexed symbols with re-extracted ones and records its new hash
+///
+/// Symbols still in the file keep their ids, so calls into it from other
+/// files stay; the file's own includes, calls and callback references are
+/// stored again. A file
+/// the report found not indexed is added; the report must then carry its
+/// current hash.
+pub fn store_reindexed(repository: &Repository, index: &CodeIndex, report: &mut FreshnessReport, reparsed: ReparsedFile) -> Result<()> {
+    let ReparsedFile { tiered, includes, calls } = reparsed;
+    if report.status == Freshness::NotIndexed {
+        let metadata = FileMetadata::new(index.id, report.file_path.clone(), report.current_hash.clone().unwrap_or_default(), Utc::now(), 0);
+        repository.create_file_metadata(metadata)?;
+    }
+    let mut metadata = repository
+        .get_file_metadata_by_path(&index.id, &report.file_path)?
+        .ok_or_else(|| anyhow!("File not indexed: {}", report.file_path))?;
+    let current_hash = report
+        .current_hash
+        .clone()
+        .ok_or_else(|| anyhow!("No current hash for {}", report.file_path))?;
+    let path = Path::new(&index.base_path).join(&report.file_path);
+    let disk = std::fs::metadata(&path)?;
+    let callback_sites = find_callback_sites(&String::from_utf8_lossy(&std::fs::read(&path)?));
+
+    metadata.file_hash = current_hash.clone();
+    metadata.size_bytes = disk.len();
+    metadata.last_modified = disk.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now());
+    metadata.symbol_count = tiered.elements.len() as u32;
+    metadata.indexed_at = Utc::now();
+    metadata.detail = if tiered.outlined + tiered.omitted > 0 { FileDetail::Reduced } else { FileDetail::Full };
+    let stored = repository.replace_file_elements(&metadata, tiered.elements)?;
+    repository.replace_file_includes(&index.id, &report.file_path, &includes)?;
+    repository.resolve_file_includes(&index.id)?;
+    if !calls.is_empty() {
+        store_call_relationships(repository, index, vec![(report.file_path.clone(), calls)])?;
+    }
+    if !callback_sites.is_empty() {
+        store_callback_relationships(repository, index, vec![(report.file_path.clone(), callback_sites)])?;
+    }
+
+    report.status = Freshness::Fresh;
+    report.indexed_hash = Some(current_hash);
+    report.reindexed_symbols = Some(stored.len());
+    Ok(())
+}
+
+/// Files of an index an update_path call covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathScope {
+    /// A file, or the files below a directory; "" is the whole index
+    Directory(String),
+    /// Files matching an index_codebase glob, relative to the base path
+    Glob(String),
+}
+
+impl PathScope {
+    /// Scope of a path given absolute or relative to the base path; `*`, `?` or `[` make it a glob
+    pub fn parse(base_path: &str, path: &str) -> Self {
+        let relative = Path::new(path).strip_prefix(base_path).unwrap_or(Path::new(path));
+        let stored = path_key(relative).trim_end_matches('/').to_string();
+        let stored = if stored == "." { String::new() } else { stored };
+        if stored.contains(['*', '?', '[']) {
+            PathScope::Glob(stored)
+        } else {
+            PathScope::Directory(stored)
+        }
+    }
+
+    /// Returns true if a stored path is in the scope
+    pub fn contains(&self, file_path: &str) -> bool {
+        match self {
+            PathScope::Directory(directory) => {
+                directory.is_empty()
+                    || file_path == directory
+                    || file_path.strip_prefix(directory.as_str()).is_some_and(|rest| rest.starts_with('/'))
+            }
+            PathScope::Glob(pattern) => glob_selects(pattern, file_path),
+        }
+    }
+}
+
+/// What bringing a scope up to date takes
+#[derive(Debug, Clone, Default)]
+pub struct PathUpdate {
+    /// New and changed files in the scope, to parse again
+    pub changed: Vec<FreshnessReport>,
+    /// Files outside the scope including a changed or deleted file, directly or not
+    pub dependents: Vec<FreshnessReport>,
+    /// Indexed files in the scope deleted from disk
+    pub missing: Vec<String>,
+    /// Files in the scope matching the index
+    pub unchanged: usize,
+}
+
+/// Compares the files of a scope with the index
+///
+/// `on_disk` lists the source files in the scope the walk rules select;
+/// indexed files in the scope are checked as well, so deleted ones are
+/// found. With `follow_includes` the files including a changed or deleted
+/// file are added, following the include graph until no new file turns
+/// up: their symbols depend on what they include, so they are parsed
+/// again even when their own content is unchanged.
+pub fn plan_path_update(
+    repository: &Repository,
+    index: &CodeIndex,
+    scope: &PathScope,
+    on_disk: &[String],
+    follow_includes: bool,
+) -> Result<PathUpdate> {
+    let mut files: BTreeSet<String> = on_disk.iter().cloned().collect();
+    files.extend(
+        repository
+            .list_file_metadata(&index.id)?
+            .into_iter()
+            .map(|metadata| metadata.file_path)
+            .filter(|file_path| scope.contains(file_path)),
+    );
+
+    let mut update = PathUpdate::default();
+    let mut touched = Vec::new();
+    for file_path in &files {
+        let mut report = check_file(repository, index, file_path)?;
+        match report.status {
+            Freshness::Fresh => update.unchanged += 1,
+            Freshness::Missing => {
+                touched.push(report.file_path.clone());
+                update.missing.push(report.file_path);
+            }
+            Freshness::Stale => {
+                touched.push(report.file_path.clone());
+                update.changed.push(report);
+            }
+            Freshness::NotIndexed => {
+                let content = read_source(Path::new(&index.base_path), file_path).map_err(|e| anyhow!("Failed to read {}: {}", file_path, e))?;
+                report.current_hash = Some(content_hash(content.as_bytes()));
+                update.changed.push(report);
+            }
+        }
+    }
+
+    if follow_includes {
+        let mut frontier = touched;
+        while !frontier.is_empty() {
+            let including = repository.get_including_files(&index.id, &frontier)?;
+            frontier = Vec::new();
+            for include in including {
+                if !files.insert(include.file_path.clone()) {
+                    continue;
+                }
+                let report = check_file(repository, index, &include.file_path)?;
+                if report.status != Freshness::Missing {
+                    frontier.push(report.file_path.clone());
+                    update.dependents.push(report);
+                }
+            }
+        }
+        update.dependents.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    }
+    Ok(update)
+}
+
+/// An indexed file that is gone from disk, as stored
+#[derive(Debug, Clone)]
+pub struct MissingFile {
+    pub file_path: String,
+    pub file_hash: String,
+    pub elements: Vec<CodeElement>,
+}
+
+/// Indexed files of an index gone from disk, with the paths of all its indexed files
+pub fn missing_files(repository: &Repository, index: &CodeIndex) -> Result<(Vec<MissingFile>, BTreeSet<String>)> {
+    let mut missing = Vec::new();
+    let mut indexed = BTreeSet::new();
+    for metadata in repository.list_file_metadata(&index.id)? {
+        if !Path::new(&index.base_path).join(&metadata.file_path).is_file() {
+            missing.push(MissingFile {
+                elements: repository.list_code_elements_by_file(&index.id, &metadata.file_path)?,
+                file_path: metadata.file_path.clone(),
+                file_hash: metadata.file_hash,
+            });
+        }
+        indexed.insert(metadata.file_path);
+    }
+    Ok((missing, indexed))
+}
+
+/// A missing file found at a new path, with its symbols extracted there
+#[derive(Debug, Clone)]
+pub struct DetectedMove {
+    pub file_move: FileMove,
+    pub current_hash: String,
+    pub tiered: TieredElements,
+}
+
+/// Looks for missing files among the unindexed files `settings` selects under an index's base path
+///
+/// A file with the content of a missing one is taken as moved without
+/// comparing outlines; the other new files are parsed and paired with the
+/// remaining missing files by outline similarity. Only moved files are
+/// returned; other new files are left for a full index run.
+pub async fn detect_moves(
+    index: &CodeIndex,
+    settings: &IndexSettings,
+    missing: &[MissingFile],
+    indexed: &BTreeSet<String>,
+    policy: &DetailPolicy,
+) -> Result<Vec<DetectedMove>> {
+    let root = Path::new(&index.base_path);
+    if missing.is_empty() || !root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let fs = LocalFs::new(root);
+    let added: Vec<String> = settings.source_files(root)?.into_iter().filter(|path| !indexed.contains(path)).collect();
+
+    let mut moves = Vec::new();
+    let mut unmatched: Vec<&MissingFile> = missing.iter().collect();
+    let mut candidates = Vec::new();
+    for path in added {
+        let Ok(content) = fs.read(&path) else { continue };
+        let hash = content_hash(&content);
+        match unmatched.iter().position(|file| file.file_hash == hash) {
+            Some(position) => {
+                let from = unmatched.remove(position).file_path.clone();
+                moves.push((FileMove { from, to: path, similarity: 1.0 }, hash));
+            }
+            None => candidates.push((path, hash)),
+        }
+    }
+
+    let mut extracted = Vec::new();
+    if !unmatched.is_empty() {
+        for (path, hash) in candidates {
+            match extract_file(index, settings, &path, policy).await {
+                Ok(reparsed) => extracted.push((path, hash, reparsed.tiered)),
+                Err(e) => warn!("Move detection skipped {}: {}", path, e),
+            }
+        }
+        let removed: Vec<(String, FileOutline)> =
+            unmatched.iter().map(|file| (file.file_path.clone(), FileOutline::new(&file.elements))).collect();
+        let added: Vec<(String, FileOutline)> =
+            extracted.iter().map(|(path, _, tiered)| (path.clone(), FileOutline::new(&tiered.elements))).collect();
+        for file_move in match_moves(&removed, &added, DEFAULT_MOVE_SIMILARITY) {
+            let hash = extracted.iter().find(|(path, _, _)| *path == file_move.to).map(|(_, hash, _)| hash.clone()).unwrap_or_default();
+            moves.push((file_move, hash));
+        }
+    }
+
+    let mut detected = Vec::new();
+    for (file_move, current_hash) in moves {
+        let tiered = match extracted.iter().position(|(path, _, _)| *path == file_move.to) {
+            Some(position) => extracted.swap_remove(position).2,
+            None => match extract_file(index, settings, &file_move.to, policy).await {
+                Ok(reparsed) => reparsed.tiered,
+                Err(e) => {
+                    warn!("Move detection skipped {}: {}", file_move.to, e);
+                    continue;
+                }
+            },
+        };
+        detected.push(DetectedMove { file_move, current_hash, tiered });
+    }
+    Ok(detected)
+}
+
+/// Moves a file's index entry to where it was found, keeping its symbols' ids
+pub fn store_moved(repository: &Repository, index: &CodeIndex, detected: DetectedMove) -> Result<usize> {
+    let disk = std::fs::metadata(Path::new(&index.base_path).join(&detected.file_move.to))?;
+    let mut metadata = repository
+        .get_file_metadata_by_path(&index.id, &detected.file_move.from)?
+        .ok_or_else(|| anyhow!("File not indexed: {}", detected.file_move.from))?;
+    metadata.file_path = detected.file_move.to.clone();
+    metadata.file_hash = detected.current_hash;
+    metadata.size_bytes = disk.len();
+    metadata.last_modified = disk.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now());
+    metadata.symbol_count = detected.tiered.elements.len() as u32;
+    metadata.indexed_at = Utc::now();
+    metadata.detail = if detected.tiered.outlined + detected.tiered.omitted > 0 { FileDetail::Reduced } else { FileDetail::Full };
+    Ok(repository.move_file(&detected.file_move.from, &metadata, detected.tiered.elements)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+
+    #[test]
+    fn test_check_and_store_reindexed() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/ring.h"), "void drain();").unwrap();
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        repository
+            .create_file_metadata(FileMetadata::new(index.id, "src/ring.h".to_string(), content_hash(b"void drain();"), Utc::now(), 13))
+            .unwrap();
+        repository
+            .create_code_element(CodeElement::new(index.id, "drain".to_string(), SymbolType::Function, "src/ring.h".to_string(), 1, 1, "a".repeat(64)))
+            .unwrap();
+
+        assert_eq!(check_file(&repository, &index, "src/ring.h").unwrap().status, Freshness::Fresh);
+        assert_eq!(check_file(&repository, &index, "src/mixer.cpp").unwrap().status, Freshness::NotIndexed);
+
+        std::fs::write(dir.path().join("src/ring.h"), "void drain();\nvoid fill();").unwrap();
+        let mut report = check_file(&repository, &index, "src/ring.h").unwrap();
+        assert_eq!(report.status, Freshness::Stale);
+        assert_ne!(report.indexed_hash, report.current_hash);
+
+        let elements = ["drain", "fill"]
+            .iter()
+            .zip(1..)
+            .map(|(name, line)| CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/ring.h".to_string(), line, 1, "b".repeat(64)))
+            .collect();
+        let reparsed = ReparsedFile { tiered: TieredElements { elements, outlined: 1, omitted: 0 }, ..ReparsedFile::default() };
+        store_reindexed(&repository, &index, &mut report, reparsed).unwrap();
+        assert_eq!(report.reindexed_symbols, Some(2));
+        let metadata = repository.get_file_metadata_by_path(&index.id, "src/ring.h").unwrap().unwrap();
+        assert_eq!(metadata.detail, FileDetail::Reduced);
+        assert_eq!(repository.list_code_elements_by_file(&index.id, "src/ring.h").unwrap().len(), 2);
+        assert_eq!(check_file(&repository, &index, "src/ring.h").unwrap().status, Freshness::Fresh);
+
+        std::fs::remove_file(dir.path().join("src/ring.h")).unwrap();
+        assert_eq!(check_file(&repository, &index, "src/ring.h").unwrap().status, Freshness::Missing);
+    }
+
+    #[test]
+    fn test_files_to_keep() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("kept.cpp"), "int kept;").unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("keep".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for file in ["kept.cpp", "deleted.cpp"] {
+            repository.create_file_metadata(FileMetadata::new(index.id, file.to_string(), "c".repeat(64), Utc::now(), 1)).unwrap();
+        }
+
+        // An indexed file left out of the listing stays while it is on disk
+        let keep = files_to_keep(&repository, &index, &["new.cpp".to_string()]).unwrap();
+        assert_eq!(keep.into_iter().collect::<Vec<_>>(), ["kept.cpp", "new.cpp"]);
+    }
+
+    #[test]
+    fn test_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("kept.cpp"), "int kept;").unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("moves".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for file in ["kept.cpp", "moved.cpp"] {
+            repository.create_file_metadata(FileMetadata::new(index.id, file.to_string(), "d".repeat(64), Utc::now(), 1)).unwrap();
+        }
+        repository
+            .create_code_element(CodeElement::new(index.id, "fade".to_string(), SymbolType::Function, "moved.cpp".to_string(), 1, 1, "e".repeat(64)))
+            .unwrap();
+
+        let (missing, indexed) = missing_files(&repository, &index).unwrap();
+        assert_eq!(indexed.into_iter().collect::<Vec<_>>(), ["kept.cpp", "moved.cpp"]);
+        assert_eq!(missing.len(), 1);
+        assert_eq!((missing[0].file_path.as_str(), missing[0].file_hash.clone()), ("moved.cpp", "d".repeat(64)));
+        assert_eq!(missing[0].elements[0].symbol_name, "fade");
+    }
+
+    #[test]
+    fn test_plan_path_update() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/dsp")).unwrap();
+        for (file, content) in [("src/dsp/filter.h", "void run();"), ("src/dsp/gain.h", "float gain();"), ("src/mixer.h", "#include \"dsp/filter.h\""), ("main.cpp", "#include \"src/mixer.h\"")] {
+            std::fs::write(dir.path().join(file), content).unwrap();
+        }
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let base_path = dir.path().to_string_lossy().to_string();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), base_path.clone())).unwrap();
+        for (file, content) in [("src/dsp/filter.h", "old"), ("src/dsp/gain.h", "float gain();"), ("src/dsp/gone.h", ""), ("src/mixer.h", "#include \"dsp/filter.h\""), ("main.cpp", "#include \"src/mixer.h\"")] {
+            repository
+                .create_file_metadata(FileMetadata::new(index.id, file.to_string(), content_hash(content.as_bytes()), Utc::now(), 0))
+                .unwrap();
+        }
+        repository.replace_file_includes(&index.id, "src/mixer.h", &["dsp/filter.h".to_string()]).unwrap();
+        repository.replace_file_includes(&index.id, "main.cpp", &["src/mixer.h".to_string()]).unwrap();
+        repository.resolve_file_includes(&index.id).unwrap();
+        std::fs::write(dir.path().join("src/dsp/eq.h"), "void eq();").unwrap();
+
+        let scope = PathScope::parse(&base_path, &format!("{}/src/dsp/", base_path));
+        assert_eq!(scope, PathScope::Directory("src/dsp".to_string()));
+        assert!(scope.contains("src/dsp/gain.h") && !scope.contains("src/dspx.h"));
+        let on_disk = ["src/dsp/eq.h", "src/dsp/filter.h", "src/dsp/gain.h"].map(str::to_string);
+        let update = plan_path_update(&repository, &index, &scope, &on_disk, true).unwrap();
+        let paths = |reports: &[FreshnessReport]| reports.iter().map(|report| report.file_path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&update.changed), ["src/dsp/eq.h", "src/dsp/filter.h"]);
+        assert!(update.changed.iter().all(|report| report.current_hash.is_some()));
+        assert_eq!(paths(&update.dependents), ["main.cpp", "src/mixer.h"]);
+        assert_eq!((update.missing.as_slice(), update.unchanged), (["src/dsp/gone.h".to_string()].as_slice(), 1));
+
+        let update = plan_path_update(&repository, &index, &PathScope::parse(&base_path, "**/*.h"), &on_disk, false).unwrap();
+        assert!(update.dependents.is_empty());
+        assert_eq!(update.changed.len() + update.missing.len() + update.unchanged, 5);
+    }
+}
@@ -0,0 +1,158 @@
+// Content grep
+//
+// Symbol search only sees what the parser made into symbols: string
+// literals, comments and TODOs never reach it. grep_code scans the text of
+// the indexed files instead and names, for every hit, the innermost symbol
+// whose block contains the line, so "TODO: drain" comes back as being in
+// `audio::Mixer::flush` rather than on line 214 of some file.
+//
+// The files searched are the index's own, read the way every other tool
+// reads them, so a tree walk with its own ignore rules would search a
+// different set of files than was indexed. Each file is first tested as a
+// whole, as ripgrep's searcher does, and split into lines only if it holds
+// a match.
+
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use super::outline::OutlineNode;
+use crate::lib::storage::models::code_element::CodeElement;
+
+/// Longest line returned with a hit; longer lines are cut around the match
+pub const MAX_GREP_LINE_CHARS: usize = 240;
+
+/// What to look for and how
+#[derive(Debug, Clone)]
+pub struct GrepPattern {
+    regex: Regex,
+}
+
+/// One matching line
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GrepMatch {
+    pub line_number: u32,
+    /// 1-based byte column of the first match on the line
+    pub column_number: u32,
+    /// The line, trimmed, and cut to `MAX_GREP_LINE_CHARS` around the match
+    pub line: String,
+    /// Text of the first match
+    pub matched: String,
+}
+
+impl GrepPattern {
+    /// Compiles a pattern; without `regex` the query is matched literally
+    pub fn new(query: &str, regex: bool, case_sensitive: bool, whole_word: bool) -> Result<Self, regex::Error> {
+        let pattern = if regex { query.to_string() } else { regex::escape(query) };
+        let pattern = if whole_word { format!(r"\b(?:{})\b", pattern) } else { pattern };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!case_sensitive)
+            .multi_line(true)
+            .size_limit(1 << 20)
+            .build()?;
+        Ok(Self { regex })
+    }
+
+    /// Matching lines of a file's text, in order, at most `limit`
+    pub fn search(&self, source: &str, limit: usize) -> Vec<GrepMatch> {
+        if !self.regex.is_match(source) {
+            return Vec::new();
+        }
+        source
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let found = self.regex.find(line)?;
+                Some(GrepMatch {
+                    line_number: index as u32 + 1,
+                    column_number: found.start() as u32 + 1,
+                    line: clip_line(line, found.start(), found.end()),
+                    matched: found.as_str().to_string(),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+/// Innermost symbol of an outline whose extent covers `line_number`
+///
+/// Outlines built from source give blocks their full extent; other symbols
+/// only cover their own line.
+pub fn enclosing_symbol(outline: &[OutlineNode], line_number: u32) -> Option<&CodeElement> {
+    let node = outline
+        .iter()
+        .find(|node| node.element.line_number <= line_number && line_number <= node.end_line)?;
+    enclosing_symbol(&node.children, line_number).or(Some(&node.element))
+}
+
+/// Trims a line and, if it is still too long, keeps a window around the match
+fn clip_line(line: &str, start: usize, end: usize) -> String {
+    let leading = line.len() - line.trim_start().len();
+    let trimmed = line.trim();
+    let length = trimmed.chars().count();
+    if length <= MAX_GREP_LINE_CHARS {
+        return trimmed.to_string();
+    }
+    let start = start.saturating_sub(leading).min(trimmed.len());
+    let end = end.saturating_sub(leading).clamp(start, trimmed.len());
+    let before = trimmed[..start].chars().count();
+    let matched = trimmed[start..end].chars().count();
+    let skip = before
+        .saturating_sub(MAX_GREP_LINE_CHARS.saturating_sub(matched) / 2)
+        .min(length - MAX_GREP_LINE_CHARS);
+    trimmed.chars().skip(skip).take(MAX_GREP_LINE_CHARS).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::mcp_server::outline::build_outline;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    const MIXER: &str = r#"namespace audio {
+// TODO: pool the buffers
+class Mixer {
+    void flush() {
+        log("flush: todo drain");
+    }
+};
+}
+"#;
+
+    #[test]
+    fn test_grep_with_enclosing_symbols() {
+        let index_id = Uuid::new_v4();
+        let element = |name: &str, symbol_type, line, scope: Option<&str>| {
+            let element = CodeElement::new(index_id, name.to_string(), symbol_type, "mixer.cpp".to_string(), line, 1, "a".repeat(64));
+            match scope {
+                Some(scope) => element.with_scope(scope.to_string()),
+                None => element,
+            }
+        };
+        let elements = vec![
+            element("audio", SymbolType::Namespace, 1, None),
+            element("Mixer", SymbolType::Class, 3, Some("audio")),
+            element("flush", SymbolType::Function, 4, Some("audio::Mixer")),
+        ];
+        let outline = build_outline(&elements, Some(MIXER));
+
+        let hits = GrepPattern::new("todo", false, false, false).unwrap().search(MIXER, 10);
+        assert_eq!(hits.iter().map(|hit| (hit.line_number, hit.column_number)).collect::<Vec<_>>(), vec![(2, 4), (5, 21)]);
+        assert_eq!(hits[1].line, r#"log("flush: todo drain");"#);
+        let enclosing: Vec<String> = hits.iter().filter_map(|hit| enclosing_symbol(&outline, hit.line_number)).map(|element| element.fully_qualified_name()).collect();
+        assert_eq!(enclosing, vec!["audio", "audio::Mixer::flush"]);
+
+        assert_eq!(GrepPattern::new("TODO", false, true, false).unwrap().search(MIXER, 10).len(), 1);
+        assert!(GrepPattern::new("flus", false, true, true).unwrap().search(MIXER, 10).is_empty());
+        let regex = GrepPattern::new(r"void\s+\w+\(\)", true, true, false).unwrap().search(MIXER, 10);
+        assert_eq!((regex.len(), regex[0].matched.as_str()), (1, "void flush()"));
+        assert!(GrepPattern::new("(", true, true, false).is_err());
+        assert!(GrepPattern::new("(", false, true, false).is_ok());
+
+        let long = format!("{}needle{}", "x".repeat(500), "y".repeat(500));
+        let clipped = GrepPattern::new("needle", false, true, false).unwrap().search(&long, 1);
+        assert_eq!(clipped[0].line.chars().count(), MAX_GREP_LINE_CHARS);
+        assert!(clipped[0].line.contains("needle"));
+    }
+}
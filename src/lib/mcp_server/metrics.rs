@@ -0,0 +1,210 @@
+// Per-tool metrics
+//
+// Every tool call is counted with its latency and outcome, next to the
+// timings of the database queries the server's repositories run. Clients
+// read them as JSON from the `cpp-index://stats` resource; the same numbers
+// render in the Prometheus text format for `cpp-index://metrics` and for an
+// HTTP endpoint once the server has an HTTP transport.
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::lib::storage::models::mcp_query_session::SessionStats;
+use crate::lib::storage::timings::{LatencyStats, QueryTimings, ToolStats};
+
+/// URI of the JSON statistics resource
+pub const STATS_RESOURCE_URI: &str = "cpp-index://stats";
+
+/// URI of the same statistics in the Prometheus text format
+pub const METRICS_RESOURCE_URI: &str = "cpp-index://metrics";
+
+/// MIME type of the Prometheus text exposition format
+pub const PROMETHEUS_MIME_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Tool call and database query metrics of a server, shared by its clones
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    started_at: DateTime<Utc>,
+    tools: Arc<Mutex<BTreeMap<String, ToolStats>>>,
+    queries: QueryTimings,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started_at: Utc::now(),
+            tools: Arc::new(Mutex::new(BTreeMap::new())),
+            queries: QueryTimings::new(),
+        }
+    }
+
+    /// Timings to attach to every repository whose queries should be counted
+    pub fn query_timings(&self) -> &QueryTimings {
+        &self.queries
+    }
+
+    /// Counts a call of `tool` that took `elapsed` and, if it failed, its error kind
+    pub fn record_tool_call(&self, tool: &str, elapsed: Duration, error_kind: Option<&str>) {
+        if let Ok(mut tools) = self.tools.lock() {
+            tools.entry(tool.to_string()).or_default().record(elapsed, error_kind);
+        }
+    }
+
+    /// Calls of each tool so far
+    pub fn tool_stats(&self) -> BTreeMap<String, ToolStats> {
+        self.tools.lock().map(|tools| tools.clone()).unwrap_or_default()
+    }
+
+    /// Totals over every call since the server started
+    pub fn session_stats(&self) -> SessionStats {
+        SessionStats::from_tools(&self.tool_stats())
+    }
+
+    /// Statistics as served by the `cpp-index://stats` resource
+    pub fn to_json(&self) -> Value {
+        let session = self.session_stats();
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let tools: BTreeMap<String, Value> = self
+            .tool_stats()
+            .into_iter()
+            .map(|(name, stats)| {
+                let entry = json!({
+                    "calls": stats.calls,
+                    "errors": stats.errors,
+                    "error_rate": stats.error_rate(),
+                    "error_kinds": stats.error_kinds,
+                    "avg_ms": stats.latency.mean_ms(),
+                    "max_ms": millis(stats.latency.max)
+                });
+                (name, entry)
+            })
+            .collect();
+        let queries: BTreeMap<String, Value> = self
+            .queries
+            .snapshot()
+            .into_iter()
+            .map(|(operation, stats)| {
+                let entry = json!({
+                    "count": stats.count,
+                    "total_ms": millis(stats.total),
+                    "avg_ms": stats.mean_ms(),
+                    "max_ms": millis(stats.max)
+                });
+                (operation, entry)
+            })
+            .collect();
+
+        json!({
+            "started_at": self.started_at.to_rfc3339(),
+            "uptime_seconds": (Utc::now() - self.started_at).num_seconds(),
+            "session": {
+                "total_queries": session.total_queries,
+                "successful_queries": session.successful_queries,
+                "failed_queries": session.failed_queries,
+                "success_rate": session.success_rate(),
+                "error_rate": session.error_rate(),
+                "avg_response_time_ms": session.avg_response_time_ms,
+                "most_used_tool": session.most_used_tool
+            },
+            "tools": tools,
+            "database_queries": queries
+        })
+    }
+
+    /// Statistics in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let tools = self.tool_stats();
+        let mut text = String::new();
+
+        let uptime = (Utc::now() - self.started_at).num_milliseconds() as f64 / 1000.0;
+        header(&mut text, "cpp_index_uptime_seconds", "gauge", "Seconds since the server started");
+        let _ = writeln!(text, "cpp_index_uptime_seconds {}", uptime);
+
+        header(&mut text, "cpp_index_tool_calls_total", "counter", "Tool calls by tool");
+        for (tool, stats) in &tools {
+            let _ = writeln!(text, "cpp_index_tool_calls_total{{tool=\"{}\"}} {}", escape_label(tool), stats.calls);
+        }
+
+        header(&mut text, "cpp_index_tool_errors_total", "counter", "Failed tool calls by tool and error kind");
+        for (tool, stats) in &tools {
+            for (kind, errors) in &stats.error_kinds {
+                let labels = format!("tool=\"{}\",kind=\"{}\"", escape_label(tool), escape_label(kind));
+                let _ = writeln!(text, "cpp_index_tool_errors_total{{{}}} {}", labels, errors);
+            }
+        }
+
+        header(&mut text, "cpp_index_tool_duration_seconds", "histogram", "Tool call latency");
+        for (tool, stats) in &tools {
+            histogram(&mut text, "cpp_index_tool_duration_seconds", &format!("tool=\"{}\"", escape_label(tool)), &stats.latency);
+        }
+
+        header(&mut text, "cpp_index_db_query_duration_seconds", "histogram", "Database query latency by repository operation");
+        for (operation, stats) in &self.queries.snapshot() {
+            let labels = format!("operation=\"{}\"", escape_label(operation));
+            histogram(&mut text, "cpp_index_db_query_duration_seconds", &labels, stats);
+        }
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+fn histogram(text: &mut String, name: &str, labels: &str, stats: &LatencyStats) {
+    for (bound, count) in stats.cumulative_buckets() {
+        let _ = writeln!(text, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound.as_secs_f64(), count);
+    }
+    let _ = writeln!(text, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, stats.count);
+    let _ = writeln!(text, "{}_sum{{{}}} {}", name, labels, stats.total.as_secs_f64());
+    let _ = writeln!(text, "{}_count{{{}}} {}", name, labels, stats.count);
+}
+
+/// Escapes a label value as the text format requires
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_metrics() {
+        let metrics = Metrics::new();
+        let shared = metrics.clone();
+        metrics.record_tool_call("search_symbols", Duration::from_millis(4), None);
+        shared.record_tool_call("search_symbols", Duration::from_millis(20), Some("validation"));
+        metrics.record_tool_call("list_indices", Duration::from_millis(6), None);
+        metrics.query_timings().record("list_code_indices", Duration::from_millis(2));
+
+        let session = metrics.session_stats();
+        assert_eq!((session.total_queries, session.successful_queries, session.failed_queries), (3, 2, 1));
+        assert_eq!(session.most_used_tool.as_deref(), Some("search_symbols"));
+        assert!((session.avg_response_time_ms.unwrap() - 10.0).abs() < 1e-6);
+
+        let stats = metrics.to_json();
+        assert_eq!(stats["tools"]["search_symbols"]["error_kinds"]["validation"], 1);
+        assert_eq!(stats["tools"]["search_symbols"]["error_rate"], 0.5);
+        assert_eq!(stats["database_queries"]["list_code_indices"]["count"], 1);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("cpp_index_tool_calls_total{tool=\"search_symbols\"} 2\n"));
+        assert!(text.contains("cpp_index_tool_errors_total{tool=\"search_symbols\",kind=\"validation\"} 1\n"));
+        assert!(text.contains("cpp_index_tool_duration_seconds_bucket{tool=\"search_symbols\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("cpp_index_tool_duration_seconds_bucket{tool=\"search_symbols\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("cpp_index_db_query_duration_seconds_count{operation=\"list_code_indices\"} 1\n"));
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -7,6 +7,24 @@ pub mod server;
 pub mod tool_handlers;
 pub mod resource_handlers;
 pub mod transport;
+pub mod diagnostics;
+pub mod context_pack;
+pub mod cursor;
+pub mod references;
+pub mod freshness;
+pub mod resolve;
+pub mod telemetry;
+pub mod review;
+pub mod risk;
+pub mod overlay;
+pub mod registry;
+pub mod scheduler;
+pub mod failover;
+pub mod progress;
+pub mod metrics;
+pub mod outline;
+pub mod query_cache;
+pub mod grep;
 
 pub use server::{McpServer, ServerInfo, ServerCapabilities};
 pub use tool_handlers::ToolHandlers;
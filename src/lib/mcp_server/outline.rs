@@ -0,0 +1,185 @@
+// File outlines
+//
+// Nests the symbols of one file by scope, namespace > class > members, the
+// way an editor's outline pane shows them. Symbols are nested by the line
+// ranges of their blocks when the source can be read, and by their stored
+// scope otherwise, e.g. for a method declared in a class of another file.
+
+use serde_json::{json, Value};
+
+use crate::lib::cpp_indexer::callbacks::mask_non_code;
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+
+/// A symbol of the outline with the symbols declared inside it
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineNode {
+    pub element: CodeElement,
+    /// Last line of the symbol's block, or of its declaration
+    pub end_line: u32,
+    pub children: Vec<OutlineNode>,
+}
+
+impl OutlineNode {
+    /// Symbols in this subtree, itself included
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().map(OutlineNode::size).sum::<usize>()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.element.id,
+            "name": self.element.symbol_name,
+            "type": self.element.symbol_type.as_str(),
+            "line_number": self.element.line_number,
+            "column_number": self.element.column_number,
+            "end_line": self.end_line,
+            "signature": self.element.signature,
+            "access": self.element.access_modifier.map(|access| access.as_str()),
+            "is_declaration": self.element.is_declaration,
+            "children": self.children.iter().map(OutlineNode::to_json).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// Outline of a file's symbols, in source order at every level
+///
+/// `source` is the file's text. With it symbols nest inside the blocks of
+/// the namespaces and types they are written in, so an out-of-line method
+/// definition stays at the top level as it does in an editor; without it
+/// every symbol spans its own line and symbols nest by their stored scope.
+pub fn build_outline(elements: &[CodeElement], source: Option<&str>) -> Vec<OutlineNode> {
+    let mut elements: Vec<&CodeElement> = elements.iter().collect();
+    elements.sort_by_key(|element| (element.line_number, element.column_number));
+
+    // Last line of each symbol's block; None for symbols without one, like declarations
+    let blocks: Vec<Option<u32>> = match source.map(mask_non_code) {
+        Some(code) => elements
+            .iter()
+            .map(|element| if is_block(element.symbol_type) { block_end(&code, element.line_number) } else { None })
+            .collect(),
+        None => vec![None; elements.len()],
+    };
+
+    // Parents come before their children in source order, so indices only point backwards
+    let parents: Vec<Option<usize>> = (0..elements.len())
+        .map(|child| {
+            let mut candidates = (0..child).rev().filter(|&parent| is_container(elements[parent].symbol_type));
+            match source {
+                Some(_) => candidates.find(|&parent| blocks[parent].is_some_and(|end| end >= elements[child].line_number)),
+                None => {
+                    let scope = elements[child].scope.as_deref().filter(|scope| !scope.is_empty())?;
+                    candidates.find(|&parent| elements[parent].fully_qualified_name() == scope)
+                }
+            }
+        })
+        .collect();
+    let end_lines: Vec<u32> = elements.iter().zip(&blocks).map(|(element, block)| block.unwrap_or(element.line_number)).collect();
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); elements.len()];
+    let mut roots = Vec::new();
+    for (index, parent) in parents.iter().enumerate() {
+        match parent {
+            Some(parent) => children[*parent].push(index),
+            None => roots.push(index),
+        }
+    }
+
+    fn node(index: usize, elements: &[&CodeElement], end_lines: &[u32], children: &[Vec<usize>]) -> OutlineNode {
+        OutlineNode {
+            element: elements[index].clone(),
+            end_line: end_lines[index],
+            children: children[index].iter().map(|&child| node(child, elements, end_lines, children)).collect(),
+        }
+    }
+    roots.into_iter().map(|root| node(root, &elements, &end_lines, &children)).collect()
+}
+
+/// Line of the `}` closing the first block at or after `start_line`
+///
+/// None if a `;` comes first, as it does for a declaration. `code` must
+/// have comments and literals masked.
+fn block_end(code: &str, start_line: u32) -> Option<u32> {
+    let mut depth = 0usize;
+    for (line_index, line) in code.lines().enumerate().skip(start_line.saturating_sub(1) as usize) {
+        for byte in line.bytes() {
+            match byte {
+                b';' if depth == 0 => return None,
+                b'{' => depth += 1,
+                b'}' => {
+                    depth = depth.checked_sub(1)?;
+                    if depth == 0 {
+                        return Some(line_index as u32 + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Symbols whose extent is a `{ ... }` block
+fn is_block(symbol_type: SymbolType) -> bool {
+    is_container(symbol_type) || matches!(symbol_type, SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor | SymbolType::Operator)
+}
+
+/// Symbols shown with other symbols nested inside them
+fn is_container(symbol_type: SymbolType) -> bool {
+    matches!(symbol_type, SymbolType::Namespace | SymbolType::Class | SymbolType::Struct | SymbolType::Union | SymbolType::Enum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    const MIXER: &str = r#"namespace audio {
+
+class Mixer {
+public:
+    void mix(float* out);   // { not a block
+    int channels;
+};
+
+enum class Mode { Mono, Stereo };
+
+}  // namespace audio
+
+void audio::Mixer::mix(float* out) {
+    out[0] = 0;
+}
+"#;
+
+    #[test]
+    fn test_build_outline() {
+        let index_id = Uuid::new_v4();
+        let element = |name: &str, symbol_type, line, scope: Option<&str>| {
+            let element = CodeElement::new(index_id, name.to_string(), symbol_type, "mixer.cpp".to_string(), line, 1, "a".repeat(64));
+            match scope {
+                Some(scope) => element.with_scope(scope.to_string()),
+                None => element,
+            }
+        };
+        let elements = vec![
+            element("mix", SymbolType::Function, 13, Some("audio::Mixer")),
+            element("Mixer", SymbolType::Class, 3, Some("audio")),
+            element("mix", SymbolType::Function, 5, Some("audio::Mixer")),
+            element("channels", SymbolType::Field, 6, Some("audio::Mixer")),
+            element("Mode", SymbolType::Enum, 9, Some("audio")),
+            element("audio", SymbolType::Namespace, 1, None),
+        ];
+
+        let outline = build_outline(&elements, Some(MIXER));
+        let summary = |nodes: &[OutlineNode]| nodes.iter().map(|node| (node.element.line_number, node.end_line, node.children.len())).collect::<Vec<_>>();
+        assert_eq!(summary(&outline), [(1, 11, 2), (13, 15, 0)]);
+        assert_eq!(summary(&outline[0].children), [(3, 7, 2), (9, 9, 0)]);
+        assert_eq!(summary(&outline[0].children[0].children), [(5, 5, 0), (6, 6, 0)]);
+        assert_eq!(outline.iter().map(OutlineNode::size).sum::<usize>(), elements.len());
+
+        // Without the source, scopes nest the members, the out-of-line definition too
+        let outline = build_outline(&elements, None);
+        assert_eq!(summary(&outline), [(1, 1, 2)]);
+        assert_eq!(summary(&outline[0].children[0].children), [(5, 5, 0), (6, 6, 0), (13, 13, 0)]);
+        assert_eq!(outline[0].children[0].to_json()["children"][1]["name"], "channels");
+    }
+}
@@ -0,0 +1,264 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::lib::cpp_indexer::detail_tiers::DetailPolicy;
+use crate::lib::cpp_indexer::symbol_extractor::SymbolExtractor;
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::code_index::CodeIndex;
+
+/// Documents open at once; the least recently changed is dropped first
+pub const MAX_OPEN_DOCUMENTS: usize = 256;
+
+/// A position in a document as editors send it: zero-based line and UTF-16 character offset
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A span of a document between two positions, end exclusive
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// One edit of a document, in the shape of an LSP `TextDocumentContentChangeEvent`
+///
+/// Without a range the text replaces the whole document.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct ContentChange {
+    #[serde(default)]
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+/// Unsaved text of a document open in an editor
+#[derive(Debug, Clone)]
+pub struct OpenDocument {
+    /// Editor-assigned version, increasing with every change
+    pub version: i64,
+    pub text: String,
+    /// Symbols extracted from the text; None if extraction was unavailable
+    pub symbols: Option<Vec<CodeElement>>,
+    /// Order of the last open or change, for eviction
+    sequence: u64,
+}
+
+/// In-memory overlay of documents open in an editor
+///
+/// Keyed by index and stored path. Positional queries on an open document
+/// read its unsaved text instead of the file on disk, so an editor and an
+/// assistant can share one server and one index.
+#[derive(Debug)]
+pub struct DocumentOverlay {
+    documents: HashMap<(Uuid, String), OpenDocument>,
+    max_documents: usize,
+    next_sequence: u64,
+}
+
+impl Default for DocumentOverlay {
+    fn default() -> Self {
+        Self::new(MAX_OPEN_DOCUMENTS)
+    }
+}
+
+impl DocumentOverlay {
+    /// Creates an overlay holding at most `max_documents` documents
+    pub fn new(max_documents: usize) -> Self {
+        Self {
+            documents: HashMap::new(),
+            max_documents: max_documents.max(1),
+            next_sequence: 0,
+        }
+    }
+
+    /// Opens a document, replacing it if it was open already
+    pub fn open(&mut self, index_id: Uuid, path: &str, version: i64, text: String) -> &mut OpenDocument {
+        let key = (index_id, path.to_string());
+        if !self.documents.contains_key(&key) {
+            while self.documents.len() >= self.max_documents {
+                let oldest = self.documents.iter().min_by_key(|(_, document)| document.sequence).map(|(key, _)| key.clone());
+                match oldest {
+                    Some(oldest) => self.documents.remove(&oldest),
+                    None => break,
+                };
+            }
+        }
+        let sequence = self.next_sequence();
+        self.documents.insert(key.clone(), OpenDocument { version, text, symbols: None, sequence });
+        self.documents.get_mut(&key).expect("document was just inserted")
+    }
+
+    /// Applies edits in order, as of `version`
+    ///
+    /// Fails without changing the document if it isn't open, `version` is
+    /// not newer than the open one or an edit falls outside the text. The
+    /// document's symbols are cleared until extracted again.
+    pub fn change(&mut self, index_id: Uuid, path: &str, version: i64, changes: &[ContentChange]) -> Result<&mut OpenDocument, String> {
+        let sequence = self.next_sequence();
+        let document = self
+            .documents
+            .get_mut(&(index_id, path.to_string()))
+            .ok_or_else(|| format!("Document not open: {}", path))?;
+        if version <= document.version {
+            return Err(format!("Version {} of {} is not newer than open version {}", version, path, document.version));
+        }
+
+        let mut text = document.text.clone();
+        for change in changes {
+            apply_change(&mut text, change)?;
+        }
+        document.text = text;
+        document.version = version;
+        document.symbols = None;
+        document.sequence = sequence;
+        Ok(document)
+    }
+
+    /// Closes a document; returns false if it wasn't open
+    pub fn close(&mut self, index_id: Uuid, path: &str) -> bool {
+        self.documents.remove(&(index_id, path.to_string())).is_some()
+    }
+
+    /// The open document at `path`, if any
+    pub fn get(&self, index_id: Uuid, path: &str) -> Option<&OpenDocument> {
+        self.documents.get(&(index_id, path.to_string()))
+    }
+
+    /// The open document at `path` for updating, if any
+    pub fn get_mut(&mut self, index_id: Uuid, path: &str) -> Option<&mut OpenDocument> {
+        self.documents.get_mut(&(index_id, path.to_string()))
+    }
+
+    /// Paths of the documents open against an index, sorted
+    pub fn paths(&self, index_id: Uuid) -> Vec<&str> {
+        let mut paths: Vec<&str> = self.documents.keys().filter(|(id, _)| *id == index_id).map(|(_, path)| path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    /// Number of open documents
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns true if no document is open
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    fn next_sequence(&mut self) -> u64 {
+        self.next_sequence += 1;
+        self.next_sequence
+    }
+}
+
+/// Symbols of unsaved text, as they would be stored for `stored_path`
+///
+/// Only the syntactic pass runs, since the text isn't on disk. Returns None
+/// if the parser can't start or the text doesn't parse.
+pub fn extract_document_symbols(index: &CodeIndex, stored_path: &str, text: &str) -> Option<Vec<CodeElement>> {
+    let extraction = SymbolExtractor::new(None)
+        .and_then(|mut extractor| extractor.extract_symbols_from_content(text, Path::new(stored_path)));
+    match extraction {
+        Ok(extraction) => Some(DetailPolicy::full().apply(&extraction.symbols, index.id, stored_path).elements),
+        Err(e) => {
+            warn!("Failed to extract symbols of open document {}: {}", stored_path, e);
+            None
+        }
+    }
+}
+
+/// Byte offset of a position; a character past the end of its line clamps to the line end
+pub fn offset_at(text: &str, position: Position) -> Option<usize> {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return Some(line_start + offset);
+        }
+        units += c.len_utf16();
+    }
+    Some(line_start + line.trim_end_matches('\r').len())
+}
+
+/// Applies one edit to a text
+pub fn apply_change(text: &mut String, change: &ContentChange) -> Result<(), String> {
+    let Some(range) = change.range else {
+        text.clone_from(&change.text);
+        return Ok(());
+    };
+    let out_of_range = |position: Position| format!("Position {}:{} is outside the document", position.line, position.character);
+    let start = offset_at(text, range.start).ok_or_else(|| out_of_range(range.start))?;
+    let end = offset_at(text, range.end).ok_or_else(|| out_of_range(range.end))?;
+    if end < start {
+        return Err(format!("Range ends before it starts at {}:{}", range.start.line, range.start.character));
+    }
+    text.replace_range(start..end, &change.text);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> ContentChange {
+        ContentChange {
+            range: Some(Range {
+                start: Position { line: start.0, character: start.1 },
+                end: Position { line: end.0, character: end.1 },
+            }),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_change() {
+        let mut text = "void mix();\nvoid drain();\n".to_string();
+        apply_change(&mut text, &edit((1, 5), (1, 10), "fill")).unwrap();
+        assert_eq!(text, "void mix();\nvoid fill();\n");
+
+        // Insertion at the end of the document, and a character past a line's end
+        apply_change(&mut text, &edit((2, 0), (2, 0), "int level;\n")).unwrap();
+        apply_change(&mut text, &edit((0, 40), (0, 40), " // ok")).unwrap();
+        assert_eq!(text, "void mix(); // ok\nvoid fill();\nint level;\n");
+
+        // Characters count UTF-16 units, so the emoji is two wide
+        let mut text = "s = \"🎵\"; x".to_string();
+        apply_change(&mut text, &edit((0, 10), (0, 11), "y")).unwrap();
+        assert_eq!(text, "s = \"🎵\"; y");
+
+        assert!(apply_change(&mut text, &edit((5, 0), (5, 1), "z")).is_err());
+        apply_change(&mut text, &ContentChange { range: None, text: "new".to_string() }).unwrap();
+        assert_eq!(text, "new");
+    }
+
+    #[test]
+    fn test_overlay_versions() {
+        let index_id = Uuid::new_v4();
+        let mut overlay = DocumentOverlay::new(2);
+        overlay.open(index_id, "src/a.cpp", 1, "int a;".to_string());
+        assert!(overlay.change(index_id, "src/a.cpp", 1, &[]).is_err());
+        let document = overlay.change(index_id, "src/a.cpp", 2, &[edit((0, 4), (0, 5), "b")]).unwrap();
+        assert_eq!((document.version, document.text.as_str()), (2, "int b;"));
+        assert!(overlay.change(index_id, "src/b.cpp", 1, &[]).is_err());
+
+        // A third document evicts the least recently changed
+        overlay.open(index_id, "src/b.cpp", 1, String::new());
+        overlay.open(index_id, "src/c.cpp", 1, String::new());
+        assert_eq!(overlay.paths(index_id), ["src/b.cpp", "src/c.cpp"]);
+        assert!(overlay.close(index_id, "src/b.cpp"));
+        assert!(!overlay.close(index_id, "src/b.cpp"));
+        assert_eq!(overlay.len(), 1);
+    }
+}
@@ -0,0 +1,179 @@
+// Progress and cancellation of long-running tool calls
+//
+// A client that passes `_meta.progressToken` with a tool call is sent
+// `notifications/progress` as the call advances. Cancellations, MCP's
+// `notifications/cancelled` or the `$/cancelRequest` of LSP clients, are
+// picked up by the transport as soon as they are read, while the call they
+// name is still running, and set that call's flag; long operations check it
+// between files and stop early.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use super::server::McpNotification;
+use super::transport::OutgoingMessage;
+
+/// Least time between two progress notifications of one call; the last one is always sent
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// JSON-RPC error code of a request the client cancelled
+pub const REQUEST_CANCELLED: i32 = -32800;
+
+/// Cancellation flags of the requests being handled, by request id
+///
+/// Shared by the server, which registers each call, and the transport,
+/// which sets the flag of a call when its cancellation arrives.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationRegistry {
+    flags: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+/// Error of a tool call stopped because the client cancelled it
+#[derive(Debug, Clone, Error)]
+#[error("{0}")]
+pub struct Cancelled(pub String);
+
+/// Where a tool call reports its progress and learns it was cancelled
+///
+/// The default reports nowhere and is never cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct ToolProgress {
+    token: Option<Value>,
+    sender: Option<mpsc::Sender<OutgoingMessage>>,
+    cancelled: Arc<AtomicBool>,
+    last_sent: Option<Instant>,
+}
+
+impl CancellationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking request `id`, returning the flag its cancellation sets
+    pub fn register(&self, id: &Value) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        if let Ok(mut flags) = self.flags.lock() {
+            flags.insert(id.to_string(), Arc::clone(&flag));
+        }
+        flag
+    }
+
+    /// Flags request `id` as cancelled; false if no such request is being handled
+    pub fn cancel(&self, id: &Value) -> bool {
+        match self.flags.lock().ok().and_then(|flags| flags.get(&id.to_string()).cloned()) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops tracking request `id` once it has been answered
+    pub fn finish(&self, id: &Value) {
+        if let Ok(mut flags) = self.flags.lock() {
+            flags.remove(&id.to_string());
+        }
+    }
+}
+
+/// Id of the request a cancellation message names, or None if `message` isn't one
+pub fn cancelled_request(message: &Value) -> Option<Value> {
+    let id = match message["method"].as_str()? {
+        "notifications/cancelled" => &message["params"]["requestId"],
+        "$/cancelRequest" => &message["params"]["id"],
+        _ => return None,
+    };
+    Some(id.clone()).filter(|id| !id.is_null())
+}
+
+impl ToolProgress {
+    /// Progress of a call that stops once `cancelled` is set
+    pub fn new(cancelled: Arc<AtomicBool>) -> Self {
+        Self { cancelled, ..Self::default() }
+    }
+
+    /// Sends progress notifications under `token` through `sender`
+    pub fn with_notifications(mut self, token: Value, sender: mpsc::Sender<OutgoingMessage>) -> Self {
+        self.token = Some(token);
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Returns true once the client has cancelled the call
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Reports `progress` of `total` done
+    ///
+    /// Notifications are throttled to one per `PROGRESS_INTERVAL` and
+    /// dropped rather than waited for when the client reads slower than
+    /// they come, so reporting never holds up the work.
+    pub fn report(&mut self, progress: usize, total: usize, message: &str) {
+        let (Some(token), Some(sender)) = (&self.token, &self.sender) else { return };
+        let now = Instant::now();
+        if progress < total && self.last_sent.is_some_and(|last| now.duration_since(last) < PROGRESS_INTERVAL) {
+            return;
+        }
+        self.last_sent = Some(now);
+        let notification = McpNotification::progress(token.clone(), progress, total, message);
+        if let Err(e) = sender.try_send(OutgoingMessage::Notification(notification)) {
+            debug!("Dropped progress notification: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cancellation_registry() {
+        let registry = CancellationRegistry::new();
+        let flag = registry.register(&json!(7));
+        let progress = ToolProgress::new(Arc::clone(&flag));
+        assert!(!progress.is_cancelled());
+
+        let cancel = json!({"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {"requestId": 7, "reason": "user"}});
+        assert!(registry.cancel(&cancelled_request(&cancel).unwrap()));
+        assert!(progress.is_cancelled());
+        // Ids are compared as written, so "7" is another request
+        assert!(!registry.cancel(&json!("7")));
+
+        let lsp = json!({"jsonrpc": "2.0", "method": "$/cancelRequest", "params": {"id": "abc"}});
+        assert_eq!(cancelled_request(&lsp), Some(json!("abc")));
+        assert_eq!(cancelled_request(&json!({"jsonrpc": "2.0", "id": 1, "method": "ping"})), None);
+
+        registry.finish(&json!(7));
+        assert!(!registry.cancel(&json!(7)));
+    }
+
+    #[tokio::test]
+    async fn test_progress_notifications_are_throttled() {
+        let (sender, mut receiver) = mpsc::channel(10);
+        let mut progress = ToolProgress::default().with_notifications(json!("index-1"), sender);
+        for done in 1..=5 {
+            progress.report(done, 5, "files indexed");
+        }
+
+        let mut sent = Vec::new();
+        while let Ok(OutgoingMessage::Notification(notification)) = receiver.try_recv() {
+            assert_eq!(notification.method, "notifications/progress");
+            assert_eq!(notification.params["progressToken"], "index-1");
+            sent.push(notification.params["progress"].as_u64().unwrap());
+        }
+        // The first report goes out at once, the last one always
+        assert_eq!(sent, [1, 5]);
+
+        // Without a token nothing is sent
+        ToolProgress::default().report(1, 1, "files indexed");
+    }
+}
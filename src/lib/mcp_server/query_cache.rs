@@ -0,0 +1,169 @@
+// Query result cache
+//
+// Agents ask nearly the same questions over and over within a session: the
+// symbols of a file they keep coming back to, a search they repeat after
+// reading its results, the details of a symbol they already looked up.
+// Answers of the hottest read tools are kept here, keyed by tool and
+// arguments. Each answer remembers the version of the index it was computed
+// from and is dropped as soon as the index has moved on, so a hit never
+// returns anything the database wouldn't.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::lib::storage::models::code_index::CodeIndex;
+
+/// Answers kept unless the server is configured otherwise
+pub const DEFAULT_QUERY_CACHE_ENTRIES: usize = 256;
+
+/// State of an index an answer was computed from
+///
+/// Writes to an index's files, symbols, relationships, symbol tags,
+/// annotations and build configurations move its `updated_at` on, and a deleted and
+/// recreated index gets a new id, so two equal versions hold the same data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexVersion {
+    index_id: Uuid,
+    updated_at: DateTime<Utc>,
+    total_files: u32,
+    total_symbols: u32,
+}
+
+impl IndexVersion {
+    pub fn of(index: &CodeIndex) -> Self {
+        Self {
+            index_id: index.id,
+            updated_at: index.updated_at,
+            total_files: index.total_files,
+            total_symbols: index.total_symbols,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedAnswer {
+    version: IndexVersion,
+    answer: Value,
+    /// Lookup count when the answer was last returned or stored
+    used: u64,
+}
+
+/// Answers of read-only tool calls, the least recently used dropped first
+#[derive(Debug)]
+pub struct QueryCache {
+    entries: HashMap<String, CachedAnswer>,
+    capacity: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for QueryCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUERY_CACHE_ENTRIES)
+    }
+}
+
+impl QueryCache {
+    /// Creates a cache holding at most `capacity` answers
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Key of a call: the tool and its arguments, whose objects serialize with sorted keys
+    pub fn key(tool_name: &str, arguments: &Value) -> String {
+        format!("{}\n{}", tool_name, arguments)
+    }
+
+    /// Returns the answer stored for `key` if it was computed from `version`
+    ///
+    /// An answer from an older version is dropped.
+    pub fn get(&mut self, key: &str, version: &IndexVersion) -> Option<Value> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) if entry.version == *version => {
+                entry.used = self.clock;
+                self.hits += 1;
+                Some(entry.answer.clone())
+            }
+            Some(_) => {
+                self.entries.remove(key);
+                self.misses += 1;
+                None
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores the answer to a call, evicting the least recently used answer when full
+    pub fn insert(&mut self, key: String, version: IndexVersion, answer: Value) {
+        self.clock += 1;
+        while self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.used).map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+        self.entries.insert(key, CachedAnswer { version, answer, used: self.clock });
+    }
+
+    /// Number of answers held
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no answers are held
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Lookups that had to run the query
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_query_cache_versions_and_eviction() {
+        let mut index = CodeIndex::new("audio".to_string(), "/src/audio".to_string());
+        let version = IndexVersion::of(&index);
+        let mut cache = QueryCache::new(2);
+        let key = |query: &str| QueryCache::key("search_symbols", &json!({"query": query, "index_name": "audio"}));
+        assert_eq!(key("Mixer"), QueryCache::key("search_symbols", &json!({"index_name": "audio", "query": "Mixer"})));
+
+        cache.insert(key("Mixer"), version.clone(), json!({"total_count": 1}));
+        cache.insert(key("Voice"), version.clone(), json!({"total_count": 2}));
+        assert_eq!(cache.get(&key("Mixer"), &version), Some(json!({"total_count": 1})));
+        // Voice was used least recently
+        cache.insert(key("Filter"), version.clone(), json!({"total_count": 3}));
+        assert_eq!(cache.get(&key("Voice"), &version), None);
+        assert_eq!((cache.len(), cache.hits(), cache.misses()), (2, 1, 1));
+
+        index.update_stats(4, 12);
+        assert_eq!(cache.get(&key("Mixer"), &IndexVersion::of(&index)), None);
+        assert_eq!(cache.get(&key("Mixer"), &version), None);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::lib::cpp_indexer::vfs::read_source;
+use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+/// How a location refers to a symbol
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceKind {
+    /// The symbol is called
+    Call,
+    /// The symbol's address is taken (`&name`), e.g. to register a callback
+    AddressTaken,
+    /// The symbol is used as a type: base class, template argument, variable type
+    TypeUsage,
+    /// The reference is an `#include` of the symbol's file
+    Include,
+    /// The symbol is only mentioned in a comment
+    DocumentationMention,
+    /// Any other relationship (friend, override, definition)
+    Other,
+}
+
+/// Per-file reference counts, broken down by kind
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct FileReferenceGroup {
+    pub file_path: String,
+    pub count: usize,
+    pub kinds: BTreeMap<ReferenceKind, usize>,
+    /// Lines of the references in this file, in the order they were added
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<u32>,
+}
+
+/// Accumulates reference counts by file and kind
+///
+/// Keeps only counts (and optionally line numbers), so it can summarize
+/// every reference to a symbol without holding the references themselves.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceSummary {
+    files: BTreeMap<String, FileReferenceGroup>,
+    kinds: BTreeMap<ReferenceKind, usize>,
+    total: usize,
+    keep_lines: bool,
+}
+
+/// Lines of source around a reference
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ContextSnippet {
+    /// 1-based line number of the first entry in `lines`
+    pub start_line: u32,
+    pub lines: Vec<String>,
+}
+
+/// Source lines read while classifying references, one entry per file
+///
+/// Files that cannot be read (moved, not checked out) are remembered as
+/// missing so each is only tried once; their references fall back to the
+/// relationship type alone.
+#[derive(Debug, Default)]
+pub struct SourceLines {
+    base_path: std::path::PathBuf,
+    files: HashMap<String, Option<Vec<String>>>,
+}
+
+impl ReferenceKind {
+    /// Returns string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferenceKind::Call => "call",
+            ReferenceKind::AddressTaken => "address_taken",
+            ReferenceKind::TypeUsage => "type_usage",
+            ReferenceKind::Include => "include",
+            ReferenceKind::DocumentationMention => "documentation_mention",
+            ReferenceKind::Other => "other",
+        }
+    }
+
+    /// Classifies a reference from its relationship type and, when available, its source line
+    ///
+    /// The line refines what the relationship alone can't tell apart: a mention
+    /// inside a comment, an address taken instead of a call, or an include.
+    pub fn classify(relationship_type: RelationshipType, line: Option<&str>, symbol_name: &str) -> Self {
+        if let Some(line) = line {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("#include") || trimmed.starts_with("#import") {
+                return ReferenceKind::Include;
+            }
+            if let Some(position) = find_word(line, symbol_name) {
+                if in_comment(line, position) {
+                    return ReferenceKind::DocumentationMention;
+                }
+                if takes_address(line, position) {
+                    return ReferenceKind::AddressTaken;
+                }
+                if relationship_type == RelationshipType::Uses {
+                    let after = line[position + symbol_name.len()..].trim_start();
+                    return if after.starts_with('(') { ReferenceKind::Call } else { ReferenceKind::TypeUsage };
+                }
+            }
+        }
+
+        match relationship_type {
+            RelationshipType::Calls => ReferenceKind::Call,
+            RelationshipType::Includes => ReferenceKind::Include,
+            RelationshipType::Uses
+            | RelationshipType::Inherits
+            | RelationshipType::Instantiates
+            | RelationshipType::Specializes => ReferenceKind::TypeUsage,
+            RelationshipType::ReferencedAsCallback => ReferenceKind::AddressTaken,
+            RelationshipType::Defines
+            | RelationshipType::ContainedIn
+            | RelationshipType::Friend
+            | RelationshipType::Overrides => ReferenceKind::Other,
+        }
+    }
+}
+
+impl ReferenceSummary {
+    /// Creates an empty summary; `keep_lines` also records each reference's line
+    pub fn new(keep_lines: bool) -> Self {
+        Self {
+            keep_lines,
+            ..Self::default()
+        }
+    }
+
+    /// Counts one reference
+    pub fn add(&mut self, file_path: &str, line_number: u32, kind: ReferenceKind) {
+        let group = self.files.entry(file_path.to_string()).or_insert_with(|| FileReferenceGroup {
+            file_path: file_path.to_string(),
+            ..FileReferenceGroup::default()
+        });
+        group.count += 1;
+        *group.kinds.entry(kind).or_insert(0) += 1;
+        if self.keep_lines {
+            group.lines.push(line_number);
+        }
+
+        *self.kinds.entry(kind).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    /// Number of references counted
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Counts per kind across all files
+    pub fn kind_counts(&self) -> &BTreeMap<ReferenceKind, usize> {
+        &self.kinds
+    }
+
+    /// Per-file groups, most referenced files first
+    pub fn files(&self) -> Vec<&FileReferenceGroup> {
+        let mut files: Vec<&FileReferenceGroup> = self.files.values().collect();
+        files.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.file_path.cmp(&b.file_path)));
+        files
+    }
+}
+
+impl SourceLines {
+    /// Reads files relative to the index base path
+    pub fn new<P: AsRef<Path>>(base_path: P) -> Self {
+        Self {
+            base_path: base_path.as_ref().to_path_buf(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Returns the 1-based line of a file, if the file is readable
+    pub fn line(&mut self, file_path: &str, line_number: u32) -> Option<&str> {
+        let base_path = &self.base_path;
+        let lines = self.files.entry(file_path.to_string()).or_insert_with(|| {
+            read_source(base_path, file_path)
+                .ok()
+                .map(|content| content.lines().map(str::to_string).collect())
+        });
+        lines.as_ref()?.get(line_number.checked_sub(1)? as usize).map(String::as_str)
+    }
+
+    /// Returns up to `context` lines either side of a 1-based line, clipped to the file
+    pub fn context(&mut self, file_path: &str, line_number: u32, context: u32) -> Option<ContextSnippet> {
+        self.line(file_path, line_number)?;
+        let lines = self.files.get(file_path)?.as_ref()?;
+        let first = line_number.saturating_sub(context).max(1);
+        let last = line_number.saturating_add(context).min(lines.len() as u32);
+        Some(ContextSnippet {
+            start_line: first,
+            lines: lines[first as usize - 1..last as usize].to_vec(),
+        })
+    }
+}
+
+/// Byte offset of the first whole-word occurrence of `word` in `line`
+pub fn find_word(line: &str, word: &str) -> Option<usize> {
+    if word.is_empty() {
+        return None;
+    }
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(word).map(|(i, _)| i).find(|&i| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + word.len()..].chars().next();
+        !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+    })
+}
+
+/// Returns true if `position` lies in a `//` comment, a `/* */` block or a doc-comment continuation line
+fn in_comment(line: &str, position: usize) -> bool {
+    let before = &line[..position];
+    let trimmed = line.trim_start();
+    let continuation = trimmed.starts_with("* ") || trimmed == "*";
+    if continuation || (trimmed.starts_with("/*") && !before.contains("*/")) {
+        return true;
+    }
+    if before.contains("//") {
+        return true;
+    }
+    match (before.rfind("/*"), before.rfind("*/")) {
+        (Some(open), Some(close)) => open > close,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if the symbol at `position` is preceded by a unary `&`
+///
+/// Qualifiers (`&Widget::draw`) are skipped; `Type &name` and `a && name`
+/// are declarations and logical operators, not address-of.
+fn takes_address(line: &str, position: usize) -> bool {
+    let mut before = line[..position].trim_end();
+    while let Some(stripped) = before.strip_suffix("::") {
+        before = stripped.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').trim_end();
+    }
+
+    let Some(rest) = before.strip_suffix('&') else { return false };
+    if rest.ends_with('&') {
+        return false;
+    }
+    match rest.trim_end().chars().next_back() {
+        None => true,
+        Some(c) => !(c.is_alphanumeric() || c == '_' || c == ')' || c == ']' || c == '>'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_from_source_line() {
+        let classify = |relationship, line| ReferenceKind::classify(relationship, Some(line), "draw");
+
+        assert_eq!(classify(RelationshipType::Calls, "    canvas.draw(shape);"), ReferenceKind::Call);
+        assert_eq!(classify(RelationshipType::Calls, "    // draw is called once per frame"), ReferenceKind::DocumentationMention);
+        assert_eq!(classify(RelationshipType::Uses, " * @see draw"), ReferenceKind::DocumentationMention);
+        assert_eq!(classify(RelationshipType::Uses, "callbacks.push_back(&Widget::draw);"), ReferenceKind::AddressTaken);
+        assert_eq!(classify(RelationshipType::Uses, "auto fn = &draw;"), ReferenceKind::AddressTaken);
+        assert_eq!(classify(RelationshipType::Uses, "void paint(Canvas &draw);"), ReferenceKind::TypeUsage);
+        assert_eq!(classify(RelationshipType::Uses, "if (ok && draw(x))"), ReferenceKind::Call);
+        assert_eq!(classify(RelationshipType::Calls, "*out = draw(x);"), ReferenceKind::Call);
+        assert_eq!(classify(RelationshipType::Includes, "#include \"draw.h\""), ReferenceKind::Include);
+        assert_eq!(ReferenceKind::classify(RelationshipType::Inherits, None, "draw"), ReferenceKind::TypeUsage);
+        assert_eq!(ReferenceKind::classify(RelationshipType::Friend, None, "draw"), ReferenceKind::Other);
+    }
+
+    #[test]
+    fn test_summary_groups_by_file() {
+        let mut summary = ReferenceSummary::new(true);
+        summary.add("src/a.cpp", 3, ReferenceKind::Call);
+        summary.add("src/b.cpp", 1, ReferenceKind::Call);
+        summary.add("src/b.cpp", 9, ReferenceKind::AddressTaken);
+
+        assert_eq!(summary.total(), 3);
+        assert_eq!(summary.kind_counts()[&ReferenceKind::Call], 2);
+        let files = summary.files();
+        assert_eq!(files[0].file_path, "src/b.cpp");
+        assert_eq!(files[0].count, 2);
+        assert_eq!(files[0].lines, vec![1, 9]);
+        assert_eq!(serde_json::to_value(files[0]).unwrap()["kinds"]["address_taken"], 1);
+    }
+
+    #[test]
+    fn test_context_is_clipped_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.cpp"), "one\ntwo\nthree\nfour\nfive\n").unwrap();
+        let mut source = SourceLines::new(dir.path());
+
+        let snippet = source.context("a.cpp", 3, 1).unwrap();
+        assert_eq!(snippet.start_line, 2);
+        assert_eq!(snippet.lines, vec!["two", "three", "four"]);
+        assert_eq!(source.context("a.cpp", 1, 2).unwrap().lines, vec!["one", "two", "three"]);
+        assert_eq!(source.context("a.cpp", 5, 2).unwrap().start_line, 3);
+        assert_eq!(source.context("a.cpp", 2, 0).unwrap().lines, vec!["two"]);
+        assert!(source.context("a.cpp", 9, 2).is_none());
+        assert!(source.context("missing.cpp", 1, 2).is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+use crate::lib::storage::models::admin_audit::AuditActor;
+use crate::lib::storage::repository::Repository;
+use crate::lib::storage::timings::QueryTimings;
+
+/// Databases of indices kept outside the server's default database
+///
+/// One server process can then answer for several repositories: each tool
+/// call names its index, and the index's database is opened on the first
+/// call that needs it. Indices registered to the same database file share
+/// one repository.
+#[derive(Debug, Default)]
+pub struct RepositoryRegistry {
+    /// Database of each registered index
+    databases: BTreeMap<String, DatabaseConfig>,
+    /// Repositories opened so far, by database path
+    open: HashMap<PathBuf, Arc<Mutex<Repository>>>,
+    /// Actor recorded in the audit trail of every repository, opened now or later
+    audit_actor: Option<AuditActor>,
+    /// Query timings of the repositories opened from now on (None = not counted)
+    query_timings: Option<QueryTimings>,
+}
+
+impl RepositoryRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the index `index_name` from the database `config` opens
+    pub fn with_database(mut self, index_name: impl Into<String>, config: DatabaseConfig) -> Self {
+        self.register(index_name, config);
+        self
+    }
+
+    /// Counts the queries of repositories opened from now on in `timings`
+    pub fn with_query_timings(mut self, timings: QueryTimings) -> Self {
+        self.query_timings = Some(timings);
+        self
+    }
+
+    /// Serves the index `index_name` from the database `config` opens, replacing an earlier registration
+    pub fn register(&mut self, index_name: impl Into<String>, config: DatabaseConfig) {
+        self.databases.insert(index_name.into(), config);
+    }
+
+    /// Returns true if `index_name` has a database of its own
+    pub fn is_registered(&self, index_name: &str) -> bool {
+        self.databases.contains_key(index_name)
+    }
+
+    /// Names of the registered indices, sorted
+    pub fn index_names(&self) -> Vec<&str> {
+        self.databases.keys().map(String::as_str).collect()
+    }
+
+    /// Returns true if no index is registered
+    pub fn is_empty(&self) -> bool {
+        self.databases.is_empty()
+    }
+
+    /// Number of databases opened so far
+    pub fn open_count(&self) -> usize {
+        self.open.len()
+    }
+
+    /// Records `actor` in the audit trail of every repository
+    pub fn set_audit_actor(&mut self, actor: AuditActor) {
+        for repository in self.open.values() {
+            if let Ok(mut repository) = repository.lock() {
+                repository.set_audit_actor(actor.clone());
+            }
+        }
+        self.audit_actor = Some(actor);
+    }
+
+    /// The repository holding `index_name`, opening its database if needed
+    ///
+    /// Returns None if the index isn't registered, so the caller falls back
+    /// to its default database.
+    pub fn get(&mut self, index_name: &str) -> Result<Option<Arc<Mutex<Repository>>>> {
+        let Some(config) = self.databases.get(index_name) else {
+            return Ok(None);
+        };
+        if let Some(repository) = self.open.get(&config.database_path) {
+            return Ok(Some(Arc::clone(repository)));
+        }
+
+        info!("Opening database {} for index '{}'", config.database_path.display(), index_name);
+        let connection = DatabaseManager::new(config.clone())
+            .and_then(|manager| manager.connect())
+            .with_context(|| format!("Failed to open the database of index {}", index_name))?;
+        let mut repository = Repository::new(connection);
+        if let Some(timings) = &self.query_timings {
+            repository = repository.with_query_timings(timings.clone());
+        }
+        if let Some(actor) = &self.audit_actor {
+            repository.set_audit_actor(actor.clone());
+        }
+
+        let repository = Arc::new(Mutex::new(repository));
+        self.open.insert(config.database_path.clone(), Arc::clone(&repository));
+        Ok(Some(repository))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_index::CodeIndex;
+
+    #[test]
+    fn test_opens_databases_on_first_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DatabaseConfig::new(dir.path().join("firmware.db"));
+        let repository = Repository::new(DatabaseManager::new(config.clone()).unwrap().connect().unwrap());
+        repository.create_code_index(CodeIndex::new("fw".to_string(), "/src/fw".to_string())).unwrap();
+        drop(repository);
+
+        let mut registry = RepositoryRegistry::new()
+            .with_database("fw", config.clone())
+            .with_database("fw-tests", config);
+        assert_eq!(registry.index_names(), ["fw", "fw-tests"]);
+        assert_eq!(registry.open_count(), 0);
+        assert!(registry.get("app").unwrap().is_none());
+
+        let fw = registry.get("fw").unwrap().unwrap();
+        assert!(fw.lock().unwrap().get_code_index_by_name("fw").unwrap().is_some());
+
+        // Indices in one database file share its repository
+        let tests = registry.get("fw-tests").unwrap().unwrap();
+        assert!(Arc::ptr_eq(&fw, &tests));
+        assert_eq!(registry.open_count(), 1);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::lib::storage::models::code_element::CodeElement;
+
+/// The definitions and declarations of one symbol
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resolution {
+    pub definitions: Vec<CodeElement>,
+    pub declarations: Vec<CodeElement>,
+}
+
+/// The identifier covering a 1-based column of a source line
+///
+/// Destructor names keep their `~`. Returns None when the column is on
+/// whitespace or punctuation.
+pub fn identifier_at(line: &str, column: u32) -> Option<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let position = (column as usize).checked_sub(1)?;
+    let (_, at) = *chars.get(position)?;
+
+    // On the `~` of a destructor, start from the name that follows it
+    let position = match at {
+        '~' if chars.get(position + 1).is_some_and(|(_, c)| is_ident(*c)) => position + 1,
+        c if is_ident(c) => position,
+        _ => return None,
+    };
+
+    let start = (0..=position).rev().take_while(|i| is_ident(chars[*i].1)).last()?;
+    let end = (position..chars.len()).take_while(|i| is_ident(chars[*i].1)).last()?;
+    let start = match start.checked_sub(1).map(|i| chars[i].1) {
+        Some('~') => start - 1,
+        _ => start,
+    };
+    let byte_end = chars.get(end + 1).map_or(line.len(), |(offset, _)| *offset);
+    let identifier = &line[chars[start].0..byte_end];
+
+    // A number is not a symbol
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(identifier)
+}
+
+/// Splits the elements sharing `target`'s name, type and scope into its definitions and declarations
+///
+/// Elements that both carry a USR pair only when their USRs are equal, so
+/// `mix(int)` and `mix(double)` stay apart whatever their signatures look
+/// like. Otherwise overloads are told apart by signature, ignoring
+/// whitespace; an element without a signature matches any. When another
+/// candidate carries the target's `definition_hash`, only elements with
+/// that hash pair with it, which separates overloads whose signatures
+/// weren't recorded.
+pub fn pair_declarations(target: &CodeElement, candidates: Vec<CodeElement>) -> Resolution {
+    let candidates: Vec<CodeElement> = candidates
+        .into_iter()
+        .filter(|candidate| {
+            candidate.symbol_name == target.symbol_name
+                && candidate.symbol_type == target.symbol_type
+                && candidate.scope == target.scope
+        })
+        .collect();
+
+    let hash_paired = candidates
+        .iter()
+        .any(|candidate| candidate.id != target.id && candidate.definition_hash == target.definition_hash);
+
+    let mut resolution = Resolution::default();
+    for candidate in candidates {
+        let paired = match (&candidate.usr, &target.usr) {
+            (Some(a), Some(b)) => a == b,
+            _ => (!hash_paired || candidate.definition_hash == target.definition_hash) && same_overload(&candidate, target),
+        };
+        if !paired {
+            continue;
+        }
+        if candidate.is_declaration {
+            resolution.declarations.push(candidate);
+        } else {
+            resolution.definitions.push(candidate);
+        }
+    }
+    resolution
+}
+
+fn same_overload(a: &CodeElement, b: &CodeElement) -> bool {
+    match (&a.signature, &b.signature) {
+        (Some(a), Some(b)) => without_whitespace(a) == without_whitespace(b),
+        _ => true,
+    }
+}
+
+fn without_whitespace(signature: &str) -> String {
+    signature.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_identifier_at() {
+        let line = "    total += mixer.gain(0.5f); Voice::~Voice();";
+        assert_eq!(identifier_at(line, 5), Some("total"));
+        assert_eq!(identifier_at(line, 9), Some("total"));
+        assert_eq!(identifier_at(line, 20), Some("gain"));
+        assert_eq!(identifier_at(line, 10), None);
+        assert_eq!(identifier_at(line, 25), None);
+        assert_eq!(identifier_at(line, 39), Some("~Voice"));
+        assert_eq!(identifier_at(line, 40), Some("~Voice"));
+        assert_eq!(identifier_at(line, 200), None);
+    }
+
+    #[test]
+    fn test_pair_overloads() {
+        let index_id = Uuid::new_v4();
+        let element = |id: i64, file: &str, signature: &str, is_declaration: bool, hash: char| {
+            let mut element = CodeElement::new(index_id, "mix".to_string(), SymbolType::Function, file.to_string(), id as u32, 1, hash.to_string().repeat(64))
+                .with_scope("audio".to_string())
+                .with_signature(signature.to_string())
+                .with_declaration(is_declaration);
+            element.id = Some(id);
+            element
+        };
+        let candidates = vec![
+            element(1, "mixer.h", "void mix(float)", true, 'a'),
+            element(2, "mixer.h", "void mix(int)", true, 'b'),
+            element(3, "mixer.cpp", "void  mix(float)", false, 'c'),
+            element(4, "mixer.cpp", "void mix(int)", false, 'd'),
+        ];
+
+        let resolution = pair_declarations(&candidates[0], candidates.clone());
+        assert_eq!(resolution.declarations.iter().map(|e| e.id).collect::<Vec<_>>(), [Some(1)]);
+        assert_eq!(resolution.definitions.iter().map(|e| e.id).collect::<Vec<_>>(), [Some(3)]);
+
+        // A shared definition hash pairs elements even without signatures
+        let mut unsigned = candidates.clone();
+        for candidate in &mut unsigned {
+            candidate.signature = None;
+        }
+        unsigned[3].definition_hash = unsigned[1].definition_hash.clone();
+        let resolution = pair_declarations(&unsigned[1], unsigned.clone());
+        assert_eq!(resolution.declarations.iter().map(|e| e.id).collect::<Vec<_>>(), [Some(2)]);
+        assert_eq!(resolution.definitions.iter().map(|e| e.id).collect::<Vec<_>>(), [Some(4)]);
+
+        // USRs pair across differently spelled signatures and split identical ones
+        let mut resolved = candidates.clone();
+        for (candidate, usr) in resolved.iter_mut().zip(["c:@N@audio@F@mix#f#", "c:@N@audio@F@mix#I#", "c:@N@audio@F@mix#f#", "c:@N@audio@F@mix#I#"]) {
+            candidate.usr = Some(usr.to_string());
+        }
+        resolved[2].signature = Some("void (float)".to_string());
+        resolved[3].signature = Some("void mix(float)".to_string());
+        let resolution = pair_declarations(&resolved[0], resolved.clone());
+        assert_eq!(resolution.definitions.iter().map(|e| e.id).collect::<Vec<_>>(), [Some(3)]);
+        let resolution = pair_declarations(&resolved[1], resolved.clone());
+        assert_eq!(resolution.definitions.iter().map(|e| e.id).collect::<Vec<_>>(), [Some(4)]);
+    }
+}
@@ -1,9 +1,20 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
 use anyhow::{anyhow, Result};
 use serde_json::{json, Value};
 use tracing::{info, instrument};
 
-// TODO: Enable when repository interface is finalized
-// use crate::lib::storage::repository::Repository;
+use super::metrics::{Metrics, METRICS_RESOURCE_URI, PROMETHEUS_MIME_TYPE, STATS_RESOURCE_URI};
+use super::registry::RepositoryRegistry;
+use crate::lib::storage::models::file_metadata::FileMetadata;
+use crate::lib::storage::repository::Repository;
+
+/// Scheme of the per-index resources, e.g. `cpp-index://my-project/files`
+pub const INDEX_RESOURCE_SCHEME: &str = "cpp-index://";
+
+/// URI template of an index's files and directories, as listed by `resources/templates/list`
+pub const FILES_URI_TEMPLATE: &str = "cpp-index://{index}/files/{path}";
 
 /// Resource Handlers for MCP Protocol
 /// 
@@ -12,17 +23,42 @@ use tracing::{info, instrument};
 /// Resources are identified by URI and return typed content.
 #[derive(Debug, Clone)]
 pub struct ResourceHandlers {
-    // TODO: Add repository when available
+    /// Database repository the indices are read from
+    repository: Option<Arc<Mutex<Repository>>>,
+    /// Indices served from databases of their own
+    registry: Option<Arc<Mutex<RepositoryRegistry>>>,
+    /// Tool call and query metrics of the server (None = not collected)
+    metrics: Option<Metrics>,
 }
 
 impl ResourceHandlers {
     /// Create new resource handlers instance
     pub fn new() -> Result<Self> {
         Ok(Self {
-            // TODO: Initialize dependencies
+            repository: None,
+            registry: None,
+            metrics: None,
         })
     }
 
+    /// Serves the file trees of the indices stored in `repository`
+    pub fn with_repository(mut self, repository: Arc<Mutex<Repository>>) -> Self {
+        self.repository = Some(repository);
+        self
+    }
+
+    /// Serves the indices registered in `registry` from their own databases
+    pub fn with_registry(mut self, registry: Arc<Mutex<RepositoryRegistry>>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Serves the statistics `metrics` collects
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
     /// Handle MCP resource read request
     #[instrument(skip(self))]
     pub async fn handle_resource_read(&self, uri: &str) -> Result<Value> {
@@ -31,6 +67,8 @@ impl ResourceHandlers {
         match uri {
             "index://metadata" => self.handle_index_metadata().await,
             "index://schema" => self.handle_database_schema().await,
+            STATS_RESOURCE_URI | METRICS_RESOURCE_URI => self.handle_metrics(uri),
+            uri if uri.starts_with(INDEX_RESOURCE_SCHEME) => self.handle_file_resource(uri),
             uri if uri.starts_with("index://") => self.handle_index_specific_resource(uri).await,
             _ => Err(anyhow!("Unknown resource URI: {}", uri)),
         }
@@ -119,6 +157,7 @@ impl ResourceHandlers {
                         "scope": { "type": "TEXT", "description": "Namespace or class scope" },
                         "signature": { "type": "TEXT", "description": "Function signature or variable declaration" },
                         "documentation": { "type": "TEXT", "description": "Extracted documentation" },
+                        "usr": { "type": "TEXT", "description": "libclang Unified Symbol Resolution id, distinct per overload" },
                         "attributes": { "type": "TEXT", "description": "JSON attributes" }
                     },
                     "indexes": [
@@ -197,6 +236,106 @@ impl ResourceHandlers {
         }))
     }
 
+    /// Handle the tool call and query statistics, as JSON or Prometheus text
+    fn handle_metrics(&self, uri: &str) -> Result<Value> {
+        let metrics = self.metrics.as_ref().ok_or_else(|| anyhow!("Metrics are not collected by this server"))?;
+        let (mime_type, text) = if uri == METRICS_RESOURCE_URI {
+            (PROMETHEUS_MIME_TYPE, metrics.to_prometheus())
+        } else {
+            ("application/json", serde_json::to_string_pretty(&metrics.to_json())?)
+        };
+        Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": mime_type,
+                "text": text
+            }]
+        }))
+    }
+
+    /// Names of the indices whose files can be browsed, sorted
+    pub fn index_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        if let Some(repository) = &self.repository {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            names.extend(repository.list_code_indices()?.into_iter().map(|index| index.name));
+        }
+        if let Some(registry) = &self.registry {
+            let registry = registry.lock().map_err(|_| anyhow!("Repository registry lock poisoned"))?;
+            names.extend(registry.index_names().into_iter().map(str::to_string));
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Handle the file tree of an index and the metadata of its files
+    ///
+    /// `cpp-index://{index}/files` is the whole tree, and
+    /// `cpp-index://{index}/files/{path}` a file's metadata, or the subtree
+    /// if `path` is a directory. Both parts are percent-decoded.
+    fn handle_file_resource(&self, uri: &str) -> Result<Value> {
+        let (index_name, path) = parse_files_uri(uri)?;
+        let (index_name, path) = (index_name.as_str(), path.as_str());
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let file = match path {
+            "" => None,
+            path => repository.get_file_metadata_by_path(&index.id, path)?,
+        };
+        let content = match file {
+            Some(file) => json!({
+                "index_name": index_name,
+                "file": file_entry(index_name, &file),
+            }),
+            None => {
+                let prefix = if path.is_empty() { String::new() } else { format!("{}/", path) };
+                let files: Vec<FileMetadata> = repository
+                    .list_file_metadata(&index.id)?
+                    .into_iter()
+                    .filter(|file| file.file_path.starts_with(&prefix))
+                    .collect();
+                if files.is_empty() && !path.is_empty() {
+                    return Err(anyhow!("No indexed file or directory {} in index {}", path, index_name));
+                }
+                json!({
+                    "index_name": index_name,
+                    "base_path": index.base_path,
+                    "tree": file_tree(index_name, path, &prefix, &files),
+                })
+            }
+        };
+
+        Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": "application/json",
+                "text": serde_json::to_string_pretty(&content)?
+            }]
+        }))
+    }
+
+    /// Registered repository of `index_name`, else the attached one
+    fn repository_of(&self, index_name: &str) -> Result<Arc<Mutex<Repository>>> {
+        if let Some(registry) = &self.registry {
+            let registered = registry
+                .lock()
+                .map_err(|_| anyhow!("Repository registry lock poisoned"))?
+                .get(index_name)?;
+            if let Some(repository) = registered {
+                return Ok(repository);
+            }
+        }
+        self.repository
+            .clone()
+            .ok_or_else(|| anyhow!("Index storage is not available"))
+    }
+
     /// Handle index-specific resource requests
     #[instrument(skip(self))]
     async fn handle_index_specific_resource(&self, uri: &str) -> Result<Value> {
@@ -263,6 +402,132 @@ impl ResourceHandlers {
 
 }
 
+/// URI of the file tree of `index_name`, or of the file or directory at `path`
+///
+/// Both are percent-encoded, the '/' of an index name like `engine/core` included.
+pub fn files_uri(index_name: &str, path: &str) -> String {
+    let index_name = percent_encode(index_name, "");
+    if path.is_empty() {
+        format!("{}{}/files", INDEX_RESOURCE_SCHEME, index_name)
+    } else {
+        format!("{}{}/files/{}", INDEX_RESOURCE_SCHEME, index_name, percent_encode(path, "/"))
+    }
+}
+
+/// Index name and path of a `cpp-index://{index}/files/{path}` URI, decoded
+///
+/// The name ends at the first `files` segment, so names holding a '/'
+/// resolve whether or not the client encoded it.
+fn parse_files_uri(uri: &str) -> Result<(String, String)> {
+    let rest = uri
+        .strip_prefix(INDEX_RESOURCE_SCHEME)
+        .ok_or_else(|| anyhow!("Invalid index resource URI format: {}", uri))?;
+    let (index_name, path) = rest
+        .match_indices("/files")
+        .find_map(|(at, marker)| {
+            let path = &rest[at + marker.len()..];
+            (at > 0 && (path.is_empty() || path.starts_with('/'))).then(|| (&rest[..at], path.trim_matches('/')))
+        })
+        .ok_or_else(|| anyhow!("Unknown index resource: {}", uri))?;
+    Ok((percent_decode(index_name)?, percent_decode(path)?))
+}
+
+/// `text` with every byte but unreserved URI characters and those in `keep` percent-encoded
+fn percent_encode(text: &str, keep: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) || keep.as_bytes().contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(text: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+        let decoded = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| anyhow!("Invalid percent-encoding in resource URI: {}", text))?;
+        bytes.push(decoded);
+        rest = &tail[2..];
+    }
+    String::from_utf8(bytes).map_err(|_| anyhow!("Resource URI is not UTF-8 once decoded: {}", text))
+}
+
+/// Metadata of one indexed file
+fn file_entry(index_name: &str, file: &FileMetadata) -> Value {
+    json!({
+        "name": file.file_path.rsplit('/').next().unwrap_or(&file.file_path),
+        "path": file.file_path,
+        "uri": files_uri(index_name, &file.file_path),
+        "hash": file.file_hash,
+        "size_bytes": file.size_bytes,
+        "symbol_count": file.symbol_count,
+        "last_modified": file.last_modified.to_rfc3339(),
+        "indexed_at": file.indexed_at.to_rfc3339(),
+        "detail": file.detail.as_str(),
+    })
+}
+
+/// Files of a directory and its subdirectories, by name
+#[derive(Default)]
+struct Directory<'a> {
+    directories: BTreeMap<&'a str, Directory<'a>>,
+    files: Vec<&'a FileMetadata>,
+}
+
+/// Nests `files`, all under `prefix`, into directories below `path`
+///
+/// Directories carry the number of files and symbols they hold, counting
+/// their subdirectories.
+fn file_tree(index_name: &str, path: &str, prefix: &str, files: &[FileMetadata]) -> Value {
+    let mut root = Directory::default();
+    for file in files {
+        let mut components: Vec<&str> = file.file_path[prefix.len()..].split('/').collect();
+        components.pop();
+        let directory = components
+            .into_iter()
+            .fold(&mut root, |directory, name| directory.directories.entry(name).or_default());
+        directory.files.push(file);
+    }
+    directory_entry(index_name, path, &root)
+}
+
+fn directory_entry(index_name: &str, path: &str, directory: &Directory) -> Value {
+    let directories: Vec<Value> = directory
+        .directories
+        .iter()
+        .map(|(name, child)| {
+            let child_path = if path.is_empty() { name.to_string() } else { format!("{}/{}", path, name) };
+            directory_entry(index_name, &child_path, child)
+        })
+        .collect();
+    let file_count = directory.files.len() as u64
+        + directories.iter().map(|child| child["file_count"].as_u64().unwrap_or(0)).sum::<u64>();
+    let symbol_count = directory.files.iter().map(|file| u64::from(file.symbol_count)).sum::<u64>()
+        + directories.iter().map(|child| child["symbol_count"].as_u64().unwrap_or(0)).sum::<u64>();
+    json!({
+        "name": path.rsplit('/').next().unwrap_or(path),
+        "path": path,
+        "uri": files_uri(index_name, path),
+        "file_count": file_count,
+        "symbol_count": symbol_count,
+        "directories": directories,
+        "files": directory.files.iter().map(|file| file_entry(index_name, file)).collect::<Vec<_>>(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -297,10 +562,105 @@ mod tests {
         assert_eq!(result["contents"][0]["mimeType"], "application/json");
     }
 
+    #[tokio::test]
+    async fn test_metrics_resources() {
+        let metrics = Metrics::new();
+        metrics.record_tool_call("search_symbols", std::time::Duration::from_millis(3), None);
+        let handlers = ResourceHandlers::new().unwrap().with_metrics(metrics);
+
+        let stats = handlers.handle_resource_read(STATS_RESOURCE_URI).await.unwrap();
+        let parsed: Value = serde_json::from_str(stats["contents"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(parsed["session"]["total_queries"], 1);
+        let text = handlers.handle_resource_read(METRICS_RESOURCE_URI).await.unwrap();
+        assert_eq!(text["contents"][0]["mimeType"], PROMETHEUS_MIME_TYPE);
+        assert!(text["contents"][0]["text"].as_str().unwrap().contains("cpp_index_tool_calls_total{tool=\"search_symbols\"} 1"));
+
+        assert!(ResourceHandlers::new().unwrap().handle_resource_read(STATS_RESOURCE_URI).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_unknown_resource() {
         let handlers = ResourceHandlers::new().unwrap();
         let result = handlers.handle_resource_read("unknown://resource").await;
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_file_tree_resources() {
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = CodeIndex::new("proj".to_string(), "/proj".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        for (path, symbols) in [("src/app.cpp", 4), ("src/ui/widget.cpp", 2), ("include/widget.h", 3), ("main.cpp", 1)] {
+            let mut file = FileMetadata::new(index_id, path.to_string(), "b".repeat(64), chrono::Utc::now(), 100);
+            file.update_indexing(symbols);
+            repository.create_file_metadata(file).unwrap();
+        }
+        let handlers = ResourceHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        assert_eq!(handlers.index_names().unwrap(), vec!["proj"]);
+        let read = |uri: &str| {
+            let handlers = handlers.clone();
+            let uri = uri.to_string();
+            async move {
+                let result = handlers.handle_resource_read(&uri).await?;
+                assert_eq!(result["contents"][0]["uri"], uri.as_str());
+                Ok::<Value, anyhow::Error>(serde_json::from_str(result["contents"][0]["text"].as_str().unwrap()).unwrap())
+            }
+        };
+
+        let tree = &read("cpp-index://proj/files").await.unwrap()["tree"];
+        assert_eq!((tree["file_count"].as_u64(), tree["symbol_count"].as_u64()), (Some(4), Some(10)));
+        assert_eq!(tree["files"][0]["path"], "main.cpp");
+        let directories: Vec<&str> = tree["directories"].as_array().unwrap().iter().map(|d| d["path"].as_str().unwrap()).collect();
+        assert_eq!(directories, vec!["include", "src"]);
+        assert_eq!(tree["directories"][1]["directories"][0]["uri"], "cpp-index://proj/files/src/ui");
+
+        let subtree = &read("cpp-index://proj/files/src").await.unwrap()["tree"];
+        assert_eq!((subtree["path"].as_str(), subtree["file_count"].as_u64()), (Some("src"), Some(2)));
+        assert_eq!(subtree["files"][0]["name"], "app.cpp");
+
+        let file = &read("cpp-index://proj/files/src/ui/widget.cpp").await.unwrap()["file"];
+        assert_eq!((file["symbol_count"].as_u64(), file["hash"].as_str()), (Some(2), Some("b".repeat(64).as_str())));
+        assert_eq!(file["detail"], "full");
+
+        assert!(read("cpp-index://proj/files/lib").await.is_err());
+        assert!(read("cpp-index://other/files").await.is_err());
+        assert!(read("cpp-index://proj/symbols").await.is_err());
+        assert!(ResourceHandlers::new().unwrap().handle_resource_read("cpp-index://proj/files").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_resources_decode_names_and_paths() {
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository.create_code_index(CodeIndex::new("engine/core".to_string(), "/engine".to_string())).unwrap();
+        let file = FileMetadata::new(index.id, "src/audio mixer/mix#1.cpp".to_string(), "c".repeat(64), chrono::Utc::now(), 10);
+        repository.create_file_metadata(file).unwrap();
+        let handlers = ResourceHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let text = |result: Value| serde_json::from_str::<Value>(result["contents"][0]["text"].as_str().unwrap()).unwrap();
+
+        let file_uri = files_uri("engine/core", "src/audio mixer/mix#1.cpp");
+        assert_eq!(file_uri, "cpp-index://engine%2Fcore/files/src/audio%20mixer/mix%231.cpp");
+        let tree = text(handlers.handle_resource_read(&files_uri("engine/core", "")).await.unwrap());
+        assert_eq!(tree["tree"]["directories"][0]["directories"][0]["files"][0]["uri"], file_uri.as_str());
+
+        let file = text(handlers.handle_resource_read(&file_uri).await.unwrap());
+        assert_eq!(file["file"]["path"], "src/audio mixer/mix#1.cpp");
+        // A '/' the client left unencoded still belongs to the index name
+        let file = text(handlers.handle_resource_read("cpp-index://engine/core/files/src/audio%20mixer/mix%231.cpp").await.unwrap());
+        assert_eq!(file["index_name"], "engine/core");
+        let subtree = text(handlers.handle_resource_read("cpp-index://engine/core/files/src/audio%20mixer/").await.unwrap());
+        assert_eq!(subtree["tree"]["file_count"], 1);
+
+        assert!(handlers.handle_resource_read("cpp-index://engine%2Fcore/files/src/%zz.cpp").await.is_err());
+        assert!(handlers.handle_resource_read("cpp-index://engine%2Fcore/filesystem").await.is_err());
+        assert!(handlers.handle_resource_read("cpp-index:///files").await.is_err());
+    }
+}
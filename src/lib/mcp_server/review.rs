@@ -0,0 +1,287 @@
+use serde::Serialize;
+use std::path::Path;
+
+use crate::lib::cpp_indexer::vfs::pattern_matches;
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+
+/// Locations CODEOWNERS is looked up in, in the order GitHub uses
+pub const CODEOWNERS_LOCATIONS: [&str; 4] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS", ".gitlab/CODEOWNERS"];
+
+/// One `@@` hunk of a unified diff
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct DiffHunk {
+    /// Path after the change, or before it for deleted files
+    pub file_path: String,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    /// Lines of the new file that were added or changed
+    pub added_lines: Vec<u32>,
+    /// Lines removed from the old file
+    pub removed_lines: u32,
+    /// Text after the second `@@`, usually the enclosing function as git sees it
+    pub section: Option<String>,
+}
+
+/// Ownership rules of a codebase, in CODEOWNERS syntax
+///
+/// Later rules take precedence over earlier ones, as on GitHub.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeOwners {
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl DiffHunk {
+    /// New-file lines the hunk touches; a pure deletion touches the line it was removed before
+    pub fn touched_lines(&self) -> Vec<u32> {
+        if self.added_lines.is_empty() {
+            vec![self.new_start.max(1)]
+        } else {
+            self.added_lines.clone()
+        }
+    }
+}
+
+/// Parses the hunks of a unified diff as produced by `git diff` or `diff -u`
+///
+/// Paths lose their `a/` and `b/` prefixes. Binary files and mode-only
+/// changes have no hunks and are skipped.
+pub fn parse_unified_diff(diff: &str) -> Vec<DiffHunk> {
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    let mut old_path: Option<String> = None;
+    let mut file_path: Option<String> = None;
+    // Remaining old and new lines of the current hunk, and the next new line number
+    let (mut old_remaining, mut new_remaining, mut new_line) = (0u32, 0u32, 0u32);
+
+    for line in diff.lines() {
+        if old_remaining > 0 || new_remaining > 0 {
+            let Some(hunk) = hunks.last_mut() else { break };
+            match line.chars().next() {
+                Some('+') => {
+                    hunk.added_lines.push(new_line);
+                    new_line += 1;
+                    new_remaining = new_remaining.saturating_sub(1);
+                    continue;
+                }
+                Some('-') => {
+                    hunk.removed_lines += 1;
+                    old_remaining = old_remaining.saturating_sub(1);
+                    continue;
+                }
+                Some(' ') | None => {
+                    new_line += 1;
+                    old_remaining = old_remaining.saturating_sub(1);
+                    new_remaining = new_remaining.saturating_sub(1);
+                    continue;
+                }
+                Some('\\') => continue,
+                _ => {
+                    // Truncated hunk; fall through to the headers
+                    old_remaining = 0;
+                    new_remaining = 0;
+                }
+            }
+        }
+
+        if let Some(path) = line.strip_prefix("--- ") {
+            old_path = diff_path(path);
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            file_path = diff_path(path).or_else(|| old_path.clone());
+        } else if line.starts_with("diff --git ") {
+            old_path = None;
+            file_path = None;
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            let Some(path) = file_path.clone() else { continue };
+            let Some((ranges, section)) = header.split_once(" @@") else { continue };
+            let mut ranges = ranges.split_whitespace();
+            let (Some(old), Some(new)) = (ranges.next().and_then(|r| r.strip_prefix('-')), ranges.next().and_then(|r| r.strip_prefix('+')))
+            else {
+                continue;
+            };
+            let ((old_start, old_lines), (new_start, new_lines)) = (parse_range(old), parse_range(new));
+            old_remaining = old_lines;
+            new_remaining = new_lines;
+            new_line = new_start;
+            let section = section.trim();
+            hunks.push(DiffHunk {
+                file_path: path,
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+                added_lines: Vec::new(),
+                removed_lines: 0,
+                section: (!section.is_empty()).then(|| section.to_string()),
+            });
+        }
+    }
+    hunks
+}
+
+/// Path from a `---`/`+++` header, None for /dev/null
+fn diff_path(header: &str) -> Option<String> {
+    // A tab separates the path from a timestamp in `diff -u` output
+    let path = header.split('\t').next().unwrap_or(header).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path.strip_prefix("a/").or_else(|| path.strip_prefix("b/")).unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// Parses `start[,count]`; the count defaults to 1
+fn parse_range(range: &str) -> (u32, u32) {
+    let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+    (start.parse().unwrap_or(0), count.parse().unwrap_or(0))
+}
+
+impl CodeOwners {
+    /// Parses CODEOWNERS content; comments and blank lines are ignored
+    pub fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(rule, _)| rule).trim())
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let pattern = fields.next()?.to_string();
+                Some((pattern, fields.map(str::to_string).collect()))
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Reads the first CODEOWNERS file found under `base_path`; None if there is none
+    pub fn load(base_path: &Path) -> Option<Self> {
+        CODEOWNERS_LOCATIONS
+            .iter()
+            .find_map(|location| std::fs::read_to_string(base_path.join(location)).ok())
+            .map(|content| Self::parse(&content))
+    }
+
+    /// Owners of a path relative to the codebase root; empty if no rule matches
+    pub fn owners_of(&self, path: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| pattern_matches(pattern, path))
+            .map(|(_, owners)| owners.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Picks the nearest element starting at or before `line_number`
+///
+/// Functions and types are preferred since they are what a location
+/// usually sits inside of.
+pub fn enclosing_element(elements: &[CodeElement], line_number: u32) -> Option<&CodeElement> {
+    let preceding = || elements.iter().filter(move |element| element.line_number <= line_number);
+    preceding()
+        .filter(|element| matches!(
+            element.symbol_type,
+            SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor
+                | SymbolType::Operator | SymbolType::Class | SymbolType::Struct
+                | SymbolType::Union | SymbolType::Enum | SymbolType::Namespace
+        ))
+        .max_by_key(|element| element.line_number)
+        .or_else(|| preceding().max_by_key(|element| element.line_number))
+}
+
+/// Returns true if a path looks like test code: in a test directory, or a `test_*`, `*_test` or `*Test` file
+pub fn is_test_path(path: &str) -> bool {
+    let mut components = path.split('/');
+    let file_name = components.next_back().unwrap_or("");
+    let in_test_directory = components
+        .any(|component| matches!(component.to_ascii_lowercase().as_str(), "test" | "tests" | "unittest" | "unittests" | "testing"));
+    let stem = file_name.split('.').next().unwrap_or(file_name);
+    let lower = stem.to_ascii_lowercase();
+    in_test_directory
+        || lower.starts_with("test_")
+        || ["_test", "_tests", "_unittest"].iter().any(|suffix| lower.ends_with(suffix))
+        || stem.ends_with("Test")
+        || stem.ends_with("Tests")
+}
+
+/// Incoming relationships recorded in test code, leaving out containment and definitions
+pub fn test_references(incoming: &[SymbolRelationship]) -> Vec<&SymbolRelationship> {
+    incoming
+        .iter()
+        .filter(|relationship| !matches!(relationship.relationship_type, RelationshipType::ContainedIn | RelationshipType::Defines))
+        .filter(|relationship| is_test_path(&relationship.file_path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cpp_indexer::vfs::wildcard_match;
+
+    const DIFF: &str = "\
+diff --git a/src/audio/mixer.cpp b/src/audio/mixer.cpp
+index 1111111..2222222 100644
+--- a/src/audio/mixer.cpp
++++ b/src/audio/mixer.cpp
+@@ -10,5 +10,6 @@ void Mixer::process(Buffer& out) {
+     for (auto& channel : channels_) {
+-        channel.render(out);
++        channel.render(out, gain_);
++        ++rendered_;
+     }
+
+     out.normalize();
+@@ -40,3 +41,2 @@ void Mixer::reset() {
+     channels_.clear();
+-    rendered_ = 0;
+ }
+diff --git a/src/old.cpp b/src/old.cpp
+deleted file mode 100644
+--- a/src/old.cpp
++++ /dev/null
+@@ -1,2 +0,0 @@
+-int old() {
+-}
+";
+
+    #[test]
+    fn test_parse_unified_diff() {
+        let hunks = parse_unified_diff(DIFF);
+        assert_eq!(hunks.len(), 3);
+
+        assert_eq!(hunks[0].file_path, "src/audio/mixer.cpp");
+        assert_eq!(hunks[0].added_lines, vec![11, 12]);
+        assert_eq!(hunks[0].removed_lines, 1);
+        assert_eq!(hunks[0].section.as_deref(), Some("void Mixer::process(Buffer& out) {"));
+
+        assert_eq!((hunks[1].new_start, hunks[1].new_lines), (41, 2));
+        assert!(hunks[1].added_lines.is_empty());
+        assert_eq!(hunks[1].touched_lines(), vec![41]);
+
+        assert_eq!(hunks[2].file_path, "src/old.cpp");
+        assert_eq!(hunks[2].removed_lines, 2);
+    }
+
+    #[test]
+    fn test_code_owners() {
+        let owners = CodeOwners::parse(
+            "# Default owners\n* @core-team\n/src/audio/ @audio-team @alice\n*.md @docs  # docs\nthird_party/ @vendor\n",
+        );
+        assert_eq!(owners.owners_of("src/main.cpp"), vec!["@core-team"]);
+        assert_eq!(owners.owners_of("src/audio/dsp/fir.cpp"), vec!["@audio-team", "@alice"]);
+        assert_eq!(owners.owners_of("src/audio/README.md"), vec!["@docs"]);
+        assert_eq!(owners.owners_of("lib/third_party/zlib/inflate.c"), vec!["@vendor"]);
+        assert!(CodeOwners::parse("/docs/** @docs\n").owners_of("src/a.cpp").is_empty());
+        assert!(wildcard_match("docs/**", "docs/api/index.md"));
+        assert!(!wildcard_match("src/*.cpp", "src/audio/mixer.cpp"));
+    }
+
+    #[test]
+    fn test_is_test_path() {
+        assert!(is_test_path("tests/mixer.cpp"));
+        assert!(is_test_path("src/audio/mixer_test.cpp"));
+        assert!(is_test_path("src/audio/MixerTest.cpp"));
+        assert!(!is_test_path("src/audio/mixer.cpp"));
+        assert!(!is_test_path("src/latest.cpp"));
+    }
+}
@@ -0,0 +1,305 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::repository::Repository;
+use super::diagnostics::relative_to_base;
+use super::review::{enclosing_element, test_references, CodeOwners, DiffHunk};
+
+/// Weight of each factor in the score; they add up to 1
+pub const RISK_WEIGHTS: RiskFactors = RiskFactors { fan_in: 0.35, untested: 0.3, complexity: 0.2, ownership_spread: 0.15 };
+
+/// Scores at or above this are high risk
+pub const HIGH_RISK_SCORE: u32 = 60;
+
+/// Scores at or above this are medium risk
+pub const MEDIUM_RISK_SCORE: u32 = 30;
+
+/// Callers at which the fan-in factor reaches one half
+const FAN_IN_MIDPOINT: f64 = 10.0;
+
+/// Changed lines at which the size half of the complexity factor reaches one half
+const CHANGED_LINES_MIDPOINT: f64 = 150.0;
+
+/// Callees at which the call half of the complexity factor reaches one half
+const CALLEES_MIDPOINT: f64 = 10.0;
+
+/// Extra owner groups at which the ownership factor reaches one half
+const OWNER_GROUPS_MIDPOINT: f64 = 2.0;
+
+/// Coarse rating of a score
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+/// Signals a score is made of, each between 0 and 1
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct RiskFactors {
+    /// How many places call the most-used touched symbol
+    pub fan_in: f64,
+    /// Share of touched symbols no test refers to
+    pub untested: f64,
+    /// Size of the change and call-out degree of the touched symbols
+    pub complexity: f64,
+    /// How many distinct owner groups must review the change
+    pub ownership_spread: f64,
+}
+
+/// A symbol enclosing changed lines, with the index signals about it
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ChangedSymbol {
+    pub symbol_id: i64,
+    pub qualified_name: String,
+    pub file_path: String,
+    pub line_number: u32,
+    /// Lines of the change inside the symbol
+    pub changed_lines: u32,
+    pub reference_count: u64,
+    pub caller_count: u64,
+    pub callee_count: u64,
+    /// References from test code
+    pub test_references: usize,
+}
+
+/// A file the change touches
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub file_path: String,
+    pub changed_lines: u32,
+    /// False if the index has no symbols for the file
+    pub indexed: bool,
+    /// None without a CODEOWNERS file; empty if no rule matches
+    pub owners: Option<Vec<String>>,
+}
+
+/// Heuristic risk of a change, computed from the index alone
+///
+/// The score is the weighted sum of the factors scaled to 0-100. Each
+/// factor saturates: it reaches one half at its midpoint and approaches
+/// one beyond it, so a single outlier can't dominate the score.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RiskReport {
+    pub score: u32,
+    pub level: RiskLevel,
+    pub factors: RiskFactors,
+    pub changed_lines: u32,
+    pub files: Vec<ChangedFile>,
+    /// Touched symbols, most called first
+    pub symbols: Vec<ChangedSymbol>,
+    /// Why the score is what it is, most significant first
+    pub reasons: Vec<String>,
+}
+
+impl RiskLevel {
+    /// Level of a score between 0 and 100
+    pub fn of_score(score: u32) -> Self {
+        if score >= HIGH_RISK_SCORE {
+            Self::High
+        } else if score >= MEDIUM_RISK_SCORE {
+            Self::Medium
+        } else {
+            Self::Low
+        }
+    }
+}
+
+impl RiskFactors {
+    /// Weighted sum of the factors
+    pub fn weighted(&self, weights: &RiskFactors) -> f64 {
+        self.fan_in * weights.fan_in
+            + self.untested * weights.untested
+            + self.complexity * weights.complexity
+            + self.ownership_spread * weights.ownership_spread
+    }
+}
+
+impl RiskReport {
+    /// Maps the hunks of a diff onto an index and scores the result
+    ///
+    /// Paths are resolved like annotate_diff resolves them; CODEOWNERS is
+    /// read from the index's base path.
+    pub fn analyze(repository: &Repository, index: &CodeIndex, hunks: &[DiffHunk]) -> Result<Self> {
+        let base_path = Path::new(&index.base_path);
+        let owners = CodeOwners::load(base_path);
+
+        let mut files: BTreeMap<String, ChangedFile> = BTreeMap::new();
+        let mut symbols: BTreeMap<i64, ChangedSymbol> = BTreeMap::new();
+        for hunk in hunks {
+            let file_path = repository.resolve_path_alias(&index.id, &relative_to_base(base_path, &hunk.file_path))?;
+            let elements = repository.list_code_elements_by_file(&index.id, &file_path)?;
+            let changed_lines = hunk.added_lines.len() as u32 + hunk.removed_lines;
+            let file = files.entry(file_path.clone()).or_insert_with(|| ChangedFile {
+                file_path: file_path.clone(),
+                changed_lines: 0,
+                indexed: !elements.is_empty(),
+                owners: owners.as_ref().map(|owners| owners.owners_of(&file_path)),
+            });
+            file.changed_lines += changed_lines;
+
+            for line in hunk.touched_lines() {
+                let Some(element) = enclosing_element(&elements, line) else { continue };
+                let Some(id) = element.id else { continue };
+                if let Some(symbol) = symbols.get_mut(&id) {
+                    symbol.changed_lines += 1;
+                    continue;
+                }
+                let popularity = repository.get_symbol_popularity(&[id])?.remove(&id).unwrap_or_default();
+                let (_, incoming) = repository.get_symbol_relationships(id)?;
+                symbols.insert(
+                    id,
+                    ChangedSymbol {
+                        symbol_id: id,
+                        qualified_name: element.fully_qualified_name(),
+                        file_path: file_path.clone(),
+                        line_number: element.line_number,
+                        changed_lines: 1,
+                        reference_count: popularity.reference_count,
+                        caller_count: popularity.caller_count,
+                        callee_count: popularity.callee_count,
+                        test_references: test_references(&incoming).len(),
+                    },
+                );
+            }
+        }
+
+        Ok(Self::assess(files.into_values().collect(), symbols.into_values().collect()))
+    }
+
+    /// Scores touched files and symbols
+    pub fn assess(files: Vec<ChangedFile>, mut symbols: Vec<ChangedSymbol>) -> Self {
+        symbols.sort_by(|a, b| b.caller_count.cmp(&a.caller_count).then(a.symbol_id.cmp(&b.symbol_id)));
+        let changed_lines: u32 = files.iter().map(|file| file.changed_lines).sum();
+        let max_callers = symbols.iter().map(|symbol| symbol.caller_count).max().unwrap_or(0);
+        let max_callees = symbols.iter().map(|symbol| symbol.callee_count).max().unwrap_or(0);
+        let untested: Vec<&ChangedSymbol> = symbols.iter().filter(|symbol| symbol.test_references == 0).collect();
+        // Files without a matching rule count as one more group
+        let owner_groups: BTreeSet<&[String]> = files.iter().filter_map(|file| file.owners.as_deref()).collect();
+
+        let factors = RiskFactors {
+            fan_in: saturate(max_callers as f64, FAN_IN_MIDPOINT),
+            untested: if symbols.is_empty() { 0.0 } else { untested.len() as f64 / symbols.len() as f64 },
+            complexity: (saturate(changed_lines as f64, CHANGED_LINES_MIDPOINT) + saturate(max_callees as f64, CALLEES_MIDPOINT)) / 2.0,
+            ownership_spread: saturate(owner_groups.len().saturating_sub(1) as f64, OWNER_GROUPS_MIDPOINT),
+        };
+        let score = (factors.weighted(&RISK_WEIGHTS) * 100.0).round().clamp(0.0, 100.0) as u32;
+
+        let mut reasons: Vec<(f64, String)> = Vec::new();
+        if let Some(symbol) = symbols.first().filter(|symbol| symbol.caller_count > 0) {
+            reasons.push((
+                factors.fan_in * RISK_WEIGHTS.fan_in,
+                format!("{} has {} callers", symbol.qualified_name, symbol.caller_count),
+            ));
+        }
+        if !untested.is_empty() {
+            let names: Vec<&str> = untested.iter().take(3).map(|symbol| symbol.qualified_name.as_str()).collect();
+            let more = if untested.len() > names.len() { format!(" and {} more", untested.len() - names.len()) } else { String::new() };
+            reasons.push((
+                factors.untested * RISK_WEIGHTS.untested,
+                format!("{} of {} changed symbols have no test references: {}{}", untested.len(), symbols.len(), names.join(", "), more),
+            ));
+        }
+        if changed_lines > 0 {
+            reasons.push((
+                factors.complexity * RISK_WEIGHTS.complexity,
+                format!("{} lines changed in {} files; touched symbols call up to {} others", changed_lines, files.len(), max_callees),
+            ));
+        }
+        if owner_groups.len() > 1 {
+            reasons.push((
+                factors.ownership_spread * RISK_WEIGHTS.ownership_spread,
+                format!("Change spans {} owner groups", owner_groups.len()),
+            ));
+        }
+        reasons.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        Self {
+            score,
+            level: RiskLevel::of_score(score),
+            factors,
+            changed_lines,
+            files,
+            symbols,
+            reasons: reasons.into_iter().map(|(_, reason)| reason).collect(),
+        }
+    }
+
+    /// The report as a JSON document
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("risk report is always serializable")
+    }
+}
+
+/// Maps 0.. onto 0..1, reaching one half at `midpoint`
+fn saturate(value: f64, midpoint: f64) -> f64 {
+    if value <= 0.0 {
+        0.0
+    } else {
+        value / (value + midpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(id: i64, callers: u64, tests: usize) -> ChangedSymbol {
+        ChangedSymbol {
+            symbol_id: id,
+            qualified_name: format!("f{}", id),
+            file_path: "src/a.cpp".to_string(),
+            line_number: id as u32,
+            changed_lines: 2,
+            reference_count: callers,
+            caller_count: callers,
+            callee_count: 1,
+            test_references: tests,
+        }
+    }
+
+    fn file(path: &str, lines: u32, owners: &[&str]) -> ChangedFile {
+        ChangedFile {
+            file_path: path.to_string(),
+            changed_lines: lines,
+            indexed: true,
+            owners: Some(owners.iter().map(|owner| owner.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_assess_scores_factors() {
+        let low = RiskReport::assess(vec![file("src/a.cpp", 4, &["@core"])], vec![symbol(1, 0, 2)]);
+        assert_eq!(low.level, RiskLevel::Low);
+        assert_eq!(low.factors.fan_in, 0.0);
+        assert_eq!(low.factors.untested, 0.0);
+        assert_eq!(low.factors.ownership_spread, 0.0);
+
+        let high = RiskReport::assess(
+            vec![file("src/a.cpp", 120, &["@core"]), file("src/audio/b.cpp", 80, &["@audio"]), file("docs/c.md", 5, &[])],
+            vec![symbol(1, 3, 0), symbol(2, 40, 0)],
+        );
+        assert_eq!(high.level, RiskLevel::High);
+        assert!(high.score > low.score);
+        assert_eq!(high.factors.untested, 1.0);
+        assert_eq!(high.factors.fan_in, 0.8);
+        assert_eq!(high.symbols[0].symbol_id, 2);
+        // Untested symbols outweigh even 40 callers: 0.3 * 1.0 against 0.35 * 0.8
+        assert_eq!(high.reasons[0], "2 of 2 changed symbols have no test references: f2, f1");
+        assert_eq!(high.reasons[1], "f2 has 40 callers");
+        assert!(high.reasons.iter().any(|reason| reason == "Change spans 3 owner groups"));
+    }
+
+    #[test]
+    fn test_risk_levels() {
+        assert_eq!(RiskLevel::of_score(0), RiskLevel::Low);
+        assert_eq!(RiskLevel::of_score(MEDIUM_RISK_SCORE), RiskLevel::Medium);
+        assert_eq!(RiskLevel::of_score(100), RiskLevel::High);
+        let total = RISK_WEIGHTS.weighted(&RiskFactors { fan_in: 1.0, untested: 1.0, complexity: 1.0, ownership_spread: 1.0 });
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How far ahead a schedule is searched for its next run
+const MAX_LOOKAHEAD_DAYS: i64 = 5 * 366;
+
+/// Maintenance a long-running server can do on its own
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    /// Re-index files whose content changed since they were indexed, following moved files
+    Refresh,
+    /// ANALYZE and PRAGMA optimize
+    Optimize,
+    /// Copy the write-ahead log back into the database
+    Checkpoint,
+    /// Drop idle client sessions and expired query snapshots
+    ReapSessions,
+    /// Back the database up next to it
+    Snapshot,
+}
+
+/// A task and when it runs, as written in the configuration
+///
+/// ```toml
+/// [[schedule]]
+/// task = "checkpoint"
+/// cron = "*/15 * * * *"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScheduledTask {
+    pub task: MaintenanceTask,
+    /// Five-field cron expression, in UTC
+    pub cron: String,
+}
+
+/// A parsed cron expression: minute, hour, day of month, month and day of week
+///
+/// Fields take `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n`, and
+/// comma-separated lists of those; day of week runs 0-7 with both 0 and 7
+/// meaning Sunday. `@hourly`, `@daily`, `@weekly` and `@monthly` stand for
+/// their usual expressions. As in cron, when both day fields are restricted
+/// a day matching either one is a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// Runs tasks when their schedules come due
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    entries: Vec<ScheduleEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct ScheduleEntry {
+    task: MaintenanceTask,
+    schedule: CronSchedule,
+    next_run: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceTask {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceTask::Refresh => "refresh",
+            MaintenanceTask::Optimize => "optimize",
+            MaintenanceTask::Checkpoint => "checkpoint",
+            MaintenanceTask::ReapSessions => "reap_sessions",
+            MaintenanceTask::Snapshot => "snapshot",
+        }
+    }
+
+    /// Returns true if the task writes to the database
+    pub fn writes(&self) -> bool {
+        matches!(self, MaintenanceTask::Refresh | MaintenanceTask::Optimize)
+    }
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("Cron expression '{}' needs 5 fields, found {}", expression, fields.len()));
+        };
+
+        let mut weekday_bits = parse_field(weekdays, 0, 7, "day of week")?;
+        // 7 is another name for Sunday
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59, "minute")?,
+            hours: parse_field(hours, 0, 23, "hour")?,
+            days: parse_field(days, 1, 31, "day of month")?,
+            months: parse_field(months, 1, 12, "month")?,
+            weekdays: weekday_bits,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+
+    /// The first minute matching the schedule strictly after `after`
+    ///
+    /// None if nothing matches within five years, as for February 30th.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = time + Duration::days(MAX_LOOKAHEAD_DAYS);
+
+        while time < limit {
+            if !has(self.months, time.month()) {
+                let (year, month) = if time.month() == 12 { (time.year() + 1, 1) } else { (time.year(), time.month() + 1) };
+                time = start_of_day(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.day_matches(time.date_naive()) {
+                time = start_of_day(time.date_naive().succ_opt()?);
+            } else if !has(self.hours, time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if !has(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+}
+
+impl Scheduler {
+    /// Schedules `tasks` from `now` on, rejecting the first invalid expression
+    pub fn new(tasks: &[ScheduledTask], now: DateTime<Utc>) -> Result<Self, String> {
+        let entries = tasks
+            .iter()
+            .map(|scheduled| {
+                let schedule = CronSchedule::parse(&scheduled.cron).map_err(|e| format!("{} schedule: {}", scheduled.task.as_str(), e))?;
+                let next_run = schedule.next_after(now);
+                Ok(ScheduleEntry { task: scheduled.task, schedule, next_run })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { entries })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// When the next task comes due
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        self.entries.iter().filter_map(|entry| entry.next_run).min()
+    }
+
+    /// Tasks due at `now`, in configuration order, each moved on to its next run
+    ///
+    /// A task whose runs were missed, e.g. while the machine slept, runs
+    /// once and is then scheduled after `now`.
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<MaintenanceTask> {
+        let mut due = Vec::new();
+        for entry in &mut self.entries {
+            if entry.next_run.is_some_and(|next_run| next_run <= now) {
+                if !due.contains(&entry.task) {
+                    due.push(entry.task);
+                }
+                entry.next_run = entry.schedule.next_after(now);
+            }
+        }
+        due
+    }
+}
+
+/// Parses one cron field into a bit per allowed value
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("Invalid step '{}' in {} field", step, name))?;
+                if step == 0 {
+                    return Err(format!("Step of 0 in {} field", name));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => {
+                let value = |value: &str| {
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|value| (min..=max).contains(value))
+                        .ok_or_else(|| format!("Invalid {} '{}' (expected {}-{})", name, value, min, max))
+                };
+                match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    // A single value with a step runs from the value to the end, as in cron
+                    None if step > 1 => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                }
+            }
+        };
+        if start > end {
+            return Err(format!("Empty range '{}' in {} field", range, name));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight exists"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_cron_schedules() {
+        let quarter_hourly = CronSchedule::parse("*/15 * * * *").unwrap();
+        assert_eq!(quarter_hourly.next_after(at("2024-03-10T10:07:30Z")), Some(at("2024-03-10T10:15:00Z")));
+        assert_eq!(quarter_hourly.next_after(at("2024-03-10T10:15:00Z")), Some(at("2024-03-10T10:30:00Z")));
+
+        // 02:30 on weekdays; Friday evening moves on to Monday
+        let nightly = CronSchedule::parse("30 2 * * 1-5").unwrap();
+        assert_eq!(nightly.next_after(at("2024-03-08T20:00:00Z")), Some(at("2024-03-11T02:30:00Z")));
+
+        assert_eq!(CronSchedule::parse("@weekly").unwrap(), CronSchedule::parse("0 0 * * 7").unwrap());
+        assert_eq!(CronSchedule::parse("0 0 1 1 *").unwrap().next_after(at("2024-06-01T00:00:00Z")), Some(at("2025-01-01T00:00:00Z")));
+        assert_eq!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(at("2024-01-01T00:00:00Z")), None);
+
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_scheduler_takes_due_tasks() {
+        let tasks = [
+            ScheduledTask { task: MaintenanceTask::Checkpoint, cron: "*/10 * * * *".to_string() },
+            ScheduledTask { task: MaintenanceTask::Optimize, cron: "@daily".to_string() },
+        ];
+        let mut scheduler = Scheduler::new(&tasks, at("2024-03-10T23:55:00Z")).unwrap();
+        assert_eq!(scheduler.next_run(), Some(at("2024-03-11T00:00:00Z")));
+        assert!(scheduler.take_due(at("2024-03-10T23:59:59Z")).is_empty());
+        assert_eq!(scheduler.take_due(at("2024-03-11T00:00:00Z")), [MaintenanceTask::Checkpoint, MaintenanceTask::Optimize]);
+        assert_eq!(scheduler.next_run(), Some(at("2024-03-11T00:10:00Z")));
+
+        // Missed runs collapse into one
+        assert_eq!(scheduler.take_due(at("2024-03-11T03:04:00Z")), [MaintenanceTask::Checkpoint]);
+        assert_eq!(scheduler.next_run(), Some(at("2024-03-11T03:10:00Z")));
+
+        let invalid = [ScheduledTask { task: MaintenanceTask::Snapshot, cron: "daily".to_string() }];
+        assert!(Scheduler::new(&invalid, Utc::now()).unwrap_err().starts_with("snapshot schedule"));
+    }
+}
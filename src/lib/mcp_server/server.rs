@@ -1,15 +1,34 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use tracing::{error, info, instrument, warn};
 use uuid::Uuid;
 
-// TODO: Enable when repository interface is finalized
-// use crate::lib::storage::repository::Repository;
-use super::tool_handlers::ToolHandlers;
-use super::resource_handlers::ResourceHandlers;
+use crate::lib::cpp_indexer::adaptive_depth::DepthPlanner;
+use crate::lib::cpp_indexer::index_settings::IndexSettings;
+use crate::lib::cpp_indexer::detail_tiers::DetailPolicy;
+use crate::lib::cpp_indexer::vendored::VendoredDedup;
+use crate::lib::storage::batch_writer::PriorityGate;
+use crate::lib::storage::connection::{CheckpointMode, ConnectionPool, DatabaseManager};
+use crate::lib::storage::embeddings::{EmbeddingBackend, EmbeddingStore};
+use crate::lib::storage::error::StorageError;
+use crate::lib::storage::models::admin_audit::AuditActor;
+use crate::lib::storage::models::code_index::IndexState;
+use crate::lib::storage::recovery::DatabaseRecovery;
+use crate::lib::storage::repository::Repository;
+use crate::lib::storage::watch::WatchChange;
+use super::freshness::{check_file, detect_moves, extract_file, store_moved, store_reindexed, Freshness, MissingFile, StaleCheck};
+use super::failover::{Failover, PrimaryState};
+use super::metrics::{Metrics, METRICS_RESOURCE_URI, PROMETHEUS_MIME_TYPE, STATS_RESOURCE_URI};
+use super::progress::{Cancelled, ToolProgress, REQUEST_CANCELLED};
+use super::registry::RepositoryRegistry;
+use super::scheduler::{MaintenanceTask, Scheduler};
+use super::telemetry::Telemetry;
+use super::tool_handlers::{ReadOnlyMode, ToolHandlers};
+use super::resource_handlers::{files_uri, ResourceHandlers, FILES_URI_TEMPLATE};
 use super::transport::Transport;
 
 /// MCP Protocol Implementation
@@ -29,10 +48,28 @@ pub struct McpServer {
     resource_handlers: ResourceHandlers,
     /// Transport layer for message handling
     transport: Transport,
-    // TODO: Add database repository when available
-    // repository: Repository,
+    /// Database repository shared with the handlers
+    repository: Option<Arc<Mutex<Repository>>>,
+    /// Indices served from databases of their own, shared with the handlers
+    registry: Arc<Mutex<RepositoryRegistry>>,
     /// Active sessions
     sessions: HashMap<String, McpSession>,
+    /// Opt-in usage counts (disabled unless configured)
+    telemetry: Telemetry,
+    /// Per-tool call counts and latencies and database query timings, served as resources
+    metrics: Metrics,
+    /// Maintenance run on a schedule while serving (None = no scheduled tasks)
+    scheduler: Option<Scheduler>,
+    /// Opens connections for maintenance that needs its own (checkpoints, backups)
+    database: Option<Arc<DatabaseManager>>,
+    /// Detail tiers of files re-indexed by a scheduled refresh
+    detail_policy: DetailPolicy,
+    /// True if the attached repository is served read-only
+    read_only: bool,
+    /// Primary or replica of a failover pair (None = serves on its own)
+    failover: Option<Failover>,
+    /// Changed watched queries the file watchers report (None = no watchers)
+    watch_alerts: Option<mpsc::UnboundedReceiver<WatchChange>>,
 }
 
 /// Server information sent during initialization
@@ -50,6 +87,8 @@ pub struct ServerInfo {
 pub struct ServerCapabilities {
     pub tools: Vec<ToolCapability>,
     pub resources: Vec<ResourceCapability>,
+    #[serde(rename = "resourceTemplates")]
+    pub resource_templates: Vec<ResourceTemplateCapability>,
     pub prompts: Vec<PromptCapability>,
 }
 
@@ -72,6 +111,17 @@ pub struct ResourceCapability {
     pub description: String,
 }
 
+/// Resource template definition, for resources whose URIs take parameters
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceTemplateCapability {
+    #[serde(rename = "uriTemplate")]
+    pub uri_template: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub name: String,
+    pub description: String,
+}
+
 /// Prompt capability definition
 #[derive(Debug, Clone, Serialize)]
 pub struct PromptCapability {
@@ -119,6 +169,11 @@ pub enum McpRequest {
         id: Value,
         params: Option<Value>,
     },
+    #[serde(rename = "resources/templates/list")]
+    ResourceTemplatesList {
+        id: Value,
+        params: Option<Value>,
+    },
     #[serde(rename = "tools/list")]
     ToolsList {
         id: Value,
@@ -151,6 +206,9 @@ pub struct InitializeParams {
 pub struct ToolCallParams {
     pub name: String,
     pub arguments: Value,
+    /// Request metadata; a `progressToken` asks for progress notifications
+    #[serde(rename = "_meta", default)]
+    pub meta: Option<Value>,
 }
 
 /// Resource read request parameters
@@ -170,6 +228,14 @@ pub struct McpResponse {
     pub error: Option<McpError>,
 }
 
+/// MCP Notification message; notifications have no id and get no reply
+#[derive(Debug, Clone, Serialize)]
+pub struct McpNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Value,
+}
+
 /// MCP Error response
 #[derive(Debug, Clone, Serialize)]
 pub struct McpError {
@@ -179,6 +245,65 @@ pub struct McpError {
     pub data: Option<Value>,
 }
 
+impl McpError {
+    /// Builds the error for a failed request
+    ///
+    /// Storage errors caused by the request itself (bad input, missing or
+    /// conflicting rows) are reported as invalid params, writes refused by
+    /// read-only storage as a server error, everything else as an internal
+    /// error. The storage error kind is included as data.
+    pub fn from_failure(context: &str, error: &anyhow::Error) -> Self {
+        if error.chain().any(|cause| cause.is::<Cancelled>()) {
+            return Self {
+                code: REQUEST_CANCELLED,
+                message: format!("{}: {}", context, error),
+                data: Some(json!({ "kind": "cancelled" })),
+            };
+        }
+        let storage_error = error.chain().find_map(|cause| cause.downcast_ref::<StorageError>());
+        let code = match storage_error {
+            Some(storage_error) if storage_error.is_client_error() => -32602, // Invalid params
+            Some(StorageError::ReadOnly(_)) => -32000, // Server error: this server can't write
+            _ => -32603, // Internal error
+        };
+
+        Self {
+            code,
+            message: format!("{}: {}", context, error),
+            data: storage_error.map(|storage_error| json!({ "kind": storage_error.kind() })),
+        }
+    }
+}
+
+impl McpNotification {
+    /// Builds a `notifications/message` log notification
+    pub fn log(level: &str, logger: &str, data: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/message".to_string(),
+            params: json!({
+                "level": level,
+                "logger": logger,
+                "data": data
+            }),
+        }
+    }
+
+    /// Builds a `notifications/progress` notification for the request that passed `token`
+    pub fn progress(token: Value, progress: usize, total: usize, message: &str) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/progress".to_string(),
+            params: json!({
+                "progressToken": token,
+                "progress": progress,
+                "total": total,
+                "message": message
+            }),
+        }
+    }
+}
+
 impl McpServer {
     /// Create new MCP server instance
     pub fn new() -> Result<Self> {
@@ -191,8 +316,12 @@ impl McpServer {
         };
 
         let capabilities = Self::build_capabilities()?;
-        let tool_handlers = ToolHandlers::new()?;
-        let resource_handlers = ResourceHandlers::new()?;
+        let metrics = Metrics::new();
+        let registry = Arc::new(Mutex::new(RepositoryRegistry::new().with_query_timings(metrics.query_timings().clone())));
+        let tool_handlers = ToolHandlers::new()?.with_registry(Arc::clone(&registry));
+        let resource_handlers = ResourceHandlers::new()?
+            .with_metrics(metrics.clone())
+            .with_registry(Arc::clone(&registry));
         let transport = Transport::new()?;
 
         Ok(Self {
@@ -201,11 +330,153 @@ impl McpServer {
             tool_handlers,
             resource_handlers,
             transport,
-            // repository,
+            repository: None,
+            registry,
             sessions: HashMap::new(),
+            telemetry: Telemetry::disabled(),
+            metrics,
+            scheduler: None,
+            database: None,
+            detail_policy: DetailPolicy::default(),
+            read_only: false,
+            failover: None,
+            watch_alerts: None,
         })
     }
 
+    /// Attach the database repository used to answer index queries
+    pub fn with_repository(mut self, repository: Repository) -> Self {
+        let repository = Arc::new(Mutex::new(repository.with_query_timings(self.metrics.query_timings().clone())));
+        self.tool_handlers = self.tool_handlers.with_repository(Arc::clone(&repository));
+        self.resource_handlers = self.resource_handlers.with_repository(Arc::clone(&repository));
+        self.repository = Some(repository);
+        self
+    }
+
+    /// Serve the indices registered in `registry` from their own databases
+    ///
+    /// Their databases are opened by the first tool call naming them; every
+    /// other index is answered from the attached repository.
+    pub fn with_registry(mut self, registry: RepositoryRegistry) -> Self {
+        self.registry = Arc::new(Mutex::new(registry.with_query_timings(self.metrics.query_timings().clone())));
+        self.tool_handlers = self.tool_handlers.with_registry(Arc::clone(&self.registry));
+        self.resource_handlers = self.resource_handlers.with_registry(Arc::clone(&self.registry));
+        self
+    }
+
+    /// Let clients take query snapshots of the database `manager` opens
+    pub fn with_database_manager(mut self, manager: Arc<DatabaseManager>) -> Self {
+        self.tool_handlers = self.tool_handlers.with_database_manager(Arc::clone(&manager));
+        self.database = Some(manager);
+        self
+    }
+
+    /// Answer queries on the default database from `pool`'s reader connections
+    ///
+    /// Attach the pool's writer with [`McpServer::with_repository`] so index
+    /// updates and queries don't wait on one connection:
+    /// `server.with_repository(Repository::new(pool.writer()?)).with_connection_pool(pool)`.
+    pub fn with_connection_pool(mut self, pool: ConnectionPool) -> Self {
+        self.tool_handlers = self.tool_handlers.with_connection_pool(pool);
+        self
+    }
+
+    /// Check files named by tool calls against the index on disk
+    pub fn with_stale_check(mut self, stale_check: StaleCheck) -> Self {
+        self.tool_handlers = self.tool_handlers.with_stale_check(stale_check);
+        self
+    }
+
+    /// Only flag stale files over `max_bytes` instead of re-indexing them inline
+    pub fn with_stale_reindex_limit(mut self, max_bytes: u64) -> Self {
+        self.tool_handlers = self.tool_handlers.with_stale_reindex_limit(max_bytes);
+        self
+    }
+
+    /// Cache up to `entries` answers of the hottest read tools (0 = no cache)
+    pub fn with_query_cache(mut self, entries: usize) -> Self {
+        self.tool_handlers = self.tool_handlers.with_query_cache(entries);
+        self
+    }
+
+    /// Answer semantic_search from the vectors in `store`, embedding queries with `backend`
+    pub fn with_embeddings(mut self, store: EmbeddingStore, backend: Box<dyn EmbeddingBackend>) -> Self {
+        self.tool_handlers = self.tool_handlers.with_embeddings(store, backend);
+        self
+    }
+
+    /// Store files re-indexed inline with the index's detail tiers
+    pub fn with_detail_policy(mut self, policy: DetailPolicy) -> Self {
+        self.tool_handlers = self.tool_handlers.with_detail_policy(policy);
+        self.detail_policy = policy;
+        self
+    }
+
+    /// Learn indexing depth per directory from the paths clients query
+    pub fn with_adaptive_depth(mut self, planner: DepthPlanner) -> Self {
+        self.tool_handlers = self.tool_handlers.with_adaptive_depth(planner);
+        self
+    }
+
+    /// Parse indexed files in `parse-worker` processes of `program`
+    pub fn with_parse_worker(mut self, program: std::path::PathBuf) -> Self {
+        self.tool_handlers = self.tool_handlers.with_parse_worker(program);
+        self
+    }
+
+    /// Skip byte-identical copies of vendored trees when indexing
+    pub fn with_vendored_dedup(mut self, dedup: VendoredDedup) -> Self {
+        self.tool_handlers = self.tool_handlers.with_vendored_dedup(dedup);
+        self
+    }
+
+    /// Serve the attached repository read-only, e.g. after a failed integrity check
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.tool_handlers = self.tool_handlers.with_read_only(read_only);
+        self.read_only = read_only;
+        self
+    }
+
+    /// Serve read-only for the reason `mode` gives, e.g. next to a writer in another process
+    pub fn with_read_only_mode(mut self, mode: ReadOnlyMode) -> Self {
+        self.tool_handlers = self.tool_handlers.with_read_only_mode(mode);
+        self.read_only = true;
+        self
+    }
+
+    /// Run maintenance tasks when their schedules come due, between requests
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = (!scheduler.is_empty()).then_some(scheduler);
+        self
+    }
+
+    /// Take part in failover as a primary writing its heartbeat or a replica watching it
+    ///
+    /// A replica should be served read-only; it answers tool calls only
+    /// while the primary is rebuilding or silent.
+    pub fn with_failover(mut self, failover: Failover) -> Self {
+        self.failover = Some(failover);
+        self
+    }
+
+    /// Count tool usage with an opt-in telemetry collector
+    pub fn with_telemetry(mut self, telemetry: Telemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    /// Let tool calls pause bulk index writes that share `gate`
+    pub fn with_priority_gate(mut self, gate: PriorityGate) -> Self {
+        self.tool_handlers = self.tool_handlers.with_priority_gate(gate);
+        self
+    }
+
+    /// Sends each watched query change received on `alerts` to the client as a log notification
+    pub fn with_watch_alerts(mut self, alerts: mpsc::UnboundedReceiver<WatchChange>) -> Self {
+        self.watch_alerts = Some(alerts);
+        self
+    }
+
     /// Build server capabilities from tool and resource specifications
     fn build_capabilities() -> Result<ServerCapabilities> {
         // Load tool specifications from embedded JSON
@@ -231,6 +502,18 @@ impl McpServer {
                 name: "Index Metadata".to_string(),
                 description: "Metadata about available code indices".to_string(),
             },
+            ResourceCapability {
+                uri: STATS_RESOURCE_URI.to_string(),
+                mime_type: "application/json".to_string(),
+                name: "Server Statistics".to_string(),
+                description: "Calls, errors and latency per tool, and database query timings".to_string(),
+            },
+            ResourceCapability {
+                uri: METRICS_RESOURCE_URI.to_string(),
+                mime_type: PROMETHEUS_MIME_TYPE.to_string(),
+                name: "Prometheus Metrics".to_string(),
+                description: "The server statistics in the Prometheus text format".to_string(),
+            },
             ResourceCapability {
                 uri: "index://schema".to_string(),
                 mime_type: "application/json".to_string(),
@@ -239,11 +522,19 @@ impl McpServer {
             },
         ];
 
+        let resource_templates = vec![ResourceTemplateCapability {
+            uri_template: FILES_URI_TEMPLATE.to_string(),
+            mime_type: "application/json".to_string(),
+            name: "Indexed Files".to_string(),
+            description: "Metadata of an indexed file, or the tree of an indexed directory; the index name and path are percent-encoded".to_string(),
+        }];
+
         let prompts = vec![];
 
         Ok(ServerCapabilities {
             tools,
             resources,
+            resource_templates,
             prompts,
         })
     }
@@ -258,14 +549,33 @@ impl McpServer {
         // Start transport layer
         self.transport.start(tx).await?;
 
-        // Main message processing loop
-        while let Some(request) = rx.recv().await {
+        // Main message processing loop; scheduled tasks and heartbeats run between requests
+        loop {
+            let next_run = self.scheduler.as_ref().and_then(|scheduler| scheduler.next_run());
+            let next_beat = self.failover.as_ref().and_then(|failover| failover.next_beat());
+            let request = tokio::select! {
+                request = rx.recv() => request,
+                _ = sleep_until(next_run) => {
+                    self.run_due_tasks().await;
+                    continue;
+                }
+                _ = sleep_until(next_beat) => {
+                    self.beat(PrimaryState::Serving);
+                    continue;
+                }
+                Some(change) = next_alert(&mut self.watch_alerts) => {
+                    self.notify_watch_change(json!(change)).await;
+                    continue;
+                }
+            };
+            let Some(request) = request else { break };
             match self.handle_request(request).await {
-                Ok(response) => {
+                Ok(Some(response)) => {
                     if let Err(e) = self.transport.send_response(response).await {
                         error!("Failed to send response: {}", e);
                     }
                 }
+                Ok(None) => {}
                 Err(e) => {
                     error!("Request handling failed: {}", e);
                     // Send error response if possible
@@ -273,18 +583,29 @@ impl McpServer {
             }
         }
 
+        // The client closed stdin; answers still queued are written before returning
+        self.transport.drain(std::time::Duration::from_secs(5)).await;
+        self.flush_telemetry();
+        if let Some(failover) = self.failover.as_mut() {
+            if let Err(e) = failover.resign() {
+                warn!("Failed to remove heartbeat {}: {}", failover.heartbeat_path().display(), e);
+            }
+        }
         Ok(())
     }
 
     /// Handle incoming MCP requests
+    ///
+    /// Returns None for a request that gets no response: a tool call the
+    /// client cancelled.
     #[instrument(skip(self))]
-    async fn handle_request(&mut self, request: McpRequest) -> Result<McpResponse> {
-        match request {
+    async fn handle_request(&mut self, request: McpRequest) -> Result<Option<McpResponse>> {
+        let response = match request {
             McpRequest::Initialize { id, params } => {
                 self.handle_initialize(id, params).await
             }
             McpRequest::ToolsCall { id, params } => {
-                self.handle_tools_call(id, params).await
+                return self.handle_tools_call(id, params).await;
             }
             McpRequest::ResourcesRead { id, params } => {
                 self.handle_resources_read(id, params).await
@@ -292,6 +613,9 @@ impl McpServer {
             McpRequest::ResourcesList { id, .. } => {
                 self.handle_resources_list(id).await
             }
+            McpRequest::ResourceTemplatesList { id, .. } => {
+                self.handle_resource_templates_list(id).await
+            }
             McpRequest::ToolsList { id, .. } => {
                 self.handle_tools_list(id).await
             }
@@ -301,7 +625,8 @@ impl McpServer {
             McpRequest::Ping { id, .. } => {
                 self.handle_ping(id).await
             }
-        }
+        };
+        response.map(Some)
     }
 
     /// Handle initialization request
@@ -317,6 +642,17 @@ impl McpServer {
 
         // Create new session
         let session_id = Uuid::new_v4().to_string();
+        let actor = AuditActor::new(format!("mcp:{}", params.client_info.name)).with_session(session_id.clone());
+        if let Some(repository) = &self.repository {
+            match repository.lock() {
+                Ok(mut repository) => repository.set_audit_actor(actor.clone()),
+                Err(_) => warn!("Repository lock poisoned; audit actor not updated"),
+            }
+        }
+        match self.registry.lock() {
+            Ok(mut registry) => registry.set_audit_actor(actor),
+            Err(_) => warn!("Repository registry lock poisoned; audit actor not updated"),
+        }
         let session = McpSession {
             id: session_id.clone(),
             client_info: Some(params.client_info),
@@ -342,31 +678,251 @@ impl McpServer {
     }
 
     /// Handle tool call request
+    ///
+    /// A call the client cancelled isn't answered; it already gave up on it.
     #[instrument(skip(self))]
-    async fn handle_tools_call(&mut self, id: Value, params: ToolCallParams) -> Result<McpResponse> {
+    async fn handle_tools_call(&mut self, id: Value, params: ToolCallParams) -> Result<Option<McpResponse>> {
         info!("Handling tool call: {}", params.name);
-        
-        match self.tool_handlers.handle_tool_call(&params.name, params.arguments).await {
-            Ok(result) => Ok(McpResponse {
+
+        // Only advertised tool names are counted, never arbitrary client input
+        let tool_name = if self.capabilities.tools.iter().any(|tool| tool.name == params.name) {
+            params.name.clone()
+        } else {
+            "unknown".to_string()
+        };
+        if let Some(primary) = self.failover.as_ref().and_then(|failover| failover.standby_for(chrono::Utc::now())) {
+            return Ok(Some(McpResponse {
                 jsonrpc: "2.0".to_string(),
                 id,
-                result: Some(result),
-                error: None,
-            }),
+                result: None,
+                error: Some(McpError {
+                    code: -32000,
+                    message: format!("Standby replica: the primary (pid {} on {}) is serving", primary.pid, primary.host),
+                    data: Some(json!({ "kind": "standby", "primary": primary })),
+                }),
+            }));
+        }
+        let started = std::time::Instant::now();
+        let cancellations = self.transport.cancellations().clone();
+        let mut progress = ToolProgress::new(cancellations.register(&id));
+        let token = params.meta.as_ref().map(|meta| meta["progressToken"].clone()).filter(|token| !token.is_null());
+        if let (Some(token), Some(sender)) = (token, self.transport.notifier()) {
+            progress = progress.with_notifications(token, sender);
+        }
+        let outcome = self.tool_handlers.handle_tool_call_with_progress(&params.name, params.arguments, &mut progress).await;
+        cancellations.finish(&id);
+        let error_kind = outcome.as_ref().err().map(|e| {
+            if e.chain().any(|cause| cause.is::<Cancelled>()) {
+                return "cancelled";
+            }
+            e.chain()
+                .find_map(|cause| cause.downcast_ref::<StorageError>())
+                .map_or("internal", StorageError::kind)
+        });
+        let elapsed = started.elapsed();
+        self.metrics.record_tool_call(&tool_name, elapsed, error_kind);
+        info!(
+            tool = %tool_name,
+            duration_ms = elapsed.as_millis() as u64,
+            outcome = error_kind.unwrap_or("ok"),
+            "Tool call finished"
+        );
+        self.telemetry.record_tool_call(&tool_name, error_kind);
+        if self.telemetry.flush_due() {
+            self.flush_telemetry();
+        }
+
+        if progress.is_cancelled() {
+            info!(tool = %tool_name, "Cancelled tool call not answered");
+            return Ok(None);
+        }
+        match outcome {
+            Ok(result) => {
+                self.notify_watch_changes(&result).await;
+                Ok(Some(McpResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: Some(result),
+                    error: None,
+                }))
+            }
             Err(e) => {
                 error!("Tool call failed: {}", e);
-                Ok(McpResponse {
+                Ok(Some(McpResponse {
                     jsonrpc: "2.0".to_string(),
                     id,
                     result: None,
-                    error: Some(McpError {
-                        code: -32603, // Internal error
-                        message: format!("Tool execution failed: {}", e),
-                        data: None,
-                    }),
-                })
+                    error: Some(McpError::from_failure("Tool execution failed", &e)),
+                }))
+            }
+        }
+    }
+
+    /// Runs the scheduled tasks that are due, logging their outcome
+    async fn run_due_tasks(&mut self) {
+        let due = match self.scheduler.as_mut() {
+            Some(scheduler) => scheduler.take_due(chrono::Utc::now()),
+            None => return,
+        };
+        // A replica serves while the primary rebuilds
+        let rebuilding = due.iter().any(|task| task.writes()) && !self.read_only;
+        if rebuilding {
+            self.beat(PrimaryState::Rebuilding);
+        }
+        for task in due {
+            if self.read_only && task.writes() {
+                warn!("Skipped scheduled {}: storage is read-only", task.as_str());
+                continue;
+            }
+            let started = std::time::Instant::now();
+            match self.run_maintenance(task).await {
+                Ok(outcome) => info!("Scheduled {} finished in {:?}: {}", task.as_str(), started.elapsed(), outcome),
+                Err(e) => error!("Scheduled {} failed: {}", task.as_str(), e),
+            }
+        }
+        if rebuilding {
+            self.beat(PrimaryState::Serving);
+        }
+    }
+
+    /// Writes the primary's heartbeat; a failed write only delays failover
+    fn beat(&mut self, state: PrimaryState) {
+        let Some(failover) = self.failover.as_mut() else { return };
+        if let Err(e) = failover.beat(state) {
+            warn!("Failed to write heartbeat {}: {}", failover.heartbeat_path().display(), e);
+        }
+    }
+
+    /// Runs one maintenance task, describing what it did
+    async fn run_maintenance(&mut self, task: MaintenanceTask) -> Result<String> {
+        match task {
+            MaintenanceTask::Refresh => {
+                let (stale, refreshed, moved) = self.refresh_stale_files().await?;
+                Ok(format!("re-indexed {} of {} changed files, followed {} moved files", refreshed, stale, moved))
+            }
+            MaintenanceTask::Optimize => {
+                let result = self.database_manager()?.maintenance()?;
+                Ok(format!("analyzed in {:?}, optimized in {:?}", result.analyze_duration, result.optimize_duration))
+            }
+            MaintenanceTask::Checkpoint => {
+                let manager = self.database_manager()?;
+                let result = manager.checkpoint(&manager.connect()?, CheckpointMode::Passive)?;
+                Ok(format!("{} of {} WAL frames checkpointed", result.checkpointed_frames, result.log_frames))
+            }
+            MaintenanceTask::ReapSessions => {
+                let sessions = self.sessions.len();
+                self.cleanup_sessions();
+                let snapshots = self.tool_handlers.expire_query_snapshots();
+                Ok(format!("closed {} idle sessions and {} expired query snapshots", sessions - self.sessions.len(), snapshots))
+            }
+            MaintenanceTask::Snapshot => {
+                let manager = self.database_manager()?;
+                let path = DatabaseRecovery::new(manager.config().clone()).create_backup(&manager.connect()?)?;
+                Ok(format!("backed up to {}", path.display()))
+            }
+        }
+    }
+
+    fn database_manager(&self) -> Result<&DatabaseManager> {
+        self.database.as_deref().ok_or_else(|| anyhow!("No database attached to run maintenance on"))
+    }
+
+    /// Re-indexes the files of active indices whose content changed on disk
+    ///
+    /// Files gone from disk are looked for among new files, and followed to
+    /// where they moved. Files are hashed under the repository lock, but
+    /// parsed without it so a long refresh doesn't hold the database.
+    async fn refresh_stale_files(&self) -> Result<(usize, usize, usize)> {
+        let Some(repository) = &self.repository else {
+            return Ok((0, 0, 0));
+        };
+        let (stale, missing) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let mut stale = Vec::new();
+            let mut missing = Vec::new();
+            for index in repository.list_code_indices()? {
+                if !repository.get_code_index_state(&index.id)?.is_some_and(|state| state == IndexState::Active) {
+                    continue;
+                }
+                let settings = match IndexSettings::load(&repository, &index) {
+                    Ok(settings) => Arc::new(settings),
+                    Err(e) => {
+                        warn!("Scheduled refresh skipped index '{}': {}", index.name, e);
+                        continue;
+                    }
+                };
+                let files = repository.list_file_metadata(&index.id)?;
+                let mut gone = Vec::new();
+                for file in &files {
+                    match check_file(&repository, &index, &file.file_path) {
+                        Ok(report) if report.status == Freshness::Stale => stale.push((index.clone(), settings.clone(), report)),
+                        Ok(report) if report.status == Freshness::Missing => gone.push(MissingFile {
+                            file_path: file.file_path.clone(),
+                            file_hash: file.file_hash.clone(),
+                            elements: repository.list_code_elements_by_file(&index.id, &file.file_path)?,
+                        }),
+                        Ok(_) => {}
+                        Err(e) => warn!("Scheduled refresh could not check {}: {}", file.file_path, e),
+                    }
+                }
+                if !gone.is_empty() {
+                    let indexed: BTreeSet<String> = files.into_iter().map(|file| file.file_path).collect();
+                    missing.push((index, settings, gone, indexed));
+                }
+            }
+            (stale, missing)
+        };
+
+        let mut refreshed = 0;
+        for (index, settings, mut report) in stale.iter().cloned() {
+            match extract_file(&index, &settings, &report.file_path, &self.detail_policy).await {
+                Ok(reparsed) => {
+                    let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+                    store_reindexed(&repository, &index, &mut report, reparsed)?;
+                    refreshed += 1;
+                }
+                Err(e) => warn!("Scheduled refresh skipped {}: {}", report.file_path, e),
+            }
+        }
+
+        let mut moved = 0;
+        for (index, settings, gone, indexed) in missing {
+            for detected in detect_moves(&index, &settings, &gone, &indexed, &self.detail_policy).await? {
+                let (from, to) = (detected.file_move.from.clone(), detected.file_move.to.clone());
+                let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+                match store_moved(&repository, &index, detected) {
+                    Ok(_) => {
+                        info!("Followed {} to {}", from, to);
+                        moved += 1;
+                    }
+                    Err(e) => warn!("Scheduled refresh could not move {} to {}: {}", from, to, e),
+                }
             }
         }
+        Ok((stale.len(), refreshed, moved))
+    }
+
+    /// Spools the usage counts collected so far; telemetry never fails a request
+    fn flush_telemetry(&mut self) {
+        let repository = self.repository.as_ref().and_then(|repository| repository.lock().ok());
+        if let Err(e) = self.telemetry.flush(repository.as_deref()) {
+            warn!("Failed to spool telemetry: {}", e);
+        }
+    }
+
+    /// Sends a log notification for each watched query a tool call reported as changed
+    async fn notify_watch_changes(&self, result: &Value) {
+        let Some(changes) = result["watch_changes"].as_array() else { return };
+        for change in changes {
+            self.notify_watch_change(change.clone()).await;
+        }
+    }
+
+    async fn notify_watch_change(&self, change: Value) {
+        let notification = McpNotification::log("warning", "watch", change);
+        if let Err(e) = self.transport.send_notification(notification).await {
+            warn!("Failed to send watch notification: {}", e);
+        }
     }
 
     /// Handle resource read request
@@ -387,11 +943,7 @@ impl McpServer {
                     jsonrpc: "2.0".to_string(),
                     id,
                     result: None,
-                    error: Some(McpError {
-                        code: -32603,
-                        message: format!("Resource read failed: {}", e),
-                        data: None,
-                    }),
+                    error: Some(McpError::from_failure("Resource read failed", &e)),
                 })
             }
         }
@@ -400,8 +952,33 @@ impl McpServer {
     /// Handle resources list request
     #[instrument(skip(self))]
     async fn handle_resources_list(&self, id: Value) -> Result<McpResponse> {
+        let mut resources = self.capabilities.resources.clone();
+        match self.resource_handlers.index_names() {
+            Ok(names) => resources.extend(names.into_iter().map(|name| ResourceCapability {
+                uri: files_uri(&name, ""),
+                mime_type: "application/json".to_string(),
+                name: format!("Files of {}", name),
+                description: format!("File tree of index {} with the hash, symbol count and indexing time of each file", name),
+            })),
+            Err(e) => warn!("Failed to list the indices for resources: {}", e),
+        }
         let result = json!({
-            "resources": self.capabilities.resources
+            "resources": resources
+        });
+
+        Ok(McpResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        })
+    }
+
+    /// Handle resource templates list request
+    #[instrument(skip(self))]
+    async fn handle_resource_templates_list(&self, id: Value) -> Result<McpResponse> {
+        let result = json!({
+            "resourceTemplates": self.capabilities.resource_templates
         });
 
         Ok(McpResponse {
@@ -465,6 +1042,27 @@ impl McpServer {
     }
 }
 
+/// Waits until `deadline`, or forever without one
+async fn sleep_until(deadline: Option<chrono::DateTime<chrono::Utc>>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep((deadline - chrono::Utc::now()).to_std().unwrap_or_default()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The next change the watchers report; never resolves without watchers or once they have all stopped
+async fn next_alert(alerts: &mut Option<mpsc::UnboundedReceiver<WatchChange>>) -> Option<WatchChange> {
+    let change = match alerts {
+        Some(receiver) => receiver.recv().await,
+        None => None,
+    };
+    if change.is_none() {
+        *alerts = None;
+        std::future::pending::<()>().await;
+    }
+    change
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -479,12 +1077,37 @@ mod tests {
         assert_eq!(server.session_count(), 0);
     }
 
+    #[test]
+    fn test_error_codes_for_storage_failures() {
+        let validation = anyhow::Error::new(StorageError::Validation("name is empty".to_string()));
+        let error = McpError::from_failure("Tool execution failed", &validation);
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.data, Some(json!({ "kind": "validation" })));
+
+        let corruption = anyhow::Error::new(StorageError::Corruption("bad page".to_string())).context("list_indices");
+        let error = McpError::from_failure("Tool execution failed", &corruption);
+        assert_eq!(error.code, -32603);
+        assert_eq!(error.data, Some(json!({ "kind": "corruption" })));
+
+        let other = McpError::from_failure("Tool execution failed", &anyhow!("boom"));
+        assert_eq!(other.code, -32603);
+        assert!(other.data.is_none());
+
+        let read_only = anyhow::Error::new(StorageError::ReadOnly("started with --read-only".to_string()));
+        let error = McpError::from_failure("Tool execution failed", &read_only);
+        assert_eq!((error.code, error.data), (-32000, Some(json!({ "kind": "read_only" }))));
+
+        let cancelled = anyhow::Error::new(Cancelled("Indexing audio cancelled".to_string()));
+        let error = McpError::from_failure("Tool execution failed", &cancelled);
+        assert_eq!((error.code, error.data), (REQUEST_CANCELLED, Some(json!({ "kind": "cancelled" }))));
+    }
+
     #[tokio::test] 
     async fn test_capabilities_building() {
         let capabilities = McpServer::build_capabilities().unwrap();
         
-        // Should have all 8 MCP tools
-        assert_eq!(capabilities.tools.len(), 8);
+        // Should have all MCP tools
+        assert_eq!(capabilities.tools.len(), 46);
         
         // Should have expected tool names
         let tool_names: Vec<&str> = capabilities.tools.iter()
@@ -498,6 +1121,67 @@ mod tests {
         assert!(tool_names.contains(&"list_indices"));
         assert!(tool_names.contains(&"delete_index"));
         assert!(tool_names.contains(&"get_file_symbols"));
+        assert!(tool_names.contains(&"get_file_outline"));
         assert!(tool_names.contains(&"update_file"));
+        assert!(tool_names.contains(&"explain_linker_error"));
+        assert!(tool_names.contains(&"get_compiler_error_context"));
+        assert!(tool_names.contains(&"annotate_diff"));
+        assert!(tool_names.contains(&"set_index_tags"));
+        assert!(tool_names.contains(&"tag_symbol"));
+        assert!(tool_names.contains(&"analyze_hot_paths"));
+        assert!(tool_names.contains(&"find_symbols_in_section"));
+        assert!(tool_names.contains(&"conditional_compilation_matrix"));
+        assert!(tool_names.contains(&"annotate_symbol"));
+        assert!(tool_names.contains(&"list_annotations"));
+        assert!(tool_names.contains(&"delete_annotation"));
+        assert!(tool_names.contains(&"save_query"));
+        assert!(tool_names.contains(&"list_saved_queries"));
+        assert!(tool_names.contains(&"run_saved_query"));
+        assert!(tool_names.contains(&"delete_saved_query"));
+        assert!(tool_names.contains(&"watch_query"));
+        assert!(tool_names.contains(&"check_watches"));
+        assert!(tool_names.contains(&"get_call_graph"));
+        assert!(tool_names.contains(&"search_text"));
+        assert!(tool_names.contains(&"grep_code"));
+        assert!(tool_names.contains(&"semantic_search"));
+        assert!(tool_names.contains(&"begin_query_snapshot"));
+        assert!(tool_names.contains(&"end_query_snapshot"));
+        assert!(tool_names.contains(&"resolve_symbol"));
+        assert!(tool_names.contains(&"get_type_hierarchy"));
+        assert!(tool_names.contains(&"get_include_graph"));
+        assert!(tool_names.contains(&"promote_file_detail"));
+        assert!(tool_names.contains(&"switch_index_revision"));
+        assert!(tool_names.contains(&"get_index_depths"));
+    }
+
+    #[tokio::test]
+    async fn test_resource_templates_list() {
+        let mut server = McpServer::new().unwrap();
+        let request: McpRequest = serde_json::from_value(json!({"jsonrpc": "2.0", "id": 3, "method": "resources/templates/list"})).unwrap();
+        let response = server.handle_request(request).await.unwrap().unwrap();
+        let templates = &response.result.unwrap()["resourceTemplates"];
+        assert_eq!(templates[0]["uriTemplate"], "cpp-index://{index}/files/{path}");
+        assert_eq!(templates[0]["mimeType"], "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_watch_alerts_end_with_the_watchers() {
+        let (alerts, inbox) = mpsc::unbounded_channel();
+        let mut inbox = Some(inbox);
+        let change = WatchChange {
+            name: "long_functions".to_string(),
+            query: "type:function lines:>300".to_string(),
+            added: vec!["src/net/socket.cpp: net::Socket::poll (function)".to_string()],
+            removed: Vec::new(),
+            total_count: 1,
+        };
+        alerts.send(change.clone()).unwrap();
+        assert_eq!(next_alert(&mut inbox).await, Some(change));
+
+        // Once every watcher stopped the alerts never resolve again
+        drop(alerts);
+        let wait = tokio::time::timeout(std::time::Duration::from_millis(20), next_alert(&mut inbox)).await;
+        assert!(wait.is_err());
+        assert!(inbox.is_none());
     }
 }
\ No newline at end of file
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::lib::storage::repository::Repository;
+
+/// Version of the report format, bumped when fields change meaning
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Tool calls after which counters are spooled even if the server keeps running
+pub const FLUSH_EVERY_CALLS: u64 = 100;
+
+/// Upper bounds of the index size buckets; sizes above the last are "1M+"
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (0, "0"),
+    (99, "1-99"),
+    (999, "100-999"),
+    (9_999, "1k-10k"),
+    (99_999, "10k-100k"),
+    (999_999, "100k-1M"),
+];
+
+/// Anonymous usage statistics for one server run (or part of one)
+///
+/// Reports hold counts only: which tools were called, how calls failed by
+/// error kind, and how many indices fall in each size bucket. No names,
+/// paths, queries or symbols are included.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TelemetryReport {
+    pub schema_version: u32,
+    pub server_version: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    /// Calls per tool name
+    pub tool_calls: BTreeMap<String, u64>,
+    /// Failed calls per error kind (e.g. "validation", "internal")
+    pub error_kinds: BTreeMap<String, u64>,
+    /// Indices per symbol count bucket
+    pub index_symbol_buckets: BTreeMap<String, u64>,
+    /// Indices per file count bucket
+    pub index_file_buckets: BTreeMap<String, u64>,
+}
+
+/// Opt-in collector of usage counts, spooled locally as JSON lines
+///
+/// A disabled collector ignores everything, so callers can record
+/// unconditionally. Spooled reports are only sent by an explicit
+/// `telemetry send`.
+#[derive(Debug, Clone)]
+pub struct Telemetry {
+    enabled: bool,
+    spool_path: PathBuf,
+    period_start: DateTime<Utc>,
+    tool_calls: BTreeMap<String, u64>,
+    error_kinds: BTreeMap<String, u64>,
+    calls_since_flush: u64,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl Telemetry {
+    /// A collector that records nothing
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            spool_path: PathBuf::new(),
+            period_start: Utc::now(),
+            tool_calls: BTreeMap::new(),
+            error_kinds: BTreeMap::new(),
+            calls_since_flush: 0,
+        }
+    }
+
+    /// An enabled collector spooling to `spool_path`
+    pub fn new<P: AsRef<Path>>(spool_path: P) -> Self {
+        Self {
+            enabled: true,
+            spool_path: spool_path.as_ref().to_path_buf(),
+            ..Self::disabled()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Counts a tool call and, if it failed, its error kind
+    pub fn record_tool_call(&mut self, tool: &str, error_kind: Option<&str>) {
+        if !self.enabled {
+            return;
+        }
+        *self.tool_calls.entry(tool.to_string()).or_default() += 1;
+        if let Some(kind) = error_kind {
+            *self.error_kinds.entry(kind.to_string()).or_default() += 1;
+        }
+        self.calls_since_flush += 1;
+    }
+
+    /// True once enough calls have been counted to spool them
+    pub fn flush_due(&self) -> bool {
+        self.enabled && self.calls_since_flush >= FLUSH_EVERY_CALLS
+    }
+
+    /// Report of the calls counted since the last flush
+    pub fn report(&self, repository: Option<&Repository>) -> Result<TelemetryReport> {
+        let mut index_symbol_buckets = BTreeMap::new();
+        let mut index_file_buckets = BTreeMap::new();
+        if let Some(repository) = repository {
+            for index in repository.list_code_indices()? {
+                *index_symbol_buckets.entry(size_bucket(index.total_symbols as u64).to_string()).or_default() += 1;
+                *index_file_buckets.entry(size_bucket(index.total_files as u64).to_string()).or_default() += 1;
+            }
+        }
+
+        Ok(TelemetryReport {
+            schema_version: REPORT_SCHEMA_VERSION,
+            server_version: crate::VERSION.to_string(),
+            period_start: self.period_start,
+            period_end: Utc::now(),
+            tool_calls: self.tool_calls.clone(),
+            error_kinds: self.error_kinds.clone(),
+            index_symbol_buckets,
+            index_file_buckets,
+        })
+    }
+
+    /// Appends a report to the spool and resets the counters
+    ///
+    /// Returns false, without writing, when disabled or nothing was counted.
+    pub fn flush(&mut self, repository: Option<&Repository>) -> Result<bool> {
+        if !self.enabled || self.tool_calls.is_empty() {
+            return Ok(false);
+        }
+
+        let report = self.report(repository)?;
+        if let Some(parent) = self.spool_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut spool = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.spool_path)
+            .with_context(|| format!("Failed to open telemetry spool {}", self.spool_path.display()))?;
+        writeln!(spool, "{}", serde_json::to_string(&report)?)?;
+
+        self.tool_calls.clear();
+        self.error_kinds.clear();
+        self.calls_since_flush = 0;
+        self.period_start = report.period_end;
+        Ok(true)
+    }
+}
+
+/// Bucket label for an index size, so reports never carry exact sizes
+pub fn size_bucket(size: u64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(upper, _)| size <= *upper)
+        .map_or("1M+", |(_, label)| label)
+}
+
+/// Reads the reports waiting in a spool; a missing spool has none
+pub fn read_spool<P: AsRef<Path>>(spool_path: P) -> Result<Vec<TelemetryReport>> {
+    let spool_path = spool_path.as_ref();
+    let content = match fs::read_to_string(spool_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).with_context(|| format!("Malformed telemetry report in {}", spool_path.display())))
+        .collect()
+}
+
+/// Posts the spooled reports to `endpoint` as JSON lines and empties the spool
+///
+/// Uses the system `curl`, which handles HTTPS and proxies. Returns the
+/// number of reports sent.
+pub fn send_spool<P: AsRef<Path>>(spool_path: P, endpoint: &str) -> Result<usize> {
+    let spool_path = spool_path.as_ref();
+    let pending = read_spool(spool_path)?.len();
+    if pending == 0 {
+        return Ok(0);
+    }
+
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--max-time", "30", "-X", "POST"])
+        .args(["-H", "Content-Type: application/x-ndjson", "--data-binary"])
+        .arg(format!("@{}", spool_path.display()))
+        .arg(endpoint)
+        .output()
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        return Err(anyhow!("Telemetry upload failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    fs::remove_file(spool_path)?;
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_index::CodeIndex;
+
+    #[test]
+    fn test_size_buckets() {
+        assert_eq!(size_bucket(0), "0");
+        assert_eq!(size_bucket(42), "1-99");
+        assert_eq!(size_bucket(10_000), "10k-100k");
+        assert_eq!(size_bucket(5_000_000), "1M+");
+    }
+
+    #[test]
+    fn test_counts_are_spooled_only_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = dir.path().join("telemetry").join("spool.jsonl");
+
+        let mut disabled = Telemetry::disabled();
+        disabled.record_tool_call("search_symbols", None);
+        assert!(!disabled.flush(None).unwrap());
+
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let mut index = CodeIndex::new("secret-project".to_string(), "/src/secret".to_string());
+        index.total_symbols = 12_345;
+        repository.create_code_index(index).unwrap();
+
+        let mut telemetry = Telemetry::new(&spool);
+        assert!(!telemetry.flush(Some(&repository)).unwrap());
+        telemetry.record_tool_call("search_symbols", None);
+        telemetry.record_tool_call("search_symbols", Some("validation"));
+        telemetry.record_tool_call("find_references", None);
+        assert!(telemetry.flush(Some(&repository)).unwrap());
+        telemetry.record_tool_call("list_indices", None);
+        assert!(telemetry.flush(None).unwrap());
+
+        let reports = read_spool(&spool).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].tool_calls["search_symbols"], 2);
+        assert_eq!(reports[0].error_kinds["validation"], 1);
+        assert_eq!(reports[0].index_symbol_buckets["10k-100k"], 1);
+        assert_eq!(reports[1].tool_calls.len(), 1);
+
+        // Nothing identifying reaches the spool
+        let raw = std::fs::read_to_string(&spool).unwrap();
+        assert!(!raw.contains("secret"));
+        assert!(read_spool(dir.path().join("missing.jsonl")).unwrap().is_empty());
+    }
+}
@@ -1,81 +1,5630 @@
 use anyhow::{anyhow, Result};
 use serde_json::{json, Value};
-use tracing::{info, instrument};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::lib::cpp_indexer::adaptive_depth::{depth_of_file, detail_policy, needs_replan, DepthPlanner};
+use crate::lib::cpp_indexer::clang_parser::HeaderCache;
+use crate::lib::cpp_indexer::detail_tiers::DetailPolicy;
+use crate::lib::cpp_indexer::conditionals::{assign_configurations, ConfigurationMatrix, MacroConfiguration};
+use crate::lib::cpp_indexer::hot_path::{find_body_hazards, HazardCategory, HotPathRules};
+use crate::lib::cpp_indexer::git::{changed_files, current_branch, GitFs};
+use crate::lib::cpp_indexer::pipeline::{IndexingPipeline, PipelineConfig, PipelineReport};
+use crate::lib::cpp_indexer::vfs::{is_source_file, read_source};
+use crate::lib::cpp_indexer::vendored::{skip_vendored_copies, VendoredDedup};
+use crate::lib::cpp_indexer::index_settings::IndexSettings;
+use crate::lib::cpp_indexer::walk_filter::{default_rules, WalkFilter};
+use crate::lib::storage::batch_writer::PriorityGate;
+use crate::lib::storage::connection::{ConnectionPool, DatabaseManager};
+use crate::lib::storage::embeddings::{EmbeddingBackend, EmbeddingStore};
+use crate::lib::storage::error::StorageError;
+use crate::lib::storage::file_moves::FileMove;
+use crate::lib::storage::fuzzy::{self, MAX_FUZZY_CANDIDATES};
+use crate::lib::storage::call_graph::{CallDirection, CallEdge, CallEdgeKind, CallGraph, CallGraphOptions, CallGraphWalker, DEFAULT_MAX_DEPTH};
+use crate::lib::storage::include_graph::{self, IncludeDirection, IncludeGraph, IncludeGraphOptions, IncludeGraphWalker};
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::code_index::{CodeIndex, IndexState};
+use crate::lib::storage::models::directory_depth::{directory_of, IndexDepth};
+use crate::lib::storage::models::file_metadata::FileDetail;
+use crate::lib::storage::models::index_error::{IndexError, IndexErrorKind};
+use crate::lib::storage::models::index_insights::{RankedFile, RankedSymbol};
+use crate::lib::storage::models::index_revision::IndexRevision;
+use crate::lib::storage::models::index_tag::IndexTag;
+use crate::lib::storage::models::saved_query::SavedQuery;
+use crate::lib::storage::models::symbol_annotation::{AnnotationKind, SymbolAnnotation};
+use crate::lib::storage::models::symbol_relationships::SymbolRelationship;
+use crate::lib::storage::models::symbol_popularity::SymbolPopularity;
+use crate::lib::storage::query::{
+    escape_like, CodeElementQuery, ElementColumn, Filter, MatchMode, QualifiedName, QueryPage, RelationshipColumn, SortDirection, SymbolRelationshipQuery,
+    TextColumn, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
+};
+use crate::lib::storage::ordering::path_key;
+use crate::lib::storage::repository::{IndexCoverage, Repository};
+use crate::lib::storage::search_explain::{explain_hit, explain_similarity, explain_symbol, query_terms};
+use crate::lib::storage::type_hierarchy::{self, HierarchyDirection, HierarchyOptions, TypeHierarchy, TypeHierarchyWalker, CLASS_TYPES};
+use crate::lib::storage::watch::WatchEvaluator;
+use super::context_pack::{ContextPackBuilder, DEFAULT_MAX_ITEMS, MAX_ITEMS};
+use super::cursor::CursorStore;
+use super::query_cache::{IndexVersion, QueryCache};
+use super::freshness::{
+    check_file, content_hash, detect_moves, extract_file, files_to_keep, missing_files, plan_path_update, store_moved, store_reindexed, Freshness,
+    FreshnessReport, PathScope, StaleCheck, DEFAULT_STALE_REINDEX_MAX_KB,
+};
+use super::references::{ReferenceKind, ReferenceSummary, SourceLines};
+use super::resolve::{identifier_at, pair_declarations, Resolution};
+use super::review::{enclosing_element, parse_unified_diff, test_references, CodeOwners};
+use super::risk::{RiskReport, HIGH_RISK_SCORE};
+use super::overlay::{extract_document_symbols, ContentChange, DocumentOverlay};
+use super::outline::{build_outline, OutlineNode};
+use super::grep::{enclosing_symbol, GrepPattern};
+use super::progress::{Cancelled, ToolProgress};
+use super::registry::RepositoryRegistry;
+use super::diagnostics::{self, CompilerDiagnostic, UnresolvedKind, UnresolvedSymbol};
 
 /// Tool Handlers for MCP Protocol
 /// 
-/// Implements handlers for all 8 MCP tools defined in the contract specification.
+/// Implements handlers for the MCP tools defined in the contract specification.
 /// Each handler validates input parameters, performs the requested operation,
 /// and returns structured results according to the response schemas.
 #[derive(Debug, Clone)]
 pub struct ToolHandlers {
-    // TODO: Add actual dependencies when available
+    /// Index storage, shared with the rest of the server
+    repository: Option<Arc<Mutex<Repository>>>,
+    /// Indices served from databases of their own, opened on first use
+    registry: Arc<Mutex<RepositoryRegistry>>,
+    /// Why tools that modify storage are rejected (None = they aren't)
+    read_only: Option<ReadOnlyMode>,
+    /// Tells concurrent bulk writers to pause while a tool call runs
+    priority_gate: Option<PriorityGate>,
+    /// Open find_references cursors, shared by all clones of the handlers
+    reference_cursors: Arc<Mutex<CursorStore<ReferenceCursor>>>,
+    /// Opens the connections behind query snapshots (None = snapshots unavailable)
+    database: Option<Arc<DatabaseManager>>,
+    /// Reader connections queries on the default database run on (None = they share the repository)
+    pool: Option<ConnectionPool>,
+    /// Open query snapshots, each a repository pinned to one database state
+    snapshots: Arc<Mutex<CursorStore<Arc<Mutex<Repository>>>>>,
+    /// Snapshot the running call reads the default database from (shared read-only servers)
+    call_snapshot: Option<Arc<Mutex<Repository>>>,
+    /// Whether calls naming a file_path check it against the file on disk
+    stale_check: StaleCheck,
+    /// Largest stale file, in bytes, re-indexed inline; larger ones are only flagged
+    stale_reindex_limit: u64,
+    /// Detail stored for the symbols of files re-indexed inline
+    detail_policy: DetailPolicy,
+    /// Learns indexing depth per directory from queried paths (None = off)
+    adaptive_depth: Option<DepthPlanner>,
+    /// Binary whose `parse-worker` processes parse files (None = parse on threads of this process)
+    parse_worker: Option<PathBuf>,
+    /// Detects vendored copies index_codebase skips (None = index every copy)
+    vendored_dedup: Option<VendoredDedup>,
+    /// Unsaved text of documents open in an editor, shared by all clones of the handlers
+    documents: Arc<Mutex<DocumentOverlay>>,
+    /// Answers of hot read tools, shared by all clones of the handlers (None = off)
+    query_cache: Option<Arc<Mutex<QueryCache>>>,
+    /// Symbol vectors semantic_search ranks by (None = semantic search disabled)
+    embeddings: Option<Arc<Mutex<EmbeddingStore>>>,
+    /// Backend the stored vectors came from, embedding semantic_search queries
+    embedder: Option<Arc<dyn EmbeddingBackend>>,
+}
+
+/// Why a server rejects the tools that modify storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadOnlyMode {
+    /// The database failed its integrity check and is served as it is
+    Degraded,
+    /// The server was started with `--read-only` and shares the database with a writer elsewhere
+    Shared,
+}
+
+/// How long an unused query snapshot stays open
+///
+/// Short on purpose: an open snapshot keeps the WAL from being checkpointed.
+pub const DEFAULT_SNAPSHOT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Query snapshots open at once; the least recently used is closed first
+pub const MAX_QUERY_SNAPSHOTS: usize = 8;
+
+/// References returned per find_references page unless the caller asks otherwise
+pub const DEFAULT_REFERENCE_PAGE_SIZE: u64 = 500;
+
+/// Largest find_references page a caller may request
+pub const MAX_REFERENCE_PAGE_SIZE: u64 = 5000;
+
+/// Most source lines find_references returns either side of a reference
+pub const MAX_REFERENCE_CONTEXT_LINES: u64 = 10;
+
+/// Symbols find_symbols_in_section returns unless the caller asks otherwise
+pub const DEFAULT_SECTION_SYMBOL_LIMIT: u64 = 500;
+
+/// Hits search_text returns unless the caller asks otherwise
+pub const DEFAULT_TEXT_SEARCH_LIMIT: u64 = 50;
+
+/// Matching lines grep_code returns unless the caller asks otherwise
+pub const DEFAULT_GREP_RESULTS: u64 = 200;
+
+/// Most matching lines one grep_code call returns
+pub const MAX_GREP_RESULTS: u64 = 5000;
+
+/// Symbols semantic_search returns unless the caller asks otherwise
+pub const DEFAULT_SEMANTIC_SEARCH_LIMIT: u64 = 20;
+
+/// Most symbols one semantic_search call returns
+pub const MAX_SEMANTIC_SEARCH_LIMIT: u64 = 500;
+
+/// Symbols search_symbols returns unless the caller asks otherwise
+pub const DEFAULT_SYMBOL_SEARCH_LIMIT: u64 = 100;
+
+/// Most symbols one search_symbols call returns
+pub const MAX_SYMBOL_SEARCH_LIMIT: u64 = 1000;
+
+/// Divergent files and symbols listed per conditional_compilation_matrix report by default
+pub const DEFAULT_MATRIX_ENTRIES: u64 = 200;
+
+/// Symbols run_saved_query returns unless the caller asks otherwise
+pub const DEFAULT_SAVED_QUERY_LIMIT: u64 = 500;
+
+/// Author recorded on annotations when the caller doesn't name one
+pub const DEFAULT_ANNOTATION_AUTHOR: &str = "assistant";
+
+/// Symbol tag marking hot-path roots when analyze_hot_paths is given no tag or roots
+pub const DEFAULT_HOT_PATH_TAG: &str = "realtime";
+
+/// Call levels analyze_hot_paths follows from its roots by default
+pub const DEFAULT_HOT_PATH_DEPTH: u32 = 10;
+
+/// Functions analyze_hot_paths inspects at most
+pub const MAX_HOT_PATH_FUNCTIONS: usize = 5000;
+
+/// Same-named elements resolve_symbol compares when pairing declarations
+pub const MAX_RESOLVE_CANDIDATES: u64 = 1000;
+
+/// Deepest call graph get_call_graph builds
+pub const MAX_CALL_GRAPH_DEPTH: u32 = 20;
+
+/// Symbols get_call_graph returns per direction at most
+pub const MAX_CALL_GRAPH_NODES: usize = 2000;
+
+/// Deepest inheritance chain get_type_hierarchy follows
+pub const MAX_HIERARCHY_DEPTH: u32 = 64;
+
+/// Classes get_type_hierarchy returns per direction at most
+pub const MAX_HIERARCHY_NODES: usize = 2000;
+
+/// Deepest include chain get_include_graph follows
+pub const MAX_INCLUDE_DEPTH: u32 = 64;
+
+/// Files get_include_graph returns per direction at most
+pub const MAX_INCLUDE_GRAPH_NODES: usize = 5000;
+
+/// Entries of each ranking get_index_insights returns unless the caller asks otherwise
+pub const DEFAULT_INSIGHT_ENTRIES: u64 = 10;
+
+/// Most entries of each ranking get_index_insights returns
+pub const MAX_INSIGHT_ENTRIES: u64 = 500;
+
+/// Version, text and symbols of an open document
+type OpenDocument = (i64, String, Option<Vec<CodeElement>>);
+
+/// Position of a paginated find_references query
+#[derive(Debug, Clone)]
+struct ReferenceCursor {
+    index_id: Uuid,
+    /// Index name, which also finds the database of a registered index
+    index_name: String,
+    /// Query snapshot the first page was read from, if any
+    snapshot_id: Option<String>,
+    /// Index base path, for reading source lines to classify references
+    base_path: String,
+    /// Elements whose references are listed
+    targets: Vec<i64>,
+    /// Last relationship id already returned
+    after_id: i64,
+    page_size: u64,
+    total_count: u64,
+    /// Source lines returned either side of each reference (0 = none)
+    context_lines: u32,
 }
 
 impl ToolHandlers {
     /// Create new tool handlers instance
     pub fn new() -> Result<Self> {
         Ok(Self {
-            // TODO: Initialize actual dependencies
+            repository: None,
+            registry: Arc::new(Mutex::new(RepositoryRegistry::new())),
+            read_only: None,
+            priority_gate: None,
+            reference_cursors: Arc::new(Mutex::new(CursorStore::default())),
+            database: None,
+            pool: None,
+            snapshots: Arc::new(Mutex::new(CursorStore::new(DEFAULT_SNAPSHOT_TTL, MAX_QUERY_SNAPSHOTS))),
+            call_snapshot: None,
+            stale_check: StaleCheck::Off,
+            stale_reindex_limit: DEFAULT_STALE_REINDEX_MAX_KB * 1024,
+            detail_policy: DetailPolicy::default(),
+            adaptive_depth: None,
+            parse_worker: None,
+            vendored_dedup: None,
+            documents: Arc::new(Mutex::new(DocumentOverlay::default())),
+            query_cache: Some(Arc::new(Mutex::new(QueryCache::default()))),
+            embeddings: None,
+            embedder: None,
         })
     }
 
+    /// Attach the repository used to answer index queries
+    pub fn with_repository(mut self, repository: Arc<Mutex<Repository>>) -> Self {
+        self.repository = Some(repository);
+        self
+    }
+
+    /// Serve the indices registered in `registry` from their own databases
+    pub fn with_registry(mut self, registry: Arc<Mutex<RepositoryRegistry>>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Reject tools that modify storage (degraded mode)
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only.then_some(ReadOnlyMode::Degraded);
+        self
+    }
+
+    /// Reject tools that modify storage, saying why
+    ///
+    /// With [`ReadOnlyMode::Shared`] and a database manager attached, each
+    /// call also reads the default database from a snapshot of its own, so
+    /// one answer never mixes states before and after a commit of the
+    /// process writing the database.
+    pub fn with_read_only_mode(mut self, mode: ReadOnlyMode) -> Self {
+        self.read_only = Some(mode);
+        self
+    }
+
+    /// Allow query snapshots on the database `manager` opens
+    pub fn with_database_manager(mut self, manager: Arc<DatabaseManager>) -> Self {
+        self.database = Some(manager);
+        self
+    }
+
+    /// Run queries on the default database on `pool`'s readers, so they don't wait behind writes
+    ///
+    /// Writes still go through the attached repository, which should hold
+    /// the pool's writer.
+    pub fn with_connection_pool(mut self, pool: ConnectionPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Check files named by tool calls against the index, optionally re-indexing stale ones
+    pub fn with_stale_check(mut self, stale_check: StaleCheck) -> Self {
+        self.stale_check = stale_check;
+        self
+    }
+
+    /// Only flag stale files over `max_bytes` instead of re-indexing them inline
+    pub fn with_stale_reindex_limit(mut self, max_bytes: u64) -> Self {
+        self.stale_reindex_limit = max_bytes;
+        self
+    }
+
+    /// Store files re-indexed inline with the index's detail tiers
+    pub fn with_detail_policy(mut self, policy: DetailPolicy) -> Self {
+        self.detail_policy = policy;
+        self
+    }
+
+    /// Count queried directories and plan indexing depth from them
+    pub fn with_adaptive_depth(mut self, planner: DepthPlanner) -> Self {
+        self.adaptive_depth = Some(planner);
+        self
+    }
+
+    /// Parse indexed files in `parse-worker` processes of `program`, so a crashing or hanging parse only fails its file
+    pub fn with_parse_worker(mut self, program: PathBuf) -> Self {
+        self.parse_worker = Some(program);
+        self
+    }
+
+    /// Skip byte-identical copies of vendored trees in index_codebase, indexing one copy each
+    pub fn with_vendored_dedup(mut self, dedup: VendoredDedup) -> Self {
+        self.vendored_dedup = Some(dedup);
+        self
+    }
+
+    /// Keep up to `entries` answers of search_symbols, get_file_symbols and get_symbol_details (0 = no cache)
+    pub fn with_query_cache(mut self, entries: usize) -> Self {
+        self.query_cache = (entries > 0).then(|| Arc::new(Mutex::new(QueryCache::new(entries))));
+        self
+    }
+
+    /// Answer semantic_search from `store`, embedding queries with the backend its vectors came from
+    pub fn with_embeddings(mut self, store: EmbeddingStore, backend: Box<dyn EmbeddingBackend>) -> Self {
+        self.embeddings = Some(Arc::new(Mutex::new(store)));
+        self.embedder = Some(Arc::from(backend));
+        self
+    }
+
+    /// Give tool calls priority over bulk writes sharing this gate
+    pub fn with_priority_gate(mut self, gate: PriorityGate) -> Self {
+        self.priority_gate = Some(gate);
+        self
+    }
+
     /// Handle MCP tool call
-    #[instrument(skip(self, arguments))]
     pub async fn handle_tool_call(&mut self, tool_name: &str, arguments: Value) -> Result<Value> {
+        self.handle_tool_call_with_progress(tool_name, arguments, &mut ToolProgress::default()).await
+    }
+
+    /// Handle MCP tool call, reporting the progress of long operations to `progress`
+    ///
+    /// Operations that support it stop early once `progress` is cancelled.
+    #[instrument(skip(self, arguments, progress))]
+    pub async fn handle_tool_call_with_progress(&mut self, tool_name: &str, arguments: Value, progress: &mut ToolProgress) -> Result<Value> {
         info!("Handling tool call: {} with arguments: {}", tool_name, arguments);
+        // index_codebase is one of the bulk writers the gate holds back
+        let _priority = self.priority_gate.as_ref().filter(|_| tool_name != "index_codebase").map(PriorityGate::enter);
+        self.call_snapshot = self.open_call_snapshot(&arguments)?;
+        let outcome = self.dispatch_tool_call(tool_name, arguments, progress).await;
+        // Dropping the snapshot ends its read transaction
+        self.call_snapshot = None;
+        outcome
+    }
+
+    /// Runs a tool call and attaches coverage and freshness to its result
+    async fn dispatch_tool_call(&mut self, tool_name: &str, arguments: Value, progress: &mut ToolProgress) -> Result<Value> {
+        let freshness = self.verify_freshness(&arguments).await?;
         
-        // For now, return placeholder responses for all tools
-        // TODO: Implement actual tool functionality when dependencies are available
-        match tool_name {
-            "index_codebase" => Ok(json!({
-                "success": false,
-                "error": "Not yet implemented",
-                "tool": tool_name
-            })),
-            "search_symbols" => Ok(json!({
-                "symbols": [],
-                "total_count": 0,
-                "error": "Not yet implemented"
-            })),
-            "get_symbol_details" => Ok(json!({
-                "error": "Not yet implemented"
-            })),
-            "find_references" => Ok(json!({
-                "references": [],
-                "error": "Not yet implemented"
-            })),
-            "list_indices" => Ok(json!({
-                "indices": [],
-                "count": 0,
-                "error": "Not yet implemented"
-            })),
-            "delete_index" => Ok(json!({
-                "success": false,
-                "error": "Not yet implemented"
-            })),
-            "get_file_symbols" => Ok(json!({
-                "symbols": [],
-                "total_symbols": 0,
-                "error": "Not yet implemented"
-            })),
-            "update_file" => Ok(json!({
-                "success": false,
-                "error": "Not yet implemented"
-            })),
+        let mut result = match tool_name {
+            "index_codebase" => self.index_codebase(&arguments, progress).await,
+            "search_symbols" => self.cached_query(tool_name, &arguments, Self::search_symbols),
+            "get_symbol_details" => self.cached_query(tool_name, &arguments, Self::get_symbol_details),
+            "find_references" => self.find_references(&arguments),
+            "list_indices" => self.list_indices(&arguments),
+            "delete_index" => self.delete_index(&arguments),
+            "get_file_symbols" => self.cached_query(tool_name, &arguments, Self::get_file_symbols),
+            "get_file_outline" => self.get_file_outline(&arguments),
+            "update_file" => self.update_file(&arguments).await,
+            "update_path" => self.update_path(&arguments).await,
+            "switch_index_revision" => self.switch_index_revision(&arguments),
+            "explain_linker_error" => self.explain_linker_error(&arguments),
+            "get_compiler_error_context" => self.get_compiler_error_context(&arguments),
+            "annotate_diff" => self.annotate_diff(&arguments),
+            "assess_diff_risk" => self.assess_diff_risk(&arguments),
+            "set_index_tags" => self.set_index_tags(&arguments),
+            "tag_symbol" => self.tag_symbol(&arguments),
+            "analyze_hot_paths" => self.analyze_hot_paths(&arguments),
+            "find_symbols_in_section" => self.find_symbols_in_section(&arguments),
+            "conditional_compilation_matrix" => self.conditional_compilation_matrix(&arguments),
+            "annotate_symbol" => self.annotate_symbol(&arguments),
+            "list_annotations" => self.list_annotations(&arguments),
+            "delete_annotation" => self.delete_annotation(&arguments),
+            "save_query" => self.save_query(&arguments),
+            "list_saved_queries" => self.list_saved_queries(&arguments),
+            "run_saved_query" => self.run_saved_query(&arguments),
+            "delete_saved_query" => self.delete_saved_query(&arguments),
+            "watch_query" => self.watch_query(&arguments),
+            "check_watches" => self.check_watches(&arguments),
+            "get_call_graph" => self.get_call_graph(&arguments),
+            "get_type_hierarchy" => self.get_type_hierarchy(&arguments),
+            "get_include_graph" => self.get_include_graph(&arguments),
+            "search_text" => self.search_text(&arguments),
+            "grep_code" => self.grep_code(&arguments),
+            "semantic_search" => self.semantic_search(&arguments),
+            "resolve_symbol" => self.resolve_symbol(&arguments),
+            "did_open_document" => self.did_open_document(&arguments),
+            "did_change_document" => self.did_change_document(&arguments),
+            "did_close_document" => self.did_close_document(&arguments),
+            "get_document_symbols" => self.get_document_symbols(&arguments),
+            "promote_file_detail" => self.promote_file_detail(&arguments).await,
+            "get_index_depths" => self.get_index_depths(&arguments),
+            "list_index_errors" => self.list_index_errors(&arguments),
+            "get_index_insights" => self.get_index_insights(&arguments),
+            "begin_query_snapshot" => self.begin_query_snapshot(),
+            "end_query_snapshot" => self.end_query_snapshot(&arguments),
             _ => Err(anyhow!("Unknown tool: {}", tool_name)),
+        }?;
+
+        self.attach_coverage(&arguments, &mut result)?;
+        if let Err(e) = self.record_query_hits(&arguments, &result) {
+            warn!("Failed to record query hits: {}", e);
         }
+        if let (Some(report), Some(fields)) = (freshness, result.as_object_mut()) {
+            fields.insert("freshness".to_string(), serde_json::to_value(report)?);
+        }
+        Ok(result)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Check the file a call names against its indexed hash
+    ///
+    /// Closes the gap between disk and index when the watcher isn't running.
+    /// With [`StaleCheck::Reindex`], a stale file is re-indexed before the
+    /// tool runs so the answer reflects the file on disk; without write
+    /// access (read-only storage, query snapshots) or past the re-index
+    /// limit it is only flagged.
+    async fn verify_freshness(&self, arguments: &Value) -> Result<Option<FreshnessReport>> {
+        let (index_name, file_path) = match (arguments["index_name"].as_str(), arguments["file_path"].as_str()) {
+            (Some(index_name), Some(file_path)) if self.stale_check != StaleCheck::Off && self.has_storage() => (index_name, file_path),
+            _ => return Ok(None),
+        };
 
-    #[tokio::test]
-    async fn test_tool_handlers_creation() {
-        let _handlers = ToolHandlers::new().unwrap();
-        // Basic smoke test - handlers should be created successfully
-        assert!(true);
+        let repository = self.repository_for(arguments)?;
+        let (index, mut report) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let index = match repository.get_code_index_by_name(index_name)? {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+            let report = check_file(&repository, &index, &stored_path(&index.base_path, file_path))?;
+            (index, report)
+        };
+        let policy = self.reindex_policy(&repository, &index, &report.file_path)?;
+
+        let can_write = self.read_only.is_none() && arguments["snapshot_id"].is_null();
+        let within_limit = std::fs::metadata(Path::new(&index.base_path).join(&report.file_path))
+            .ok()
+            .map_or(true, |metadata| metadata.len() <= self.stale_reindex_limit);
+        if self.stale_check == StaleCheck::Reindex && report.status == Freshness::Stale && can_write && within_limit {
+            // Parse without holding the lock; a failed re-index leaves the file flagged stale
+            let reparsed = match self.index_settings(&repository, &index) {
+                Ok(settings) => extract_file(&index, &settings, &report.file_path, &policy).await,
+                Err(e) => Err(e),
+            };
+            match reparsed {
+                Ok(reparsed) => {
+                    let repository = self.repository_of(index_name)?;
+                    let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+                    store_reindexed(&repository, &index, &mut report, reparsed)?;
+                }
+                Err(e) => warn!("Inline re-index failed: {}", e),
+            }
+        }
+        Ok(Some(report))
+    }
+
+    /// Answer a read-only query from the cache while its index is unchanged
+    ///
+    /// The index version is read first, so an answer computed while a write
+    /// lands is stored under the older version and never served. Calls on a
+    /// query snapshot bypass the cache.
+    fn cached_query(&self, tool_name: &str, arguments: &Value, run: impl FnOnce(&Self, &Value) -> Result<Value>) -> Result<Value> {
+        let (Some(cache), Some(index_name)) = (&self.query_cache, arguments["index_name"].as_str()) else {
+            return run(self, arguments);
+        };
+        if !arguments["snapshot_id"].is_null() {
+            return run(self, arguments);
+        }
+        let version = {
+            let repository = self.repository_for(arguments)?;
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            repository.get_code_index_by_name(index_name)?.as_ref().map(IndexVersion::of)
+        };
+        // The query takes the repository lock itself, so it runs after the guard above is gone
+        let Some(version) = version else {
+            return run(self, arguments);
+        };
+
+        let key = QueryCache::key(tool_name, arguments);
+        if let Some(answer) = cache.lock().map_err(|_| anyhow!("Query cache lock poisoned"))?.get(&key, &version) {
+            return Ok(answer);
+        }
+        let answer = run(self, arguments)?;
+        cache.lock().map_err(|_| anyhow!("Query cache lock poisoned"))?.insert(key, version, answer.clone());
+        Ok(answer)
+    }
+
+    /// Detail a file re-indexed inline is stored with: its directory's planned depth, if any
+    fn reindex_policy(&self, repository: &Arc<Mutex<Repository>>, index: &CodeIndex, file_path: &str) -> Result<DetailPolicy> {
+        if self.adaptive_depth.is_none() {
+            return Ok(self.detail_policy);
+        }
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let depths = repository.list_directory_depths(&index.id)?;
+        Ok(depth_of_file(&depths, file_path).map_or(self.detail_policy, detail_policy))
+    }
+
+    /// How the files of an index are parsed, as it was created
+    fn index_settings(&self, repository: &Arc<Mutex<Repository>>, index: &CodeIndex) -> Result<IndexSettings> {
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        Ok(IndexSettings::load(&repository, index)?)
+    }
+
+    /// Repository an indexing run writes through
+    ///
+    /// Runs on the default database get a connection of their own, so the
+    /// shared repository stays free while files are parsed; an in-memory or
+    /// per-index database has the one connection, locked for the run.
+    fn indexing_repository(&self, index_name: &str, shared: &Arc<Mutex<Repository>>) -> Result<Arc<Mutex<Repository>>> {
+        match &self.database {
+            Some(manager) if !manager.config().is_in_memory() && self.call_snapshot.is_none() && self.registered(index_name)?.is_none() => {
+                let connection = manager.connect()?;
+                let repository = shared.lock().map_err(|_| anyhow!("Repository lock poisoned"))?.on_connection(connection);
+                Ok(Arc::new(Mutex::new(repository)))
+            }
+            _ => Ok(Arc::clone(shared)),
+        }
+    }
+
+    /// Follows indexed files moved on disk to their new paths, keeping their symbols' ids and notes
+    ///
+    /// Candidates are parsed without holding the repository lock. Returns
+    /// the moves stored.
+    async fn follow_moves(&self, repository: &Arc<Mutex<Repository>>, index: &CodeIndex) -> Result<Vec<FileMove>> {
+        let (missing, indexed, settings) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let (missing, indexed) = missing_files(&repository, index)?;
+            if missing.is_empty() {
+                return Ok(Vec::new());
+            }
+            (missing, indexed, IndexSettings::load(&repository, index)?)
+        };
+        let detected = detect_moves(index, &settings, &missing, &indexed, &self.detail_policy).await?;
+
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let mut moves = Vec::new();
+        for detected in detected {
+            let file_move = detected.file_move.clone();
+            store_moved(&repository, index, detected)?;
+            info!("Followed {} to {} in '{}'", file_move.from, file_move.to, index.name);
+            moves.push(file_move);
+        }
+        Ok(moves)
+    }
+
+    /// Count the directories a call touched towards adaptive indexing depth
+    ///
+    /// Directories come from the call's `file_path` and the file paths in its
+    /// result. Depths are re-planned at most once a day as hits come in.
+    /// Calls on query snapshots or read-only storage are not counted.
+    fn record_query_hits(&self, arguments: &Value, result: &Value) -> Result<()> {
+        let (planner, index_name) = match (self.adaptive_depth, arguments["index_name"].as_str()) {
+            (Some(planner), Some(index_name)) if self.read_only.is_none() && arguments["snapshot_id"].is_null() && self.has_storage() => {
+                (planner, index_name)
+            }
+            _ => return Ok(()),
+        };
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = match repository.get_code_index_by_name(index_name)? {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let mut directories = BTreeSet::new();
+        if let Some(file_path) = arguments["file_path"].as_str() {
+            directories.insert(directory_of(&stored_path(&index.base_path, file_path)).to_string());
+        }
+        collect_directories(result, &mut directories);
+        if directories.is_empty() {
+            return Ok(());
+        }
+
+        let now = chrono::Utc::now();
+        repository.record_directory_hits(&index.id, &directories, now.date_naive())?;
+        let last_planned = repository.list_directory_depths(&index.id)?.iter().map(|depth| depth.assigned_at).max();
+        if needs_replan(last_planned, now) {
+            planner.replan(&repository, &index.id, now)?;
+        }
+        Ok(())
+    }
+
+    /// Qualify an answer from an index that is still being built or updated
+    ///
+    /// Partial indices are queried rather than refused; the result gains a
+    /// `coverage` object saying how much of the codebase it reflects and,
+    /// for calls naming a `file_path`, whether that file is indexed yet.
+    fn attach_coverage(&self, arguments: &Value, result: &mut Value) -> Result<()> {
+        let index_name = match arguments["index_name"].as_str() {
+            Some(index_name) if result.is_object() && self.has_storage() => index_name,
+            _ => return Ok(()),
+        };
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = match repository.get_code_index_by_name(index_name)? {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let coverage = repository.get_index_coverage(&index.id)?;
+        if !coverage.is_partial() {
+            return Ok(());
+        }
+
+        let mut entry = coverage_entry(&coverage);
+        if let Some(file_path) = arguments["file_path"].as_str() {
+            entry["file_indexed"] = json!(repository.is_file_indexed(&index.id, &stored_path(&index.base_path, file_path))?);
+        }
+        result["coverage"] = entry;
+        Ok(())
+    }
+
+    /// List indices, optionally restricted to those carrying all given tags
+    ///
+    /// Covers every database the server serves, so a session spanning
+    /// several repositories sees all of their indices.
+    fn list_indices(&self, arguments: &Value) -> Result<Value> {
+        let include_stats = arguments["include_stats"].as_bool().unwrap_or(true);
+        let tag_filter = string_map(&arguments["tags"], "tags")?;
+        let page = page_arguments(arguments, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT);
+
+        let indices = page.slice(self.listed_indices(arguments, &tag_filter)?).map_err(|e| anyhow!(e))?;
+
+        let mut entries = Vec::with_capacity(indices.items.len());
+        for (index, repository) in &indices.items {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let mut entry = json!({
+                "id": index.id.to_string(),
+                "name": index.name,
+                "base_path": index.base_path,
+                "created_at": index.created_at.to_rfc3339(),
+                "updated_at": index.updated_at.to_rfc3339(),
+                "index_version": index.index_version.to_string(),
+                "tags": repository.get_index_tags(&index.id)?
+            });
+            let coverage = repository.get_index_coverage(&index.id)?;
+            entry["state"] = json!(coverage.state.as_str());
+            if coverage.is_partial() {
+                entry["coverage"] = coverage_entry(&coverage);
+            }
+            if include_stats {
+                entry["total_files"] = json!(index.total_files);
+                entry["total_symbols"] = json!(index.total_symbols);
+            }
+            entries.push(entry);
+        }
+
+        Ok(json!({
+            "indices": entries,
+            "total_count": indices.total_count,
+            "next_cursor": indices.next_cursor
+        }))
+    }
+
+    /// Create an index of the source files under `base_path`, or bring an existing one up to date
+    ///
+    /// With `incremental`, an existing index only re-parses files whose
+    /// content changed or that are new, and drops files deleted from disk;
+    /// without it, an index of that name already existing is an error.
+    /// Files are parsed on the indexing pipeline's workers, off the async
+    /// runtime, writing through a connection of their own when the database
+    /// allows one, so the shared repository isn't locked for the run. A
+    /// cancelled run stops between files.
+    ///
+    /// Which files are walked is saved with the index when it is created;
+    /// `file_patterns`, `exclude_patterns` and `respect_ignore_files` passed
+    /// to a later run apply to that run only: files they leave out stay
+    /// indexed, and only files deleted from disk are dropped. The project
+    /// file and preset narrow every walk.
+    ///
+    /// An incremental run first follows files moved on disk, so they keep
+    /// their symbols' ids and notes instead of being dropped and added again.
+    ///
+    /// Progress is reported per file parsed. A cancelled run keeps the files
+    /// parsed so far; running it again with `incremental` finishes the rest.
+    async fn index_codebase(&self, arguments: &Value, progress: &mut ToolProgress) -> Result<Value> {
+        let name = required_str(arguments, "name")?;
+        let base_path = required_str(arguments, "base_path")?;
+        let incremental = arguments["incremental"].as_bool().unwrap_or(false);
+        let file_patterns = string_list(&arguments["file_patterns"], "file_patterns")?;
+        let exclude_patterns = string_list(&arguments["exclude_patterns"], "exclude_patterns")?;
+        let respect_ignore_files = match &arguments["respect_ignore_files"] {
+            Value::Null => None,
+            value => Some(value.as_bool().ok_or_else(|| anyhow!("respect_ignore_files must be a boolean"))?),
+        };
+        self.ensure_writable()?;
+
+        let root = std::fs::canonicalize(base_path).map_err(|e| anyhow!("Cannot index {}: {}", base_path, e))?;
+        if !root.is_dir() {
+            return Err(anyhow!("Not a directory: {}", base_path));
+        }
+
+        let repository = self.repository_of(name)?;
+        let existing = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?.get_code_index_by_name(name)?;
+        let moved = match existing {
+            Some(index) if incremental => self.follow_moves(&repository, &index).await?,
+            _ => Vec::new(),
+        };
+        let (index, rules, settings, files, removed, previous_state) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let existing = repository.get_code_index_by_name(name)?;
+            if existing.is_some() && !incremental {
+                return Err(anyhow!("Index '{}' already exists; pass incremental to update it", name));
+            }
+
+            // Arguments override the index's saved rules, which override the defaults
+            let saved = match &existing {
+                Some(index) => repository.get_walk_rules(&index.id)?,
+                None => None,
+            };
+            let mut rules = saved.unwrap_or_else(|| default_rules(Uuid::nil()));
+            if !arguments["file_patterns"].is_null() {
+                rules.include_patterns = file_patterns;
+            }
+            if !arguments["exclude_patterns"].is_null() {
+                rules.exclude_patterns = exclude_patterns;
+            }
+            if let Some(respect_ignore_files) = respect_ignore_files {
+                rules.respect_ignore_files = respect_ignore_files;
+            }
+            rules.validate().map_err(|e| anyhow!(e))?;
+            let (index, created) = match existing {
+                Some(index) => (index, false),
+                None => {
+                    let index = repository.create_code_index(CodeIndex::new(name.to_string(), root.to_string_lossy().to_string()))?;
+                    rules.index_id = index.id;
+                    repository.save_walk_rules(&rules)?;
+                    (index, true)
+                }
+            };
+            // This run's rules narrow the walk; the project file and preset still apply
+            let settings = IndexSettings::load(&repository, &index)?;
+            let mut files = settings.selection.clone().with_walk_filter(WalkFilter::from_rules(&rules)).source_files(&root)?;
+            if let Some(dedup) = &self.vendored_dedup {
+                files = skip_vendored_copies(&repository, &index, files, dedup)?.0;
+            }
+
+            if created {
+                (index, rules, settings, files, 0, None)
+            } else {
+                // Only files gone from disk are pruned, not the ones this run's patterns leave out
+                let (pruned, _) = repository.prune_missing_files(&index.id, &files_to_keep(&repository, &index, &files)?)?;
+                let mut unchanged = BTreeSet::new();
+                for metadata in repository.list_file_metadata(&index.id)? {
+                    if check_file(&repository, &index, &metadata.file_path)?.status == Freshness::Fresh {
+                        unchanged.insert(metadata.file_path);
+                    }
+                }
+                let previous_state = repository.get_code_index_state(&index.id)?;
+                repository.update_code_index_state(&index.id, IndexState::Updating)?;
+                let changed = files.into_iter().filter(|file| !unchanged.contains(file)).collect();
+                (index, rules, settings, changed, pruned.len(), previous_state)
+            }
+        };
+
+        // Parsing is blocking work, so it runs off the async runtime; a call cancelled meanwhile stops between files
+        let writer = self.indexing_repository(name, &repository)?;
+        let config = PipelineConfig::default().with_detail_policy(self.detail_policy).with_adaptive_depth(self.adaptive_depth.is_some());
+        let mut pipeline = IndexingPipeline::new(config);
+        if let Some(gate) = &self.priority_gate {
+            pipeline = pipeline.with_priority_gate(gate.clone());
+        }
+        let mut progress = progress.clone();
+        let run_name = name.to_string();
+        let parse_worker = self.parse_worker.clone();
+        let run = tokio::task::spawn_blocking(move || -> Result<(PipelineReport, CodeIndex)> {
+            let writer = writer.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let file_count = files.len();
+            let on_progress = |done, total| {
+                progress.report(done, total, "files parsed");
+                !progress.is_cancelled()
+            };
+            let headers = HeaderCache::new();
+            let new_worker = || settings.worker(&headers, parse_worker.as_deref());
+            let report = match pipeline.run_with_progress(&writer, &index, files, new_worker, on_progress) {
+                Ok(report) => report,
+                Err(e) => {
+                    writer.update_code_index_state(&index.id, IndexState::Failed)?;
+                    return Err(anyhow!("Indexing {} failed: {}", run_name, e));
+                }
+            };
+            if report.cancelled || progress.is_cancelled() {
+                // A complete index stays usable with part of its files refreshed; any other is left incomplete
+                let state = previous_state.filter(|state| *state == IndexState::Active).unwrap_or(IndexState::Failed);
+                writer.update_code_index_state(&index.id, state)?;
+                let parsed = report.files_indexed + report.failures.len();
+                return Err(Cancelled(format!(
+                    "Indexing {} cancelled after {} of {} files; run it with incremental to finish",
+                    run_name, parsed, file_count
+                ))
+                .into());
+            }
+            writer.update_code_index_state(&index.id, IndexState::Active)?;
+            let index = writer.get_code_index(&index.id)?.ok_or_else(|| anyhow!("Index not found: {}", run_name))?;
+            Ok((report, index))
+        });
+        let (report, index) = run.await.map_err(|e| anyhow!("Indexing {} stopped: {}", name, e))??;
+
+        let errors: Vec<Value> = report
+            .failures
+            .iter()
+            .map(|(file, error)| json!({"file_path": file, "line_number": error_line(error), "message": error}))
+            .collect();
+        Ok(json!({
+            "success": true,
+            "index_id": index.id.to_string(),
+            "name": index.name,
+            "base_path": index.base_path,
+            "incremental": incremental,
+            "files_processed": report.files_indexed + report.failures.len(),
+            "files_removed": removed,
+            "files_moved": moved.iter().map(move_entry).collect::<Vec<_>>(),
+            "symbols_found": report.symbols_stored,
+            "calls_stored": report.calls_stored,
+            "callbacks_stored": report.callbacks_stored,
+            "total_files": index.total_files,
+            "total_symbols": index.total_symbols,
+            "errors": errors,
+            "walk_rules": {
+                "include_patterns": rules.include_patterns,
+                "exclude_patterns": rules.exclude_patterns,
+                "respect_ignore_files": rules.respect_ignore_files
+            },
+            "duration_ms": report.elapsed.as_millis() as u64
+        }))
+    }
+
+    /// Delete an index and everything stored for it; `confirm` must be true
+    fn delete_index(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        if arguments["confirm"].as_bool() != Some(true) {
+            return Err(anyhow!("Deleting index '{}' requires confirm: true", index_name));
+        }
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        repository.delete_code_index(&index.id)?;
+        if let Some(store) = &self.embeddings {
+            store.lock().map_err(|_| anyhow!("Embedding store lock poisoned"))?.remove(&index.id)?;
+        }
+        Ok(json!({
+            "success": true,
+            "index_name": index_name,
+            "deleted_files": index.total_files,
+            "deleted_symbols": index.total_symbols
+        }))
+    }
+
+    /// List the symbols stored for one file, in line order or grouped by type
+    fn get_file_symbols(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+        let group_by_type = arguments["group_by_type"].as_bool().unwrap_or(false);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let stored = stored_path(&index.base_path, file_path);
+        let elements = repository.list_code_elements_by_file(&index.id, &stored)?;
+
+        let mut response = json!({
+            "index_name": index_name,
+            "file_path": stored,
+            "total_symbols": elements.len()
+        });
+        if group_by_type {
+            let mut groups: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+            for element in &elements {
+                groups.entry(element.symbol_type.as_str()).or_default().push(reference_entry(element));
+            }
+            response["symbols_by_type"] = json!(groups);
+        } else {
+            response["symbols"] = json!(elements.iter().map(reference_entry).collect::<Vec<_>>());
+        }
+        Ok(response)
+    }
+
+    /// Get the symbols of a file nested by scope, as an editor's outline shows them
+    ///
+    /// Line ranges come from the file on disk; if it can't be read, every
+    /// symbol spans its own line and symbols nest by their stored scope.
+    fn get_file_outline(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let stored = stored_path(&index.base_path, file_path);
+        let elements = repository.list_code_elements_by_file(&index.id, &stored)?;
+        let source = read_source(Path::new(&index.base_path), &stored).ok();
+        let outline = build_outline(&elements, source.as_deref());
+
+        Ok(json!({
+            "index_name": index_name,
+            "file_path": stored,
+            "total_symbols": elements.len(),
+            "ranges_from_source": source.is_some(),
+            "outline": outline.iter().map(OutlineNode::to_json).collect::<Vec<_>>()
+        }))
+    }
+
+    /// Re-index one file after it changed on disk
+    ///
+    /// A file the index doesn't know yet is added; one deleted from disk is
+    /// dropped along with its symbols. Either may be one half of a moved
+    /// file, which is followed instead, keeping its symbols' ids. The file is
+    /// parsed without holding the repository lock, as inline re-indexing does.
+    async fn update_file(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let (index, mut report, before) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let index = repository
+                .get_code_index_by_name(index_name)?
+                .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+            let report = check_file(&repository, &index, &stored_path(&index.base_path, file_path))?;
+            let before = repository.list_code_elements_by_file(&index.id, &report.file_path)?;
+            (index, report, before)
+        };
+
+        if matches!(report.status, Freshness::Missing | Freshness::NotIndexed) {
+            let moves = self.follow_moves(&repository, &index).await?;
+            if let Some(file_move) = moves.iter().find(|file_move| file_move.from == report.file_path || file_move.to == report.file_path) {
+                let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+                let after = repository.list_code_elements_by_file(&index.id, &file_move.to)?;
+                let file_hash = repository.get_file_metadata_by_path(&index.id, &file_move.to)?.map(|metadata| metadata.file_hash);
+                // Only the old path's symbols were listed; a file found at its new path has nothing to compare
+                let before = if file_move.from == report.file_path { before } else { after.clone() };
+                let mut response = file_update(index_name, &file_move.to, &before, &after, file_hash, started);
+                response["status"] = json!(report.status);
+                response["moved"] = move_entry(file_move);
+                return Ok(response);
+            }
+        }
+        let after = match report.status {
+            Freshness::Fresh => {
+                let mut response = file_update(index_name, &report.file_path, &before, &before, report.indexed_hash.clone(), started);
+                response["status"] = json!(report.status);
+                response["message"] = json!("File has not changed since last index");
+                return Ok(response);
+            }
+            Freshness::Missing => {
+                repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?.remove_file(&index.id, &report.file_path)?;
+                Vec::new()
+            }
+            Freshness::NotIndexed | Freshness::Stale => {
+                if report.status == Freshness::NotIndexed {
+                    let content = read_source(Path::new(&index.base_path), &report.file_path)
+                        .map_err(|e| anyhow!("Failed to read {}: {}", report.file_path, e))?;
+                    report.current_hash = Some(content_hash(content.as_bytes()));
+                }
+                self.reindex_checked(&repository, &index, &mut report).await?;
+                let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+                repository.list_code_elements_by_file(&index.id, &report.file_path)?
+            }
+        };
+        let file_hash = if report.status == Freshness::Missing { None } else { report.indexed_hash.clone() };
+        let mut response = file_update(index_name, &report.file_path, &before, &after, file_hash, started);
+        response["status"] = json!(report.status);
+        Ok(response)
+    }
+
+    /// Re-index every file under a directory, or matching a glob, that changed on disk
+    ///
+    /// `path` is absolute or relative to the base path; a file path updates
+    /// just that file. New files the walk rules select are added and
+    /// deleted ones dropped; files moved anywhere in the index are followed
+    /// to their new paths first. Unless `include_dependents` is false, files
+    /// including a changed or deleted file are parsed again too, following
+    /// the include graph. A file that fails to parse is reported and the
+    /// rest are still updated.
+    async fn update_path(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let path = required_str(arguments, "path")?;
+        let include_dependents = arguments["include_dependents"].as_bool().unwrap_or(true);
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let index = repository
+            .lock()
+            .map_err(|_| anyhow!("Repository lock poisoned"))?
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        // Moved files are followed first, so they aren't dropped and added again below
+        let moved = self.follow_moves(&repository, &index).await?;
+        let update = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let scope = PathScope::parse(&index.base_path, path);
+            let root = Path::new(&index.base_path);
+            if let PathScope::Directory(directory) = &scope {
+                let indexed = repository.list_file_metadata(&index.id)?.iter().any(|metadata| scope.contains(&metadata.file_path));
+                if !root.join(directory).exists() && !indexed {
+                    return Err(anyhow!("Nothing on disk or indexed at {}", path));
+                }
+            }
+
+            // Only the scope's directory is walked; a glob walks below its literal leading directories
+            let settings = IndexSettings::load(&repository, &index)?;
+            let directory = match &scope {
+                PathScope::Directory(directory) => directory.clone(),
+                PathScope::Glob(pattern) => {
+                    let literal = pattern.split('/').take_while(|component| !component.contains(['*', '?', '['])).collect::<Vec<_>>();
+                    literal.join("/")
+                }
+            };
+            let on_disk: Vec<String> = if root.is_dir() {
+                settings.selection.source_files_under(root, &directory)?.into_iter().filter(|file| scope.contains(file)).collect()
+            } else {
+                Vec::new()
+            };
+            plan_path_update(&repository, &index, &scope, &on_disk, include_dependents)?
+        };
+
+        let mut symbols_removed = 0;
+        if !update.missing.is_empty() {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            for file_path in &update.missing {
+                symbols_removed += repository.remove_file(&index.id, file_path)?;
+            }
+        }
+
+        let mut indexed = Vec::new();
+        let mut dependents = Vec::new();
+        let mut failed = Vec::new();
+        let mut symbols = 0;
+        let reports = update.changed.into_iter().map(|report| (report, false));
+        for (mut report, dependent) in reports.chain(update.dependents.into_iter().map(|report| (report, true))) {
+            match self.reindex_checked(&repository, &index, &mut report).await {
+                Ok(()) => {
+                    symbols += report.reindexed_symbols.unwrap_or(0);
+                    if dependent {
+                        dependents.push(report.file_path);
+                    } else {
+                        indexed.push(report.file_path);
+                    }
+                }
+                Err(e) => {
+                    warn!("update_path couldn't re-index {}: {}", report.file_path, e);
+                    failed.push(json!({ "file_path": report.file_path, "error": e.to_string() }));
+                }
+            }
+        }
+        if !indexed.is_empty() || !dependents.is_empty() {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            repository.recount_index_totals(&index.id)?;
+        }
+
+        info!(
+            "update_path {} in '{}': {} indexed, {} dependents, {} removed, {} unchanged, {} failed",
+            path,
+            index_name,
+            indexed.len(),
+            dependents.len(),
+            update.missing.len(),
+            update.unchanged,
+            failed.len()
+        );
+        Ok(json!({
+            "success": true,
+            "index_name": index_name,
+            "path": path,
+            "indexed": indexed,
+            "dependents": dependents,
+            "removed": update.missing,
+            "moved": moved.iter().map(move_entry).collect::<Vec<_>>(),
+            "skipped": update.unchanged,
+            "failed": failed,
+            "symbols": symbols,
+            "symbols_removed": symbols_removed,
+            "elapsed_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// Parses a checked file again and stores it, adding it to the index if it wasn't there
+    ///
+    /// The report must carry the file's current hash. Parsing runs without
+    /// the repository lock.
+    async fn reindex_checked(&self, repository: &Arc<Mutex<Repository>>, index: &CodeIndex, report: &mut FreshnessReport) -> Result<()> {
+        let policy = self.reindex_policy(repository, index, &report.file_path)?;
+        let settings = self.index_settings(repository, index)?;
+        let reparsed = extract_file(index, &settings, &report.file_path, &policy).await?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        store_reindexed(&repository, index, report, reparsed)
+    }
+
+    /// Switch an index to the branch checked out at its base path, keeping the one it leaves
+    ///
+    /// `revision` names the branch and defaults to the git branch, or the
+    /// commit when HEAD is detached. Only files that differ are touched:
+    /// git lists them when the base path is the top of a checkout and the
+    /// index was last switched at a known commit, otherwise every source file
+    /// is hashed. The versions left behind are stashed and versions stashed
+    /// earlier are restored without parsing, so hopping between branches only
+    /// parses content the index hasn't seen. Uncommitted edits are picked up
+    /// by update_file as usual.
+    fn switch_index_revision(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let root = Path::new(&index.base_path);
+        let head = GitFs::open(root, "HEAD").ok();
+        let commit = head.as_ref().map(|head| head.commit().to_string());
+        let name = match (arguments["revision"].as_str(), current_branch(root).ok().flatten()) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(branch)) => branch,
+            (None, None) => commit.clone().ok_or_else(|| anyhow!("{} is not a git checkout; pass the revision name", index.base_path))?,
+        };
+
+        let current = repository.get_current_revision(&index.id)?;
+        let live = repository.get_live_files(&index.id)?;
+        let settings = IndexSettings::load(&repository, &index)?;
+        let selected = |file: &String| is_source_file(file) && settings.selects(file);
+        // git lists paths from the top of the checkout, so only a base path at the top can use them
+        let changed = current
+            .as_ref()
+            .filter(|_| root.join(".git").exists())
+            .and_then(|current| GitFs::open(root, current.commit.as_deref()?).ok())
+            .zip(head.as_ref())
+            .and_then(|(before, head)| changed_files(&before, head).ok());
+        let candidates: BTreeSet<String> = match changed {
+            Some(changed) => changed.into_iter().filter(|file| live.contains_key(file) || selected(file)).collect(),
+            None => settings.source_files(root)?.into_iter().chain(live.keys().cloned()).collect(),
+        };
+        if let Some(current) = &current {
+            repository.record_revision_files(current, &live)?;
+        }
+
+        let (mut unchanged, mut restored, mut removed) = (0, 0, 0);
+        let mut to_parse = Vec::new();
+        for path in &candidates {
+            let on_disk = match std::fs::read(root.join(path)) {
+                Ok(content) => Some(content_hash(&content)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(anyhow!("Failed to read {}: {}", path, e)),
+            };
+            if live.get(path) == on_disk.as_ref() {
+                unchanged += 1;
+                continue;
+            }
+            if live.contains_key(path) {
+                repository.stash_file(&index.id, path)?;
+            }
+            match on_disk {
+                None => {
+                    repository.remove_file(&index.id, path)?;
+                    removed += 1;
+                }
+                Some(hash) => match repository.restore_stashed_file(&index.id, path, &hash)? {
+                    Some(_) => restored += 1,
+                    None => to_parse.push(path.clone()),
+                },
+            }
+        }
+
+        let report = if to_parse.is_empty() {
+            // What the pipeline would derive once files are stored
+            repository.refresh_symbol_popularity(&index.id)?;
+            repository.resolve_file_includes(&index.id)?;
+            assign_configurations(&repository, &index)?;
+            PipelineReport::default()
+        } else {
+            repository.update_code_index_state(&index.id, IndexState::Updating)?;
+            let pipeline = IndexingPipeline::new(PipelineConfig::default().with_detail_policy(self.detail_policy));
+            let headers = HeaderCache::new();
+            let new_worker = || settings.worker(&headers, self.parse_worker.as_deref());
+            match pipeline.run(&repository, &index, to_parse, new_worker) {
+                Ok(report) => {
+                    repository.update_code_index_state(&index.id, IndexState::Active)?;
+                    report
+                }
+                Err(e) => {
+                    repository.update_code_index_state(&index.id, IndexState::Failed)?;
+                    return Err(anyhow!("Switching {} to {} failed: {}", index_name, name, e));
+                }
+            }
+        };
+
+        let target = match repository.get_index_revision(&index.id, &name)? {
+            Some(target) => target,
+            None => {
+                let mut revision = IndexRevision::new(index.id, name.clone());
+                if let Some(parent_id) = current.as_ref().and_then(|current| current.id) {
+                    revision = revision.with_parent(parent_id);
+                }
+                if let Some(commit) = &commit {
+                    revision = revision.with_commit(commit.clone());
+                }
+                repository.create_index_revision(revision)?
+            }
+        };
+        repository.record_revision_files(&target, &repository.get_live_files(&index.id)?)?;
+        repository.set_current_revision(&target, commit.as_deref())?;
+        let pruned = repository.prune_stashed_files(&index.id)?;
+        let revisions: Vec<String> = repository.list_index_revisions(&index.id)?.into_iter().map(|revision| revision.name).collect();
+        let index = repository.get_code_index(&index.id)?.ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let failures: Vec<Value> = report.failures.iter().map(|(file, error)| json!({"file_path": file, "error": error})).collect();
+        Ok(json!({
+            "success": true,
+            "index_name": index_name,
+            "revision": name,
+            "commit": commit,
+            "previous_revision": current.map(|current| current.name),
+            "files_compared": candidates.len(),
+            "files_unchanged": unchanged,
+            "files_restored": restored,
+            "files_parsed": report.files_indexed,
+            "files_removed": removed,
+            "stashed_versions_pruned": pruned,
+            "total_files": index.total_files,
+            "total_symbols": index.total_symbols,
+            "revisions": revisions,
+            "failures": failures,
+            "elapsed_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// Find references to a symbol, one page at a time
+    ///
+    /// The first call returns the symbol's declarations (unless disabled) and
+    /// the first page of references. When more remain, `next_cursor` resumes
+    /// the query; the cursor holds only the last position, so symbols with tens
+    /// of thousands of references never build one huge result in memory.
+    ///
+    /// Each reference is classified (call, address taken, type usage, include,
+    /// documentation mention) and the page is grouped by file. With
+    /// `summary_only`, every reference is counted but no locations are returned.
+    fn find_references(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let summary_only = arguments["summary_only"].as_bool().unwrap_or(false);
+        let resumed = match arguments["cursor"].as_str() {
+            Some(cursor) => Some(
+                self.reference_cursors
+                    .lock()
+                    .map_err(|_| anyhow!("Cursor store lock poisoned"))?
+                    .take(cursor)
+                    .ok_or_else(|| anyhow!("Unknown or expired cursor: {}", cursor))?,
+            ),
+            None => None,
+        };
+        // A continuation reads from the database and snapshot its first page came from
+        let repository = match &resumed {
+            Some(state) => self.repository_at(Some(&state.index_name), state.snapshot_id.as_deref())?,
+            None => self.repository_for(arguments)?,
+        };
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+
+        let (cursor, declarations, annotations) = match resumed {
+            Some(state) => (state, Vec::new(), Vec::new()),
+            None => {
+                let index_name = required_str(arguments, "index_name")?;
+                let symbol_name = required_str(arguments, "symbol_name")?;
+                let symbol_type = match arguments["symbol_type"].as_str() {
+                    Some(name) => Some(
+                        SymbolType::all()
+                            .iter()
+                            .copied()
+                            .find(|t| t.as_str() == name)
+                            .ok_or_else(|| anyhow!("Unknown symbol_type: {}", name))?,
+                    ),
+                    None => None,
+                };
+                let usr = arguments["usr"].as_str();
+                let include_declarations = arguments["include_declarations"].as_bool().unwrap_or(true);
+                let page_size = arguments["page_size"]
+                    .as_u64()
+                    .unwrap_or(DEFAULT_REFERENCE_PAGE_SIZE)
+                    .clamp(1, MAX_REFERENCE_PAGE_SIZE);
+                let context_lines = arguments["context_lines"].as_u64().unwrap_or(0).min(MAX_REFERENCE_CONTEXT_LINES) as u32;
+
+                let index = repository
+                    .get_code_index_by_name(index_name)?
+                    .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+                let targets: Vec<CodeElement> = repository
+                    .find_code_elements_by_name(&index.id, symbol_name)?
+                    .into_iter()
+                    .filter(|element| symbol_type.map_or(true, |t| element.symbol_type == t))
+                    .filter(|element| usr.map_or(true, |usr| element.usr.as_deref() == Some(usr)))
+                    .collect();
+                let target_ids: Vec<i64> = targets.iter().filter_map(|element| element.id).collect();
+
+                let declarations = if include_declarations { targets } else { Vec::new() };
+                let references = repository.count_relationships(&references_query(&target_ids, 0))?;
+                let state = ReferenceCursor {
+                    index_id: index.id,
+                    index_name: index.name.clone(),
+                    snapshot_id: arguments["snapshot_id"].as_str().map(str::to_string),
+                    base_path: index.base_path.clone(),
+                    targets: target_ids,
+                    after_id: 0,
+                    page_size,
+                    total_count: references + declarations.len() as u64,
+                    context_lines,
+                };
+                let annotations = repository.get_symbol_annotations(&index.id, symbol_name, None)?;
+                (state, declarations, annotations)
+            }
+        };
+
+        let by_id = |ids: Vec<i64>| -> Result<HashMap<i64, CodeElement>> {
+            let query = CodeElementQuery::new()
+                .filter(Filter::eq(ElementColumn::IndexId, cursor.index_id.to_string()))
+                .filter(Filter::in_list(ElementColumn::Id, ids));
+            Ok(repository
+                .query_code_elements(&query)?
+                .into_iter()
+                .filter_map(|element| element.id.map(|id| (id, element)))
+                .collect())
+        };
+        let targets = by_id(cursor.targets.clone())?;
+        let mut source_lines = SourceLines::new(&cursor.base_path);
+        let mut classify = |relationship: &SymbolRelationship, target: &CodeElement| {
+            let line = source_lines.line(&relationship.file_path, relationship.line_number);
+            ReferenceKind::classify(relationship.relationship_type, line, &target.symbol_name)
+        };
+
+        if summary_only {
+            let mut summary = ReferenceSummary::new(false);
+            let mut after_id = 0;
+            loop {
+                let page = repository.query_relationships(
+                    &references_query(&cursor.targets, after_id).limit(MAX_REFERENCE_PAGE_SIZE),
+                )?;
+                for relationship in &page {
+                    if let Some(target) = targets.get(&relationship.to_symbol_id) {
+                        summary.add(&relationship.file_path, relationship.line_number, classify(relationship, target));
+                    }
+                }
+                match page.last().and_then(|r| r.id) {
+                    Some(last) if page.len() as u64 == MAX_REFERENCE_PAGE_SIZE => after_id = last,
+                    _ => break,
+                }
+            }
+
+            return Ok(json!({
+                "files": summary.files(),
+                "file_count": summary.files().len(),
+                "kind_counts": summary.kind_counts(),
+                "total_count": summary.total(),
+                "query_time_ms": started.elapsed().as_millis() as u64
+            }));
+        }
+
+        // Fetch one extra row to learn whether another page follows
+        let mut page = repository.query_relationships(
+            &references_query(&cursor.targets, cursor.after_id).limit(cursor.page_size + 1),
+        )?;
+        let has_more = page.len() as u64 > cursor.page_size;
+        page.truncate(cursor.page_size as usize);
+        let sources = by_id(page.iter().map(|r| r.from_symbol_id).collect::<BTreeSet<_>>().into_iter().collect())?;
+
+        let mut summary = ReferenceSummary::new(true);
+        let mut symbols: Vec<Value> = declarations.iter().map(reference_entry).collect();
+        for relationship in &page {
+            let Some(target) = targets.get(&relationship.to_symbol_id) else { continue };
+            let kind = classify(relationship, target);
+            summary.add(&relationship.file_path, relationship.line_number, kind);
+
+            let mut entry = reference_entry(target);
+            entry["file_path"] = json!(relationship.file_path);
+            entry["line_number"] = json!(relationship.line_number);
+            // Relationships don't record a column
+            entry["column_number"] = json!(0);
+            entry["scope"] = json!(sources.get(&relationship.from_symbol_id).map(CodeElement::fully_qualified_name));
+            entry["is_declaration"] = json!(false);
+            entry["relationship_type"] = json!(relationship.relationship_type.as_str());
+            entry["reference_kind"] = json!(kind);
+            symbols.push(entry);
+        }
+        // Read through the same cache that classified the references, so each file is read once
+        if cursor.context_lines > 0 {
+            for entry in &mut symbols {
+                let (Some(file_path), Some(line_number)) = (entry["file_path"].as_str(), entry["line_number"].as_u64()) else { continue };
+                let context = source_lines.context(file_path, line_number as u32, cursor.context_lines);
+                entry["context"] = json!(context);
+            }
+        }
+
+        let next_cursor = match (has_more, page.last().and_then(|r| r.id)) {
+            (true, Some(after_id)) => Some(
+                self.reference_cursors
+                    .lock()
+                    .map_err(|_| anyhow!("Cursor store lock poisoned"))?
+                    .insert(ReferenceCursor { after_id, ..cursor.clone() }),
+            ),
+            _ => None,
+        };
+
+        let mut response = json!({
+            "symbols": symbols,
+            "returned_count": symbols.len(),
+            "files": summary.files(),
+            "kind_counts": summary.kind_counts(),
+            "total_count": cursor.total_count,
+            "next_cursor": next_cursor,
+            "query_time_ms": started.elapsed().as_millis() as u64
+        });
+        // Annotations describe the symbol, so only the first page carries them
+        if !annotations.is_empty() {
+            response["annotations"] = json!(annotations.iter().map(annotation_entry).collect::<Vec<_>>());
+        }
+        Ok(response)
+    }
+
+    /// Set or remove key/value tags on an index
+    fn set_index_tags(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let set = string_map(&arguments["tags"], "tags")?;
+        let remove: Vec<&str> = match &arguments["remove"] {
+            Value::Null => Vec::new(),
+            Value::Array(keys) => keys
+                .iter()
+                .map(|key| key.as_str().ok_or_else(|| anyhow!("remove must be an array of tag keys")))
+                .collect::<Result<_>>()?,
+            _ => return Err(anyhow!("remove must be an array of tag keys")),
+        };
+        if set.is_empty() && remove.is_empty() {
+            return Err(anyhow!("Either tags or remove must be provided"));
+        }
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        for (key, value) in set {
+            let tag = IndexTag::new(index.id, key, value);
+            tag.validate().map_err(|e| anyhow!(e))?;
+            repository.set_index_tag(&tag)?;
+        }
+        for key in remove {
+            repository.remove_index_tag(&index.id, key)?;
+        }
+
+        Ok(json!({
+            "success": true,
+            "index_name": index_name,
+            "tags": repository.get_index_tags(&index.id)?
+        }))
+    }
+
+    /// Add or remove a tag on every symbol matching a name
+    fn tag_symbol(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let symbol_name = required_str(arguments, "symbol_name")?;
+        let tag = required_str(arguments, "tag")?;
+        let scope = arguments["scope"].as_str();
+        let remove = arguments["remove"].as_bool().unwrap_or(false);
+        IndexTag::validate_key(tag).map_err(|e| anyhow!(e))?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let elements: Vec<CodeElement> = repository
+            .find_code_elements_by_name(&index.id, symbol_name)?
+            .into_iter()
+            .filter(|element| scope.map_or(true, |scope| element.scope.as_deref() == Some(scope)))
+            .collect();
+        if elements.is_empty() {
+            return Err(anyhow!("Symbol not found: {}", symbol_name));
+        }
+
+        let mut changed = 0;
+        for id in elements.iter().filter_map(|element| element.id) {
+            let applied = if remove {
+                repository.remove_symbol_tag(id, tag)?
+            } else {
+                repository.add_symbol_tag(id, tag)?
+            };
+            changed += applied as usize;
+        }
+
+        Ok(json!({
+            "success": true,
+            "tag": tag,
+            "removed": remove,
+            "changed": changed,
+            "symbols": elements.iter().map(reference_entry).collect::<Vec<_>>()
+        }))
+    }
+
+    /// List hazardous calls reachable from hot-path roots
+    ///
+    /// Roots are the functions carrying `tag` (or named in `roots`). Every
+    /// function they can reach, through direct calls, virtual dispatch and
+    /// callbacks, is scanned for allocation, locking and forbidden APIs; each
+    /// finding carries the call path from its root.
+    fn analyze_hot_paths(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let tag = arguments["tag"].as_str().unwrap_or(DEFAULT_HOT_PATH_TAG);
+        let root_names = string_list(&arguments["roots"], "roots")?;
+        let max_depth = arguments["max_depth"].as_u64().map_or(DEFAULT_HOT_PATH_DEPTH, |depth| depth as u32);
+        let rules = HotPathRules::new()
+            .forbid(HazardCategory::Forbidden, string_list(&arguments["forbidden"], "forbidden")?)
+            .allow(string_list(&arguments["allowed"], "allowed")?);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let roots: Vec<CodeElement> = if root_names.is_empty() {
+            repository.find_code_elements_by_tag(&index.id, tag)?
+        } else {
+            let mut roots = Vec::new();
+            for name in &root_names {
+                roots.extend(repository.find_code_elements_by_name(&index.id, name)?);
+            }
+            roots
+        };
+        let roots: Vec<CodeElement> = roots.into_iter().filter(CodeElement::is_callable).collect();
+        if roots.is_empty() {
+            return Err(anyhow!(
+                "No hot-path roots found: tag functions with tag_symbol (tag \"{}\") or pass roots",
+                tag
+            ));
+        }
+        let root_ids: Vec<i64> = roots.iter().filter_map(|element| element.id).collect();
+
+        let options = CallGraphOptions::default()
+            .with_max_depth(max_depth)
+            .with_max_nodes(MAX_HOT_PATH_FUNCTIONS)
+            .with_resolve_virtual(true);
+        let graph = CallGraphWalker::new(&repository, options).walk(&root_ids)?;
+
+        // The first edge reaching a symbol is the one the walk discovered it through
+        let mut parents: HashMap<i64, &CallEdge> = HashMap::new();
+        for edge in &graph.edges {
+            if !root_ids.contains(&edge.to_symbol_id) {
+                parents.entry(edge.to_symbol_id).or_insert(edge);
+            }
+        }
+        let names: HashMap<i64, String> = graph
+            .nodes
+            .iter()
+            .filter_map(|node| node.element.id.map(|id| (id, node.element.fully_qualified_name())))
+            .collect();
+
+        let base_path = Path::new(&index.base_path);
+        let mut sources: HashMap<&str, Option<String>> = HashMap::new();
+        let mut findings = Vec::new();
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut functions_checked = 0;
+        for node in graph.nodes.iter().filter(|node| node.element.is_callable() && !node.element.is_declaration) {
+            let element = &node.element;
+            let source = sources
+                .entry(element.file_path.as_str())
+                .or_insert_with(|| read_source(base_path, &element.file_path).ok());
+            let Some(source) = source else { continue };
+            functions_checked += 1;
+
+            let hazards = find_body_hazards(source, element.line_number, &rules);
+            if hazards.is_empty() {
+                continue;
+            }
+
+            let mut path = Vec::new();
+            let mut indirect = false;
+            let mut current = element.id;
+            while let Some(id) = current {
+                path.push(names.get(&id).cloned().unwrap_or_default());
+                let edge = parents.get(&id);
+                indirect |= edge.is_some_and(|edge| edge.kind != CallEdgeKind::Direct);
+                current = edge.map(|edge| edge.from_symbol_id);
+            }
+            path.reverse();
+
+            for hazard in hazards {
+                *counts.entry(hazard.category.as_str()).or_insert(0) += 1;
+                findings.push(json!({
+                    "function": reference_entry(element),
+                    "category": hazard.category,
+                    "api": hazard.api,
+                    "file_path": element.file_path,
+                    "line_number": hazard.line,
+                    "column_number": hazard.column,
+                    "path": path,
+                    "indirect": indirect
+                }));
+            }
+        }
+
+        Ok(json!({
+            "roots": roots.iter().map(reference_entry).collect::<Vec<_>>(),
+            "findings": findings,
+            "counts": counts,
+            "functions_reached": graph.nodes.len(),
+            "functions_checked": functions_checked,
+            "truncated": graph.truncated,
+            "query_time_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// List symbols placed in a memory section, or every section's symbol count
+    ///
+    /// A section also matches its subsections, so ".itcm" finds symbols in
+    /// ".itcm.text" too.
+    fn find_symbols_in_section(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let symbol_type = match arguments["symbol_type"].as_str() {
+            Some(name) => Some(
+                SymbolType::all()
+                    .iter()
+                    .copied()
+                    .find(|t| t.as_str() == name)
+                    .ok_or_else(|| anyhow!("Unknown symbol_type: {}", name))?,
+            ),
+            None => None,
+        };
+        let page = page_arguments(arguments, DEFAULT_SECTION_SYMBOL_LIMIT, MAX_REFERENCE_PAGE_SIZE);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let Some(section) = arguments["section"].as_str() else {
+            return Ok(json!({
+                "index_name": index_name,
+                "sections": repository.count_code_elements_by_section(&index.id)?
+            }));
+        };
+
+        let mut query = CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+            .filter(Filter::or(vec![
+                Filter::eq(ElementColumn::MemorySection, section.to_string()),
+                Filter::like(ElementColumn::MemorySection, format!("{}.%", escape_like(section))),
+            ]));
+        if let Some(symbol_type) = symbol_type {
+            query = query.filter(Filter::eq(ElementColumn::SymbolType, symbol_type));
+        }
+        let query = query
+            .order_by_asc(ElementColumn::FilePath)
+            .order_by_asc(ElementColumn::LineNumber);
+
+        let symbols = repository.query_code_elements_page(&query, &page)?.map(|element| {
+            let mut entry = reference_entry(&element);
+            entry["memory_section"] = json!(element.memory_section);
+            entry
+        });
+
+        Ok(json!({
+            "index_name": index_name,
+            "section": section,
+            "symbols": symbols.items,
+            "total_count": symbols.total_count,
+            "truncated": symbols.has_more(),
+            "next_cursor": symbols.next_cursor
+        }))
+    }
+
+    /// Search symbols by name, optionally narrowed by type, file and scope
+    ///
+    /// `match_mode` is substring (default), prefix, glob, regex, exact or
+    /// fuzzy; `exact_match` is shorthand for exact. A query such as
+    /// `render::Vector` matches its last component that way, inside scopes
+    /// ending in the qualifier (see `QualifiedName`); regexes are never split.
+    /// `file_path` is a glob over stored paths and `scope` matches that scope
+    /// and the scopes nested in it.
+    /// Results are ranked by reference count unless `rank` is "name", so the
+    /// widely used symbol comes before a test helper of the same name. Fuzzy
+    /// results are always ranked by closeness of the name (see `fuzzy::rank`)
+    /// and carry their score. With `explain` each symbol says how its name
+    /// matched and what it was ranked by.
+    fn search_symbols(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let pattern = required_str(arguments, "query")?;
+        let match_mode = match (arguments["exact_match"].as_bool(), arguments["match_mode"].as_str()) {
+            (Some(true), _) => MatchMode::Exact,
+            (_, Some(mode)) => MatchMode::parse(mode).ok_or_else(|| anyhow!("Unknown match_mode: {}", mode))?,
+            (_, None) => MatchMode::default(),
+        };
+        let symbol_type = match arguments["symbol_type"].as_str() {
+            Some(name) => Some(
+                SymbolType::all()
+                    .iter()
+                    .copied()
+                    .find(|t| t.as_str() == name)
+                    .ok_or_else(|| anyhow!("Unknown symbol_type: {}", name))?,
+            ),
+            None => None,
+        };
+        let page = page_arguments(arguments, DEFAULT_SYMBOL_SEARCH_LIMIT, MAX_SYMBOL_SEARCH_LIMIT);
+        let by_popularity = match arguments["rank"].as_str().unwrap_or("popularity") {
+            "popularity" => true,
+            "name" => false,
+            other => return Err(anyhow!("Unknown rank: {} (expected popularity or name)", other)),
+        };
+        let explain = arguments["explain"].as_bool().unwrap_or(false);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let qualified = QualifiedName::parse(pattern).filter(|_| match_mode != MatchMode::Regex);
+        let fuzzy_name = qualified.as_ref().map_or(pattern, |qualified| qualified.name.as_str());
+        let mut query = CodeElementQuery::new().filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()));
+        if let Some(symbol_type) = symbol_type {
+            query = query.filter(Filter::eq(ElementColumn::SymbolType, symbol_type));
+        }
+        if let Some(file_path) = arguments["file_path"].as_str() {
+            query = query.filter(Filter::glob(ElementColumn::FilePath, file_path));
+        }
+        if let Some(scope) = arguments["scope"].as_str() {
+            query = query.filter(Filter::or(vec![
+                Filter::eq(ElementColumn::Scope, scope.to_string()),
+                Filter::like(ElementColumn::Scope, format!("{}::%", escape_like(scope))),
+            ]));
+        }
+        if let Some(name) = arguments["configuration"].as_str() {
+            let configuration = repository
+                .get_build_configuration(&index.id, name)?
+                .ok_or_else(|| anyhow!("Build configuration not found: {}", name))?;
+            let excluded = repository.list_configuration_exclusions(configuration.id.unwrap_or_default())?;
+            if !excluded.is_empty() {
+                query = query.filter(Filter::negate(Filter::in_list(ElementColumn::Id, excluded)));
+            }
+        }
+        // Fuzzy candidates are drawn from the symbols the other filters leave
+        let name_filter = match (&qualified, match_mode) {
+            (Some(qualified), MatchMode::Fuzzy) => {
+                let scoped = query.clone().filter(qualified.scope_filter());
+                Ok(Filter::in_list(ElementColumn::Id, repository.fuzzy_candidates(fuzzy_name, &scoped, MAX_FUZZY_CANDIDATES)?))
+            }
+            (None, MatchMode::Fuzzy) => Ok(Filter::in_list(ElementColumn::Id, repository.fuzzy_candidates(fuzzy_name, &query, MAX_FUZZY_CANDIDATES)?)),
+            (Some(qualified), _) => qualified.filter(match_mode),
+            (None, _) => match_mode.filter(ElementColumn::SymbolName, pattern),
+        };
+        query = query.filter(name_filter.map_err(|e| anyhow!(e))?);
+        if by_popularity {
+            query = query.order_by(ElementColumn::ReferenceCount, SortDirection::Descending);
+        }
+        let query = query
+            .order_by_asc(ElementColumn::SymbolName)
+            .order_by_asc(ElementColumn::FilePath)
+            .order_by_asc(ElementColumn::LineNumber);
+
+        let elements = if match_mode == MatchMode::Fuzzy {
+            let ranked = fuzzy::rank(fuzzy_name, repository.query_code_elements(&query)?);
+            page.slice(ranked).map_err(|e| anyhow!(e))?.map(|candidate| (candidate.element, Some(candidate.score)))
+        } else {
+            repository.query_code_elements_page(&query, &page)?.map(|element| (element, None))
+        };
+        let ids: Vec<i64> = elements.items.iter().filter_map(|(element, _)| element.id).collect();
+        let popularity = repository.get_symbol_popularity(&ids)?;
+        let symbols = elements.map(|(element, score)| {
+            let mut entry = reference_entry(&element);
+            let counts = element.id.and_then(|id| popularity.get(&id)).copied().unwrap_or_default();
+            entry["popularity"] = popularity_entry(&counts);
+            if let Some(score) = score {
+                entry["score"] = json!(score);
+            }
+            if explain {
+                entry["explanation"] = json!(explain_symbol(&element, pattern, match_mode, qualified.is_some(), score, &counts, by_popularity));
+            }
+            entry
+        });
+
+        Ok(json!({
+            "index_name": index_name,
+            "query": pattern,
+            "match_mode": match_mode.as_str(),
+            "qualified": qualified.is_some(),
+            "rank": match (match_mode, by_popularity) {
+                (MatchMode::Fuzzy, _) => "match",
+                (_, true) => "popularity",
+                (_, false) => "name",
+            },
+            "configuration": arguments["configuration"].as_str(),
+            "symbols": symbols.items,
+            "total_count": symbols.total_count,
+            "truncated": symbols.has_more(),
+            "next_cursor": symbols.next_cursor,
+            "query_time_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// One symbol with its documentation, popularity and outgoing relationships
+    ///
+    /// With `include_body` the stored source text is decompressed and
+    /// returned too; it is null when the index was built without bodies.
+    fn get_symbol_details(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let symbol_id = arguments["symbol_id"].as_i64().ok_or_else(|| anyhow!("Missing required parameter: symbol_id"))?;
+        let include_relationships = arguments["include_relationships"].as_bool().unwrap_or(true);
+        let include_body = arguments["include_body"].as_bool().unwrap_or(false);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let symbol = repository
+            .get_code_element(symbol_id)?
+            .filter(|element| element.index_id == index.id)
+            .ok_or_else(|| anyhow!("Symbol {} not found in index {}", symbol_id, index_name))?;
+        let popularity = repository.get_symbol_popularity(&[symbol_id])?.remove(&symbol_id).unwrap_or_default();
+
+        let mut details = reference_entry(&symbol);
+        details["signature"] = json!(repository.get_symbol_signature(symbol_id)?);
+        details["qualified_name"] = json!(symbol.fully_qualified_name());
+        details["access_modifier"] = json!(symbol.access_modifier.map(|access| access.as_str()));
+        details["documentation"] = json!(symbol.documentation);
+        details["definition_hash"] = json!(symbol.definition_hash);
+        details["popularity"] = popularity_entry(&popularity);
+        details["configurations"] = json!(repository.get_symbol_configurations(&index.id, symbol_id)?);
+        details["annotations"] = json!(repository
+            .get_symbol_annotations(&index.id, &symbol.symbol_name, symbol.scope.as_deref())?
+            .iter()
+            .map(annotation_entry)
+            .collect::<Vec<_>>());
+        // The other declarations and the definition of this overload
+        let resolution = declarations_of(&repository, &symbol)?;
+        details["definitions"] = json!(resolution.definitions.iter().map(reference_entry).collect::<Vec<_>>());
+        details["declarations"] = json!(resolution.declarations.iter().map(reference_entry).collect::<Vec<_>>());
+        if include_body {
+            details["body"] = json!(repository.get_symbol_body(symbol_id)?);
+        }
+
+        if include_relationships {
+            let (outgoing, _) = repository.get_symbol_relationships(symbol_id)?;
+            let target_ids: BTreeSet<i64> = outgoing.iter().map(|relationship| relationship.to_symbol_id).collect();
+            let targets: HashMap<i64, String> = repository
+                .query_code_elements(&CodeElementQuery::new().filter(Filter::in_list(ElementColumn::Id, target_ids)))?
+                .iter()
+                .filter_map(|element| element.id.map(|id| (id, element.fully_qualified_name())))
+                .collect();
+            details["relationships"] = outgoing
+                .iter()
+                .map(|relationship| {
+                    json!({
+                        "target_symbol_id": relationship.to_symbol_id,
+                        "target_symbol_name": targets.get(&relationship.to_symbol_id),
+                        "relationship_type": relationship.relationship_type.as_str(),
+                        "file_path": relationship.file_path,
+                        "line_number": relationship.line_number
+                    })
+                })
+                .collect();
+        }
+
+        Ok(details)
+    }
+
+    /// Full-text search over symbol names, signatures, scopes and documentation
+    ///
+    /// Every word of `query` must match, the last as a prefix; with `raw` the
+    /// query is an FTS5 expression (phrases, OR, NEAR, column filters). With
+    /// `explain` each symbol says which fields matched which terms and how
+    /// its score was made up, popularity breaking ties.
+    fn search_text(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let text = required_str(arguments, "query")?;
+        let raw = arguments["raw"].as_bool().unwrap_or(false);
+        let explain = arguments["explain"].as_bool().unwrap_or(false);
+        let page = page_arguments(arguments, DEFAULT_TEXT_SEARCH_LIMIT, MAX_REFERENCE_PAGE_SIZE);
+        let columns = string_list(&arguments["columns"], "columns")?
+            .iter()
+            .map(|name| TextColumn::parse(name).ok_or_else(|| anyhow!("Unknown column: {}", name)))
+            .collect::<Result<Vec<_>>>()?;
+        let symbol_types = symbol_type_list(&arguments["symbol_types"])?;
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let hits = repository.full_text_search(&index.id, text, &columns, Some(&symbol_types), raw, &page)?;
+        let (terms, popularity) = if explain {
+            let ids: Vec<i64> = hits.items.iter().filter_map(|hit| hit.element.id).collect();
+            (query_terms(text, raw), repository.get_symbol_popularity(&ids)?)
+        } else {
+            Default::default()
+        };
+        let symbols = hits.map(|hit| {
+            let mut entry = reference_entry(&hit.element);
+            entry["documentation"] = json!(hit.element.documentation);
+            entry["score"] = json!(hit.score);
+            entry["snippet"] = json!(hit.snippet);
+            if explain {
+                let counts = hit.element.id.and_then(|id| popularity.get(&id)).copied().unwrap_or_default();
+                entry["explanation"] = json!(explain_hit(&hit, &terms, &columns, &counts));
+            }
+            entry
+        });
+
+        Ok(json!({
+            "index_name": index_name,
+            "query": text,
+            "symbols": symbols.items,
+            "total_count": symbols.total_count,
+            "truncated": symbols.has_more(),
+            "next_cursor": symbols.next_cursor,
+            "query_time_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// Find symbols related in meaning to a question, by embedding similarity
+    ///
+    /// The index must have been embedded with the configured backend
+    /// (`index embed`). Symbols added or re-indexed since are missing until
+    /// it is embedded again, which the answer flags as stale. With `explain`
+    /// each symbol says what its score is.
+    fn semantic_search(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let query = required_str(arguments, "query")?;
+        let limit = arguments["limit"]
+            .as_u64()
+            .unwrap_or(DEFAULT_SEMANTIC_SEARCH_LIMIT)
+            .clamp(1, MAX_SEMANTIC_SEARCH_LIMIT) as usize;
+        let symbol_types = symbol_type_list(&arguments["symbol_types"])?;
+        let explain = arguments["explain"].as_bool().unwrap_or(false);
+        let (Some(store), Some(backend)) = (&self.embeddings, &self.embedder) else {
+            return Err(anyhow!("Semantic search is disabled; configure an embeddings backend and run `index embed`"));
+        };
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let store = store.lock().map_err(|_| anyhow!("Embedding store lock poisoned"))?;
+        let status = store
+            .status(&index.id)?
+            .ok_or_else(|| anyhow!("Index {} has no embeddings; run `index embed --name {}`", index_name, index_name))?;
+        let hits = store.search(&index.id, backend.as_ref(), query, &symbol_types, limit)?;
+
+        let query_ids = CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+            .filter(Filter::in_list(ElementColumn::Id, hits.iter().map(|hit| hit.element_id)));
+        let elements: HashMap<i64, CodeElement> = repository
+            .query_code_elements(&query_ids)?
+            .into_iter()
+            .filter_map(|element| element.id.map(|id| (id, element)))
+            .collect();
+        // Vectors of symbols deleted since the index was embedded have no element left
+        let symbols: Vec<Value> = hits
+            .iter()
+            .filter_map(|hit| {
+                let element = elements.get(&hit.element_id)?;
+                let mut entry = reference_entry(element);
+                entry["qualified_name"] = json!(element.fully_qualified_name());
+                entry["documentation"] = json!(element.documentation);
+                entry["score"] = json!(hit.score);
+                if explain {
+                    entry["explanation"] = json!(explain_similarity(f64::from(hit.score), &status.backend));
+                }
+                Some(entry)
+            })
+            .collect();
+
+        Ok(json!({
+            "index_name": index_name,
+            "query": query,
+            "backend": status.backend,
+            "symbols": symbols,
+            "total_count": symbols.len(),
+            "stale": status.index_updated_at != index.updated_at,
+            "embedded_at": status.embedded_at.to_rfc3339(),
+            "query_time_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// Search the text of the indexed files, naming the symbol around each hit
+    ///
+    /// Finds what symbol search can't: string literals, comments, TODOs.
+    /// Files are read from the index base path in path order; unreadable
+    /// files are counted and skipped. The repository lock is only held to
+    /// list the files and, afterwards, to outline those with hits.
+    fn grep_code(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let query = required_str(arguments, "pattern")?;
+        let pattern = GrepPattern::new(
+            query,
+            arguments["regex"].as_bool().unwrap_or(false),
+            arguments["case_sensitive"].as_bool().unwrap_or(true),
+            arguments["whole_word"].as_bool().unwrap_or(false),
+        )
+        .map_err(|e| anyhow!("Invalid pattern: {}", e))?;
+        let max_results = arguments["max_results"].as_u64().unwrap_or(DEFAULT_GREP_RESULTS).clamp(1, MAX_GREP_RESULTS) as usize;
+
+        let repository = self.repository_for(arguments)?;
+        let (index, files) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let index = repository
+                .get_code_index_by_name(index_name)?
+                .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+            let scope = arguments["path"].as_str().map(|path| PathScope::parse(&index.base_path, path));
+            let files: Vec<String> = repository
+                .list_file_metadata(&index.id)?
+                .into_iter()
+                .map(|metadata| metadata.file_path)
+                .filter(|file_path| scope.as_ref().map_or(true, |scope| scope.contains(file_path)))
+                .collect();
+            (index, files)
+        };
+
+        let base_path = Path::new(&index.base_path);
+        let mut hits = Vec::new();
+        let mut unreadable = Vec::new();
+        let mut searched = 0;
+        let mut count = 0;
+        let mut truncated = false;
+        for file_path in &files {
+            let Ok(source) = read_source(base_path, file_path) else {
+                unreadable.push(file_path.clone());
+                continue;
+            };
+            searched += 1;
+            // One hit past the limit tells whether there are more
+            let found = pattern.search(&source, max_results + 1 - count);
+            count += found.len();
+            if !found.is_empty() {
+                hits.push((file_path.clone(), source, found));
+            }
+            if count > max_results {
+                truncated = true;
+                if let Some((_, _, found)) = hits.last_mut() {
+                    found.pop();
+                }
+                break;
+            }
+        }
+
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let mut matches = Vec::new();
+        for (file_path, source, found) in &hits {
+            let outline = build_outline(&repository.list_code_elements_by_file(&index.id, file_path)?, Some(source));
+            for hit in found {
+                let symbol = enclosing_symbol(&outline, hit.line_number).map(|element| {
+                    json!({
+                        "id": element.id,
+                        "name": element.symbol_name,
+                        "qualified_name": element.fully_qualified_name(),
+                        "type": element.symbol_type.as_str(),
+                        "line_number": element.line_number
+                    })
+                });
+                matches.push(json!({
+                    "file_path": file_path,
+                    "line_number": hit.line_number,
+                    "column_number": hit.column_number,
+                    "line": hit.line,
+                    "matched": hit.matched,
+                    "symbol": symbol
+                }));
+            }
+        }
+
+        Ok(json!({
+            "index_name": index_name,
+            "pattern": query,
+            "matches": matches,
+            "total_count": matches.len(),
+            "truncated": truncated,
+            "files_searched": searched,
+            "unreadable_files": unreadable,
+            "query_time_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// Report which files and symbols exist under each macro configuration
+    ///
+    /// Each configuration is a name and a list of `NAME[=VALUE]` definitions.
+    /// Files are read from the index base path and their `#if` conditions
+    /// evaluated per configuration; unreadable files are listed and skipped.
+    fn conditional_compilation_matrix(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let path_prefix = arguments["path_prefix"].as_str();
+        let max_entries = arguments["max_entries"].as_u64().unwrap_or(DEFAULT_MATRIX_ENTRIES) as usize;
+
+        let configurations = match &arguments["configurations"] {
+            Value::Array(entries) if entries.len() >= 2 => entries
+                .iter()
+                .map(|entry| {
+                    let name = required_str(entry, "name")?;
+                    string_list(&entry["defines"], "defines")?.iter().try_fold(MacroConfiguration::new(name), |config, definition| {
+                        let (macro_name, value) = MacroConfiguration::parse_define(definition).map_err(|e| anyhow!(e))?;
+                        Ok(config.with_define(macro_name, value))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => return Err(anyhow!("configurations must be an array of at least two configurations")),
+        };
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let mut query = CodeElementQuery::new().filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()));
+        if let Some(prefix) = path_prefix {
+            query = query.filter(Filter::like(ElementColumn::FilePath, format!("{}%", escape_like(prefix))));
+        }
+        let mut by_file: BTreeMap<String, Vec<CodeElement>> = BTreeMap::new();
+        for element in repository.query_code_elements(&query.order_by_asc(ElementColumn::LineNumber))? {
+            by_file.entry(element.file_path.clone()).or_default().push(element);
+        }
+
+        let base_path = Path::new(&index.base_path);
+        let mut unreadable = Vec::new();
+        let mut files = Vec::with_capacity(by_file.len());
+        for (file_path, elements) in &by_file {
+            match read_source(base_path, file_path) {
+                Ok(content) => files.push((file_path.as_str(), content, elements.as_slice())),
+                Err(_) => unreadable.push(file_path.as_str()),
+            }
+        }
+
+        let mut matrix = ConfigurationMatrix::build(
+            &configurations,
+            files.iter().map(|(file_path, content, elements)| (*file_path, content.as_str(), *elements)),
+        );
+        let divergent_file_count = matrix.divergent_files.len();
+        let divergent_symbol_count = matrix.divergent_symbols.len();
+        matrix.divergent_files.truncate(max_entries);
+        matrix.divergent_symbols.truncate(max_entries);
+
+        Ok(json!({
+            "index_name": index_name,
+            "configurations": matrix.configurations,
+            "common_files": matrix.common_files,
+            "common_symbols": matrix.common_symbols,
+            "divergent_files": matrix.divergent_files,
+            "divergent_symbols": matrix.divergent_symbols,
+            "divergent_file_count": divergent_file_count,
+            "divergent_symbol_count": divergent_symbol_count,
+            "unreadable_files": unreadable,
+            "query_time_ms": started.elapsed().as_millis() as u64
+        }))
+    }
+
+    /// Attach a note or bookmark to a symbol
+    fn annotate_symbol(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let symbol_name = required_str(arguments, "symbol_name")?;
+        let kind = match arguments["kind"].as_str() {
+            Some(kind) => AnnotationKind::parse(kind).ok_or_else(|| anyhow!("Unknown annotation kind: {}", kind))?,
+            None => AnnotationKind::Note,
+        };
+        let text = arguments["text"].as_str().unwrap_or("");
+        let author = arguments["author"].as_str().unwrap_or(DEFAULT_ANNOTATION_AUTHOR);
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let mut annotation = SymbolAnnotation::new(index.id, symbol_name.to_string(), author.to_string(), kind, text.to_string());
+        if let Some(scope) = arguments["scope"].as_str() {
+            annotation = annotation.with_scope(scope.to_string());
+        }
+        annotation.validate().map_err(|e| anyhow!(e))?;
+        let annotation = repository.create_annotation(annotation)?;
+
+        Ok(json!({
+            "success": true,
+            "annotation": annotation_entry(&annotation)
+        }))
+    }
+
+    /// List annotations of an index, of one symbol, author or kind
+    fn list_annotations(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let kind = match arguments["kind"].as_str() {
+            Some(kind) => Some(AnnotationKind::parse(kind).ok_or_else(|| anyhow!("Unknown annotation kind: {}", kind))?),
+            None => None,
+        };
+        let author = arguments["author"].as_str();
+        let page = page_arguments(arguments, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let annotations = match arguments["symbol_name"].as_str() {
+            Some(symbol_name) => repository
+                .get_symbol_annotations(&index.id, symbol_name, arguments["scope"].as_str())?
+                .into_iter()
+                .filter(|annotation| author.map_or(true, |author| annotation.author == author))
+                .filter(|annotation| kind.map_or(true, |kind| annotation.kind == kind))
+                .collect(),
+            None => repository.list_annotations(&index.id, author, kind)?,
+        };
+        let annotations = page.slice(annotations).map_err(|e| anyhow!(e))?;
+
+        Ok(json!({
+            "index_name": index_name,
+            "annotations": annotations.items.iter().map(annotation_entry).collect::<Vec<_>>(),
+            "total_count": annotations.total_count,
+            "next_cursor": annotations.next_cursor
+        }))
+    }
+
+    /// List the files of an index its last runs couldn't index, and why
+    fn list_index_errors(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let kind = match arguments["kind"].as_str() {
+            Some(kind) => Some(IndexErrorKind::parse(kind).ok_or_else(|| anyhow!("Unknown error kind: {}", kind))?),
+            None => None,
+        };
+        let page = page_arguments(arguments, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let errors = repository.list_index_errors(&index.id, kind)?;
+        let mut kind_counts: BTreeMap<&str, u64> = BTreeMap::new();
+        for error in &errors {
+            *kind_counts.entry(error.kind.as_str()).or_default() += 1;
+        }
+        let errors = page.slice(errors).map_err(|e| anyhow!(e))?;
+
+        Ok(json!({
+            "index_name": index_name,
+            "errors": errors.items.iter().map(index_error_entry).collect::<Vec<_>>(),
+            "kind_counts": kind_counts,
+            "total_count": errors.total_count,
+            "next_cursor": errors.next_cursor
+        }))
+    }
+
+    /// Hot spots of an index: fan-in, fan-out, largest classes, most included headers and busiest files
+    fn get_index_insights(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let top = arguments["top"].as_u64().unwrap_or(DEFAULT_INSIGHT_ENTRIES).clamp(1, MAX_INSIGHT_ENTRIES) as usize;
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let insights = repository.get_index_insights(&index.id, top)?;
+
+        let symbols = |ranked: &[RankedSymbol]| ranked.iter().map(ranked_symbol_entry).collect::<Vec<_>>();
+        let files = |ranked: &[RankedFile]| {
+            ranked
+                .iter()
+                .map(|entry| json!({ "file_path": entry.file_path, "count": entry.count }))
+                .collect::<Vec<_>>()
+        };
+        Ok(json!({
+            "index_name": index_name,
+            "fan_in": symbols(&insights.fan_in),
+            "fan_out": symbols(&insights.fan_out),
+            "largest_classes": symbols(&insights.largest_classes),
+            "most_included_headers": files(&insights.most_included_headers),
+            "busiest_files": files(&insights.busiest_files)
+        }))
+    }
+
+    /// Delete an annotation by id
+    fn delete_annotation(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let id = arguments["id"].as_i64().ok_or_else(|| anyhow!("Missing required parameter: id"))?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        // Only delete annotations belonging to the named index
+        let owned = repository.list_annotations(&index.id, None, None)?.iter().any(|annotation| annotation.id == Some(id));
+        if !owned || !repository.delete_annotation(id)? {
+            return Err(anyhow!("Annotation not found: {}", id));
+        }
+
+        Ok(json!({
+            "success": true,
+            "id": id
+        }))
+    }
+
+    /// Save a named filter expression, replacing any query of the same name
+    fn save_query(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let name = required_str(arguments, "name")?;
+        let query = required_str(arguments, "query")?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let mut saved = SavedQuery::new(index.id, name.to_string(), query.to_string())
+            .with_watch(arguments["watch"].as_bool().unwrap_or(false));
+        if let Some(description) = arguments["description"].as_str() {
+            saved = saved.with_description(description.to_string());
+        }
+        saved.validate().map_err(|e| anyhow!(e))?;
+        let saved = repository.save_query(saved)?;
+
+        Ok(json!({
+            "success": true,
+            "saved_query": saved_query_entry(&saved)
+        }))
+    }
+
+    /// List the saved queries of an index
+    fn list_saved_queries(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let page = page_arguments(arguments, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let queries = page.slice(repository.list_saved_queries(&index.id)?).map_err(|e| anyhow!(e))?;
+        Ok(json!({
+            "index_name": index_name,
+            "saved_queries": queries.items.iter().map(saved_query_entry).collect::<Vec<_>>(),
+            "total_count": queries.total_count,
+            "next_cursor": queries.next_cursor
+        }))
+    }
+
+    /// Run a saved query and return the matching symbols
+    fn run_saved_query(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let name = required_str(arguments, "name")?;
+        let page = page_arguments(arguments, DEFAULT_SAVED_QUERY_LIMIT, MAX_REFERENCE_PAGE_SIZE);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let saved = repository
+            .get_saved_query(&index.id, name)?
+            .ok_or_else(|| anyhow!("Saved query not found: {}", name))?;
+
+        let query = saved.to_element_query().map_err(|e| anyhow!(e))?;
+        let symbols = repository.query_code_elements_page(&query, &page)?.map(|element| reference_entry(&element));
+
+        Ok(json!({
+            "index_name": index_name,
+            "name": saved.name,
+            "query": saved.query,
+            "symbols": symbols.items,
+            "total_count": symbols.total_count,
+            "truncated": symbols.has_more(),
+            "next_cursor": symbols.next_cursor
+        }))
+    }
+
+    /// Delete a saved query by name
+    fn delete_saved_query(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let name = required_str(arguments, "name")?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        if !repository.delete_saved_query(&index.id, name)? {
+            return Err(anyhow!("Saved query not found: {}", name));
+        }
+
+        Ok(json!({
+            "success": true,
+            "name": name
+        }))
+    }
+
+    /// Start or stop watching a saved query
+    ///
+    /// A new watch records the query's current result set as its baseline
+    /// right away, so the next check reports changes relative to now.
+    fn watch_query(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let name = required_str(arguments, "name")?;
+        let watch = arguments["watch"].as_bool().unwrap_or(true);
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        if !repository.set_query_watched(&index.id, name, watch)? {
+            return Err(anyhow!("Saved query not found: {}", name));
+        }
+        if watch {
+            let saved = repository
+                .get_saved_query(&index.id, name)?
+                .ok_or_else(|| anyhow!("Saved query not found: {}", name))?;
+            WatchEvaluator::new(&repository).evaluate(&saved)?;
+        }
+
+        Ok(json!({
+            "success": true,
+            "name": name,
+            "watched": watch
+        }))
+    }
+
+    /// Re-run the watched queries of an index and report changed result sets
+    ///
+    /// Each check moves the baselines forward, so a change is reported once.
+    /// The server also sends every reported change as a log notification.
+    fn check_watches(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+
+        let watched_count = repository.list_watched_queries(&index.id)?.len();
+        let changes = WatchEvaluator::new(&repository).run(&index.id)?;
+
+        Ok(json!({
+            "index_name": index_name,
+            "watched_count": watched_count,
+            "watch_changes": changes
+        }))
+    }
+
+    /// Transitive callers and callees of a symbol
+    ///
+    /// Follows Calls relationships (and callback references, unless
+    /// `include_callbacks` is false) breadth-first from `symbol_id`, in one or
+    /// both directions, so the whole neighbourhood comes back in one call.
+    fn get_call_graph(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let symbol_id = arguments["symbol_id"].as_i64().ok_or_else(|| anyhow!("Missing required parameter: symbol_id"))?;
+        let directions = match arguments["direction"].as_str().unwrap_or("both") {
+            "callers" => vec![CallDirection::Callers],
+            "callees" => vec![CallDirection::Callees],
+            "both" => vec![CallDirection::Callers, CallDirection::Callees],
+            other => return Err(anyhow!("Unknown direction: {} (expected callers, callees or both)", other)),
+        };
+        let max_depth = arguments["max_depth"]
+            .as_u64()
+            .map_or(DEFAULT_MAX_DEPTH, |depth| depth.min(MAX_CALL_GRAPH_DEPTH as u64) as u32);
+        let max_nodes = arguments["max_nodes"]
+            .as_u64()
+            .map_or(MAX_CALL_GRAPH_NODES, |nodes| (nodes as usize).min(MAX_CALL_GRAPH_NODES));
+        let options = CallGraphOptions::default()
+            .with_max_depth(max_depth)
+            .with_max_nodes(max_nodes)
+            .with_resolve_virtual(arguments["resolve_virtual"].as_bool().unwrap_or(false))
+            .with_callbacks(arguments["include_callbacks"].as_bool().unwrap_or(true));
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let symbol = repository
+            .get_code_element(symbol_id)?
+            .filter(|element| element.index_id == index.id)
+            .ok_or_else(|| anyhow!("Symbol {} not found in index {}", symbol_id, index_name))?;
+
+        let mut response = json!({
+            "index_name": index_name,
+            "symbol": reference_entry(&symbol),
+            "max_depth": max_depth
+        });
+        for direction in directions {
+            let graph = CallGraphWalker::new(&repository, options.with_direction(direction)).walk(&[symbol_id])?;
+            let key = match direction {
+                CallDirection::Callers => "callers",
+                CallDirection::Callees => "callees",
+            };
+            response[key] = call_graph_entry(&graph, symbol_id);
+        }
+        response["query_time_ms"] = json!(started.elapsed().as_millis() as u64);
+
+        Ok(response)
+    }
+
+    /// Base-class chain and derived-class tree of a class
+    ///
+    /// Follows Inherits relationships both ways from the class and, unless
+    /// disabled, lists the overrides between methods of the classes reached,
+    /// which is what virtual dispatch through the class can land on.
+    fn get_type_hierarchy(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let symbol_id = arguments["symbol_id"].as_i64().ok_or_else(|| anyhow!("Missing required parameter: symbol_id"))?;
+        let directions = match arguments["direction"].as_str().unwrap_or("both") {
+            "bases" => vec![HierarchyDirection::Bases],
+            "derived" => vec![HierarchyDirection::Derived],
+            "both" => vec![HierarchyDirection::Bases, HierarchyDirection::Derived],
+            other => return Err(anyhow!("Unknown direction: {} (expected bases, derived or both)", other)),
+        };
+        let max_depth = arguments["max_depth"]
+            .as_u64()
+            .map_or(type_hierarchy::DEFAULT_MAX_DEPTH, |depth| depth.min(MAX_HIERARCHY_DEPTH as u64) as u32);
+        let max_nodes = arguments["max_nodes"]
+            .as_u64()
+            .map_or(MAX_HIERARCHY_NODES, |nodes| (nodes as usize).min(MAX_HIERARCHY_NODES));
+        let include_overrides = arguments["include_overrides"].as_bool().unwrap_or(true);
+        let options = HierarchyOptions::default().with_max_depth(max_depth).with_max_nodes(max_nodes);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let symbol = repository
+            .get_code_element(symbol_id)?
+            .filter(|element| element.index_id == index.id)
+            .ok_or_else(|| anyhow!("Symbol {} not found in index {}", symbol_id, index_name))?;
+        if !CLASS_TYPES.contains(&symbol.symbol_type) {
+            return Err(anyhow!("Symbol {} is a {}, not a class", symbol_id, symbol.symbol_type.as_str()));
+        }
+
+        let mut response = json!({
+            "index_name": index_name,
+            "symbol": reference_entry(&symbol),
+            "max_depth": max_depth
+        });
+        let mut hierarchies = Vec::new();
+        for direction in directions {
+            let walker = TypeHierarchyWalker::new(&repository, options.with_direction(direction));
+            let hierarchy = walker.walk(symbol_id)?;
+            let key = match direction {
+                HierarchyDirection::Bases => "bases",
+                HierarchyDirection::Derived => "derived",
+            };
+            response[key] = hierarchy_entry(&hierarchy);
+            hierarchies.push(hierarchy);
+        }
+
+        if include_overrides {
+            let mut seen = BTreeSet::new();
+            let classes: Vec<&CodeElement> = hierarchies
+                .iter()
+                .flat_map(|hierarchy| &hierarchy.nodes)
+                .map(|node| &node.element)
+                .filter(|element| seen.insert(element.id))
+                .collect();
+            let overrides = TypeHierarchyWalker::new(&repository, options).overrides(&classes)?;
+            response["overrides"] = overrides
+                .iter()
+                .map(|edge| json!({"method": reference_entry(&edge.method), "overrides": reference_entry(&edge.overridden)}))
+                .collect();
+        }
+        response["query_time_ms"] = json!(started.elapsed().as_millis() as u64);
+
+        Ok(response)
+    }
+
+    /// Files a file includes and files including it, directly and transitively
+    fn get_include_graph(&self, arguments: &Value) -> Result<Value> {
+        let started = Instant::now();
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+        let directions = match arguments["direction"].as_str().unwrap_or("both") {
+            "includes" => vec![IncludeDirection::Includes],
+            "included_by" => vec![IncludeDirection::IncludedBy],
+            "both" => vec![IncludeDirection::Includes, IncludeDirection::IncludedBy],
+            other => return Err(anyhow!("Unknown direction: {} (expected includes, included_by or both)", other)),
+        };
+        let max_depth = arguments["max_depth"]
+            .as_u64()
+            .map_or(include_graph::DEFAULT_MAX_DEPTH, |depth| depth.min(MAX_INCLUDE_DEPTH as u64) as u32);
+        let max_nodes = arguments["max_nodes"]
+            .as_u64()
+            .map_or(include_graph::DEFAULT_MAX_NODES, |nodes| (nodes as usize).min(MAX_INCLUDE_GRAPH_NODES));
+        let options = IncludeGraphOptions::default().with_max_depth(max_depth).with_max_nodes(max_nodes);
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let file_path = stored_path(&index.base_path, file_path);
+        if repository.get_file_metadata_by_path(&index.id, &file_path)?.is_none() {
+            return Err(anyhow!("File not indexed: {}", file_path));
+        }
+
+        let mut response = json!({
+            "index_name": index_name,
+            "file_path": file_path,
+            "max_depth": max_depth
+        });
+        for direction in directions {
+            let graph = IncludeGraphWalker::new(&repository, options.with_direction(direction)).walk(&index.id, &file_path)?;
+            let key = match direction {
+                IncludeDirection::Includes => "includes",
+                IncludeDirection::IncludedBy => "included_by",
+            };
+            response[key] = include_graph_entry(&graph, direction);
+        }
+        response["query_time_ms"] = json!(started.elapsed().as_millis() as u64);
+
+        Ok(response)
+    }
+
+    /// Re-index one file with all of its symbols in full detail
+    ///
+    /// Undoes a tiered detail policy for the files an agent is working in,
+    /// without paying for full detail across the whole index. A file that is
+    /// already stored in full and unchanged on disk is left alone.
+    async fn promote_file_detail(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+        self.ensure_writable()?;
+
+        let repository = self.repository_of(index_name)?;
+        let (index, mut report, previous) = {
+            let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+            let index = repository
+                .get_code_index_by_name(index_name)?
+                .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+            let stored = stored_path(&index.base_path, file_path);
+            let metadata = repository
+                .get_file_metadata_by_path(&index.id, &stored)?
+                .ok_or_else(|| anyhow!("File not indexed: {}", stored))?;
+            let report = check_file(&repository, &index, &stored)?;
+            (index, report, metadata.detail)
+        };
+
+        let mut response = json!({
+            "index_name": index_name,
+            "file_path": report.file_path,
+            "previous_detail": previous.as_str(),
+            "detail": FileDetail::Full.as_str()
+        });
+        match report.status {
+            Freshness::Missing => return Err(anyhow!("File no longer exists: {}", report.file_path)),
+            Freshness::Fresh if previous == FileDetail::Full => {
+                response["promoted"] = json!(false);
+                return Ok(response);
+            }
+            _ => {}
+        }
+
+        // Parse without holding the lock, as inline re-indexing does
+        let settings = self.index_settings(&repository, &index)?;
+        let reparsed = extract_file(&index, &settings, &report.file_path, &DetailPolicy::full()).await?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        store_reindexed(&repository, &index, &mut report, reparsed)?;
+        response["promoted"] = json!(true);
+        response["symbols"] = json!(report.reindexed_symbols);
+        Ok(response)
+    }
+
+    /// Report the indexing depth assigned to each directory
+    ///
+    /// Lists the stored assignment next to what a plan made now would assign,
+    /// so pending promotions and demotions are visible before the next
+    /// re-plan. Works with adaptive depth off, using the default planner.
+    fn get_index_depths(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let planner = self.adaptive_depth.unwrap_or_default();
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let assigned: HashMap<String, _> = repository
+            .list_directory_depths(&index.id)?
+            .into_iter()
+            .map(|depth| (depth.directory.clone(), depth))
+            .collect();
+        let planned = planner.plan_index(&repository, &index.id, chrono::Utc::now())?;
+
+        let mut counts: BTreeMap<&str, u64> = [IndexDepth::Deep, IndexDepth::Standard, IndexDepth::Shallow]
+            .iter()
+            .map(|depth| (depth.as_str(), 0))
+            .collect();
+        let mut pending = 0;
+        let directories: Vec<Value> = planned
+            .iter()
+            .map(|plan| {
+                let current = assigned.get(&plan.directory);
+                if let Some(current) = current {
+                    *counts.entry(current.depth.as_str()).or_default() += 1;
+                }
+                if current.map(|current| current.depth) != Some(plan.depth) {
+                    pending += 1;
+                }
+                json!({
+                    "directory": plan.directory,
+                    "depth": current.map(|current| current.depth.as_str()),
+                    "assigned_at": current.map(|current| current.assigned_at.to_rfc3339()),
+                    "hits": plan.hits,
+                    "planned_depth": plan.depth.as_str()
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "index_name": index_name,
+            "adaptive": self.adaptive_depth.is_some(),
+            "promote_hits": planner.promote_hits(),
+            "window_days": planner.window_days(),
+            "counts": counts,
+            "pending_changes": pending,
+            "directories": directories
+        }))
+    }
+
+    /// Explain undefined symbol errors from linker output
+    ///
+    /// For each unresolved symbol, looks up its declarations and definitions
+    /// in the index, attaches the build target owning each site and derives
+    /// hints about the likely cause.
+    fn explain_linker_error(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let error_message = required_str(arguments, "error_message")?;
+
+        let unresolved = diagnostics::parse_linker_errors(error_message);
+        if unresolved.is_empty() {
+            return Err(anyhow!("No undefined symbol errors found in error_message"));
+        }
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let base_path = Path::new(&index.base_path);
+
+        let mut symbols = Vec::with_capacity(unresolved.len());
+        for symbol in &unresolved {
+            let candidates = repository.find_code_elements_by_name(&index.id, &symbol.name)?;
+            let (matching, other_scopes): (Vec<CodeElement>, Vec<CodeElement>) = candidates
+                .into_iter()
+                .partition(|element| scope_matches(symbol.scope.as_deref(), element));
+            let (declarations, definitions): (Vec<CodeElement>, Vec<CodeElement>) =
+                matching.into_iter().partition(|element| element.is_declaration);
+
+            let hints = linker_hints(symbol, &declarations, &definitions, &other_scopes, base_path);
+            let site = |element: &CodeElement| symbol_site(element, base_path);
+
+            symbols.push(json!({
+                "symbol": symbol,
+                "declarations": declarations.iter().map(site).collect::<Vec<_>>(),
+                "definitions": definitions.iter().map(site).collect::<Vec<_>>(),
+                "other_scope_candidates": other_scopes.iter().map(site).collect::<Vec<_>>(),
+                "hints": hints
+            }));
+        }
+
+        Ok(json!({
+            "index_name": index_name,
+            "symbols": symbols,
+            "total_count": unresolved.len()
+        }))
+    }
+
+    /// Gather index context for compiler diagnostics
+    ///
+    /// For each error or warning, packages the symbol enclosing the reported
+    /// location together with the definitions of the types and the overloads
+    /// of the functions the message mentions.
+    fn get_compiler_error_context(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let error_message = required_str(arguments, "error_message")?;
+        let max_items = arguments["max_items"]
+            .as_u64()
+            .map_or(DEFAULT_MAX_ITEMS, |n| n.min(MAX_ITEMS as u64) as usize)
+            .max(1);
+
+        let parsed = diagnostics::parse_compiler_diagnostics(error_message);
+        if parsed.is_empty() {
+            return Err(anyhow!("No compiler diagnostics found in error_message"));
+        }
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let base_path = Path::new(&index.base_path);
+
+        let mut results = Vec::with_capacity(parsed.len());
+        for diagnostic in &parsed {
+            let file_path = diagnostics::relative_to_base(base_path, &diagnostic.file_path);
+            // Diagnostics in a skipped vendored copy are answered from the indexed copy
+            let file_path = repository.resolve_path_alias(&index.id, &file_path)?;
+            let file_elements = repository.list_code_elements_by_file(&index.id, &file_path)?;
+            let at_location: Vec<CodeElement> = enclosing_element(&file_elements, diagnostic.line_number)
+                .into_iter()
+                .cloned()
+                .collect();
+
+            let mut types = Vec::new();
+            let mut overloads = Vec::new();
+            let mut related = Vec::new();
+            for symbol in &diagnostic.symbols {
+                // An unqualified name in a message may refer to any scope
+                let candidates = repository
+                    .find_code_elements_by_name(&index.id, &symbol.name)?
+                    .into_iter()
+                    .filter(|element| symbol.scope.is_none() || scope_matches(symbol.scope.as_deref(), element));
+
+                let mut symbol_types: Vec<CodeElement> = Vec::new();
+                for element in candidates {
+                    match element.symbol_type {
+                        SymbolType::Class | SymbolType::Struct | SymbolType::Union
+                        | SymbolType::Enum | SymbolType::Typedef => symbol_types.push(element),
+                        SymbolType::Function | SymbolType::Constructor
+                        | SymbolType::Destructor | SymbolType::Operator => overloads.push(element),
+                        _ => related.push(element),
+                    }
+                }
+                // Forward declarations add little once the definition is known
+                if symbol_types.iter().any(|element| !element.is_declaration) {
+                    symbol_types.retain(|element| !element.is_declaration);
+                }
+                types.extend(symbol_types);
+            }
+
+            let mut builder = ContextPackBuilder::new(diagnostic.message.clone()).with_max_items(max_items);
+            builder
+                .add_section("location", "Symbol enclosing the reported location", &at_location)
+                .add_section("types", "Definitions of types named in the diagnostic", &types)
+                .add_section("overloads", "Declarations and overloads of functions named in the diagnostic", &overloads)
+                .add_section("related", "Other symbols named in the diagnostic", &related);
+
+            results.push(json!({
+                "diagnostic": diagnostic_summary(diagnostic, &file_path),
+                "context": builder.build()
+            }));
+        }
+
+        Ok(json!({
+            "index_name": index_name,
+            "diagnostics": results,
+            "total_count": parsed.len()
+        }))
+    }
+
+    /// Map the hunks of a unified diff to the symbols they change
+    ///
+    /// Each hunk lists the symbols enclosing its changed lines with their
+    /// popularity, the references to them from test files (line coverage is
+    /// not ingested, so tests are found through the index) and the file's
+    /// owners from the codebase's CODEOWNERS file.
+    fn annotate_diff(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let diff = required_str(arguments, "diff")?;
+        let hunks = parse_unified_diff(diff);
+        if hunks.is_empty() {
+            return Err(anyhow!("No hunks found in diff"));
+        }
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let base_path = Path::new(&index.base_path);
+        let owners = CodeOwners::load(base_path);
+
+        let mut files: HashMap<String, Vec<CodeElement>> = HashMap::new();
+        let mut symbols: HashMap<i64, Value> = HashMap::new();
+        let mut results = Vec::with_capacity(hunks.len());
+        for hunk in &hunks {
+            let file_path = diagnostics::relative_to_base(base_path, &hunk.file_path);
+            let file_path = repository.resolve_path_alias(&index.id, &file_path)?;
+            if !files.contains_key(&file_path) {
+                let elements = repository.list_code_elements_by_file(&index.id, &file_path)?;
+                files.insert(file_path.clone(), elements);
+            }
+            let elements = &files[&file_path];
+
+            let mut enclosing: Vec<&CodeElement> = Vec::new();
+            for line in hunk.touched_lines() {
+                if let Some(element) = enclosing_element(elements, line) {
+                    if !enclosing.iter().any(|seen| seen.id == element.id) {
+                        enclosing.push(element);
+                    }
+                }
+            }
+
+            let mut hunk_symbols = Vec::with_capacity(enclosing.len());
+            for element in enclosing {
+                let Some(id) = element.id else { continue };
+                if let std::collections::hash_map::Entry::Vacant(entry) = symbols.entry(id) {
+                    entry.insert(review_entry(&repository, element)?);
+                }
+                hunk_symbols.push(symbols[&id].clone());
+            }
+
+            results.push(json!({
+                "file_path": file_path,
+                "old_start": hunk.old_start,
+                "old_lines": hunk.old_lines,
+                "new_start": hunk.new_start,
+                "new_lines": hunk.new_lines,
+                "added_lines": hunk.added_lines.len(),
+                "removed_lines": hunk.removed_lines,
+                "section": hunk.section,
+                "indexed": !elements.is_empty(),
+                "symbols": hunk_symbols,
+                "owners": owners.as_ref().map(|owners| owners.owners_of(&file_path))
+            }));
+        }
+
+        Ok(json!({
+            "index_name": index_name,
+            "hunks": results,
+            "hunk_count": hunks.len(),
+            "file_count": files.len(),
+            "symbol_count": symbols.len(),
+            "has_codeowners": owners.is_some()
+        }))
+    }
+
+    /// Heuristic risk score of a diff from the fan-in, test coverage, size and ownership of what it touches
+    ///
+    /// With `threshold` the result says whether the score reaches it, so a
+    /// CI job can flag the change.
+    fn assess_diff_risk(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let diff = required_str(arguments, "diff")?;
+        let threshold = arguments["threshold"].as_u64().unwrap_or(HIGH_RISK_SCORE as u64);
+        let hunks = parse_unified_diff(diff);
+        if hunks.is_empty() {
+            return Err(anyhow!("No hunks found in diff"));
+        }
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let report = RiskReport::analyze(&repository, &index, &hunks)?;
+
+        let mut result = serde_json::to_value(&report)?;
+        result["index_name"] = json!(index_name);
+        result["threshold"] = json!(threshold);
+        result["flagged"] = json!(u64::from(report.score) >= threshold);
+        Ok(result)
+    }
+
+    /// Resolve the symbol at a source position to its definition and declarations
+    ///
+    /// The position is matched against elements declared on that line first,
+    /// then against references recorded there, using the identifier under the
+    /// column to pick among several symbols on one line. Each match is paired
+    /// with its other declarations and definitions by definition_hash or
+    /// signature, so overloads resolve separately. A call whose overload
+    /// can't be told apart returns every candidate.
+    fn resolve_symbol(&self, arguments: &Value) -> Result<Value> {
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+        let line = arguments["line"].as_u64().filter(|line| *line > 0).ok_or_else(|| anyhow!("Missing required parameter: line"))?;
+        let column = arguments["column"].as_u64().filter(|column| *column > 0).ok_or_else(|| anyhow!("Missing required parameter: column"))?;
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let file_path = stored_path(&index.base_path, file_path);
+
+        // An open document answers from its unsaved text; without any source
+        // (moved, or not on this machine) the line alone decides
+        let open = self.open_document(index.id, &file_path)?;
+        let source = match &open {
+            Some((_, text, _)) => Some(text.clone()),
+            None => read_source(Path::new(&index.base_path), &file_path).ok(),
+        };
+        let line_text = source.as_deref().and_then(|source| source.lines().nth(line as usize - 1));
+        let identifier = line_text.and_then(|text| identifier_at(text, column as u32)).map(str::to_string);
+        let on_identifier = line_text.is_none() || identifier.is_some();
+        let names_match = |element: &CodeElement| match identifier.as_deref() {
+            Some(identifier) => element.symbol_name == identifier,
+            None => true,
+        };
+
+        // Declared on this line: the element closest before the column
+        let declared = match open.as_ref().and_then(|(_, _, symbols)| symbols.as_ref()) {
+            Some(symbols) => {
+                let mut declared: Vec<CodeElement> = symbols.iter().filter(|element| u64::from(element.line_number) == line).cloned().collect();
+                declared.sort_by_key(|element| element.column_number);
+                declared
+            }
+            None => repository.query_code_elements(
+                &CodeElementQuery::new()
+                    .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+                    .filter(Filter::eq(ElementColumn::FilePath, file_path.clone()))
+                    .filter(Filter::eq(ElementColumn::LineNumber, line as i64))
+                    .order_by_asc(ElementColumn::ColumnNumber),
+            )?,
+        };
+        let declared = declared
+            .iter()
+            .filter(|element| on_identifier && names_match(element))
+            .rfind(|element| identifier.is_some() || u64::from(element.column_number) <= column)
+            .cloned();
+
+        let (resolved_from, targets) = match declared {
+            Some(element) => ("declaration", vec![element]),
+            None if !on_identifier => ("reference", Vec::new()),
+            None => {
+                let relationships = repository.query_relationships(
+                    &SymbolRelationshipQuery::new()
+                        .filter(Filter::eq(RelationshipColumn::FilePath, file_path.clone()))
+                        .filter(Filter::eq(RelationshipColumn::LineNumber, line as i64))
+                        .order_by_asc(RelationshipColumn::Id),
+                )?;
+                let mut seen = BTreeSet::new();
+                let mut targets = Vec::new();
+                for relationship in relationships {
+                    if !seen.insert(relationship.to_symbol_id) {
+                        continue;
+                    }
+                    match repository.get_code_element(relationship.to_symbol_id)? {
+                        Some(element) if element.index_id == index.id && names_match(&element) => targets.push(element),
+                        _ => {}
+                    }
+                }
+                match identifier.as_deref() {
+                    // Unsaved text has no recorded references; fall back to the symbols of that name
+                    Some(identifier) if targets.is_empty() && open.is_some() => {
+                        let named = repository.query_code_elements(
+                            &CodeElementQuery::new()
+                                .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+                                .filter(Filter::eq(ElementColumn::SymbolName, identifier.to_string()))
+                                .order_by_asc(ElementColumn::Id)
+                                .limit(MAX_RESOLVE_CANDIDATES),
+                        )?;
+                        ("name", named)
+                    }
+                    _ => ("reference", targets),
+                }
+            }
+        };
+
+        let mut symbols = Vec::with_capacity(targets.len());
+        let mut resolved = BTreeSet::new();
+        for target in &targets {
+            let resolution = declarations_of(&repository, target)?;
+            // Two references to one overload resolve to the same symbol
+            let key: Vec<Option<i64>> = resolution.definitions.iter().chain(&resolution.declarations).map(|element| element.id).collect();
+            if !resolved.insert(key) {
+                continue;
+            }
+            symbols.push(json!({
+                "name": target.symbol_name,
+                "qualified_name": target.fully_qualified_name(),
+                "type": target.symbol_type.as_str(),
+                "signature": target.signature,
+                "definitions": resolution.definitions.iter().map(reference_entry).collect::<Vec<_>>(),
+                "declarations": resolution.declarations.iter().map(reference_entry).collect::<Vec<_>>()
+            }));
+        }
+
+        Ok(json!({
+            "index_name": index_name,
+            "file_path": file_path,
+            "line": line,
+            "column": column,
+            "identifier": identifier,
+            "document_version": open.as_ref().map(|(version, _, _)| version),
+            "resolved_from": if symbols.is_empty() { None } else { Some(resolved_from) },
+            "ambiguous": symbols.len() > 1,
+            "symbols": symbols
+        }))
+    }
+
+    /// Open a document in the overlay, as an editor's didOpen
+    ///
+    /// Until it is closed, positional queries on the document read the text
+    /// given here and through did_change_document instead of the file on disk.
+    fn did_open_document(&self, arguments: &Value) -> Result<Value> {
+        let (index, file_path) = self.document_target(arguments)?;
+        let text = required_str(arguments, "text")?;
+        let version = arguments["version"].as_i64().unwrap_or(0);
+
+        let symbols = extract_document_symbols(&index, &file_path, text);
+        let mut documents = self.documents.lock().map_err(|_| anyhow!("Document overlay lock poisoned"))?;
+        let document = documents.open(index.id, &file_path, version, text.to_string());
+        document.symbols = symbols;
+        Ok(document_entry(&file_path, document.version, document.symbols.as_deref()))
+    }
+
+    /// Apply edits to an open document, as an editor's didChange
+    ///
+    /// `content_changes` are LSP content change events: a range with
+    /// zero-based lines and UTF-16 characters, or no range to replace the
+    /// whole text. A version not newer than the open one is rejected.
+    fn did_change_document(&self, arguments: &Value) -> Result<Value> {
+        let (index, file_path) = self.document_target(arguments)?;
+        let version = arguments["version"].as_i64().ok_or_else(|| anyhow!("Missing required parameter: version"))?;
+        let changes: Vec<ContentChange> = serde_json::from_value(arguments["content_changes"].clone())
+            .map_err(|e| anyhow!("Invalid content_changes: {}", e))?;
+
+        let text = {
+            let mut documents = self.documents.lock().map_err(|_| anyhow!("Document overlay lock poisoned"))?;
+            documents.change(index.id, &file_path, version, &changes).map_err(|e| anyhow!(e))?.text.clone()
+        };
+        // Parsed without the lock; a newer change arriving meanwhile keeps its own symbols
+        let symbols = extract_document_symbols(&index, &file_path, &text);
+        let symbol_count = symbols.as_ref().map(Vec::len);
+        let mut documents = self.documents.lock().map_err(|_| anyhow!("Document overlay lock poisoned"))?;
+        if let Some(document) = documents.get_mut(index.id, &file_path).filter(|document| document.version == version) {
+            document.symbols = symbols;
+        }
+        Ok(json!({
+            "file_path": file_path,
+            "version": version,
+            "symbol_count": symbol_count
+        }))
+    }
+
+    /// Drop a document from the overlay, as an editor's didClose
+    fn did_close_document(&self, arguments: &Value) -> Result<Value> {
+        let (index, file_path) = self.document_target(arguments)?;
+        let mut documents = self.documents.lock().map_err(|_| anyhow!("Document overlay lock poisoned"))?;
+        Ok(json!({
+            "file_path": file_path,
+            "closed": documents.close(index.id, &file_path)
+        }))
+    }
+
+    /// Outline of a file for an editor: from its unsaved text if open, else from the index
+    fn get_document_symbols(&self, arguments: &Value) -> Result<Value> {
+        let (index, file_path) = self.document_target(arguments)?;
+        let page = page_arguments(arguments, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT);
+        if let Some((version, _, Some(symbols))) = self.open_document(index.id, &file_path)? {
+            let symbols = page.slice(symbols).map_err(|e| anyhow!(e))?;
+            let mut entry = document_entry(&file_path, version, Some(&symbols.items));
+            entry["source"] = json!("overlay");
+            entry["total_count"] = json!(symbols.total_count);
+            entry["next_cursor"] = json!(symbols.next_cursor);
+            return Ok(entry);
+        }
+
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let elements = repository.list_code_elements_by_file_page(&index.id, &file_path, &page)?;
+        Ok(json!({
+            "file_path": file_path,
+            "source": "index",
+            "version": null,
+            "symbols": elements.items.iter().map(reference_entry).collect::<Vec<_>>(),
+            "symbol_count": elements.items.len(),
+            "total_count": elements.total_count,
+            "next_cursor": elements.next_cursor
+        }))
+    }
+
+    /// Index and stored path a document tool names
+    fn document_target(&self, arguments: &Value) -> Result<(CodeIndex, String)> {
+        let index_name = required_str(arguments, "index_name")?;
+        let file_path = required_str(arguments, "file_path")?;
+        let repository = self.repository_for(arguments)?;
+        let repository = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?;
+        let index = repository
+            .get_code_index_by_name(index_name)?
+            .ok_or_else(|| anyhow!("Index not found: {}", index_name))?;
+        let file_path = stored_path(&index.base_path, file_path);
+        Ok((index, file_path))
+    }
+
+    /// Version, text and symbols of an open document, copied out of the overlay
+    fn open_document(&self, index_id: Uuid, file_path: &str) -> Result<Option<OpenDocument>> {
+        let documents = self.documents.lock().map_err(|_| anyhow!("Document overlay lock poisoned"))?;
+        Ok(documents
+            .get(index_id, file_path)
+            .map(|document| (document.version, document.text.clone(), document.symbols.clone())))
+    }
+
+    /// Start a query snapshot
+    ///
+    /// Read tools given the returned `snapshot_id` answer from the database
+    /// as it was at this call, so a sequence of calls (outline, details,
+    /// references) stays consistent while the watcher or another writer
+    /// commits updates in between. Snapshots end with end_query_snapshot or
+    /// after DEFAULT_SNAPSHOT_TTL without use.
+    fn begin_query_snapshot(&self) -> Result<Value> {
+        let manager = self
+            .database
+            .as_ref()
+            .ok_or_else(|| anyhow!("Query snapshots are not available for this storage"))?;
+        let repository = Repository::new(manager.connect_snapshot()?);
+        let snapshot_id = self
+            .snapshots
+            .lock()
+            .map_err(|_| anyhow!("Snapshot store lock poisoned"))?
+            .insert(Arc::new(Mutex::new(repository)));
+
+        Ok(json!({
+            "snapshot_id": snapshot_id,
+            "started_at": chrono::Utc::now().to_rfc3339(),
+            "expires_after_seconds": DEFAULT_SNAPSHOT_TTL.as_secs()
+        }))
+    }
+
+    /// Closes query snapshots unused for DEFAULT_SNAPSHOT_TTL, returning how many
+    ///
+    /// Expired snapshots are otherwise only closed by the next snapshot
+    /// call, holding back WAL checkpoints on an idle server until then.
+    pub fn expire_query_snapshots(&self) -> usize {
+        match self.snapshots.lock() {
+            Ok(mut snapshots) => snapshots.expire(),
+            Err(_) => 0,
+        }
+    }
+
+    /// End a query snapshot, releasing its read transaction
+    fn end_query_snapshot(&self, arguments: &Value) -> Result<Value> {
+        let snapshot_id = required_str(arguments, "snapshot_id")?;
+        // Dropping the connection ends its read transaction
+        let ended = self
+            .snapshots
+            .lock()
+            .map_err(|_| anyhow!("Snapshot store lock poisoned"))?
+            .take(snapshot_id)
+            .is_some();
+
+        Ok(json!({
+            "snapshot_id": snapshot_id,
+            "ended": ended
+        }))
+    }
+
+    /// Repository a read tool answers from: its query snapshot if one is named, else a pooled reader
+    fn repository_for(&self, arguments: &Value) -> Result<Arc<Mutex<Repository>>> {
+        self.repository_at(arguments["index_name"].as_str(), arguments["snapshot_id"].as_str())
+    }
+
+    /// Repository reads of `index_name` go to: `snapshot_id`'s if given, else the index's database
+    fn repository_at(&self, index_name: Option<&str>, snapshot_id: Option<&str>) -> Result<Arc<Mutex<Repository>>> {
+        let snapshot_id = match snapshot_id {
+            Some(snapshot_id) => snapshot_id,
+            None => {
+                let registered = match index_name {
+                    Some(index_name) => self.registered(index_name)?,
+                    None => None,
+                };
+                return registered.map_or_else(|| self.reader(), Ok);
+            }
+        };
+        self.snapshots
+            .lock()
+            .map_err(|_| anyhow!("Snapshot store lock poisoned"))?
+            .get(snapshot_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown or expired query snapshot: {}", snapshot_id))
+    }
+
+    /// Repository holding `index_name`: its own database if registered, else the default one
+    fn repository_of(&self, index_name: &str) -> Result<Arc<Mutex<Repository>>> {
+        match (self.registered(index_name)?, &self.call_snapshot) {
+            (Some(repository), _) => Ok(repository),
+            (None, Some(snapshot)) => Ok(snapshot.clone()),
+            (None, None) => self.repository().cloned(),
+        }
+    }
+
+    /// Repository of `index_name` if it is registered to a database of its own
+    fn registered(&self, index_name: &str) -> Result<Option<Arc<Mutex<Repository>>>> {
+        self.registry
+            .lock()
+            .map_err(|_| anyhow!("Repository registry lock poisoned"))?
+            .get(index_name)
+    }
+
+    /// Default database repository for a query: on a pooled reader if there are any
+    ///
+    /// It logs, times and audits like the default repository, and the
+    /// reader goes back to the pool once the call drops it.
+    fn reader(&self) -> Result<Arc<Mutex<Repository>>> {
+        if let Some(snapshot) = &self.call_snapshot {
+            return Ok(snapshot.clone());
+        }
+        match &self.pool {
+            Some(pool) if pool.max_readers() > 0 => {
+                // Checked out first, so a wait for a free reader doesn't hold the default repository
+                let connection = pool.reader()?;
+                let repository = self.repository()?.lock().map_err(|_| anyhow!("Repository lock poisoned"))?.on_connection(connection);
+                Ok(Arc::new(Mutex::new(repository)))
+            }
+            _ => self.repository().cloned(),
+        }
+    }
+
+    /// Returns true if a default repository is attached or any index is registered
+    fn has_storage(&self) -> bool {
+        self.repository.is_some() || self.registry.lock().is_ok_and(|registry| !registry.is_empty())
+    }
+
+    /// Indices list_indices reports, with the repository holding each, sorted by name
+    ///
+    /// Outside a snapshot these are the default database's indices plus each
+    /// index registered to a database of its own.
+    fn listed_indices(&self, arguments: &Value, tag_filter: &BTreeMap<String, String>) -> Result<Vec<(CodeIndex, Arc<Mutex<Repository>>)>> {
+        let mut registered = Vec::new();
+        if arguments["snapshot_id"].is_null() {
+            let mut registry = self.registry.lock().map_err(|_| anyhow!("Repository registry lock poisoned"))?;
+            let names: Vec<String> = registry.index_names().into_iter().map(str::to_string).collect();
+            for name in names {
+                if let Some(repository) = registry.get(&name)? {
+                    registered.push((name, repository));
+                }
+            }
+        }
+        let default = match self.repository_for(arguments) {
+            Ok(repository) => Some(repository),
+            Err(_) if !registered.is_empty() => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut indices = Vec::new();
+        if let Some(repository) = default {
+            let found = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?.list_code_indices_by_tags(tag_filter)?;
+            indices.extend(
+                found
+                    .into_iter()
+                    .filter(|index| !registered.iter().any(|(name, _)| *name == index.name))
+                    .map(|index| (index, Arc::clone(&repository))),
+            );
+        }
+        for (name, repository) in registered {
+            let found = repository.lock().map_err(|_| anyhow!("Repository lock poisoned"))?.list_code_indices_by_tags(tag_filter)?;
+            indices.extend(found.into_iter().filter(|index| index.name == name).map(|index| (index, Arc::clone(&repository))));
+        }
+        indices.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        Ok(indices)
+    }
+
+    /// Returns the repository or an error when the server runs without storage
+    fn repository(&self) -> Result<&Arc<Mutex<Repository>>> {
+        self.repository
+            .as_ref()
+            .ok_or_else(|| anyhow!("Index storage is not available"))
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        let reason = match self.read_only {
+            None => return Ok(()),
+            Some(ReadOnlyMode::Degraded) => "the database failed its integrity check and is served read-only; run the CLI to recover it",
+            Some(ReadOnlyMode::Shared) => "this server was started with --read-only; run the tool on the process that writes the index",
+        };
+        Err(StorageError::ReadOnly(reason.to_string()).into())
+    }
+
+    /// Snapshot a call on a shared read-only server reads the default database from
+    ///
+    /// None when the call names its own query snapshot, or when the server
+    /// isn't shared or its database can't take snapshots (no WAL).
+    fn open_call_snapshot(&self, arguments: &Value) -> Result<Option<Arc<Mutex<Repository>>>> {
+        match (&self.database, self.read_only) {
+            (Some(manager), Some(ReadOnlyMode::Shared))
+                if arguments["snapshot_id"].is_null() && manager.config().enable_wal_mode && !manager.config().is_in_memory() =>
+            {
+                Ok(Some(Arc::new(Mutex::new(Repository::new(manager.connect_snapshot()?)))))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Extracts a required string argument
+fn required_str<'a>(arguments: &'a Value, name: &str) -> Result<&'a str> {
+    arguments[name]
+        .as_str()
+        .ok_or_else(|| anyhow!("Missing required parameter: {}", name))
+}
+
+/// Reads the `limit`, `offset` and `cursor` arguments of a paged tool
+fn page_arguments(arguments: &Value, default_limit: u64, max_limit: u64) -> QueryPage {
+    let page = QueryPage::new(arguments["limit"].as_u64().unwrap_or(default_limit).clamp(1, max_limit))
+        .with_offset(arguments["offset"].as_u64().unwrap_or(0));
+    match arguments["cursor"].as_str() {
+        Some(cursor) => page.with_cursor(cursor),
+        None => page,
+    }
+}
+
+/// Reads an optional list of symbol type names, such as a symbol_types filter
+fn symbol_type_list(value: &Value) -> Result<Vec<SymbolType>> {
+    string_list(value, "symbol_types")?
+        .iter()
+        .map(|name| {
+            SymbolType::all()
+                .iter()
+                .copied()
+                .find(|t| t.as_str() == name)
+                .ok_or_else(|| anyhow!("Unknown symbol_type: {}", name))
+        })
+        .collect()
+}
+
+/// Reads an optional object of string values, such as a tag map
+fn string_map(value: &Value, name: &str) -> Result<BTreeMap<String, String>> {
+    match value {
+        Value::Null => Ok(BTreeMap::new()),
+        Value::Object(entries) => entries
+            .iter()
+            .map(|(key, value)| {
+                value
+                    .as_str()
+                    .map(|v| (key.clone(), v.to_string()))
+                    .ok_or_else(|| anyhow!("{} values must be strings", name))
+            })
+            .collect(),
+        _ => Err(anyhow!("{} must be an object of string values", name)),
+    }
+}
+
+/// Reads an optional array of strings
+fn string_list(value: &Value, name: &str) -> Result<Vec<String>> {
+    match value {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("{} must be an array of strings", name))
+            })
+            .collect(),
+        _ => Err(anyhow!("{} must be an array of strings", name)),
+    }
+}
+
+/// Relationships pointing at `targets` after the last returned id, in id order
+fn references_query(targets: &[i64], after_id: i64) -> SymbolRelationshipQuery {
+    SymbolRelationshipQuery::new()
+        .filter(Filter::in_list(RelationshipColumn::ToSymbolId, targets.iter().copied()))
+        .filter(Filter::gt(RelationshipColumn::Id, after_id))
+        .order_by_asc(RelationshipColumn::Id)
+}
+
+/// Line a parse error names as `file:line:column:`, or 0 if it names none
+fn error_line(message: &str) -> u32 {
+    let parts: Vec<&str> = message.split(':').collect();
+    parts
+        .windows(2)
+        .find_map(|pair| match (pair[0].trim().parse(), pair[1].trim().parse::<u32>()) {
+            (Ok(line), Ok(_)) => Some(line),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Answers update_file with the symbols a file gained, lost and changed
+///
+/// Symbols are matched by type, qualified name and whether they declare or
+/// define; of several matches, those stored alike pair up first. A matched
+/// symbol whose signature or definition changed is modified.
+fn file_update(index_name: &str, file_path: &str, before: &[CodeElement], after: &[CodeElement], file_hash: Option<String>, started: Instant) -> Value {
+    type Key = (&'static str, String, bool);
+    let key = |element: &CodeElement| -> Key { (element.symbol_type.as_str(), element.fully_qualified_name(), element.is_declaration) };
+    let mut old: BTreeMap<Key, Vec<&CodeElement>> = BTreeMap::new();
+    for element in before {
+        old.entry(key(element)).or_default().push(element);
+    }
+    let mut new: BTreeMap<Key, Vec<&CodeElement>> = BTreeMap::new();
+    for element in after {
+        new.entry(key(element)).or_default().push(element);
+    }
+
+    let change = |kind: &str, element: &CodeElement| {
+        json!({
+            "type": kind,
+            "symbol_name": element.fully_qualified_name(),
+            "symbol_type": element.symbol_type.as_str(),
+            "line_number": element.line_number
+        })
+    };
+    let (mut added, mut removed, mut modified) = (0, 0, 0);
+    let mut changes = Vec::new();
+    let keys: BTreeSet<&Key> = old.keys().chain(new.keys()).collect();
+    for key in keys {
+        let mut was = old.get(key).cloned().unwrap_or_default();
+        let mut now = new.get(key).cloned().unwrap_or_default();
+        now.retain(|element| {
+            let alike = was.iter().position(|old| old.signature == element.signature && old.definition_hash == element.definition_hash);
+            alike.map(|position| was.remove(position)).is_none()
+        });
+        for (old, element) in was.iter().zip(&now) {
+            let mut entry = change("modified", element);
+            entry["old_signature"] = json!(old.signature);
+            entry["new_signature"] = json!(element.signature);
+            changes.push(entry);
+            modified += 1;
+        }
+        for element in was.iter().skip(now.len()) {
+            changes.push(change("removed", element));
+            removed += 1;
+        }
+        for element in now.iter().skip(was.len()) {
+            changes.push(change("added", element));
+            added += 1;
+        }
+    }
+
+    json!({
+        "success": true,
+        "index_name": index_name,
+        "file_path": file_path,
+        "symbols_added": added,
+        "symbols_removed": removed,
+        "symbols_modified": modified,
+        "total_symbols": after.len(),
+        "update_time_ms": started.elapsed().as_millis() as u64,
+        "file_hash": file_hash,
+        "changes": changes
+    })
+}
+
+/// Describes a file followed to its new path
+fn move_entry(file_move: &FileMove) -> Value {
+    json!({ "from": file_move.from, "to": file_move.to, "similarity": file_move.similarity })
+}
+
+/// Describes an element in the SearchResult shape used by find_references
+fn reference_entry(element: &CodeElement) -> Value {
+    json!({
+        "id": element.id,
+        "name": element.symbol_name,
+        "type": element.symbol_type.as_str(),
+        "file_path": element.file_path,
+        "line_number": element.line_number,
+        "column_number": element.column_number,
+        "scope": element.scope,
+        "signature": element.signature,
+        "usr": element.usr,
+        "is_declaration": element.is_declaration
+    })
+}
+
+/// Describes a ranked symbol of get_index_insights: its reference entry and the count it ranks by
+fn ranked_symbol_entry(ranked: &RankedSymbol) -> Value {
+    let mut entry = reference_entry(&ranked.element);
+    entry["qualified_name"] = json!(ranked.element.fully_qualified_name());
+    entry["count"] = json!(ranked.count);
+    entry
+}
+
+/// Describes an open document and the symbols extracted from its text
+fn document_entry(file_path: &str, version: i64, symbols: Option<&[CodeElement]>) -> Value {
+    json!({
+        "file_path": file_path,
+        "version": version,
+        "symbols": symbols.map(|symbols| symbols.iter().map(reference_entry).collect::<Vec<_>>()),
+        "symbol_count": symbols.map(<[CodeElement]>::len)
+    })
+}
+
+/// A changed symbol with its popularity and the references to it from test files
+fn review_entry(repository: &Repository, element: &CodeElement) -> Result<Value> {
+    let id = element.id.unwrap_or_default();
+    let popularity = repository.get_symbol_popularity(&[id])?.remove(&id).unwrap_or_default();
+    let (_, incoming) = repository.get_symbol_relationships(id)?;
+    let test_references = test_references(&incoming);
+    let test_files: BTreeSet<&str> = test_references.iter().map(|relationship| relationship.file_path.as_str()).collect();
+
+    let mut entry = reference_entry(element);
+    entry["qualified_name"] = json!(element.fully_qualified_name());
+    entry["popularity"] = popularity_entry(&popularity);
+    entry["tests"] = json!({
+        "reference_count": test_references.len(),
+        "test_files": test_files
+    });
+    Ok(entry)
+}
+
+/// Describes how widely a symbol is used
+fn popularity_entry(popularity: &SymbolPopularity) -> Value {
+    json!({
+        "reference_count": popularity.reference_count,
+        "caller_count": popularity.caller_count,
+        "callee_count": popularity.callee_count,
+        "summary": popularity.summary()
+    })
+}
+
+/// Describes one direction of a call graph, leaving out the root itself
+fn call_graph_entry(graph: &CallGraph, root_id: i64) -> Value {
+    let nodes: Vec<Value> = graph
+        .nodes
+        .iter()
+        .filter(|node| node.element.id != Some(root_id))
+        .map(|node| {
+            let mut entry = reference_entry(&node.element);
+            entry["depth"] = json!(node.depth);
+            entry
+        })
+        .collect();
+
+    json!({
+        "symbols": nodes,
+        "edges": graph.edges,
+        "truncated": graph.truncated
+    })
+}
+
+/// Adds the directory of every `file_path` in a tool result
+fn collect_directories(value: &Value, directories: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                match (key.as_str(), field) {
+                    ("file_path", Value::String(file_path)) => {
+                        directories.insert(directory_of(file_path).to_string());
+                    }
+                    _ => collect_directories(field, directories),
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_directories(item, directories)),
+        _ => {}
+    }
+}
+
+/// Describes one direction of a type hierarchy, leaving out the class itself
+fn hierarchy_entry(hierarchy: &TypeHierarchy) -> Value {
+    let classes: Vec<Value> = hierarchy
+        .nodes
+        .iter()
+        .filter(|node| node.element.id != Some(hierarchy.root))
+        .map(|node| {
+            let mut entry = reference_entry(&node.element);
+            entry["depth"] = json!(node.depth);
+            entry
+        })
+        .collect();
+
+    json!({
+        "classes": classes,
+        "edges": hierarchy.edges,
+        "truncated": hierarchy.truncated
+    })
+}
+
+fn include_graph_entry(graph: &IncludeGraph, direction: IncludeDirection) -> Value {
+    let transitive: Vec<Value> = graph
+        .nodes
+        .iter()
+        .filter(|node| node.depth > 1)
+        .map(|node| json!({"file_path": node.file_path, "depth": node.depth}))
+        .collect();
+    let edges: Vec<Value> = graph
+        .edges
+        .iter()
+        .map(|edge| json!({"from": edge.file_path, "to": edge.resolved_path, "include_path": edge.include_path}))
+        .collect();
+
+    let mut entry = json!({
+        "direct": graph.direct().collect::<Vec<_>>(),
+        "transitive": transitive,
+        "edges": edges,
+        "truncated": graph.truncated
+    });
+    if direction == IncludeDirection::Includes {
+        entry["unresolved"] = json!(graph.unresolved);
+    }
+    entry
+}
+
+/// Stored form of a path given by a caller, absolute or relative to the index base path
+fn stored_path(base_path: &str, file_path: &str) -> String {
+    let path = Path::new(file_path);
+    path_key(path.strip_prefix(base_path).unwrap_or(path))
+}
+
+fn coverage_entry(coverage: &IndexCoverage) -> Value {
+    json!({
+        "state": coverage.state.as_str(),
+        "complete": false,
+        "indexed_files": coverage.indexed_files,
+        "pending_files": coverage.pending_files,
+        "failed_files": coverage.failed_files,
+        "known_files": coverage.known_files(),
+        "percent_indexed": (coverage.percent_indexed() * 10.0).round() / 10.0,
+        "note": format!(
+            "Index is {}: results cover {} of {} files discovered so far and may be incomplete",
+            coverage.state.as_str(),
+            coverage.indexed_files,
+            coverage.known_files()
+        )
+    })
+}
+
+/// Describes an annotation for tool responses
+fn annotation_entry(annotation: &SymbolAnnotation) -> Value {
+    json!({
+        "id": annotation.id,
+        "symbol_name": annotation.symbol_name,
+        "scope": annotation.scope,
+        "kind": annotation.kind,
+        "text": annotation.text,
+        "author": annotation.author,
+        "created_at": annotation.created_at.to_rfc3339()
+    })
+}
+
+/// Describes a file that failed to index for tool responses
+fn index_error_entry(error: &IndexError) -> Value {
+    json!({
+        "file_path": error.file_path,
+        "kind": error.kind,
+        "message": error.message,
+        "recorded_at": error.recorded_at.to_rfc3339()
+    })
+}
+
+/// Describes a saved query for tool responses
+fn saved_query_entry(saved: &SavedQuery) -> Value {
+    json!({
+        "name": saved.name,
+        "query": saved.query,
+        "description": saved.description,
+        "watched": saved.watched,
+        "last_checked_at": saved.last_checked_at.map(|checked| checked.to_rfc3339()),
+        "updated_at": saved.updated_at.to_rfc3339()
+    })
+}
+
+/// Definitions and declarations of the overload `target` is one of
+fn declarations_of(repository: &Repository, target: &CodeElement) -> Result<Resolution> {
+    let mut query = CodeElementQuery::new()
+        .filter(Filter::eq(ElementColumn::IndexId, target.index_id.to_string()))
+        .filter(Filter::eq(ElementColumn::SymbolName, target.symbol_name.clone()))
+        .filter(Filter::eq(ElementColumn::SymbolType, target.symbol_type));
+    query = match &target.scope {
+        Some(scope) => query.filter(Filter::eq(ElementColumn::Scope, scope.clone())),
+        None => query.filter(Filter::IsNull(ElementColumn::Scope)),
+    };
+    let query = query
+        .order_by_asc(ElementColumn::FilePath)
+        .order_by_asc(ElementColumn::LineNumber)
+        .limit(MAX_RESOLVE_CANDIDATES);
+    Ok(pair_declarations(target, repository.query_code_elements(&query)?))
+}
+
+/// Checks whether an indexed element lives in the scope the linker asked for
+fn scope_matches(wanted: Option<&str>, element: &CodeElement) -> bool {
+    match (wanted, element.scope.as_deref()) {
+        (None, None) => true,
+        (None, Some(scope)) => scope.is_empty(),
+        (Some(wanted), Some(scope)) => scope == wanted || wanted.ends_with(&format!("::{}", scope)),
+        (Some(_), None) => false,
+    }
+}
+
+/// Describes a parsed diagnostic with its path resolved against the index
+fn diagnostic_summary(diagnostic: &CompilerDiagnostic, file_path: &str) -> Value {
+    json!({
+        "file_path": file_path,
+        "line_number": diagnostic.line_number,
+        "column_number": diagnostic.column_number,
+        "severity": diagnostic.severity,
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "notes": diagnostic.notes,
+        "symbols": diagnostic.symbols
+    })
+}
+
+/// Describes where an element lives, including its owning build target
+fn symbol_site(element: &CodeElement, base_path: &Path) -> Value {
+    json!({
+        "file_path": element.file_path,
+        "line_number": element.line_number,
+        "column_number": element.column_number,
+        "scope": element.scope,
+        "signature": element.signature,
+        "build_target": diagnostics::find_build_target(base_path, &element.file_path)
+    })
+}
+
+/// Derives human-readable explanations for an unresolved symbol
+fn linker_hints(
+    symbol: &UnresolvedSymbol,
+    declarations: &[CodeElement],
+    definitions: &[CodeElement],
+    other_scopes: &[CodeElement],
+    base_path: &Path,
+) -> Vec<String> {
+    let mut hints = Vec::new();
+
+    match symbol.kind {
+        UnresolvedKind::Vtable | UnresolvedKind::Typeinfo => hints.push(format!(
+            "The {} for {} is emitted with its first non-inline virtual function; make sure every declared virtual member of {} has a definition that is compiled and linked",
+            if symbol.kind == UnresolvedKind::Vtable { "vtable" } else { "type information" },
+            symbol.qualified_name,
+            symbol.name
+        )),
+        UnresolvedKind::Symbol => {}
+    }
+
+    if declarations.is_empty() && definitions.is_empty() {
+        if other_scopes.is_empty() {
+            hints.push(format!(
+                "{} is not in the index; it may come from an external library that is not linked, or the index may be out of date",
+                symbol.qualified_name
+            ));
+        } else {
+            hints.push(format!(
+                "No {} exists, but {} exists in another scope; check namespaces and class qualifiers on the definition",
+                symbol.qualified_name, symbol.name
+            ));
+        }
+        return hints;
+    }
+
+    if definitions.is_empty() {
+        hints.push(format!(
+            "{} is declared but has no definition in the index; add a definition or link the library that provides it",
+            symbol.qualified_name
+        ));
+        if declarations.iter().any(|d| d.signature.as_deref().is_some_and(|s| s.contains("template"))) {
+            hints.push("Templates must be defined in the header or explicitly instantiated where their definition is visible".to_string());
+        }
+        return hints;
+    }
+
+    let targets: Vec<String> = definitions
+        .iter()
+        .map(|d| match diagnostics::find_build_target(base_path, &d.file_path) {
+            Some(target) => format!("{} ({})", target.directory, target.build_file),
+            None => format!("{} (no build file found)", d.file_path),
+        })
+        .collect();
+    hints.push(format!(
+        "{} is defined in build target {}; make sure that target is compiled and linked into the failing binary",
+        symbol.qualified_name,
+        targets.join(", ")
+    ));
+
+    let declared_signatures: Vec<&str> = declarations.iter().filter_map(|d| d.signature.as_deref()).collect();
+    let mismatched = definitions
+        .iter()
+        .filter_map(|d| d.signature.as_deref())
+        .any(|s| !declared_signatures.is_empty() && !declared_signatures.contains(&s));
+    if mismatched {
+        hints.push("The definition's signature differs from the declaration; check parameter types, const qualifiers and calling conventions".to_string());
+    }
+
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cpp_indexer::walk_filter::glob_selects;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::file_metadata::FileMetadata;
+
+    fn repository() -> Repository {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        Repository::new(manager.connect().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_tool_handlers_creation() {
+        let _handlers = ToolHandlers::new().unwrap();
+        // Basic smoke test - handlers should be created successfully
+        assert!(true);
+    }
+
+    #[tokio::test]
+    async fn test_explain_linker_error() {
+        use crate::lib::storage::models::code_element::SymbolType;
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = CodeIndex::new("test".to_string(), "/nonexistent".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        repository.create_code_element(
+            CodeElement::new(index_id, "area".to_string(), SymbolType::Function, "include/shape.h".to_string(), 12, 5, "a".repeat(64))
+                .with_scope("geometry::Shape".to_string())
+                .with_declaration(true),
+        ).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers.handle_tool_call("explain_linker_error", json!({
+            "index_name": "test",
+            "error_message": "main.cpp:(.text+0x1f): undefined reference to `geometry::Shape::area() const'"
+        })).await.unwrap();
+
+        assert_eq!(result["total_count"], 1);
+        let symbol = &result["symbols"][0];
+        assert_eq!(symbol["declarations"].as_array().unwrap().len(), 1);
+        assert!(symbol["definitions"].as_array().unwrap().is_empty());
+        assert!(symbol["hints"][0].as_str().unwrap().contains("no definition"));
+    }
+
+    #[tokio::test]
+    async fn test_get_compiler_error_context() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = CodeIndex::new("test".to_string(), "/proj".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let element = |name: &str, symbol_type, file: &str, line| {
+            CodeElement::new(index_id, name.to_string(), symbol_type, file.to_string(), line, 1, "a".repeat(64))
+        };
+        repository.create_code_element(element("main", SymbolType::Function, "src/app.cpp", 10)).unwrap();
+        repository.create_code_element(element("Canvas", SymbolType::Class, "include/canvas.h", 3)).unwrap();
+        repository.create_code_element(element("draw", SymbolType::Function, "include/widget.h", 8).with_scope("Widget".to_string())).unwrap();
+        repository.create_code_element(element("draw", SymbolType::Function, "include/widget.h", 9).with_scope("Widget".to_string())).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers.handle_tool_call("get_compiler_error_context", json!({
+            "index_name": "test",
+            "error_message": "/proj/src/app.cpp:14:17: error: no matching function for call to 'Widget::draw(int)'\n\
+                              /proj/include/widget.h:9:10: note: candidate: 'void Widget::draw(const Canvas&)'"
+        })).await.unwrap();
+
+        assert_eq!(result["total_count"], 1);
+        let diagnostic = &result["diagnostics"][0];
+        assert_eq!(diagnostic["diagnostic"]["file_path"], "src/app.cpp");
+        let sections = diagnostic["context"]["sections"].as_array().unwrap();
+        let section = |name: &str| sections.iter().find(|s| s["name"] == name).unwrap()["items"].as_array().unwrap().len();
+        assert_eq!(section("location"), 1);
+        assert_eq!(section("overloads"), 2);
+        assert_eq!(section("types"), 1);
+
+        // max_items is kept within 1..=MAX_ITEMS
+        let error_message = "/proj/src/app.cpp:14:17: error: no matching function for call to 'Widget::draw(int)'";
+        for (max_items, expected) in [(0, 1), (u64::MAX, 3)] {
+            let result = handlers.handle_tool_call("get_compiler_error_context", json!({
+                "index_name": "test",
+                "error_message": error_message,
+                "max_items": max_items
+            })).await.unwrap();
+            assert_eq!(result["diagnostics"][0]["context"]["item_count"], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_references_pages_with_cursor() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = CodeIndex::new("test".to_string(), "/proj".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let element = |name: &str, file: &str, line| {
+            CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64))
+        };
+        let target = repository.create_code_element(element("draw", "include/canvas.h", 4).with_declaration(true)).unwrap();
+        let caller = repository.create_code_element(element("render", "src/app.cpp", 1).with_scope("app".to_string())).unwrap();
+        for line in 2..=6 {
+            repository.create_symbol_relationship(SymbolRelationship::new(
+                caller.id.unwrap(),
+                target.id.unwrap(),
+                RelationshipType::Calls,
+                "src/app.cpp".to_string(),
+                line,
+            )).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let first = handlers.handle_tool_call("find_references", json!({
+            "index_name": "test",
+            "symbol_name": "draw",
+            "page_size": 2
+        })).await.unwrap();
+        assert_eq!(first["total_count"], 6);
+        assert_eq!(first["returned_count"], 3);
+        assert_eq!(first["symbols"][0]["is_declaration"], true);
+        assert_eq!(first["symbols"][1]["scope"], "app::render");
+        assert_eq!(first["symbols"][1]["line_number"], 2);
+
+        let mut lines = Vec::new();
+        let mut cursor = first["next_cursor"].as_str().unwrap().to_string();
+        loop {
+            let page = handlers.handle_tool_call("find_references", json!({ "cursor": cursor })).await.unwrap();
+            lines.extend(page["symbols"].as_array().unwrap().iter().map(|s| s["line_number"].as_u64().unwrap()));
+            match page["next_cursor"].as_str() {
+                Some(next) => cursor = next.to_string(),
+                None => break,
+            }
+        }
+        assert_eq!(lines, vec![4, 5, 6]);
+
+        let expired = handlers.handle_tool_call("find_references", json!({ "cursor": cursor })).await;
+        assert!(expired.unwrap_err().to_string().contains("cursor"));
+
+        let without_declarations = handlers.handle_tool_call("find_references", json!({
+            "index_name": "test",
+            "symbol_name": "draw",
+            "include_declarations": false
+        })).await.unwrap();
+        assert_eq!(without_declarations["total_count"], 5);
+        assert!(without_declarations["next_cursor"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_find_references_classifies_and_groups() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/app.cpp"), "void render() {\n    draw();\n    // draw once per frame\n    register_callback(&draw);\n}\n").unwrap();
+        std::fs::write(dir.path().join("src/menu.cpp"), "void menu() {\n    draw();\n}\n").unwrap();
+
+        let repository = repository();
+        let index = CodeIndex::new("test".to_string(), dir.path().to_string_lossy().to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let element = |name: &str, file: &str, line| {
+            CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64))
+        };
+        let target = repository.create_code_element(element("draw", "include/draw.h", 1)).unwrap().id.unwrap();
+        let render = repository.create_code_element(element("render", "src/app.cpp", 1)).unwrap().id.unwrap();
+        let menu = repository.create_code_element(element("menu", "src/menu.cpp", 1)).unwrap().id.unwrap();
+        for (from, file, line, relationship_type) in [
+            (render, "src/app.cpp", 2, RelationshipType::Calls),
+            (render, "src/app.cpp", 3, RelationshipType::Uses),
+            (render, "src/app.cpp", 4, RelationshipType::Uses),
+            (menu, "src/menu.cpp", 2, RelationshipType::Calls),
+        ] {
+            repository.create_symbol_relationship(SymbolRelationship::new(from, target, relationship_type, file.to_string(), line)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let page = handlers.handle_tool_call("find_references", json!({
+            "index_name": "test",
+            "symbol_name": "draw",
+            "include_declarations": false
+        })).await.unwrap();
+        let kinds: Vec<&str> = page["symbols"].as_array().unwrap().iter().map(|s| s["reference_kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, vec!["call", "documentation_mention", "address_taken", "call"]);
+        assert_eq!(page["files"][0]["file_path"], "src/app.cpp");
+        assert_eq!(page["files"][0]["lines"], json!([2, 3, 4]));
+        assert!(page["symbols"][0].get("context").is_none());
+
+        let page = handlers.handle_tool_call("find_references", json!({
+            "index_name": "test",
+            "symbol_name": "draw",
+            "context_lines": 1
+        })).await.unwrap();
+        // The declaration's header isn't on disk, so it has no context
+        assert!(page["symbols"][0]["context"].is_null());
+        assert_eq!(page["symbols"][1]["context"], json!({"start_line": 1, "lines": ["void render() {", "    draw();", "    // draw once per frame"]}));
+        assert_eq!(page["symbols"][4]["context"]["lines"], json!(["void menu() {", "    draw();", "}"]));
+
+        let summary = handlers.handle_tool_call("find_references", json!({
+            "index_name": "test",
+            "symbol_name": "draw",
+            "summary_only": true
+        })).await.unwrap();
+        assert!(summary.get("symbols").is_none());
+        assert_eq!(summary["total_count"], 4);
+        assert_eq!(summary["file_count"], 2);
+        assert_eq!(summary["kind_counts"]["call"], 2);
+        assert_eq!(summary["files"][1]["kinds"]["call"], 1);
+        assert!(summary["files"][0].get("lines").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_annotate_diff_maps_hunks_to_symbols() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".github")).unwrap();
+        std::fs::write(dir.path().join(".github/CODEOWNERS"), "* @core\n/src/audio/ @audio-team\n").unwrap();
+
+        let repository = repository();
+        let index = CodeIndex::new("engine".to_string(), dir.path().to_string_lossy().to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let element = |name: &str, file: &str, line| {
+            repository
+                .create_code_element(CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let process = element("process", "src/audio/mixer.cpp", 8);
+        let reset = element("reset", "src/audio/mixer.cpp", 38);
+        let main = element("main", "src/main.cpp", 1);
+        let test_case = element("test_process", "tests/mixer_test.cpp", 5);
+        for (from, file) in [(main, "src/main.cpp"), (test_case, "tests/mixer_test.cpp")] {
+            repository.create_symbol_relationship(SymbolRelationship::new(from, process, RelationshipType::Calls, file.to_string(), 6)).unwrap();
+        }
+        repository.refresh_symbol_popularity(&index_id).unwrap();
+
+        let diff = "\
+--- a/src/audio/mixer.cpp
++++ b/src/audio/mixer.cpp
+@@ -10,2 +10,3 @@ void Mixer::process() {
+     for (auto& channel : channels_) {
++        channel.render();
+     }
+@@ -40,2 +41,1 @@ void Mixer::reset() {
+-    rendered_ = 0;
+ }
+";
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers.handle_tool_call("annotate_diff", json!({"index_name": "engine", "diff": diff})).await.unwrap();
+        assert_eq!(result["hunk_count"], 2);
+        assert_eq!(result["has_codeowners"], true);
+
+        let first = &result["hunks"][0];
+        assert_eq!(first["owners"], json!(["@audio-team"]));
+        assert_eq!(first["symbols"][0]["id"], process);
+        assert_eq!(first["symbols"][0]["popularity"]["caller_count"], 2);
+        assert_eq!(first["symbols"][0]["tests"]["reference_count"], 1);
+        assert_eq!(first["symbols"][0]["tests"]["test_files"], json!(["tests/mixer_test.cpp"]));
+        assert_eq!(result["hunks"][1]["symbols"][0]["id"], reset);
+        assert_eq!(result["hunks"][1]["symbols"][0]["tests"]["reference_count"], 0);
+
+        assert!(handlers.handle_tool_call("annotate_diff", json!({"index_name": "engine", "diff": "not a diff"})).await.is_err());
+
+        // Risk comes from the same mapping: reset has no tests, process has two callers
+        let risk = handlers.handle_tool_call("assess_diff_risk", json!({"index_name": "engine", "diff": diff, "threshold": 1})).await.unwrap();
+        assert_eq!(risk["symbols"][0]["symbol_id"], process);
+        assert_eq!(risk["factors"]["untested"], 0.5);
+        assert_eq!(risk["files"][0]["owners"], json!(["@audio-team"]));
+        assert_eq!(risk["flagged"], true);
+        assert!(risk["score"].as_u64().unwrap() < u64::from(HIGH_RISK_SCORE));
+    }
+
+    #[tokio::test]
+    async fn test_analyze_hot_paths_from_tagged_root() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("audio.cpp"),
+            "void process() {\n    mix();\n}\n\nvoid mix() {\n    log_line(\"mixing\");\n    buffer.resize(64);\n}\n\nvoid log_line(const char* text) {\n    std::lock_guard<std::mutex> guard(log_mutex);\n    printf(\"%s\\n\", text);\n}\n",
+        )
+        .unwrap();
+
+        let repository = repository();
+        let index = CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let function = |name: &str, line| {
+            repository
+                .create_code_element(CodeElement::new(index_id, name.to_string(), SymbolType::Function, "audio.cpp".to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let (process, mix, log_line) = (function("process", 1), function("mix", 5), function("log_line", 10));
+        for (from, to, line) in [(process, mix, 2), (mix, log_line, 6)] {
+            repository.create_symbol_relationship(SymbolRelationship::new(from, to, RelationshipType::Calls, "audio.cpp".to_string(), line)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        assert!(handlers.handle_tool_call("analyze_hot_paths", json!({"index_name": "audio"})).await.is_err());
+
+        let tagged = handlers.handle_tool_call("tag_symbol", json!({
+            "index_name": "audio",
+            "symbol_name": "process",
+            "tag": "realtime"
+        })).await.unwrap();
+        assert_eq!(tagged["changed"], 1);
+
+        let report = handlers.handle_tool_call("analyze_hot_paths", json!({
+            "index_name": "audio",
+            "forbidden": ["resize"]
+        })).await.unwrap();
+        let findings: Vec<(&str, &str)> = report["findings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|finding| (finding["api"].as_str().unwrap(), finding["category"].as_str().unwrap()))
+            .collect();
+        assert_eq!(findings, vec![("resize", "forbidden"), ("lock_guard", "locking"), ("printf", "forbidden")]);
+        assert_eq!(report["findings"][2]["path"], json!(["process", "mix", "log_line"]));
+        assert_eq!(report["findings"][2]["line_number"], 12);
+        assert_eq!(report["counts"]["forbidden"], 2);
+        assert_eq!(report["functions_checked"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_call_graph_both_directions() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("app".to_string(), "/app".to_string())).unwrap();
+        let function = |name: &str, line| {
+            repository
+                .create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "app.cpp".to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let (main, run, step, draw) = (function("main", 1), function("run", 5), function("step", 10), function("draw", 15));
+        for (from, to, line) in [(main, run, 2), (run, step, 6), (step, draw, 11)] {
+            repository.create_symbol_relationship(SymbolRelationship::new(from, to, RelationshipType::Calls, "app.cpp".to_string(), line)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let graph = handlers.handle_tool_call("get_call_graph", json!({
+            "index_name": "app",
+            "symbol_id": run
+        })).await.unwrap();
+        assert_eq!(graph["symbol"]["name"], "run");
+        let names = |value: &Value| -> Vec<(String, u64)> {
+            value["symbols"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|node| (node["name"].as_str().unwrap().to_string(), node["depth"].as_u64().unwrap()))
+                .collect()
+        };
+        assert_eq!(names(&graph["callers"]), vec![("main".to_string(), 1)]);
+        assert_eq!(names(&graph["callees"]), vec![("step".to_string(), 1), ("draw".to_string(), 2)]);
+        assert_eq!(graph["callees"]["edges"][0]["line_number"], 6);
+
+        let shallow = handlers.handle_tool_call("get_call_graph", json!({
+            "index_name": "app",
+            "symbol_id": run,
+            "direction": "callees",
+            "max_depth": 1
+        })).await.unwrap();
+        assert!(shallow.get("callers").is_none());
+        assert_eq!(names(&shallow["callees"]), vec![("step".to_string(), 1)]);
+
+        assert!(handlers.handle_tool_call("get_call_graph", json!({"index_name": "app", "symbol_id": 9999})).await.is_err());
+        assert!(handlers.handle_tool_call("get_call_graph", json!({"index_name": "app", "symbol_id": run, "direction": "up"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_type_hierarchy() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("ui".to_string(), "/ui".to_string())).unwrap();
+        let element = |name: &str, symbol_type, scope: &str, line| {
+            let element = CodeElement::new(index.id, name.to_string(), symbol_type, "ui.h".to_string(), line, 1, "a".repeat(64)).with_scope(scope.to_string());
+            repository.create_code_element(element).unwrap().id.unwrap()
+        };
+        let (widget, button, toggle) = (element("Widget", SymbolType::Class, "ui", 1), element("Button", SymbolType::Class, "ui", 5), element("Toggle", SymbolType::Class, "ui", 9));
+        let (widget_paint, toggle_paint) = (element("paint", SymbolType::Function, "ui::Widget", 2), element("paint", SymbolType::Function, "ui::Toggle", 10));
+        for (from, to, relationship_type) in [
+            (button, widget, RelationshipType::Inherits),
+            (toggle, button, RelationshipType::Inherits),
+            (toggle_paint, widget_paint, RelationshipType::Overrides),
+        ] {
+            repository.create_symbol_relationship(SymbolRelationship::new(from, to, relationship_type, "ui.h".to_string(), 1)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let hierarchy = handlers.handle_tool_call("get_type_hierarchy", json!({
+            "index_name": "ui",
+            "symbol_id": button
+        })).await.unwrap();
+        assert_eq!(hierarchy["symbol"]["name"], "Button");
+        assert_eq!(hierarchy["bases"]["classes"][0]["name"], "Widget");
+        assert_eq!(hierarchy["derived"]["classes"][0]["name"], "Toggle");
+        assert_eq!(hierarchy["overrides"][0]["method"]["scope"], "ui::Toggle");
+        assert_eq!(hierarchy["overrides"][0]["overrides"]["scope"], "ui::Widget");
+
+        let bases = handlers.handle_tool_call("get_type_hierarchy", json!({
+            "index_name": "ui",
+            "symbol_id": toggle,
+            "direction": "bases",
+            "max_depth": 1,
+            "include_overrides": false
+        })).await.unwrap();
+        assert!(bases.get("derived").is_none() && bases.get("overrides").is_none());
+        assert_eq!(bases["bases"]["classes"].as_array().unwrap().len(), 1);
+
+        assert!(handlers.handle_tool_call("get_type_hierarchy", json!({"index_name": "ui", "symbol_id": widget_paint})).await.is_err());
+        assert!(handlers.handle_tool_call("get_type_hierarchy", json!({"index_name": "ui", "symbol_id": widget, "direction": "up"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_include_graph() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::file_metadata::FileMetadata;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        for (file, includes) in [
+            ("src/mixer.cpp", vec!["mixer.h"]),
+            ("src/mixer.h", vec!["format.h", "vector"]),
+            ("src/format.h", vec![]),
+        ] {
+            repository.create_file_metadata(FileMetadata::new(index.id, file.to_string(), "a".repeat(64), chrono::Utc::now(), 1)).unwrap();
+            repository.replace_file_includes(&index.id, file, &includes.iter().map(|include| include.to_string()).collect::<Vec<_>>()).unwrap();
+        }
+        repository.resolve_file_includes(&index.id).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let graph = handlers.handle_tool_call("get_include_graph", json!({
+            "index_name": "audio",
+            "file_path": "/audio/src/format.h"
+        })).await.unwrap();
+        assert_eq!(graph["file_path"], "src/format.h");
+        assert_eq!(graph["included_by"]["direct"], json!(["src/mixer.h"]));
+        assert_eq!(graph["included_by"]["transitive"][0]["file_path"], "src/mixer.cpp");
+        assert_eq!(graph["includes"]["direct"], json!([]));
+
+        let includes = handlers.handle_tool_call("get_include_graph", json!({
+            "index_name": "audio",
+            "file_path": "src/mixer.cpp",
+            "direction": "includes"
+        })).await.unwrap();
+        assert!(includes.get("included_by").is_none());
+        assert_eq!(includes["includes"]["transitive"][0]["file_path"], "src/format.h");
+        assert_eq!(includes["includes"]["unresolved"], json!(["vector"]));
+
+        assert!(handlers.handle_tool_call("get_include_graph", json!({"index_name": "audio", "file_path": "src/missing.h"})).await.is_err());
+        assert!(handlers.handle_tool_call("get_include_graph", json!({"index_name": "audio", "file_path": "src/mixer.h", "direction": "up"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_text() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        repository.create_code_element(
+            CodeElement::new(index.id, "drain".to_string(), SymbolType::Function, "src/ring.h".to_string(), 10, 1, "a".repeat(64))
+                .with_documentation("Copies buffered samples without blocking".to_string()),
+        ).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers.handle_tool_call("search_text", json!({
+            "index_name": "audio",
+            "query": "buffered sampl",
+            "symbol_types": ["function"]
+        })).await.unwrap();
+        assert_eq!(result["symbols"][0]["name"], "drain");
+        assert_eq!(result["symbols"][0]["documentation"], "Copies buffered samples without blocking");
+        assert!(result["symbols"][0]["snippet"].as_str().unwrap().contains("[buffered]"));
+        assert!(result["symbols"][0].get("explanation").is_none());
+
+        let explained = handlers.handle_tool_call("search_text", json!({
+            "index_name": "audio",
+            "query": "buffered sampl",
+            "explain": true
+        })).await.unwrap();
+        let explanation = &explained["symbols"][0]["explanation"];
+        assert_eq!(explanation["matches"][0]["field"], "documentation");
+        assert_eq!(explanation["matches"][0]["term"], "buffered");
+        assert_eq!(explanation["matches"][0]["match_type"], "token");
+        assert_eq!(explanation["matches"][1]["match_type"], "prefix");
+        assert_eq!(explanation["components"][0]["name"], "bm25");
+        assert_eq!(explanation["components"][1]["name"], "popularity");
+        assert_eq!(explanation["score"], explained["symbols"][0]["score"]);
+
+        assert!(handlers.handle_tool_call("search_text", json!({"index_name": "audio", "query": "x", "columns": ["body"]})).await.is_err());
+        assert!(handlers.handle_tool_call("search_text", json!({"index_name": "audio", "query": "\"open", "raw": true})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_symbols_match_modes() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("ui".to_string(), "/ui".to_string())).unwrap();
+        let element = |name: &str, file: &str, scope: &str| {
+            CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64)).with_scope(scope.to_string())
+        };
+        repository.create_code_element(element("CreateWidget", "src/widget.cpp", "ui")).unwrap();
+        repository.create_code_element(element("CreateButtonWidget", "src/button.cpp", "ui::controls")).unwrap();
+        repository.create_code_element(element("CreateWidgetLater", "src/widget.cpp", "ui")).unwrap();
+        repository.create_code_element(element("CreateWidget", "test/widget_test.cpp", "uitest")).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let names = |result: &Value| -> Vec<String> {
+            result["symbols"].as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap().to_string()).collect()
+        };
+
+        let result = handlers.handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "widget"})).await.unwrap();
+        assert_eq!(result["match_mode"], "substring");
+        assert_eq!(result["total_count"], 4);
+
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "^Create.*Widget$", "match_mode": "regex", "file_path": "src/*"}))
+            .await
+            .unwrap();
+        assert_eq!(names(&result), ["CreateButtonWidget", "CreateWidget"]);
+
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "CreateWidget", "exact_match": true, "scope": "ui", "limit": 1}))
+            .await
+            .unwrap();
+        assert_eq!(names(&result), ["CreateWidget"]);
+        assert_eq!(result["symbols"][0]["file_path"], "src/widget.cpp");
+        assert_eq!(result["truncated"], false);
+
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "Create*", "match_mode": "glob", "scope": "ui", "limit": 2}))
+            .await
+            .unwrap();
+        assert_eq!(result["total_count"], 3);
+        assert_eq!(result["truncated"], true);
+        let first_page = names(&result);
+
+        // The cursor picks up after the last page; offset skips the same rows
+        let rest = json!({"index_name": "ui", "query": "Create*", "match_mode": "glob", "scope": "ui", "limit": 2, "cursor": result["next_cursor"]});
+        let result = handlers.handle_tool_call("search_symbols", rest).await.unwrap();
+        assert_eq!(result["symbols"].as_array().unwrap().len(), 1);
+        assert!(!first_page.contains(&names(&result)[0]));
+        assert!(result["next_cursor"].is_null());
+        let skipped = json!({"index_name": "ui", "query": "Create*", "match_mode": "glob", "scope": "ui", "offset": 2});
+        assert_eq!(names(&handlers.handle_tool_call("search_symbols", skipped).await.unwrap()), names(&result));
+        let bad_cursor = json!({"index_name": "ui", "query": "widget", "cursor": "later"});
+        assert!(handlers.handle_tool_call("search_symbols", bad_cursor).await.is_err());
+
+        // Qualified queries match the innermost scopes, or the whole name when anchored
+        let result = handlers.handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "controls::widget"})).await.unwrap();
+        assert_eq!(result["qualified"], true);
+        assert_eq!(names(&result), ["CreateButtonWidget"]);
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "ui::CreateWidget", "exact_match": true}))
+            .await
+            .unwrap();
+        assert_eq!(result["total_count"], 1);
+        assert_eq!(result["symbols"][0]["file_path"], "src/widget.cpp");
+        let anchored = json!({"index_name": "ui", "query": "::controls::CreateButtonWidget", "exact_match": true});
+        assert_eq!(handlers.handle_tool_call("search_symbols", anchored).await.unwrap()["total_count"], 0);
+        assert!(handlers.handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "ui::"})).await.is_err());
+
+        assert!(handlers.handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "(", "match_mode": "regex"})).await.is_err());
+        assert!(handlers.handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "x", "match_mode": "soundex"})).await.is_err());
+
+        // Fuzzy matches forgive typos and come closest first, with their scores
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "ui", "query": "CreateWidgte", "match_mode": "fuzzy"}))
+            .await
+            .unwrap();
+        assert_eq!(names(&result), ["CreateWidget", "CreateWidget", "CreateWidgetLater", "CreateButtonWidget"]);
+        assert_eq!(result["rank"], "match");
+        assert_eq!(result["symbols"][0]["file_path"], "src/widget.cpp");
+        assert!(result["symbols"][0]["score"].as_f64().unwrap() > 0.9);
+        let qualified = json!({"index_name": "ui", "query": "controls::Widgt", "match_mode": "fuzzy"});
+        assert_eq!(names(&handlers.handle_tool_call("search_symbols", qualified).await.unwrap()), ["CreateButtonWidget"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_symbols_ranks_by_popularity() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("core".to_string(), "/core".to_string())).unwrap();
+        let element = |name: &str, file: &str| {
+            CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64))
+        };
+        let helper = repository.create_code_element(element("init", "test/helpers.cpp")).unwrap().id.unwrap();
+        let init = repository.create_code_element(element("init", "src/core.cpp")).unwrap().id.unwrap();
+        let main = repository.create_code_element(element("main", "src/main.cpp")).unwrap().id.unwrap();
+        let setup = repository.create_code_element(element("setup", "src/setup.cpp")).unwrap().id.unwrap();
+        for (from, line) in [(main, 3), (main, 9), (setup, 4)] {
+            repository
+                .create_symbol_relationship(SymbolRelationship::new(from, init, RelationshipType::Calls, "src/main.cpp".to_string(), line))
+                .unwrap();
+        }
+        repository.refresh_symbol_popularity(&index.id).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "core", "query": "init", "exact_match": true}))
+            .await
+            .unwrap();
+        assert_eq!(result["rank"], "popularity");
+        assert_eq!(result["symbols"][0]["id"], init);
+        assert_eq!(result["symbols"][0]["popularity"]["summary"], "used 3 times by 2 callers");
+        assert!(result["symbols"][0].get("explanation").is_none());
+
+        let explained = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "core", "query": "init", "exact_match": true, "explain": true}))
+            .await
+            .unwrap();
+        let explanation = &explained["symbols"][0]["explanation"];
+        assert_eq!((explanation["matches"][0]["field"].as_str(), explanation["matches"][0]["match_type"].as_str()), (Some("name"), Some("exact")));
+        assert_eq!((explanation["score"].as_f64(), explanation["components"][0]["name"].as_str()), (Some(3.0), Some("popularity")));
+
+        let result = handlers
+            .handle_tool_call("search_symbols", json!({"index_name": "core", "query": "init", "exact_match": true, "rank": "name", "explain": true}))
+            .await
+            .unwrap();
+        assert_eq!(result["symbols"][0]["id"], init);
+        assert_eq!(result["symbols"][1]["id"], helper);
+        assert!(result["symbols"][0]["explanation"]["score"].is_null());
+
+        let details = handlers.handle_tool_call("get_symbol_details", json!({"index_name": "core", "symbol_id": main})).await.unwrap();
+        assert_eq!(details["popularity"]["callee_count"], 1);
+        assert_eq!(details["relationships"].as_array().unwrap().len(), 2);
+        assert_eq!(details["relationships"][0]["target_symbol_name"], "init");
+
+        assert!(handlers.handle_tool_call("get_symbol_details", json!({"index_name": "core", "symbol_id": 9999})).await.is_err());
+        assert!(handlers.handle_tool_call("search_symbols", json!({"index_name": "core", "query": "init", "rank": "recent"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_symbols_by_configuration() {
+        use crate::lib::storage::models::build_configuration::BuildConfiguration;
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("core".to_string(), "/core".to_string())).unwrap();
+        let element = |name: &str, line| {
+            CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/platform.cpp".to_string(), line, 1, "a".repeat(64))
+        };
+        let open_win = repository.create_code_element(element("open_file", 4)).unwrap().id.unwrap();
+        let open_posix = repository.create_code_element(element("open_file", 8)).unwrap().id.unwrap();
+        let windows = repository
+            .save_build_configuration(BuildConfiguration::new(index.id, "windows".to_string()).with_define("_WIN32", "1"))
+            .unwrap();
+        let linux = repository.save_build_configuration(BuildConfiguration::new(index.id, "linux".to_string())).unwrap();
+        repository
+            .replace_configuration_exclusions(&index.id, &[(windows.id.unwrap(), open_posix), (linux.id.unwrap(), open_win)])
+            .unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let search = json!({"index_name": "core", "query": "open_file", "exact_match": true});
+        assert_eq!(handlers.handle_tool_call("search_symbols", search.clone()).await.unwrap()["total_count"], 2);
+
+        let mut windows_only = search.clone();
+        windows_only["configuration"] = json!("windows");
+        let result = handlers.handle_tool_call("search_symbols", windows_only).await.unwrap();
+        assert_eq!(result["total_count"], 1);
+        assert_eq!(result["symbols"][0]["id"], open_win);
+
+        let details = handlers.handle_tool_call("get_symbol_details", json!({"index_name": "core", "symbol_id": open_posix})).await.unwrap();
+        assert_eq!(details["configurations"], json!(["linux"]));
+
+        let mut unknown = search;
+        unknown["configuration"] = json!("macos");
+        assert!(handlers.handle_tool_call("search_symbols", unknown).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_snapshot_sees_one_state() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let dir = tempfile::tempdir().unwrap();
+        let manager = Arc::new(DatabaseManager::new(DatabaseConfig::new(dir.path().join("index.db"))).unwrap());
+        let repository = Arc::new(Mutex::new(Repository::new(manager.connect().unwrap())));
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::clone(&repository))
+            .with_database_manager(manager);
+        repository.lock().unwrap().create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+
+        let snapshot = handlers.handle_tool_call("begin_query_snapshot", json!({})).await.unwrap();
+        let snapshot_id = snapshot["snapshot_id"].as_str().unwrap().to_string();
+        // The watcher commits a new index mid-sequence
+        repository.lock().unwrap().create_code_index(CodeIndex::new("net".to_string(), "/net".to_string())).unwrap();
+
+        let live = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(live["total_count"], 2);
+        let pinned = handlers.handle_tool_call("list_indices", json!({"snapshot_id": snapshot_id})).await.unwrap();
+        assert_eq!(pinned["total_count"], 1);
+        assert_eq!(pinned["indices"][0]["name"], "audio");
+
+        let ended = handlers.handle_tool_call("end_query_snapshot", json!({"snapshot_id": snapshot_id})).await.unwrap();
+        assert_eq!(ended["ended"], true);
+        assert!(handlers.handle_tool_call("list_indices", json!({"snapshot_id": snapshot_id})).await.is_err());
+        assert!(ToolHandlers::new().unwrap().handle_tool_call("begin_query_snapshot", json!({})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shared_read_only_server() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = DatabaseConfig::new(dir.path().join("index.db"));
+        // Another process writes the database
+        let writer = Repository::new(DatabaseManager::new(config.clone()).unwrap().connect().unwrap());
+        writer.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+
+        let manager = Arc::new(DatabaseManager::new(config.with_read_only(true)).unwrap());
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::new(Mutex::new(Repository::new(manager.connect().unwrap()))))
+            .with_database_manager(manager)
+            .with_read_only_mode(ReadOnlyMode::Shared);
+
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(listed["total_count"], 1);
+        writer.create_code_index(CodeIndex::new("net".to_string(), "/net".to_string())).unwrap();
+        assert_eq!(handlers.handle_tool_call("list_indices", json!({})).await.unwrap()["total_count"], 2);
+
+        for (tool, arguments) in [
+            ("index_codebase", json!({"name": "audio", "base_path": dir.path().to_string_lossy(), "incremental": true})),
+            ("delete_index", json!({"index_name": "audio", "confirm": true})),
+            ("update_file", json!({"index_name": "audio", "file_path": "mixer.cpp"})),
+        ] {
+            let error = handlers.handle_tool_call(tool, arguments).await.unwrap_err();
+            assert_eq!(error.downcast_ref::<StorageError>().map(StorageError::kind), Some("read_only"), "{}", tool);
+            assert!(error.to_string().contains("--read-only"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_index_coverage() {
+        use crate::lib::storage::models::code_index::{CodeIndex, IndexState};
+        use crate::lib::storage::models::file_metadata::{FileMetadata, FileProcessingState};
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        for (path, state) in [("src/mixer.cpp", FileProcessingState::Indexed), ("src/ring.h", FileProcessingState::Pending)] {
+            let metadata = repository
+                .create_file_metadata(FileMetadata::new(index.id, path.to_string(), "a".repeat(64), chrono::Utc::now(), 10))
+                .unwrap();
+            repository.update_file_processing_state(metadata.id.unwrap(), state).unwrap();
+        }
+        let repository = Arc::new(Mutex::new(repository));
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::clone(&repository));
+
+        // Queries are answered while indexing runs, with coverage attached
+        let result = handlers.handle_tool_call("find_symbols_in_section", json!({"index_name": "audio", "section": ".text"})).await.unwrap();
+        assert_eq!(result["coverage"]["state"], "creating");
+        assert_eq!(result["coverage"]["percent_indexed"], 50.0);
+        assert_eq!(result["coverage"]["known_files"], 2);
+
+        let file = handlers.handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "/audio/src/ring.h"})).await.unwrap();
+        assert_eq!(file["coverage"]["file_indexed"], false);
+        let file = handlers.handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "src/mixer.cpp"})).await.unwrap();
+        assert_eq!(file["coverage"]["file_indexed"], true);
+
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(listed["indices"][0]["coverage"]["pending_files"], 1);
+
+        repository.lock().unwrap().update_code_index_state(&index.id, IndexState::Active).unwrap();
+        let result = handlers.handle_tool_call("find_symbols_in_section", json!({"index_name": "audio", "section": ".text"})).await.unwrap();
+        assert!(result.get("coverage").is_none());
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(listed["indices"][0]["state"], "active");
+    }
+
+    #[tokio::test]
+    async fn test_stale_file_flagged() {
+        use crate::lib::storage::models::code_index::{CodeIndex, IndexState};
+        use crate::lib::storage::models::file_metadata::FileMetadata;
+        use super::super::freshness::content_hash;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mixer.cpp"), "void mix() {}").unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        repository.update_code_index_state(&index.id, IndexState::Active).unwrap();
+        repository
+            .create_file_metadata(FileMetadata::new(index.id, "mixer.cpp".to_string(), content_hash(b"void mix() {}"), chrono::Utc::now(), 13))
+            .unwrap();
+
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::new(Mutex::new(repository)))
+            .with_stale_check(StaleCheck::Flag);
+        let arguments = json!({"index_name": "audio", "file_path": dir.path().join("mixer.cpp").to_string_lossy()});
+        let result = handlers.handle_tool_call("get_file_symbols", arguments.clone()).await.unwrap();
+        assert_eq!(result["freshness"]["status"], "fresh");
+
+        std::fs::write(dir.path().join("mixer.cpp"), "void mix(float gain) {}").unwrap();
+        let result = handlers.handle_tool_call("get_file_symbols", arguments).await.unwrap();
+        assert_eq!(result["freshness"]["status"], "stale");
+        assert_eq!(result["freshness"]["file_path"], "mixer.cpp");
+        assert!(result["freshness"].get("reindexed_symbols").is_none());
+
+        // Files over the re-index limit are only flagged
+        let result = handlers.clone().with_stale_check(StaleCheck::Reindex).with_stale_reindex_limit(8)
+            .handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "mixer.cpp"})).await.unwrap();
+        assert_eq!(result["freshness"]["status"], "stale");
+        assert!(result["freshness"].get("reindexed_symbols").is_none());
+
+        // Off by default
+        let result = handlers.clone().with_stale_check(StaleCheck::Off)
+            .handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "mixer.cpp"})).await.unwrap();
+        assert!(result.get("freshness").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_promote_file_detail_skips_full_files() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::file_metadata::FileMetadata;
+        use super::super::freshness::content_hash;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mixer.cpp"), "void mix() {}").unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for (path, content) in [("mixer.cpp", "void mix() {}"), ("gone.cpp", "")] {
+            repository
+                .create_file_metadata(FileMetadata::new(index.id, path.to_string(), content_hash(content.as_bytes()), chrono::Utc::now(), 13))
+                .unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers
+            .handle_tool_call("promote_file_detail", json!({"index_name": "audio", "file_path": "mixer.cpp"}))
+            .await
+            .unwrap();
+        assert_eq!(result["promoted"], false);
+        assert_eq!(result["previous_detail"], "full");
+
+        assert!(handlers.handle_tool_call("promote_file_detail", json!({"index_name": "audio", "file_path": "gone.cpp"})).await.is_err());
+        assert!(handlers.handle_tool_call("promote_file_detail", json!({"index_name": "audio", "file_path": "voice.cpp"})).await.is_err());
+        let mut read_only = handlers.clone().with_read_only(true);
+        assert!(read_only.handle_tool_call("promote_file_detail", json!({"index_name": "audio", "file_path": "mixer.cpp"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_tools_and_delete_index() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mixer.cpp"), "class Mixer {}; void mix() {}").unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for (path, content) in [("mixer.cpp", "class Mixer {}; void mix() {}"), ("gone.cpp", "void gone() {}")] {
+            let metadata = FileMetadata::new(index.id, path.to_string(), content_hash(content.as_bytes()), chrono::Utc::now(), 10);
+            repository.update_file_metadata(&repository.create_file_metadata(metadata).unwrap()).unwrap();
+        }
+        for (name, symbol_type, file) in [("Mixer", SymbolType::Class, "mixer.cpp"), ("mix", SymbolType::Function, "mixer.cpp"), ("gone", SymbolType::Function, "gone.cpp")] {
+            repository
+                .create_code_element(CodeElement::new(index.id, name.to_string(), symbol_type, file.to_string(), 1, 1, "a".repeat(64)))
+                .unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let arguments = json!({"index_name": "audio", "file_path": dir.path().join("mixer.cpp").to_string_lossy(), "group_by_type": true});
+        let file = handlers.handle_tool_call("get_file_symbols", arguments).await.unwrap();
+        assert_eq!(file["file_path"], "mixer.cpp");
+        assert_eq!(file["total_symbols"], 2);
+        assert_eq!(file["symbols_by_type"]["class"][0]["name"], "Mixer");
+
+        let fresh = handlers.handle_tool_call("update_file", json!({"index_name": "audio", "file_path": "mixer.cpp"})).await.unwrap();
+        assert_eq!((fresh["status"].as_str(), fresh["symbols_modified"].as_u64()), (Some("fresh"), Some(0)));
+        assert_eq!((fresh["total_symbols"].as_u64(), fresh["message"].as_str()), (Some(2), Some("File has not changed since last index")));
+        let removed = handlers.handle_tool_call("update_file", json!({"index_name": "audio", "file_path": "gone.cpp"})).await.unwrap();
+        assert_eq!((removed["status"].as_str(), removed["symbols_removed"].as_u64(), removed["total_symbols"].as_u64()), (Some("missing"), Some(1), Some(0)));
+        assert_eq!(removed["changes"], json!([{"type": "removed", "symbol_name": "gone", "symbol_type": "function", "line_number": 1}]));
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(listed["indices"][0]["total_files"], 1);
+
+        assert!(handlers.handle_tool_call("delete_index", json!({"index_name": "audio"})).await.is_err());
+        let deleted = handlers.handle_tool_call("delete_index", json!({"index_name": "audio", "confirm": true})).await.unwrap();
+        assert_eq!(deleted["success"], true);
+        assert!(handlers.handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "mixer.cpp"})).await.is_err());
+        assert!(glob_selects("**/*.cpp", "main.cpp") && glob_selects("**/build/**", "build/gen.cpp"));
+    }
+
+    #[tokio::test]
+    async fn test_index_codebase() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("README.md"), "# audio").unwrap();
+        let manager = Arc::new(DatabaseManager::new(DatabaseConfig::new(dir.path().join("index.db"))).unwrap());
+        let repository = Repository::new(manager.connect().unwrap());
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository))).with_database_manager(manager);
+
+        let arguments = json!({"name": "audio", "base_path": dir.path().to_string_lossy()});
+        let created = handlers.handle_tool_call("index_codebase", arguments.clone()).await.unwrap();
+        assert_eq!((created["files_processed"].as_u64(), created["symbols_found"].as_u64()), (Some(0), Some(0)));
+        assert!(created["duration_ms"].is_u64() && created["errors"] == json!([]));
+        assert!(handlers.handle_tool_call("index_codebase", arguments).await.is_err());
+
+        // The run wrote through its own connection; the shared one sees the finished index
+        let arguments = json!({"name": "audio", "base_path": dir.path().to_string_lossy(), "incremental": true});
+        let updated = handlers.handle_tool_call("index_codebase", arguments).await.unwrap();
+        assert_eq!((updated["files_removed"].as_u64(), updated["files_moved"].clone()), (Some(0), json!([])));
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(listed["indices"][0]["state"], "active");
+        // A cancelled run leaves a complete index usable
+        let mut cancelled = ToolProgress::new(Arc::new(std::sync::atomic::AtomicBool::new(true)));
+        let arguments = json!({"name": "audio", "base_path": dir.path().to_string_lossy(), "incremental": true});
+        let error = handlers.handle_tool_call_with_progress("index_codebase", arguments, &mut cancelled).await.unwrap_err();
+        assert!(error.is::<Cancelled>());
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(listed["indices"][0]["state"], "active");
+        assert_eq!(error_line("mixer.cpp:25:7: error: expected ';'"), 25);
+        assert_eq!(error_line("Failed to parse mixer.cpp"), 0);
+    }
+
+    #[test]
+    fn test_file_update_lists_changes() {
+        let index_id = Uuid::new_v4();
+        let element = |name: &str, line, signature: &str| {
+            CodeElement::new(index_id, name.to_string(), SymbolType::Function, "mixer.cpp".to_string(), line, 1, "a".repeat(64))
+                .with_signature(signature.to_string())
+        };
+        let before = [element("mix", 3, "void mix(int)"), element("reset", 8, "void reset()"), element("gone", 12, "void gone()")];
+        let after = [element("mix", 3, "void mix(int, bool)"), element("reset", 9, "void reset()"), element("fresh", 15, "void fresh()")];
+        let update = file_update("audio", "mixer.cpp", &before, &after, Some("abc".to_string()), Instant::now());
+        assert_eq!((update["symbols_added"].as_u64(), update["symbols_removed"].as_u64(), update["symbols_modified"].as_u64()), (Some(1), Some(1), Some(1)));
+        assert_eq!((update["total_symbols"].as_u64(), update["file_hash"].as_str()), (Some(3), Some("abc")));
+        let changes = update["changes"].as_array().unwrap();
+        let modified = changes.iter().find(|change| change["type"] == "modified").unwrap();
+        assert_eq!((modified["symbol_name"].as_str(), modified["old_signature"].as_str()), (Some("mix"), Some("void mix(int)")));
+        assert_eq!(modified["new_signature"], "void mix(int, bool)");
+        assert!(changes.iter().any(|change| change["type"] == "added" && change["symbol_name"] == "fresh"));
+        assert!(changes.iter().any(|change| change["type"] == "removed" && change["line_number"] == 12));
+    }
+
+    #[tokio::test]
+    async fn test_update_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/dsp")).unwrap();
+        std::fs::write(dir.path().join("src/dsp/gain.cpp"), "float gain() {}").unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for (path, content) in [("src/dsp/gain.cpp", "float gain() {}"), ("src/dsp/gone.cpp", "void gone() {}")] {
+            let metadata = FileMetadata::new(index.id, path.to_string(), content_hash(content.as_bytes()), chrono::Utc::now(), 10);
+            repository.create_file_metadata(metadata).unwrap();
+        }
+        repository
+            .create_code_element(CodeElement::new(index.id, "gone".to_string(), SymbolType::Function, "src/dsp/gone.cpp".to_string(), 1, 1, "a".repeat(64)))
+            .unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let updated = handlers.handle_tool_call("update_path", json!({"index_name": "audio", "path": "src/dsp/"})).await.unwrap();
+        assert_eq!((&updated["removed"], &updated["moved"]), (&json!(["src/dsp/gone.cpp"]), &json!([])));
+        assert_eq!((updated["skipped"].as_u64(), updated["symbols_removed"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(updated["indexed"], json!([]));
+
+        let again = handlers.handle_tool_call("update_path", json!({"index_name": "audio", "path": "src/**/*.cpp"})).await.unwrap();
+        assert_eq!((&again["removed"], again["skipped"].as_u64()), (&json!([]), Some(1)));
+        assert!(handlers.handle_tool_call("update_path", json!({"index_name": "audio", "path": "include"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_follows_index_updates() {
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        let metadata = repository
+            .create_file_metadata(FileMetadata::new(index.id, "mixer.cpp".to_string(), "a".repeat(64), chrono::Utc::now(), 10))
+            .unwrap();
+        let element = |name: &str| CodeElement::new(index.id, name.to_string(), SymbolType::Function, "mixer.cpp".to_string(), 1, 1, "a".repeat(64));
+        repository.replace_file_elements(&metadata, vec![element("mix")]).unwrap();
+        let repository = Arc::new(Mutex::new(repository));
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::clone(&repository));
+
+        let arguments = json!({"index_name": "audio", "file_path": "mixer.cpp"});
+        let first = handlers.handle_tool_call("get_file_symbols", arguments.clone()).await.unwrap();
+        let again = handlers.handle_tool_call("get_file_symbols", arguments.clone()).await.unwrap();
+        assert_eq!((&first, first["total_symbols"].as_u64()), (&again, Some(1)));
+        let cache = handlers.query_cache.clone().unwrap();
+        let counts = cache.lock().map(|cache| (cache.hits(), cache.misses())).unwrap();
+        assert_eq!(counts, (1, 1));
+
+        // Re-indexing the file moves the index on, so the cached answer is dropped
+        repository.lock().unwrap().replace_file_elements(&metadata, vec![element("mix"), element("pan")]).unwrap();
+        let updated = handlers.handle_tool_call("get_file_symbols", arguments.clone()).await.unwrap();
+        assert_eq!(updated["total_symbols"], 2);
+
+        // So does relating its symbols, which get_symbol_details reports
+        let ids: Vec<i64> = repository.lock().unwrap().list_code_elements_by_file(&index.id, "mixer.cpp").unwrap().iter().filter_map(|element| element.id).collect();
+        let details = json!({"index_name": "audio", "symbol_id": ids[0]});
+        assert_eq!(handlers.handle_tool_call("get_symbol_details", details.clone()).await.unwrap()["relationships"], json!([]));
+        let calls = SymbolRelationship::new(ids[0], ids[1], RelationshipType::Calls, "mixer.cpp".to_string(), 1);
+        repository.lock().unwrap().create_symbol_relationship(calls).unwrap();
+        let related = handlers.handle_tool_call("get_symbol_details", details).await.unwrap();
+        assert_eq!(related["relationships"][0]["target_symbol_id"], ids[1]);
+
+        let mut uncached = ToolHandlers::new().unwrap().with_repository(repository).with_query_cache(0);
+        assert_eq!(uncached.handle_tool_call("get_file_symbols", arguments).await.unwrap()["total_symbols"], 2);
+        assert!(uncached.query_cache.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_file_outline() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mixer.h"), "namespace audio {\nclass Mixer {\n    void mix();\n};\n}\n").unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for (name, symbol_type, line) in [("audio", SymbolType::Namespace, 1), ("Mixer", SymbolType::Class, 2), ("mix", SymbolType::Function, 3)] {
+            repository
+                .create_code_element(CodeElement::new(index.id, name.to_string(), symbol_type, "mixer.h".to_string(), line, 1, "a".repeat(64)))
+                .unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let outline = handlers.handle_tool_call("get_file_outline", json!({"index_name": "audio", "file_path": "mixer.h"})).await.unwrap();
+        assert_eq!((outline["total_symbols"].as_u64(), outline["ranges_from_source"].as_bool()), (Some(3), Some(true)));
+        let namespace = &outline["outline"][0];
+        assert_eq!((namespace["name"].as_str(), namespace["end_line"].as_u64()), (Some("audio"), Some(5)));
+        assert_eq!(namespace["children"][0]["end_line"], 4);
+        assert_eq!(namespace["children"][0]["children"][0]["name"], "mix");
+        assert!(handlers.handle_tool_call("get_file_outline", json!({"index_name": "video", "file_path": "mixer.h"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_grep_code() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/mixer.cpp"), "// TODO: pool\nvoid mix() {\n    log(\"todo: drain\");\n}\n").unwrap();
+        std::fs::write(dir.path().join("main.cpp"), "int main() { return 0; } // TODO\n").unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for path in ["main.cpp", "src/gone.cpp", "src/mixer.cpp"] {
+            repository
+                .create_file_metadata(FileMetadata::new(index.id, path.to_string(), "a".repeat(64), chrono::Utc::now(), 10))
+                .unwrap();
+        }
+        repository
+            .create_code_element(CodeElement::new(index.id, "mix".to_string(), SymbolType::Function, "src/mixer.cpp".to_string(), 2, 6, "a".repeat(64)))
+            .unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let found = handlers.handle_tool_call("grep_code", json!({"index_name": "audio", "pattern": "todo", "case_sensitive": false, "path": "src"})).await.unwrap();
+        assert_eq!((found["total_count"].as_u64(), found["files_searched"].as_u64()), (Some(2), Some(1)));
+        assert_eq!(found["unreadable_files"], json!(["src/gone.cpp"]));
+        assert_eq!(found["matches"][0]["symbol"], Value::Null);
+        assert_eq!((found["matches"][1]["line_number"].as_u64(), found["matches"][1]["symbol"]["name"].as_str()), (Some(3), Some("mix")));
+
+        let limited = handlers.handle_tool_call("grep_code", json!({"index_name": "audio", "pattern": "TODO", "max_results": 1})).await.unwrap();
+        assert_eq!((limited["matches"][0]["file_path"].as_str(), limited["truncated"].as_bool()), (Some("main.cpp"), Some(true)));
+        assert!(handlers.handle_tool_call("grep_code", json!({"index_name": "audio", "pattern": "(", "regex": true})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search() {
+        use crate::lib::storage::embeddings::HashingEmbedder;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("engine".to_string(), "/src/engine".to_string())).unwrap();
+        for (name, documentation) in [("FrameRateLimiter", "Caps how often frames are presented"), ("loadTexture", "Decodes an image into a texture")] {
+            repository
+                .create_code_element(
+                    CodeElement::new(index.id, name.to_string(), SymbolType::Class, "engine.h".to_string(), 1, 1, "a".repeat(64))
+                        .with_documentation(documentation.to_string()),
+                )
+                .unwrap();
+        }
+        let index = repository.get_code_index_by_name("engine").unwrap().unwrap();
+        let mut store = EmbeddingStore::in_memory().unwrap();
+        store.refresh(&repository, &index, &HashingEmbedder::default()).unwrap();
+
+        let mut disabled = ToolHandlers::new().unwrap();
+        assert!(disabled.handle_tool_call("semantic_search", json!({"index_name": "engine", "query": "frame rate"})).await.is_err());
+
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::new(Mutex::new(repository)))
+            .with_embeddings(store, Box::new(HashingEmbedder::default()));
+        let found = handlers
+            .handle_tool_call("semantic_search", json!({"index_name": "engine", "query": "where is frame rate limiting handled?", "limit": 1, "explain": true}))
+            .await
+            .unwrap();
+        assert_eq!((found["symbols"][0]["name"].as_str(), found["total_count"].as_u64()), (Some("FrameRateLimiter"), Some(1)));
+        assert_eq!(found["symbols"][0]["explanation"]["components"][0]["name"], "similarity");
+        assert_eq!((found["backend"].as_str(), found["stale"].as_bool()), (Some("hashing-512"), Some(false)));
+        let none = handlers.handle_tool_call("semantic_search", json!({"index_name": "engine", "query": "frame", "symbol_types": ["enum"]})).await.unwrap();
+        assert_eq!(none["total_count"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_switch_index_revision_restores_stashed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = repository();
+        let index = repository
+            .create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string()))
+            .unwrap();
+        for (path, content, symbol) in [("mixer.cpp", "void mix() {}", "mix"), ("gone.cpp", "void gone() {}", "gone")] {
+            std::fs::write(dir.path().join(path), content).unwrap();
+            let metadata = FileMetadata::new(index.id, path.to_string(), content_hash(content.as_bytes()), chrono::Utc::now(), 10);
+            repository.update_file_metadata(&repository.create_file_metadata(metadata).unwrap()).unwrap();
+            repository
+                .create_code_element(CodeElement::new(index.id, symbol.to_string(), SymbolType::Function, path.to_string(), 1, 1, "a".repeat(64)))
+                .unwrap();
+        }
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let switch = |revision: &str| json!({"index_name": "audio", "revision": revision});
+
+        let main = handlers.handle_tool_call("switch_index_revision", switch("main")).await.unwrap();
+        assert_eq!((main["files_unchanged"].as_u64(), main["previous_revision"].is_null()), (Some(2), true));
+
+        // The feature branch deletes a file; nothing needs parsing
+        std::fs::remove_file(dir.path().join("gone.cpp")).unwrap();
+        let feature = handlers.handle_tool_call("switch_index_revision", switch("feature")).await.unwrap();
+        assert_eq!((feature["files_removed"].as_u64(), feature["files_parsed"].as_u64()), (Some(1), Some(0)));
+        assert_eq!(feature["total_files"], 1);
+
+        // Back on main the file comes back from the stash
+        std::fs::write(dir.path().join("gone.cpp"), "void gone() {}").unwrap();
+        let back = handlers.handle_tool_call("switch_index_revision", switch("main")).await.unwrap();
+        assert_eq!((back["files_restored"].as_u64(), back["files_parsed"].as_u64()), (Some(1), Some(0)));
+        assert_eq!((back["previous_revision"].as_str(), back["total_files"].as_u64()), (Some("feature"), Some(2)));
+        assert_eq!(back["revisions"], json!(["feature", "main"]));
+        let symbols = handlers.handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "gone.cpp"})).await.unwrap();
+        assert_eq!(symbols["symbols"][0]["name"], "gone");
+
+        assert!(handlers.handle_tool_call("switch_index_revision", json!({"index_name": "video", "revision": "main"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_depth_learns_queried_directories() {
+        use crate::lib::storage::models::file_metadata::FileMetadata;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        for path in ["src/audio/mixer.cpp", "src/ui/panel.cpp"] {
+            repository.create_file_metadata(FileMetadata::new(index.id, path.to_string(), "a".repeat(64), chrono::Utc::now(), 10)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::new(Mutex::new(repository)))
+            .with_adaptive_depth(DepthPlanner::new().with_promote_hits(2));
+        let arguments = json!({"index_name": "audio", "file_path": "/audio/src/audio/mixer.cpp"});
+        handlers.handle_tool_call("get_file_symbols", arguments.clone()).await.unwrap();
+        handlers.handle_tool_call("get_file_symbols", arguments).await.unwrap();
+
+        // The first call planned from one hit; the second is pending until the next re-plan
+        let report = handlers.handle_tool_call("get_index_depths", json!({"index_name": "audio"})).await.unwrap();
+        assert_eq!(report["adaptive"], true);
+        assert_eq!(report["directories"][0]["directory"], "src/audio");
+        assert_eq!(report["directories"][0]["depth"], "standard");
+        assert_eq!(report["directories"][0]["hits"], 2);
+        assert_eq!(report["directories"][0]["planned_depth"], "deep");
+        assert_eq!(report["directories"][1]["depth"], "shallow");
+        assert_eq!(report["pending_changes"], 1);
+        assert_eq!(report["counts"]["standard"], 1);
+
+        // Nothing is counted with adaptive depth off
+        let mut plain = ToolHandlers::new().unwrap().with_repository(Arc::clone(handlers.repository().unwrap()));
+        plain.handle_tool_call("get_file_symbols", json!({"index_name": "audio", "file_path": "src/ui/panel.cpp"})).await.unwrap();
+        let report = plain.handle_tool_call("get_index_depths", json!({"index_name": "audio"})).await.unwrap();
+        assert_eq!(report["adaptive"], false);
+        assert_eq!(report["directories"][1]["hits"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_symbol_separates_overloads() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mixer.h"), "namespace audio {\nvoid mix(float gain);\nvoid mix(int channels);\n}\n").unwrap();
+        std::fs::write(dir.path().join("app.cpp"), "int main() {\n    audio::mix(0.5f);\n}\n").unwrap();
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), dir.path().to_string_lossy().to_string())).unwrap();
+        let mix = |file: &str, line, signature: &str, is_declaration| {
+            let element = CodeElement::new(index.id, "mix".to_string(), SymbolType::Function, file.to_string(), line, 6, "a".repeat(64))
+                .with_scope("audio".to_string())
+                .with_signature(signature.to_string())
+                .with_declaration(is_declaration);
+            repository.create_code_element(element).unwrap().id.unwrap()
+        };
+        let float_declaration = mix("mixer.h", 2, "void mix(float gain)", true);
+        mix("mixer.h", 3, "void mix(int channels)", true);
+        let float_definition = mix("mixer.cpp", 10, "void mix(float gain)", false);
+        mix("mixer.cpp", 20, "void mix(int channels)", false);
+        let main = repository
+            .create_code_element(CodeElement::new(index.id, "main".to_string(), SymbolType::Function, "app.cpp".to_string(), 1, 5, "a".repeat(64)))
+            .unwrap()
+            .id
+            .unwrap();
+        repository.create_symbol_relationship(SymbolRelationship::new(main, float_definition, RelationshipType::Calls, "app.cpp".to_string(), 2)).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let ids = |entries: &Value| entries.as_array().unwrap().iter().map(|e| e["id"].as_i64().unwrap()).collect::<Vec<_>>();
+
+        // From the declaration in the header
+        let result = handlers.handle_tool_call("resolve_symbol", json!({
+            "index_name": "audio", "file_path": "mixer.h", "line": 2, "column": 7
+        })).await.unwrap();
+        assert_eq!(result["resolved_from"], "declaration");
+        assert_eq!(result["identifier"], "mix");
+        assert_eq!(ids(&result["symbols"][0]["definitions"]), [float_definition]);
+        assert_eq!(ids(&result["symbols"][0]["declarations"]), [float_declaration]);
+
+        // From a call site
+        let result = handlers.handle_tool_call("resolve_symbol", json!({
+            "index_name": "audio", "file_path": dir.path().join("app.cpp").to_string_lossy(), "line": 2, "column": 14
+        })).await.unwrap();
+        assert_eq!(result["resolved_from"], "reference");
+        assert_eq!(result["ambiguous"], false);
+        assert_eq!(result["symbols"][0]["qualified_name"], "audio::mix");
+        assert_eq!(ids(&result["symbols"][0]["declarations"]), [float_declaration]);
+
+        // Nothing under the cursor
+        let result = handlers.handle_tool_call("resolve_symbol", json!({
+            "index_name": "audio", "file_path": "app.cpp", "line": 2, "column": 2
+        })).await.unwrap();
+        assert_eq!(result["symbols"], json!([]));
+        assert!(handlers.handle_tool_call("resolve_symbol", json!({"index_name": "audio", "file_path": "app.cpp", "line": 2})).await.is_err());
+
+        // An open document answers from its unsaved text, where the call moved down a line
+        let text = "int main() {\n    float level = 0.5f;\n    audio::mix(level);\n}\n";
+        handlers.handle_tool_call("did_open_document", json!({"index_name": "audio", "file_path": "app.cpp", "text": text, "version": 1})).await.unwrap();
+        let result = handlers.handle_tool_call("resolve_symbol", json!({
+            "index_name": "audio", "file_path": "app.cpp", "line": 3, "column": 12
+        })).await.unwrap();
+        assert_eq!(result["document_version"], 1);
+        assert_eq!(result["resolved_from"], "name");
+        assert_eq!(result["ambiguous"], true);
+
+        let edit = json!([{"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 0}}, "text": "// entry\n"}]);
+        handlers.handle_tool_call("did_change_document", json!({
+            "index_name": "audio", "file_path": "app.cpp", "version": 2, "content_changes": edit
+        })).await.unwrap();
+        let result = handlers.handle_tool_call("resolve_symbol", json!({
+            "index_name": "audio", "file_path": "app.cpp", "line": 4, "column": 12
+        })).await.unwrap();
+        assert_eq!((result["document_version"].as_i64(), result["identifier"].as_str()), (Some(2), Some("mix")));
+        assert!(handlers.handle_tool_call("did_change_document", json!({
+            "index_name": "audio", "file_path": "app.cpp", "version": 2, "content_changes": edit
+        })).await.is_err());
+
+        // Closed documents are answered from disk and the index again
+        let closed = handlers.handle_tool_call("did_close_document", json!({"index_name": "audio", "file_path": "app.cpp"})).await.unwrap();
+        assert_eq!(closed["closed"], true);
+        let outline = handlers.handle_tool_call("get_document_symbols", json!({"index_name": "audio", "file_path": "app.cpp"})).await.unwrap();
+        assert_eq!(outline["source"], "index");
+        assert_eq!(ids(&outline["symbols"]), [main]);
+        let result = handlers.handle_tool_call("resolve_symbol", json!({
+            "index_name": "audio", "file_path": "app.cpp", "line": 2, "column": 14
+        })).await.unwrap();
+        assert_eq!(result["resolved_from"], "reference");
+        assert!(result["document_version"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_usr_separates_overloads() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/src/audio".to_string())).unwrap();
+        // libclang spells the definitions' signatures differently from the header's
+        let mix = |file: &str, line, signature: &str, usr: &str, is_declaration| {
+            let element = CodeElement::new(index.id, "mix".to_string(), SymbolType::Function, file.to_string(), line, 6, "a".repeat(64))
+                .with_scope("audio".to_string())
+                .with_signature(signature.to_string())
+                .with_usr(usr.to_string())
+                .with_declaration(is_declaration);
+            repository.create_code_element(element).unwrap().id.unwrap()
+        };
+        let float_declaration = mix("mixer.h", 2, "void mix(float gain)", "c:@N@audio@F@mix#f#", true);
+        let double_declaration = mix("mixer.h", 3, "void mix(double gain)", "c:@N@audio@F@mix#d#", true);
+        let float_definition = mix("mixer.cpp", 10, "void (float)", "c:@N@audio@F@mix#f#", false);
+        let double_definition = mix("mixer.cpp", 20, "void (double)", "c:@N@audio@F@mix#d#", false);
+        let main = repository
+            .create_code_element(CodeElement::new(index.id, "main".to_string(), SymbolType::Function, "app.cpp".to_string(), 1, 5, "a".repeat(64)))
+            .unwrap()
+            .id
+            .unwrap();
+        for (target, line) in [(float_definition, 2), (double_definition, 3), (double_definition, 4)] {
+            repository.create_symbol_relationship(SymbolRelationship::new(main, target, RelationshipType::Calls, "app.cpp".to_string(), line)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let ids = |entries: &Value| entries.as_array().unwrap().iter().map(|e| e["id"].as_i64().unwrap()).collect::<Vec<_>>();
+
+        let details = handlers.handle_tool_call("get_symbol_details", json!({"index_name": "audio", "symbol_id": double_declaration})).await.unwrap();
+        assert_eq!(details["usr"], "c:@N@audio@F@mix#d#");
+        assert_eq!(ids(&details["definitions"]), [double_definition]);
+        assert_eq!(ids(&details["declarations"]), [double_declaration]);
+
+        let all = handlers.handle_tool_call("find_references", json!({"index_name": "audio", "symbol_name": "mix"})).await.unwrap();
+        assert_eq!(all["total_count"], 7);
+        let float = handlers.handle_tool_call("find_references", json!({
+            "index_name": "audio", "symbol_name": "mix", "usr": "c:@N@audio@F@mix#f#"
+        })).await.unwrap();
+        assert_eq!(float["total_count"], 3);
+        assert_eq!(ids(&float["symbols"]), [float_declaration, float_definition, float_definition]);
+    }
+
+    #[tokio::test]
+    async fn test_list_index_errors() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("app".to_string(), "/app".to_string())).unwrap();
+        for (file, kind, message) in [
+            ("gen/huge.cpp", IndexErrorKind::Timeout, "Parsing took longer than 120s"),
+            ("broken.cpp", IndexErrorKind::Parse, "expected ';'"),
+            ("crash.cpp", IndexErrorKind::Panic, "index out of bounds"),
+        ] {
+            repository.record_index_error(&IndexError::new(index.id, file.to_string(), kind, message.to_string())).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let errors = handlers.handle_tool_call("list_index_errors", json!({"index_name": "app"})).await.unwrap();
+        assert_eq!(errors["total_count"], 3);
+        assert_eq!(errors["errors"][0]["file_path"], "broken.cpp");
+        assert_eq!(errors["kind_counts"], json!({"panic": 1, "parse": 1, "timeout": 1}));
+
+        let timeouts = handlers.handle_tool_call("list_index_errors", json!({"index_name": "app", "kind": "timeout"})).await.unwrap();
+        assert_eq!(timeouts["errors"], json!([{
+            "file_path": "gen/huge.cpp",
+            "kind": "timeout",
+            "message": "Parsing took longer than 120s",
+            "recorded_at": timeouts["errors"][0]["recorded_at"]
+        }]));
+        assert!(handlers.handle_tool_call("list_index_errors", json!({"index_name": "app", "kind": "oom"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_index_insights() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let repository = repository();
+        let index = repository.create_code_index(CodeIndex::new("app".to_string(), "/app".to_string())).unwrap();
+        let function = |name: &str, line| {
+            let element = CodeElement::new(index.id, name.to_string(), SymbolType::Function, "app.cpp".to_string(), line, 1, "a".repeat(64));
+            repository.create_code_element(element.with_scope("app".to_string())).unwrap().id.unwrap()
+        };
+        let log = function("log", 1);
+        let (run, stop) = (function("run", 5), function("stop", 9));
+        for caller in [run, stop] {
+            repository.create_symbol_relationship(SymbolRelationship::new(caller, log, RelationshipType::Calls, "app.cpp".to_string(), 6)).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let insights = handlers.handle_tool_call("get_index_insights", json!({"index_name": "app", "top": 1})).await.unwrap();
+        assert_eq!(insights["fan_in"][0]["qualified_name"], "app::log");
+        assert_eq!(insights["fan_in"][0]["count"], 2);
+        assert_eq!(insights["fan_out"].as_array().unwrap().len(), 1);
+        assert_eq!(insights["busiest_files"], json!([{"file_path": "app.cpp", "count": 3}]));
+    }
+
+    #[tokio::test]
+    async fn test_find_symbols_in_section() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = CodeIndex::new("fw".to_string(), "/fw".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        for (name, symbol_type, section) in [
+            ("fir_filter", SymbolType::Function, ".itcm"),
+            ("fft", SymbolType::Function, ".itcm.text"),
+            ("coefficients", SymbolType::Variable, ".itcm"),
+            ("isr", SymbolType::Function, ".iram1"),
+        ] {
+            let element = CodeElement::new(index_id, name.to_string(), symbol_type, "src/dsp.c".to_string(), 1, 1, "a".repeat(64))
+                .with_memory_section(section.to_string());
+            repository.create_code_element(element).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let functions = handlers.handle_tool_call("find_symbols_in_section", json!({
+            "index_name": "fw",
+            "section": ".itcm",
+            "symbol_type": "function"
+        })).await.unwrap();
+        let names: BTreeSet<&str> = functions["symbols"].as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, BTreeSet::from(["fft", "fir_filter"]));
+        assert_eq!(functions["total_count"], 2);
+
+        let sections = handlers.handle_tool_call("find_symbols_in_section", json!({"index_name": "fw"})).await.unwrap();
+        assert_eq!(sections["sections"], json!({".iram1": 1, ".itcm": 2, ".itcm.text": 1}));
+    }
+
+    #[tokio::test]
+    async fn test_conditional_compilation_matrix() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("io.cpp"), "void open_file() {}\n#ifdef _WIN32\nvoid open_handle() {}\n#else\nvoid open_fd() {}\n#endif\n").unwrap();
+        std::fs::write(dir.path().join("win.cpp"), "#if defined(_WIN32)\nvoid registry() {}\n#endif\n").unwrap();
+
+        let repository = repository();
+        let index = CodeIndex::new("io".to_string(), dir.path().to_string_lossy().to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        for (name, file, line) in [("open_file", "io.cpp", 1), ("open_handle", "io.cpp", 3), ("open_fd", "io.cpp", 5), ("registry", "win.cpp", 2)] {
+            repository.create_code_element(CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64))).unwrap();
+        }
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let report = handlers.handle_tool_call("conditional_compilation_matrix", json!({
+            "index_name": "io",
+            "configurations": [
+                {"name": "windows", "defines": ["_WIN32"]},
+                {"name": "linux", "defines": ["__linux__=1"]}
+            ]
+        })).await.unwrap();
+
+        assert_eq!(report["common_files"], 1);
+        assert_eq!(report["common_symbols"], 1);
+        assert_eq!(report["divergent_files"][0]["name"], "win.cpp");
+        assert_eq!(report["divergent_files"][0]["present_in"], json!(["windows"]));
+        assert_eq!(report["divergent_symbol_count"], 3);
+        assert_eq!(report["configurations"][0]["unique_symbols"], 2);
+        assert_eq!(report["configurations"][1]["symbol_count"], 2);
+
+        let single = handlers.handle_tool_call("conditional_compilation_matrix", json!({
+            "index_name": "io",
+            "configurations": [{"name": "windows", "defines": ["_WIN32"]}]
+        })).await;
+        assert!(single.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_annotations_round_trip() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = CodeIndex::new("app".to_string(), "/app".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let symbol_id = repository.create_code_element(CodeElement::new(index_id, "main".to_string(), SymbolType::Function, "main.cpp".to_string(), 1, 1, "a".repeat(64))).unwrap().id;
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let created = handlers.handle_tool_call("annotate_symbol", json!({
+            "index_name": "app",
+            "symbol_name": "main",
+            "text": "Main entry point",
+            "author": "dana"
+        })).await.unwrap();
+        let id = created["annotation"]["id"].as_i64().unwrap();
+        handlers.handle_tool_call("annotate_symbol", json!({"index_name": "app", "symbol_name": "main", "kind": "bookmark"})).await.unwrap();
+        assert!(handlers.handle_tool_call("annotate_symbol", json!({"index_name": "app", "symbol_name": "main"})).await.is_err());
+
+        // Annotations come back with the symbol's references
+        let references = handlers.handle_tool_call("find_references", json!({"index_name": "app", "symbol_name": "main"})).await.unwrap();
+        assert_eq!(references["annotations"][0]["text"], "Main entry point");
+        assert_eq!(references["annotations"][1]["author"], "assistant");
+        let details = handlers.handle_tool_call("get_symbol_details", json!({"index_name": "app", "symbol_id": symbol_id})).await.unwrap();
+        assert_eq!(details["annotations"][0]["text"], "Main entry point");
+
+        let mine = handlers.handle_tool_call("list_annotations", json!({"index_name": "app", "author": "dana"})).await.unwrap();
+        assert_eq!(mine["total_count"], 1);
+
+        handlers.handle_tool_call("delete_annotation", json!({"index_name": "app", "id": id})).await.unwrap();
+        assert!(handlers.handle_tool_call("delete_annotation", json!({"index_name": "app", "id": id})).await.is_err());
+        let remaining = handlers.handle_tool_call("list_annotations", json!({"index_name": "app", "symbol_name": "main"})).await.unwrap();
+        assert_eq!(remaining["annotations"][0]["kind"], "bookmark");
+    }
+
+    #[tokio::test]
+    async fn test_saved_queries_round_trip() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        let index = CodeIndex::new("fw".to_string(), "/fw".to_string());
+        let index_id = index.id;
+        repository.create_code_index(index).unwrap();
+        let element = |name: &str, file: &str, section: Option<&str>| {
+            let mut element = CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64));
+            if let Some(section) = section {
+                element = element.with_memory_section(section.to_string());
+            }
+            repository.create_code_element(element).unwrap();
+        };
+        element("gpio_isr", "src/drivers/gpio.c", Some(".iram1"));
+        element("uart_isr", "src/drivers/uart.c", Some(".iram1"));
+        element("app_main", "src/main.c", None);
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        handlers.handle_tool_call("save_query", json!({
+            "index_name": "fw",
+            "name": "hot-functions",
+            "query": "type:function section:.iram1",
+            "description": "Functions in IRAM"
+        })).await.unwrap();
+        assert!(handlers.handle_tool_call("save_query", json!({"index_name": "fw", "name": "bad", "query": "colour:red"})).await.is_err());
+
+        let listed = handlers.handle_tool_call("list_saved_queries", json!({"index_name": "fw"})).await.unwrap();
+        assert_eq!(listed["saved_queries"][0]["description"], "Functions in IRAM");
+
+        let result = handlers.handle_tool_call("run_saved_query", json!({"index_name": "fw", "name": "hot-functions", "limit": 1})).await.unwrap();
+        assert_eq!(result["total_count"], 2);
+        assert_eq!(result["truncated"], true);
+        assert_eq!(result["symbols"][0]["name"], "gpio_isr");
+
+        // Watching records a baseline; checks report what changed since
+        handlers.handle_tool_call("watch_query", json!({"index_name": "fw", "name": "hot-functions"})).await.unwrap();
+        let quiet = handlers.handle_tool_call("check_watches", json!({"index_name": "fw"})).await.unwrap();
+        assert_eq!(quiet["watched_count"], 1);
+        assert_eq!(quiet["watch_changes"].as_array().unwrap().len(), 0);
+        {
+            let repository = handlers.repository().unwrap().lock().unwrap();
+            repository
+                .create_code_element(
+                    CodeElement::new(index_id, "spi_isr".to_string(), SymbolType::Function, "src/drivers/spi.c".to_string(), 1, 1, "a".repeat(64))
+                        .with_memory_section(".iram1".to_string()),
+                )
+                .unwrap();
+        }
+        let changed = handlers.handle_tool_call("check_watches", json!({"index_name": "fw"})).await.unwrap();
+        assert_eq!(changed["watch_changes"][0]["added"][0], "src/drivers/spi.c: spi_isr (function)");
+
+        handlers.handle_tool_call("delete_saved_query", json!({"index_name": "fw", "name": "hot-functions"})).await.unwrap();
+        assert!(handlers.handle_tool_call("run_saved_query", json!({"index_name": "fw", "name": "hot-functions"})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_index_tags_and_list_filter() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let repository = repository();
+        repository.create_code_index(CodeIndex::new("engine".to_string(), "/src/engine".to_string())).unwrap();
+        repository.create_code_index(CodeIndex::new("tools".to_string(), "/src/tools".to_string())).unwrap();
+
+        let mut handlers = ToolHandlers::new().unwrap().with_repository(Arc::new(Mutex::new(repository)));
+        let result = handlers.handle_tool_call("set_index_tags", json!({
+            "index_name": "engine",
+            "tags": { "team": "rendering", "branch": "main" }
+        })).await.unwrap();
+        assert_eq!(result["tags"]["team"], "rendering");
+
+        let result = handlers.handle_tool_call("set_index_tags", json!({
+            "index_name": "engine",
+            "remove": ["branch"]
+        })).await.unwrap();
+        assert!(result["tags"].get("branch").is_none());
+
+        let all = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        assert_eq!(all["total_count"], 2);
+
+        let filtered = handlers.handle_tool_call("list_indices", json!({
+            "tags": { "team": "rendering" },
+            "include_stats": false
+        })).await.unwrap();
+        assert_eq!(filtered["total_count"], 1);
+        assert_eq!(filtered["indices"][0]["name"], "engine");
+        assert!(filtered["indices"][0].get("total_files").is_none());
+
+        let invalid = handlers.handle_tool_call("set_index_tags", json!({
+            "index_name": "engine",
+            "tags": { "bad key": "x" }
+        })).await;
+        assert!(invalid.is_err());
+
+        let mut handlers = handlers.with_read_only(true);
+        let rejected = handlers.handle_tool_call("set_index_tags", json!({
+            "index_name": "engine",
+            "tags": { "team": "tools" }
+        })).await;
+        assert!(rejected.unwrap_err().to_string().contains("read-only"));
+    }
+
+    #[tokio::test]
+    async fn test_indices_served_from_several_databases() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+
+        let element = |index: &CodeIndex, name: &str| {
+            CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/main.cpp".to_string(), 1, 1, "a".repeat(64))
+        };
+        let repository = repository();
+        let app = repository.create_code_index(CodeIndex::new("app".to_string(), "/src/app".to_string())).unwrap();
+        repository.create_code_element(element(&app, "run_app")).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let firmware = DatabaseConfig::new(dir.path().join("firmware.db"));
+        let other = Repository::new(DatabaseManager::new(firmware.clone()).unwrap().connect().unwrap());
+        let fw = other.create_code_index(CodeIndex::new("fw".to_string(), "/src/fw".to_string())).unwrap();
+        other.create_code_element(element(&fw, "run_firmware")).unwrap();
+        drop(other);
+
+        let registry = RepositoryRegistry::new().with_database("fw", firmware.clone());
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::new(Mutex::new(repository)))
+            .with_registry(Arc::new(Mutex::new(registry)));
+
+        // Each call is answered from the database of the index it names
+        let search = |index_name: &str| json!({"index_name": index_name, "query": "run"});
+        let result = handlers.handle_tool_call("search_symbols", search("app")).await.unwrap();
+        assert_eq!(result["symbols"][0]["name"], "run_app");
+        let result = handlers.handle_tool_call("search_symbols", search("fw")).await.unwrap();
+        assert_eq!(result["symbols"][0]["name"], "run_firmware");
+
+        let listed = handlers.handle_tool_call("list_indices", json!({})).await.unwrap();
+        let names: Vec<&str> = listed["indices"].as_array().unwrap().iter().map(|index| index["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["app", "fw"]);
+
+        handlers
+            .handle_tool_call("annotate_symbol", json!({"index_name": "fw", "symbol_name": "run_firmware", "text": "Boot entry"}))
+            .await
+            .unwrap();
+        let stored = Repository::new(DatabaseManager::new(firmware).unwrap().connect().unwrap());
+        assert_eq!(stored.list_annotations(&fw.id, None, None).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_find_references_pages_through_registered_index() {
+        use crate::lib::storage::models::code_index::CodeIndex;
+        use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+        let dir = tempfile::tempdir().unwrap();
+        let firmware = DatabaseConfig::new(dir.path().join("firmware.db"));
+        let other = Repository::new(DatabaseManager::new(firmware.clone()).unwrap().connect().unwrap());
+        let fw = other.create_code_index(CodeIndex::new("fw".to_string(), "/src/fw".to_string())).unwrap();
+        let element = |name: &str, line| {
+            CodeElement::new(fw.id, name.to_string(), SymbolType::Function, "src/boot.cpp".to_string(), line, 1, "a".repeat(64))
+        };
+        let target = other.create_code_element(element("init_clock", 1)).unwrap();
+        let caller = other.create_code_element(element("boot", 10)).unwrap();
+        for line in 11..=15 {
+            other.create_symbol_relationship(SymbolRelationship::new(
+                caller.id.unwrap(),
+                target.id.unwrap(),
+                RelationshipType::Calls,
+                "src/boot.cpp".to_string(),
+                line,
+            )).unwrap();
+        }
+        drop(other);
+
+        let registry = RepositoryRegistry::new().with_database("fw", firmware);
+        let mut handlers = ToolHandlers::new()
+            .unwrap()
+            .with_repository(Arc::new(Mutex::new(repository())))
+            .with_registry(Arc::new(Mutex::new(registry)));
+
+        // Continuations carry only the cursor, yet keep reading the registered database
+        let first = handlers.handle_tool_call("find_references", json!({
+            "index_name": "fw",
+            "symbol_name": "init_clock",
+            "include_declarations": false,
+            "page_size": 2
+        })).await.unwrap();
+        let mut lines: Vec<u64> = first["symbols"].as_array().unwrap().iter().map(|s| s["line_number"].as_u64().unwrap()).collect();
+        let mut cursor = first["next_cursor"].as_str().map(str::to_string);
+        while let Some(next) = cursor {
+            let page = handlers.handle_tool_call("find_references", json!({ "cursor": next })).await.unwrap();
+            lines.extend(page["symbols"].as_array().unwrap().iter().map(|s| s["line_number"].as_u64().unwrap()));
+            cursor = page["next_cursor"].as_str().map(str::to_string);
+        }
+        assert_eq!(lines, [11, 12, 13, 14, 15]);
+    }
+
+    #[tokio::test]
+    async fn test_explain_linker_error_without_repository() {
+        let mut handlers = ToolHandlers::new().unwrap();
+        let result = handlers.handle_tool_call("explain_linker_error", json!({
+            "index_name": "test",
+            "error_message": "undefined reference to `foo()'"
+        })).await;
+
+        assert!(result.is_err());
     }
 }
\ No newline at end of file
@@ -1,11 +1,23 @@
 use anyhow::{anyhow, Result};
+use serde::Serialize;
 use serde_json::{json, Value};
 // use std::io; // TODO: Enable when needed
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tracing::{debug, error, info, instrument, warn};
 
-use super::server::{McpRequest, McpResponse};
+use super::progress::{cancelled_request, CancellationRegistry};
+use super::server::{McpNotification, McpRequest, McpResponse};
+
+/// A message written to the client: a response to a request, or a notification
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum OutgoingMessage {
+    Response(McpResponse),
+    Notification(McpNotification),
+}
 
 /// STDIO Transport for MCP Protocol
 /// 
@@ -14,12 +26,14 @@ use super::server::{McpRequest, McpResponse};
 /// between the MCP client and server handlers.
 #[derive(Debug)]
 pub struct Transport {
-    /// Channel for sending requests to server
-    request_sender: Option<mpsc::Sender<McpRequest>>,
-    /// Channel for receiving responses from server
-    response_receiver: Option<mpsc::Receiver<McpResponse>>,
-    /// Response sender for internal use
-    response_sender: Option<mpsc::Sender<McpResponse>>,
+    /// Channel for receiving outgoing messages from server
+    response_receiver: Option<mpsc::Receiver<OutgoingMessage>>,
+    /// Outgoing message sender for internal use
+    response_sender: Option<mpsc::Sender<OutgoingMessage>>,
+    /// Flags of requests in flight, set by cancellations as they are read
+    cancellations: CancellationRegistry,
+    /// STDOUT writer task, finished by [`Transport::drain`]
+    writer: Option<JoinHandle<()>>,
     /// Flag to track if transport is running
     is_running: bool,
 }
@@ -28,9 +42,10 @@ impl Transport {
     /// Create new transport instance
     pub fn new() -> Result<Self> {
         Ok(Self {
-            request_sender: None,
             response_receiver: None,
             response_sender: None,
+            cancellations: CancellationRegistry::new(),
+            writer: None,
             is_running: false,
         })
     }
@@ -49,26 +64,25 @@ impl Transport {
         }
 
         // Set up response channel
-        let (response_tx, response_rx) = mpsc::channel::<McpResponse>(100);
+        let (response_tx, response_rx) = mpsc::channel::<OutgoingMessage>(100);
         self.response_sender = Some(response_tx);
         self.response_receiver = Some(response_rx);
-        self.request_sender = Some(server_sender);
 
-        // Start STDIN reader task
-        let request_sender = self.request_sender.as_ref().unwrap().clone();
+        // Start STDIN reader task; it holds the only request sender, so the server stops when stdin closes
+        let cancellations = self.cancellations.clone();
         tokio::spawn(async move {
-            if let Err(e) = Self::stdin_reader_task(request_sender).await {
+            if let Err(e) = Self::stdin_reader_task(server_sender, cancellations).await {
                 error!("STDIN reader task failed: {}", e);
             }
         });
 
         // Start STDOUT writer task
         let response_receiver = self.response_receiver.take().unwrap();
-        tokio::spawn(async move {
+        self.writer = Some(tokio::spawn(async move {
             if let Err(e) = Self::stdout_writer_task(response_receiver).await {
                 error!("STDOUT writer task failed: {}", e);
             }
-        });
+        }));
 
         self.is_running = true;
         info!("STDIO transport started successfully");
@@ -79,9 +93,29 @@ impl Transport {
     /// Send response message to client via STDOUT
     #[instrument(skip(self, response))]
     pub async fn send_response(&self, response: McpResponse) -> Result<()> {
+        self.send(OutgoingMessage::Response(response)).await
+    }
+
+    /// Send a notification to the client via STDOUT
+    #[instrument(skip(self, notification))]
+    pub async fn send_notification(&self, notification: McpNotification) -> Result<()> {
+        self.send(OutgoingMessage::Notification(notification)).await
+    }
+
+    /// Sender for notifications from code that can't wait, None until started
+    pub fn notifier(&self) -> Option<mpsc::Sender<OutgoingMessage>> {
+        self.response_sender.clone()
+    }
+
+    /// Flags of the requests being handled, which cancellations set
+    pub fn cancellations(&self) -> &CancellationRegistry {
+        &self.cancellations
+    }
+
+    async fn send(&self, message: OutgoingMessage) -> Result<()> {
         if let Some(sender) = &self.response_sender {
-            sender.send(response).await
-                .map_err(|e| anyhow!("Failed to send response: {}", e))?;
+            sender.send(message).await
+                .map_err(|e| anyhow!("Failed to send message: {}", e))?;
             Ok(())
         } else {
             Err(anyhow!("Transport not started"))
@@ -89,8 +123,11 @@ impl Transport {
     }
 
     /// STDIN reader task - reads JSON-RPC messages from STDIN
-    #[instrument(skip(request_sender))]
-    async fn stdin_reader_task(request_sender: mpsc::Sender<McpRequest>) -> Result<()> {
+    ///
+    /// Cancellations are applied here rather than forwarded, so they take
+    /// effect while the server is still busy with the request they cancel.
+    #[instrument(skip(request_sender, cancellations))]
+    async fn stdin_reader_task(request_sender: mpsc::Sender<McpRequest>, cancellations: CancellationRegistry) -> Result<()> {
         info!("Starting STDIN reader task");
 
         let stdin = tokio::io::stdin();
@@ -113,6 +150,13 @@ impl Transport {
 
                     debug!("Received raw message: {}", line);
 
+                    if let Some(id) = Self::parse_cancellation(line) {
+                        if !cancellations.cancel(&id) {
+                            debug!("Ignoring cancellation of request {}, which is not running", id);
+                        }
+                        continue;
+                    }
+
                     match Self::parse_request(line) {
                         Ok(request) => {
                             debug!("Parsed request: {:?}", request);
@@ -135,7 +179,7 @@ impl Transport {
                                 }),
                             };
                             
-                            if let Err(e) = Self::write_response_to_stdout(&error_response).await {
+                            if let Err(e) = Self::write_message_to_stdout(&OutgoingMessage::Response(error_response)).await {
                                 error!("Failed to send error response: {}", e);
                             }
                         }
@@ -152,15 +196,15 @@ impl Transport {
         Ok(())
     }
 
-    /// STDOUT writer task - writes JSON-RPC responses to STDOUT
+    /// STDOUT writer task - writes JSON-RPC responses and notifications to STDOUT
     #[instrument(skip(response_receiver))]
-    async fn stdout_writer_task(mut response_receiver: mpsc::Receiver<McpResponse>) -> Result<()> {
+    async fn stdout_writer_task(mut response_receiver: mpsc::Receiver<OutgoingMessage>) -> Result<()> {
         info!("Starting STDOUT writer task");
 
-        while let Some(response) = response_receiver.recv().await {
-            debug!("Sending response: {:?}", response);
+        while let Some(message) = response_receiver.recv().await {
+            debug!("Sending message: {:?}", message);
             
-            if let Err(e) = Self::write_response_to_stdout(&response).await {
+            if let Err(e) = Self::write_message_to_stdout(&message).await {
                 error!("Failed to write response to STDOUT: {}", e);
                 // Continue processing other responses
             }
@@ -193,11 +237,16 @@ impl Transport {
         Ok(request)
     }
 
-    /// Write response to STDOUT as JSON-RPC message
-    #[instrument(skip(response))]
-    async fn write_response_to_stdout(response: &McpResponse) -> Result<()> {
-        let json_str = serde_json::to_string(response)
-            .map_err(|e| anyhow!("Failed to serialize response: {}", e))?;
+    /// Id of the request a cancellation notification names, None for any other message
+    fn parse_cancellation(line: &str) -> Option<Value> {
+        serde_json::from_str::<Value>(line).ok().as_ref().and_then(cancelled_request)
+    }
+
+    /// Write a message to STDOUT as JSON-RPC message
+    #[instrument(skip(message))]
+    async fn write_message_to_stdout(message: &OutgoingMessage) -> Result<()> {
+        let json_str = serde_json::to_string(message)
+            .map_err(|e| anyhow!("Failed to serialize message: {}", e))?;
 
         let mut stdout = tokio::io::stdout();
         stdout.write_all(json_str.as_bytes()).await
@@ -207,15 +256,24 @@ impl Transport {
         stdout.flush().await
             .map_err(|e| anyhow!("Failed to flush STDOUT: {}", e))?;
 
-        debug!("Response written to STDOUT: {}", json_str);
+        debug!("Message written to STDOUT: {}", json_str);
         Ok(())
     }
 
+    /// Stops accepting messages and waits up to `timeout` for those already sent to be written
+    pub async fn drain(&mut self, timeout: Duration) {
+        self.response_sender = None;
+        if let Some(writer) = self.writer.take() {
+            if tokio::time::timeout(timeout, writer).await.is_err() {
+                warn!("Gave up writing pending messages after {:?}", timeout);
+            }
+        }
+    }
+
     /// Stop the transport layer
     pub fn stop(&mut self) {
         info!("Stopping STDIO transport");
         self.is_running = false;
-        self.request_sender = None;
         self.response_sender = None;
         self.response_receiver = None;
     }
@@ -375,6 +433,26 @@ mod tests {
         assert_eq!(message_type, "response");
     }
 
+    #[test]
+    fn test_outgoing_message_serialization() {
+        let notification = McpNotification::log("info", "watch", json!({"name": "hot-functions"}));
+        let message = serde_json::to_string(&OutgoingMessage::Notification(notification)).unwrap();
+        assert_eq!(Transport::get_message_type(&message).unwrap(), "request:notifications/message");
+
+        let parsed: Value = serde_json::from_str(&message).unwrap();
+        assert!(parsed.get("id").is_none());
+        assert_eq!(parsed["params"]["data"]["name"], "hot-functions");
+    }
+
+    #[test]
+    fn test_parse_cancellation() {
+        let cancel = r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":3,"reason":"timeout"}}"#;
+        assert_eq!(Transport::parse_cancellation(cancel), Some(json!(3)));
+        assert_eq!(Transport::parse_cancellation(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":4}}"#), Some(json!(4)));
+        assert_eq!(Transport::parse_cancellation(&Transport::create_test_message("tools/call", json!({}))), None);
+        assert_eq!(Transport::parse_cancellation("not json"), None);
+    }
+
     #[tokio::test]
     async fn test_transport_creation() {
         let transport = Transport::new().unwrap();
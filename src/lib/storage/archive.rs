@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::recovery::io_error;
+use crate::lib::storage::repository::{IndexContents, Repository};
+use crate::lib::storage::schema::CURRENT_SCHEMA_VERSION;
+
+/// First bytes of every archive
+pub const ARCHIVE_MAGIC: &[u8; 8] = b"CPIDXARC";
+
+/// Layout version of the archive body; bumped when older readers can't load it
+///
+/// Version 2 added symbol tags, annotations, build configurations, walk
+/// rules and symbol bodies; version 1 archives load without them.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 2;
+
+/// Conventional file extension for archives
+pub const ARCHIVE_EXTENSION: &str = "cpidx";
+
+/// A complete index in a form that can move between databases
+///
+/// On disk an archive is the magic bytes, the format version as a
+/// little-endian u32, then the gzip-compressed JSON of this struct. The
+/// version sits outside the compressed body so a reader can refuse a newer
+/// archive without decoding it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexArchive {
+    /// Schema version of the database the archive was exported from
+    pub schema_version: i32,
+    pub exported_at: DateTime<Utc>,
+    pub index: CodeIndex,
+    #[serde(flatten)]
+    pub contents: IndexContents,
+}
+
+impl IndexArchive {
+    /// Reads everything stored for the named index
+    pub fn from_index(repository: &Repository, name: &str) -> Result<Self> {
+        let index = repository
+            .get_code_index_by_name(name)?
+            .ok_or_else(|| StorageError::not_found("Index", name))?;
+
+        Ok(Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: Utc::now(),
+            contents: repository.get_index_contents(&index.id)?,
+            index,
+        })
+    }
+
+    /// Writes the archive in its compressed, versioned form
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        writer.write_all(ARCHIVE_MAGIC).map_err(|e| io_error("Failed to write archive", e))?;
+        writer
+            .write_all(&ARCHIVE_FORMAT_VERSION.to_le_bytes())
+            .map_err(|e| io_error("Failed to write archive", e))?;
+
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        serde_json::to_writer(&mut encoder, self)
+            .map_err(|e| io_error("Failed to write archive", std::io::Error::from(e)))?;
+        encoder.finish().map_err(|e| io_error("Failed to write archive", e))?;
+        Ok(())
+    }
+
+    /// Reads an archive written by `write_to`
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let mut header = [0u8; 12];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| StorageError::Validation("Not an index archive: file is too short".to_string()))?;
+        if &header[..8] != ARCHIVE_MAGIC {
+            return Err(StorageError::Validation("Not an index archive".to_string()));
+        }
+        let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if version > ARCHIVE_FORMAT_VERSION {
+            return Err(StorageError::Validation(format!(
+                "Archive format version {} is newer than this build supports ({})",
+                version, ARCHIVE_FORMAT_VERSION
+            )));
+        }
+
+        serde_json::from_reader(GzDecoder::new(reader)).map_err(|e| StorageError::Corruption(format!("Unreadable index archive: {}", e)))
+    }
+
+    /// Stores the archived index as a new index
+    ///
+    /// The index gets a fresh id, so an archive can be imported into the
+    /// database it was exported from under another name. `name` and
+    /// `base_path` replace the archived values, e.g. when a CI build is
+    /// imported on a machine with the checkout elsewhere.
+    pub fn restore(self, repository: &Repository, name: Option<&str>, base_path: Option<&str>) -> Result<CodeIndex> {
+        let mut index = CodeIndex::new(
+            name.unwrap_or(&self.index.name).to_string(),
+            base_path.unwrap_or(&self.index.base_path).to_string(),
+        );
+        if repository.get_code_index_by_name(&index.name)?.is_some() {
+            return Err(StorageError::Conflict(format!("Index '{}' already exists", index.name)));
+        }
+        index.created_at = self.index.created_at;
+        index.update_stats(self.index.total_files, self.index.total_symbols);
+        index.index_version = self.index.index_version;
+
+        repository.restore_index(index, self.contents)
+    }
+}
+
+/// Exports the named index to an archive file
+pub fn export_index(repository: &Repository, name: &str, path: &Path) -> Result<IndexArchive> {
+    let archive = IndexArchive::from_index(repository, name)?;
+    let file = File::create(path).map_err(|e| io_error("Failed to create archive", e))?;
+    let mut writer = BufWriter::new(file);
+    archive.write_to(&mut writer)?;
+    writer.flush().map_err(|e| io_error("Failed to write archive", e))?;
+    Ok(archive)
+}
+
+/// Imports an archive file as a new index
+pub fn import_index(repository: &Repository, path: &Path, name: Option<&str>, base_path: Option<&str>) -> Result<CodeIndex> {
+    let file = File::open(path).map_err(|e| io_error("Failed to open archive", e))?;
+    IndexArchive::read_from(BufReader::new(file))?.restore(repository, name, base_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::build_configuration::BuildConfiguration;
+    use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+    use crate::lib::storage::models::file_metadata::FileMetadata;
+    use crate::lib::storage::models::index_tag::IndexTag;
+    use crate::lib::storage::models::symbol_annotation::{AnnotationKind, SymbolAnnotation};
+    use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+    use crate::lib::storage::models::walk_rules::WalkRules;
+
+    fn repository() -> Repository {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        Repository::new(manager.connect().unwrap())
+    }
+
+    /// An index with two files, three functions and two calls, plus what users and configurations add to it
+    fn populate(repository: &Repository) -> CodeIndex {
+        let index = repository.create_code_index(CodeIndex::new("engine".to_string(), "/ci/engine".to_string())).unwrap();
+        repository.set_index_tag(&IndexTag::new(index.id, "branch".to_string(), "main".to_string())).unwrap();
+
+        for (file, symbols) in [("src/main.cpp", 1), ("src/core.cpp", 2)] {
+            let mut metadata = FileMetadata::new(index.id, file.to_string(), "b".repeat(64), Utc::now(), 100);
+            metadata.symbol_count = symbols;
+            let metadata = repository.create_file_metadata(metadata).unwrap();
+            repository.update_file_metadata(&metadata).unwrap();
+        }
+        let element = |name: &str, file: &str| {
+            let element = CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64));
+            let body = format!("void {}() {{}}", name);
+            repository.create_code_element(element.with_body(body)).unwrap().id.unwrap()
+        };
+        let main = element("main", "src/main.cpp");
+        let init = element("init", "src/core.cpp");
+        let log = element("log", "src/core.cpp");
+        for (from, to, line) in [(main, init, 2), (init, log, 3)] {
+            repository
+                .create_symbol_relationship(SymbolRelationship::new(from, to, RelationshipType::Calls, "src/main.cpp".to_string(), line))
+                .unwrap();
+        }
+
+        repository.add_symbol_tag(log, "hot").unwrap();
+        let note = SymbolAnnotation::new(index.id, "init".to_string(), "dana".to_string(), AnnotationKind::Note, "Runs once".to_string());
+        repository.create_annotation(note).unwrap();
+        let debug = repository.save_build_configuration(BuildConfiguration::new(index.id, "debug".to_string()).with_define("DEBUG", "1")).unwrap();
+        repository.replace_configuration_exclusions(&index.id, &[(debug.id.unwrap(), log)]).unwrap();
+        repository.save_walk_rules(&WalkRules::new(index.id, Vec::new(), vec!["third_party/**".to_string()], true)).unwrap();
+        index
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = repository();
+        populate(&source);
+        let mut bytes = Vec::new();
+        IndexArchive::from_index(&source, "engine").unwrap().write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[..8], ARCHIVE_MAGIC);
+
+        let target = repository();
+        let archive = IndexArchive::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(archive.contents.elements.len(), 3);
+        let index = archive.restore(&target, None, Some("/home/dev/engine")).unwrap();
+        assert_eq!(index.name, "engine");
+        assert_eq!(index.base_path, "/home/dev/engine");
+        assert_eq!(index.total_symbols, 3);
+        assert_eq!(target.get_index_tags(&index.id).unwrap()["branch"], "main");
+        assert_eq!(target.list_file_metadata(&index.id).unwrap().len(), 2);
+
+        let init = &target.find_code_elements_by_name(&index.id, "init").unwrap()[0];
+        let (outgoing, incoming) = target.get_symbol_relationships(init.id.unwrap()).unwrap();
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(target.get_code_element(outgoing[0].to_symbol_id).unwrap().unwrap().symbol_name, "log");
+        assert_eq!(target.get_symbol_popularity(&[init.id.unwrap()]).unwrap()[&init.id.unwrap()].reference_count, 1);
+
+        let log = &target.find_code_elements_by_name(&index.id, "log").unwrap()[0];
+        assert_eq!(target.get_symbol_tags(log.id.unwrap()).unwrap(), ["hot"]);
+        assert_eq!(target.get_symbol_body(init.id.unwrap()).unwrap().as_deref(), Some("void init() {}"));
+        assert_eq!(target.get_symbol_annotations(&index.id, "init", None).unwrap()[0].text, "Runs once");
+        let debug = target.get_build_configuration(&index.id, "debug").unwrap().unwrap();
+        assert_eq!(debug.defines["DEBUG"], "1");
+        assert_eq!(target.list_configuration_exclusions(debug.id.unwrap()).unwrap(), [log.id.unwrap()]);
+        assert_eq!(target.get_walk_rules(&index.id).unwrap().unwrap().exclude_patterns, ["third_party/**"]);
+    }
+
+    #[test]
+    fn test_import_into_same_database_needs_new_name() {
+        let repository = repository();
+        let original = populate(&repository);
+        let mut bytes = Vec::new();
+        IndexArchive::from_index(&repository, "engine").unwrap().write_to(&mut bytes).unwrap();
+
+        let archive = IndexArchive::read_from(bytes.as_slice()).unwrap();
+        assert!(matches!(archive.clone().restore(&repository, None, None), Err(StorageError::Conflict(_))));
+        let copy = archive.restore(&repository, Some("engine-ci"), None).unwrap();
+        assert_ne!(copy.id, original.id);
+        assert_eq!(repository.list_index_relationships(&copy.id).unwrap().len(), 2);
+        assert_eq!(repository.list_index_relationships(&original.id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rejects_foreign_and_newer_archives() {
+        assert!(matches!(IndexArchive::read_from(&b"PK\x03\x04 not an archive"[..]), Err(StorageError::Validation(_))));
+
+        let mut newer = ARCHIVE_MAGIC.to_vec();
+        newer.extend_from_slice(&(ARCHIVE_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(IndexArchive::read_from(newer.as_slice()), Err(StorageError::Validation(_))));
+
+        let mut truncated = ARCHIVE_MAGIC.to_vec();
+        truncated.extend_from_slice(&ARCHIVE_FORMAT_VERSION.to_le_bytes());
+        truncated.extend_from_slice(b"\x1f\x8b");
+        assert!(matches!(IndexArchive::read_from(truncated.as_slice()), Err(StorageError::Corruption(_))));
+    }
+}
@@ -0,0 +1,348 @@
+use rusqlite::{Transaction, TransactionBehavior};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::file_metadata::FileMetadata;
+use crate::lib::storage::models::index_error::IndexError;
+use crate::lib::storage::repository::Repository;
+
+/// Rows written per transaction before the writer commits and checks for queries
+pub const DEFAULT_CHUNK_SIZE: usize = 250;
+
+/// Longest a single write transaction stays open
+pub const DEFAULT_MAX_CHUNK_DURATION_MS: u64 = 20;
+
+/// Longest the writer pauses for waiting queries before it resumes anyway
+pub const DEFAULT_MAX_YIELD_MS: u64 = 100;
+
+/// Interval at which a yielding writer re-checks for waiting queries
+const YIELD_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Signals bulk writers that interactive queries are waiting
+///
+/// Query handlers hold a [`PriorityGuard`] while they run; writers finish their
+/// current chunk early and pause between chunks while any guard is alive. The
+/// gate is cheap to clone and shared between the server and the indexer.
+#[derive(Debug, Clone, Default)]
+pub struct PriorityGate {
+    waiting: Arc<AtomicUsize>,
+}
+
+/// Marks an interactive query as in flight until dropped
+#[derive(Debug)]
+pub struct PriorityGuard {
+    waiting: Arc<AtomicUsize>,
+}
+
+/// Chunking and yield settings for bulk writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchWriteConfig {
+    /// Maximum rows per transaction
+    pub chunk_size: usize,
+    /// Maximum time a transaction stays open
+    pub max_chunk_duration: Duration,
+    /// Maximum pause between chunks while queries are waiting
+    pub max_yield: Duration,
+}
+
+/// Writes large batches in short transactions with yield points between them
+///
+/// Use a repository opened on its own connection: in WAL mode readers on other
+/// connections never wait for the writer, and the short transactions keep the
+/// write lock free for the server's own writes.
+#[derive(Debug)]
+pub struct BatchWriter<'a> {
+    repository: &'a Repository,
+    gate: PriorityGate,
+    config: BatchWriteConfig,
+}
+
+/// Outcome of a batch write
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchWriteReport {
+    /// Rows written
+    pub rows_written: usize,
+    /// Transactions committed
+    pub chunks: usize,
+    /// Times the writer paused for waiting queries
+    pub yields: usize,
+    /// Total time spent paused
+    pub yielded: Duration,
+}
+
+impl PriorityGate {
+    /// Creates a gate with no queries waiting
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an interactive query for the lifetime of the returned guard
+    pub fn enter(&self) -> PriorityGuard {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        PriorityGuard {
+            waiting: Arc::clone(&self.waiting),
+        }
+    }
+
+    /// Returns true while any interactive query is in flight
+    pub fn has_waiting(&self) -> bool {
+        self.waiting.load(Ordering::SeqCst) > 0
+    }
+
+    /// Blocks until no queries are waiting or `max_wait` elapses; returns the time spent
+    pub fn wait_idle(&self, max_wait: Duration) -> Duration {
+        let start = Instant::now();
+        while self.has_waiting() && start.elapsed() < max_wait {
+            thread::sleep(YIELD_POLL_INTERVAL);
+        }
+        start.elapsed()
+    }
+}
+
+impl Drop for PriorityGuard {
+    fn drop(&mut self) {
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for BatchWriteConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_chunk_duration: Duration::from_millis(DEFAULT_MAX_CHUNK_DURATION_MS),
+            max_yield: Duration::from_millis(DEFAULT_MAX_YIELD_MS),
+        }
+    }
+}
+
+impl<'a> BatchWriter<'a> {
+    /// Creates a writer with default chunking that yields to queries on `gate`
+    pub fn new(repository: &'a Repository, gate: PriorityGate) -> Self {
+        Self {
+            repository,
+            gate,
+            config: BatchWriteConfig::default(),
+        }
+    }
+
+    /// Overrides the chunking and yield settings
+    pub fn with_config(mut self, config: BatchWriteConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Upserts code elements in chunks, yielding to interactive queries between chunks
+    ///
+    /// Each chunk is committed on its own, so a failure leaves earlier chunks in
+    /// place; because elements are upserted the batch can simply be retried.
+    pub fn write_code_elements(&self, elements: Vec<CodeElement>) -> Result<(Vec<CodeElement>, BatchWriteReport)> {
+        let mut report = BatchWriteReport::default();
+        let mut written = Vec::with_capacity(elements.len());
+        let mut pending = elements.into_iter().peekable();
+
+        while pending.peek().is_some() {
+            let chunk_start = Instant::now();
+            let transaction = Transaction::new_unchecked(self.repository.connection(), TransactionBehavior::Immediate)?;
+
+            let mut rows = 0;
+            while rows < self.config.chunk_size.max(1) {
+                let Some(element) = pending.next() else { break };
+                written.push(self.repository.create_or_update_code_element(element)?);
+                rows += 1;
+
+                if chunk_start.elapsed() >= self.config.max_chunk_duration || self.gate.has_waiting() {
+                    break;
+                }
+            }
+
+            transaction.commit()?;
+            report.rows_written += rows;
+            report.chunks += 1;
+
+            if pending.peek().is_some() && self.gate.has_waiting() {
+                report.yields += 1;
+                report.yielded += self.gate.wait_idle(self.config.max_yield);
+            }
+        }
+
+        Ok((written, report))
+    }
+
+    /// Stores indexed and failed files in chunks, yielding to interactive queries between chunks
+    ///
+    /// Files are stored whole: a chunk ends once it holds `chunk_size`
+    /// symbols, or after any file while queries are waiting. Failed files
+    /// go with the first chunk. Returns the number of symbols stored.
+    pub fn write_file_batch(
+        &self,
+        indexed: Vec<(FileMetadata, Vec<CodeElement>, Vec<String>)>,
+        mut failed: Vec<(FileMetadata, IndexError)>,
+    ) -> Result<(usize, BatchWriteReport)> {
+        let mut report = BatchWriteReport::default();
+        let mut stored = 0;
+        let mut pending = indexed.into_iter().peekable();
+
+        while pending.peek().is_some() || !failed.is_empty() {
+            let mut chunk = Vec::new();
+            let mut rows = 0;
+            for file in pending.by_ref() {
+                rows += file.1.len();
+                chunk.push(file);
+                if rows >= self.config.chunk_size.max(1) || self.gate.has_waiting() {
+                    break;
+                }
+            }
+
+            stored += self.repository.store_file_batch(chunk, std::mem::take(&mut failed))?;
+            report.rows_written += rows;
+            report.chunks += 1;
+
+            if pending.peek().is_some() && self.gate.has_waiting() {
+                report.yields += 1;
+                report.yielded += self.gate.wait_idle(self.config.max_yield);
+            }
+        }
+
+        Ok((stored, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_element::SymbolType;
+    use crate::lib::storage::models::code_index::CodeIndex;
+    use crate::lib::storage::models::index_error::IndexErrorKind;
+    use chrono::Utc;
+    use tempfile::tempdir;
+
+    fn elements(index: &CodeIndex, count: u32) -> Vec<CodeElement> {
+        (1..=count)
+            .map(|line| {
+                CodeElement::new(
+                    index.id,
+                    format!("fn{}", line),
+                    SymbolType::Function,
+                    "src/big.cpp".to_string(),
+                    line,
+                    1,
+                    "a".repeat(64),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_priority_gate_guards() {
+        let gate = PriorityGate::new();
+        assert!(!gate.has_waiting());
+
+        let guard = gate.enter();
+        let second = gate.clone().enter();
+        assert!(gate.has_waiting());
+        drop(guard);
+        assert!(gate.has_waiting());
+        drop(second);
+        assert!(!gate.has_waiting());
+        assert!(gate.wait_idle(Duration::from_secs(1)) < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_batch_writer_chunks_and_upserts() {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repo = Repository::new(manager.connect().unwrap());
+        let index = repo.create_code_index(CodeIndex::new("big".to_string(), "/big".to_string())).unwrap();
+
+        let config = BatchWriteConfig {
+            chunk_size: 10,
+            max_chunk_duration: Duration::from_secs(10),
+            max_yield: Duration::from_millis(5),
+        };
+        let writer = BatchWriter::new(&repo, PriorityGate::new()).with_config(config);
+
+        let (written, report) = writer.write_code_elements(elements(&index, 25)).unwrap();
+        assert_eq!(written.len(), 25);
+        assert!(written.iter().all(|e| e.id.is_some()));
+        assert_eq!(report.rows_written, 25);
+        assert_eq!(report.chunks, 3);
+        assert_eq!(report.yields, 0);
+
+        // Rewriting the same batch updates rows instead of duplicating them
+        writer.write_code_elements(elements(&index, 25)).unwrap();
+        assert_eq!(repo.list_code_elements_by_file(&index.id, "src/big.cpp").unwrap().len(), 25);
+    }
+
+    #[test]
+    fn test_batch_writer_yields_to_waiting_queries() {
+        let dir = tempdir().unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(dir.path().join("index.db"))).unwrap();
+        let writer_repo = Repository::new(manager.connect().unwrap());
+        let reader_repo = Repository::new(manager.connect().unwrap());
+        let index = writer_repo.create_code_index(CodeIndex::new("big".to_string(), "/big".to_string())).unwrap();
+
+        let gate = PriorityGate::new();
+        let _query = gate.enter();
+        let config = BatchWriteConfig {
+            chunk_size: 100,
+            max_chunk_duration: Duration::from_secs(10),
+            max_yield: Duration::from_millis(2),
+        };
+
+        let (_, report) = BatchWriter::new(&writer_repo, gate.clone())
+            .with_config(config)
+            .write_code_elements(elements(&index, 5))
+            .unwrap();
+
+        // A waiting query ends every chunk after one row
+        assert_eq!(report.chunks, 5);
+        assert_eq!(report.yields, 4);
+
+        // Committed chunks are visible to readers on other connections
+        assert_eq!(reader_repo.list_code_elements_by_file(&index.id, "src/big.cpp").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_batch_writer_stores_files_whole() {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repo = Repository::new(manager.connect().unwrap());
+        let index = repo.create_code_index(CodeIndex::new("big".to_string(), "/big".to_string())).unwrap();
+        let files = |count: usize| -> Vec<(FileMetadata, Vec<CodeElement>, Vec<String>)> {
+            (0..count)
+                .map(|n| {
+                    let path = format!("src/file{}.cpp", n);
+                    let symbols = elements(&index, 4).into_iter().map(|element| CodeElement { file_path: path.clone(), ..element }).collect();
+                    (FileMetadata::new(index.id, path, "a".repeat(64), Utc::now(), 10), symbols, Vec::new())
+                })
+                .collect()
+        };
+        let broken = FileMetadata::new(index.id, "src/broken.cpp".to_string(), "0".repeat(64), Utc::now(), 0);
+        let error = IndexError::new(index.id, "src/broken.cpp".to_string(), IndexErrorKind::Parse, "expected ;".to_string());
+        let config = BatchWriteConfig {
+            chunk_size: 6,
+            max_chunk_duration: Duration::from_secs(10),
+            max_yield: Duration::from_millis(2),
+        };
+
+        let (stored, report) = BatchWriter::new(&repo, PriorityGate::new())
+            .with_config(config)
+            .write_file_batch(files(3), vec![(broken, error)])
+            .unwrap();
+        assert_eq!(stored, 12);
+        // Two files reach the chunk size; the third is committed on its own
+        assert_eq!(report.chunks, 2);
+        assert_eq!(repo.list_code_elements_by_file(&index.id, "src/file2.cpp").unwrap().len(), 4);
+        assert_eq!(repo.list_index_errors(&index.id, None).unwrap().len(), 1);
+
+        // A waiting query ends every chunk after one file
+        let gate = PriorityGate::new();
+        let _query = gate.enter();
+        let (_, report) = BatchWriter::new(&repo, gate.clone()).with_config(config).write_file_batch(files(3), Vec::new()).unwrap();
+        assert_eq!(report.chunks, 3);
+        assert_eq!(report.yields, 2);
+    }
+}
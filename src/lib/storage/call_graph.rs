@@ -0,0 +1,434 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+use crate::lib::storage::query::{CodeElementQuery, ElementColumn, Filter, RelationshipColumn, SymbolRelationshipQuery};
+use crate::lib::storage::repository::Repository;
+
+/// Default number of call levels followed from the roots
+pub const DEFAULT_MAX_DEPTH: u32 = 3;
+
+/// Default cap on the number of symbols in one graph
+pub const DEFAULT_MAX_NODES: usize = 500;
+
+/// Which way calls are followed from the roots
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CallDirection {
+    /// Functions the roots call
+    Callees,
+    /// Functions that call the roots
+    Callers,
+}
+
+/// How one function reaches another
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CallEdgeKind {
+    /// A recorded call
+    Direct,
+    /// A call to a virtual method that may dispatch to this overrider
+    VirtualDispatch,
+    /// The function is passed or stored as a callback; it may be called indirectly
+    Callback,
+}
+
+/// Traversal settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallGraphOptions {
+    pub direction: CallDirection,
+    /// Call levels followed from the roots
+    pub max_depth: u32,
+    /// Traversal stops once this many symbols are in the graph
+    pub max_nodes: usize,
+    /// Expand calls through virtual methods to every known overrider
+    pub resolve_virtual: bool,
+    /// Follow ReferencedAsCallback relationships as possible indirect calls
+    pub include_callbacks: bool,
+}
+
+/// An edge from caller (or virtual base) to callee (or overrider)
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
+pub struct CallEdge {
+    pub from_symbol_id: i64,
+    pub to_symbol_id: i64,
+    pub kind: CallEdgeKind,
+    /// Location of the call; None for virtual dispatch edges
+    pub file_path: Option<String>,
+    pub line_number: Option<u32>,
+}
+
+/// A symbol in the graph and the number of calls between it and the nearest root
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CallGraphNode {
+    pub element: CodeElement,
+    pub depth: u32,
+}
+
+/// Result of a call graph traversal
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CallGraph {
+    pub roots: Vec<i64>,
+    /// Nodes in breadth-first order
+    pub nodes: Vec<CallGraphNode>,
+    pub edges: Vec<CallEdge>,
+    /// True if `max_nodes` stopped the traversal early
+    pub truncated: bool,
+}
+
+/// Walks Calls relationships breadth-first from a set of root symbols
+///
+/// Functions referenced as callbacks are followed too, as `Callback` edges:
+/// the call itself happens through a pointer that static extraction can't
+/// resolve, so these edges mark possible rather than certain calls.
+///
+/// With `resolve_virtual`, a method is treated as also reaching everything
+/// that overrides it (via Overrides relationships, transitively), so the
+/// graph answers "who can this call dispatch to" instead of stopping at the
+/// base declaration. Walking callers, the callers of overridden base methods
+/// are included for the same reason.
+pub struct CallGraphWalker<'a> {
+    repository: &'a Repository,
+    options: CallGraphOptions,
+}
+
+impl Default for CallGraphOptions {
+    fn default() -> Self {
+        Self {
+            direction: CallDirection::Callees,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+            resolve_virtual: false,
+            include_callbacks: true,
+        }
+    }
+}
+
+impl CallGraphOptions {
+    /// Follows calls in the given direction
+    pub fn with_direction(mut self, direction: CallDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the number of call levels followed
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Caps the number of symbols in the graph
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes.max(1);
+        self
+    }
+
+    /// Expands virtual calls to overriders
+    pub fn with_resolve_virtual(mut self, resolve_virtual: bool) -> Self {
+        self.resolve_virtual = resolve_virtual;
+        self
+    }
+
+    /// Follows functions referenced as callbacks as well as direct calls
+    pub fn with_callbacks(mut self, include_callbacks: bool) -> Self {
+        self.include_callbacks = include_callbacks;
+        self
+    }
+}
+
+impl<'a> CallGraphWalker<'a> {
+    /// Creates a walker over the repository's relationships
+    pub fn new(repository: &'a Repository, options: CallGraphOptions) -> Self {
+        Self { repository, options }
+    }
+
+    /// Builds the graph reachable from `roots`
+    pub fn walk(&self, roots: &[i64]) -> Result<CallGraph> {
+        let mut traversal = Traversal::new(self.options.max_nodes);
+        let mut frontier: Vec<i64> = roots.iter().copied().filter(|&root| traversal.visit(root, 0)).collect();
+
+        let mut depth = 0;
+        while !frontier.is_empty() && !traversal.truncated {
+            if self.options.resolve_virtual {
+                frontier = self.expand_virtual(frontier, depth, &mut traversal)?;
+            }
+            if depth >= self.options.max_depth || traversal.truncated {
+                break;
+            }
+
+            let mut next = Vec::new();
+            for call in self.calls_touching(&frontier)? {
+                let neighbor = match self.options.direction {
+                    CallDirection::Callees => call.to_symbol_id,
+                    CallDirection::Callers => call.from_symbol_id,
+                };
+                if traversal.visit(neighbor, depth + 1) {
+                    next.push(neighbor);
+                } else if traversal.truncated {
+                    break;
+                }
+                let kind = match call.relationship_type {
+                    RelationshipType::ReferencedAsCallback => CallEdgeKind::Callback,
+                    _ => CallEdgeKind::Direct,
+                };
+                traversal.push_edge(CallEdge {
+                    from_symbol_id: call.from_symbol_id,
+                    to_symbol_id: call.to_symbol_id,
+                    kind,
+                    file_path: Some(call.file_path),
+                    line_number: Some(call.line_number),
+                });
+            }
+
+            frontier = next;
+            depth += 1;
+        }
+
+        let mut elements = self.elements(&traversal.order)?;
+        let nodes = traversal
+            .order
+            .iter()
+            .filter_map(|id| {
+                elements.remove(id).map(|element| CallGraphNode { element, depth: traversal.depths[id] })
+            })
+            .collect();
+
+        Ok(CallGraph {
+            roots: roots.to_vec(),
+            nodes,
+            edges: traversal.edges,
+            truncated: traversal.truncated,
+        })
+    }
+
+    /// Adds overriders (callees) or overridden bases (callers) of the frontier at the same depth
+    fn expand_virtual(&self, mut frontier: Vec<i64>, depth: u32, traversal: &mut Traversal) -> Result<Vec<i64>> {
+        let column = match self.options.direction {
+            CallDirection::Callees => RelationshipColumn::ToSymbolId,
+            CallDirection::Callers => RelationshipColumn::FromSymbolId,
+        };
+
+        let mut pending = frontier.clone();
+        while !pending.is_empty() && !traversal.truncated {
+            let overrides = self.repository.query_relationships(
+                &SymbolRelationshipQuery::new()
+                    .filter(Filter::eq(RelationshipColumn::RelationshipType, RelationshipType::Overrides))
+                    .filter(Filter::in_list(column, pending.iter().copied()))
+                    .order_by_asc(RelationshipColumn::Id),
+            )?;
+
+            let mut discovered = Vec::new();
+            for relationship in overrides {
+                // Overrides point from the overrider to the method it overrides
+                let (base, overrider) = (relationship.to_symbol_id, relationship.from_symbol_id);
+                let neighbor = match self.options.direction {
+                    CallDirection::Callees => overrider,
+                    CallDirection::Callers => base,
+                };
+                if traversal.visit(neighbor, depth) {
+                    discovered.push(neighbor);
+                } else if traversal.truncated {
+                    break;
+                }
+                traversal.push_edge(CallEdge {
+                    from_symbol_id: base,
+                    to_symbol_id: overrider,
+                    kind: CallEdgeKind::VirtualDispatch,
+                    file_path: None,
+                    line_number: None,
+                });
+            }
+
+            frontier.extend(&discovered);
+            pending = discovered;
+        }
+        Ok(frontier)
+    }
+
+    /// Calls (and callback references, if enabled) made by (callees) or made to (callers) any of `ids`
+    fn calls_touching(&self, ids: &[i64]) -> Result<Vec<SymbolRelationship>> {
+        let column = match self.options.direction {
+            CallDirection::Callees => RelationshipColumn::FromSymbolId,
+            CallDirection::Callers => RelationshipColumn::ToSymbolId,
+        };
+        let mut types = vec![RelationshipType::Calls];
+        if self.options.include_callbacks {
+            types.push(RelationshipType::ReferencedAsCallback);
+        }
+        self.repository.query_relationships(
+            &SymbolRelationshipQuery::new()
+                .filter(Filter::in_list(RelationshipColumn::RelationshipType, types))
+                .filter(Filter::in_list(column, ids.iter().copied()))
+                .order_by_asc(RelationshipColumn::Id),
+        )
+    }
+
+    fn elements(&self, ids: &[i64]) -> Result<HashMap<i64, CodeElement>> {
+        let query = CodeElementQuery::new().filter(Filter::in_list(ElementColumn::Id, ids.iter().copied()));
+        Ok(self
+            .repository
+            .query_code_elements(&query)?
+            .into_iter()
+            .filter_map(|element| element.id.map(|id| (id, element)))
+            .collect())
+    }
+}
+
+/// Visited symbols and collected edges of one walk
+struct Traversal {
+    depths: HashMap<i64, u32>,
+    order: Vec<i64>,
+    edges: Vec<CallEdge>,
+    seen_edges: HashSet<CallEdge>,
+    max_nodes: usize,
+    truncated: bool,
+}
+
+impl Traversal {
+    fn new(max_nodes: usize) -> Self {
+        Self {
+            depths: HashMap::new(),
+            order: Vec::new(),
+            edges: Vec::new(),
+            seen_edges: HashSet::new(),
+            max_nodes,
+            truncated: false,
+        }
+    }
+
+    /// Records a newly reached symbol; false if already visited or the node cap is hit
+    fn visit(&mut self, id: i64, depth: u32) -> bool {
+        if self.depths.contains_key(&id) {
+            return false;
+        }
+        if self.order.len() >= self.max_nodes {
+            self.truncated = true;
+            return false;
+        }
+        self.depths.insert(id, depth);
+        self.order.push(id);
+        true
+    }
+
+    fn push_edge(&mut self, edge: CallEdge) {
+        if self.seen_edges.insert(edge.clone()) {
+            self.edges.push(edge);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_element::SymbolType;
+    use crate::lib::storage::models::code_index::CodeIndex;
+
+    /// main -> render -> Shape::draw, overridden by Circle::draw and Square::draw;
+    /// Circle::draw -> fill
+    fn shapes() -> (Repository, HashMap<&'static str, i64>) {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repo = Repository::new(manager.connect().unwrap());
+        let index = repo.create_code_index(CodeIndex::new("shapes".to_string(), "/shapes".to_string())).unwrap();
+
+        let mut ids = HashMap::new();
+        for (line, (name, scope)) in [("main", ""), ("render", ""), ("draw", "Shape"), ("draw", "Circle"), ("draw", "Square"), ("fill", "")]
+            .into_iter()
+            .enumerate()
+        {
+            let mut element = CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/shapes.cpp".to_string(), line as u32 + 1, 1, "a".repeat(64));
+            if !scope.is_empty() {
+                element = element.with_scope(scope.to_string());
+            }
+            let key = match scope {
+                "" => name,
+                "Shape" => "Shape::draw",
+                "Circle" => "Circle::draw",
+                _ => "Square::draw",
+            };
+            ids.insert(key, repo.create_code_element(element).unwrap().id.unwrap());
+        }
+
+        let relate = |from: &str, to: &str, relationship_type| {
+            repo.create_symbol_relationship(SymbolRelationship::new(ids[from], ids[to], relationship_type, "src/shapes.cpp".to_string(), 10)).unwrap();
+        };
+        relate("main", "render", RelationshipType::Calls);
+        relate("render", "Shape::draw", RelationshipType::Calls);
+        relate("Circle::draw", "Shape::draw", RelationshipType::Overrides);
+        relate("Square::draw", "Shape::draw", RelationshipType::Overrides);
+        relate("Circle::draw", "fill", RelationshipType::Calls);
+
+        (repo, ids)
+    }
+
+    fn node_ids(graph: &CallGraph) -> HashSet<i64> {
+        graph.nodes.iter().filter_map(|node| node.element.id).collect()
+    }
+
+    #[test]
+    fn test_callees_stop_at_base_without_virtual_resolution() {
+        let (repo, ids) = shapes();
+        let graph = CallGraphWalker::new(&repo, CallGraphOptions::default().with_max_depth(5)).walk(&[ids["main"]]).unwrap();
+
+        assert_eq!(node_ids(&graph), HashSet::from([ids["main"], ids["render"], ids["Shape::draw"]]));
+        assert!(graph.edges.iter().all(|edge| edge.kind == CallEdgeKind::Direct));
+        assert!(!graph.truncated);
+    }
+
+    #[test]
+    fn test_callees_expand_to_overriders() {
+        let (repo, ids) = shapes();
+        let options = CallGraphOptions::default().with_max_depth(5).with_resolve_virtual(true);
+        let graph = CallGraphWalker::new(&repo, options).walk(&[ids["main"]]).unwrap();
+
+        assert!(node_ids(&graph).is_superset(&HashSet::from([ids["Circle::draw"], ids["Square::draw"], ids["fill"]])));
+        let dispatch: Vec<&CallEdge> = graph.edges.iter().filter(|edge| edge.kind == CallEdgeKind::VirtualDispatch).collect();
+        assert_eq!(dispatch.len(), 2);
+        assert!(dispatch.iter().all(|edge| edge.from_symbol_id == ids["Shape::draw"]));
+
+        // Overriders sit at the depth of the call they resolve
+        let depth = |key: &str| graph.nodes.iter().find(|node| node.element.id == Some(ids[key])).unwrap().depth;
+        assert_eq!(depth("Circle::draw"), depth("Shape::draw"));
+        assert_eq!(depth("fill"), depth("Circle::draw") + 1);
+    }
+
+    #[test]
+    fn test_callback_edges_are_flagged() {
+        let (repo, ids) = shapes();
+        repo.create_symbol_relationship(SymbolRelationship::new(
+            ids["main"],
+            ids["fill"],
+            RelationshipType::ReferencedAsCallback,
+            "src/shapes.cpp".to_string(),
+            12,
+        ))
+        .unwrap();
+
+        let graph = CallGraphWalker::new(&repo, CallGraphOptions::default().with_max_depth(1)).walk(&[ids["main"]]).unwrap();
+        let callback = graph.edges.iter().find(|edge| edge.to_symbol_id == ids["fill"]).unwrap();
+        assert_eq!(callback.kind, CallEdgeKind::Callback);
+        assert_eq!(callback.line_number, Some(12));
+
+        let direct_only = CallGraphWalker::new(&repo, CallGraphOptions::default().with_max_depth(1).with_callbacks(false))
+            .walk(&[ids["main"]])
+            .unwrap();
+        assert!(!node_ids(&direct_only).contains(&ids["fill"]));
+    }
+
+    #[test]
+    fn test_callers_through_overridden_base() {
+        let (repo, ids) = shapes();
+        let options = CallGraphOptions::default()
+            .with_direction(CallDirection::Callers)
+            .with_resolve_virtual(true);
+        let graph = CallGraphWalker::new(&repo, options).walk(&[ids["Circle::draw"]]).unwrap();
+        assert!(node_ids(&graph).contains(&ids["render"]));
+        assert!(!node_ids(&graph).contains(&ids["Square::draw"]));
+
+        let limited = CallGraphWalker::new(&repo, options.with_max_nodes(2)).walk(&[ids["Circle::draw"]]).unwrap();
+        assert_eq!(limited.nodes.len(), 2);
+        assert!(limited.truncated);
+    }
+}
@@ -0,0 +1,618 @@
+// Code intelligence exports
+//
+// Sourcegraph and other code intelligence tools don't speak MCP; they read
+// SCIP, a protobuf index, or LSIF, a graph written as JSON lines. Both are
+// built here from the same per-file documents: the definitions and
+// declarations stored for each file and the references recorded by
+// relationships, each naming its symbol by a moniker that stays the same
+// across files, plus hover text from signatures and doc comments.
+
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::models::symbol_relationships::{RelationshipType, SymbolRelationship};
+
+/// Scheme of the symbol monikers
+pub const MONIKER_SCHEME: &str = "cpp-index";
+
+/// Relationships whose line is a reference to the target symbol
+const REFERENCE_TYPES: [RelationshipType; 7] = [
+    RelationshipType::Calls,
+    RelationshipType::Uses,
+    RelationshipType::Inherits,
+    RelationshipType::Instantiates,
+    RelationshipType::Specializes,
+    RelationshipType::Friend,
+    RelationshipType::ReferencedAsCallback,
+];
+
+/// SCIP symbol roles
+const ROLE_DEFINITION: u64 = 0x1;
+const ROLE_FORWARD_DEFINITION: u64 = 0x40;
+
+/// Formats an index can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeIntelFormat {
+    Scip,
+    Lsif,
+}
+
+/// What an occurrence does with its symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OccurrenceRole {
+    Definition,
+    Declaration,
+    Reference,
+}
+
+/// A symbol's name at a place in a file
+///
+/// Positions are 0-based, columns in bytes from the start of the line.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Occurrence {
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    pub symbol: String,
+    pub role: OccurrenceRole,
+}
+
+/// What is shown for a symbol, stored with the document defining it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub display_name: String,
+    /// Markdown hover text: the signature as a code block, then the doc comment
+    pub documentation: Vec<String>,
+    /// Symbols this one implements: overridden methods and base classes
+    pub implements: Vec<String>,
+}
+
+/// Everything exported for one file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    /// Path relative to the project root
+    pub relative_path: String,
+    pub occurrences: Vec<Occurrence>,
+    pub symbols: Vec<SymbolInfo>,
+}
+
+/// An index converted to code intelligence documents
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeIntelIndex {
+    /// Absolute path of the indexed codebase
+    pub project_root: String,
+    /// Documents sorted by path
+    pub documents: Vec<Document>,
+    /// References left out because their column couldn't be found in the source
+    pub unlocated_references: usize,
+}
+
+impl CodeIntelFormat {
+    /// Parses "scip" or "lsif"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "scip" => Some(Self::Scip),
+            "lsif" => Some(Self::Lsif),
+            _ => None,
+        }
+    }
+
+    /// Format implied by a file's extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension().and_then(|extension| extension.to_str()).and_then(Self::parse)
+    }
+}
+
+impl CodeIntelIndex {
+    /// Converts an index's symbols and relationships
+    ///
+    /// Relationships only record the line of a reference; `locate` returns
+    /// the byte column of a symbol name on a 1-based line of a file, usually
+    /// by reading the source. References it can't place are counted in
+    /// `unlocated_references` rather than exported at a guessed column.
+    pub fn build(
+        index: &CodeIndex,
+        elements: &[CodeElement],
+        relationships: &[SymbolRelationship],
+        mut locate: impl FnMut(&str, u32, &str) -> Option<u32>,
+    ) -> Self {
+        let monikers = symbol_monikers(elements);
+        let by_id: HashMap<i64, &CodeElement> = elements.iter().filter_map(|element| Some((element.id?, element))).collect();
+        let mut documents: BTreeMap<String, Document> = BTreeMap::new();
+        // Hover text comes from a definition where there is one, else from the first declaration
+        let mut symbols: HashMap<String, (String, SymbolInfo, bool)> = HashMap::new();
+
+        for element in elements {
+            let Some(symbol) = element.id.and_then(|id| monikers.get(&id)) else {
+                continue;
+            };
+            let start = element.column_number.saturating_sub(1);
+            let role = if element.is_declaration { OccurrenceRole::Declaration } else { OccurrenceRole::Definition };
+            document(&mut documents, &element.file_path).occurrences.push(Occurrence {
+                line: element.line_number.saturating_sub(1),
+                start,
+                end: start + element.symbol_name.len() as u32,
+                symbol: symbol.clone(),
+                role,
+            });
+
+            let replaces = match symbols.get(symbol) {
+                None => true,
+                Some((_, _, from_definition)) => !from_definition && !element.is_declaration,
+            };
+            if replaces {
+                let info = SymbolInfo {
+                    symbol: symbol.clone(),
+                    display_name: element.symbol_name.clone(),
+                    documentation: hover_text(element),
+                    implements: Vec::new(),
+                };
+                symbols.insert(symbol.clone(), (element.file_path.clone(), info, !element.is_declaration));
+            }
+        }
+
+        let mut unlocated_references = 0;
+        for relationship in relationships {
+            let Some(to) = by_id.get(&relationship.to_symbol_id) else {
+                continue;
+            };
+            let (Some(from_symbol), Some(to_symbol)) = (monikers.get(&relationship.from_symbol_id), monikers.get(&relationship.to_symbol_id)) else {
+                continue;
+            };
+
+            if matches!(relationship.relationship_type, RelationshipType::Overrides | RelationshipType::Inherits) {
+                if let Some((_, info, _)) = symbols.get_mut(from_symbol) {
+                    if !info.implements.contains(to_symbol) {
+                        info.implements.push(to_symbol.clone());
+                    }
+                }
+            }
+            if !REFERENCE_TYPES.contains(&relationship.relationship_type) {
+                continue;
+            }
+            match locate(&relationship.file_path, relationship.line_number, &to.symbol_name) {
+                Some(start) => document(&mut documents, &relationship.file_path).occurrences.push(Occurrence {
+                    line: relationship.line_number.saturating_sub(1),
+                    start,
+                    end: start + to.symbol_name.len() as u32,
+                    symbol: to_symbol.clone(),
+                    role: OccurrenceRole::Reference,
+                }),
+                None => unlocated_references += 1,
+            }
+        }
+
+        for (file_path, info, _) in symbols.into_values() {
+            document(&mut documents, &file_path).symbols.push(info);
+        }
+        for document in documents.values_mut() {
+            document.occurrences.sort();
+            document.occurrences.dedup();
+            document.symbols.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        }
+
+        Self {
+            project_root: index.base_path.clone(),
+            documents: documents.into_values().collect(),
+            unlocated_references,
+        }
+    }
+
+    /// Number of distinct symbols with hover information
+    pub fn symbol_count(&self) -> usize {
+        self.documents.iter().map(|document| document.symbols.len()).sum()
+    }
+
+    /// Encodes the documents as a SCIP `Index` protobuf message
+    pub fn to_scip(&self, tool_name: &str, tool_version: &str) -> Vec<u8> {
+        let mut tool_info = ProtoWriter::default();
+        tool_info.string(1, tool_name);
+        tool_info.string(2, tool_version);
+
+        let mut metadata = ProtoWriter::default();
+        metadata.message(2, &tool_info);
+        metadata.string(3, &file_uri(&self.project_root));
+        // TextEncoding.UTF8
+        metadata.uint(4, 1);
+
+        let mut scip = ProtoWriter::default();
+        scip.message(1, &metadata);
+        for document in &self.documents {
+            let mut message = ProtoWriter::default();
+            message.string(1, &document.relative_path);
+            for occurrence in &document.occurrences {
+                let mut encoded = ProtoWriter::default();
+                encoded.packed(1, &[occurrence.line, occurrence.start, occurrence.end]);
+                encoded.string(2, &occurrence.symbol);
+                encoded.uint(
+                    3,
+                    match occurrence.role {
+                        OccurrenceRole::Definition => ROLE_DEFINITION,
+                        OccurrenceRole::Declaration => ROLE_FORWARD_DEFINITION,
+                        OccurrenceRole::Reference => 0,
+                    },
+                );
+                message.message(2, &encoded);
+            }
+            for symbol in &document.symbols {
+                let mut encoded = ProtoWriter::default();
+                encoded.string(1, &symbol.symbol);
+                for documentation in &symbol.documentation {
+                    encoded.string(3, documentation);
+                }
+                for implemented in &symbol.implements {
+                    let mut relationship = ProtoWriter::default();
+                    relationship.string(1, implemented);
+                    // is_implementation
+                    relationship.uint(3, 1);
+                    encoded.message(4, &relationship);
+                }
+                encoded.string(6, &symbol.display_name);
+                message.message(3, &encoded);
+            }
+            message.string(4, "CPP");
+            // PositionEncoding.UTF8CodeUnitOffsetFromLineStart
+            message.uint(6, 1);
+            scip.message(2, &message);
+        }
+        scip.0
+    }
+
+    /// Writes the documents as an LSIF graph, one vertex or edge per line
+    ///
+    /// Each symbol gets a result set carrying its moniker, hover, definition
+    /// and reference results; every occurrence is a range pointing at it.
+    /// LSIF columns count UTF-16 code units, so byte columns are converted
+    /// using the 1-based source line `line` returns; where it returns None
+    /// the line is taken to be ASCII and the byte columns are kept.
+    pub fn to_lsif(&self, tool_name: &str, tool_version: &str, mut line: impl FnMut(&str, u32) -> Option<String>) -> String {
+        let mut graph = LsifGraph::default();
+        graph.vertex(
+            "metaData",
+            json!({
+                "version": "0.4.3",
+                "projectRoot": file_uri(&self.project_root),
+                "positionEncoding": "utf-16",
+                "toolInfo": {"name": tool_name, "version": tool_version},
+            }),
+        );
+        let project = graph.vertex("project", json!({"kind": "cpp"}));
+
+        let mut result_sets: BTreeMap<&str, u64> = BTreeMap::new();
+        for symbol in self.documents.iter().flat_map(|document| &document.symbols) {
+            let result_set = graph.vertex("resultSet", json!({}));
+            let moniker = graph.vertex("moniker", json!({"scheme": MONIKER_SCHEME, "identifier": symbol.symbol, "kind": "export"}));
+            graph.edge("moniker", result_set, moniker);
+            let contents: Vec<Value> = symbol.documentation.iter().map(|documentation| json!(documentation)).collect();
+            let hover = graph.vertex("hoverResult", json!({"result": {"contents": contents}}));
+            graph.edge("textDocument/hover", result_set, hover);
+            result_sets.insert(&symbol.symbol, result_set);
+        }
+
+        // Ranges by symbol and role, with the document they are in, for the result items
+        let mut items: BTreeMap<&str, Vec<(OccurrenceRole, u64, u64)>> = BTreeMap::new();
+        let mut documents = Vec::new();
+        for document in &self.documents {
+            let uri = file_uri(&Path::new(&self.project_root).join(&document.relative_path).to_string_lossy());
+            let id = graph.vertex("document", json!({"uri": uri, "languageId": "cpp"}));
+            documents.push(id);
+
+            let mut ranges = Vec::new();
+            for occurrence in &document.occurrences {
+                let Some(&result_set) = result_sets.get(occurrence.symbol.as_str()) else {
+                    continue;
+                };
+                let (start, end) = match line(&document.relative_path, occurrence.line + 1) {
+                    Some(text) => (utf16_column(&text, occurrence.start), utf16_column(&text, occurrence.end)),
+                    None => (occurrence.start, occurrence.end),
+                };
+                let range = graph.vertex(
+                    "range",
+                    json!({
+                        "start": {"line": occurrence.line, "character": start},
+                        "end": {"line": occurrence.line, "character": end},
+                    }),
+                );
+                graph.edge("next", range, result_set);
+                ranges.push(range);
+                items.entry(&occurrence.symbol).or_default().push((occurrence.role, range, id));
+            }
+            if !ranges.is_empty() {
+                graph.edges("contains", id, &ranges);
+            }
+        }
+        graph.edges("contains", project, &documents);
+
+        for (symbol, occurrences) in items {
+            let result_set = result_sets[symbol];
+            let definitions: Vec<_> = occurrences.iter().filter(|(role, _, _)| *role == OccurrenceRole::Definition).collect();
+            if !definitions.is_empty() {
+                let result = graph.vertex("definitionResult", json!({}));
+                graph.edge("textDocument/definition", result_set, result);
+                for (_, range, document) in definitions {
+                    graph.item(result, &[*range], *document, None);
+                }
+            }
+
+            let result = graph.vertex("referenceResult", json!({}));
+            graph.edge("textDocument/references", result_set, result);
+            for (role, range, document) in &occurrences {
+                let property = if *role == OccurrenceRole::Reference { "references" } else { "definitions" };
+                graph.item(result, &[*range], *document, Some(property));
+            }
+        }
+        graph.lines
+    }
+}
+
+/// Monikers of the elements by id
+///
+/// A moniker is the scheme and the descriptors of the symbol's qualified
+/// name, SCIP style: `audio/Mixer#mix().` is the method `mix` of class
+/// `Mixer` in namespace `audio`. Scope components are types when a class,
+/// struct, union or enum of that qualified name is indexed, namespaces
+/// otherwise. Overloads get `(+1)`, `(+2)`... in the order of their
+/// signatures, so a declaration and its definition share a moniker.
+pub fn symbol_monikers(elements: &[CodeElement]) -> HashMap<i64, String> {
+    let types: std::collections::HashSet<String> = elements
+        .iter()
+        .filter(|element| is_type(element.symbol_type))
+        .map(CodeElement::fully_qualified_name)
+        .collect();
+    let mut overloads: HashMap<String, Vec<&str>> = HashMap::new();
+    for element in elements.iter().filter(|element| is_callable(element.symbol_type)) {
+        overloads.entry(element.fully_qualified_name()).or_default().push(element.signature.as_deref().unwrap_or(""));
+    }
+    for signatures in overloads.values_mut() {
+        signatures.sort_unstable();
+        signatures.dedup();
+    }
+
+    elements
+        .iter()
+        .filter_map(|element| {
+            let mut descriptors = String::new();
+            let mut prefix = String::new();
+            for component in element.scope.as_deref().unwrap_or("").split("::").filter(|component| !component.is_empty()) {
+                if !prefix.is_empty() {
+                    prefix.push_str("::");
+                }
+                prefix.push_str(component);
+                descriptors.push_str(&escape_name(component));
+                descriptors.push(if types.contains(&prefix) { '#' } else { '/' });
+            }
+
+            let name = escape_name(&element.symbol_name);
+            match element.symbol_type {
+                symbol_type if is_callable(symbol_type) => {
+                    let signature = element.signature.as_deref().unwrap_or("");
+                    let overload = overloads[&element.fully_qualified_name()].iter().position(|s| *s == signature).unwrap_or(0);
+                    let disambiguator = if overload == 0 { String::new() } else { format!("+{}", overload) };
+                    descriptors.push_str(&format!("{}({}).", name, disambiguator));
+                }
+                symbol_type if is_type(symbol_type) || symbol_type == SymbolType::Typedef => descriptors.push_str(&format!("{}#", name)),
+                SymbolType::Namespace => descriptors.push_str(&format!("{}/", name)),
+                SymbolType::Macro => descriptors.push_str(&format!("{}!", name)),
+                _ => descriptors.push_str(&format!("{}.", name)),
+            }
+            Some((element.id?, format!("{} . . . {}", MONIKER_SCHEME, descriptors)))
+        })
+        .collect()
+}
+
+/// UTF-16 code units before byte `column` of a line
+fn utf16_column(line: &str, column: u32) -> u32 {
+    let mut end = (column as usize).min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    line[..end].encode_utf16().count() as u32
+}
+
+fn is_type(symbol_type: SymbolType) -> bool {
+    matches!(symbol_type, SymbolType::Class | SymbolType::Struct | SymbolType::Union | SymbolType::Enum | SymbolType::Template)
+}
+
+fn is_callable(symbol_type: SymbolType) -> bool {
+    matches!(symbol_type, SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor | SymbolType::Operator)
+}
+
+/// A descriptor name as is if it is a simple identifier, else in backticks
+fn escape_name(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '+' | '-' | '$')) {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('`', "``"))
+    }
+}
+
+fn hover_text(element: &CodeElement) -> Vec<String> {
+    let mut documentation = Vec::new();
+    if let Some(signature) = element.signature.as_deref().filter(|signature| !signature.is_empty()) {
+        documentation.push(format!("```cpp\n{}\n```", signature));
+    }
+    if let Some(comment) = element.documentation.as_deref().filter(|comment| !comment.is_empty()) {
+        documentation.push(comment.to_string());
+    }
+    documentation
+}
+
+fn document<'a>(documents: &'a mut BTreeMap<String, Document>, file_path: &str) -> &'a mut Document {
+    documents
+        .entry(file_path.to_string())
+        .or_insert_with(|| Document { relative_path: file_path.to_string(), ..Document::default() })
+}
+
+fn file_uri(path: &str) -> String {
+    format!("file://{}", path.replace('\\', "/"))
+}
+
+/// Protobuf encoding of the few field kinds SCIP uses
+#[derive(Default)]
+struct ProtoWriter(Vec<u8>);
+
+impl ProtoWriter {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    /// Default values are left out, as protobuf encoders do
+    fn uint(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, 0);
+            self.varint(value);
+        }
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        if !value.is_empty() {
+            self.bytes(field, value.as_bytes());
+        }
+    }
+
+    fn message(&mut self, field: u32, message: &ProtoWriter) {
+        self.bytes(field, &message.0);
+    }
+
+    fn packed(&mut self, field: u32, values: &[u32]) {
+        let mut packed = ProtoWriter::default();
+        for value in values {
+            packed.varint(*value as u64);
+        }
+        self.bytes(field, &packed.0);
+    }
+}
+
+/// LSIF vertices and edges, numbered in the order they are written
+#[derive(Default)]
+struct LsifGraph {
+    lines: String,
+    next_id: u64,
+}
+
+impl LsifGraph {
+    fn write(&mut self, mut element: Value) -> u64 {
+        self.next_id += 1;
+        element["id"] = json!(self.next_id);
+        self.lines.push_str(&element.to_string());
+        self.lines.push('\n');
+        self.next_id
+    }
+
+    fn vertex(&mut self, label: &str, mut properties: Value) -> u64 {
+        properties["type"] = json!("vertex");
+        properties["label"] = json!(label);
+        self.write(properties)
+    }
+
+    fn edge(&mut self, label: &str, out_v: u64, in_v: u64) -> u64 {
+        self.write(json!({"type": "edge", "label": label, "outV": out_v, "inV": in_v}))
+    }
+
+    fn edges(&mut self, label: &str, out_v: u64, in_vs: &[u64]) -> u64 {
+        self.write(json!({"type": "edge", "label": label, "outV": out_v, "inVs": in_vs}))
+    }
+
+    fn item(&mut self, out_v: u64, in_vs: &[u64], document: u64, property: Option<&str>) -> u64 {
+        let mut edge = json!({"type": "edge", "label": "item", "outV": out_v, "inVs": in_vs, "document": document});
+        if let Some(property) = property {
+            edge["property"] = json!(property);
+        }
+        self.write(edge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn element(id: i64, name: &str, symbol_type: SymbolType, scope: Option<&str>, file: &str, line: u32, declaration: bool) -> CodeElement {
+        let mut element = CodeElement::new(Uuid::nil(), name.to_string(), symbol_type, file.to_string(), line, 7, "h".to_string());
+        element.id = Some(id);
+        element.scope = scope.map(str::to_string);
+        element.is_declaration = declaration;
+        element
+    }
+
+    fn relationship(from: i64, to: i64, relationship_type: RelationshipType, file: &str, line: u32) -> SymbolRelationship {
+        SymbolRelationship { id: None, from_symbol_id: from, to_symbol_id: to, relationship_type, file_path: file.to_string(), line_number: line }
+    }
+
+    #[test]
+    fn test_code_intel_export() {
+        let index = CodeIndex::new("audio".to_string(), "/work/audio".to_string());
+        let mut mix = element(3, "mix", SymbolType::Function, Some("audio::Mixer"), "src/mixer.h", 8, true);
+        mix.signature = Some("void mix(float gain)".to_string());
+        let mut mix_definition = element(4, "mix", SymbolType::Function, Some("audio::Mixer"), "src/mixer.cpp", 12, false);
+        mix_definition.signature = mix.signature.clone();
+        mix_definition.documentation = Some("Mixes the queued buffers.".to_string());
+        let mut mix_int = element(5, "mix", SymbolType::Function, Some("audio::Mixer"), "src/mixer.h", 9, true);
+        mix_int.signature = Some("void mix(int gain)".to_string());
+        let elements = vec![
+            element(1, "audio", SymbolType::Namespace, None, "src/mixer.h", 1, false),
+            element(2, "Mixer", SymbolType::Class, Some("audio"), "src/mixer.h", 3, false),
+            mix,
+            mix_definition,
+            mix_int,
+            element(6, "play", SymbolType::Function, None, "src/player.cpp", 4, false),
+            element(7, "operator==", SymbolType::Operator, Some("audio::Mixer"), "src/mixer.h", 10, true),
+        ];
+        let relationships = vec![
+            relationship(6, 4, RelationshipType::Calls, "src/player.cpp", 5),
+            relationship(6, 4, RelationshipType::Calls, "src/player.cpp", 6),
+        ];
+
+        let monikers = symbol_monikers(&elements);
+        assert_eq!(monikers[&2], "cpp-index . . . audio/Mixer#");
+        assert_eq!(monikers[&3], "cpp-index . . . audio/Mixer#mix().");
+        assert_eq!(monikers[&4], monikers[&3]);
+        assert_eq!(monikers[&5], "cpp-index . . . audio/Mixer#mix(+1).");
+        assert_eq!(monikers[&7], "cpp-index . . . audio/Mixer#`operator==`().");
+
+        let export = CodeIntelIndex::build(&index, &elements, &relationships, |_, line, _| (line == 5).then_some(4));
+        assert_eq!(export.unlocated_references, 1);
+        let paths: Vec<&str> = export.documents.iter().map(|document| document.relative_path.as_str()).collect();
+        assert_eq!(paths, ["src/mixer.cpp", "src/mixer.h", "src/player.cpp"]);
+
+        // Hover text comes from the definition, which lives in mixer.cpp
+        let mixer_cpp = &export.documents[0];
+        assert_eq!(mixer_cpp.symbols[0].documentation, ["```cpp\nvoid mix(float gain)\n```", "Mixes the queued buffers."]);
+        let player = &export.documents[2];
+        assert_eq!(player.occurrences[1], Occurrence { line: 4, start: 4, end: 7, symbol: monikers[&4].clone(), role: OccurrenceRole::Reference });
+
+        let scip = export.to_scip("cpp-index-mcp", "0.1.0");
+        assert_eq!(scip[0], 0x0a);
+        assert!(scip.windows(b"audio/Mixer#mix(+1).".len()).any(|window| window == b"audio/Mixer#mix(+1)."));
+        assert_eq!(export.symbol_count(), 6);
+
+        // A two-byte character before the reference is one UTF-16 unit in LSIF
+        let lsif = export.to_lsif("cpp-index-mcp", "0.1.0", |file, line| (file == "src/player.cpp" && line == 5).then(|| "\u{e9}  mix();".to_string()));
+        let lines: Vec<Value> = lsif.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines[0]["label"], "metaData");
+        assert!(lines.iter().enumerate().all(|(i, line)| line["id"] == json!(i + 1)));
+        let references = lines.iter().filter(|line| line["label"] == "item" && line["property"] == "references").count();
+        assert_eq!(references, 1);
+        assert_eq!(lines.iter().filter(|line| line["label"] == "document").count(), 3);
+        assert!(lines.iter().any(|line| line["label"] == "range" && line["start"] == json!({"line": 4, "character": 3})));
+        assert_eq!(utf16_column("\u{1f3b5} mix", 5), 3);
+    }
+}
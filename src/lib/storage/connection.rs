@@ -1,7 +1,14 @@
-use rusqlite::{Connection, OpenFlags, Result};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{Connection, OpenFlags};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
 use std::path::{Path, PathBuf};
 use std::fs;
-use crate::lib::storage::schema::{SchemaMigrator, CURRENT_SCHEMA_VERSION};
+use crate::lib::storage::disk_space::DiskSpaceGuard;
+use crate::lib::storage::encryption::{apply_key, EncryptionKey};
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::recovery::DatabaseRecovery;
+use crate::lib::storage::schema::{SchemaCompatibility, SchemaMigrator, CURRENT_SCHEMA_VERSION};
+use tracing::warn;
 
 /// Database configuration options
 #[derive(Debug, Clone)]
@@ -12,7 +19,7 @@ pub struct DatabaseConfig {
     pub create_if_missing: bool,
     /// Whether to enable WAL mode for better concurrency
     pub enable_wal_mode: bool,
-    /// Connection pool size (for future connection pooling)
+    /// Connections a [`ConnectionPool`] keeps open: one writer and up to `pool_size - 1` readers
     pub pool_size: u32,
     /// Query timeout in seconds
     pub query_timeout_seconds: u64,
@@ -20,6 +27,16 @@ pub struct DatabaseConfig {
     pub max_size_mb: u64,
     /// Enable query logging for debugging
     pub enable_query_logging: bool,
+    /// WAL pages after which SQLite checkpoints on commit (0 = never)
+    pub wal_autocheckpoint_pages: u32,
+    /// Seconds between scheduled checkpoints (0 = only size triggered)
+    pub wal_checkpoint_interval_seconds: u64,
+    /// WAL size in MB above which a scheduled checkpoint truncates the log (0 = unlimited)
+    pub wal_size_limit_mb: u64,
+    /// Key for an encrypted database (None = plaintext)
+    pub encryption_key: Option<EncryptionKey>,
+    /// Open every connection read-only, leaving the file to a writer in another process
+    pub read_only: bool,
 }
 
 impl DatabaseConfig {
@@ -33,6 +50,11 @@ impl DatabaseConfig {
             query_timeout_seconds: 30,
             max_size_mb: 0, // Unlimited
             enable_query_logging: false,
+            wal_autocheckpoint_pages: 1000,
+            wal_checkpoint_interval_seconds: 300,
+            wal_size_limit_mb: 64,
+            encryption_key: None,
+            read_only: false,
         }
     }
 
@@ -46,6 +68,11 @@ impl DatabaseConfig {
             query_timeout_seconds: 10,
             max_size_mb: 0,
             enable_query_logging: true,
+            wal_autocheckpoint_pages: 0,
+            wal_checkpoint_interval_seconds: 0,
+            wal_size_limit_mb: 0,
+            encryption_key: None,
+            read_only: false,
         }
     }
 
@@ -63,6 +90,11 @@ impl DatabaseConfig {
             query_timeout_seconds: 10,
             max_size_mb: 100, // 100MB limit for temp databases
             enable_query_logging: true,
+            wal_autocheckpoint_pages: 1000,
+            wal_checkpoint_interval_seconds: 0,
+            wal_size_limit_mb: 16,
+            encryption_key: None,
+            read_only: false,
         })
     }
 
@@ -90,6 +122,43 @@ impl DatabaseConfig {
         self
     }
 
+    /// Sets how many WAL pages trigger SQLite's automatic checkpoint
+    pub fn with_wal_autocheckpoint(mut self, pages: u32) -> Self {
+        self.wal_autocheckpoint_pages = pages;
+        self
+    }
+
+    /// Sets the interval between scheduled checkpoints
+    pub fn with_wal_checkpoint_interval(mut self, interval_seconds: u64) -> Self {
+        self.wal_checkpoint_interval_seconds = interval_seconds;
+        self
+    }
+
+    /// Sets the WAL size that forces a truncating checkpoint
+    pub fn with_wal_size_limit_mb(mut self, wal_size_limit_mb: u64) -> Self {
+        self.wal_size_limit_mb = wal_size_limit_mb;
+        self
+    }
+
+    /// Opens connections read-only, e.g. for one of several servers sharing a database
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Encrypts the database with the given key
+    pub fn with_encryption_key(mut self, key: EncryptionKey) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Returns the path of the write-ahead log next to the database
+    pub fn wal_path(&self) -> PathBuf {
+        let mut path = self.database_path.clone().into_os_string();
+        path.push("-wal");
+        PathBuf::from(path)
+    }
+
     /// Validates the database configuration
     pub fn validate(&self) -> Result<(), String> {
         // Check if parent directory exists (for file-based databases)
@@ -124,19 +193,26 @@ impl DatabaseConfig {
 }
 
 /// Database connection manager that handles connection setup and configuration
+#[derive(Debug)]
 pub struct DatabaseManager {
     config: DatabaseConfig,
 }
 
 impl DatabaseManager {
     /// Creates a new database manager with the given configuration
-    pub fn new(config: DatabaseConfig) -> Result<Self, String> {
-        config.validate()?;
+    pub fn new(config: DatabaseConfig) -> Result<Self> {
+        config.validate().map_err(StorageError::Validation)?;
         Ok(Self { config })
     }
 
     /// Opens a connection to the database and applies all migrations
+    ///
+    /// With `read_only` configured the database is opened as it is, never
+    /// created, configured or migrated; see [`Self::connect_shared_reader`].
     pub fn connect(&self) -> Result<Connection> {
+        if self.config.read_only {
+            return self.connect_shared_reader();
+        }
         self.ensure_database_directory()?;
         let mut connection = self.open_connection()?;
         self.configure_connection(&mut connection)?;
@@ -152,6 +228,78 @@ impl DatabaseManager {
         Ok(connection)
     }
 
+    /// Opens an existing database read-only, without configuring or migrating it
+    ///
+    /// Used to inspect a database that may be damaged and to serve it in degraded mode.
+    pub fn connect_read_only(&self) -> Result<Connection> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let connection = Connection::open_with_flags(&self.config.database_path, flags)?;
+        if let Some(key) = &self.config.encryption_key {
+            apply_key(&connection, key)?;
+        }
+        connection.busy_timeout(std::time::Duration::from_secs(self.config.query_timeout_seconds))?;
+        connection.execute("PRAGMA query_only = ON", [])?;
+        register_functions(&connection)?;
+        Ok(connection)
+    }
+
+    /// Opens a read-only connection to a database another process writes
+    ///
+    /// Several processes can read while one writer updates the file: in WAL
+    /// mode each read transaction sees the last commit before it started,
+    /// and readers never block the writer. A read-only process can't
+    /// migrate, so the database must already have this build's schema, or a
+    /// newer one this build may read.
+    fn connect_shared_reader(&self) -> Result<Connection> {
+        if !self.config.is_in_memory() && !self.config.database_path.exists() {
+            return Err(StorageError::not_found("Database", self.config.database_path.display()));
+        }
+        let connection = self.connect_read_only()?;
+        let migrator = SchemaMigrator::new(connection);
+        match migrator.compatibility()? {
+            SchemaCompatibility::Incompatible { found } => {
+                return Err(StorageError::NewerSchema { found, supported: CURRENT_SCHEMA_VERSION });
+            }
+            SchemaCompatibility::Current => {
+                let found = migrator.get_current_version()?;
+                if found < CURRENT_SCHEMA_VERSION {
+                    return Err(StorageError::ReadOnly(format!(
+                        "database schema version {} needs migrating to {}; open it once with write access first",
+                        found, CURRENT_SCHEMA_VERSION
+                    )));
+                }
+            }
+            SchemaCompatibility::ReadOnly { .. } => {}
+        }
+        Ok(migrator.into_connection())
+    }
+
+    /// Opens a read-only connection pinned to the database's current state
+    ///
+    /// The connection holds an open read transaction: until it is dropped it
+    /// keeps seeing the data as of this call, while other connections go on
+    /// committing. Needs WAL mode, since a reader would otherwise block
+    /// writers. Long-lived snapshots also hold back WAL checkpoints, so keep
+    /// them short.
+    pub fn connect_snapshot(&self) -> Result<Connection> {
+        if self.config.is_in_memory() {
+            return Err(StorageError::Validation(
+                "Query snapshots need a file database".to_string(),
+            ));
+        }
+        if !self.config.enable_wal_mode {
+            return Err(StorageError::Validation(
+                "Query snapshots need WAL mode".to_string(),
+            ));
+        }
+
+        let connection = self.connect_read_only()?;
+        connection.execute_batch("BEGIN DEFERRED")?;
+        // The snapshot is taken by the first read, not by BEGIN
+        connection.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))?;
+        Ok(connection)
+    }
+
     /// Ensures the database directory exists
     fn ensure_database_directory(&self) -> Result<()> {
         if self.config.is_in_memory() {
@@ -182,17 +330,35 @@ impl DatabaseManager {
         // For better safety
         flags |= OpenFlags::SQLITE_OPEN_NO_MUTEX;
 
-        Connection::open_with_flags(&self.config.database_path, flags)
+        let connection = Connection::open_with_flags(&self.config.database_path, flags)?;
+        // The key must be set before anything reads the file
+        if let Some(key) = &self.config.encryption_key {
+            apply_key(&connection, key)?;
+        }
+        Ok(connection)
     }
 
     /// Configures the connection with performance and safety settings
     fn configure_connection(&self, connection: &mut Connection) -> Result<()> {
+        register_functions(connection)?;
+
         // Enable foreign key constraints
         connection.execute("PRAGMA foreign_keys = ON", [])?;
 
         // Configure WAL mode for better concurrency (if enabled and not in-memory)
         if self.config.enable_wal_mode && !self.config.is_in_memory() {
-            connection.execute("PRAGMA journal_mode = WAL", [])?;
+            // journal_mode reports the resulting mode as a row
+            connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+            connection.query_row(
+                &format!("PRAGMA wal_autocheckpoint = {}", self.config.wal_autocheckpoint_pages),
+                [],
+                |_| Ok(()),
+            )?;
+            // Cap the size the WAL is truncated back to after a checkpoint
+            if self.config.wal_size_limit_mb > 0 {
+                let limit_bytes = self.config.wal_size_limit_mb * 1024 * 1024;
+                connection.query_row(&format!("PRAGMA journal_size_limit = {}", limit_bytes), [], |_| Ok(()))?;
+            }
         }
 
         // Configure synchronous mode for better performance while maintaining safety
@@ -217,7 +383,8 @@ impl DatabaseManager {
         // Set maximum database size if specified
         if self.config.max_size_mb > 0 && !self.config.is_in_memory() {
             let max_pages = (self.config.max_size_mb * 1024 * 1024) / 4096; // 4KB pages
-            connection.execute(&format!("PRAGMA max_page_count = {}", max_pages), [])?;
+            // Answers with the new limit, which execute() refuses
+            connection.pragma_update_and_check(None, "max_page_count", max_pages, |_| Ok(()))?;
         }
 
         // Enable query optimization
@@ -227,19 +394,121 @@ impl DatabaseManager {
     }
 
     /// Applies all database migrations
+    ///
+    /// A database created by a newer release is never migrated: migrations
+    /// only run forward. If its schema still admits this build as a reader
+    /// it is opened read-only, since writes would leave the tables this
+    /// build doesn't know out of step; otherwise it is refused.
     fn apply_migrations(&self, connection: &mut Connection) -> Result<()> {
         let migrated_conn = std::mem::replace(connection, Connection::open(":memory:")?);
         let mut migrator = SchemaMigrator::new(migrated_conn);
-        migrator.migrate()?;
+        match migrator.compatibility()? {
+            SchemaCompatibility::Current => migrator.migrate()?,
+            SchemaCompatibility::ReadOnly { found } => {
+                warn!(
+                    "Database schema version {} is newer than this build's ({}); opening it read-only",
+                    found, CURRENT_SCHEMA_VERSION
+                );
+                migrator.connection().execute("PRAGMA query_only = ON", [])?;
+            }
+            SchemaCompatibility::Incompatible { found } => {
+                return Err(StorageError::NewerSchema { found, supported: CURRENT_SCHEMA_VERSION });
+            }
+        }
         *connection = migrator.into_connection();
         Ok(())
     }
 
+    /// Writes a copy of the database stripped down to schema version `target`
+    ///
+    /// The copy is what a binary of that version expects, so indices can be
+    /// shared with teammates who haven't upgraded; data of the stripped
+    /// features is lost in the copy. The database itself is left as is.
+    pub fn export_downgraded(&self, destination: &Path, target: i32) -> Result<()> {
+        if !(1..=CURRENT_SCHEMA_VERSION).contains(&target) {
+            return Err(StorageError::Validation(format!(
+                "Schema version {} is out of range; this build downgrades to 1..={}",
+                target, CURRENT_SCHEMA_VERSION
+            )));
+        }
+        if destination.exists() {
+            return Err(StorageError::Conflict(format!("{} already exists", destination.display())));
+        }
+
+        // This build can't undo the migrations of a newer release
+        let source = self.connect_raw()?;
+        let source = match SchemaMigrator::new(source).compatibility()? {
+            SchemaCompatibility::ReadOnly { found } | SchemaCompatibility::Incompatible { found } => {
+                return Err(StorageError::NewerSchema { found, supported: CURRENT_SCHEMA_VERSION });
+            }
+            SchemaCompatibility::Current => self.connect()?,
+        };
+        source.execute("VACUUM INTO ?1", [destination.to_string_lossy()])?;
+        drop(source);
+
+        let copy = Connection::open(destination)?;
+        if let Some(key) = &self.config.encryption_key {
+            apply_key(&copy, key)?;
+        }
+        let mut migrator = SchemaMigrator::new(copy);
+        migrator.downgrade(target)?;
+        migrator.connection().execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Moves the database itself to schema version `target`, upgrading or downgrading it
+    ///
+    /// Every version between the database's and `target` must be crossable,
+    /// or nothing changes; a database of a newer release is refused since
+    /// this build doesn't know how to undo its migrations. A backup recovery
+    /// can restore is written before anything changes. Opening the database
+    /// normally migrates it back to this build's version.
+    pub fn migrate_in_place(&self, target: i32) -> Result<SchemaMigration> {
+        if !(1..=CURRENT_SCHEMA_VERSION).contains(&target) {
+            return Err(StorageError::Validation(format!(
+                "Schema version {} is out of range; this build migrates to 1..={}",
+                target, CURRENT_SCHEMA_VERSION
+            )));
+        }
+        if self.config.read_only {
+            return Err(StorageError::ReadOnly("Can't migrate a database opened read-only".to_string()));
+        }
+
+        let mut migrator = SchemaMigrator::new(self.connect_raw()?);
+        if let SchemaCompatibility::ReadOnly { found } | SchemaCompatibility::Incompatible { found } = migrator.compatibility()? {
+            return Err(StorageError::NewerSchema { found, supported: CURRENT_SCHEMA_VERSION });
+        }
+        let from = migrator.get_current_version()?;
+        let missing = migrator.missing_steps(target)?;
+        if !missing.is_empty() {
+            return Err(StorageError::Validation(format!(
+                "Can't migrate from schema version {} to {}: versions {:?} have no migration in that direction",
+                from, target, missing
+            )));
+        }
+        if from == target {
+            return Ok(SchemaMigration { from, to: target, backup_path: None });
+        }
+
+        let backup_path = if self.config.is_in_memory() {
+            None
+        } else {
+            Some(DatabaseRecovery::new(self.config.clone()).create_backup(migrator.connection())?)
+        };
+        migrator.migrate_to(target)?;
+        Ok(SchemaMigration { from, to: target, backup_path })
+    }
+
     /// Returns the database configuration
     pub fn config(&self) -> &DatabaseConfig {
         &self.config
     }
 
+    /// Returns a disk space guard honouring this database's location and size limit
+    pub fn disk_space_guard(&self) -> DiskSpaceGuard {
+        DiskSpaceGuard::new(&self.config.database_path).with_max_size_mb(self.config.max_size_mb)
+    }
+
     /// Checks if the database exists and is accessible
     pub fn database_exists(&self) -> bool {
         if self.config.is_in_memory() {
@@ -287,6 +556,7 @@ impl DatabaseManager {
         Ok(DatabaseInfo {
             schema_version,
             file_size_bytes: file_size,
+            wal_size_bytes: self.wal_size_bytes(),
             page_count,
             page_size,
             table_count,
@@ -298,6 +568,35 @@ impl DatabaseManager {
         })
     }
 
+    /// Returns the current size of the write-ahead log (0 if there is none)
+    pub fn wal_size_bytes(&self) -> u64 {
+        if self.config.is_in_memory() {
+            return 0;
+        }
+        fs::metadata(self.config.wal_path()).map(|m| m.len()).unwrap_or(0)
+    }
+
+    /// Copies WAL content back into the database
+    pub fn checkpoint(&self, connection: &Connection, mode: CheckpointMode) -> Result<CheckpointResult> {
+        let started = std::time::Instant::now();
+        let wal_size_before = self.wal_size_bytes();
+        let (busy, log_frames, checkpointed_frames): (i64, i64, i64) = connection.query_row(
+            &format!("PRAGMA wal_checkpoint({})", mode.as_str()),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        Ok(CheckpointResult {
+            mode,
+            busy: busy != 0,
+            log_frames,
+            checkpointed_frames,
+            wal_size_before,
+            wal_size_after: self.wal_size_bytes(),
+            duration: started.elapsed(),
+        })
+    }
+
     /// Performs database maintenance operations
     pub fn maintenance(&self) -> Result<MaintenanceResult> {
         let connection = self.connect()?;
@@ -357,11 +656,257 @@ impl DatabaseManager {
     }
 }
 
+/// Connections to one database shared by concurrent callers
+///
+/// Index updates go through a single writer connection, as SQLite allows
+/// only one writer at a time. Queries check out read-only connections,
+/// opened on demand up to `pool_size - 1` of them, so in WAL mode they read
+/// the last committed state instead of waiting behind a re-index. Without
+/// WAL, or for an in-memory database, there are no readers and everything
+/// shares the writer.
+#[derive(Debug, Clone)]
+pub struct ConnectionPool {
+    shared: Arc<PoolShared>,
+}
+
+/// A connection checked out of a [`ConnectionPool`], returned to it on drop
+///
+/// A plain [`Connection`] converts into one that belongs to no pool.
+#[derive(Debug)]
+pub struct PooledConnection {
+    connection: Option<Connection>,
+    home: Option<(Arc<PoolShared>, PoolRole)>,
+}
+
+#[derive(Debug)]
+struct PoolShared {
+    manager: DatabaseManager,
+    readers: PoolLane,
+    writer: PoolLane,
+}
+
+/// Connections of one role: idle ones and how many are open in total
+#[derive(Debug)]
+struct PoolLane {
+    capacity: u32,
+    state: Mutex<LaneState>,
+    returned: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct LaneState {
+    idle: Vec<Connection>,
+    open: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PoolRole {
+    Reader,
+    Writer,
+}
+
+impl ConnectionPool {
+    /// Opens the writer, migrating the database, and sizes the readers from `pool_size`
+    pub fn new(manager: DatabaseManager) -> Result<Self> {
+        let config = manager.config();
+        let readers = if config.enable_wal_mode && !config.is_in_memory() { config.pool_size - 1 } else { 0 };
+        let writer = PoolLane::new(1);
+        *writer.lock() = LaneState { idle: vec![manager.connect()?], open: 1 };
+        let shared = PoolShared { readers: PoolLane::new(readers), writer, manager };
+        Ok(Self { shared: Arc::new(shared) })
+    }
+
+    /// The pooled database's manager
+    pub fn manager(&self) -> &DatabaseManager {
+        &self.shared.manager
+    }
+
+    /// Most reader connections the pool opens; 0 means queries share the writer
+    pub fn max_readers(&self) -> u32 {
+        self.shared.readers.capacity
+    }
+
+    /// Reader connections open now, idle or checked out
+    pub fn open_readers(&self) -> u32 {
+        self.shared.readers.lock().open
+    }
+
+    /// Checks out a read-only connection, or the writer if the pool has no readers
+    ///
+    /// Waits up to the query timeout for a connection to be returned when
+    /// all of them are in use.
+    pub fn reader(&self) -> Result<PooledConnection> {
+        match self.shared.readers.capacity {
+            0 => self.checkout(PoolRole::Writer),
+            _ => self.checkout(PoolRole::Reader),
+        }
+    }
+
+    /// Checks out the writer, waiting up to the query timeout while another caller holds it
+    pub fn writer(&self) -> Result<PooledConnection> {
+        self.checkout(PoolRole::Writer)
+    }
+
+    fn checkout(&self, role: PoolRole) -> Result<PooledConnection> {
+        let lane = self.shared.lane(role);
+        let timeout = std::time::Duration::from_secs(self.shared.manager.config().query_timeout_seconds);
+        let deadline = std::time::Instant::now() + timeout;
+        let mut state = lane.lock();
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PooledConnection { connection: Some(connection), home: Some((Arc::clone(&self.shared), role)) });
+            }
+            if state.open < lane.capacity {
+                state.open += 1;
+                drop(state);
+                return match self.shared.open(role) {
+                    Ok(connection) => Ok(PooledConnection { connection: Some(connection), home: Some((Arc::clone(&self.shared), role)) }),
+                    Err(e) => {
+                        lane.release();
+                        Err(e)
+                    }
+                };
+            }
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                return Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+                    Some(format!("No {} connection became free within {}s", role.as_str(), timeout.as_secs())),
+                )
+                .into());
+            }
+            state = lane.returned.wait_timeout(state, deadline - now).unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+}
+
+impl PoolShared {
+    fn open(&self, role: PoolRole) -> Result<Connection> {
+        match role {
+            PoolRole::Reader => self.manager.connect_read_only(),
+            PoolRole::Writer => self.manager.connect(),
+        }
+    }
+
+    fn lane(&self, role: PoolRole) -> &PoolLane {
+        match role {
+            PoolRole::Reader => &self.readers,
+            PoolRole::Writer => &self.writer,
+        }
+    }
+}
+
+impl PoolLane {
+    fn new(capacity: u32) -> Self {
+        Self { capacity, state: Mutex::new(LaneState::default()), returned: Condvar::new() }
+    }
+
+    /// The lane's state; nothing panics while holding it, so a poisoned lock is still consistent
+    fn lock(&self) -> MutexGuard<'_, LaneState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a connection to the idle list; one left in a transaction is closed instead
+    fn checkin(&self, connection: Connection) {
+        let mut state = self.lock();
+        if connection.is_autocommit() {
+            state.idle.push(connection);
+        } else {
+            state.open = state.open.saturating_sub(1);
+        }
+        self.returned.notify_one();
+    }
+
+    /// Gives up a slot whose connection was closed or kept by its caller
+    fn release(&self) {
+        let mut state = self.lock();
+        state.open = state.open.saturating_sub(1);
+        self.returned.notify_one();
+    }
+}
+
+impl PoolRole {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PoolRole::Reader => "reader",
+            PoolRole::Writer => "writer",
+        }
+    }
+}
+
+impl PooledConnection {
+    /// Takes the connection out of its pool, which may open another in its place
+    pub fn into_inner(mut self) -> Connection {
+        let connection = self.connection.take().expect("connection is present until dropped");
+        if let Some((shared, role)) = self.home.take() {
+            shared.lane(role).release();
+        }
+        connection
+    }
+}
+
+impl From<Connection> for PooledConnection {
+    fn from(connection: Connection) -> Self {
+        Self { connection: Some(connection), home: None }
+    }
+}
+
+impl std::ops::Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().expect("connection is present until dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let (Some(connection), Some((shared, role))) = (self.connection.take(), self.home.take()) {
+            shared.lane(role).checkin(connection);
+        }
+    }
+}
+
+/// Registers the SQL functions queries rely on
+///
+/// `regexp(pattern, text)` backs the REGEXP operator; each statement compiles
+/// its pattern once. NULL text never matches.
+pub fn register_functions(connection: &Connection) -> Result<()> {
+    connection.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+            let regex: Arc<regex::Regex> = ctx.get_or_create_aux(0, |pattern| -> std::result::Result<_, BoxError> {
+                Ok(regex::Regex::new(pattern.as_str()?)?)
+            })?;
+            match ctx.get_raw(1) {
+                rusqlite::types::ValueRef::Null => Ok(false),
+                text => Ok(regex.is_match(text.as_str().map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?)),
+            }
+        },
+    )?;
+    Ok(())
+}
+
+/// Outcome of [`DatabaseManager::migrate_in_place`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaMigration {
+    /// Schema version before the migration
+    pub from: i32,
+    pub to: i32,
+    /// Backup of the database as it was; None if nothing changed or it lives in memory
+    pub backup_path: Option<PathBuf>,
+}
+
 /// Information about the database
 #[derive(Debug, Clone)]
 pub struct DatabaseInfo {
     pub schema_version: i32,
     pub file_size_bytes: i64,
+    /// Size of the write-ahead log not yet checkpointed into the database
+    pub wal_size_bytes: u64,
     pub page_count: i64,
     pub page_size: i64,
     pub table_count: i64,
@@ -393,6 +938,95 @@ impl DatabaseInfo {
     }
 }
 
+/// How aggressively a checkpoint waits for readers and writers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointMode {
+    /// Checkpoint what can be done without waiting
+    Passive,
+    /// Wait for writers, then checkpoint everything
+    Full,
+    /// Like Full, then wait for readers so the log can restart from the beginning
+    Restart,
+    /// Like Restart, then truncate the WAL file to zero bytes
+    Truncate,
+}
+
+impl CheckpointMode {
+    /// Returns the SQLite pragma argument
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckpointMode::Passive => "PASSIVE",
+            CheckpointMode::Full => "FULL",
+            CheckpointMode::Restart => "RESTART",
+            CheckpointMode::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// Result of a WAL checkpoint
+#[derive(Debug, Clone)]
+pub struct CheckpointResult {
+    pub mode: CheckpointMode,
+    /// True if the checkpoint could not complete because of concurrent access
+    pub busy: bool,
+    /// Frames in the WAL (-1 if not in WAL mode)
+    pub log_frames: i64,
+    /// Frames copied into the database (-1 if not in WAL mode)
+    pub checkpointed_frames: i64,
+    pub wal_size_before: u64,
+    pub wal_size_after: u64,
+    pub duration: std::time::Duration,
+}
+
+/// Triggers checkpoints when the WAL grows past its size limit or the interval elapses
+///
+/// SQLite's autocheckpoint never shrinks the WAL and is starved by long-lived
+/// readers; callers that write continuously (such as the file watcher) call
+/// `maybe_checkpoint` after each batch to keep the log bounded.
+#[derive(Debug)]
+pub struct CheckpointScheduler {
+    interval: Option<std::time::Duration>,
+    size_limit_bytes: Option<u64>,
+    last_checkpoint: std::time::Instant,
+}
+
+impl CheckpointScheduler {
+    /// Creates a scheduler from the database configuration
+    pub fn new(config: &DatabaseConfig) -> Self {
+        Self {
+            interval: (config.wal_checkpoint_interval_seconds > 0)
+                .then(|| std::time::Duration::from_secs(config.wal_checkpoint_interval_seconds)),
+            size_limit_bytes: (config.wal_size_limit_mb > 0).then(|| config.wal_size_limit_mb * 1024 * 1024),
+            last_checkpoint: std::time::Instant::now(),
+        }
+    }
+
+    /// Returns the checkpoint mode due now, if any
+    pub fn due(&self, wal_size_bytes: u64) -> Option<CheckpointMode> {
+        if self.size_limit_bytes.is_some_and(|limit| wal_size_bytes > limit) {
+            return Some(CheckpointMode::Truncate);
+        }
+        if wal_size_bytes > 0 && self.interval.is_some_and(|interval| self.last_checkpoint.elapsed() >= interval) {
+            return Some(CheckpointMode::Passive);
+        }
+        None
+    }
+
+    /// Checkpoints if a threshold has been crossed
+    pub fn maybe_checkpoint(&mut self, manager: &DatabaseManager, connection: &Connection) -> Result<Option<CheckpointResult>> {
+        let mode = match self.due(manager.wal_size_bytes()) {
+            Some(mode) => mode,
+            None => return Ok(None),
+        };
+
+        let result = manager.checkpoint(connection, mode)?;
+        if !result.busy {
+            self.last_checkpoint = std::time::Instant::now();
+        }
+        Ok(Some(result))
+    }
+}
+
 /// Result of database maintenance operations
 #[derive(Debug, Clone)]
 pub struct MaintenanceResult {
@@ -483,6 +1117,151 @@ mod tests {
         assert!(db_path.exists());
     }
 
+    #[test]
+    fn test_newer_schema_is_refused() {
+        let temp_dir = tempdir().unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(temp_dir.path().join("newer.db"))).unwrap();
+        let newer = manager.connect().unwrap();
+        newer.execute("INSERT INTO schema_migrations (version) VALUES (?1)", [CURRENT_SCHEMA_VERSION + 1]).unwrap();
+
+        // Readable by this build: opened read-only
+        let read_only = manager.connect().unwrap();
+        let count: i64 = read_only.query_row("SELECT COUNT(*) FROM code_indices", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+        assert!(read_only
+            .execute("INSERT INTO code_indices (id, name, base_path, created_at, updated_at) VALUES ('a', 'engine', '/src', '2024-01-01', '2024-01-01')", [])
+            .is_err());
+
+        newer.execute("UPDATE schema_compatibility SET min_reader_version = ?1", [CURRENT_SCHEMA_VERSION + 1]).unwrap();
+        let error = manager.connect().unwrap_err();
+        assert!(matches!(error, StorageError::NewerSchema { found, supported } if found == CURRENT_SCHEMA_VERSION + 1 && supported == CURRENT_SCHEMA_VERSION));
+        assert!(error.to_string().contains("self-update"));
+        assert!(!error.is_client_error());
+    }
+
+    #[test]
+    fn test_read_only_processes_share_a_database() {
+        let temp_dir = tempdir().unwrap();
+        let config = DatabaseConfig::new(temp_dir.path().join("shared.db"));
+        let missing = DatabaseManager::new(config.clone().with_read_only(true)).unwrap();
+        assert!(matches!(missing.connect(), Err(StorageError::NotFound(_))));
+        assert!(!config.database_path.exists());
+
+        let writer = DatabaseManager::new(config.clone()).unwrap().connect().unwrap();
+        let insert = |id: &str| {
+            writer.execute(
+                "INSERT INTO code_indices (id, name, base_path, created_at, updated_at) VALUES (?1, ?1, '/src', '2024-01-01', '2024-01-01')",
+                [id],
+            )
+        };
+        insert("engine").unwrap();
+
+        let reader = DatabaseManager::new(config.with_read_only(true)).unwrap().connect().unwrap();
+        let count = || -> i64 { reader.query_row("SELECT COUNT(*) FROM code_indices", [], |row| row.get(0)).unwrap() };
+        reader.execute_batch("BEGIN DEFERRED").unwrap();
+        assert_eq!(count(), 1);
+        // The writer commits while the reader's transaction keeps its snapshot
+        insert("audio").unwrap();
+        assert_eq!(count(), 1);
+        reader.execute_batch("COMMIT").unwrap();
+        assert_eq!(count(), 2);
+
+        let error = StorageError::from(reader.execute("DELETE FROM code_indices", []).unwrap_err());
+        assert_eq!(error.kind(), "read_only");
+
+        writer.execute("DELETE FROM schema_migrations WHERE version = ?1", [CURRENT_SCHEMA_VERSION]).unwrap();
+        assert!(matches!(missing.connect(), Err(StorageError::ReadOnly(_))));
+    }
+
+    #[test]
+    fn test_export_downgraded() {
+        let temp_dir = tempdir().unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(temp_dir.path().join("index.db"))).unwrap();
+        manager.connect().unwrap().execute(
+            "INSERT INTO code_indices (id, name, base_path, created_at, updated_at)
+             VALUES ('a', 'engine', '/src', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+            [],
+        ).unwrap();
+
+        let destination = temp_dir.path().join("index-v12.db");
+        manager.export_downgraded(&destination, 12).unwrap();
+        let copy = Connection::open(&destination).unwrap();
+        let version: i32 = copy.query_row("SELECT MAX(version) FROM schema_migrations", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, 12);
+        let name: String = copy.query_row("SELECT name FROM code_indices", [], |row| row.get(0)).unwrap();
+        assert_eq!(name, "engine");
+        let stripped: i64 = copy
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE name IN ('code_elements_fts', 'symbol_bodies')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stripped, 0);
+        assert_eq!(manager.get_database_info().unwrap().schema_version, CURRENT_SCHEMA_VERSION);
+
+        assert!(matches!(manager.export_downgraded(&destination, 10), Err(StorageError::Conflict(_))));
+        assert!(matches!(manager.export_downgraded(&temp_dir.path().join("future.db"), CURRENT_SCHEMA_VERSION + 1), Err(StorageError::Validation(_))));
+
+        // A database of a newer release is refused rather than copied with its migrations half undone
+        manager.connect().unwrap().execute("INSERT INTO schema_migrations (version) VALUES (?1)", [CURRENT_SCHEMA_VERSION + 1]).unwrap();
+        assert!(matches!(manager.export_downgraded(&temp_dir.path().join("newer.db"), 12), Err(StorageError::NewerSchema { .. })));
+        assert!(!temp_dir.path().join("newer.db").exists());
+    }
+
+    #[test]
+    fn test_migrate_in_place() {
+        let temp_dir = tempdir().unwrap();
+        let config = DatabaseConfig::new(temp_dir.path().join("index.db"));
+        let manager = DatabaseManager::new(config.clone()).unwrap();
+        manager.connect().unwrap().execute(
+            "INSERT INTO code_indices (id, name, base_path, created_at, updated_at)
+             VALUES ('a', 'engine', '/src', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+            [],
+        ).unwrap();
+        let version = || -> i32 { manager.connect_raw().unwrap().query_row("SELECT MAX(version) FROM schema_migrations", [], |row| row.get(0)).unwrap() };
+
+        let migration = manager.migrate_in_place(12).unwrap();
+        assert_eq!((migration.from, migration.to, version()), (CURRENT_SCHEMA_VERSION, 12, 12));
+        // The backup is the database as it was before
+        let backup = Connection::open(migration.backup_path.unwrap()).unwrap();
+        let backed_up: i32 = backup.query_row("SELECT MAX(version) FROM schema_migrations", [], |row| row.get(0)).unwrap();
+        assert_eq!(backed_up, CURRENT_SCHEMA_VERSION);
+
+        let migration = manager.migrate_in_place(20).unwrap();
+        assert_eq!((migration.from, version()), (12, 20));
+        assert_eq!(manager.migrate_in_place(20).unwrap().backup_path, None);
+        let name: String = manager.connect().unwrap().query_row("SELECT name FROM code_indices", [], |row| row.get(0)).unwrap();
+        assert_eq!((name.as_str(), version()), ("engine", CURRENT_SCHEMA_VERSION));
+
+        assert!(matches!(manager.migrate_in_place(0), Err(StorageError::Validation(_))));
+        let read_only = DatabaseManager::new(config.with_read_only(true)).unwrap();
+        assert!(matches!(read_only.migrate_in_place(12), Err(StorageError::ReadOnly(_))));
+        manager.connect().unwrap().execute("INSERT INTO schema_migrations (version) VALUES (?1)", [CURRENT_SCHEMA_VERSION + 1]).unwrap();
+        assert!(matches!(manager.migrate_in_place(12), Err(StorageError::NewerSchema { .. })));
+    }
+
+    #[test]
+    fn test_snapshot_ignores_later_commits() {
+        let temp_dir = tempdir().unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(temp_dir.path().join("snapshot.db"))).unwrap();
+        let writer = manager.connect().unwrap();
+        let count = |connection: &Connection| -> i64 {
+            connection.query_row("SELECT COUNT(*) FROM code_indices", [], |row| row.get(0)).unwrap()
+        };
+
+        let snapshot = manager.connect_snapshot().unwrap();
+        writer.execute(
+            "INSERT INTO code_indices (id, name, base_path, created_at, updated_at)
+             VALUES ('a', 'engine', '/src', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+            [],
+        ).unwrap();
+
+        assert_eq!(count(&writer), 1);
+        assert_eq!(count(&snapshot), 0);
+        drop(snapshot);
+        assert_eq!(count(&manager.connect_snapshot().unwrap()), 1);
+
+        let in_memory = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        assert!(matches!(in_memory.connect_snapshot(), Err(StorageError::Validation(_))));
+    }
+
     #[test]
     fn test_database_info() {
         let config = DatabaseConfig::in_memory();
@@ -498,6 +1277,34 @@ mod tests {
         assert_eq!(info.schema_version, CURRENT_SCHEMA_VERSION);
     }
 
+    #[test]
+    fn test_wal_checkpoint_scheduling() {
+        let temp_dir = tempdir().unwrap();
+        let config = DatabaseConfig::new(temp_dir.path().join("wal.db"))
+            .with_wal_autocheckpoint(0)
+            .with_wal_checkpoint_interval(0)
+            .with_wal_size_limit_mb(64);
+        let manager = DatabaseManager::new(config.clone()).unwrap();
+        let connection = manager.connect().unwrap();
+
+        let info = manager.get_database_info().unwrap();
+        assert!(info.wal_size_bytes > 0);
+
+        // Below the size limit and without an interval nothing is due
+        let mut scheduler = CheckpointScheduler::new(&config);
+        assert_eq!(scheduler.due(info.wal_size_bytes), None);
+        assert!(scheduler.maybe_checkpoint(&manager, &connection).unwrap().is_none());
+        assert_eq!(scheduler.due(65 * 1024 * 1024), Some(CheckpointMode::Truncate));
+
+        let result = manager.checkpoint(&connection, CheckpointMode::Truncate).unwrap();
+        assert!(!result.busy);
+        assert!(result.wal_size_before > 0);
+        assert_eq!(result.wal_size_after, 0);
+
+        let interval = CheckpointScheduler::new(&config.with_wal_checkpoint_interval(1));
+        assert_eq!(interval.due(4096), None);
+    }
+
     #[test]
     fn test_database_maintenance() {
         let config = DatabaseConfig::in_memory();
@@ -506,9 +1313,8 @@ mod tests {
         
         let result = manager.maintenance().unwrap();
         
-        assert!(result.analyze_duration.as_millis() >= 0);
-        assert!(result.optimize_duration.as_millis() >= 0);
         assert!(result.vacuum_duration.is_none()); // No vacuum for in-memory
+        assert_eq!(result.total_duration(), result.analyze_duration + result.optimize_duration);
     }
 
     #[test]
@@ -516,6 +1322,7 @@ mod tests {
         let info = DatabaseInfo {
             schema_version: 1,
             file_size_bytes: 1024,
+            wal_size_bytes: 0,
             page_count: 1,
             page_size: 1024,
             table_count: 1,
@@ -544,4 +1351,40 @@ mod tests {
         manager.delete_database().unwrap();
         assert!(!manager.database_exists());
     }
+
+    #[test]
+    fn test_pool_readers_do_not_wait_for_writer() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = DatabaseConfig::new(temp_dir.path().join("pool.db")).with_query_timeout(1);
+        config.pool_size = 3;
+        let pool = ConnectionPool::new(DatabaseManager::new(config).unwrap()).unwrap();
+        assert_eq!(pool.max_readers(), 2);
+
+        let writer = pool.writer().unwrap();
+        writer.execute("CREATE TABLE counters (value INTEGER)", []).unwrap();
+        writer.execute_batch("BEGIN IMMEDIATE; INSERT INTO counters VALUES (1);").unwrap();
+
+        // Readers see the last commit while the write is open
+        let reader = pool.reader().unwrap();
+        let count: i64 = reader.query_row("SELECT COUNT(*) FROM counters", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+        assert!(reader.execute("INSERT INTO counters VALUES (2)", []).is_err());
+
+        // One writer: a second checkout times out; readers run out past pool_size - 1
+        assert!(pool.writer().is_err());
+        let second = pool.reader().unwrap();
+        assert!(pool.reader().is_err());
+        drop(second);
+        assert!(pool.reader().is_ok());
+        assert_eq!(pool.open_readers(), 2);
+
+        writer.execute_batch("COMMIT").unwrap();
+        drop(writer);
+        let count: i64 = pool.reader().unwrap().query_row("SELECT COUNT(*) FROM counters", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+
+        let in_memory = ConnectionPool::new(DatabaseManager::new(DatabaseConfig::in_memory()).unwrap()).unwrap();
+        assert_eq!(in_memory.max_readers(), 0);
+        assert!(in_memory.reader().is_ok());
+    }
 }
\ No newline at end of file
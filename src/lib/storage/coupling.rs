@@ -0,0 +1,271 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::directory_depth::directory_of;
+use crate::lib::storage::repository::Repository;
+
+/// Unit that references are aggregated into
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CouplingGranularity {
+    File,
+    Directory,
+}
+
+/// File formats reports are exported in; each report supports some of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Html,
+}
+
+/// Fan-in and fan-out of one file or directory
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CouplingMetrics {
+    /// File path, or directory path ("." for the index root)
+    pub unit: String,
+    /// Distinct units referencing this one
+    pub fan_in: u64,
+    /// Distinct units this one references
+    pub fan_out: u64,
+    /// References from other units into this one
+    pub incoming_references: u64,
+    /// References from this unit into others
+    pub outgoing_references: u64,
+    /// fan_out / (fan_in + fan_out): 0 is depended upon only, 1 only depends on others
+    pub instability: f64,
+}
+
+/// References from one unit to another; a cell of the heatmap
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CouplingEdge {
+    pub from: String,
+    pub to: String,
+    pub reference_count: u64,
+}
+
+/// Coupling of every unit that references or is referenced by another
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CouplingReport {
+    pub granularity: CouplingGranularity,
+    /// Sorted by unit
+    pub units: Vec<CouplingMetrics>,
+    /// Sorted by source, then target
+    pub edges: Vec<CouplingEdge>,
+}
+
+impl CouplingGranularity {
+    /// Parses "file" or "directory"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "file" => Some(Self::File),
+            "directory" | "dir" => Some(Self::Directory),
+            _ => None,
+        }
+    }
+
+    /// Unit a stored file path belongs to
+    fn unit_of(self, file_path: &str) -> &str {
+        match self {
+            Self::File => file_path,
+            Self::Directory => match directory_of(file_path) {
+                "" => ".",
+                directory => directory,
+            },
+        }
+    }
+}
+
+impl ExportFormat {
+    /// Parses "csv", "json" or "html"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+
+    /// Format implied by a file's extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension().and_then(|extension| extension.to_str()).and_then(Self::parse)
+    }
+}
+
+impl CouplingReport {
+    /// Computes coupling from the cross-file references of an index
+    pub fn analyze(repository: &Repository, index_id: &Uuid, granularity: CouplingGranularity) -> Result<Self> {
+        Ok(Self::from_dependencies(&repository.get_file_dependencies(index_id, &[])?, granularity))
+    }
+
+    /// Aggregates file-to-file reference counts into units
+    ///
+    /// References between files of the same directory don't count at
+    /// directory granularity.
+    pub fn from_dependencies(dependencies: &BTreeMap<(String, String), u64>, granularity: CouplingGranularity) -> Self {
+        let mut edges: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+        for ((from, to), count) in dependencies {
+            let (from, to) = (granularity.unit_of(from), granularity.unit_of(to));
+            if from != to {
+                *edges.entry((from, to)).or_default() += count;
+            }
+        }
+
+        let mut dependents: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut dependencies_of: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        let mut incoming: BTreeMap<&str, u64> = BTreeMap::new();
+        let mut outgoing: BTreeMap<&str, u64> = BTreeMap::new();
+        for (&(from, to), &count) in &edges {
+            dependencies_of.entry(from).or_default().insert(to);
+            dependents.entry(to).or_default().insert(from);
+            *outgoing.entry(from).or_default() += count;
+            *incoming.entry(to).or_default() += count;
+        }
+
+        let units: BTreeSet<&str> = edges.keys().flat_map(|&(from, to)| [from, to]).collect();
+        let units = units
+            .into_iter()
+            .map(|unit| {
+                let fan_in = dependents.get(unit).map_or(0, BTreeSet::len) as u64;
+                let fan_out = dependencies_of.get(unit).map_or(0, BTreeSet::len) as u64;
+                CouplingMetrics {
+                    unit: unit.to_string(),
+                    fan_in,
+                    fan_out,
+                    incoming_references: incoming.get(unit).copied().unwrap_or(0),
+                    outgoing_references: outgoing.get(unit).copied().unwrap_or(0),
+                    // Every unit has at least one edge, so the sum is never zero
+                    instability: fan_out as f64 / (fan_in + fan_out) as f64,
+                }
+            })
+            .collect();
+
+        let edges = edges
+            .into_iter()
+            .map(|((from, to), reference_count)| CouplingEdge { from: from.to_string(), to: to.to_string(), reference_count })
+            .collect();
+
+        Self { granularity, units, edges }
+    }
+
+    /// One row per unit, with a header row
+    pub fn units_csv(&self) -> String {
+        let mut csv = String::from("unit,fan_in,fan_out,incoming_references,outgoing_references,instability\n");
+        for metrics in &self.units {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{:.3}\n",
+                csv_field(&metrics.unit),
+                metrics.fan_in,
+                metrics.fan_out,
+                metrics.incoming_references,
+                metrics.outgoing_references,
+                metrics.instability
+            ));
+        }
+        csv
+    }
+
+    /// One row per pair of coupled units, with a header row
+    pub fn edges_csv(&self) -> String {
+        let mut csv = String::from("from,to,reference_count\n");
+        for edge in &self.edges {
+            csv.push_str(&format!("{},{},{}\n", csv_field(&edge.from), csv_field(&edge.to), edge.reference_count));
+        }
+        csv
+    }
+
+    /// Units and edges as a JSON document
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("coupling report is always serializable")
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies() -> BTreeMap<(String, String), u64> {
+        [
+            ("src/app/main.cpp", "src/core/init.cpp", 3),
+            ("src/app/main.cpp", "src/core/log.cpp", 1),
+            ("src/app/menu.cpp", "src/core/log.cpp", 2),
+            ("src/core/init.cpp", "src/core/log.cpp", 4),
+            ("tools/gen.cpp", "src/core/log.cpp", 1),
+        ]
+        .into_iter()
+        .map(|(from, to, count)| ((from.to_string(), to.to_string()), count))
+        .collect()
+    }
+
+    #[test]
+    fn test_file_coupling() {
+        let report = CouplingReport::from_dependencies(&dependencies(), CouplingGranularity::File);
+        assert_eq!(report.units.len(), 5);
+        assert_eq!(report.edges.len(), 5);
+
+        let log = report.units.iter().find(|metrics| metrics.unit == "src/core/log.cpp").unwrap();
+        assert_eq!((log.fan_in, log.fan_out), (4, 0));
+        assert_eq!(log.incoming_references, 8);
+        assert_eq!(log.instability, 0.0);
+
+        let main = report.units.iter().find(|metrics| metrics.unit == "src/app/main.cpp").unwrap();
+        assert_eq!((main.fan_in, main.fan_out, main.outgoing_references), (0, 2, 4));
+        assert_eq!(main.instability, 1.0);
+    }
+
+    #[test]
+    fn test_directory_coupling_skips_internal_references() {
+        let report = CouplingReport::from_dependencies(&dependencies(), CouplingGranularity::Directory);
+        let units: Vec<&str> = report.units.iter().map(|metrics| metrics.unit.as_str()).collect();
+        assert_eq!(units, ["src/app", "src/core", "tools"]);
+        assert_eq!(
+            report.edges,
+            [
+                CouplingEdge { from: "src/app".to_string(), to: "src/core".to_string(), reference_count: 6 },
+                CouplingEdge { from: "tools".to_string(), to: "src/core".to_string(), reference_count: 1 },
+            ]
+        );
+
+        let core = &report.units[1];
+        assert_eq!((core.fan_in, core.fan_out, core.incoming_references), (2, 0, 7));
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let mut dependencies = dependencies();
+        dependencies.insert(("a,b.cpp".to_string(), "c.cpp".to_string()), 2);
+        let report = CouplingReport::from_dependencies(&dependencies, CouplingGranularity::File);
+
+        let units = report.units_csv();
+        let mut lines = units.lines();
+        assert_eq!(lines.next(), Some("unit,fan_in,fan_out,incoming_references,outgoing_references,instability"));
+        assert_eq!(lines.next(), Some("\"a,b.cpp\",0,1,0,2,1.000"));
+        assert!(report.edges_csv().contains("src/core/init.cpp,src/core/log.cpp,4\n"));
+
+        assert_eq!(ExportFormat::from_path(Path::new("out/coupling.CSV")), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_path(Path::new("coupling")), None);
+        assert_eq!(ExportFormat::from_path(Path::new("dsm.htm")), Some(ExportFormat::Html));
+    }
+}
@@ -0,0 +1,272 @@
+use rusqlite::ErrorCode;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::lib::storage::error::StorageError;
+
+/// Number of files measured when estimating the size of a new index
+pub const DEFAULT_SAMPLE_SIZE: usize = 200;
+
+/// Estimated database bytes written per byte of source code (symbols, relationships, indices)
+pub const DB_BYTES_PER_SOURCE_BYTE: f64 = 1.5;
+
+/// Estimated database bytes written per file regardless of its size (file metadata row, hashes)
+pub const DB_BYTES_PER_FILE: u64 = 2048;
+
+/// Extra free space kept for the WAL, temporary b-trees and other processes
+pub const DEFAULT_RESERVE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Raised instead of a raw SQLITE_FULL so callers can stop cleanly and resume later
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DiskSpaceError {
+    /// Not enough free space to start or continue indexing
+    #[error(
+        "Insufficient disk space for {path}: about {required_mb} MB needed but only {available_mb} MB free. \
+         Indexing is paused; free up space (e.g. `index gc`) and re-run to resume from where it stopped.",
+        path = .path.display(),
+        required_mb = .required_bytes / (1024 * 1024),
+        available_mb = .available_bytes / (1024 * 1024)
+    )]
+    Insufficient {
+        path: PathBuf,
+        required_bytes: u64,
+        available_bytes: u64,
+    },
+    /// SQLite reported the disk or the configured size limit as full
+    #[error(
+        "Database {path} is full. Indexing is paused and the last batch was rolled back; \
+         free up space or raise the size limit and re-run to resume from where it stopped.",
+        path = .path.display()
+    )]
+    DatabaseFull { path: PathBuf },
+}
+
+/// Extrapolated size of an index built from a set of source files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeEstimate {
+    /// Total number of files to index
+    pub file_count: usize,
+    /// Number of files actually measured
+    pub sampled_files: usize,
+    /// Extrapolated total size of the source files
+    pub source_bytes: u64,
+    /// Estimated database growth
+    pub database_bytes: u64,
+}
+
+/// Checks free space around the database before and during indexing
+#[derive(Debug, Clone)]
+pub struct DiskSpaceGuard {
+    database_path: PathBuf,
+    reserve_bytes: u64,
+    max_size_bytes: Option<u64>,
+}
+
+impl SizeEstimate {
+    /// Estimates database growth from a sample of the files to index
+    ///
+    /// Files are sampled at even intervals so large trees are not walked twice;
+    /// unreadable files count as empty.
+    pub fn from_files<P: AsRef<Path>>(files: &[P], sample_size: usize) -> Self {
+        let file_count = files.len();
+        if file_count == 0 {
+            return Self {
+                file_count: 0,
+                sampled_files: 0,
+                source_bytes: 0,
+                database_bytes: 0,
+            };
+        }
+
+        let step = (file_count / sample_size.max(1)).max(1);
+        let sampled: Vec<u64> = files
+            .iter()
+            .step_by(step)
+            .map(|file| fs::metadata(file).map(|m| m.len()).unwrap_or(0))
+            .collect();
+
+        let sampled_bytes: u64 = sampled.iter().sum();
+        let source_bytes = sampled_bytes * file_count as u64 / sampled.len() as u64;
+
+        Self {
+            file_count,
+            sampled_files: sampled.len(),
+            source_bytes,
+            database_bytes: Self::database_bytes_for(source_bytes, file_count),
+        }
+    }
+
+    /// Estimated database bytes for the given amount of source and number of files
+    pub fn database_bytes_for(source_bytes: u64, file_count: usize) -> u64 {
+        (source_bytes as f64 * DB_BYTES_PER_SOURCE_BYTE) as u64 + file_count as u64 * DB_BYTES_PER_FILE
+    }
+}
+
+impl DiskSpaceGuard {
+    /// Creates a guard for the database at the given path
+    pub fn new<P: AsRef<Path>>(database_path: P) -> Self {
+        Self {
+            database_path: database_path.as_ref().to_path_buf(),
+            reserve_bytes: DEFAULT_RESERVE_BYTES,
+            max_size_bytes: None,
+        }
+    }
+
+    /// Sets how much free space must remain after indexing
+    pub fn with_reserve_bytes(mut self, reserve_bytes: u64) -> Self {
+        self.reserve_bytes = reserve_bytes;
+        self
+    }
+
+    /// Also enforces the database's configured maximum size (0 = unlimited)
+    pub fn with_max_size_mb(self, max_size_mb: u64) -> Self {
+        self.with_max_size_bytes(max_size_mb * 1024 * 1024)
+    }
+
+    /// Also enforces a maximum database size in bytes (0 = unlimited)
+    pub fn with_max_size_bytes(mut self, max_size_bytes: u64) -> Self {
+        self.max_size_bytes = (max_size_bytes > 0).then_some(max_size_bytes);
+        self
+    }
+
+    /// Checks that the estimated growth fits before indexing starts
+    pub fn preflight(&self, estimate: &SizeEstimate) -> Result<(), DiskSpaceError> {
+        self.ensure_available(estimate.database_bytes)
+    }
+
+    /// Checks that `remaining_bytes` more can still be written; call between batches
+    pub fn ensure_available(&self, remaining_bytes: u64) -> Result<(), DiskSpaceError> {
+        let required_bytes = remaining_bytes + self.reserve_bytes;
+
+        if let Some(available_bytes) = free_space(self.space_root()) {
+            if available_bytes < required_bytes {
+                return Err(DiskSpaceError::Insufficient {
+                    path: self.database_path.clone(),
+                    required_bytes,
+                    available_bytes,
+                });
+            }
+        }
+
+        if let Some(max_size_bytes) = self.max_size_bytes {
+            let current = fs::metadata(&self.database_path).map(|m| m.len()).unwrap_or(0);
+            let available_bytes = max_size_bytes.saturating_sub(current);
+            if available_bytes < remaining_bytes {
+                return Err(DiskSpaceError::Insufficient {
+                    path: self.database_path.clone(),
+                    required_bytes: remaining_bytes,
+                    available_bytes,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Converts SQLITE_FULL into a resumable error; other errors are not disk related
+    pub fn classify(&self, error: &StorageError) -> Option<DiskSpaceError> {
+        is_disk_full(error).then(|| DiskSpaceError::DatabaseFull {
+            path: self.database_path.clone(),
+        })
+    }
+
+    /// Nearest existing directory on the database's filesystem
+    fn space_root(&self) -> &Path {
+        let mut path = self.database_path.as_path();
+        while !path.exists() {
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => path = parent,
+                _ => return Path::new("."),
+            }
+        }
+        path
+    }
+}
+
+/// Returns true if SQLite failed because the disk or the page limit is full
+pub fn is_disk_full(error: &StorageError) -> bool {
+    matches!(error, StorageError::Sqlite(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::DiskFull)
+}
+
+/// Returns the bytes available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is a valid NUL-terminated string and stats is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+/// Free space is not queried on this platform; checks fall back to SQLite errors
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_size_estimate_extrapolates_sample() {
+        let dir = tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..10)
+            .map(|i| {
+                let path = dir.path().join(format!("file{}.cpp", i));
+                fs::write(&path, vec![b'x'; 1000]).unwrap();
+                path
+            })
+            .collect();
+
+        let estimate = SizeEstimate::from_files(&files, 5);
+        assert_eq!(estimate.file_count, 10);
+        assert_eq!(estimate.sampled_files, 5);
+        assert_eq!(estimate.source_bytes, 10_000);
+        assert_eq!(estimate.database_bytes, SizeEstimate::database_bytes_for(10_000, 10));
+
+        let empty = SizeEstimate::from_files::<PathBuf>(&[], DEFAULT_SAMPLE_SIZE);
+        assert_eq!(empty.database_bytes, 0);
+    }
+
+    #[test]
+    fn test_preflight_reports_insufficient_space() {
+        let dir = tempdir().unwrap();
+        let guard = DiskSpaceGuard::new(dir.path().join("missing/index.db")).with_reserve_bytes(0);
+
+        assert!(free_space(dir.path()).is_some());
+        assert!(guard.ensure_available(0).is_ok());
+
+        let err = guard.ensure_available(u64::MAX / 2).unwrap_err();
+        assert!(matches!(err, DiskSpaceError::Insufficient { .. }));
+        assert!(err.to_string().contains("re-run to resume"));
+    }
+
+    #[test]
+    fn test_max_size_limit() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("index.db");
+        fs::write(&db_path, vec![0u8; 512 * 1024]).unwrap();
+
+        let guard = DiskSpaceGuard::new(&db_path).with_reserve_bytes(0).with_max_size_mb(1);
+        assert!(guard.ensure_available(256 * 1024).is_ok());
+        assert!(guard.ensure_available(768 * 1024).is_err());
+    }
+
+    #[test]
+    fn test_classify_sqlite_full() {
+        let guard = DiskSpaceGuard::new("/tmp/index.db");
+        let full = StorageError::from(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_FULL), None));
+        let busy = StorageError::from(rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY), None));
+
+        assert!(matches!(guard.classify(&full), Some(DiskSpaceError::DatabaseFull { .. })));
+        assert!(guard.classify(&busy).is_none());
+    }
+}
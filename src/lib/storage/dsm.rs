@@ -0,0 +1,277 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::directory_depth::directory_of;
+use crate::lib::storage::models::symbol_relationships::RelationshipType;
+use crate::lib::storage::repository::Repository;
+
+/// Directory depth used when none is given
+pub const DEFAULT_DSM_LEVEL: usize = 2;
+
+/// Relationships that make one directory depend on another
+pub const DSM_RELATIONSHIP_TYPES: [RelationshipType; 3] =
+    [RelationshipType::Includes, RelationshipType::Calls, RelationshipType::ReferencedAsCallback];
+
+/// Design structure matrix of directory-to-directory dependencies
+///
+/// `cells[row][column]` counts the references from `directories[row]` into
+/// `directories[column]`. Directories in a dependency cycle are grouped
+/// next to each other and listed in `cycles`, one group per strongly
+/// connected component.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct DependencyMatrix {
+    /// Number of leading path components that name a directory
+    pub level: usize,
+    pub directories: Vec<String>,
+    pub cells: Vec<Vec<u64>>,
+    /// Groups of directories that depend on each other, each sorted by name
+    pub cycles: Vec<Vec<String>>,
+}
+
+/// First `level` components of a file's directory, "." for files at the index root
+pub fn directory_at_level(file_path: &str, level: usize) -> String {
+    let components: Vec<&str> = directory_of(file_path).split('/').filter(|c| !c.is_empty()).take(level).collect();
+    if components.is_empty() {
+        ".".to_string()
+    } else {
+        components.join("/")
+    }
+}
+
+impl DependencyMatrix {
+    /// Builds the matrix from the include and call relationships of an index
+    pub fn analyze(repository: &Repository, index_id: &Uuid, level: usize) -> Result<Self> {
+        Ok(Self::from_dependencies(&repository.get_file_dependencies(index_id, &DSM_RELATIONSHIP_TYPES)?, level))
+    }
+
+    /// Aggregates file-to-file reference counts into directories `level` deep
+    pub fn from_dependencies(dependencies: &BTreeMap<(String, String), u64>, level: usize) -> Self {
+        let level = level.max(1);
+        let mut edges: BTreeMap<(String, String), u64> = BTreeMap::new();
+        let mut directories: BTreeSet<String> = BTreeSet::new();
+        for ((from, to), count) in dependencies {
+            let (from, to) = (directory_at_level(from, level), directory_at_level(to, level));
+            directories.insert(from.clone());
+            directories.insert(to.clone());
+            if from != to {
+                *edges.entry((from, to)).or_default() += count;
+            }
+        }
+
+        let cycles = strongly_connected(&directories, &edges);
+        // Members of a cycle sit together, ordered by their first member's name
+        let mut order: Vec<String> = Vec::with_capacity(directories.len());
+        let mut placed: BTreeSet<&str> = BTreeSet::new();
+        for directory in &directories {
+            if placed.contains(directory.as_str()) {
+                continue;
+            }
+            match cycles.iter().find(|cycle| cycle.contains(directory)) {
+                Some(cycle) => {
+                    placed.extend(cycle.iter().map(String::as_str));
+                    order.extend(cycle.iter().cloned());
+                }
+                None => {
+                    placed.insert(directory.as_str());
+                    order.push(directory.clone());
+                }
+            }
+        }
+
+        let position: BTreeMap<&str, usize> = order.iter().enumerate().map(|(i, directory)| (directory.as_str(), i)).collect();
+        let mut cells = vec![vec![0; order.len()]; order.len()];
+        for ((from, to), count) in &edges {
+            cells[position[from.as_str()]][position[to.as_str()]] = *count;
+        }
+
+        Self { level, directories: order, cells, cycles }
+    }
+
+    /// True if both directories belong to the same cycle
+    pub fn in_cycle(&self, row: usize, column: usize) -> bool {
+        let (from, to) = (&self.directories[row], &self.directories[column]);
+        self.cycles.iter().any(|cycle| cycle.contains(from) && cycle.contains(to))
+    }
+
+    /// The matrix as a JSON document
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("dependency matrix is always serializable")
+    }
+
+    /// A standalone HTML page with the matrix as a table, cycle cells highlighted
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Dependency structure matrix</title>\n<style>\n\
+             table { border-collapse: collapse; font: 12px monospace; }\n\
+             th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: right; }\n\
+             th.row { text-align: left; }\n\
+             td.self { background: #eee; }\n\
+             td.cycle { background: #f4b6b6; font-weight: bold; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+        html.push_str(&format!("<h1>Dependency structure matrix (level {})</h1>\n", self.level));
+        html.push_str("<p>Row depends on column; numbers count include and call references.</p>\n");
+
+        html.push_str("<table>\n<tr><th></th>");
+        for (column, directory) in self.directories.iter().enumerate() {
+            html.push_str(&format!("<th title=\"{}\">{}</th>", escape_html(directory), column + 1));
+        }
+        html.push_str("</tr>\n");
+        for (row, directory) in self.directories.iter().enumerate() {
+            html.push_str(&format!("<tr><th class=\"row\">{} {}</th>", row + 1, escape_html(directory)));
+            for (column, &count) in self.cells[row].iter().enumerate() {
+                let class = if row == column {
+                    " class=\"self\""
+                } else if count > 0 && self.in_cycle(row, column) {
+                    " class=\"cycle\""
+                } else {
+                    ""
+                };
+                let value = if count > 0 { count.to_string() } else { String::new() };
+                html.push_str(&format!("<td{}>{}</td>", class, value));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+
+        if self.cycles.is_empty() {
+            html.push_str("<p>No dependency cycles.</p>\n");
+        } else {
+            html.push_str("<h2>Cycles</h2>\n<ul>\n");
+            for cycle in &self.cycles {
+                let names: Vec<String> = cycle.iter().map(|directory| escape_html(directory)).collect();
+                html.push_str(&format!("<li>{}</li>\n", names.join(" &harr; ")));
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/// Strongly connected components with more than one member (Tarjan's algorithm)
+fn strongly_connected(nodes: &BTreeSet<String>, edges: &BTreeMap<(String, String), u64>) -> Vec<Vec<String>> {
+    let mut successors: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (from, to) in edges.keys() {
+        successors.entry(from.as_str()).or_default().push(to.as_str());
+    }
+
+    struct State<'a> {
+        successors: BTreeMap<&'a str, Vec<&'a str>>,
+        next_index: usize,
+        index: BTreeMap<&'a str, usize>,
+        low_link: BTreeMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: BTreeSet<&'a str>,
+        components: Vec<Vec<String>>,
+    }
+
+    fn visit<'a>(state: &mut State<'a>, node: &'a str) {
+        state.index.insert(node, state.next_index);
+        state.low_link.insert(node, state.next_index);
+        state.next_index += 1;
+        state.stack.push(node);
+        state.on_stack.insert(node);
+
+        let successors = state.successors.get(node).cloned().unwrap_or_default();
+        for successor in successors {
+            if !state.index.contains_key(successor) {
+                visit(state, successor);
+                let low = state.low_link[node].min(state.low_link[successor]);
+                state.low_link.insert(node, low);
+            } else if state.on_stack.contains(successor) {
+                let low = state.low_link[node].min(state.index[successor]);
+                state.low_link.insert(node, low);
+            }
+        }
+
+        if state.low_link[node] == state.index[node] {
+            let mut component = Vec::new();
+            while let Some(member) = state.stack.pop() {
+                state.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == node {
+                    break;
+                }
+            }
+            if component.len() > 1 {
+                component.sort();
+                state.components.push(component);
+            }
+        }
+    }
+
+    let mut state = State {
+        successors,
+        next_index: 0,
+        index: BTreeMap::new(),
+        low_link: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+    for node in nodes {
+        if !state.index.contains_key(node.as_str()) {
+            visit(&mut state, node);
+        }
+    }
+    state.components.sort();
+    state.components
+}
+
+/// Escapes text for use in HTML content and attribute values
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies() -> BTreeMap<(String, String), u64> {
+        [
+            ("src/app/main.cpp", "src/core/init.cpp", 3),
+            ("src/core/init.cpp", "src/net/socket.cpp", 2),
+            ("src/net/socket.cpp", "src/core/log.cpp", 1),
+            ("src/core/log.cpp", "src/core/init.cpp", 5),
+            ("tools/gen/gen.cpp", "src/app/main.cpp", 1),
+            ("main.cpp", "src/app/main.cpp", 1),
+        ]
+        .into_iter()
+        .map(|(from, to, count)| ((from.to_string(), to.to_string()), count))
+        .collect()
+    }
+
+    #[test]
+    fn test_directory_at_level() {
+        assert_eq!(directory_at_level("src/core/detail/init.cpp", 2), "src/core");
+        assert_eq!(directory_at_level("src/init.cpp", 2), "src");
+        assert_eq!(directory_at_level("init.cpp", 2), ".");
+    }
+
+    #[test]
+    fn test_matrix_groups_cycles() {
+        let matrix = DependencyMatrix::from_dependencies(&dependencies(), 2);
+        assert_eq!(matrix.directories, [".", "src/app", "src/core", "src/net", "tools/gen"]);
+        assert_eq!(matrix.cycles, [vec!["src/core".to_string(), "src/net".to_string()]]);
+
+        // Intra-directory references (log -> init) stay off the matrix
+        assert_eq!(matrix.cells[2][2], 0);
+        assert_eq!(matrix.cells[1][2], 3);
+        assert!(matrix.in_cycle(2, 3));
+        assert!(!matrix.in_cycle(1, 2));
+
+        let top = DependencyMatrix::from_dependencies(&dependencies(), 1);
+        assert_eq!(top.directories, [".", "src", "tools"]);
+        assert!(top.cycles.is_empty());
+    }
+
+    #[test]
+    fn test_html_highlights_cycles() {
+        let html = DependencyMatrix::from_dependencies(&dependencies(), 2).to_html();
+        assert_eq!(html.matches("class=\"cycle\"").count(), 2);
+        assert!(html.contains("<li>src/core &harr; src/net</li>"));
+    }
+}
@@ -0,0 +1,143 @@
+// Code element listings
+//
+// `query` prints the symbols it finds for a person at a terminal or for a
+// script: an aligned table, a JSON array or CSV rows, all with the same
+// columns.
+
+use serde::Serialize;
+
+use crate::lib::storage::coupling::csv_field;
+use crate::lib::storage::models::code_element::CodeElement;
+
+/// Formats a listing can be printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// One listed symbol
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ElementRow {
+    /// Qualified name
+    pub symbol: String,
+    #[serde(rename = "type")]
+    pub symbol_type: &'static str,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub declaration: bool,
+    pub signature: Option<String>,
+}
+
+impl ListingFormat {
+    /// Parses "table", "json" or "csv"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "table" => Some(Self::Table),
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl From<&CodeElement> for ElementRow {
+    fn from(element: &CodeElement) -> Self {
+        Self {
+            symbol: element.fully_qualified_name(),
+            symbol_type: element.symbol_type.as_str(),
+            file: element.file_path.clone(),
+            line: element.line_number,
+            column: element.column_number,
+            declaration: element.is_declaration,
+            signature: element.signature.clone(),
+        }
+    }
+}
+
+/// Renders rows in `format`; tables and CSV have a header row
+pub fn render_rows(rows: &[ElementRow], format: ListingFormat) -> String {
+    match format {
+        ListingFormat::Table => table(rows),
+        ListingFormat::Json => serde_json::to_string_pretty(rows).expect("element rows are always serializable"),
+        ListingFormat::Csv => {
+            let mut csv = String::from("symbol,type,file,line,column,declaration,signature\n");
+            for row in rows {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    csv_field(&row.symbol),
+                    row.symbol_type,
+                    csv_field(&row.file),
+                    row.line,
+                    row.column,
+                    row.declaration,
+                    csv_field(row.signature.as_deref().unwrap_or(""))
+                ));
+            }
+            csv
+        }
+    }
+}
+
+/// Columns padded to their widest cell; the signature comes last and isn't padded
+fn table(rows: &[ElementRow]) -> String {
+    let cells: Vec<[String; 4]> = std::iter::once(["SYMBOL".to_string(), "TYPE".to_string(), "LOCATION".to_string(), "SIGNATURE".to_string()])
+        .chain(rows.iter().map(|row| {
+            [
+                row.symbol.clone(),
+                row.symbol_type.to_string(),
+                format!("{}:{}:{}", row.file, row.line, row.column),
+                row.signature.clone().unwrap_or_default(),
+            ]
+        }))
+        .collect();
+    let widths: Vec<usize> = (0..3).map(|column| cells.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+
+    let mut table = String::new();
+    for [symbol, symbol_type, location, signature] in &cells {
+        let line = format!("{:<w0$}  {:<w1$}  {:<w2$}  {}", symbol, symbol_type, location, signature, w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(symbol: &str, signature: Option<&str>) -> ElementRow {
+        ElementRow {
+            symbol: symbol.to_string(),
+            symbol_type: "function",
+            file: "src/audio/mixer.cpp".to_string(),
+            line: 42,
+            column: 6,
+            declaration: false,
+            signature: signature.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_render_rows() {
+        let rows = [row("audio::Mixer::mix", Some("void mix(float, int)")), row("audio::reset", None)];
+
+        let table = render_rows(&rows, ListingFormat::Table);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "SYMBOL             TYPE      LOCATION                  SIGNATURE");
+        assert_eq!(lines[1], "audio::Mixer::mix  function  src/audio/mixer.cpp:42:6  void mix(float, int)");
+        assert_eq!(lines[2], "audio::reset       function  src/audio/mixer.cpp:42:6");
+
+        let csv = render_rows(&rows, ListingFormat::Csv);
+        assert!(csv.contains("audio::Mixer::mix,function,src/audio/mixer.cpp,42,6,false,\"void mix(float, int)\"\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&render_rows(&rows, ListingFormat::Json)).unwrap();
+        assert_eq!(json[1]["type"], "function");
+        assert!(json[1]["signature"].is_null());
+
+        assert_eq!(ListingFormat::parse("CSV"), Some(ListingFormat::Csv));
+        assert_eq!(ListingFormat::parse("xml"), None);
+    }
+}
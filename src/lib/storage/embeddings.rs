@@ -0,0 +1,851 @@
+// Symbol embeddings
+//
+// Semantic search ranks symbols by how close they are in meaning to a
+// question ("where is frame rate limiting handled?") instead of by shared
+// words. The name, scope, signature and doc comment of each symbol are turned
+// into a vector by a pluggable backend: the built-in hashing embedder, a local
+// ONNX sentence model, any local command or an OpenAI-compatible embeddings
+// API. Vectors live in a sidecar database next to the index database,
+// `<database>.embeddings`, so indices that never use semantic search don't
+// carry them. With the sqlite-vss extensions loaded each embedded index gets
+// a `vss0` table searched by approximate nearest neighbours; without them
+// vectors are compared by brute-force cosine similarity, which takes well
+// under a second for a few hundred thousand symbols.
+//
+// Each vector is stored with a hash of the text it was computed from, so
+// embedding an index again after a re-index only sends the symbols whose
+// text changed to the backend.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, LoadExtensionGuard, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use uuid::Uuid;
+
+use crate::lib::storage::connection::DatabaseConfig;
+use crate::lib::storage::encryption::apply_key;
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::code_index::CodeIndex;
+use crate::lib::storage::query::{CodeElementQuery, ElementColumn, Filter};
+use crate::lib::storage::repository::Repository;
+
+/// Dimensions of the built-in hashing embedder unless configured otherwise
+pub const DEFAULT_HASHING_DIMENSIONS: usize = 512;
+
+/// Texts sent to a backend per call
+pub const EMBEDDING_BATCH_SIZE: usize = 64;
+
+/// Elements read from the index per page while embedding it
+const ELEMENT_PAGE_SIZE: u64 = 5000;
+
+/// Neighbours fetched from vss0 per result asked for when filtering by symbol type
+const VSS_FILTER_OVERFETCH: usize = 8;
+
+/// Runs an ONNX sentence model on `{"texts": [...]}` from stdin: tokenizes
+/// with `tokenizers`, mean-pools the last hidden state over the attention mask
+const ONNX_SCRIPT: &str = r#"
+import json, sys
+import numpy as np
+import onnxruntime
+from tokenizers import Tokenizer
+
+model, tokenizer_path = sys.argv[1], sys.argv[2]
+texts = json.load(sys.stdin)["texts"]
+tokenizer = Tokenizer.from_file(tokenizer_path)
+tokenizer.enable_padding()
+tokenizer.enable_truncation(512)
+encodings = tokenizer.encode_batch(texts)
+ids = np.array([e.ids for e in encodings], dtype=np.int64)
+mask = np.array([e.attention_mask for e in encodings], dtype=np.int64)
+session = onnxruntime.InferenceSession(model, providers=["CPUExecutionProvider"])
+inputs = {"input_ids": ids, "attention_mask": mask, "token_type_ids": np.zeros_like(ids)}
+names = {i.name for i in session.get_inputs()}
+hidden = session.run(None, {name: value for name, value in inputs.items() if name in names})[0]
+if hidden.ndim == 3:
+    weights = mask[..., None].astype(hidden.dtype)
+    hidden = (hidden * weights).sum(axis=1) / np.clip(weights.sum(axis=1), 1e-9, None)
+json.dump({"embeddings": hidden.tolist()}, sys.stdout)
+"#;
+
+/// Words too common in questions to say anything about a symbol
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "be", "by", "does", "for", "from", "get", "how", "in", "is", "it", "of", "on", "or", "set",
+    "the", "this", "to", "what", "where", "which", "with",
+];
+
+/// Turns texts into vectors of equal length
+///
+/// Vectors of different backends, or of one backend with different models,
+/// are not comparable; `name` tells them apart.
+pub trait EmbeddingBackend: Debug + Send + Sync {
+    /// Backend and model the vectors come from, e.g. `hashing-512`
+    fn name(&self) -> String;
+
+    /// One vector per text, in order
+    fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, String>;
+}
+
+/// Which backend computes embeddings, from the `embeddings` configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum EmbeddingConfig {
+    /// No semantic search
+    #[default]
+    Disabled,
+    /// Built in: hashed identifier words and trigrams, no model needed
+    Hashing { dimensions: usize },
+    /// A sentence model in ONNX format with its `tokenizer.json`, run by onnxruntime
+    Onnx { model: PathBuf, tokenizer: PathBuf, python: Option<String> },
+    /// A local program reading `{"texts": [...]}` on stdin and printing `{"embeddings": [[...], ...]}`
+    Command { command: String, model: String },
+    /// An OpenAI-compatible `/embeddings` endpoint
+    Http { endpoint: String, model: String, api_key_env: Option<String> },
+}
+
+/// Embeds text by hashing its words and their trigrams into a fixed number of buckets
+///
+/// Needs no model, so it works offline and on any machine. Identifiers are
+/// split at case changes and underscores and words reduced to a rough stem,
+/// so `FrameRateLimiter` lands close to "frame rate limiting"; meaning beyond
+/// shared word parts needs a model backend.
+#[derive(Debug, Clone)]
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+/// Embeds text with a local program
+#[derive(Debug, Clone)]
+pub struct CommandEmbedder {
+    command: String,
+    model: String,
+}
+
+/// Embeds text with an ONNX sentence model, mean-pooling its token vectors
+///
+/// The model runs in onnxruntime's Python bindings, with the `tokenizers`
+/// package splitting text the way the model was trained; both must be
+/// installed for `python`.
+#[derive(Debug, Clone)]
+pub struct OnnxEmbedder {
+    model: PathBuf,
+    tokenizer: PathBuf,
+    python: String,
+}
+
+/// Embeds text with an OpenAI-compatible embeddings API, through the system `curl`
+#[derive(Debug, Clone)]
+pub struct HttpEmbedder {
+    endpoint: String,
+    model: String,
+    /// Environment variable holding the API key, sent as a bearer token
+    api_key_env: Option<String>,
+}
+
+/// What embedding an index did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EmbeddingRefresh {
+    /// Symbols sent to the backend
+    pub embedded: usize,
+    /// Symbols whose text was unchanged, keeping their vector
+    pub reused: usize,
+    /// Stored vectors dropped: of symbols gone from the index or whose text changed, or of another backend
+    pub removed: usize,
+}
+
+/// The embeddings stored for an index
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmbeddingStatus {
+    pub backend: String,
+    pub dimensions: usize,
+    pub symbols: u64,
+    /// `updated_at` of the index when it was embedded; differs from the index's once it changes
+    pub index_updated_at: DateTime<Utc>,
+    pub embedded_at: DateTime<Utc>,
+}
+
+/// A symbol close to a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticHit {
+    pub element_id: i64,
+    /// Cosine similarity to the query, -1 to 1
+    pub score: f32,
+}
+
+/// Symbol vectors of any number of indices, in a sidecar database
+#[derive(Debug)]
+pub struct EmbeddingStore {
+    connection: Connection,
+    /// Whether the sqlite-vss extensions are loaded
+    vss: bool,
+}
+
+impl EmbeddingConfig {
+    /// The configured backend; None if semantic search is disabled
+    pub fn backend(&self) -> Option<Box<dyn EmbeddingBackend>> {
+        match self {
+            EmbeddingConfig::Disabled => None,
+            EmbeddingConfig::Hashing { dimensions } => Some(Box::new(HashingEmbedder::new(*dimensions))),
+            EmbeddingConfig::Onnx { model, tokenizer, python } => {
+                Some(Box::new(OnnxEmbedder::new(model, tokenizer, python.as_deref().unwrap_or("python3"))))
+            }
+            EmbeddingConfig::Command { command, model } => Some(Box::new(CommandEmbedder::new(command, model))),
+            EmbeddingConfig::Http { endpoint, model, api_key_env } => {
+                Some(Box::new(HttpEmbedder::new(endpoint, model, api_key_env.as_deref())))
+            }
+        }
+    }
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions: dimensions.max(16) }
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASHING_DIMENSIONS)
+    }
+}
+
+impl EmbeddingBackend for HashingEmbedder {
+    fn name(&self) -> String {
+        format!("hashing-{}", self.dimensions)
+    }
+
+    fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, String> {
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut vector = vec![0f32; self.dimensions];
+                let mut add = |feature: &str, weight: f32| {
+                    let hash = fnv1a(feature.as_bytes());
+                    let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+                    vector[(hash % self.dimensions as u64) as usize] += sign * weight;
+                };
+                for word in words(text) {
+                    let stem = stem(&word);
+                    add(&format!("w:{}", stem), 1.0);
+                    let padded: Vec<char> = format!("<{}>", stem).chars().collect();
+                    for trigram in padded.windows(3) {
+                        add(&format!("t:{}", trigram.iter().collect::<String>()), 0.3);
+                    }
+                }
+                normalize(&mut vector);
+                vector
+            })
+            .collect())
+    }
+}
+
+impl CommandEmbedder {
+    /// Runs `command` with `sh -c`; `model` names the vectors it produces
+    pub fn new(command: &str, model: &str) -> Self {
+        Self { command: command.to_string(), model: model.to_string() }
+    }
+}
+
+impl EmbeddingBackend for CommandEmbedder {
+    fn name(&self) -> String {
+        format!("command:{}", self.model)
+    }
+
+    fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, String> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&self.command);
+        run_embedding_command(command, &self.model, texts)
+    }
+}
+
+impl OnnxEmbedder {
+    /// Runs `model` with the tokenizer saved next to it by `tokenizers`, under the `python` interpreter
+    pub fn new(model: &Path, tokenizer: &Path, python: &str) -> Self {
+        Self { model: model.to_path_buf(), tokenizer: tokenizer.to_path_buf(), python: python.to_string() }
+    }
+}
+
+impl EmbeddingBackend for OnnxEmbedder {
+    fn name(&self) -> String {
+        let model = self.model.file_stem().map_or_else(|| self.model.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+        format!("onnx:{}", model)
+    }
+
+    fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, String> {
+        let mut command = Command::new(&self.python);
+        command.arg("-c").arg(ONNX_SCRIPT).arg(&self.model).arg(&self.tokenizer);
+        run_embedding_command(command, &self.name(), texts)
+    }
+}
+
+impl HttpEmbedder {
+    pub fn new(endpoint: &str, model: &str, api_key_env: Option<&str>) -> Self {
+        Self { endpoint: endpoint.to_string(), model: model.to_string(), api_key_env: api_key_env.map(str::to_string) }
+    }
+}
+
+impl EmbeddingBackend for HttpEmbedder {
+    fn name(&self) -> String {
+        format!("http:{}", self.model)
+    }
+
+    /// Posts the texts in one request; the key and body go to curl on stdin, not on its command line
+    fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, String> {
+        let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut config = format!(
+            "header = {}\ndata-binary = {}\n",
+            quote("Content-Type: application/json"),
+            quote(&json!({"model": self.model, "input": texts}).to_string())
+        );
+        if let Some(variable) = &self.api_key_env {
+            let key = std::env::var(variable).map_err(|_| format!("{} is not set", variable))?;
+            config.push_str(&format!("header = {}\n", quote(&format!("Authorization: Bearer {}", key))));
+        }
+
+        let mut child = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--max-time", "120", "--config", "-"])
+            .arg(&self.endpoint)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run curl: {}", e))?;
+        child
+            .stdin
+            .take()
+            .ok_or("curl has no stdin")?
+            .write_all(config.as_bytes())
+            .map_err(|e| format!("Failed to write to curl: {}", e))?;
+        let output = child.wait_with_output().map_err(|e| format!("curl failed: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("Embedding request failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        #[derive(Deserialize)]
+        struct Datum {
+            index: usize,
+            embedding: Vec<f32>,
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            data: Vec<Datum>,
+        }
+        let mut response: Response =
+            serde_json::from_slice(&output.stdout).map_err(|e| format!("Malformed embeddings response: {}", e))?;
+        response.data.sort_by_key(|datum| datum.index);
+        checked(response.data.into_iter().map(|datum| datum.embedding).collect(), texts.len())
+    }
+}
+
+impl EmbeddingStore {
+    /// Opens the sidecar of the configured database, creating it if needed
+    pub fn open(config: &DatabaseConfig) -> Result<Self> {
+        if config.is_in_memory() {
+            return Self::in_memory();
+        }
+        let connection = Connection::open(Self::sidecar_path(&config.database_path))?;
+        if let Some(key) = &config.encryption_key {
+            apply_key(&connection, key)?;
+        }
+        Self::init(connection)
+    }
+
+    /// Loads the sqlite-vss `vector0` and `vss0` extensions from `directory`
+    ///
+    /// Embedding an index then also builds its `vss0` table, and searches
+    /// of indices that have one no longer read every vector.
+    pub fn with_vss(mut self, directory: &Path) -> Result<Self> {
+        // SAFETY: the extensions are the ones the user configured; loading is
+        // disabled again before the connection runs anything else
+        unsafe {
+            let _guard = LoadExtensionGuard::new(&self.connection)?;
+            for extension in ["vector0", "vss0"] {
+                self.connection.load_extension(directory.join(extension), None::<&str>).map_err(|e| {
+                    StorageError::Backend(format!("Failed to load sqlite-vss extension {} from {}: {}", extension, directory.display(), e))
+                })?;
+            }
+        }
+        self.vss = true;
+        Ok(self)
+    }
+
+    /// A store that lives as long as the value, for tests and in-memory databases
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    /// Sidecar of a database, `<database>.embeddings`
+    pub fn sidecar_path(database_path: &Path) -> PathBuf {
+        let mut path = database_path.to_path_buf().into_os_string();
+        path.push(".embeddings");
+        PathBuf::from(path)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        // journal_mode reports the resulting mode as a row
+        connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        connection.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS embedded_indices (
+                index_id TEXT PRIMARY KEY,
+                backend TEXT NOT NULL,
+                dimensions INTEGER NOT NULL,
+                index_updated_at TEXT NOT NULL,
+                embedded_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS symbol_embeddings (
+                index_id TEXT NOT NULL,
+                element_id INTEGER NOT NULL,
+                symbol_type TEXT NOT NULL,
+                text_hash TEXT NOT NULL,
+                vector BLOB NOT NULL,
+                PRIMARY KEY (index_id, element_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_symbol_embeddings_hash ON symbol_embeddings(index_id, text_hash);
+            "#,
+        )?;
+        Ok(Self { connection, vss: false })
+    }
+
+    /// Embeds the symbols of an index, reusing the vectors of symbols whose text didn't change
+    ///
+    /// Vectors of another backend are all replaced. The index's symbols are
+    /// read a page at a time; the backend is called in batches of
+    /// `EMBEDDING_BATCH_SIZE`.
+    pub fn refresh(&mut self, repository: &Repository, index: &CodeIndex, backend: &dyn EmbeddingBackend) -> Result<EmbeddingRefresh> {
+        let index_id = index.id.to_string();
+        let backend_name = backend.name();
+        let same_backend = self.status(&index.id)?.is_some_and(|status| status.backend == backend_name);
+        let mut previous: HashMap<String, Vec<u8>> = HashMap::new();
+        if same_backend {
+            let mut statement = self.connection.prepare("SELECT text_hash, vector FROM symbol_embeddings WHERE index_id = ?1")?;
+            let rows = statement.query_map(params![index_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))?;
+            for row in rows {
+                let (hash, vector) = row?;
+                previous.insert(hash, vector);
+            }
+        }
+        let before: u64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM symbol_embeddings WHERE index_id = ?1", params![index_id], |row| row.get(0))?;
+
+        let mut refresh = EmbeddingRefresh::default();
+        let mut dimensions = previous.values().next().map_or(0, |vector| vector.len() / 4);
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM symbol_embeddings WHERE index_id = ?1", params![index_id])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO symbol_embeddings (index_id, element_id, symbol_type, text_hash, vector) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            let mut pending: Vec<PendingSymbol> = Vec::new();
+            let mut after_id = 0i64;
+            loop {
+                let query = CodeElementQuery::new()
+                    .filter(Filter::eq(ElementColumn::IndexId, index_id.clone()))
+                    .filter(Filter::gt(ElementColumn::Id, after_id))
+                    .order_by_asc(ElementColumn::Id)
+                    .limit(ELEMENT_PAGE_SIZE);
+                let elements = repository.query_code_elements(&query)?;
+                for element in &elements {
+                    let Some(id) = element.id else { continue };
+                    let text = embedding_text(element);
+                    let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+                    match previous.get(&hash) {
+                        Some(vector) => {
+                            insert.execute(params![index_id, id, element.symbol_type.as_str(), hash, vector])?;
+                            refresh.reused += 1;
+                        }
+                        None => {
+                            pending.push(PendingSymbol { id, symbol_type: element.symbol_type, hash, text });
+                            refresh.embedded += 1;
+                            if pending.len() >= EMBEDDING_BATCH_SIZE {
+                                store_batch(&mut insert, &index_id, backend, &mut pending, &mut dimensions)?;
+                            }
+                        }
+                    }
+                }
+                match elements.last().and_then(|element| element.id) {
+                    Some(last) if elements.len() as u64 == ELEMENT_PAGE_SIZE => after_id = last,
+                    _ => break,
+                }
+            }
+            store_batch(&mut insert, &index_id, backend, &mut pending, &mut dimensions)?;
+        }
+        transaction.execute(
+            r#"INSERT OR REPLACE INTO embedded_indices (index_id, backend, dimensions, index_updated_at, embedded_at)
+               VALUES (?1, ?2, ?3, ?4, ?5)"#,
+            params![index_id, backend_name, dimensions as i64, index.updated_at.to_rfc3339(), Utc::now().to_rfc3339()],
+        )?;
+        if self.vss {
+            rebuild_vss_table(&transaction, &index_id, dimensions)?;
+        }
+        transaction.commit()?;
+
+        refresh.removed = (before as usize).saturating_sub(refresh.reused);
+        Ok(refresh)
+    }
+
+    /// What is stored for an index; None if it was never embedded
+    pub fn status(&self, index_id: &Uuid) -> Result<Option<EmbeddingStatus>> {
+        let row = self
+            .connection
+            .query_row(
+                r#"SELECT backend, dimensions, index_updated_at, embedded_at,
+                          (SELECT COUNT(*) FROM symbol_embeddings s WHERE s.index_id = e.index_id)
+                   FROM embedded_indices e WHERE index_id = ?1"#,
+                params![index_id.to_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, i64>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((backend, dimensions, index_updated_at, embedded_at, symbols)) = row else { return Ok(None) };
+        let timestamp = |value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|e| StorageError::Corruption(format!("Invalid embedding timestamp '{}': {}", value, e)))
+        };
+        Ok(Some(EmbeddingStatus {
+            backend,
+            dimensions: dimensions as usize,
+            symbols: symbols as u64,
+            index_updated_at: timestamp(&index_updated_at)?,
+            embedded_at: timestamp(&embedded_at)?,
+        }))
+    }
+
+    /// Symbols of an index closest to `query`, best first
+    ///
+    /// The query is embedded with `backend`, which must be the one the index
+    /// was embedded with. `symbol_types` limits the candidates (empty = all).
+    pub fn search(
+        &self,
+        index_id: &Uuid,
+        backend: &dyn EmbeddingBackend,
+        query: &str,
+        symbol_types: &[SymbolType],
+        limit: usize,
+    ) -> Result<Vec<SemanticHit>> {
+        let status = self
+            .status(index_id)?
+            .ok_or_else(|| StorageError::NotFound(format!("Embeddings of index {}; run `index embed` first", index_id)))?;
+        if status.backend != backend.name() {
+            return Err(StorageError::Validation(format!(
+                "Index was embedded with {}, not {}; run `index embed` again",
+                status.backend,
+                backend.name()
+            )));
+        }
+        let mut query_vector = backend
+            .embed(&[query.to_string()])
+            .map_err(|e| StorageError::Backend(format!("{}: {}", status.backend, e)))?
+            .pop()
+            .ok_or_else(|| StorageError::Backend(format!("{} returned no vector", status.backend)))?;
+        normalize(&mut query_vector);
+        if self.vss && self.has_vss_table(&index_id.to_string())? {
+            return self.search_vss(index_id, &query_vector, symbol_types, limit);
+        }
+
+        let mut hits: Vec<SemanticHit> = Vec::new();
+        let mut statement = self
+            .connection
+            .prepare("SELECT element_id, symbol_type, vector FROM symbol_embeddings WHERE index_id = ?1")?;
+        let rows = statement.query_map(params![index_id.to_string()], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Vec<u8>>(2)?))
+        })?;
+        for row in rows {
+            let (element_id, symbol_type, vector) = row?;
+            if !symbol_types.is_empty() && !symbol_types.iter().any(|t| t.as_str() == symbol_type) {
+                continue;
+            }
+            let score = vector
+                .chunks_exact(4)
+                .zip(&query_vector)
+                .map(|(bytes, q)| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) * q)
+                .sum::<f32>();
+            hits.push(SemanticHit { element_id, score });
+        }
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.element_id.cmp(&b.element_id)));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Drops the vectors of an index, e.g. after it was deleted
+    pub fn remove(&self, index_id: &Uuid) -> Result<usize> {
+        let index_id = index_id.to_string();
+        let removed = self.connection.execute("DELETE FROM symbol_embeddings WHERE index_id = ?1", params![index_id])?;
+        self.connection.execute("DELETE FROM embedded_indices WHERE index_id = ?1", params![index_id])?;
+        if self.vss {
+            self.connection.execute_batch(&format!("DROP TABLE IF EXISTS {}", vss_table(&index_id)))?;
+        }
+        Ok(removed)
+    }
+
+    fn has_vss_table(&self, index_id: &str) -> Result<bool> {
+        Ok(self
+            .connection
+            .query_row("SELECT 1 FROM sqlite_master WHERE name = ?1", params![vss_table(index_id)], |_| Ok(()))
+            .optional()?
+            .is_some())
+    }
+
+    /// Nearest neighbours from the index's `vss0` table
+    ///
+    /// vss0 ranks by squared L2 distance, which for unit vectors is
+    /// `2 - 2 * cosine`. With a symbol type filter more neighbours are
+    /// fetched than asked for, since the filter applies after the search.
+    fn search_vss(&self, index_id: &Uuid, query_vector: &[f32], symbol_types: &[SymbolType], limit: usize) -> Result<Vec<SemanticHit>> {
+        let index_id = index_id.to_string();
+        let neighbours = if symbol_types.is_empty() { limit } else { limit.saturating_mul(VSS_FILTER_OVERFETCH) };
+        let mut statement = self.connection.prepare(&format!(
+            r#"SELECT v.rowid, v.distance, s.symbol_type
+               FROM (SELECT rowid, distance FROM {} WHERE vss_search(embedding, ?1) LIMIT ?2) v
+               JOIN symbol_embeddings s ON s.index_id = ?3 AND s.element_id = v.rowid
+               ORDER BY v.distance, v.rowid"#,
+            vss_table(&index_id)
+        ))?;
+        let rows = statement.query_map(params![to_bytes(query_vector), neighbours as i64, index_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?, row.get::<_, String>(2)?))
+        })?;
+        let mut hits = Vec::new();
+        for row in rows {
+            let (element_id, distance, symbol_type) = row?;
+            if !symbol_types.is_empty() && !symbol_types.iter().any(|t| t.as_str() == symbol_type) {
+                continue;
+            }
+            hits.push(SemanticHit { element_id, score: 1.0 - distance as f32 / 2.0 });
+            if hits.len() == limit {
+                break;
+            }
+        }
+        Ok(hits)
+    }
+}
+
+/// The `vss0` table of an index
+fn vss_table(index_id: &str) -> String {
+    format!("vss_{}", index_id.replace('-', ""))
+}
+
+/// Replaces the `vss0` table of an index with one holding its current vectors
+fn rebuild_vss_table(transaction: &rusqlite::Transaction<'_>, index_id: &str, dimensions: usize) -> Result<()> {
+    let table = vss_table(index_id);
+    transaction.execute_batch(&format!("DROP TABLE IF EXISTS {}", table))?;
+    if dimensions == 0 {
+        return Ok(());
+    }
+    transaction.execute_batch(&format!("CREATE VIRTUAL TABLE {} USING vss0(embedding({}))", table, dimensions))?;
+    transaction.execute(
+        &format!("INSERT INTO {} (rowid, embedding) SELECT element_id, vector FROM symbol_embeddings WHERE index_id = ?1", table),
+        params![index_id],
+    )?;
+    Ok(())
+}
+
+/// A symbol waiting for its vector
+struct PendingSymbol {
+    id: i64,
+    symbol_type: SymbolType,
+    hash: String,
+    text: String,
+}
+
+/// Embeds the pending symbols in one backend call and inserts their vectors
+fn store_batch(
+    insert: &mut rusqlite::Statement<'_>,
+    index_id: &str,
+    backend: &dyn EmbeddingBackend,
+    pending: &mut Vec<PendingSymbol>,
+    dimensions: &mut usize,
+) -> Result<()> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let texts: Vec<String> = pending.iter().map(|symbol| symbol.text.clone()).collect();
+    let vectors = backend.embed(&texts).map_err(|e| StorageError::Backend(format!("{}: {}", backend.name(), e)))?;
+    for (symbol, mut vector) in pending.drain(..).zip(vectors) {
+        if *dimensions == 0 {
+            *dimensions = vector.len();
+        } else if vector.len() != *dimensions {
+            return Err(StorageError::Backend(format!(
+                "{} returned a vector of {} dimensions after ones of {}",
+                backend.name(),
+                vector.len(),
+                dimensions
+            )));
+        }
+        normalize(&mut vector);
+        insert.execute(params![index_id, symbol.id, symbol.symbol_type.as_str(), symbol.hash, to_bytes(&vector)])?;
+    }
+    Ok(())
+}
+
+/// Sends `{"model": ..., "texts": [...]}` to a command's stdin and reads `{"embeddings": [[...], ...]}` from its stdout
+fn run_embedding_command(mut command: Command, model: &str, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run embedding command: {}", e))?;
+    let request = json!({"model": model, "texts": texts}).to_string();
+    child
+        .stdin
+        .take()
+        .ok_or("Embedding command has no stdin")?
+        .write_all(request.as_bytes())
+        .map_err(|e| format!("Failed to write to embedding command: {}", e))?;
+    let output = child.wait_with_output().map_err(|e| format!("Embedding command failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Embedding command exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    #[derive(Deserialize)]
+    struct Response {
+        embeddings: Vec<Vec<f32>>,
+    }
+    let response: Response =
+        serde_json::from_slice(&output.stdout).map_err(|e| format!("Malformed embedding command output: {}", e))?;
+    checked(response.embeddings, texts.len())
+}
+
+/// Text a symbol is embedded from: its kind, qualified name in words, signature and doc comment
+pub fn embedding_text(element: &CodeElement) -> String {
+    let qualified = element.fully_qualified_name();
+    let mut text = format!("{} {} ({})", element.symbol_type.as_str(), words(&qualified).join(" "), qualified);
+    for part in [&element.signature, &element.documentation].into_iter().flatten() {
+        text.push('\n');
+        text.push_str(part);
+    }
+    text
+}
+
+/// Lowercase words of a text, identifiers split at case changes, digits and underscores
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for token in text.split(|c: char| !c.is_alphanumeric()) {
+        let chars: Vec<char> = token.chars().collect();
+        let mut start = 0;
+        for i in 1..=chars.len() {
+            let boundary = i == chars.len()
+                || (chars[i].is_uppercase() && chars[i - 1].is_lowercase())
+                || (chars[i].is_uppercase() && chars.get(i + 1).is_some_and(|c| c.is_lowercase()) && chars[i - 1].is_uppercase())
+                || (chars[i].is_ascii_digit() != chars[i - 1].is_ascii_digit());
+            if boundary {
+                let word: String = chars[start..i].iter().collect::<String>().to_lowercase();
+                if !word.is_empty() && !STOP_WORDS.contains(&word.as_str()) {
+                    words.push(word);
+                }
+                start = i;
+            }
+        }
+    }
+    words
+}
+
+/// Rough stem of a word, so "limiter", "limiting" and "limits" meet
+fn stem(word: &str) -> &str {
+    for suffix in ["ing", "ers", "er", "ed", "es", "s", "e"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.chars().count() >= 3 {
+                return stem;
+            }
+        }
+    }
+    word
+}
+
+/// 64-bit FNV-1a, stable across platforms and releases unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn to_bytes(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Checks a backend returned one vector per text
+fn checked(vectors: Vec<Vec<f32>>, expected: usize) -> std::result::Result<Vec<Vec<f32>>, String> {
+    if vectors.len() != expected {
+        return Err(format!("Expected {} vectors, got {}", expected, vectors.len()));
+    }
+    Ok(vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::DatabaseManager;
+
+    #[test]
+    fn test_embed_and_search_index() {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("engine".to_string(), "/src/engine".to_string()))
+            .unwrap();
+        let element = |name: &str, symbol_type, scope: &str, documentation: &str| {
+            CodeElement::new(index.id, name.to_string(), symbol_type, "engine.cpp".to_string(), 1, 1, "a".repeat(64))
+                .with_scope(scope.to_string())
+                .with_documentation(documentation.to_string())
+        };
+        for element in [
+            element("FrameRateLimiter", SymbolType::Class, "render", "Sleeps so frames are not presented faster than the target rate"),
+            element("loadTexture", SymbolType::Function, "assets", "Decodes an image file into a GPU texture"),
+            element("mixVoices", SymbolType::Function, "audio", "Sums the active voices into the output buffer"),
+        ] {
+            repository.create_code_element(element).unwrap();
+        }
+
+        let backend = HashingEmbedder::default();
+        let mut store = EmbeddingStore::in_memory().unwrap();
+        assert_eq!(store.refresh(&repository, &index, &backend).unwrap(), EmbeddingRefresh { embedded: 3, reused: 0, removed: 0 });
+        let status = store.status(&index.id).unwrap().unwrap();
+        assert_eq!((status.backend.as_str(), status.dimensions, status.symbols), ("hashing-512", 512, 3));
+
+        let names = |hits: Vec<SemanticHit>| -> Vec<String> {
+            hits.iter().map(|hit| repository.get_code_element(hit.element_id).unwrap().unwrap().symbol_name).collect()
+        };
+        let hits = store.search(&index.id, &backend, "where is frame rate limiting handled?", &[], 3).unwrap();
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(names(hits)[0], "FrameRateLimiter");
+        assert_eq!(names(store.search(&index.id, &backend, "texture loading", &[SymbolType::Function], 1).unwrap()), vec!["loadTexture"]);
+        assert!(store.search(&index.id, &backend, "frame rate", &[SymbolType::Enum], 5).unwrap().is_empty());
+
+        // Unchanged symbols keep their vectors; another backend replaces them all
+        assert_eq!(store.refresh(&repository, &index, &backend).unwrap(), EmbeddingRefresh { embedded: 0, reused: 3, removed: 0 });
+        let other = HashingEmbedder::new(64);
+        assert!(matches!(store.search(&index.id, &other, "frame", &[], 1), Err(StorageError::Validation(_))));
+        assert_eq!(store.refresh(&repository, &index, &other).unwrap(), EmbeddingRefresh { embedded: 3, reused: 0, removed: 3 });
+        assert_eq!(store.remove(&index.id).unwrap(), 3);
+        assert!(matches!(store.search(&index.id, &other, "frame", &[], 1), Err(StorageError::NotFound(_))));
+
+        let command = CommandEmbedder::new(r#"cat > /dev/null; echo '{"embeddings": [[1, 0], [0, 1]]}'"#, "fixed");
+        assert_eq!(command.embed(&["a".to_string(), "b".to_string()]).unwrap(), vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert!(command.embed(&["a".to_string()]).is_err());
+        let onnx = OnnxEmbedder::new(Path::new("/models/all-MiniLM-L6-v2.onnx"), Path::new("/models/tokenizer.json"), "python3");
+        assert_eq!(onnx.name(), "onnx:all-MiniLM-L6-v2");
+
+        assert_eq!(words("FrameRateLimiter::HTTPServer2"), vec!["frame", "rate", "limiter", "http", "server", "2"]);
+        assert_eq!((stem("limiting"), stem("limiter"), stem("limits")), ("limit", "limit", "limit"));
+    }
+}
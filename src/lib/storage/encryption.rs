@@ -0,0 +1,171 @@
+use rusqlite::{Connection, ErrorCode};
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::lib::storage::error::{Result, StorageError};
+
+/// Environment variable holding the database key
+pub const KEY_ENV_VAR: &str = "CPP_INDEX_DB_KEY";
+
+/// Shortest accepted key, in characters
+pub const MIN_KEY_LENGTH: usize = 8;
+
+/// Passphrase for an encrypted index database
+///
+/// Databases are encrypted page by page with SQLCipher, which is only linked
+/// when the crate is built with the `encryption` feature. The key is never
+/// printed by `Debug`, so configurations can be logged safely.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey(String);
+
+impl EncryptionKey {
+    /// Wraps a passphrase, rejecting empty or short ones
+    pub fn new(passphrase: impl Into<String>) -> std::result::Result<Self, String> {
+        let passphrase = passphrase.into();
+        if passphrase.chars().count() < MIN_KEY_LENGTH {
+            return Err(format!("Encryption key must be at least {} characters", MIN_KEY_LENGTH));
+        }
+        Ok(Self(passphrase))
+    }
+
+    /// Reads the key from CPP_INDEX_DB_KEY, if set
+    pub fn from_env() -> std::result::Result<Option<Self>, String> {
+        match std::env::var(KEY_ENV_VAR) {
+            Ok(passphrase) if !passphrase.is_empty() => Self::new(passphrase).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Runs a shell command and uses its trimmed output as the key
+    ///
+    /// Meant for keyring helpers, e.g. `secret-tool lookup service cpp-index`
+    /// or `security find-generic-password -s cpp-index -w`.
+    pub fn from_command(command: &str) -> std::result::Result<Self, String> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .map_err(|e| format!("Failed to run key command: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("Key command exited with {}", output.status));
+        }
+        let passphrase = String::from_utf8(output.stdout).map_err(|_| "Key command output is not UTF-8".to_string())?;
+        Self::new(passphrase.trim_end_matches(['\r', '\n']))
+    }
+
+    fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(<redacted>)")
+    }
+}
+
+/// SQLCipher version linked into this build, or None for plain SQLite
+pub fn cipher_version(connection: &Connection) -> Option<String> {
+    connection.query_row("PRAGMA cipher_version", [], |row| row.get(0)).ok()
+}
+
+/// Keys a freshly opened connection and checks the key fits the database
+///
+/// Must run before any other statement on the connection.
+pub fn apply_key(connection: &Connection, key: &EncryptionKey) -> Result<()> {
+    // Plain SQLite ignores PRAGMA key, which would leave the database unencrypted
+    if cipher_version(connection).is_none() {
+        return Err(StorageError::Validation(
+            "Database encryption requires a build with the `encryption` feature (SQLCipher)".to_string(),
+        ));
+    }
+
+    connection.pragma_update(None, "key", key.expose())?;
+    match connection.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(())) {
+        Ok(()) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(error, _)) if error.code == ErrorCode::NotADatabase => Err(StorageError::Validation(
+            "Wrong encryption key, or the database is not encrypted".to_string(),
+        )),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes an encrypted copy of a plaintext database
+///
+/// `plaintext` must be opened without a key; the copy at `destination` is
+/// keyed with `key` and can replace the original.
+pub fn export_encrypted(plaintext: &Connection, destination: &Path, key: &EncryptionKey) -> Result<()> {
+    if cipher_version(plaintext).is_none() {
+        return Err(StorageError::Validation(
+            "Database encryption requires a build with the `encryption` feature (SQLCipher)".to_string(),
+        ));
+    }
+    if destination.exists() {
+        return Err(StorageError::Conflict(format!("{} already exists", destination.display())));
+    }
+
+    plaintext.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        [destination.to_string_lossy().as_ref(), key.expose()],
+    )?;
+    let exported = plaintext.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()));
+    plaintext.execute("DETACH DATABASE encrypted", [])?;
+    exported?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_validation_and_redaction() {
+        assert!(EncryptionKey::new("short").is_err());
+        let key = EncryptionKey::new("correct horse battery").unwrap();
+        assert_eq!(format!("{:?}", key), "EncryptionKey(<redacted>)");
+
+        assert_eq!(EncryptionKey::from_command("printf 'from keyring\\n'").unwrap(), EncryptionKey::new("from keyring").unwrap());
+        assert!(EncryptionKey::from_command("exit 3").is_err());
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn test_plain_sqlite_refuses_key() {
+        let connection = Connection::open_in_memory().unwrap();
+        let key = EncryptionKey::new("correct horse battery").unwrap();
+        let error = apply_key(&connection, &key).unwrap_err();
+        assert!(error.to_string().contains("encryption"));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain_path = dir.path().join("plain.db");
+        let encrypted_path = dir.path().join("encrypted.db");
+        let key = EncryptionKey::new("correct horse battery").unwrap();
+
+        let plain = Connection::open(&plain_path).unwrap();
+        plain.execute_batch("CREATE TABLE t (x TEXT); INSERT INTO t VALUES ('secret symbol');").unwrap();
+        export_encrypted(&plain, &encrypted_path, &key).unwrap();
+
+        let bytes = std::fs::read(&encrypted_path).unwrap();
+        assert!(!bytes.windows(13).any(|window| window == b"secret symbol"));
+
+        let connection = Connection::open(&encrypted_path).unwrap();
+        apply_key(&connection, &key).unwrap();
+        let value: String = connection.query_row("SELECT x FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(value, "secret symbol");
+
+        let wrong = Connection::open(&encrypted_path).unwrap();
+        assert!(apply_key(&wrong, &EncryptionKey::new("wrong passphrase").unwrap()).is_err());
+
+        // Managed databases are keyed before they're configured and migrated
+        use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+        let config = DatabaseConfig::new(dir.path().join("index.db")).with_encryption_key(key.clone());
+        DatabaseManager::new(config.clone()).unwrap().connect().unwrap();
+        assert!(DatabaseManager::new(config).unwrap().connect_read_only().is_ok());
+        assert!(DatabaseManager::new(DatabaseConfig::new(dir.path().join("index.db"))).unwrap().connect().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use rusqlite::ErrorCode;
+use thiserror::Error;
+
+use crate::lib::storage::disk_space::DiskSpaceError;
+
+/// Result type used throughout the storage layer
+pub type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+/// Errors raised by the storage layer
+///
+/// Distinguishes bad input (`Validation`, `NotFound`, `Conflict`) from damaged
+/// data (`Corruption`), writes refused by read-only storage (`ReadOnly`),
+/// failures of external backends (`Backend`), a full disk (`DiskSpace`) and
+/// other database failures (`Sqlite`), so callers can report each appropriately.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    /// Input failed model validation
+    #[error("Validation failed: {0}")]
+    Validation(String),
+    /// The requested row does not exist
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// The write conflicts with existing data (unique or foreign key constraint)
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// Stored data is unreadable or the database file is damaged
+    #[error("Database corruption: {0}")]
+    Corruption(String),
+    /// The write was refused because the storage is opened read-only
+    #[error("Read-only: {0}")]
+    ReadOnly(String),
+    /// The database was written by a newer release whose schema this build doesn't know
+    #[error(
+        "Database schema version {found} is newer than this build supports ({supported}); \
+         run `cpp-index-mcp self-update`, or `index migrate --to {supported}` it with the release that created it"
+    )]
+    NewerSchema { found: i32, supported: i32 },
+    /// An external program or service the storage layer relies on failed, e.g. an embeddings API
+    #[error("Backend failed: {0}")]
+    Backend(String),
+    /// Too little space is left to go on; what was stored so far is kept
+    #[error("{0}")]
+    DiskSpace(#[from] DiskSpaceError),
+    /// Any other SQLite failure
+    #[error("Database error: {0}")]
+    Sqlite(rusqlite::Error),
+}
+
+impl StorageError {
+    /// Short machine-readable name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StorageError::Validation(_) => "validation",
+            StorageError::NotFound(_) => "not_found",
+            StorageError::Conflict(_) => "conflict",
+            StorageError::Corruption(_) => "corruption",
+            StorageError::ReadOnly(_) => "read_only",
+            StorageError::NewerSchema { .. } => "newer_schema",
+            StorageError::Backend(_) => "backend",
+            StorageError::DiskSpace(_) => "disk_space",
+            StorageError::Sqlite(_) => "sqlite",
+        }
+    }
+
+    /// Returns true if the error was caused by the caller's input rather than the database
+    pub fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            StorageError::Validation(_) | StorageError::NotFound(_) | StorageError::Conflict(_)
+        )
+    }
+
+    /// Builds a NotFound error for an entity, e.g. `not_found("Code index", id)`
+    pub fn not_found(entity: &str, key: impl std::fmt::Display) -> Self {
+        StorageError::NotFound(format!("{} {}", entity, key))
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> Self {
+        match &error {
+            rusqlite::Error::QueryReturnedNoRows => StorageError::NotFound("No matching row".to_string()),
+            rusqlite::Error::SqliteFailure(e, message) => {
+                let message = message.clone().unwrap_or_else(|| e.to_string());
+                match e.code {
+                    ErrorCode::ConstraintViolation => StorageError::Conflict(message),
+                    ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase => StorageError::Corruption(message),
+                    ErrorCode::ReadOnly => StorageError::ReadOnly(message),
+                    _ => StorageError::Sqlite(error),
+                }
+            }
+            // Rows that cannot be decoded into models mean the stored data is bad
+            rusqlite::Error::InvalidColumnType(..) | rusqlite::Error::FromSqlConversionFailure(..) => {
+                StorageError::Corruption(error.to_string())
+            }
+            _ => StorageError::Sqlite(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite_failure(code: i32) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None)
+    }
+
+    #[test]
+    fn test_classify_rusqlite_errors() {
+        assert!(matches!(StorageError::from(rusqlite::Error::QueryReturnedNoRows), StorageError::NotFound(_)));
+        assert!(matches!(
+            StorageError::from(sqlite_failure(rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE)),
+            StorageError::Conflict(_)
+        ));
+        assert!(matches!(StorageError::from(sqlite_failure(rusqlite::ffi::SQLITE_CORRUPT)), StorageError::Corruption(_)));
+        assert!(matches!(StorageError::from(sqlite_failure(rusqlite::ffi::SQLITE_BUSY)), StorageError::Sqlite(_)));
+        assert!(matches!(StorageError::from(sqlite_failure(rusqlite::ffi::SQLITE_READONLY)), StorageError::ReadOnly(_)));
+        assert!(matches!(
+            StorageError::from(rusqlite::Error::InvalidColumnType(0, "id".to_string(), rusqlite::types::Type::Text)),
+            StorageError::Corruption(_)
+        ));
+    }
+
+    #[test]
+    fn test_kind_and_client_errors() {
+        let error = StorageError::not_found("Code index", "engine");
+        assert_eq!(error.to_string(), "Not found: Code index engine");
+        assert_eq!(error.kind(), "not_found");
+        assert!(error.is_client_error());
+        assert!(!StorageError::Corruption("bad page".to_string()).is_client_error());
+    }
+}
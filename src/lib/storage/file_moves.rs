@@ -0,0 +1,139 @@
+// Moved-file detection
+//
+// A file renamed or moved between refreshes shows up as an indexed file
+// gone from disk and an unindexed file that appeared elsewhere. Pairing
+// them by their symbol outlines lets the index follow the file, updating
+// paths in place so symbol ids, tags and notes survive the refactor instead
+// of being dropped with the old path and recreated under the new one.
+
+use std::collections::BTreeMap;
+
+use crate::lib::storage::models::code_element::CodeElement;
+
+/// Share of outline entries two files must have in common to count as one moved file
+pub const DEFAULT_MOVE_SIMILARITY: f64 = 0.6;
+
+/// The symbols a file declares, by kind and qualified name
+///
+/// Line numbers and bodies are left out, so edits made along with a move
+/// only lower the similarity by the symbols they add or remove.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileOutline {
+    entries: BTreeMap<(&'static str, String), usize>,
+}
+
+/// A file followed from its old path to its new one
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+    /// Outline similarity, 1.0 for identical content
+    pub similarity: f64,
+}
+
+impl FileOutline {
+    pub fn new(elements: &[CodeElement]) -> Self {
+        let mut entries = BTreeMap::new();
+        for element in elements {
+            *entries.entry((element.symbol_type.as_str(), element.fully_qualified_name())).or_insert(0) += 1;
+        }
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Shared entries over all entries of either outline (Jaccard over the multisets)
+    ///
+    /// Two empty outlines have nothing to compare and score 0.
+    pub fn similarity(&self, other: &FileOutline) -> f64 {
+        let shared: usize = self
+            .entries
+            .iter()
+            .map(|(key, count)| other.entries.get(key).map_or(0, |other_count| (*count).min(*other_count)))
+            .sum();
+        match self.len() + other.len() - shared {
+            0 => 0.0,
+            total => shared as f64 / total as f64,
+        }
+    }
+}
+
+/// Pairs removed files with added ones whose outlines are at least `threshold` similar
+///
+/// The most similar pairs are taken first and each file is used once, so
+/// of two near-copies only the closer one is taken for the move. Ties go
+/// to the pair whose file names match, then to path order.
+pub fn match_moves(removed: &[(String, FileOutline)], added: &[(String, FileOutline)], threshold: f64) -> Vec<FileMove> {
+    let mut candidates: Vec<(f64, bool, usize, usize)> = Vec::new();
+    for (i, (from, from_outline)) in removed.iter().enumerate() {
+        for (j, (to, to_outline)) in added.iter().enumerate() {
+            let similarity = from_outline.similarity(to_outline);
+            if similarity >= threshold {
+                candidates.push((similarity, file_name(from) == file_name(to), i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.cmp(&a.1)).then((a.2, a.3).cmp(&(b.2, b.3))));
+
+    let mut used_removed = vec![false; removed.len()];
+    let mut used_added = vec![false; added.len()];
+    let mut moves = Vec::new();
+    for (similarity, _, i, j) in candidates {
+        if used_removed[i] || used_added[j] {
+            continue;
+        }
+        used_removed[i] = true;
+        used_added[j] = true;
+        moves.push(FileMove { from: removed[i].0.clone(), to: added[j].0.clone(), similarity });
+    }
+    moves
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    fn outline(file: &str, symbols: &[(&str, SymbolType)]) -> (String, FileOutline) {
+        let index_id = Uuid::new_v4();
+        let elements: Vec<CodeElement> = symbols
+            .iter()
+            .map(|(name, symbol_type)| {
+                CodeElement::new(index_id, name.to_string(), *symbol_type, file.to_string(), 1, 1, "a".repeat(64)).with_scope("audio".to_string())
+            })
+            .collect();
+        (file.to_string(), FileOutline::new(&elements))
+    }
+
+    #[test]
+    fn test_match_moves_by_outline() {
+        use SymbolType::*;
+        let mixer = [("Mixer", Class), ("mix", Function), ("gain", Function), ("pan", Function)];
+        let removed = vec![outline("src/mixer.cpp", &mixer), outline("src/old_util.cpp", &[("clamp", Function)])];
+        // Moved with one function added; an unrelated file appeared too
+        let added = vec![
+            outline("src/audio/mixer.cpp", &[("Mixer", Class), ("mix", Function), ("gain", Function), ("pan", Function), ("mute", Function)]),
+            outline("src/audio/reverb.cpp", &[("Reverb", Class), ("mix", Function)]),
+        ];
+        assert_eq!(removed[0].1.similarity(&added[0].1), 0.8);
+
+        let moves = match_moves(&removed, &added, DEFAULT_MOVE_SIMILARITY);
+        assert_eq!(moves, [FileMove { from: "src/mixer.cpp".to_string(), to: "src/audio/mixer.cpp".to_string(), similarity: 0.8 }]);
+
+        // Of two identical copies, the one keeping the file name wins
+        let copies = vec![outline("src/audio/mix.cpp", &mixer), outline("src/audio/mixer.cpp", &mixer)];
+        assert_eq!(match_moves(&removed[..1], &copies, DEFAULT_MOVE_SIMILARITY)[0].to, "src/audio/mixer.cpp");
+        assert_eq!(FileOutline::default().similarity(&FileOutline::default()), 0.0);
+    }
+}
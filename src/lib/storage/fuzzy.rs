@@ -0,0 +1,186 @@
+// Fuzzy symbol matching
+//
+// A misspelled or half-remembered name should still find its symbol.
+// Candidates sharing a trigram with the query come from the trigram index over
+// symbol names; they are scored here by how close their names are, and among
+// equally close names types and functions come before variables and macros,
+// and definitions before their declarations.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+
+/// Lowest score a name must reach to be returned
+pub const MIN_FUZZY_SCORE: f64 = 0.5;
+
+/// Most candidates read from the trigram index for one query
+pub const MAX_FUZZY_CANDIDATES: usize = 2000;
+
+/// A symbol and how closely its name matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub element: CodeElement,
+    /// 1.0 for the query itself, ignoring case, down to `MIN_FUZZY_SCORE`
+    pub score: f64,
+}
+
+/// Lowercased trigrams of `text`; shorter text is its own single gram
+pub fn trigrams(text: &str) -> BTreeSet<String> {
+    let chars: Vec<char> = text.to_lowercase().chars().collect();
+    if chars.len() < 3 {
+        return std::iter::once(chars.iter().collect()).filter(|gram: &String| !gram.is_empty()).collect();
+    }
+    chars.windows(3).map(|gram| gram.iter().collect()).collect()
+}
+
+/// FTS5 expression matching names that share any trigram with `pattern`
+///
+/// None when the pattern is shorter than three characters, which the
+/// trigram tokenizer can't look up.
+pub fn trigram_query(pattern: &str) -> Option<String> {
+    let grams = trigrams(pattern.trim());
+    if grams.is_empty() || grams.iter().any(|gram| gram.chars().count() < 3) {
+        return None;
+    }
+    let terms: Vec<String> = grams.iter().map(|gram| format!("\"{}\"", gram.replace('"', "\"\""))).collect();
+    Some(terms.join(" OR "))
+}
+
+/// How closely `name` matches `pattern`, from 0.0 to 1.0, ignoring case
+///
+/// The best of three measures: edit distance between the whole names, the
+/// trigrams they share, and the pattern found inside the name with few
+/// edits, which counts for less the more of the name is left over. A name
+/// equal to the pattern scores 1.0.
+pub fn name_score(pattern: &str, name: &str) -> f64 {
+    let pattern: Vec<char> = pattern.trim().to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    if pattern.is_empty() || name.is_empty() {
+        return 0.0;
+    }
+    if pattern == name {
+        return 1.0;
+    }
+
+    let longest = pattern.len().max(name.len()) as f64;
+    let whole = 1.0 - edit_distance(&pattern, &name, false) as f64 / longest;
+
+    let (pattern_grams, name_grams) = (trigrams(&String::from_iter(&pattern)), trigrams(&String::from_iter(&name)));
+    let shared = pattern_grams.intersection(&name_grams).count() as f64;
+    let grams = 2.0 * shared / (pattern_grams.len() + name_grams.len()) as f64;
+
+    let inside = 1.0 - edit_distance(&pattern, &name, true) as f64 / pattern.len() as f64;
+    let coverage = (pattern.len() as f64 / name.len() as f64).min(1.0);
+    let partial = inside.max(0.0) * (0.7 + 0.25 * coverage);
+
+    whole.max(grams).max(partial).clamp(0.0, 1.0)
+}
+
+/// Order in which kinds of symbols are listed among equally close names
+pub fn kind_priority(symbol_type: SymbolType) -> u8 {
+    match symbol_type {
+        SymbolType::Class | SymbolType::Struct | SymbolType::Union | SymbolType::Enum | SymbolType::Typedef | SymbolType::Template => 0,
+        SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor | SymbolType::Operator | SymbolType::Lambda => 1,
+        SymbolType::Namespace => 2,
+        SymbolType::Macro => 3,
+        SymbolType::Variable | SymbolType::Field | SymbolType::EnumConstant => 4,
+        SymbolType::Unknown => 5,
+    }
+}
+
+/// Scores `elements` against `pattern` and ranks those scoring at least `MIN_FUZZY_SCORE`
+///
+/// Scores are compared to two decimals, so a near tie is decided by kind
+/// priority, then definitions before declarations, then name, file and line.
+pub fn rank(pattern: &str, elements: Vec<CodeElement>) -> Vec<FuzzyMatch> {
+    let mut matches: Vec<FuzzyMatch> = elements
+        .into_iter()
+        .map(|element| FuzzyMatch { score: name_score(pattern, &element.symbol_name), element })
+        .filter(|candidate| candidate.score >= MIN_FUZZY_SCORE)
+        .collect();
+    matches.sort_by(compare);
+    matches
+}
+
+fn compare(a: &FuzzyMatch, b: &FuzzyMatch) -> Ordering {
+    let bucket = |score: f64| (score * 100.0).round() as i64;
+    bucket(b.score)
+        .cmp(&bucket(a.score))
+        .then(kind_priority(a.element.symbol_type).cmp(&kind_priority(b.element.symbol_type)))
+        .then(a.element.is_declaration.cmp(&b.element.is_declaration))
+        .then_with(|| a.element.symbol_name.cmp(&b.element.symbol_name))
+        .then_with(|| a.element.file_path.cmp(&b.element.file_path))
+        .then(a.element.line_number.cmp(&b.element.line_number))
+}
+
+/// Edits (insertions, deletions, substitutions and swaps of neighbours) turning `pattern` into `name`
+///
+/// With `anywhere` the pattern may match any stretch of the name, so the
+/// rest of the name costs nothing.
+fn edit_distance(pattern: &[char], name: &[char], anywhere: bool) -> usize {
+    // rows[i][j]: edits turning pattern[..i] into name[..j]
+    let mut rows = vec![vec![0usize; name.len() + 1]; pattern.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = if anywhere { 0 } else { j };
+    }
+    for i in 1..=pattern.len() {
+        for j in 1..=name.len() {
+            let substitution = rows[i - 1][j - 1] + usize::from(pattern[i - 1] != name[j - 1]);
+            let mut best = substitution.min(rows[i - 1][j] + 1).min(rows[i][j - 1] + 1);
+            if i > 1 && j > 1 && pattern[i - 1] == name[j - 2] && pattern[i - 2] == name[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    let last = &rows[pattern.len()];
+    if anywhere {
+        last.iter().copied().min().unwrap_or(pattern.len())
+    } else {
+        last[name.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_fuzzy_scores_and_ranking() {
+        assert_eq!(name_score("audiomixer", "AudioMixer"), 1.0);
+        assert_eq!(edit_distance(&['m', 'i', 'x', 'r'], &['m', 'i', 'x', 'e', 'r'], false), 1);
+        assert!(name_score("AudoiMixer", "AudioMixer") >= 0.9);
+        assert!(name_score("widgte", "CreateWidget") > MIN_FUZZY_SCORE);
+        assert!(name_score("mixer", "Reverb") < MIN_FUZZY_SCORE);
+        assert_eq!(trigram_query("Mix\"er").unwrap(), "\"\"\"er\" OR \"ix\"\"\" OR \"mix\" OR \"x\"\"e\"");
+        assert_eq!(trigram_query("ab"), None);
+
+        let index_id = Uuid::new_v4();
+        let element = |name: &str, symbol_type: SymbolType, declaration: bool| {
+            let mut element = CodeElement::new(index_id, name.to_string(), symbol_type, "src/mixer.h".to_string(), 1, 1, "a".repeat(64));
+            element.is_declaration = declaration;
+            element
+        };
+        let ranked = rank(
+            "Mixr",
+            vec![
+                element("mixer", SymbolType::Variable, false),
+                element("Mixer", SymbolType::Function, true),
+                element("Mixer", SymbolType::Function, false),
+                element("Mixer", SymbolType::Class, false),
+                element("Reverb", SymbolType::Class, false),
+            ],
+        );
+        let order: Vec<(SymbolType, bool)> = ranked.iter().map(|m| (m.element.symbol_type, m.element.is_declaration)).collect();
+        assert_eq!(
+            order,
+            [(SymbolType::Class, false), (SymbolType::Function, false), (SymbolType::Function, true), (SymbolType::Variable, false)]
+        );
+        assert!((ranked[0].score - 0.8).abs() < 1e-9);
+    }
+}
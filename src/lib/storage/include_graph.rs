@@ -0,0 +1,201 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::file_include::FileInclude;
+use crate::lib::storage::repository::Repository;
+
+/// Default number of include levels followed from the file
+pub const DEFAULT_MAX_DEPTH: u32 = 16;
+
+/// Default cap on the number of files in one graph
+pub const DEFAULT_MAX_NODES: usize = 2000;
+
+/// Which way includes are followed from the file
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IncludeDirection {
+    /// Files the file includes, directly or through other headers
+    Includes,
+    /// Files including the file, directly or through other headers
+    IncludedBy,
+}
+
+/// Traversal settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncludeGraphOptions {
+    pub direction: IncludeDirection,
+    /// Include levels followed from the file
+    pub max_depth: u32,
+    /// Traversal stops once this many files are in the graph
+    pub max_nodes: usize,
+}
+
+/// A file in the graph and the number of includes between it and the starting file
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct IncludeGraphNode {
+    pub file_path: String,
+    pub depth: u32,
+}
+
+/// Result of an include graph traversal in one direction
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct IncludeGraph {
+    pub root: String,
+    /// Files in breadth-first order, the starting file first
+    pub nodes: Vec<IncludeGraphNode>,
+    /// Includes between files of the graph
+    pub edges: Vec<FileInclude>,
+    /// Includes of files in the graph that refer to no indexed file, such as system headers
+    pub unresolved: BTreeSet<String>,
+    /// True if `max_nodes` stopped the traversal early
+    pub truncated: bool,
+}
+
+/// Walks resolved includes breadth-first from a file
+///
+/// Walking `IncludedBy` answers "what has to be rebuilt if this header
+/// changes": every file reached includes the header, possibly through
+/// others. A file reached along two paths appears once, at its shortest
+/// depth, with an edge for every path; include cycles end where a file is
+/// reached again.
+pub struct IncludeGraphWalker<'a> {
+    repository: &'a Repository,
+    options: IncludeGraphOptions,
+}
+
+impl Default for IncludeGraphOptions {
+    fn default() -> Self {
+        Self {
+            direction: IncludeDirection::Includes,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+impl IncludeGraphOptions {
+    /// Follows includes in the given direction
+    pub fn with_direction(mut self, direction: IncludeDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the number of include levels followed
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Caps the number of files in the graph
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes.max(1);
+        self
+    }
+}
+
+impl IncludeGraph {
+    /// Files at depth 1: included by, or including, the starting file itself
+    pub fn direct(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().filter(|node| node.depth == 1).map(|node| node.file_path.as_str())
+    }
+
+    /// Files beyond depth 1, reached only through other files
+    pub fn transitive(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().filter(|node| node.depth > 1).map(|node| node.file_path.as_str())
+    }
+}
+
+impl<'a> IncludeGraphWalker<'a> {
+    /// Creates a walker over the repository's include edges
+    pub fn new(repository: &'a Repository, options: IncludeGraphOptions) -> Self {
+        Self { repository, options }
+    }
+
+    /// Builds the graph reachable from `file_path` in index `index_id`
+    pub fn walk(&self, index_id: &Uuid, file_path: &str) -> Result<IncludeGraph> {
+        let mut depths = HashMap::from([(file_path.to_string(), 0)]);
+        let mut nodes = vec![IncludeGraphNode { file_path: file_path.to_string(), depth: 0 }];
+        let mut edges = Vec::new();
+        let mut unresolved = BTreeSet::new();
+        let mut truncated = false;
+        let mut frontier = vec![file_path.to_string()];
+        let mut depth = 0;
+
+        while !frontier.is_empty() && depth < self.options.max_depth && !truncated {
+            let includes = match self.options.direction {
+                IncludeDirection::Includes => self.repository.get_file_includes(index_id, &frontier)?,
+                IncludeDirection::IncludedBy => self.repository.get_including_files(index_id, &frontier)?,
+            };
+
+            let mut next = Vec::new();
+            for include in includes {
+                let neighbor = match (self.options.direction, &include.resolved_path) {
+                    (IncludeDirection::Includes, Some(resolved_path)) => resolved_path.clone(),
+                    (IncludeDirection::Includes, None) => {
+                        unresolved.insert(include.include_path);
+                        continue;
+                    }
+                    (IncludeDirection::IncludedBy, _) => include.file_path.clone(),
+                };
+                if !depths.contains_key(&neighbor) {
+                    if nodes.len() >= self.options.max_nodes {
+                        truncated = true;
+                        break;
+                    }
+                    depths.insert(neighbor.clone(), depth + 1);
+                    nodes.push(IncludeGraphNode { file_path: neighbor.clone(), depth: depth + 1 });
+                    next.push(neighbor);
+                }
+                edges.push(include);
+            }
+
+            frontier = next;
+            depth += 1;
+        }
+
+        Ok(IncludeGraph { root: file_path.to_string(), nodes, edges, unresolved, truncated })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_index::CodeIndex;
+    use crate::lib::storage::models::file_metadata::FileMetadata;
+    use chrono::Utc;
+
+    #[test]
+    fn test_include_graph() {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repository = Repository::new(manager.connect().unwrap());
+        let index = repository.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        for (file, includes) in [
+            ("src/mixer.cpp", vec!["mixer.h", "vector"]),
+            ("src/mixer.h", vec!["audio/format.h"]),
+            ("src/player.cpp", vec!["mixer.h"]),
+            ("include/audio/format.h", vec!["cstdint", "audio/format.h"]),
+        ] {
+            repository.create_file_metadata(FileMetadata::new(index.id, file.to_string(), "a".repeat(64), Utc::now(), 1)).unwrap();
+            let includes: Vec<String> = includes.iter().map(|include| include.to_string()).collect();
+            repository.replace_file_includes(&index.id, file, &includes).unwrap();
+        }
+        assert_eq!(repository.resolve_file_includes(&index.id).unwrap(), 4);
+
+        let includes = IncludeGraphWalker::new(&repository, IncludeGraphOptions::default()).walk(&index.id, "src/mixer.cpp").unwrap();
+        assert_eq!(includes.direct().collect::<Vec<_>>(), ["src/mixer.h"]);
+        assert_eq!(includes.transitive().collect::<Vec<_>>(), ["include/audio/format.h"]);
+        assert_eq!(includes.unresolved, ["cstdint", "vector"].iter().map(|include| include.to_string()).collect());
+
+        let options = IncludeGraphOptions::default().with_direction(IncludeDirection::IncludedBy);
+        let included_by = IncludeGraphWalker::new(&repository, options).walk(&index.id, "include/audio/format.h").unwrap();
+        // The header's include of itself is an edge back to the root, not a new file
+        assert_eq!(included_by.direct().collect::<Vec<_>>(), ["src/mixer.h"]);
+        assert_eq!(included_by.transitive().collect::<Vec<_>>(), ["src/mixer.cpp", "src/player.cpp"]);
+
+        let shallow = IncludeGraphWalker::new(&repository, options.with_max_depth(1)).walk(&index.id, "include/audio/format.h").unwrap();
+        assert_eq!(shallow.nodes.len(), 2);
+    }
+}
@@ -4,7 +4,31 @@
 // including code indices, symbols, relationships, and query sessions.
 
 pub mod models;
-// TODO: Implement these modules in later tasks
-// pub mod schema;
-// pub mod connection; 
-// pub mod repository;
\ No newline at end of file
+pub mod schema;
+pub mod archive;
+pub mod batch_writer;
+pub mod call_graph;
+pub mod code_intel;
+pub mod connection;
+pub mod coupling;
+pub mod disk_space;
+pub mod dsm;
+pub mod element_listing;
+pub mod embeddings;
+pub mod encryption;
+pub mod error;
+pub mod file_moves;
+pub mod fuzzy;
+pub mod include_graph;
+pub mod ordering;
+pub mod query;
+pub mod query_dsl;
+pub mod repository;
+pub mod recovery;
+pub mod retention;
+pub mod search_explain;
+pub mod snapshot;
+pub mod tags;
+pub mod timings;
+pub mod type_hierarchy;
+pub mod watch;
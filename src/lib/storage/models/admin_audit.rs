@@ -0,0 +1,165 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Maximum length of an actor name
+pub const MAX_ACTOR_LENGTH: usize = 128;
+
+/// Who performs administrative operations on a repository
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditActor {
+    /// Client or user name, e.g. "cli:dana" or "mcp:claude-desktop"
+    pub name: String,
+    /// MCP session or other connection identifier
+    pub session_id: Option<String>,
+}
+
+/// One administrative operation in the append-only audit trail
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    /// Primary key (auto-generated)
+    pub id: Option<i64>,
+    pub operation: AuditOperation,
+    /// Index the operation applied to; None for database-wide operations
+    pub index_id: Option<Uuid>,
+    /// Index name at the time of the operation, kept after the index is deleted
+    pub index_name: Option<String>,
+    pub actor: String,
+    pub session_id: Option<String>,
+    /// Operation parameters as a JSON object
+    pub parameters: Value,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Kind of administrative operation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    CreateIndex,
+    UpdateIndex,
+    DeleteIndex,
+    ArchiveIndex,
+    SetTag,
+    RemoveTag,
+    Backup,
+    Restore,
+    Encrypt,
+    RepairIndex,
+}
+
+impl AuditActor {
+    /// Creates an actor without a session
+    pub fn new(name: impl Into<String>) -> Self {
+        let mut name: String = name.into();
+        if name.len() > MAX_ACTOR_LENGTH {
+            let mut end = MAX_ACTOR_LENGTH;
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            name.truncate(end);
+        }
+        Self { name, session_id: None }
+    }
+
+    /// The user running the command line, from $USER or $USERNAME
+    pub fn local_user() -> Self {
+        let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+        if user.is_empty() {
+            Self::new("cli")
+        } else {
+            Self::new(format!("cli:{}", user))
+        }
+    }
+
+    /// Sets the session the actor is connected through
+    pub fn with_session(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+}
+
+impl AuditEntry {
+    /// Creates an entry recorded now
+    pub fn new(operation: AuditOperation, actor: &AuditActor, parameters: Value) -> Self {
+        Self {
+            id: None,
+            operation,
+            index_id: None,
+            index_name: None,
+            actor: actor.name.clone(),
+            session_id: actor.session_id.clone(),
+            parameters,
+            recorded_at: Utc::now(),
+        }
+    }
+
+    /// Sets the index the operation applied to
+    pub fn with_index(mut self, index_id: Uuid, index_name: impl Into<String>) -> Self {
+        self.index_id = Some(index_id);
+        self.index_name = Some(index_name.into());
+        self
+    }
+}
+
+impl AuditOperation {
+    /// Returns all operations
+    pub fn all() -> &'static [AuditOperation] {
+        &[
+            AuditOperation::CreateIndex,
+            AuditOperation::UpdateIndex,
+            AuditOperation::DeleteIndex,
+            AuditOperation::ArchiveIndex,
+            AuditOperation::SetTag,
+            AuditOperation::RemoveTag,
+            AuditOperation::Backup,
+            AuditOperation::Restore,
+            AuditOperation::Encrypt,
+            AuditOperation::RepairIndex,
+        ]
+    }
+
+    /// Returns string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOperation::CreateIndex => "create_index",
+            AuditOperation::UpdateIndex => "update_index",
+            AuditOperation::DeleteIndex => "delete_index",
+            AuditOperation::ArchiveIndex => "archive_index",
+            AuditOperation::SetTag => "set_tag",
+            AuditOperation::RemoveTag => "remove_tag",
+            AuditOperation::Backup => "backup",
+            AuditOperation::Restore => "restore",
+            AuditOperation::Encrypt => "encrypt",
+            AuditOperation::RepairIndex => "repair_index",
+        }
+    }
+
+    /// Parses the string representation
+    pub fn parse(operation: &str) -> Option<Self> {
+        Self::all().iter().copied().find(|candidate| candidate.as_str() == operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_audit_entry_construction() {
+        let actor = AuditActor::new("mcp:desktop").with_session("s-1");
+        let index_id = Uuid::new_v4();
+        let entry = AuditEntry::new(AuditOperation::DeleteIndex, &actor, json!({"reason": "gc"})).with_index(index_id, "firmware");
+
+        assert_eq!(entry.actor, "mcp:desktop");
+        assert_eq!(entry.session_id.as_deref(), Some("s-1"));
+        assert_eq!(entry.index_name.as_deref(), Some("firmware"));
+        assert_eq!(AuditActor::new("x".repeat(500)).name.len(), MAX_ACTOR_LENGTH);
+
+        for operation in AuditOperation::all() {
+            assert_eq!(AuditOperation::parse(operation.as_str()), Some(*operation));
+        }
+        assert_eq!(AuditOperation::parse("drop_table"), None);
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/// Maximum length of a build configuration name
+pub const MAX_CONFIGURATION_NAME_LENGTH: usize = 64;
+
+/// Name of the configuration created alongside an index
+pub const DEFAULT_CONFIGURATION_NAME: &str = "default";
+
+/// A named set of `-D`/`-U` flags an index is built under (e.g. "linux-debug")
+///
+/// Every symbol of an index belongs to each of its configurations unless
+/// the configuration excludes it, i.e. the symbol sits in a preprocessor
+/// branch that is not compiled with these macros.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BuildConfiguration {
+    /// Primary key (auto-generated)
+    pub id: Option<i64>,
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Unique within the index
+    pub name: String,
+    /// Macros defined, with their values ("1" for a bare `-DNAME`)
+    pub defines: BTreeMap<String, String>,
+    /// Macros explicitly undefined
+    pub undefines: BTreeSet<String>,
+    /// Timestamp when the configuration was first saved
+    pub created_at: DateTime<Utc>,
+}
+
+impl BuildConfiguration {
+    /// Creates a configuration with no macros defined
+    pub fn new(index_id: Uuid, name: String) -> Self {
+        Self {
+            id: None,
+            index_id,
+            name,
+            defines: BTreeMap::new(),
+            undefines: BTreeSet::new(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Defines a macro, cancelling an earlier undefine of it
+    pub fn with_define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        self.undefines.remove(&name);
+        self.defines.insert(name, value.into());
+        self
+    }
+
+    /// Undefines a macro, cancelling an earlier define of it
+    pub fn with_undefine(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        self.defines.remove(&name);
+        self.undefines.insert(name);
+        self
+    }
+
+    /// Returns true if `name` is a valid C identifier
+    pub fn is_macro_name(name: &str) -> bool {
+        name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Validates a configuration name: letters, digits, '_', '-' and '.' only
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("Build configuration name cannot be empty".to_string());
+        }
+
+        if name.len() > MAX_CONFIGURATION_NAME_LENGTH {
+            return Err(format!("Build configuration name cannot exceed {} characters", MAX_CONFIGURATION_NAME_LENGTH));
+        }
+
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(format!("Build configuration name contains invalid characters: {}", name));
+        }
+
+        Ok(())
+    }
+
+    /// Validates the name and every macro name
+    pub fn validate(&self) -> Result<(), String> {
+        Self::validate_name(&self.name)?;
+
+        for name in self.defines.keys().chain(&self.undefines) {
+            if !Self::is_macro_name(name) {
+                return Err(format!("Invalid macro name: {}", name));
+            }
+        }
+
+        if let Some(name) = self.defines.keys().find(|name| self.undefines.contains(*name)) {
+            return Err(format!("Macro {} cannot be both defined and undefined", name));
+        }
+
+        Ok(())
+    }
+
+    /// The configuration as compiler flags, defines first
+    pub fn compile_flags(&self) -> Vec<String> {
+        self.defines
+            .iter()
+            .map(|(name, value)| format!("-D{}={}", name, value))
+            .chain(self.undefines.iter().map(|name| format!("-U{}", name)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_configuration_validation() {
+        let config = |name: &str| BuildConfiguration::new(Uuid::new_v4(), name.to_string());
+
+        assert!(config("linux-debug").with_define("NDEBUG", "1").validate().is_ok());
+        assert!(config("linux debug").validate().is_err());
+        assert!(config("").validate().is_err());
+        assert!(config("gpu").with_define("1GPU", "1").validate().is_err());
+
+        // The later of a define and an undefine of the same macro wins
+        let gpu = config("gpu").with_define("ENABLE_GPU", "1").with_undefine("ENABLE_GPU");
+        assert!(gpu.defines.is_empty());
+        assert!(gpu.validate().is_ok());
+
+        let mut both = config("both").with_define("ENABLE_GPU", "1");
+        both.undefines.insert("ENABLE_GPU".to_string());
+        assert!(both.validate().is_err());
+    }
+
+    #[test]
+    fn test_compile_flags() {
+        let config = BuildConfiguration::new(Uuid::new_v4(), "win".to_string())
+            .with_define("_WIN32", "1")
+            .with_define("ARCH_LEVEL", "2")
+            .with_undefine("NDEBUG");
+        assert_eq!(config.compile_flags(), vec!["-DARCH_LEVEL=2", "-D_WIN32=1", "-UNDEBUG"]);
+    }
+}
@@ -28,6 +28,18 @@ pub struct CodeElement {
     pub is_declaration: bool,
     /// Function signature or variable type (optional)
     pub signature: Option<String>,
+    /// Linker section the symbol is placed in (e.g., ".itcm", ".iram1")
+    pub memory_section: Option<String>,
+    /// Doc comment preceding the declaration, without comment markers
+    pub documentation: Option<String>,
+    /// Unified Symbol Resolution id from libclang, shared by the declarations
+    /// and definition of a symbol and distinct for each overload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usr: Option<String>,
+    /// Source text of the symbol, stored compressed when given and never
+    /// loaded with the element (see `Repository::get_symbol_body`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
 }
 
 /// Type of C++ symbol
@@ -48,6 +60,8 @@ pub enum SymbolType {
     Operator,
     Field,
     EnumConstant,
+    /// Lambda expression, named after where it is written
+    Lambda,
     Unknown,
 }
 
@@ -83,6 +97,10 @@ impl CodeElement {
             access_modifier: None,
             is_declaration: false,
             signature: None,
+            memory_section: None,
+            documentation: None,
+            usr: None,
+            body: None,
         }
     }
 
@@ -110,6 +128,30 @@ impl CodeElement {
         self
     }
 
+    /// Sets the linker section for this code element
+    pub fn with_memory_section(mut self, memory_section: String) -> Self {
+        self.memory_section = Some(memory_section);
+        self
+    }
+
+    /// Sets the documentation for this code element
+    pub fn with_documentation(mut self, documentation: String) -> Self {
+        self.documentation = Some(documentation);
+        self
+    }
+
+    /// Sets the Unified Symbol Resolution id of this code element
+    pub fn with_usr(mut self, usr: String) -> Self {
+        self.usr = Some(usr);
+        self
+    }
+
+    /// Sets the source text to store with the element
+    pub fn with_body(mut self, body: String) -> Self {
+        self.body = Some(body);
+        self
+    }
+
     /// Validates the code element fields
     pub fn validate(&self) -> Result<(), String> {
         if self.symbol_name.trim().is_empty() {
@@ -142,6 +184,10 @@ impl CodeElement {
             return Err("Definition hash must contain only hexadecimal characters".to_string());
         }
 
+        if self.memory_section.as_ref().is_some_and(|section| section.trim().is_empty()) {
+            return Err("Memory section cannot be empty".to_string());
+        }
+
         Ok(())
     }
 
@@ -153,6 +199,11 @@ impl CodeElement {
         }
     }
 
+    /// Enclosing scopes joined by "::", empty at global scope
+    pub fn namespace_path(&self) -> &str {
+        self.scope.as_deref().unwrap_or("")
+    }
+
     /// Returns true if this symbol is a type definition
     pub fn is_type(&self) -> bool {
         matches!(
@@ -196,6 +247,7 @@ impl SymbolType {
             SymbolType::Operator,
             SymbolType::Field,
             SymbolType::EnumConstant,
+            SymbolType::Lambda,
             SymbolType::Unknown,
         ]
     }
@@ -218,6 +270,7 @@ impl SymbolType {
             SymbolType::Operator => "operator",
             SymbolType::Field => "field",
             SymbolType::EnumConstant => "enum_constant",
+            SymbolType::Lambda => "lambda",
             SymbolType::Unknown => "unknown",
         }
     }
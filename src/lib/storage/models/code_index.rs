@@ -81,6 +81,17 @@ impl CodeIndex {
 }
 
 impl IndexState {
+    /// Name stored in the database and returned by tools
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexState::Creating => "creating",
+            IndexState::Active => "active",
+            IndexState::Updating => "updating",
+            IndexState::Archived => "archived",
+            IndexState::Failed => "failed",
+        }
+    }
+
     /// Returns true if the index is in a state where it can be queried
     pub fn is_queryable(&self) -> bool {
         matches!(self, IndexState::Active)
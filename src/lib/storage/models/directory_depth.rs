@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How deeply the files of a directory are indexed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexDepth {
+    /// Every symbol with signatures, docs and references
+    Deep,
+    /// Public API in full, internal symbols as name and location only
+    Standard,
+    /// Public API as name and location only
+    Shallow,
+}
+
+/// Depth assigned to a directory from how often it was queried
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DirectoryDepth {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Directory relative to the index base path ("" for the root)
+    pub directory: String,
+    pub depth: IndexDepth,
+    /// Queries touching the directory within the planning window
+    pub hits: u64,
+    pub assigned_at: DateTime<Utc>,
+}
+
+impl IndexDepth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexDepth::Deep => "deep",
+            IndexDepth::Standard => "standard",
+            IndexDepth::Shallow => "shallow",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "deep" => Some(IndexDepth::Deep),
+            "standard" => Some(IndexDepth::Standard),
+            "shallow" => Some(IndexDepth::Shallow),
+            _ => None,
+        }
+    }
+}
+
+/// Directory part of a stored file path, "" for files at the index root
+pub fn directory_of(file_path: &str) -> &str {
+    file_path.rsplit_once('/').map_or("", |(directory, _)| directory)
+}
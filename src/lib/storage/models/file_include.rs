@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// An `#include` of one indexed file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct FileInclude {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Including file, relative to the index base path
+    pub file_path: String,
+    /// Path as written between the quotes or angle brackets
+    pub include_path: String,
+    /// Indexed file the include refers to; None for system headers and files outside the index
+    pub resolved_path: Option<String>,
+}
+
+/// The indexed file an include of `file_path` refers to
+///
+/// The path is first looked up next to the including file, as the quoted
+/// form does. Failing that, any indexed file whose path ends in the include
+/// path is a candidate, as if its root were an include directory; the one
+/// sharing the most leading directories with the including file wins, then
+/// the shortest path.
+pub fn resolve_include(file_path: &str, include_path: &str, files: &BTreeSet<String>) -> Option<String> {
+    let directory = file_path.rsplit_once('/').map_or("", |(directory, _)| directory);
+    let sibling = normalize(&format!("{}/{}", directory, include_path));
+    if files.contains(&sibling) {
+        return Some(sibling);
+    }
+
+    let include_path = normalize(include_path);
+    let suffix = format!("/{}", include_path);
+    files
+        .iter()
+        .filter(|file| **file == include_path || file.ends_with(&suffix))
+        .min_by_key(|file| (std::cmp::Reverse(shared_directories(file, file_path)), file.len()))
+        .cloned()
+}
+
+/// Drops empty and "." components and applies ".." to a slash-separated path
+fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
+/// Number of leading directories two paths share
+fn shared_directories(a: &str, b: &str) -> usize {
+    let (a, b) = (a.rsplit_once('/').map_or("", |(directory, _)| directory), b.rsplit_once('/').map_or("", |(directory, _)| directory));
+    a.split('/').zip(b.split('/')).take_while(|(a, b)| !a.is_empty() && a == b).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_include() {
+        let files: BTreeSet<String> = [
+            "src/audio/mixer.cpp",
+            "src/audio/mixer.h",
+            "src/audio/detail/ring.h",
+            "include/audio/format.h",
+            "tests/unit/audio/format.h",
+            "src/util/log.h",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+
+        let resolve = |include: &str| resolve_include("src/audio/mixer.cpp", include, &files);
+        assert_eq!(resolve("mixer.h").as_deref(), Some("src/audio/mixer.h"));
+        assert_eq!(resolve("./detail/ring.h").as_deref(), Some("src/audio/detail/ring.h"));
+        assert_eq!(resolve("../util/log.h").as_deref(), Some("src/util/log.h"));
+        assert_eq!(resolve("audio/format.h").as_deref(), Some("include/audio/format.h"));
+        assert_eq!(resolve_include("tests/unit/audio/format_test.cpp", "audio/format.h", &files).as_deref(), Some("tests/unit/audio/format.h"));
+        assert_eq!(resolve("vector"), None);
+    }
+}
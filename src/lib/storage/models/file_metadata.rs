@@ -21,6 +21,19 @@ pub struct FileMetadata {
     pub symbol_count: u32,
     /// Timestamp when file was last indexed
     pub indexed_at: DateTime<Utc>,
+    /// Whether some of the file's symbols were stored with reduced detail
+    pub detail: FileDetail,
+}
+
+/// Detail level a file's symbols were stored with
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileDetail {
+    /// Every symbol stored with all extracted fields
+    #[default]
+    Full,
+    /// Indexed under a tiered detail policy; some symbols are outlines or left out
+    Reduced,
 }
 
 /// Represents the state of file processing
@@ -36,6 +49,23 @@ pub enum FileProcessingState {
     Error,
 }
 
+impl FileDetail {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileDetail::Full => "full",
+            FileDetail::Reduced => "reduced",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "full" => Some(FileDetail::Full),
+            "reduced" => Some(FileDetail::Reduced),
+            _ => None,
+        }
+    }
+}
+
 impl FileMetadata {
     /// Creates a new FileMetadata
     pub fn new(
@@ -55,9 +85,16 @@ impl FileMetadata {
             size_bytes,
             symbol_count: 0,
             indexed_at: now,
+            detail: FileDetail::Full,
         }
     }
 
+    /// Records the detail level the file's symbols are stored with
+    pub fn with_detail(mut self, detail: FileDetail) -> Self {
+        self.detail = detail;
+        self
+    }
+
     /// Updates the symbol count and indexed timestamp
     pub fn update_indexing(&mut self, symbol_count: u32) {
         self.symbol_count = symbol_count;
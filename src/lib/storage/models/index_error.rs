@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Longest error message stored; longer ones are cut at a character boundary
+pub const MAX_ERROR_MESSAGE_LENGTH: usize = 4096;
+
+/// Why the last indexing run couldn't index a file
+///
+/// A file has at most one error, replaced by every failed run and cleared
+/// once the file is indexed. The rest of the index is still built, so the
+/// errors list what is missing from it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexError {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Relative path from codebase root
+    pub file_path: String,
+    pub kind: IndexErrorKind,
+    /// What the reader or parser reported
+    pub message: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Stage at which indexing a file failed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexErrorKind {
+    /// The file couldn't be read
+    Read,
+    /// The parser reported an error
+    Parse,
+    /// Parsing took longer than the per-file timeout and was abandoned
+    Timeout,
+    /// The parser panicked
+    Panic,
+    /// The process parsing the file died, e.g. because libclang crashed
+    Crash,
+}
+
+impl IndexError {
+    pub fn new(index_id: Uuid, file_path: String, kind: IndexErrorKind, message: String) -> Self {
+        let mut message = message;
+        if message.len() > MAX_ERROR_MESSAGE_LENGTH {
+            let end = (0..=MAX_ERROR_MESSAGE_LENGTH).rev().find(|end| message.is_char_boundary(*end)).unwrap_or(0);
+            message.truncate(end);
+        }
+        Self {
+            index_id,
+            file_path,
+            kind,
+            message,
+            recorded_at: Utc::now(),
+        }
+    }
+}
+
+impl IndexErrorKind {
+    pub const ALL: [IndexErrorKind; 5] = [IndexErrorKind::Read, IndexErrorKind::Parse, IndexErrorKind::Timeout, IndexErrorKind::Panic, IndexErrorKind::Crash];
+
+    /// Returns string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexErrorKind::Read => "read",
+            IndexErrorKind::Parse => "parse",
+            IndexErrorKind::Timeout => "timeout",
+            IndexErrorKind::Panic => "panic",
+            IndexErrorKind::Crash => "crash",
+        }
+    }
+
+    /// Parses the string representation
+    pub fn parse(kind: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|candidate| candidate.as_str() == kind)
+    }
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::lib::storage::models::code_element::CodeElement;
+
+/// Hot spots of a code index, each list ranked highest first
+///
+/// Computed on demand with aggregate queries over the stored symbols,
+/// relationships and includes, so it reflects the index as it is now.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct IndexInsights {
+    /// Symbols referenced by the most distinct symbols
+    pub fan_in: Vec<RankedSymbol>,
+    /// Symbols referencing the most distinct symbols
+    pub fan_out: Vec<RankedSymbol>,
+    /// Classes, structs and unions declaring the most members
+    pub largest_classes: Vec<RankedSymbol>,
+    /// Indexed files included by the most files
+    pub most_included_headers: Vec<RankedFile>,
+    /// Files declaring the most symbols
+    pub busiest_files: Vec<RankedFile>,
+}
+
+/// A symbol with the count it is ranked by
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RankedSymbol {
+    pub element: CodeElement,
+    pub count: u64,
+}
+
+/// A file with the count it is ranked by
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RankedFile {
+    /// Relative path from codebase root
+    pub file_path: String,
+    pub count: u64,
+}
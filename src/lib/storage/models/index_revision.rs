@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::file_metadata::FileMetadata;
+use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+/// Maximum length of a revision name
+pub const MAX_REVISION_NAME_LENGTH: usize = 255;
+
+/// A branch (or detached commit) of the codebase an index has been switched to
+///
+/// The live index only ever holds the symbols of its current revision.
+/// Every revision records which files it had and their content hashes, as
+/// the difference from its parent, the revision it was branched from; a
+/// file whose content the parent changes later is copied into the revision
+/// first, so each revision keeps seeing its own files.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexRevision {
+    /// Primary key (auto-generated)
+    pub id: Option<i64>,
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Branch name, unique within the index
+    pub name: String,
+    /// Commit the revision was at when the index last switched to it
+    pub commit: Option<String>,
+    /// Revision whose files this one shares until they change
+    pub parent_id: Option<i64>,
+    /// True for the revision the live index holds
+    pub is_current: bool,
+    /// Timestamp when the revision was first switched to
+    pub created_at: DateTime<Utc>,
+    /// Timestamp when the index last switched to the revision
+    pub switched_at: DateTime<Utc>,
+}
+
+/// Everything stored for one version of a file, kept while another version is live
+///
+/// Stashed files are shared by every revision with that content, so
+/// switching back to a branch restores its files without parsing them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StashedFile {
+    pub metadata: FileMetadata,
+    /// Symbols with their bodies, without ids
+    pub elements: Vec<CodeElement>,
+    /// Paths the file includes, as written
+    pub includes: Vec<String>,
+    /// Relationships to and from its symbols, call edges included
+    #[serde(default)]
+    pub relationships: Vec<StashedRelationship>,
+}
+
+/// A relationship of a stashed file's symbols, with both ends named instead of numbered
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StashedRelationship {
+    pub from: StashedSymbol,
+    pub to: StashedSymbol,
+    pub relationship_type: RelationshipType,
+    /// File where the relationship occurs
+    pub file_path: String,
+    pub line_number: u32,
+}
+
+/// One end of a stashed relationship, found again by file, qualified name and kind
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StashedSymbol {
+    pub file_path: String,
+    pub qualified_name: String,
+    pub symbol_type: SymbolType,
+    /// Line it was on; picks among symbols sharing the name
+    pub line_number: u32,
+}
+
+impl StashedSymbol {
+    pub fn new(element: &CodeElement) -> Self {
+        Self {
+            file_path: element.file_path.clone(),
+            qualified_name: element.fully_qualified_name(),
+            symbol_type: element.symbol_type,
+            line_number: element.line_number,
+        }
+    }
+
+    /// Returns true if `element` is this symbol, wherever it is in its file now
+    pub fn names(&self, element: &CodeElement) -> bool {
+        element.file_path == self.file_path && element.symbol_type == self.symbol_type && element.fully_qualified_name() == self.qualified_name
+    }
+}
+
+impl IndexRevision {
+    /// Creates a revision without a parent or commit
+    pub fn new(index_id: Uuid, name: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: None,
+            index_id,
+            name,
+            commit: None,
+            parent_id: None,
+            is_current: false,
+            created_at: now,
+            switched_at: now,
+        }
+    }
+
+    /// Sets the commit the revision is at
+    pub fn with_commit(mut self, commit: String) -> Self {
+        self.commit = Some(commit);
+        self
+    }
+
+    /// Sets the revision this one branched from
+    pub fn with_parent(mut self, parent_id: i64) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
+
+    /// Validates the revision name
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Revision name cannot be empty".to_string());
+        }
+        if self.name.len() > MAX_REVISION_NAME_LENGTH {
+            return Err(format!("Revision name cannot exceed {} characters", MAX_REVISION_NAME_LENGTH));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Maximum length of a tag key
+pub const MAX_TAG_KEY_LENGTH: usize = 64;
+
+/// Maximum length of a tag value
+pub const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+/// Free-form key/value metadata attached to a Code Index (team, branch, toolchain, ttl)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexTag {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Tag key (e.g., "team")
+    pub key: String,
+    /// Tag value (e.g., "rendering")
+    pub value: String,
+    /// Timestamp when the tag was last set
+    pub updated_at: DateTime<Utc>,
+}
+
+impl IndexTag {
+    /// Creates a new IndexTag
+    pub fn new(index_id: Uuid, key: String, value: String) -> Self {
+        Self {
+            index_id,
+            key,
+            value,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Parses a "key=value" assignment as given on the command line
+    pub fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("Tag must be in key=value form: {}", assignment))?;
+        Self::validate_key(key.trim())?;
+        Ok((key.trim().to_string(), value.trim().to_string()))
+    }
+
+    /// Validates a tag key: letters, digits, '_', '-', '.' and ':' only
+    pub fn validate_key(key: &str) -> Result<(), String> {
+        if key.is_empty() {
+            return Err("Tag key cannot be empty".to_string());
+        }
+
+        if key.len() > MAX_TAG_KEY_LENGTH {
+            return Err(format!("Tag key cannot exceed {} characters", MAX_TAG_KEY_LENGTH));
+        }
+
+        if !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')) {
+            return Err(format!("Tag key contains invalid characters: {}", key));
+        }
+
+        Ok(())
+    }
+
+    /// Validates the tag fields
+    pub fn validate(&self) -> Result<(), String> {
+        Self::validate_key(&self.key)?;
+
+        if self.value.len() > MAX_TAG_VALUE_LENGTH {
+            return Err(format!("Tag value cannot exceed {} characters", MAX_TAG_VALUE_LENGTH));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_tag_new() {
+        let index_id = Uuid::new_v4();
+        let tag = IndexTag::new(index_id, "team".to_string(), "rendering".to_string());
+
+        assert_eq!(tag.index_id, index_id);
+        assert_eq!(tag.key, "team");
+        assert_eq!(tag.value, "rendering");
+        assert!(tag.updated_at <= Utc::now());
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(
+            IndexTag::parse_assignment("branch=release/5.3").unwrap(),
+            ("branch".to_string(), "release/5.3".to_string())
+        );
+        assert_eq!(
+            IndexTag::parse_assignment("ttl=").unwrap(),
+            ("ttl".to_string(), String::new())
+        );
+        assert!(IndexTag::parse_assignment("team").is_err());
+        assert!(IndexTag::parse_assignment("=value").is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut tag = IndexTag::new(Uuid::new_v4(), "toolchain".to_string(), "clang-17".to_string());
+        assert!(tag.validate().is_ok());
+
+        tag.key = "bad key".to_string();
+        assert!(tag.validate().is_err());
+
+        tag.key = "k".repeat(MAX_TAG_KEY_LENGTH + 1);
+        assert!(tag.validate().is_err());
+
+        tag.key = "ci.pipeline".to_string();
+        tag.value = "v".repeat(MAX_TAG_VALUE_LENGTH + 1);
+        assert!(tag.validate().is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
 use uuid::Uuid;
 
+use crate::lib::storage::timings::ToolStats;
+
 /// Tracks MCP client sessions and query history
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct McpQuerySession {
@@ -21,6 +25,9 @@ pub struct McpQuerySession {
     pub status: SessionStatus,
     /// Optional metadata about the client
     pub client_metadata: Option<String>,
+    /// Tool calls recorded since the session was created or loaded; not stored
+    #[serde(skip)]
+    pub tool_calls: BTreeMap<String, ToolStats>,
 }
 
 /// Represents the status of an MCP session
@@ -59,6 +66,7 @@ impl McpQuerySession {
             query_count: 0,
             status: SessionStatus::Active,
             client_metadata: None,
+            tool_calls: BTreeMap::new(),
         }
     }
 
@@ -74,6 +82,7 @@ impl McpQuerySession {
             query_count: 0,
             status: SessionStatus::Active,
             client_metadata: None,
+            tool_calls: BTreeMap::new(),
         }
     }
 
@@ -95,6 +104,12 @@ impl McpQuerySession {
         self.update_activity();
     }
 
+    /// Records a call of `tool` that took `elapsed` and, if it failed, its error kind
+    pub fn record_tool_call(&mut self, tool: &str, elapsed: Duration, error_kind: Option<&str>) {
+        self.tool_calls.entry(tool.to_string()).or_default().record(elapsed, error_kind);
+        self.record_query();
+    }
+
     /// Updates the last activity timestamp
     pub fn update_activity(&mut self) {
         self.last_activity = Utc::now();
@@ -174,15 +189,9 @@ impl McpQuerySession {
         }
     }
 
-    /// Returns session statistics
+    /// Returns statistics of the tool calls recorded with `record_tool_call`
     pub fn basic_stats(&self) -> SessionStats {
-        SessionStats {
-            total_queries: self.query_count,
-            successful_queries: 0, // Would need query log to calculate
-            failed_queries: 0,     // Would need query log to calculate
-            avg_response_time_ms: None, // Would need timing data
-            most_used_tool: None,  // Would need query log to calculate
-        }
+        SessionStats::from_tools(&self.tool_calls)
     }
 }
 
@@ -219,6 +228,25 @@ impl SessionStatus {
 }
 
 impl SessionStats {
+    /// Totals over the calls of every tool
+    pub fn from_tools(tools: &BTreeMap<String, ToolStats>) -> Self {
+        let count = |value: u64| u32::try_from(value).unwrap_or(u32::MAX);
+        let calls: u64 = tools.values().map(|stats| stats.calls).sum();
+        let errors: u64 = tools.values().map(|stats| stats.errors).sum();
+        let total: Duration = tools.values().map(|stats| stats.latency.total).sum();
+        Self {
+            total_queries: count(calls),
+            successful_queries: count(calls - errors),
+            failed_queries: count(errors),
+            avg_response_time_ms: (calls > 0).then(|| total.as_secs_f64() * 1000.0 / calls as f64),
+            // The first by name among equally used tools
+            most_used_tool: tools
+                .iter()
+                .max_by(|(a_name, a), (b_name, b)| a.calls.cmp(&b.calls).then(b_name.cmp(a_name)))
+                .map(|(name, _)| name.clone()),
+        }
+    }
+
     /// Returns the success rate as a percentage
     pub fn success_rate(&self) -> f64 {
         if self.total_queries > 0 {
@@ -488,6 +516,16 @@ mod tests {
 
         assert_eq!(empty_stats.success_rate(), 0.0);
         assert_eq!(empty_stats.error_rate(), 0.0);
+
+        // A session's statistics come from the calls recorded on it
+        let mut session = McpQuerySession::new("Test Client".to_string());
+        assert_eq!(session.basic_stats(), empty_stats);
+        session.record_tool_call("search_symbols", Duration::from_millis(30), None);
+        session.record_tool_call("find_references", Duration::from_millis(10), Some("not_found"));
+        let stats = session.basic_stats();
+        assert_eq!((stats.total_queries, stats.successful_queries, stats.failed_queries, session.query_count), (2, 1, 1, 2));
+        assert_eq!(stats.avg_response_time_ms, Some(20.0));
+        assert_eq!(stats.most_used_tool.as_deref(), Some("find_references"));
     }
 
     #[test]
@@ -6,4 +6,19 @@ pub mod code_index;
 pub mod code_element;
 pub mod file_metadata;
 pub mod symbol_relationships;
-pub mod mcp_query_session;
\ No newline at end of file
+pub mod mcp_query_session;
+pub mod index_tag;
+pub mod slow_query;
+pub mod symbol_annotation;
+pub mod saved_query;
+pub mod path_alias;
+pub mod admin_audit;
+pub mod directory_depth;
+pub mod symbol_popularity;
+pub mod build_configuration;
+pub mod file_include;
+pub mod index_revision;
+pub mod walk_rules;
+pub mod parse_settings;
+pub mod index_error;
+pub mod index_insights;
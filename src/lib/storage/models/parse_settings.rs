@@ -0,0 +1,29 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How the parser of an index was set up beyond its defines and preset
+///
+/// Saved by `index create` so later updates parse files with the same
+/// compilation database and include directories.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ParseSettings {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// compile_commands.json the index was built with
+    pub compile_commands: Option<String>,
+    /// `-I` flags inferred from the codebase's includes
+    pub include_flags: Vec<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ParseSettings {
+    pub fn new(index_id: Uuid, compile_commands: Option<String>, include_flags: Vec<String>) -> Self {
+        Self {
+            index_id,
+            compile_commands,
+            include_flags,
+            updated_at: Utc::now(),
+        }
+    }
+}
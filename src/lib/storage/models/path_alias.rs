@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A directory skipped during indexing because it is a byte-identical copy of another
+///
+/// Files under `alias_path` resolve to the same relative path under
+/// `canonical_path`, whose symbols are indexed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PathAlias {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Skipped copy, relative to the index base path (e.g., "external/zlib")
+    pub alias_path: String,
+    /// Indexed copy, relative to the index base path (e.g., "third_party/zlib")
+    pub canonical_path: String,
+    /// Number of source files in the tree
+    pub file_count: u64,
+    /// Content hash shared by both copies
+    pub tree_hash: String,
+}
+
+impl PathAlias {
+    /// Validates the alias fields
+    pub fn validate(&self) -> Result<(), String> {
+        if self.alias_path.is_empty() || self.canonical_path.is_empty() {
+            return Err("Alias and canonical paths cannot be empty".to_string());
+        }
+
+        if self.alias_path == self.canonical_path {
+            return Err(format!("Path cannot be an alias of itself: {}", self.alias_path));
+        }
+
+        Ok(())
+    }
+
+    /// Maps a file under the alias to the same file under the canonical path
+    pub fn resolve(&self, file_path: &str) -> Option<String> {
+        let rest = file_path.strip_prefix(&self.alias_path)?;
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(format!("{}{}", self.canonical_path, rest))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_alias_resolve() {
+        let alias = PathAlias {
+            index_id: Uuid::new_v4(),
+            alias_path: "external/zlib".to_string(),
+            canonical_path: "third_party/zlib".to_string(),
+            file_count: 3,
+            tree_hash: "abc".to_string(),
+        };
+
+        assert!(alias.validate().is_ok());
+        assert_eq!(alias.resolve("external/zlib/inflate.c").as_deref(), Some("third_party/zlib/inflate.c"));
+        assert_eq!(alias.resolve("external/zlib").as_deref(), Some("third_party/zlib"));
+        assert_eq!(alias.resolve("external/zlibng/inflate.c"), None);
+        assert_eq!(alias.resolve("src/main.cpp"), None);
+    }
+}
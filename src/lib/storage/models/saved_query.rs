@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::lib::storage::query::{CodeElementQuery, ElementColumn, Filter};
+use crate::lib::storage::query_dsl::parse_element_query;
+
+/// Maximum length of a saved query name
+pub const MAX_SAVED_QUERY_NAME_LENGTH: usize = 64;
+
+/// Maximum length of a saved query's filter expression
+pub const MAX_SAVED_QUERY_LENGTH: usize = 1024;
+
+/// A named filter expression stored with a Code Index (e.g. "hot-functions")
+///
+/// The query is kept as text in the query syntax and parsed each time it is
+/// run, so it keeps working as the index is rebuilt.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SavedQuery {
+    /// Primary key (auto-generated)
+    pub id: Option<i64>,
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Name the query is run by; unique within the index
+    pub name: String,
+    /// Filter expression (e.g., "type:function section:.iram1")
+    pub query: String,
+    /// What the query is for
+    pub description: Option<String>,
+    /// Standing query: changes to its result set are reported after reindexing
+    pub watched: bool,
+    /// Timestamp when the watch was last evaluated
+    pub last_checked_at: Option<DateTime<Utc>>,
+    /// Timestamp when the query was first saved
+    pub created_at: DateTime<Utc>,
+    /// Timestamp when the query was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SavedQuery {
+    /// Creates a new SavedQuery
+    pub fn new(index_id: Uuid, name: String, query: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: None,
+            index_id,
+            name,
+            query,
+            description: None,
+            watched: false,
+            last_checked_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Sets the description
+    pub fn with_description(mut self, description: String) -> Self {
+        self.description = Some(description).filter(|description| !description.trim().is_empty());
+        self
+    }
+
+    /// Marks the query as a standing query
+    pub fn with_watch(mut self, watched: bool) -> Self {
+        self.watched = watched;
+        self
+    }
+
+    /// Validates a query name: letters, digits, '_', '-' and '.' only
+    pub fn validate_name(name: &str) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("Saved query name cannot be empty".to_string());
+        }
+
+        if name.len() > MAX_SAVED_QUERY_NAME_LENGTH {
+            return Err(format!("Saved query name cannot exceed {} characters", MAX_SAVED_QUERY_NAME_LENGTH));
+        }
+
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(format!("Saved query name contains invalid characters: {}", name));
+        }
+
+        Ok(())
+    }
+
+    /// Validates the saved query fields, including that the query parses
+    pub fn validate(&self) -> Result<(), String> {
+        Self::validate_name(&self.name)?;
+
+        if self.query.len() > MAX_SAVED_QUERY_LENGTH {
+            return Err(format!("Saved query cannot exceed {} characters", MAX_SAVED_QUERY_LENGTH));
+        }
+
+        parse_element_query(&self.query)?;
+        Ok(())
+    }
+
+    /// Query over this index's code elements, ordered by file and line
+    pub fn to_element_query(&self) -> Result<CodeElementQuery, String> {
+        Ok(parse_element_query(&self.query)?
+            .filter(Filter::eq(ElementColumn::IndexId, self.index_id.to_string()))
+            .order_by_asc(ElementColumn::FilePath)
+            .order_by_asc(ElementColumn::LineNumber))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_query_validation() {
+        let saved = |name: &str, query: &str| SavedQuery::new(Uuid::new_v4(), name.to_string(), query.to_string());
+
+        assert!(saved("hot-functions", "type:function section:.iram1").validate().is_ok());
+        assert!(saved("", "type:function").validate().is_err());
+        assert!(saved("hot functions", "type:function").validate().is_err());
+        assert!(saved("hot-functions", "type:gadget").validate().is_err());
+        assert!(saved("hot-functions", "").validate().is_err());
+        assert_eq!(saved("q", "x").with_description("  ".to_string()).description, None);
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Default duration above which a repository query is logged
+pub const DEFAULT_SLOW_QUERY_THRESHOLD_MS: u64 = 200;
+
+/// Number of slow query entries kept; older entries are discarded
+pub const MAX_SLOW_QUERY_ENTRIES: usize = 1000;
+
+/// Maximum length of a single parameter in the summary
+const MAX_PARAM_LENGTH: usize = 64;
+
+/// A repository query that took longer than the configured threshold
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SlowQuery {
+    /// Primary key (auto-generated)
+    pub id: Option<i64>,
+    /// When the query ran
+    pub recorded_at: DateTime<Utc>,
+    /// Repository method that issued the query (e.g., "search_code_elements")
+    pub operation: String,
+    /// SQL text, whitespace collapsed
+    pub sql: String,
+    /// Short, truncated rendering of the bound parameters
+    pub params_summary: String,
+    /// Wall-clock duration in milliseconds
+    pub duration_ms: f64,
+    /// Rows returned to the caller
+    pub rows_returned: u64,
+}
+
+impl SlowQuery {
+    /// Creates a new SlowQuery entry
+    pub fn new(operation: &str, sql: &str, params_summary: String, duration: Duration, rows_returned: usize) -> Self {
+        Self {
+            id: None,
+            recorded_at: Utc::now(),
+            operation: operation.to_string(),
+            sql: sql.split_whitespace().collect::<Vec<_>>().join(" "),
+            params_summary,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            rows_returned: rows_returned as u64,
+        }
+    }
+
+    /// Renders named parameters as `name=value`, truncating long values
+    pub fn summarize_params(params: &[(&str, &dyn std::fmt::Display)]) -> String {
+        params
+            .iter()
+            .map(|(name, value)| {
+                let value = value.to_string();
+                if value.chars().count() > MAX_PARAM_LENGTH {
+                    let truncated: String = value.chars().take(MAX_PARAM_LENGTH).collect();
+                    format!("{}={}...", name, truncated)
+                } else {
+                    format!("{}={}", name, value)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_query_new() {
+        let query = SlowQuery::new(
+            "search_code_elements",
+            "SELECT id\n    FROM code_elements\n    WHERE index_id = ?1",
+            "index_id=abc".to_string(),
+            Duration::from_millis(250),
+            3,
+        );
+
+        assert_eq!(query.sql, "SELECT id FROM code_elements WHERE index_id = ?1");
+        assert_eq!(query.duration_ms, 250.0);
+        assert_eq!(query.rows_returned, 3);
+        assert!(query.id.is_none());
+    }
+
+    #[test]
+    fn test_summarize_params() {
+        let long = "x".repeat(MAX_PARAM_LENGTH + 10);
+        let summary = SlowQuery::summarize_params(&[("pattern", &"draw%"), ("path", &long)]);
+
+        assert!(summary.starts_with("pattern=draw%, path=xxx"));
+        assert!(summary.ends_with("..."));
+        assert_eq!(summary.len(), "pattern=draw%, path=".len() + MAX_PARAM_LENGTH + 3);
+    }
+}
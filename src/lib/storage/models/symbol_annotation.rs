@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Maximum length of an annotation's text
+pub const MAX_ANNOTATION_LENGTH: usize = 4096;
+
+/// Maximum length of an annotation author
+pub const MAX_AUTHOR_LENGTH: usize = 64;
+
+/// A note or bookmark attached to a symbol by a user or assistant
+///
+/// Annotations refer to the symbol by scope and name rather than by element
+/// id, so they survive re-indexing the file the symbol lives in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SymbolAnnotation {
+    /// Primary key (auto-generated)
+    pub id: Option<i64>,
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Name of the annotated symbol
+    pub symbol_name: String,
+    /// Scope of the annotated symbol (e.g., "audio::Engine"); None for the global scope
+    pub scope: Option<String>,
+    /// Who wrote the annotation (e.g., a user name or "assistant")
+    pub author: String,
+    /// Kind of annotation
+    pub kind: AnnotationKind,
+    /// Annotation text
+    pub text: String,
+    /// Timestamp when the annotation was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// Kind of annotation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationKind {
+    /// Free-form explanation ("main entry point", "deprecated, use X")
+    Note,
+    /// A symbol worth coming back to
+    Bookmark,
+}
+
+impl SymbolAnnotation {
+    /// Creates a new SymbolAnnotation
+    pub fn new(index_id: Uuid, symbol_name: String, author: String, kind: AnnotationKind, text: String) -> Self {
+        Self {
+            id: None,
+            index_id,
+            symbol_name,
+            scope: None,
+            author,
+            kind,
+            text,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Sets the scope of the annotated symbol
+    pub fn with_scope(mut self, scope: String) -> Self {
+        self.scope = Some(scope).filter(|scope| !scope.is_empty());
+        self
+    }
+
+    /// Validates the annotation fields
+    pub fn validate(&self) -> Result<(), String> {
+        if self.symbol_name.trim().is_empty() {
+            return Err("Symbol name cannot be empty".to_string());
+        }
+
+        if self.author.trim().is_empty() {
+            return Err("Annotation author cannot be empty".to_string());
+        }
+
+        if self.author.len() > MAX_AUTHOR_LENGTH {
+            return Err(format!("Annotation author cannot exceed {} characters", MAX_AUTHOR_LENGTH));
+        }
+
+        if self.kind == AnnotationKind::Note && self.text.trim().is_empty() {
+            return Err("Note text cannot be empty".to_string());
+        }
+
+        if self.text.len() > MAX_ANNOTATION_LENGTH {
+            return Err(format!("Annotation text cannot exceed {} characters", MAX_ANNOTATION_LENGTH));
+        }
+
+        Ok(())
+    }
+}
+
+impl AnnotationKind {
+    /// Returns string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnotationKind::Note => "note",
+            AnnotationKind::Bookmark => "bookmark",
+        }
+    }
+
+    /// Parses the string representation
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "note" => Some(AnnotationKind::Note),
+            "bookmark" => Some(AnnotationKind::Bookmark),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotation_validation() {
+        let note = |text: &str| SymbolAnnotation::new(Uuid::new_v4(), "main".to_string(), "dana".to_string(), AnnotationKind::Note, text.to_string());
+
+        assert!(note("Main entry point").validate().is_ok());
+        assert!(note("  ").validate().is_err());
+        assert!(note(&"x".repeat(MAX_ANNOTATION_LENGTH + 1)).validate().is_err());
+
+        // Bookmarks don't need text
+        let bookmark = SymbolAnnotation::new(Uuid::new_v4(), "main".to_string(), "dana".to_string(), AnnotationKind::Bookmark, String::new());
+        assert!(bookmark.validate().is_ok());
+        assert_eq!(bookmark.with_scope(String::new()).scope, None);
+        assert_eq!(AnnotationKind::parse("bookmark"), Some(AnnotationKind::Bookmark));
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// How much of the codebase depends on a symbol
+///
+/// Counted from the stored relationships when an index is built, so
+/// reading it costs nothing at query time.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SymbolPopularity {
+    /// Incoming references of every kind except containment and definition
+    pub reference_count: u64,
+    /// Distinct functions calling the symbol directly or through a callback
+    pub caller_count: u64,
+    /// Distinct functions the symbol calls
+    pub callee_count: u64,
+}
+
+impl SymbolPopularity {
+    /// Callers plus callees
+    pub fn call_degree(&self) -> u64 {
+        self.caller_count + self.callee_count
+    }
+
+    /// One-line description for assistants, e.g. "used 412 times by 37 callers"
+    pub fn summary(&self) -> String {
+        let times = if self.reference_count == 1 { "time" } else { "times" };
+        match self.caller_count {
+            0 => format!("used {} {}", self.reference_count, times),
+            1 => format!("used {} {} by 1 caller", self.reference_count, times),
+            callers => format!("used {} {} by {} callers", self.reference_count, times, callers),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let popularity = SymbolPopularity { reference_count: 412, caller_count: 37, callee_count: 5 };
+        assert_eq!(popularity.summary(), "used 412 times by 37 callers");
+        assert_eq!(popularity.call_degree(), 42);
+
+        let popularity = SymbolPopularity { reference_count: 1, caller_count: 1, callee_count: 0 };
+        assert_eq!(popularity.summary(), "used 1 time by 1 caller");
+        assert_eq!(SymbolPopularity::default().summary(), "used 0 times");
+    }
+}
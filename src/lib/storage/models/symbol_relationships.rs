@@ -40,6 +40,8 @@ pub enum RelationshipType {
     Overrides,
     /// Template specialization
     Specializes,
+    /// Function stored as a callback (function pointer, std::function, std::bind)
+    ReferencedAsCallback,
 }
 
 impl SymbolRelationship {
@@ -103,6 +105,7 @@ impl SymbolRelationship {
             | RelationshipType::ContainedIn
             | RelationshipType::Overrides
             | RelationshipType::Specializes
+            | RelationshipType::ReferencedAsCallback
         )
     }
 
@@ -153,6 +156,7 @@ impl RelationshipType {
             RelationshipType::Friend,
             RelationshipType::Overrides,
             RelationshipType::Specializes,
+            RelationshipType::ReferencedAsCallback,
         ]
     }
 
@@ -169,6 +173,7 @@ impl RelationshipType {
             RelationshipType::Friend => "friend",
             RelationshipType::Overrides => "overrides",
             RelationshipType::Specializes => "specializes",
+            RelationshipType::ReferencedAsCallback => "referenced_as_callback",
         }
     }
 
@@ -185,6 +190,7 @@ impl RelationshipType {
             RelationshipType::Friend => "Friend class/function relationship",
             RelationshipType::Overrides => "Virtual function override",
             RelationshipType::Specializes => "Template specialization",
+            RelationshipType::ReferencedAsCallback => "Function referenced as a callback (possible indirect call)",
         }
     }
 
@@ -206,6 +212,7 @@ impl RelationshipType {
             RelationshipType::Uses 
             | RelationshipType::Calls 
             | RelationshipType::Instantiates
+            | RelationshipType::ReferencedAsCallback
         )
     }
 
@@ -0,0 +1,46 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Which files under the base path of an index are indexed
+///
+/// Paths are relative to the base path; the patterns are globs where `*`
+/// stays within a path component and `**` crosses them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WalkRules {
+    /// Foreign key to Code Index
+    pub index_id: Uuid,
+    /// Files to index (empty = every source file)
+    pub include_patterns: Vec<String>,
+    /// Files and directories to skip, even if included
+    pub exclude_patterns: Vec<String>,
+    /// Whether `.gitignore` and `.cppindexignore` files are honored
+    pub respect_ignore_files: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WalkRules {
+    pub fn new(index_id: Uuid, include_patterns: Vec<String>, exclude_patterns: Vec<String>, respect_ignore_files: bool) -> Self {
+        Self {
+            index_id,
+            include_patterns,
+            exclude_patterns,
+            respect_ignore_files,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Validates the rule fields
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(pattern) = self
+            .include_patterns
+            .iter()
+            .chain(&self.exclude_patterns)
+            .find(|pattern| pattern.trim().is_empty())
+        {
+            return Err(format!("Walk patterns cannot be empty: {:?}", pattern));
+        }
+
+        Ok(())
+    }
+}
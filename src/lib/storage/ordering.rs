@@ -0,0 +1,55 @@
+// Output ordering
+//
+// Every list the server or CLI returns is in a total, platform-independent
+// order, so outputs of two runs (or of Linux and Windows builds) can be
+// diffed directly:
+//
+// - Text compares by UTF-8 bytes: SQLite's BINARY collation and Rust's `str`
+//   ordering agree, and neither depends on the locale.
+// - Stored paths compare as stored, by bytes; the parsers store the path
+//   they were given and nothing rewrites it. Paths walked from disk sort by
+//   `path_key`, which turns `\` separators into `/` and drops a leading
+//   `./`, so a Windows walk visits files in the same order as a Linux one.
+// - Every sort ends on a unique key (usually the row id), so ties between
+//   equal names, lines or timestamps always resolve the same way.
+// - Maps in responses are keyed objects, which serialize with sorted keys.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// A relative path with `/` separators and no leading `./`, as a sort key
+pub fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut path = path.as_str();
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path.to_string()
+}
+
+/// Sort key of a filesystem path, the same on every platform
+pub fn path_key(path: &Path) -> String {
+    normalize_path(&path.to_string_lossy())
+}
+
+/// Compares paths by their sort keys
+pub fn compare_paths(a: &str, b: &str) -> Ordering {
+    normalize_path(a).cmp(&normalize_path(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_order_independent_of_separator() {
+        assert_eq!(normalize_path(".\\src\\audio\\ring.h"), "src/audio/ring.h");
+        assert_eq!(path_key(Path::new("./include/a.h")), "include/a.h");
+        assert_eq!(compare_paths("src\\b.cpp", "src/b.cpp"), Ordering::Equal);
+
+        // Byte order, not component or locale order: '-' sorts before '/'
+        let mut paths = vec!["src/a/z.cpp", "src\\a-b.cpp", "src/Z.cpp", "src/a.cpp"];
+        paths.sort_by(|a, b| compare_paths(a, b));
+        assert_eq!(paths, ["src/Z.cpp", "src\\a-b.cpp", "src/a.cpp", "src/a/z.cpp"]);
+    }
+}
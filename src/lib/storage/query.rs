@@ -0,0 +1,927 @@
+// Typed query builder
+//
+// Filters, sorts and pagination are plain values that render to SQL with
+// positional parameters, so callers never assemble SQL strings or track `?N`
+// indices. Columns are enums per table, which keeps queries limited to columns
+// that exist.
+
+use rusqlite::types::Value;
+
+use crate::lib::storage::models::code_element::SymbolType;
+use crate::lib::storage::models::symbol_relationships::RelationshipType;
+
+/// A column that can be filtered and sorted on
+pub trait Column: Copy + PartialEq {
+    /// SQL name of the column
+    fn name(&self) -> &'static str;
+
+    /// Unique column ending every sort, so ties always come out in the same order
+    fn key() -> Self;
+}
+
+/// Columns of the code_elements table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementColumn {
+    Id,
+    IndexId,
+    SymbolName,
+    SymbolType,
+    FilePath,
+    LineNumber,
+    ColumnNumber,
+    DefinitionHash,
+    Scope,
+    AccessModifier,
+    IsDeclaration,
+    Signature,
+    MemorySection,
+    Documentation,
+    Usr,
+    // Derived from scope and name when written, and not part of the rows read into CodeElement
+    FullyQualifiedName,
+    NamespacePath,
+    // Popularity counters: sortable, but not part of the rows read into CodeElement
+    ReferenceCount,
+    CallerCount,
+    CalleeCount,
+}
+
+/// Columns of the symbol_relationships table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelationshipColumn {
+    Id,
+    FromSymbolId,
+    ToSymbolId,
+    RelationshipType,
+    FilePath,
+    LineNumber,
+}
+
+/// Comparison operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A boolean condition over the columns of one table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter<C> {
+    Compare(C, CompareOp, Value),
+    /// SQL LIKE with the pattern used as given ('%' and '_' are wildcards, '\' escapes them)
+    Like(C, String),
+    /// SQL GLOB, case-sensitive ('*', '?' and '[...]' are wildcards)
+    Glob(C, String),
+    /// Regular expression match through the connection's `regexp` function
+    Regex(C, String),
+    In(C, Vec<Value>),
+    IsNull(C),
+    IsNotNull(C),
+    And(Vec<Filter<C>>),
+    Or(Vec<Filter<C>>),
+    Not(Box<Filter<C>>),
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// A sort key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort<C> {
+    pub column: C,
+    pub direction: SortDirection,
+}
+
+/// A composable query over one table
+///
+/// Top-level filters are combined with AND; sorts apply in the order added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query<C> {
+    pub filters: Vec<Filter<C>>,
+    pub sorts: Vec<Sort<C>>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+/// Rows a page holds unless the caller asks otherwise
+pub const DEFAULT_PAGE_LIMIT: u64 = 500;
+
+/// Most rows one page may hold
+pub const MAX_PAGE_LIMIT: u64 = 5000;
+
+/// Prefix of page cursors, so other tools' cursors are rejected rather than misread
+const PAGE_CURSOR_PREFIX: &str = "pg";
+
+/// Which slice of a result set to return
+///
+/// A cursor is the `next_cursor` of the previous page and takes precedence
+/// over the offset. Cursors only record a position, so a page boundary can
+/// shift if rows are added or removed between calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPage {
+    pub limit: u64,
+    pub offset: u64,
+    pub cursor: Option<String>,
+}
+
+/// One page of a result set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Rows matching the query across all pages
+    pub total_count: u64,
+    /// Cursor of the following page; None on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Query over code elements
+pub type CodeElementQuery = Query<ElementColumn>;
+
+/// Query over symbol relationships
+pub type SymbolRelationshipQuery = Query<RelationshipColumn>;
+
+impl Column for ElementColumn {
+    fn name(&self) -> &'static str {
+        match self {
+            ElementColumn::Id => "id",
+            ElementColumn::IndexId => "index_id",
+            ElementColumn::SymbolName => "symbol_name",
+            ElementColumn::SymbolType => "symbol_type",
+            ElementColumn::FilePath => "file_path",
+            ElementColumn::LineNumber => "line_number",
+            ElementColumn::ColumnNumber => "column_number",
+            ElementColumn::DefinitionHash => "definition_hash",
+            ElementColumn::Scope => "scope",
+            ElementColumn::AccessModifier => "access_modifier",
+            ElementColumn::IsDeclaration => "is_declaration",
+            ElementColumn::Signature => "signature",
+            ElementColumn::MemorySection => "memory_section",
+            ElementColumn::Documentation => "documentation",
+            ElementColumn::Usr => "usr",
+            ElementColumn::FullyQualifiedName => "fully_qualified_name",
+            ElementColumn::NamespacePath => "namespace_path",
+            ElementColumn::ReferenceCount => "reference_count",
+            ElementColumn::CallerCount => "caller_count",
+            ElementColumn::CalleeCount => "callee_count",
+        }
+    }
+
+    fn key() -> Self {
+        ElementColumn::Id
+    }
+}
+
+impl ElementColumn {
+    /// Columns of a code element, in the order the repository reads its rows
+    pub const ALL: &'static [ElementColumn] = &[
+        ElementColumn::Id,
+        ElementColumn::IndexId,
+        ElementColumn::SymbolName,
+        ElementColumn::SymbolType,
+        ElementColumn::FilePath,
+        ElementColumn::LineNumber,
+        ElementColumn::ColumnNumber,
+        ElementColumn::DefinitionHash,
+        ElementColumn::Scope,
+        ElementColumn::AccessModifier,
+        ElementColumn::IsDeclaration,
+        ElementColumn::Signature,
+        ElementColumn::MemorySection,
+        ElementColumn::Documentation,
+        ElementColumn::Usr,
+    ];
+}
+
+impl Column for RelationshipColumn {
+    fn name(&self) -> &'static str {
+        match self {
+            RelationshipColumn::Id => "id",
+            RelationshipColumn::FromSymbolId => "from_symbol_id",
+            RelationshipColumn::ToSymbolId => "to_symbol_id",
+            RelationshipColumn::RelationshipType => "relationship_type",
+            RelationshipColumn::FilePath => "file_path",
+            RelationshipColumn::LineNumber => "line_number",
+        }
+    }
+
+    fn key() -> Self {
+        RelationshipColumn::Id
+    }
+}
+
+impl RelationshipColumn {
+    /// All columns, in the order the repository reads relationship rows
+    pub const ALL: &'static [RelationshipColumn] = &[
+        RelationshipColumn::Id,
+        RelationshipColumn::FromSymbolId,
+        RelationshipColumn::ToSymbolId,
+        RelationshipColumn::RelationshipType,
+        RelationshipColumn::FilePath,
+        RelationshipColumn::LineNumber,
+    ];
+}
+
+impl CompareOp {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "=",
+            CompareOp::NotEq => "<>",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+}
+
+impl<C: Column> Filter<C> {
+    /// column = value
+    pub fn eq(column: C, value: impl Into<Value>) -> Self {
+        Filter::Compare(column, CompareOp::Eq, value.into())
+    }
+
+    /// column <> value
+    pub fn ne(column: C, value: impl Into<Value>) -> Self {
+        Filter::Compare(column, CompareOp::NotEq, value.into())
+    }
+
+    /// column < value
+    pub fn lt(column: C, value: impl Into<Value>) -> Self {
+        Filter::Compare(column, CompareOp::Lt, value.into())
+    }
+
+    /// column <= value
+    pub fn le(column: C, value: impl Into<Value>) -> Self {
+        Filter::Compare(column, CompareOp::Le, value.into())
+    }
+
+    /// column > value
+    pub fn gt(column: C, value: impl Into<Value>) -> Self {
+        Filter::Compare(column, CompareOp::Gt, value.into())
+    }
+
+    /// column >= value
+    pub fn ge(column: C, value: impl Into<Value>) -> Self {
+        Filter::Compare(column, CompareOp::Ge, value.into())
+    }
+
+    /// column LIKE pattern
+    pub fn like(column: C, pattern: impl Into<String>) -> Self {
+        Filter::Like(column, pattern.into())
+    }
+
+    /// column LIKE '%text%', with `text` matched literally
+    pub fn contains(column: C, text: &str) -> Self {
+        Filter::Like(column, format!("%{}%", escape_like(text)))
+    }
+
+    /// column GLOB 'pattern'
+    pub fn glob(column: C, pattern: impl Into<String>) -> Self {
+        Filter::Glob(column, pattern.into())
+    }
+
+    /// column REGEXP 'pattern'
+    pub fn regex(column: C, pattern: impl Into<String>) -> Self {
+        Filter::Regex(column, pattern.into())
+    }
+
+    /// column IN (values); matches nothing if `values` is empty
+    pub fn in_list<V: Into<Value>>(column: C, values: impl IntoIterator<Item = V>) -> Self {
+        Filter::In(column, values.into_iter().map(Into::into).collect())
+    }
+
+    /// All of the filters; matches everything if empty
+    pub fn and(filters: Vec<Filter<C>>) -> Self {
+        Filter::And(filters)
+    }
+
+    /// Any of the filters; matches nothing if empty
+    pub fn or(filters: Vec<Filter<C>>) -> Self {
+        Filter::Or(filters)
+    }
+
+    /// Negates the filter
+    pub fn negate(filter: Filter<C>) -> Self {
+        Filter::Not(Box::new(filter))
+    }
+
+    /// Appends the SQL for this filter and its parameters
+    fn render(&self, sql: &mut String, params: &mut Vec<Value>) {
+        match self {
+            Filter::Compare(column, op, value) => {
+                sql.push_str(&format!("{} {} ?", column.name(), op.as_sql()));
+                params.push(value.clone());
+            }
+            Filter::Like(column, pattern) => {
+                sql.push_str(&format!("{} LIKE ? ESCAPE '\\'", column.name()));
+                params.push(Value::Text(pattern.clone()));
+            }
+            Filter::Glob(column, pattern) => {
+                sql.push_str(&format!("{} GLOB ?", column.name()));
+                params.push(Value::Text(pattern.clone()));
+            }
+            Filter::Regex(column, pattern) => {
+                sql.push_str(&format!("{} REGEXP ?", column.name()));
+                params.push(Value::Text(pattern.clone()));
+            }
+            Filter::In(_, values) if values.is_empty() => sql.push('0'),
+            Filter::In(column, values) => {
+                let placeholders = vec!["?"; values.len()].join(", ");
+                sql.push_str(&format!("{} IN ({})", column.name(), placeholders));
+                params.extend(values.iter().cloned());
+            }
+            Filter::IsNull(column) => sql.push_str(&format!("{} IS NULL", column.name())),
+            Filter::IsNotNull(column) => sql.push_str(&format!("{} IS NOT NULL", column.name())),
+            Filter::And(filters) => Self::render_group(filters, " AND ", "1", sql, params),
+            Filter::Or(filters) => Self::render_group(filters, " OR ", "0", sql, params),
+            Filter::Not(filter) => {
+                sql.push_str("NOT (");
+                filter.render(sql, params);
+                sql.push(')');
+            }
+        }
+    }
+
+    fn render_group(filters: &[Filter<C>], separator: &str, empty: &str, sql: &mut String, params: &mut Vec<Value>) {
+        if filters.is_empty() {
+            sql.push_str(empty);
+            return;
+        }
+
+        sql.push('(');
+        for (i, filter) in filters.iter().enumerate() {
+            if i > 0 {
+                sql.push_str(separator);
+            }
+            filter.render(sql, params);
+        }
+        sql.push(')');
+    }
+}
+
+impl<C: Column> Default for Query<C> {
+    fn default() -> Self {
+        Self {
+            filters: Vec::new(),
+            sorts: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+impl<C: Column> Query<C> {
+    /// Creates a query that matches every row
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a filter; all filters must match
+    pub fn filter(mut self, filter: Filter<C>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Adds a sort key
+    pub fn order_by(mut self, column: C, direction: SortDirection) -> Self {
+        self.sorts.push(Sort { column, direction });
+        self
+    }
+
+    /// Adds an ascending sort key
+    pub fn order_by_asc(self, column: C) -> Self {
+        self.order_by(column, SortDirection::Ascending)
+    }
+
+    /// Limits the number of rows returned
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first `offset` rows
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Renders a SELECT of `columns` from `table` and its positional parameters
+    ///
+    /// The key column is appended to the sort keys unless already present, so
+    /// the row order is fully determined even without explicit sorts.
+    pub fn to_sql(&self, table: &str, columns: &[C]) -> (String, Vec<Value>) {
+        let column_list = columns.iter().map(|c| c.name()).collect::<Vec<_>>().join(", ");
+        let mut sql = format!("SELECT {} FROM {}", column_list, table);
+        let mut params = Vec::new();
+        self.render_where(&mut sql, &mut params);
+
+        let mut sorts: Vec<String> = self
+            .sorts
+            .iter()
+            .map(|sort| match sort.direction {
+                SortDirection::Ascending => sort.column.name().to_string(),
+                SortDirection::Descending => format!("{} DESC", sort.column.name()),
+            })
+            .collect();
+        if !self.sorts.iter().any(|sort| sort.column == C::key()) {
+            sorts.push(C::key().name().to_string());
+        }
+        sql.push_str(&format!(" ORDER BY {}", sorts.join(", ")));
+
+        match (self.limit, self.offset) {
+            (Some(limit), Some(offset)) => sql.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset)),
+            (Some(limit), None) => sql.push_str(&format!(" LIMIT {}", limit)),
+            // SQLite only accepts OFFSET after a LIMIT
+            (None, Some(offset)) => sql.push_str(&format!(" LIMIT -1 OFFSET {}", offset)),
+            (None, None) => {}
+        }
+
+        (sql, params)
+    }
+
+    /// Renders a COUNT of the matching rows, ignoring sort keys, limit and offset
+    pub fn to_count_sql(&self, table: &str) -> (String, Vec<Value>) {
+        let mut sql = format!("SELECT COUNT(*) FROM {}", table);
+        let mut params = Vec::new();
+        self.render_where(&mut sql, &mut params);
+        (sql, params)
+    }
+
+    fn render_where(&self, sql: &mut String, params: &mut Vec<Value>) {
+        if self.filters.is_empty() {
+            return;
+        }
+
+        sql.push_str(" WHERE ");
+        for (i, filter) in self.filters.iter().enumerate() {
+            if i > 0 {
+                sql.push_str(" AND ");
+            }
+            filter.render(sql, params);
+        }
+    }
+}
+
+impl Default for QueryPage {
+    fn default() -> Self {
+        Self::new(DEFAULT_PAGE_LIMIT)
+    }
+}
+
+impl QueryPage {
+    /// First page of at most `limit` rows, clamped to 1..=[`MAX_PAGE_LIMIT`]
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit: limit.clamp(1, MAX_PAGE_LIMIT),
+            offset: 0,
+            cursor: None,
+        }
+    }
+
+    /// Skips the first `offset` rows
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Continues where the page that returned `cursor` ended
+    pub fn with_cursor(mut self, cursor: impl Into<String>) -> Self {
+        self.cursor = Some(cursor.into());
+        self
+    }
+
+    /// Index of the first row of the page
+    pub fn start(&self) -> Result<u64, String> {
+        match &self.cursor {
+            Some(cursor) => cursor
+                .strip_prefix(PAGE_CURSOR_PREFIX)
+                .and_then(|offset| u64::from_str_radix(offset, 16).ok())
+                .ok_or_else(|| format!("Invalid cursor: {}", cursor)),
+            None => Ok(self.offset),
+        }
+    }
+
+    /// Restricts a query to the page
+    pub fn apply<C: Column>(&self, query: Query<C>) -> Result<Query<C>, String> {
+        Ok(query.limit(self.limit).offset(self.start()?))
+    }
+
+    /// Wraps the rows a query returned for this page
+    pub fn page<T>(&self, items: Vec<T>, total_count: u64) -> Result<Page<T>, String> {
+        let end = self.start()? + items.len() as u64;
+        let next_cursor = (end < total_count && !items.is_empty()).then(|| format!("{}{:x}", PAGE_CURSOR_PREFIX, end));
+        Ok(Page { items, total_count, next_cursor })
+    }
+
+    /// Cuts the page out of rows already in memory
+    pub fn slice<T>(&self, items: Vec<T>) -> Result<Page<T>, String> {
+        let total_count = items.len() as u64;
+        let start = self.start()?.min(total_count) as usize;
+        let items: Vec<T> = items.into_iter().skip(start).take(self.limit as usize).collect();
+        self.page(items, total_count)
+    }
+}
+
+impl<T> Page<T> {
+    /// Converts the items, keeping the counts and cursor
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total_count: self.total_count,
+            next_cursor: self.next_cursor,
+        }
+    }
+
+    /// True if rows remain after this page
+    pub fn has_more(&self) -> bool {
+        self.next_cursor.is_some()
+    }
+}
+
+impl From<SymbolType> for Value {
+    fn from(symbol_type: SymbolType) -> Self {
+        Value::Text(symbol_type.as_str().to_string())
+    }
+}
+
+impl From<RelationshipType> for Value {
+    fn from(relationship_type: RelationshipType) -> Self {
+        Value::Text(relationship_type.as_str().to_string())
+    }
+}
+
+/// Columns of the full-text index over code elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextColumn {
+    Name,
+    Signature,
+    Scope,
+    Documentation,
+}
+
+impl TextColumn {
+    /// All columns, in the order of the code_elements_fts table
+    pub const ALL: &'static [TextColumn] = &[
+        TextColumn::Name,
+        TextColumn::Signature,
+        TextColumn::Scope,
+        TextColumn::Documentation,
+    ];
+
+    /// Column name in code_elements_fts
+    pub fn fts_name(&self) -> &'static str {
+        match self {
+            TextColumn::Name => "symbol_name",
+            TextColumn::Signature => "signature",
+            TextColumn::Scope => "scope",
+            TextColumn::Documentation => "documentation",
+        }
+    }
+
+    /// Name clients use for the column
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextColumn::Name => "name",
+            TextColumn::Signature => "signature",
+            TextColumn::Scope => "scope",
+            TextColumn::Documentation => "documentation",
+        }
+    }
+
+    /// BM25 weight of the column when ranking hits; names count most
+    pub fn weight(&self) -> f64 {
+        match self {
+            TextColumn::Name => 10.0,
+            TextColumn::Signature => 4.0,
+            TextColumn::Scope => 2.0,
+            TextColumn::Documentation => 1.0,
+        }
+    }
+
+    /// Parses the name clients use, e.g. "name" or "documentation"
+    pub fn parse(column: &str) -> Option<Self> {
+        match column {
+            "name" => Some(TextColumn::Name),
+            "signature" => Some(TextColumn::Signature),
+            "scope" => Some(TextColumn::Scope),
+            "documentation" | "docs" => Some(TextColumn::Documentation),
+            _ => None,
+        }
+    }
+}
+
+/// How a name pattern is matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// The whole name
+    Exact,
+    /// Anywhere in the name, case-insensitive
+    #[default]
+    Substring,
+    /// Start of the name, case-insensitive
+    Prefix,
+    /// Shell-style wildcards ('*', '?', '[...]') over the whole name, case-sensitive
+    Glob,
+    /// Regular expression anywhere in the name unless anchored, case-sensitive
+    Regex,
+    /// Close to the name, allowing typos; ranked by closeness (see `fuzzy`)
+    Fuzzy,
+}
+
+impl MatchMode {
+    pub const ALL: &'static [MatchMode] = &[
+        MatchMode::Exact,
+        MatchMode::Substring,
+        MatchMode::Prefix,
+        MatchMode::Glob,
+        MatchMode::Regex,
+        MatchMode::Fuzzy,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchMode::Exact => "exact",
+            MatchMode::Substring => "substring",
+            MatchMode::Prefix => "prefix",
+            MatchMode::Glob => "glob",
+            MatchMode::Regex => "regex",
+            MatchMode::Fuzzy => "fuzzy",
+        }
+    }
+
+    pub fn parse(mode: &str) -> Option<Self> {
+        MatchMode::ALL.iter().copied().find(|candidate| candidate.as_str() == mode)
+    }
+
+    /// Filter matching `pattern` against `column`
+    ///
+    /// Fails when a regex does not compile, so bad patterns are reported as
+    /// such rather than as SQLite errors. Fuzzy matches are scored rather
+    /// than filtered, so they have no filter and fail too.
+    pub fn filter<C>(&self, column: C, pattern: &str) -> Result<Filter<C>, String> {
+        Ok(match self {
+            MatchMode::Exact => Filter::Compare(column, CompareOp::Eq, Value::Text(pattern.to_string())),
+            MatchMode::Substring => Filter::Like(column, format!("%{}%", escape_like(pattern))),
+            MatchMode::Prefix => Filter::Like(column, format!("{}%", escape_like(pattern))),
+            MatchMode::Glob => Filter::Glob(column, pattern.to_string()),
+            MatchMode::Regex => {
+                regex::Regex::new(pattern).map_err(|e| format!("Invalid regex: {}", e))?;
+                Filter::Regex(column, pattern.to_string())
+            }
+            MatchMode::Fuzzy => return Err("Fuzzy matching ranks candidates from the trigram index and has no filter".to_string()),
+        })
+    }
+}
+
+/// A symbol name given with some or all of its enclosing scopes, e.g. `render::Vector`
+///
+/// The scopes given must be the innermost ones: `render::Vector` matches
+/// `engine::render::Vector` but neither `render::detail::Vector` nor
+/// `prerender::Vector`. A leading `::` anchors the query at the global
+/// scope, so `::render::Vector` only matches `render::Vector` itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualifiedName {
+    /// Scopes before the name, joined by "::"; empty for `::Name`
+    pub qualifier: String,
+    pub name: String,
+    pub anchored: bool,
+}
+
+impl QualifiedName {
+    /// Splits a query containing "::"; None for a plain name
+    pub fn parse(query: &str) -> Option<Self> {
+        let query = query.trim();
+        let unanchored = query.strip_prefix("::");
+        let (qualifier, name) = match unanchored.unwrap_or(query).rsplit_once("::") {
+            Some((qualifier, name)) => (qualifier, name),
+            None => ("", unanchored?),
+        };
+        let qualifier: Vec<&str> = qualifier.split("::").map(str::trim).filter(|part| !part.is_empty()).collect();
+        Some(Self { qualifier: qualifier.join("::"), name: name.trim().to_string(), anchored: unanchored.is_some() })
+    }
+
+    /// Filter matching the name with `match_mode` inside the scopes given
+    ///
+    /// An exact match compares whole qualified names; other modes match the
+    /// last component as they would a plain name, then its namespace path.
+    pub fn filter(&self, match_mode: MatchMode) -> Result<Filter<ElementColumn>, String> {
+        if self.name.is_empty() {
+            return Err("Qualified name ends in '::' without a symbol name".to_string());
+        }
+        if match_mode == MatchMode::Exact {
+            let qualified = if self.qualifier.is_empty() { self.name.clone() } else { format!("{}::{}", self.qualifier, self.name) };
+            return Ok(self.suffix(ElementColumn::FullyQualifiedName, qualified));
+        }
+        Ok(Filter::and(vec![match_mode.filter(ElementColumn::SymbolName, &self.name)?, self.scope_filter()]))
+    }
+
+    /// Filter matching symbols declared inside the scopes given, whatever their name
+    pub fn scope_filter(&self) -> Filter<ElementColumn> {
+        self.suffix(ElementColumn::NamespacePath, self.qualifier.clone())
+    }
+
+    // Innermost scopes are matched as a '::'-bounded suffix
+    fn suffix(&self, column: ElementColumn, value: String) -> Filter<ElementColumn> {
+        if self.anchored {
+            Filter::eq(column, value)
+        } else {
+            Filter::or(vec![Filter::glob(column, format!("*::{}", escape_glob(&value))), Filter::eq(column, value)])
+        }
+    }
+}
+
+/// Text matching itself in a GLOB pattern, e.g. for `operator[]` or `operator*`
+fn escape_glob(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '[' | '*' | '?' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Text matching itself in a LIKE pattern, e.g. for `my_var` or `100%`
+pub fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Turns free text into an FTS5 MATCH expression
+///
+/// Every word must match; words are quoted so operators and punctuation in
+/// the input can't break the expression, and the last word also matches as a
+/// prefix. Restricting to `columns` (empty means all) uses a column filter.
+/// Returns None when the text contains no words.
+pub fn full_text_query(text: &str, columns: &[TextColumn]) -> Option<String> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .collect();
+    let (last, rest) = words.split_last()?;
+
+    let mut terms: Vec<String> = rest.iter().map(|word| format!("\"{}\"", word)).collect();
+    terms.push(format!("\"{}\"*", last));
+    let expression = terms.join(" ");
+
+    if columns.is_empty() {
+        return Some(expression);
+    }
+    let names: Vec<&str> = columns.iter().map(TextColumn::fts_name).collect();
+    Some(format!("{{{}}} : ({})", names.join(" "), expression))
+}
+
+/// Renders bound parameters for logs, e.g. `?1='draw%', ?2=3`
+pub fn describe_params(params: &[Value]) -> String {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let value = match value {
+                Value::Null => "NULL".to_string(),
+                Value::Integer(n) => n.to_string(),
+                Value::Real(n) => n.to_string(),
+                Value::Text(text) => format!("'{}'", text),
+                Value::Blob(bytes) => format!("<{} bytes>", bytes.len()),
+            };
+            format!("?{}={}", i + 1, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_filters_and_sorts() {
+        let query = CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, "abc".to_string()))
+            .filter(Filter::contains(ElementColumn::SymbolName, "draw"))
+            .filter(Filter::in_list(ElementColumn::SymbolType, [SymbolType::Function, SymbolType::Class]))
+            .order_by_asc(ElementColumn::SymbolName)
+            .order_by(ElementColumn::LineNumber, SortDirection::Descending);
+
+        let (sql, params) = query.to_sql("code_elements", &[ElementColumn::Id, ElementColumn::SymbolName]);
+        assert_eq!(
+            sql,
+            "SELECT id, symbol_name FROM code_elements WHERE index_id = ? AND symbol_name LIKE ? ESCAPE '\\' \
+             AND symbol_type IN (?, ?) ORDER BY symbol_name, line_number DESC, id"
+        );
+        assert_eq!(params.len(), 4);
+        assert_eq!(params[1], Value::Text("%draw%".to_string()));
+        assert_eq!(params[3], Value::Text("class".to_string()));
+    }
+
+    #[test]
+    fn test_render_groups_and_pagination() {
+        let query = SymbolRelationshipQuery::new()
+            .filter(Filter::or(vec![
+                Filter::eq(RelationshipColumn::FromSymbolId, 7i64),
+                Filter::eq(RelationshipColumn::ToSymbolId, 7i64),
+            ]))
+            .filter(Filter::negate(Filter::IsNull(RelationshipColumn::FilePath)))
+            .filter(Filter::in_list::<Value>(RelationshipColumn::RelationshipType, []))
+            .limit(10)
+            .offset(20);
+
+        let (sql, params) = query.to_sql("symbol_relationships", &[RelationshipColumn::Id]);
+        assert_eq!(
+            sql,
+            "SELECT id FROM symbol_relationships WHERE (from_symbol_id = ? OR to_symbol_id = ?) \
+             AND NOT (file_path IS NULL) AND 0 ORDER BY id LIMIT 10 OFFSET 20"
+        );
+        assert_eq!(describe_params(&params), "?1=7, ?2=7");
+
+        let (sql, _) = SymbolRelationshipQuery::new().offset(5).to_sql("t", &[RelationshipColumn::Id]);
+        assert_eq!(sql, "SELECT id FROM t ORDER BY id LIMIT -1 OFFSET 5");
+
+        let (sql, _) = SymbolRelationshipQuery::new().filter(Filter::and(vec![])).to_sql("t", &[RelationshipColumn::Id]);
+        assert_eq!(sql, "SELECT id FROM t WHERE 1 ORDER BY id");
+
+        let (sql, params) = query.to_count_sql("symbol_relationships");
+        assert_eq!(
+            sql,
+            "SELECT COUNT(*) FROM symbol_relationships WHERE (from_symbol_id = ? OR to_symbol_id = ?) \
+             AND NOT (file_path IS NULL) AND 0"
+        );
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn test_full_text_query() {
+        assert_eq!(full_text_query("ring buf", &[]).unwrap(), "\"ring\" \"buf\"*");
+        assert_eq!(full_text_query("  \"-\"  ", &[]), None);
+        assert_eq!(
+            full_text_query("drain OR NOT(x", &[TextColumn::Name, TextColumn::Documentation]).unwrap(),
+            "{symbol_name documentation} : (\"drain\" \"OR\" \"NOT(x\"*)"
+        );
+        assert_eq!(TextColumn::parse("docs"), Some(TextColumn::Documentation));
+        assert_eq!(TextColumn::parse("file_path"), None);
+    }
+
+    #[test]
+    fn test_qualified_name() {
+        assert_eq!(QualifiedName::parse("Vector"), None);
+        let qualified = QualifiedName::parse("engine :: render::Vector").unwrap();
+        assert_eq!((qualified.qualifier.as_str(), qualified.name.as_str(), qualified.anchored), ("engine::render", "Vector", false));
+        assert!(QualifiedName::parse("::main").unwrap().anchored);
+        assert!(QualifiedName::parse("render::").unwrap().filter(MatchMode::Exact).is_err());
+
+        let filter = QualifiedName::parse("render::Vector").unwrap().filter(MatchMode::Exact).unwrap();
+        let (sql, params) = CodeElementQuery::new().filter(filter).to_sql("code_elements", &[ElementColumn::Id]);
+        assert!(sql.contains("(fully_qualified_name GLOB ? OR fully_qualified_name = ?)"));
+        assert_eq!(params, [Value::Text("*::render::Vector".to_string()), Value::Text("render::Vector".to_string())]);
+
+        let filter = QualifiedName::parse("Matrix::operator[]").unwrap().filter(MatchMode::Exact).unwrap();
+        let (_, params) = CodeElementQuery::new().filter(filter).to_sql("code_elements", &[ElementColumn::Id]);
+        assert_eq!(params[0], Value::Text("*::Matrix::operator[[]]".to_string()));
+        assert_eq!(escape_glob("operator*?"), "operator[*][?]");
+
+        let filter = QualifiedName::parse("::render::Vec").unwrap().filter(MatchMode::Prefix).unwrap();
+        let (sql, params) = CodeElementQuery::new().filter(filter).to_sql("code_elements", &[ElementColumn::Id]);
+        assert!(sql.contains("symbol_name LIKE ? ESCAPE") && sql.contains("namespace_path = ?"));
+        assert_eq!(params, [Value::Text("Vec%".to_string()), Value::Text("render".to_string())]);
+    }
+
+    #[test]
+    fn test_query_page() {
+        let first = QueryPage::new(2);
+        let page = first.slice(vec!["a", "b", "c", "d", "e"]).unwrap();
+        assert_eq!((page.items.clone(), page.total_count), (vec!["a", "b"], 5));
+
+        let second = QueryPage::new(2).with_cursor(page.next_cursor.unwrap());
+        assert_eq!(second.start(), Ok(2));
+        let (sql, _) = second.apply(CodeElementQuery::new()).unwrap().to_sql("code_elements", &[ElementColumn::Id]);
+        assert!(sql.ends_with("LIMIT 2 OFFSET 2"));
+
+        let last = QueryPage::new(2).with_offset(4).slice(vec!["a", "b", "c", "d", "e"]).unwrap();
+        assert_eq!(last.items, vec!["e"]);
+        assert!(!last.has_more());
+
+        assert_eq!(QueryPage::new(0).limit, 1);
+        assert_eq!(QueryPage::new(u64::MAX).limit, MAX_PAGE_LIMIT);
+        assert!(QueryPage::new(2).with_cursor("abc").start().is_err());
+    }
+}
@@ -0,0 +1,144 @@
+// Text query syntax
+//
+// A compact filter language over code elements, used by saved queries and the
+// command line, e.g. `type:function,operator file:src/audio/* -name:test*`.
+// Terms are separated by whitespace and all must match. A term is
+// `field:value`, or a bare value that is matched against the symbol name;
+// a leading '-' negates it. Comma-separated values match any of them, and in
+// text fields '*' and '?' are wildcards.
+
+use crate::lib::storage::models::code_element::{AccessModifier, SymbolType};
+use crate::lib::storage::query::{escape_like, CodeElementQuery, ElementColumn, Filter};
+
+/// Field names accepted before ':'
+pub const QUERY_FIELDS: &[&str] = &["name", "type", "file", "scope", "access", "section", "decl"];
+
+/// Parses a filter expression into a query over code elements
+///
+/// The query has no index filter, sort keys or limit; callers add those.
+pub fn parse_element_query(text: &str) -> Result<CodeElementQuery, String> {
+    let mut query = CodeElementQuery::new();
+    let mut terms = 0;
+
+    for term in text.split_whitespace() {
+        let (negated, term) = match term.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, term),
+        };
+        let (field, value) = term.split_once(':').unwrap_or(("name", term));
+        if value.is_empty() {
+            return Err(format!("Missing value for '{}'", field));
+        }
+
+        let filter = term_filter(field, value)?;
+        query = query.filter(if negated { Filter::negate(filter) } else { filter });
+        terms += 1;
+    }
+
+    if terms == 0 {
+        return Err("Query cannot be empty".to_string());
+    }
+
+    Ok(query)
+}
+
+/// Filter of one `field:value` term, without negation
+pub fn term_filter(field: &str, value: &str) -> Result<Filter<ElementColumn>, String> {
+    let values: Vec<&str> = value.split(',').filter(|v| !v.is_empty()).collect();
+    if values.is_empty() {
+        return Err(format!("Missing value for '{}'", field));
+    }
+
+    match field {
+        "name" => Ok(any_text(ElementColumn::SymbolName, &values)),
+        "file" => Ok(any_text(ElementColumn::FilePath, &values)),
+        "scope" => Ok(any_text(ElementColumn::Scope, &values)),
+        "section" => Ok(any_text(ElementColumn::MemorySection, &values)),
+        "type" => {
+            let types = values
+                .iter()
+                .map(|name| {
+                    SymbolType::all()
+                        .iter()
+                        .copied()
+                        .find(|symbol_type| symbol_type.as_str() == *name)
+                        .ok_or_else(|| format!("Unknown symbol type: {}", name))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Filter::in_list(ElementColumn::SymbolType, types))
+        }
+        "access" => {
+            let modifiers = values
+                .iter()
+                .map(|name| {
+                    [AccessModifier::Public, AccessModifier::Protected, AccessModifier::Private]
+                        .into_iter()
+                        .find(|modifier| modifier.as_str() == *name)
+                        .map(|modifier| modifier.as_str().to_string())
+                        .ok_or_else(|| format!("Unknown access modifier: {}", name))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Filter::in_list(ElementColumn::AccessModifier, modifiers))
+        }
+        "decl" => match value {
+            "true" | "yes" => Ok(Filter::eq(ElementColumn::IsDeclaration, true)),
+            "false" | "no" => Ok(Filter::eq(ElementColumn::IsDeclaration, false)),
+            _ => Err(format!("decl must be true or false, got '{}'", value)),
+        },
+        _ => Err(format!("Unknown query field '{}' (expected one of: {})", field, QUERY_FIELDS.join(", "))),
+    }
+}
+
+/// Matches a text column against any of the given values or glob patterns
+fn any_text(column: ElementColumn, values: &[&str]) -> Filter<ElementColumn> {
+    let mut filters: Vec<_> = values.iter().map(|value| text_filter(column, value)).collect();
+    if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        Filter::or(filters)
+    }
+}
+
+/// Exact match, or LIKE when the value has '*' or '?' wildcards; anything else matches literally
+fn text_filter(column: ElementColumn, value: &str) -> Filter<ElementColumn> {
+    if value.contains(['*', '?']) {
+        Filter::like(column, escape_like(value).replace('*', "%").replace('?', "_"))
+    } else {
+        Filter::eq(column, value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::query::describe_params;
+
+    #[test]
+    fn test_parse_element_query() {
+        let query = parse_element_query("type:function,constructor file:src/audio/* -name:test* decl:false").unwrap();
+        let (sql, params) = query.to_sql("code_elements", &[ElementColumn::Id]);
+        assert_eq!(
+            sql,
+            "SELECT id FROM code_elements WHERE symbol_type IN (?, ?) AND file_path LIKE ? ESCAPE '\\' \
+             AND NOT (symbol_name LIKE ? ESCAPE '\\') AND is_declaration = ? ORDER BY id"
+        );
+        assert_eq!(describe_params(&params), "?1='function', ?2='constructor', ?3='src/audio/%', ?4='test%', ?5=0");
+
+        // Bare words match the name; several values match any of them
+        let (sql, params) = parse_element_query("process scope:audio::Engine,audio::Mixer")
+            .unwrap()
+            .to_sql("t", &[ElementColumn::Id]);
+        assert_eq!(sql, "SELECT id FROM t WHERE symbol_name = ? AND (scope = ? OR scope = ?) ORDER BY id");
+        assert_eq!(params.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_element_query("   ").is_err());
+        assert!(parse_element_query("type:gadget").unwrap_err().contains("gadget"));
+        assert!(parse_element_query("colour:red").unwrap_err().contains("Unknown query field"));
+        assert!(parse_element_query("decl:maybe").is_err());
+        assert!(parse_element_query("name:").is_err());
+        assert!(parse_element_query("file:,").is_err());
+    }
+}
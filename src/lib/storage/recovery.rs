@@ -0,0 +1,423 @@
+use chrono::Utc;
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::connection::{ConnectionPool, DatabaseConfig, DatabaseManager};
+use crate::lib::storage::repository::Repository;
+
+/// Maximum number of problems reported by an integrity check
+const MAX_REPORTED_PROBLEMS: usize = 20;
+
+/// Rows copied per table, and the tables that could not be copied, by a salvage
+type Salvaged = (Vec<(String, usize)>, Vec<String>);
+
+/// Result of checking a database file before use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseHealth {
+    /// The database passed its integrity check
+    Healthy,
+    /// There is no database file yet
+    Missing,
+    /// The database failed its integrity check or could not be read
+    Corrupt { problems: Vec<String> },
+}
+
+/// How the server reaches its database
+#[derive(Debug)]
+pub enum ServerStorage {
+    /// A healthy database, read and written through a pool
+    Pooled(ConnectionPool),
+    /// A damaged database, served read-only over one connection
+    Degraded(Repository),
+}
+
+/// How a damaged database was recovered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryStrategy {
+    /// Salvageable rows were copied into a freshly created database
+    Salvage,
+    /// The latest backup replaced the damaged database
+    RestoreBackup,
+    /// Nothing could be saved; an empty database was created and indices must be rebuilt
+    Reset,
+}
+
+/// Outcome of a recovery attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    pub strategy: RecoveryStrategy,
+    /// Where the damaged database was moved; it is kept for inspection
+    pub quarantined_path: PathBuf,
+    /// Tables copied during salvage, with the number of rows recovered
+    pub tables_recovered: Vec<(String, usize)>,
+    /// Tables that could not be read during salvage
+    pub tables_lost: Vec<String>,
+    /// Backup restored, if any
+    pub backup_path: Option<PathBuf>,
+}
+
+/// Detects database corruption and recovers from it
+///
+/// Recovery first salvages what can still be read from the damaged file. If
+/// the file is unreadable as a whole, the latest backup is restored instead,
+/// and without a backup an empty database takes its place.
+pub struct DatabaseRecovery {
+    config: DatabaseConfig,
+}
+
+impl DatabaseHealth {
+    /// Returns true if the database is damaged
+    pub fn is_corrupt(&self) -> bool {
+        matches!(self, DatabaseHealth::Corrupt { .. })
+    }
+}
+
+impl DatabaseRecovery {
+    /// Creates a recovery helper for the configured database
+    pub fn new(config: DatabaseConfig) -> Self {
+        Self { config }
+    }
+
+    /// Runs SQLite's integrity check against the database file
+    pub fn check(&self) -> DatabaseHealth {
+        if self.config.is_in_memory() {
+            return DatabaseHealth::Healthy;
+        }
+        if !self.config.database_path.exists() {
+            return DatabaseHealth::Missing;
+        }
+
+        let problems = DatabaseManager::new(self.config.clone())
+            .map_err(|e| vec![e.to_string()])
+            .and_then(|manager| manager.connect_read_only().map_err(|e| vec![e.to_string()]))
+            .and_then(|connection| integrity_problems(&connection).map_err(|e| vec![e.to_string()]));
+
+        match problems {
+            Ok(problems) if problems.is_empty() => DatabaseHealth::Healthy,
+            Ok(problems) | Err(problems) => DatabaseHealth::Corrupt { problems },
+        }
+    }
+
+    /// Directory holding backups of this database
+    pub fn backup_dir(&self) -> PathBuf {
+        let mut dir = self.config.database_path.clone().into_os_string();
+        dir.push(".backups");
+        PathBuf::from(dir)
+    }
+
+    /// Returns the most recent backup, if any
+    pub fn latest_backup(&self) -> Option<PathBuf> {
+        fs::read_dir(self.backup_dir())
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "db"))
+            .max()
+    }
+
+    /// Writes a consistent copy of the database into the backup directory
+    pub fn create_backup(&self, connection: &Connection) -> Result<PathBuf> {
+        let dir = self.backup_dir();
+        fs::create_dir_all(&dir).map_err(|e| io_error("Failed to create backup directory", e))?;
+
+        // Timestamped names sort chronologically, which latest_backup relies on
+        let path = dir.join(format!("{}.db", Utc::now().format("%Y%m%dT%H%M%S%.3fZ")));
+        connection.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+        Ok(path)
+    }
+
+    /// Moves the damaged database aside and rebuilds it from what can be saved
+    pub fn recover(&self) -> Result<RecoveryReport> {
+        let quarantined_path = self.quarantine()?;
+
+        match self.salvage(&quarantined_path) {
+            Ok((tables_recovered, tables_lost)) => {
+                info!("Salvaged {} tables from {}", tables_recovered.len(), quarantined_path.display());
+                Ok(RecoveryReport {
+                    strategy: RecoveryStrategy::Salvage,
+                    quarantined_path,
+                    tables_recovered,
+                    tables_lost,
+                    backup_path: None,
+                })
+            }
+            Err(salvage_error) => {
+                warn!("Salvage of {} failed: {}", quarantined_path.display(), salvage_error);
+                let backup_path = match self.latest_backup() {
+                    Some(backup_path) => backup_path,
+                    None => {
+                        // Leave an empty, usable database behind rather than nothing at all
+                        let _ = fs::remove_file(&self.config.database_path);
+                        DatabaseManager::new(self.config.clone())
+                            ?
+                            .connect()?;
+                        return Ok(RecoveryReport {
+                            strategy: RecoveryStrategy::Reset,
+                            quarantined_path,
+                            tables_recovered: Vec::new(),
+                            tables_lost: Vec::new(),
+                            backup_path: None,
+                        });
+                    }
+                };
+
+                let _ = fs::remove_file(&self.config.database_path);
+                fs::copy(&backup_path, &self.config.database_path)
+                    .map_err(|e| io_error("Failed to restore backup", e))?;
+                // Bring an older backup up to the current schema
+                DatabaseManager::new(self.config.clone())
+                    ?
+                    .connect()?;
+
+                info!("Restored {} from backup {}", self.config.database_path.display(), backup_path.display());
+                Ok(RecoveryReport {
+                    strategy: RecoveryStrategy::RestoreBackup,
+                    quarantined_path,
+                    tables_recovered: Vec::new(),
+                    tables_lost: Vec::new(),
+                    backup_path: Some(backup_path),
+                })
+            }
+        }
+    }
+
+    /// Opens the database for serving queries, falling back to read-only if it is damaged
+    pub fn open_for_server(&self) -> Result<ServerStorage> {
+        let manager = DatabaseManager::new(self.config.clone())?;
+
+        if let DatabaseHealth::Corrupt { problems } = self.check() {
+            warn!(
+                "Database {} failed its integrity check ({}); serving read-only",
+                self.config.database_path.display(),
+                problems.join("; ")
+            );
+            return Ok(ServerStorage::Degraded(Repository::new(manager.connect_read_only()?)));
+        }
+
+        Ok(ServerStorage::Pooled(ConnectionPool::new(manager)?))
+    }
+
+    /// Renames the damaged database and its WAL/SHM files out of the way
+    fn quarantine(&self) -> Result<PathBuf> {
+        let suffix = format!("corrupt-{}", Utc::now().format("%Y%m%dT%H%M%S"));
+        let target = with_suffix(&self.config.database_path, &suffix);
+
+        fs::rename(&self.config.database_path, &target)
+            .map_err(|e| io_error("Failed to move damaged database aside", e))?;
+        for sidecar in ["-wal", "-shm"] {
+            let source = PathBuf::from(format!("{}{}", self.config.database_path.display(), sidecar));
+            if source.exists() {
+                let _ = fs::rename(&source, PathBuf::from(format!("{}{}", target.display(), sidecar)));
+            }
+        }
+
+        Ok(target)
+    }
+
+    /// Copies every readable row from the damaged file into a new database
+    fn salvage(&self, damaged: &Path) -> Result<Salvaged> {
+        let manager = DatabaseManager::new(self.config.clone())?;
+        let connection = manager.connect()?;
+
+        connection.execute("ATTACH DATABASE ?1 AS salvage", [damaged.to_string_lossy()])?;
+        // Fails for files that are not databases at all
+        connection.query_row("SELECT COUNT(*) FROM salvage.sqlite_master", [], |_| Ok(()))?;
+        connection.execute("PRAGMA foreign_keys = OFF", [])?;
+
+        let tables: Vec<String> = connection
+            .prepare(
+                "SELECT name FROM main.sqlite_master
+                 WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'schema_migrations'",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut recovered = Vec::new();
+        let mut lost = Vec::new();
+        for table in tables {
+            match copy_table(&connection, &table) {
+                Ok(rows) => recovered.push((table, rows)),
+                Err(e) => {
+                    warn!("Could not salvage table {}: {}", table, e);
+                    lost.push(table);
+                }
+            }
+        }
+
+        connection.execute("DETACH DATABASE salvage", [])?;
+        connection.execute("PRAGMA foreign_keys = ON", [])?;
+        remove_orphans(&connection)?;
+
+        Ok((recovered, lost))
+    }
+}
+
+/// Returns the problems reported by PRAGMA integrity_check; empty if the database is sound
+fn integrity_problems(connection: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = connection.prepare(&format!("PRAGMA integrity_check({})", MAX_REPORTED_PROBLEMS))?;
+    let problems = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(problems.into_iter().filter(|p| p != "ok").collect())
+}
+
+/// Copies the columns a table shares between the damaged and the new schema
+fn copy_table(connection: &Connection, table: &str) -> rusqlite::Result<usize> {
+    let main_columns = table_columns(connection, "main", table)?;
+    let salvage_columns = table_columns(connection, "salvage", table)?;
+    let columns: Vec<String> = main_columns
+        .into_iter()
+        .filter(|column| salvage_columns.contains(column))
+        .map(|column| format!("\"{}\"", column))
+        .collect();
+    if columns.is_empty() {
+        return Ok(0);
+    }
+
+    let columns = columns.join(", ");
+    connection.execute(
+        &format!(
+            "INSERT OR IGNORE INTO main.\"{table}\" ({columns}) SELECT {columns} FROM salvage.\"{table}\"",
+            table = table,
+            columns = columns
+        ),
+        [],
+    )
+}
+
+/// Lists the column names of a table in the given schema
+fn table_columns(connection: &Connection, schema: &str, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = connection.prepare(&format!("PRAGMA {}.table_info(\"{}\")", schema, table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+/// Deletes salvaged rows whose parent rows were lost
+fn remove_orphans(connection: &Connection) -> rusqlite::Result<()> {
+    loop {
+        let orphans: Vec<(String, i64)> = connection
+            .prepare("PRAGMA foreign_key_check")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        if orphans.is_empty() {
+            return Ok(());
+        }
+
+        for (table, rowid) in orphans {
+            connection.execute(&format!("DELETE FROM \"{}\" WHERE rowid = ?1", table), [rowid])?;
+        }
+    }
+}
+
+/// Appends ".suffix" to a path
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), suffix))
+}
+
+/// Wraps an I/O error the way the rest of the storage layer reports file failures
+pub(crate) fn io_error(context: &str, error: std::io::Error) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_IOERR),
+        Some(format!("{}: {}", context, error)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_index::CodeIndex;
+    use crate::lib::storage::models::index_tag::IndexTag;
+    use tempfile::tempdir;
+
+    fn populated_database(path: &Path) -> DatabaseConfig {
+        let config = DatabaseConfig::new(path).with_wal_mode(false);
+        let repository = Repository::new(DatabaseManager::new(config.clone()).unwrap().connect().unwrap());
+        let index = repository
+            .create_code_index(CodeIndex::new("engine".to_string(), "/src/engine".to_string()))
+            .unwrap();
+        repository
+            .set_index_tag(&IndexTag::new(index.id, "team".to_string(), "core".to_string()))
+            .unwrap();
+        config
+    }
+
+    #[test]
+    fn test_check_detects_damage() {
+        let dir = tempdir().unwrap();
+        let config = populated_database(&dir.path().join("index.db"));
+        let recovery = DatabaseRecovery::new(config.clone());
+        assert_eq!(recovery.check(), DatabaseHealth::Healthy);
+
+        fs::write(&config.database_path, b"this is not a database at all").unwrap();
+        assert!(recovery.check().is_corrupt());
+
+        // Without a backup the unreadable file is replaced by an empty database
+        let report = recovery.recover().unwrap();
+        assert_eq!(report.strategy, RecoveryStrategy::Reset);
+        assert_eq!(recovery.check(), DatabaseHealth::Healthy);
+
+        let missing = DatabaseRecovery::new(DatabaseConfig::new(dir.path().join("missing.db")));
+        assert_eq!(missing.check(), DatabaseHealth::Missing);
+    }
+
+    #[test]
+    fn test_salvage_keeps_readable_rows() {
+        let dir = tempdir().unwrap();
+        let config = populated_database(&dir.path().join("index.db"));
+        let recovery = DatabaseRecovery::new(config.clone());
+
+        let report = recovery.recover().unwrap();
+        assert_eq!(report.strategy, RecoveryStrategy::Salvage);
+        assert!(report.quarantined_path.exists());
+        assert!(report.tables_lost.is_empty());
+        assert!(report.tables_recovered.contains(&("code_indices".to_string(), 1)));
+        assert!(report.tables_recovered.contains(&("index_tags".to_string(), 1)));
+
+        assert_eq!(recovery.check(), DatabaseHealth::Healthy);
+        let repository = Repository::new(DatabaseManager::new(config).unwrap().connect().unwrap());
+        let index = repository.get_code_index_by_name("engine").unwrap().unwrap();
+        assert_eq!(repository.get_index_tags(&index.id).unwrap()["team"], "core");
+    }
+
+    #[test]
+    fn test_restore_from_backup_when_unreadable() {
+        let dir = tempdir().unwrap();
+        let config = populated_database(&dir.path().join("index.db"));
+        let recovery = DatabaseRecovery::new(config.clone());
+
+        let connection = DatabaseManager::new(config.clone()).unwrap().connect().unwrap();
+        let backup = recovery.create_backup(&connection).unwrap();
+        drop(connection);
+        assert_eq!(recovery.latest_backup(), Some(backup.clone()));
+
+        fs::write(&config.database_path, b"this is not a database at all").unwrap();
+        let report = recovery.recover().unwrap();
+        assert_eq!(report.strategy, RecoveryStrategy::RestoreBackup);
+        assert_eq!(report.backup_path, Some(backup));
+
+        let repository = Repository::new(DatabaseManager::new(config).unwrap().connect().unwrap());
+        assert!(repository.get_code_index_by_name("engine").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_open_for_server_degrades_to_read_only() {
+        let dir = tempdir().unwrap();
+        let config = populated_database(&dir.path().join("index.db"));
+
+        let ServerStorage::Pooled(pool) = DatabaseRecovery::new(config.clone()).open_for_server().unwrap() else {
+            panic!("a healthy database is pooled");
+        };
+        assert!(Repository::new(pool.writer().unwrap()).get_code_index_by_name("engine").unwrap().is_some());
+        drop(pool);
+
+        fs::write(&config.database_path, b"this is not a database at all").unwrap();
+        let storage = DatabaseRecovery::new(config).open_for_server().unwrap();
+        assert!(matches!(storage, ServerStorage::Degraded(_)));
+    }
+}
@@ -1,23 +1,144 @@
-use rusqlite::{Connection, Result, params, Row};
-use chrono::{DateTime, Utc};
+use rusqlite::{Connection, params, OptionalExtension, Row, Transaction, TransactionBehavior};
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
 
+use crate::lib::storage::connection::PooledConnection;
+use crate::lib::storage::disk_space::DiskSpaceGuard;
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::admin_audit::{AuditActor, AuditEntry, AuditOperation};
+use crate::lib::storage::models::build_configuration::BuildConfiguration;
 use crate::lib::storage::models::code_index::{CodeIndex, IndexState};
 use crate::lib::storage::models::code_element::{CodeElement, SymbolType, AccessModifier};
-use crate::lib::storage::models::file_metadata::{FileMetadata, FileProcessingState};
+use crate::lib::storage::models::file_include::{resolve_include, FileInclude};
+use crate::lib::storage::models::file_metadata::{FileDetail, FileMetadata, FileProcessingState};
 use crate::lib::storage::models::symbol_relationships::{SymbolRelationship, RelationshipType, RelationshipQuery};
 use crate::lib::storage::models::mcp_query_session::{McpQuerySession, SessionStatus, SessionQuery};
+use crate::lib::storage::models::index_error::{IndexError, IndexErrorKind};
+use crate::lib::storage::models::index_insights::{IndexInsights, RankedFile, RankedSymbol};
+use crate::lib::storage::models::index_tag::IndexTag;
+use crate::lib::storage::models::index_revision::{IndexRevision, StashedFile, StashedRelationship, StashedSymbol};
+use crate::lib::storage::models::symbol_annotation::{AnnotationKind, SymbolAnnotation};
+use crate::lib::storage::models::path_alias::PathAlias;
+use crate::lib::storage::models::walk_rules::WalkRules;
+use crate::lib::storage::models::parse_settings::ParseSettings;
+use crate::lib::storage::models::directory_depth::{DirectoryDepth, IndexDepth};
+use crate::lib::storage::models::saved_query::SavedQuery;
+use crate::lib::storage::models::slow_query::{SlowQuery, MAX_SLOW_QUERY_ENTRIES};
+use crate::lib::storage::models::symbol_popularity::SymbolPopularity;
+use crate::lib::storage::recovery::io_error;
+use crate::lib::storage::timings::QueryTimings;
+use crate::lib::storage::fuzzy::{self, trigram_query, MAX_FUZZY_CANDIDATES};
+use crate::lib::storage::query::{
+    describe_params, escape_like, full_text_query, CodeElementQuery, ElementColumn, Filter, MatchMode, Page, QueryPage,
+    RelationshipColumn, SymbolRelationshipQuery, TextColumn,
+};
+
+/// zstd level for symbol bodies; favours indexing speed over the last few percent of size
+pub const SYMBOL_BODY_COMPRESSION_LEVEL: i32 = 3;
+
+/// Characters of a signature kept in code_elements
+///
+/// Longer signatures, mostly template instantiations, keep this many there
+/// followed by '…' for searches, listings and overload matching, and are
+/// stored whole, compressed, in symbol_signatures.
+pub const SIGNATURE_INLINE_LIMIT: usize = 200;
+
+/// The form of `signature` code_elements stores; unchanged unless it's over `SIGNATURE_INLINE_LIMIT`
+pub fn inline_signature(signature: &str) -> Cow<'_, str> {
+    match signature.char_indices().nth(SIGNATURE_INLINE_LIMIT) {
+        Some((end, _)) if !is_inline_signature(signature) => Cow::Owned(format!("{}…", &signature[..end])),
+        _ => Cow::Borrowed(signature),
+    }
+}
+
+/// Returns true if `signature` is the start of a longer one, as read back from code_elements
+fn is_inline_signature(signature: &str) -> bool {
+    signature.ends_with('…') && signature.chars().count() == SIGNATURE_INLINE_LIMIT + 1
+}
+
+/// Overloads are told apart by kind, qualified name, signature and declaration-ness
+fn overload_key(element: &CodeElement) -> (SymbolType, String, Option<String>, bool) {
+    let signature = element.signature.as_deref().map(|signature| inline_signature(signature).into_owned());
+    (element.symbol_type, element.fully_qualified_name(), signature, element.is_declaration)
+}
+
+/// Compresses a body or signature for symbol_bodies or symbol_signatures
+fn compress(text: &str) -> Result<Vec<u8>> {
+    Ok(zstd::stream::encode_all(text.as_bytes(), SYMBOL_BODY_COMPRESSION_LEVEL).map_err(|e| io_error("Failed to compress symbol text", e))?)
+}
+
+/// Reads back the `what` of a symbol from symbol_bodies or symbol_signatures
+fn decompress(compressed: &[u8], what: &str, symbol_id: i64) -> Result<String> {
+    let text = zstd::stream::decode_all(compressed)
+        .map_err(|e| StorageError::Corruption(format!("Unreadable {} of symbol {}: {}", what.to_lowercase(), symbol_id, e)))?;
+    String::from_utf8(text).map_err(|_| StorageError::Corruption(format!("{} of symbol {} is not UTF-8", what, symbol_id)))
+}
 
 /// Repository providing CRUD operations for all storage models
+#[derive(Debug)]
 pub struct Repository {
-    connection: Connection,
+    connection: PooledConnection,
+    /// Queries slower than this are written to the slow query log (None = disabled)
+    slow_query_threshold: Option<Duration>,
+    /// Administrative operations are recorded in the audit trail as this actor (None = not audited)
+    audit_actor: Option<AuditActor>,
+    /// Timed queries are counted here by operation (None = not counted)
+    query_timings: Option<QueryTimings>,
 }
 
 impl Repository {
-    /// Creates a new repository with the given database connection
-    pub fn new(connection: Connection) -> Self {
-        Self { connection }
+    /// Creates a new repository with the given database connection, pooled or not
+    pub fn new(connection: impl Into<PooledConnection>) -> Self {
+        Self {
+            connection: connection.into(),
+            slow_query_threshold: None,
+            audit_actor: None,
+            query_timings: None,
+        }
+    }
+
+    /// Logs queries that take longer than `threshold`
+    pub fn with_slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Counts how long each timed query takes in `timings`, which other repositories may share
+    pub fn with_query_timings(mut self, timings: QueryTimings) -> Self {
+        self.query_timings = Some(timings);
+        self
+    }
+
+    /// Records index creation, updates, deletion and tag changes as `actor`
+    pub fn with_audit_actor(mut self, actor: AuditActor) -> Self {
+        self.audit_actor = Some(actor);
+        self
+    }
+
+    /// A repository on `connection` that logs slow queries, counts timings and audits like this one
+    pub fn on_connection(&self, connection: impl Into<PooledConnection>) -> Self {
+        Self {
+            connection: connection.into(),
+            slow_query_threshold: self.slow_query_threshold,
+            audit_actor: self.audit_actor.clone(),
+            query_timings: self.query_timings.clone(),
+        }
+    }
+
+    /// Changes the actor recorded for later operations, e.g. when an MCP client connects
+    pub fn set_audit_actor(&mut self, actor: AuditActor) {
+        self.audit_actor = Some(actor);
+    }
+
+    /// Returns the actor administrative operations are recorded as
+    pub fn audit_actor(&self) -> Option<&AuditActor> {
+        self.audit_actor.as_ref()
     }
 
     /// Returns a reference to the underlying connection
@@ -27,14 +148,14 @@ impl Repository {
 
     /// Consumes the repository and returns the connection
     pub fn into_connection(self) -> Connection {
-        self.connection
+        self.connection.into_inner()
     }
 
     // === Code Index CRUD Operations ===
 
     /// Creates a new code index
-    pub fn create_code_index(&self, mut index: CodeIndex) -> Result<CodeIndex> {
-        index.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
+    pub fn create_code_index(&self, index: CodeIndex) -> Result<CodeIndex> {
+        index.validate().map_err(StorageError::Validation)?;
         
         self.connection.execute(
             r#"
@@ -55,7 +176,8 @@ impl Repository {
                 "creating"
             ],
         )?;
-        
+
+        self.audit(AuditOperation::CreateIndex, Some((&index.id, &index.name)), json!({"base_path": index.base_path}))?;
         Ok(index)
     }
 
@@ -107,7 +229,7 @@ impl Repository {
 
     /// Updates a code index
     pub fn update_code_index(&self, index: &CodeIndex) -> Result<()> {
-        index.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
+        index.validate().map_err(StorageError::Validation)?;
         
         let rows_affected = self.connection.execute(
             r#"
@@ -128,60 +250,341 @@ impl Repository {
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("Code index", index.id));
         }
-        
+
+        self.audit(
+            AuditOperation::UpdateIndex,
+            Some((&index.id, &index.name)),
+            json!({"base_path": index.base_path, "index_version": index.index_version}),
+        )?;
         Ok(())
     }
 
     /// Updates the state of a code index
     pub fn update_code_index_state(&self, id: &Uuid, state: IndexState) -> Result<()> {
-        let state_str = match state {
-            IndexState::Creating => "creating",
-            IndexState::Active => "active",
-            IndexState::Updating => "updating",
-            IndexState::Archived => "archived",
-            IndexState::Failed => "failed",
-        };
-        
         let rows_affected = self.connection.execute(
             "UPDATE code_indices SET state = ?2, updated_at = ?3 WHERE id = ?1",
-            params![id.to_string(), state_str, Utc::now().to_rfc3339()],
+            params![id.to_string(), state.as_str(), Utc::now().to_rfc3339()],
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("Code index", id));
+        }
+
+        // Other transitions are indexing progress rather than administration
+        if state == IndexState::Archived {
+            self.audit_index(AuditOperation::ArchiveIndex, id, json!({}))?;
         }
-        
         Ok(())
     }
 
+    /// Returns the lifecycle state of a code index
+    pub fn get_code_index_state(&self, id: &Uuid) -> Result<Option<IndexState>> {
+        let mut stmt = self.connection.prepare("SELECT state FROM code_indices WHERE id = ?1")?;
+        let mut rows = stmt.query([id.to_string()])?;
+
+        let state_str: String = match rows.next()? {
+            Some(row) => row.get(0)?,
+            None => return Ok(None),
+        };
+
+        let state = match state_str.as_str() {
+            "creating" => IndexState::Creating,
+            "active" => IndexState::Active,
+            "updating" => IndexState::Updating,
+            "archived" => IndexState::Archived,
+            "failed" => IndexState::Failed,
+            _ => return Err(StorageError::Corruption(format!("Invalid index state: {}", state_str))),
+        };
+
+        Ok(Some(state))
+    }
+
+    /// How much of an index has been processed, by file state
+    pub fn get_index_coverage(&self, id: &Uuid) -> Result<IndexCoverage> {
+        let state = self.get_code_index_state(id)?.ok_or_else(|| StorageError::not_found("Code index", id))?;
+        let (indexed_files, pending_files, failed_files) = self.connection.query_row(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE processing_state = 'indexed'),
+                COUNT(*) FILTER (WHERE processing_state IN ('pending', 'processing')),
+                COUNT(*) FILTER (WHERE processing_state = 'error')
+            FROM file_metadata WHERE index_id = ?1
+            "#,
+            [id.to_string()],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)? as u64)),
+        )?;
+
+        Ok(IndexCoverage { state, indexed_files, pending_files, failed_files })
+    }
+
     /// Deletes a code index and all related data
     pub fn delete_code_index(&self, id: &Uuid) -> Result<()> {
+        let name = self.audit_actor.as_ref().map(|_| self.index_name(id)).transpose()?.flatten();
         let rows_affected = self.connection.execute(
             "DELETE FROM code_indices WHERE id = ?1",
             [id.to_string()],
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("Code index", id));
         }
-        
+
+        self.audit(AuditOperation::DeleteIndex, name.as_deref().map(|name| (id, name)), json!({"index_id": id.to_string()}))?;
+        Ok(())
+    }
+
+    /// Reads everything stored for an index besides the index row, with the bodies of its symbols
+    pub fn get_index_contents(&self, index_id: &Uuid) -> Result<IndexContents> {
+        let mut elements = self.query_code_elements(
+            &CodeElementQuery::new()
+                .filter(Filter::eq(ElementColumn::IndexId, index_id.to_string()))
+                .order_by_asc(ElementColumn::Id),
+        )?;
+
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT t.symbol_id, t.tag FROM symbol_tags t
+            JOIN code_elements e ON e.id = t.symbol_id
+            WHERE e.index_id = ?1
+            ORDER BY t.symbol_id, t.tag
+            "#
+        )?;
+        let symbol_tags = stmt.query_map([index_id.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(i64, String)>, _>>()?;
+
+        let configurations = self.list_build_configurations(index_id)?;
+        let mut configuration_exclusions = Vec::new();
+        for configuration_id in configurations.iter().filter_map(|configuration| configuration.id) {
+            for symbol_id in self.list_configuration_exclusions(configuration_id)? {
+                configuration_exclusions.push((configuration_id, symbol_id));
+            }
+        }
+
+        let mut stmt = self.connection.prepare(
+            "SELECT b.symbol_id FROM symbol_bodies b JOIN code_elements e ON e.id = b.symbol_id WHERE e.index_id = ?1"
+        )?;
+        let body_ids = stmt.query_map([index_id.to_string()], |row| row.get(0))?
+            .collect::<Result<BTreeSet<i64>, _>>()?;
+        let mut stmt = self.connection.prepare(
+            "SELECT s.symbol_id FROM symbol_signatures s JOIN code_elements e ON e.id = s.symbol_id WHERE e.index_id = ?1"
+        )?;
+        let signature_ids = stmt.query_map([index_id.to_string()], |row| row.get(0))?
+            .collect::<Result<BTreeSet<i64>, _>>()?;
+        for element in &mut elements {
+            if let Some(id) = element.id.filter(|id| body_ids.contains(id)) {
+                element.body = self.get_symbol_body(id)?;
+            }
+            if let Some(id) = element.id.filter(|id| signature_ids.contains(id)) {
+                element.signature = self.get_symbol_signature(id)?;
+            }
+        }
+
+        Ok(IndexContents {
+            tags: self.get_index_tags(index_id)?,
+            files: self.list_file_metadata(index_id)?,
+            elements,
+            relationships: self.list_index_relationships(index_id)?,
+            symbol_tags,
+            annotations: self.list_annotations(index_id, None, None)?,
+            configurations,
+            configuration_exclusions,
+            walk_rules: self.get_walk_rules(index_id)?,
+        })
+    }
+
+    /// Creates an index from previously exported contents, in one transaction
+    ///
+    /// Elements, relationships and configurations keep the ids of the
+    /// database they came from; they are stored under the new index and
+    /// everything referring to them is rewritten to the ids they receive
+    /// here. Totals and popularity counts are recomputed and the index is
+    /// left active.
+    pub fn restore_index(&self, index: CodeIndex, contents: IndexContents) -> Result<CodeIndex> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let index = self.create_code_index(index)?;
+        for (key, value) in &contents.tags {
+            self.set_index_tag(&IndexTag::new(index.id, key.clone(), value.clone()))?;
+        }
+
+        for mut metadata in contents.files {
+            metadata.id = None;
+            metadata.index_id = index.id;
+            let metadata = self.create_file_metadata(metadata)?;
+            self.update_file_metadata(&metadata)?;
+        }
+
+        let original_ids: Vec<Option<i64>> = contents.elements.iter().map(|element| element.id).collect();
+        let elements = contents
+            .elements
+            .into_iter()
+            .map(|mut element| {
+                element.id = None;
+                element.index_id = index.id;
+                element
+            })
+            .collect();
+        let ids: HashMap<i64, i64> = original_ids
+            .into_iter()
+            .zip(self.insert_code_elements(elements)?)
+            .filter_map(|(original, created)| Some((original?, created.id?)))
+            .collect();
+        let remap = |id: i64| {
+            ids.get(&id)
+                .copied()
+                .ok_or_else(|| StorageError::Validation(format!("Archived contents refer to unknown symbol {}", id)))
+        };
+
+        let relationships = contents
+            .relationships
+            .into_iter()
+            .map(|mut relationship| {
+                relationship.id = None;
+                relationship.from_symbol_id = remap(relationship.from_symbol_id)?;
+                relationship.to_symbol_id = remap(relationship.to_symbol_id)?;
+                Ok(relationship)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.insert_symbol_relationships(relationships)?;
+
+        for (symbol_id, tag) in &contents.symbol_tags {
+            self.add_symbol_tag(remap(*symbol_id)?, tag)?;
+        }
+        for mut annotation in contents.annotations {
+            annotation.id = None;
+            annotation.index_id = index.id;
+            self.create_annotation(annotation)?;
+        }
+
+        let mut configuration_ids = HashMap::new();
+        for mut configuration in contents.configurations {
+            let original = configuration.id.take();
+            configuration.index_id = index.id;
+            let saved = self.save_build_configuration(configuration)?;
+            if let (Some(original), Some(saved)) = (original, saved.id) {
+                configuration_ids.insert(original, saved);
+            }
+        }
+        let mut stmt = self.connection.prepare(
+            "INSERT OR IGNORE INTO configuration_excluded_symbols (configuration_id, symbol_id) VALUES (?1, ?2)"
+        )?;
+        for (configuration_id, symbol_id) in &contents.configuration_exclusions {
+            let configuration_id = configuration_ids.get(configuration_id).ok_or_else(|| {
+                StorageError::Validation(format!("Archived contents refer to unknown configuration {}", configuration_id))
+            })?;
+            stmt.execute(params![configuration_id, remap(*symbol_id)?])?;
+        }
+        drop(stmt);
+
+        if let Some(mut rules) = contents.walk_rules {
+            rules.index_id = index.id;
+            self.save_walk_rules(&rules)?;
+        }
+
+        self.refresh_symbol_popularity(&index.id)?;
+        self.update_code_index_state(&index.id, IndexState::Active)?;
+        let index = self.get_code_index(&index.id)?.ok_or_else(|| StorageError::not_found("Code index", index.id))?;
+        transaction.commit()?;
+        Ok(index)
+    }
+
+    // === Index Tag Operations ===
+
+    /// Sets a tag on a code index, replacing any existing value for the key
+    pub fn set_index_tag(&self, tag: &IndexTag) -> Result<()> {
+        tag.validate().map_err(StorageError::Validation)?;
+
+        self.connection.execute(
+            r#"
+            INSERT INTO index_tags (index_id, tag_key, tag_value, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(index_id, tag_key) DO UPDATE SET
+                tag_value = excluded.tag_value, updated_at = excluded.updated_at
+            "#,
+            params![
+                tag.index_id.to_string(),
+                tag.key,
+                tag.value,
+                tag.updated_at.to_rfc3339()
+            ],
+        )?;
+
+        self.audit_index(AuditOperation::SetTag, &tag.index_id, json!({"key": tag.key, "value": tag.value}))?;
         Ok(())
     }
 
+    /// Removes a tag from a code index, returning whether it existed
+    pub fn remove_index_tag(&self, index_id: &Uuid, key: &str) -> Result<bool> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM index_tags WHERE index_id = ?1 AND tag_key = ?2",
+            params![index_id.to_string(), key],
+        )?;
+
+        if rows_affected > 0 {
+            self.audit_index(AuditOperation::RemoveTag, index_id, json!({"key": key}))?;
+        }
+        Ok(rows_affected > 0)
+    }
+
+    /// Returns all tags of a code index keyed by tag key
+    pub fn get_index_tags(&self, index_id: &Uuid) -> Result<BTreeMap<String, String>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT tag_key, tag_value FROM index_tags WHERE index_id = ?1 ORDER BY tag_key"
+        )?;
+
+        let tags = stmt.query_map([index_id.to_string()], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        Ok(tags)
+    }
+
+    /// Lists code indices carrying every given tag key/value pair
+    pub fn list_code_indices_by_tags(&self, tags: &BTreeMap<String, String>) -> Result<Vec<CodeIndex>> {
+        let started = Instant::now();
+        let mut query = String::from(
+            "SELECT id, name, base_path, created_at, updated_at, total_files, total_symbols, index_version, state FROM code_indices ci WHERE 1=1"
+        );
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        for (key, value) in tags {
+            query.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM index_tags t WHERE t.index_id = ci.id AND t.tag_key = ?{} AND t.tag_value = ?{})",
+                params.len() + 1,
+                params.len() + 2
+            ));
+            params.push(Box::new(key.clone()));
+            params.push(Box::new(value.clone()));
+        }
+
+        query.push_str(" ORDER BY name");
+
+        let mut stmt = self.connection.prepare(&query)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let indices = stmt.query_map(&param_refs[..], |row| {
+            self.row_to_code_index(row)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        self.record_if_slow("list_code_indices_by_tags", &query, || format!("{:?}", tags), started, indices.len());
+        Ok(indices)
+    }
+
     // === File Metadata CRUD Operations ===
 
     /// Creates a new file metadata entry
     pub fn create_file_metadata(&self, mut metadata: FileMetadata) -> Result<FileMetadata> {
-        metadata.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
+        metadata.validate().map_err(StorageError::Validation)?;
         
         self.connection.execute(
             r#"
             INSERT INTO file_metadata (
                 index_id, file_path, file_hash, last_modified, 
-                size_bytes, symbol_count, indexed_at, processing_state
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                size_bytes, symbol_count, indexed_at, processing_state, detail
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
             "#,
             params![
                 metadata.index_id.to_string(),
@@ -191,7 +594,8 @@ impl Repository {
                 metadata.size_bytes,
                 metadata.symbol_count,
                 metadata.indexed_at.to_rfc3339(),
-                "pending"
+                "pending",
+                metadata.detail.as_str()
             ],
         )?;
         
@@ -204,13 +608,13 @@ impl Repository {
         let mut stmt = self.connection.prepare(
             r#"
             SELECT id, index_id, file_path, file_hash, last_modified, 
-                   size_bytes, symbol_count, indexed_at, processing_state 
+                   size_bytes, symbol_count, indexed_at, processing_state, detail 
             FROM file_metadata WHERE id = ?1
             "#
         )?;
         
         let mut rows = stmt.query_map([id], |row| {
-            Ok(self.row_to_file_metadata(row)?)
+            self.row_to_file_metadata(row)
         })?;
         
         match rows.next() {
@@ -224,13 +628,13 @@ impl Repository {
         let mut stmt = self.connection.prepare(
             r#"
             SELECT id, index_id, file_path, file_hash, last_modified, 
-                   size_bytes, symbol_count, indexed_at, processing_state 
+                   size_bytes, symbol_count, indexed_at, processing_state, detail 
             FROM file_metadata WHERE index_id = ?1 AND file_path = ?2
             "#
         )?;
         
         let mut rows = stmt.query_map(params![index_id.to_string(), file_path], |row| {
-            Ok(self.row_to_file_metadata(row)?)
+            self.row_to_file_metadata(row)
         })?;
         
         match rows.next() {
@@ -239,35 +643,51 @@ impl Repository {
         }
     }
 
+    /// Returns true if a file of the index has been fully processed
+    pub fn is_file_indexed(&self, index_id: &Uuid, file_path: &str) -> Result<bool> {
+        Ok(self.connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM file_metadata WHERE index_id = ?1 AND file_path = ?2 AND processing_state = 'indexed')",
+            params![index_id.to_string(), file_path],
+            |row| row.get(0),
+        )?)
+    }
+
     /// Lists file metadata for an index
     pub fn list_file_metadata(&self, index_id: &Uuid) -> Result<Vec<FileMetadata>> {
-        let mut stmt = self.connection.prepare(
-            r#"
+        let started = Instant::now();
+        let sql = r#"
             SELECT id, index_id, file_path, file_hash, last_modified, 
-                   size_bytes, symbol_count, indexed_at, processing_state 
+                   size_bytes, symbol_count, indexed_at, processing_state, detail 
             FROM file_metadata WHERE index_id = ?1 ORDER BY file_path
-            "#
-        )?;
+            "#;
+        let mut stmt = self.connection.prepare(sql)?;
         
         let metadata_list = stmt.query_map([index_id.to_string()], |row| {
-            Ok(self.row_to_file_metadata(row)?)
+            self.row_to_file_metadata(row)
         })?
         .collect::<Result<Vec<_>, _>>()?;
         
+        self.record_if_slow(
+            "list_file_metadata",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id)]),
+            started,
+            metadata_list.len(),
+        );
         Ok(metadata_list)
     }
 
-    /// Updates file metadata
+    /// Updates file metadata, marking the file indexed and clearing its error
     pub fn update_file_metadata(&self, metadata: &FileMetadata) -> Result<()> {
-        metadata.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
+        metadata.validate().map_err(StorageError::Validation)?;
         
-        let id = metadata.id.ok_or(rusqlite::Error::InvalidColumnName("File metadata ID is required".to_string()))?;
+        let id = metadata.id.ok_or_else(|| StorageError::Validation("File metadata ID is required".to_string()))?;
         
         let rows_affected = self.connection.execute(
             r#"
             UPDATE file_metadata SET 
                 file_hash = ?2, last_modified = ?3, size_bytes = ?4,
-                symbol_count = ?5, indexed_at = ?6, processing_state = ?7
+                symbol_count = ?5, indexed_at = ?6, processing_state = ?7, detail = ?8
             WHERE id = ?1
             "#,
             params![
@@ -277,13 +697,15 @@ impl Repository {
                 metadata.size_bytes,
                 metadata.symbol_count,
                 metadata.indexed_at.to_rfc3339(),
-                "indexed"
+                "indexed",
+                metadata.detail.as_str()
             ],
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("File metadata", id));
         }
+        self.clear_index_error(&metadata.index_id, &metadata.file_path)?;
         
         Ok(())
     }
@@ -303,7 +725,7 @@ impl Repository {
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("File metadata", id));
         }
         
         Ok(())
@@ -317,7 +739,7 @@ impl Repository {
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("File metadata", id));
         }
         
         Ok(())
@@ -326,16 +748,99 @@ impl Repository {
     // === Code Element CRUD Operations ===
 
     /// Creates a new code element
-    pub fn create_code_element(&self, mut element: CodeElement) -> Result<CodeElement> {
-        element.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
-        
-        self.connection.execute(
+    pub fn create_code_element(&self, element: CodeElement) -> Result<CodeElement> {
+        let mut created = self.insert_code_elements(vec![element])?;
+        Ok(created.remove(0))
+    }
+
+    /// Creates many code elements with one prepared statement in one transaction
+    ///
+    /// Every element is validated before anything is written; if any insert
+    /// fails none of the elements are stored.
+    pub fn create_code_elements_batch(&self, elements: Vec<CodeElement>) -> Result<Vec<CodeElement>> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let created = self.insert_code_elements(elements)?;
+        transaction.commit()?;
+        Ok(created)
+    }
+
+    /// Validates then inserts elements through one cached statement, inside whatever transaction is open
+    fn insert_code_elements(&self, mut elements: Vec<CodeElement>) -> Result<Vec<CodeElement>> {
+        for element in &elements {
+            element.validate().map_err(StorageError::Validation)?;
+        }
+
+        let mut stmt = self.connection.prepare_cached(
             r#"
             INSERT INTO code_elements (
                 index_id, symbol_name, symbol_type, file_path, line_number,
                 column_number, definition_hash, scope, access_modifier, 
-                is_declaration, signature
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                is_declaration, signature, memory_section, documentation,
+                fully_qualified_name, namespace_path, usr
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+            "#,
+        )?;
+        for element in &mut elements {
+            stmt.execute(params![
+                element.index_id.to_string(),
+                element.symbol_name,
+                element.symbol_type.as_str(),
+                element.file_path,
+                element.line_number,
+                element.column_number,
+                element.definition_hash,
+                element.scope,
+                element.access_modifier.map(|a| a.as_str()),
+                element.is_declaration,
+                element.signature.as_deref().map(inline_signature),
+                element.memory_section,
+                element.documentation,
+                element.fully_qualified_name(),
+                element.namespace_path(),
+                element.usr
+            ])?;
+            let id = self.connection.last_insert_rowid();
+            if let Some(body) = &element.body {
+                self.store_symbol_body(id, body)?;
+            }
+            if element.signature.as_deref().is_some_and(|signature| inline_signature(signature) != signature) {
+                self.store_symbol_signature(id, element.signature.as_deref())?;
+            }
+            element.id = Some(id);
+        }
+        Ok(elements)
+    }
+
+    /// Inserts a code element, or updates the existing row at the same location
+    ///
+    /// Elements are identified by (index, file, line, column, name, kind); the
+    /// hash, scope, access, declaration flag, signature, section, documentation and USR are overwritten. Use
+    /// this when re-indexing a file so a skipped or partial delete can't leave
+    /// duplicate symbols behind.
+    pub fn create_or_update_code_element(&self, mut element: CodeElement) -> Result<CodeElement> {
+        element.validate().map_err(StorageError::Validation)?;
+
+        let id: i64 = self.connection.query_row(
+            r#"
+            INSERT INTO code_elements (
+                index_id, symbol_name, symbol_type, file_path, line_number,
+                column_number, definition_hash, scope, access_modifier,
+                is_declaration, signature, memory_section, documentation,
+                fully_qualified_name, namespace_path, usr
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+            ON CONFLICT(index_id, file_path, line_number, column_number, symbol_name, symbol_type)
+            DO UPDATE SET
+                definition_hash = excluded.definition_hash,
+                scope = excluded.scope,
+                fully_qualified_name = excluded.fully_qualified_name,
+                namespace_path = excluded.namespace_path,
+                access_modifier = excluded.access_modifier,
+                is_declaration = excluded.is_declaration,
+                signature = excluded.signature,
+                memory_section = excluded.memory_section,
+                documentation = excluded.documentation,
+                usr = excluded.usr
+            RETURNING id
             "#,
             params![
                 element.index_id.to_string(),
@@ -348,11 +853,21 @@ impl Repository {
                 element.scope,
                 element.access_modifier.map(|a| a.as_str()),
                 element.is_declaration,
-                element.signature
+                element.signature.as_deref().map(inline_signature),
+                element.memory_section,
+                element.documentation,
+                element.fully_qualified_name(),
+                element.namespace_path(),
+                element.usr
             ],
+            |row| row.get(0),
         )?;
-        
-        element.id = Some(self.connection.last_insert_rowid());
+
+        if let Some(body) = &element.body {
+            self.store_symbol_body(id, body)?;
+        }
+        self.store_symbol_signature(id, element.signature.as_deref())?;
+        element.id = Some(id);
         Ok(element)
     }
 
@@ -362,7 +877,7 @@ impl Repository {
             r#"
             SELECT id, index_id, symbol_name, symbol_type, file_path, line_number,
                    column_number, definition_hash, scope, access_modifier, 
-                   is_declaration, signature
+                   is_declaration, signature, memory_section, documentation, usr
             FROM code_elements WHERE id = ?1
             "#
         )?;
@@ -377,83 +892,291 @@ impl Repository {
         }
     }
 
-    /// Searches for code elements by symbol name pattern
-    pub fn search_code_elements(&self, index_id: &Uuid, name_pattern: &str, symbol_types: Option<&[SymbolType]>) -> Result<Vec<CodeElement>> {
-        let mut query = String::from(
-            r#"
-            SELECT id, index_id, symbol_name, symbol_type, file_path, line_number,
-                   column_number, definition_hash, scope, access_modifier, 
-                   is_declaration, signature
-            FROM code_elements 
-            WHERE index_id = ?1 AND symbol_name LIKE ?2
-            "#
-        );
-        
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![
-            Box::new(index_id.to_string()),
-            Box::new(format!("%{}%", name_pattern)),
-        ];
-        
+    /// Searches for code elements by symbol name pattern, one page at a time
+    ///
+    /// See [`MatchMode`] for how `name_pattern` is matched; an invalid regex
+    /// or cursor is a validation error. Fuzzy matches come closest first
+    /// (see [`fuzzy::rank`]), other matches by name and file.
+    pub fn search_code_elements(
+        &self,
+        index_id: &Uuid,
+        name_pattern: &str,
+        match_mode: MatchMode,
+        symbol_types: Option<&[SymbolType]>,
+        page: &QueryPage,
+    ) -> Result<Page<CodeElement>> {
+        let mut query = CodeElementQuery::new().filter(Filter::eq(ElementColumn::IndexId, index_id.to_string()));
+
         if let Some(types) = symbol_types {
             if !types.is_empty() {
-                query.push_str(" AND symbol_type IN (");
-                for (i, symbol_type) in types.iter().enumerate() {
-                    if i > 0 {
-                        query.push_str(", ");
-                    }
-                    query.push_str(&format!("?{}", params.len() + 1));
-                    params.push(Box::new(symbol_type.as_str().to_string()));
-                }
-                query.push(')');
+                query = query.filter(Filter::in_list(ElementColumn::SymbolType, types.iter().copied()));
             }
         }
+
+        let name_filter = match match_mode {
+            MatchMode::Fuzzy => Filter::in_list(ElementColumn::Id, self.fuzzy_candidates(name_pattern, &query, MAX_FUZZY_CANDIDATES)?),
+            _ => match_mode.filter(ElementColumn::SymbolName, name_pattern).map_err(StorageError::Validation)?,
+        };
+        let query = query.filter(name_filter);
+
+        if match_mode == MatchMode::Fuzzy {
+            let elements = self.select_code_elements("search_code_elements", &query)?;
+            let ranked = fuzzy::rank(name_pattern, elements).into_iter().map(|candidate| candidate.element).collect();
+            return page.slice(ranked).map_err(StorageError::Validation);
+        }
+
+        let query = query
+            .order_by_asc(ElementColumn::SymbolName)
+            .order_by_asc(ElementColumn::FilePath);
+
+        self.select_code_elements_page("search_code_elements", &query, page)
+    }
+
+    /// Ids of up to `limit` symbols in `within` whose names share the most trigrams with `pattern`
+    ///
+    /// These are candidates for [`fuzzy::rank`] to score. The filters of
+    /// `within` apply before the limit, so symbols of other types or files
+    /// don't crowd out the ones asked for. Patterns under three characters
+    /// have no trigrams and take names containing them instead, shortest first.
+    pub fn fuzzy_candidates(&self, pattern: &str, within: &CodeElementQuery, limit: usize) -> Result<Vec<i64>> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(StorageError::Validation("Fuzzy query cannot be empty".to_string()));
+        }
+        let started = Instant::now();
+        let (scope, scope_params) = within.to_sql("code_elements", &[ElementColumn::Id]);
+        let (sql, needle) = match trigram_query(pattern) {
+            Some(expression) => (
+                format!(
+                    r#"
+                    SELECT e.id FROM code_elements_trigrams
+                    JOIN code_elements e ON e.id = code_elements_trigrams.rowid
+                    WHERE code_elements_trigrams MATCH ? AND e.id IN ({})
+                    ORDER BY code_elements_trigrams.rank, e.id LIMIT ?
+                    "#,
+                    scope
+                ),
+                expression,
+            ),
+            None => (
+                format!(
+                    r#"
+                    SELECT id FROM code_elements
+                    WHERE symbol_name LIKE ? ESCAPE '\' AND id IN ({})
+                    ORDER BY length(symbol_name), id LIMIT ?
+                    "#,
+                    scope
+                ),
+                format!("%{}%", escape_like(pattern)),
+            ),
+        };
+
+        let mut values = vec![rusqlite::types::Value::Text(needle)];
+        values.extend(scope_params);
+        values.push(rusqlite::types::Value::Integer(limit as i64));
+        let mut stmt = self.connection.prepare(&sql)?;
+        let ids = stmt
+            .query_map(rusqlite::params_from_iter(values.iter()), |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        self.record_if_slow("fuzzy_candidates", &sql, || describe_params(&values), started, ids.len());
+        Ok(ids)
+    }
+
+    /// Searches symbol names, signatures, scopes and documentation with FTS5
+    ///
+    /// `text` is free text (see [`full_text_query`]) unless `raw` is set, in
+    /// which case it is passed to MATCH as an FTS5 expression. Hits are ranked
+    /// by BM25 with names weighted above signatures, scopes and documentation,
+    /// and equally relevant hits by reference count. Returns one page of hits.
+    pub fn full_text_search(
+        &self,
+        index_id: &Uuid,
+        text: &str,
+        columns: &[TextColumn],
+        symbol_types: Option<&[SymbolType]>,
+        raw: bool,
+        page: &QueryPage,
+    ) -> Result<Page<TextSearchHit>> {
+        let expression = if raw {
+            text.trim().to_string()
+        } else {
+            full_text_query(text, columns).unwrap_or_default()
+        };
+        if expression.is_empty() {
+            return Err(StorageError::Validation("Search text cannot be empty".to_string()));
+        }
+
+        let start = page.start().map_err(StorageError::Validation)?;
+        let started = Instant::now();
+        let weights: Vec<String> = TextColumn::ALL.iter().map(|column| format!("{:.1}", column.weight())).collect();
+        let mut matches = String::from(
+            r#"
+            FROM code_elements_fts
+            JOIN code_elements e ON e.id = code_elements_fts.rowid
+            WHERE code_elements_fts MATCH ?1 AND e.index_id = ?2
+            "#,
+        );
+        let mut values = vec![
+            rusqlite::types::Value::Text(expression.clone()),
+            rusqlite::types::Value::Text(index_id.to_string()),
+        ];
+        if let Some(types) = symbol_types.filter(|types| !types.is_empty()) {
+            let placeholders: Vec<String> = (0..types.len()).map(|i| format!("?{}", i + 3)).collect();
+            matches.push_str(&format!(" AND e.symbol_type IN ({})", placeholders.join(", ")));
+            values.extend(types.iter().map(|t| rusqlite::types::Value::Text(t.as_str().to_string())));
+        }
+        let sql = format!(
+            r#"
+            SELECT e.id, e.index_id, e.symbol_name, e.symbol_type, e.file_path, e.line_number,
+                   e.column_number, e.definition_hash, e.scope, e.access_modifier,
+                   e.is_declaration, e.signature, e.memory_section, e.documentation, e.usr,
+                   -bm25(code_elements_fts, {}) AS score,
+                   snippet(code_elements_fts, -1, '[', ']', '...', 12)
+            {}
+            ORDER BY score DESC, e.reference_count DESC, e.symbol_name, e.file_path, e.line_number, e.id LIMIT {} OFFSET {}
+            "#,
+            weights.join(", "),
+            matches,
+            page.limit,
+            start
+        );
+
+        // Malformed raw expressions are the client's mistake
+        let client_error = |e: rusqlite::Error| match e {
+            rusqlite::Error::SqliteFailure(_, Some(message)) if message.starts_with("fts5:") || message.starts_with("no such column") => {
+                StorageError::Validation(format!("Invalid full-text query: {}", message))
+            }
+            e => e.into(),
+        };
+        let mut stmt = self.connection.prepare(&sql)?;
+        let hits = stmt
+            .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                Ok(TextSearchHit {
+                    element: self.row_to_code_element(row)?,
+                    score: row.get(15)?,
+                    snippet: row.get(16)?,
+                })
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(client_error)?;
+        self.record_if_slow("full_text_search", &sql, || describe_params(&values), started, hits.len());
+
+        let returned = hits.len() as u64;
+        let total_count = if returned < page.limit && (returned > 0 || start == 0) {
+            start + returned
+        } else {
+            let count: i64 = self
+                .connection
+                .query_row(&format!("SELECT COUNT(*) {}", matches), rusqlite::params_from_iter(values.iter()), |row| row.get(0))
+                .map_err(client_error)?;
+            count as u64
+        };
+        page.page(hits, total_count).map_err(StorageError::Validation)
+    }
+
+    /// Runs a typed query over code elements
+    pub fn query_code_elements(&self, query: &CodeElementQuery) -> Result<Vec<CodeElement>> {
+        self.select_code_elements("query_code_elements", query)
+    }
+
+    /// Runs a typed query one page at a time, replacing its own limit and offset
+    pub fn query_code_elements_page(&self, query: &CodeElementQuery, page: &QueryPage) -> Result<Page<CodeElement>> {
+        self.select_code_elements_page("query_code_elements", query, page)
+    }
+
+    /// Counts the code elements matching a typed query, ignoring its limit and offset
+    pub fn count_code_elements(&self, query: &CodeElementQuery) -> Result<u64> {
+        let started = Instant::now();
+        let (sql, params) = query.to_count_sql("code_elements");
+        let count: i64 = self
+            .connection
+            .query_row(&sql, rusqlite::params_from_iter(params.iter()), |row| row.get(0))?;
+
+        self.record_if_slow("count_code_elements", &sql, || describe_params(&params), started, 1);
+        Ok(count as u64)
+    }
+
+    /// Lists code elements for a file
+    pub fn list_code_elements_by_file(&self, index_id: &Uuid, file_path: &str) -> Result<Vec<CodeElement>> {
+        let started = Instant::now();
+        let sql = r#"
+            SELECT id, index_id, symbol_name, symbol_type, file_path, line_number,
+                   column_number, definition_hash, scope, access_modifier, 
+                   is_declaration, signature, memory_section, documentation, usr
+            FROM code_elements 
+            WHERE index_id = ?1 AND file_path = ?2 
+            ORDER BY line_number, column_number, symbol_name, id
+            "#;
+        let mut stmt = self.connection.prepare(sql)?;
         
-        query.push_str(" ORDER BY symbol_name, file_path");
-        
-        let mut stmt = self.connection.prepare(&query)?;
-        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        
-        let elements = stmt.query_map(&param_refs[..], |row| {
+        let elements = stmt.query_map(params![index_id.to_string(), file_path], |row| {
             Ok(self.row_to_code_element(row)?)
         })?
         .collect::<Result<Vec<_>, _>>()?;
         
+        self.record_if_slow(
+            "list_code_elements_by_file",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id), ("file_path", &file_path)]),
+            started,
+            elements.len(),
+        );
         Ok(elements)
     }
 
-    /// Lists code elements for a file
-    pub fn list_code_elements_by_file(&self, index_id: &Uuid, file_path: &str) -> Result<Vec<CodeElement>> {
-        let mut stmt = self.connection.prepare(
-            r#"
+    /// Lists one page of the code elements of a file, in the order of [`Self::list_code_elements_by_file`]
+    pub fn list_code_elements_by_file_page(&self, index_id: &Uuid, file_path: &str, page: &QueryPage) -> Result<Page<CodeElement>> {
+        let query = CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, index_id.to_string()))
+            .filter(Filter::eq(ElementColumn::FilePath, file_path.to_string()))
+            .order_by_asc(ElementColumn::LineNumber)
+            .order_by_asc(ElementColumn::ColumnNumber)
+            .order_by_asc(ElementColumn::SymbolName);
+        self.select_code_elements_page("list_code_elements_by_file", &query, page)
+    }
+
+    /// Finds code elements whose name matches exactly, declarations before definitions
+    pub fn find_code_elements_by_name(&self, index_id: &Uuid, symbol_name: &str) -> Result<Vec<CodeElement>> {
+        let started = Instant::now();
+        let sql = r#"
             SELECT id, index_id, symbol_name, symbol_type, file_path, line_number,
                    column_number, definition_hash, scope, access_modifier, 
-                   is_declaration, signature
+                   is_declaration, signature, memory_section, documentation, usr
             FROM code_elements 
-            WHERE index_id = ?1 AND file_path = ?2 
-            ORDER BY line_number, column_number
-            "#
-        )?;
+            WHERE index_id = ?1 AND symbol_name = ?2 
+            ORDER BY is_declaration DESC, file_path, line_number, column_number, id
+            "#;
+        let mut stmt = self.connection.prepare(sql)?;
         
-        let elements = stmt.query_map(params![index_id.to_string(), file_path], |row| {
-            Ok(self.row_to_code_element(row)?)
+        let elements = stmt.query_map(params![index_id.to_string(), symbol_name], |row| {
+            self.row_to_code_element(row)
         })?
         .collect::<Result<Vec<_>, _>>()?;
         
+        self.record_if_slow(
+            "find_code_elements_by_name",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id), ("symbol_name", &symbol_name)]),
+            started,
+            elements.len(),
+        );
         Ok(elements)
     }
 
     /// Updates a code element
     pub fn update_code_element(&self, element: &CodeElement) -> Result<()> {
-        element.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
+        element.validate().map_err(StorageError::Validation)?;
         
-        let id = element.id.ok_or(rusqlite::Error::InvalidColumnName("Code element ID is required".to_string()))?;
+        let id = element.id.ok_or_else(|| StorageError::Validation("Code element ID is required".to_string()))?;
         
         let rows_affected = self.connection.execute(
             r#"
             UPDATE code_elements SET 
                 symbol_name = ?2, symbol_type = ?3, file_path = ?4, line_number = ?5,
                 column_number = ?6, definition_hash = ?7, scope = ?8, 
-                access_modifier = ?9, is_declaration = ?10, signature = ?11
+                access_modifier = ?9, is_declaration = ?10, signature = ?11,
+                memory_section = ?12, documentation = ?13,
+                fully_qualified_name = ?14, namespace_path = ?15, usr = ?16
             WHERE id = ?1
             "#,
             params![
@@ -467,14 +1190,20 @@ impl Repository {
                 element.scope,
                 element.access_modifier.map(|a| a.as_str()),
                 element.is_declaration,
-                element.signature
+                element.signature.as_deref().map(inline_signature),
+                element.memory_section,
+                element.documentation,
+                element.fully_qualified_name(),
+                element.namespace_path(),
+                element.usr
             ],
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("Code element", id));
         }
-        
+        self.store_symbol_signature(id, element.signature.as_deref())?;
+
         Ok(())
     }
 
@@ -488,320 +1217,2206 @@ impl Repository {
         Ok(())
     }
 
-    /// Deletes a code element by ID
-    pub fn delete_code_element(&self, id: i64) -> Result<()> {
-        let rows_affected = self.connection.execute(
-            "DELETE FROM code_elements WHERE id = ?1",
-            [id],
-        )?;
-        
-        if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
-        }
-        
-        Ok(())
+    /// Swaps a file's symbols for freshly extracted ones and records its new metadata, atomically
+    ///
+    /// Symbols that are still there keep their ids, as [`Repository::replace_code_elements`]
+    /// describes, so relationships from other files into this one survive.
+    pub fn replace_file_elements(&self, metadata: &FileMetadata, elements: Vec<CodeElement>) -> Result<Vec<CodeElement>> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let created = self.replace_code_elements(&metadata.index_id, &metadata.file_path, elements)?;
+        self.update_file_metadata(metadata)?;
+        self.touch_code_index(&metadata.index_id)?;
+        transaction.commit()?;
+        Ok(created)
     }
 
-    // === Symbol Relationship CRUD Operations ===
-
-    /// Creates a new symbol relationship
-    pub fn create_symbol_relationship(&self, mut relationship: SymbolRelationship) -> Result<SymbolRelationship> {
-        relationship.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
-        
+    /// Stores the symbols extracted from a file in place of those stored for it
+    ///
+    /// Each extracted symbol takes over the id of a stored one of the same
+    /// kind, qualified name, signature and declaration-ness, in line order,
+    /// so relationships from unchanged files into this one are kept. Stored
+    /// symbols left over are deleted with their relationships, and the calls
+    /// and callback references recorded in the file are dropped for the
+    /// caller to resolve again. Runs inside the caller's transaction.
+    fn replace_code_elements(&self, index_id: &Uuid, file_path: &str, elements: Vec<CodeElement>) -> Result<Vec<CodeElement>> {
+        let previous = self.list_code_elements_by_file(index_id, file_path)?;
+        let mut ids: HashMap<_, std::collections::VecDeque<i64>> = HashMap::new();
+        for element in &previous {
+            let key = overload_key(element);
+            ids.entry(key).or_default().extend(element.id);
+        }
+        let mut kept = Vec::new();
+        let mut added = Vec::new();
+        for mut element in elements {
+            let key = overload_key(&element);
+            match ids.get_mut(&key).and_then(|ids| ids.pop_front()) {
+                Some(id) => {
+                    element.id = Some(id);
+                    kept.push(element);
+                }
+                None => added.push(element),
+            }
+        }
+        for id in ids.into_values().flatten() {
+            self.delete_code_element(id)?;
+        }
         self.connection.execute(
-            r#"
-            INSERT INTO symbol_relationships (
-                from_symbol_id, to_symbol_id, relationship_type, 
-                file_path, line_number
-            ) VALUES (?1, ?2, ?3, ?4, ?5)
-            "#,
+            "DELETE FROM symbol_relationships WHERE file_path = ?2 AND relationship_type IN (?3, ?4) \
+             AND from_symbol_id IN (SELECT id FROM code_elements WHERE index_id = ?1 AND file_path = ?2)",
             params![
-                relationship.from_symbol_id,
-                relationship.to_symbol_id,
-                relationship.relationship_type.as_str(),
-                relationship.file_path,
-                relationship.line_number
+                index_id.to_string(),
+                file_path,
+                RelationshipType::Calls.as_str(),
+                RelationshipType::ReferencedAsCallback.as_str()
             ],
         )?;
-        
-        relationship.id = Some(self.connection.last_insert_rowid());
-        Ok(relationship)
+        // Kept symbols may trade places; parking them on a column no symbol has keeps their identities apart meanwhile
+        let mut park = self.connection.prepare_cached("UPDATE code_elements SET column_number = -id WHERE id = ?1")?;
+        for element in &kept {
+            park.execute([element.id])?;
+        }
+        for element in &kept {
+            self.update_code_element(element)?;
+            match &element.body {
+                Some(body) => self.store_symbol_body(element.id.unwrap_or_default(), body)?,
+                None => {
+                    self.connection.prepare_cached("DELETE FROM symbol_bodies WHERE symbol_id = ?1")?.execute([element.id])?;
+                }
+            }
+        }
+        kept.extend(self.insert_code_elements(added)?);
+        kept.sort_by_key(|element| (element.line_number, element.column_number));
+        Ok(kept)
     }
 
-    /// Queries symbol relationships using the relationship query builder
-    pub fn query_symbol_relationships(&self, query: &RelationshipQuery) -> Result<Vec<SymbolRelationship>> {
-        let mut sql = String::from(
-            r#"
-            SELECT id, from_symbol_id, to_symbol_id, relationship_type, file_path, line_number
-            FROM symbol_relationships WHERE 1=1
-            "#
-        );
-        
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
-        
-        if let Some(from_id) = query.from_symbol_id {
-            sql.push_str(&format!(" AND from_symbol_id = ?{}", params.len() + 1));
-            params.push(Box::new(from_id));
+    /// Moves an indexed file to the path in `metadata`, keeping its symbols' ids, atomically
+    ///
+    /// `elements` are the symbols extracted at the new path. Each takes over
+    /// the id of a stored symbol of the same kind, qualified name, signature
+    /// and declaration-ness, in line order; stored symbols left over are
+    /// deleted and new ones inserted. Includes and relationships recorded
+    /// for the old path follow the file. Returns the stored symbols.
+    pub fn move_file(&self, from: &str, metadata: &FileMetadata, elements: Vec<CodeElement>) -> Result<Vec<CodeElement>> {
+        let index_id = metadata.index_id.to_string();
+        let to = metadata.file_path.as_str();
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        if self.get_file_metadata_by_path(&metadata.index_id, to)?.is_some() {
+            return Err(StorageError::Conflict(format!("File {} is already indexed", to)));
         }
-        
-        if let Some(to_id) = query.to_symbol_id {
-            sql.push_str(&format!(" AND to_symbol_id = ?{}", params.len() + 1));
-            params.push(Box::new(to_id));
+        let stored = self
+            .get_file_metadata_by_path(&metadata.index_id, from)?
+            .ok_or_else(|| StorageError::not_found("File", from))?;
+        let previous = self.list_code_elements_by_file(&metadata.index_id, from)?;
+
+        for sql in [
+            "UPDATE file_metadata SET file_path = ?3 WHERE index_id = ?1 AND file_path = ?2",
+            "UPDATE file_includes SET file_path = ?3 WHERE index_id = ?1 AND file_path = ?2",
+            "UPDATE file_includes SET resolved_path = ?3 WHERE index_id = ?1 AND resolved_path = ?2",
+            "UPDATE symbol_relationships SET file_path = ?3 \
+             WHERE file_path = ?2 AND from_symbol_id IN (SELECT id FROM code_elements WHERE index_id = ?1)",
+        ] {
+            self.connection.execute(sql, params![index_id, from, to])?;
         }
-        
-        if !query.relationship_types.is_empty() {
-            sql.push_str(" AND relationship_type IN (");
-            for (i, rel_type) in query.relationship_types.iter().enumerate() {
-                if i > 0 {
-                    sql.push_str(", ");
+
+        let mut ids: HashMap<_, std::collections::VecDeque<i64>> = HashMap::new();
+        for element in &previous {
+            let key = overload_key(element);
+            ids.entry(key).or_default().extend(element.id);
+        }
+        let mut kept = Vec::new();
+        let mut added = Vec::new();
+        for mut element in elements {
+            element.file_path = to.to_string();
+            let key = overload_key(&element);
+            match ids.get_mut(&key).and_then(|ids| ids.pop_front()) {
+                Some(id) => {
+                    element.id = Some(id);
+                    self.update_code_element(&element)?;
+                    kept.push(element);
                 }
-                sql.push_str(&format!("?{}", params.len() + 1));
-                params.push(Box::new(rel_type.as_str().to_string()));
+                None => added.push(element),
             }
-            sql.push(')');
         }
-        
-        if let Some(pattern) = &query.file_path_pattern {
-            sql.push_str(&format!(" AND file_path LIKE ?{}", params.len() + 1));
-            params.push(Box::new(format!("%{}%", pattern)));
+        for id in ids.into_values().flatten() {
+            self.delete_code_element(id)?;
         }
-        
-        sql.push_str(" ORDER BY from_symbol_id, to_symbol_id");
-        
-        let mut stmt = self.connection.prepare(&sql)?;
-        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        
-        let relationships = stmt.query_map(&param_refs[..], |row| {
-            Ok(self.row_to_symbol_relationship(row)?)
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-        
-        Ok(relationships)
+        kept.extend(self.insert_code_elements(added)?);
+
+        let mut metadata = metadata.clone();
+        metadata.id = stored.id;
+        self.update_file_metadata(&metadata)?;
+        self.touch_code_index(&metadata.index_id)?;
+        transaction.commit()?;
+        Ok(kept)
     }
 
-    /// Lists all relationships for a symbol (both incoming and outgoing)
-    pub fn get_symbol_relationships(&self, symbol_id: i64) -> Result<(Vec<SymbolRelationship>, Vec<SymbolRelationship>)> {
-        // Outgoing relationships (from this symbol)
-        let outgoing = self.query_symbol_relationships(
-            &RelationshipQuery::new().from_symbol(symbol_id)
-        )?;
-        
-        // Incoming relationships (to this symbol)
-        let incoming = self.query_symbol_relationships(
-            &RelationshipQuery::new().to_symbol(symbol_id)
-        )?;
-        
-        Ok((outgoing, incoming))
+    /// Drops a file deleted from disk from the index, atomically
+    ///
+    /// Its symbols go with it, taking their relationships along, as do the
+    /// includes it recorded; the index totals are recounted. Returns the
+    /// number of symbols removed.
+    pub fn remove_file(&self, index_id: &Uuid, file_path: &str) -> Result<usize> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let metadata = self
+            .get_file_metadata_by_path(index_id, file_path)?
+            .ok_or_else(|| StorageError::not_found("File", file_path))?;
+        let removed = self.delete_file_rows(&metadata)?;
+        self.recount_index_totals(index_id)?;
+        transaction.commit()?;
+        Ok(removed)
     }
 
-    /// Deletes symbol relationships for a file (used during re-indexing)
-    pub fn delete_symbol_relationships_by_file(&self, file_path: &str) -> Result<()> {
+    /// Drops every file of an index that isn't in `present`, in one transaction
+    ///
+    /// `present` holds the stored paths found on disk. Files recorded in
+    /// `file_metadata` but missing from it lose their symbols, relationships
+    /// and includes as `remove_file` drops them, and the index totals are
+    /// recounted once. Returns the pruned paths, sorted, and the number of
+    /// symbols removed.
+    pub fn prune_missing_files(&self, index_id: &Uuid, present: &BTreeSet<String>) -> Result<(Vec<String>, usize)> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let mut pruned = Vec::new();
+        let mut removed = 0;
+        for metadata in self.list_file_metadata(index_id)? {
+            if !present.contains(&metadata.file_path) {
+                removed += self.delete_file_rows(&metadata)?;
+                pruned.push(metadata.file_path);
+            }
+        }
+        if !pruned.is_empty() {
+            self.recount_index_totals(index_id)?;
+        }
+        transaction.commit()?;
+        pruned.sort();
+        Ok((pruned, removed))
+    }
+
+    /// Deletes a file's symbols, includes and metadata; relationships cascade with the symbols
+    fn delete_file_rows(&self, metadata: &FileMetadata) -> Result<usize> {
+        let removed = self.connection.execute(
+            "DELETE FROM code_elements WHERE index_id = ?1 AND file_path = ?2",
+            params![metadata.index_id.to_string(), metadata.file_path],
+        )?;
         self.connection.execute(
-            "DELETE FROM symbol_relationships WHERE file_path = ?1",
-            [file_path],
+            "DELETE FROM file_includes WHERE index_id = ?1 AND file_path = ?2",
+            params![metadata.index_id.to_string(), metadata.file_path],
         )?;
-        
-        Ok(())
+        self.clear_index_error(&metadata.index_id, &metadata.file_path)?;
+        self.delete_file_metadata(metadata.id.unwrap_or_default())?;
+        Ok(removed)
     }
 
-    /// Deletes a symbol relationship by ID
-    pub fn delete_symbol_relationship(&self, id: i64) -> Result<()> {
-        let rows_affected = self.connection.execute(
-            "DELETE FROM symbol_relationships WHERE id = ?1",
-            [id],
+    /// Resets the file and symbol totals of an index from its indexed files
+    pub fn recount_index_totals(&self, index_id: &Uuid) -> Result<()> {
+        self.connection.execute(
+            "UPDATE code_indices SET
+                total_files = (SELECT COUNT(*) FROM file_metadata WHERE index_id = ?1 AND processing_state = 'indexed'),
+                total_symbols = (SELECT COALESCE(SUM(symbol_count), 0) FROM file_metadata WHERE index_id = ?1 AND processing_state = 'indexed'),
+                updated_at = ?2
+             WHERE id = ?1",
+            params![index_id.to_string(), Utc::now().to_rfc3339()],
         )?;
-        
-        if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
-        }
-        
         Ok(())
     }
 
-    // === MCP Query Session CRUD Operations ===
-
-    /// Creates a new MCP query session
-    pub fn create_mcp_session(&self, mut session: McpQuerySession) -> Result<McpQuerySession> {
-        session.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
-        
+    /// Records that an index's symbols changed, so answers cached for its previous state go stale
+    fn touch_code_index(&self, index_id: &Uuid) -> Result<()> {
         self.connection.execute(
-            r#"
-            INSERT INTO mcp_query_sessions (
-                session_id, client_name, active_index_id, created_at, 
-                last_activity, query_count, status, client_metadata
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
-            "#,
-            params![
-                session.session_id.to_string(),
-                session.client_name,
-                session.active_index_id.map(|id| id.to_string()),
-                session.created_at.to_rfc3339(),
-                session.last_activity.to_rfc3339(),
-                session.query_count,
-                session.status.as_str(),
-                session.client_metadata
-            ],
+            "UPDATE code_indices SET updated_at = ?2 WHERE id = ?1",
+            params![index_id.to_string(), Utc::now().to_rfc3339()],
         )?;
-        
-        Ok(session)
+        Ok(())
     }
 
-    /// Retrieves an MCP session by ID
-    pub fn get_mcp_session(&self, session_id: &Uuid) -> Result<Option<McpQuerySession>> {
-        let mut stmt = self.connection.prepare(
-            r#"
-            SELECT session_id, client_name, active_index_id, created_at, 
-                   last_activity, query_count, status, client_metadata
-            FROM mcp_query_sessions WHERE session_id = ?1
-            "#
+    /// Touches the indices holding the given symbols, as [`Self::touch_code_index`] does
+    fn touch_symbol_indices(&self, symbol_ids: impl IntoIterator<Item = i64>) -> Result<()> {
+        let mut stmt = self.connection.prepare_cached(
+            "UPDATE code_indices SET updated_at = ?2 WHERE id = (SELECT index_id FROM code_elements WHERE id = ?1)"
         )?;
-        
-        let mut rows = stmt.query_map([session_id.to_string()], |row| {
-            Ok(self.row_to_mcp_session(row)?)
-        })?;
-        
-        match rows.next() {
-            Some(session) => Ok(Some(session?)),
-            None => Ok(None),
+        let now = Utc::now().to_rfc3339();
+        for symbol_id in symbol_ids.into_iter().collect::<BTreeSet<_>>() {
+            stmt.execute(params![symbol_id, now])?;
         }
+        Ok(())
     }
 
-    /// Queries MCP sessions using the session query builder
-    pub fn query_mcp_sessions(&self, query: &SessionQuery) -> Result<Vec<McpQuerySession>> {
-        let mut sql = String::from(
-            r#"
-            SELECT session_id, client_name, active_index_id, created_at, 
-                   last_activity, query_count, status, client_metadata
-            FROM mcp_query_sessions WHERE 1=1
-            "#
-        );
-        
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
-        
-        if let Some(pattern) = &query.client_name_pattern {
-            sql.push_str(&format!(" AND client_name LIKE ?{}", params.len() + 1));
-            params.push(Box::new(format!("%{}%", pattern)));
-        }
-        
-        if let Some(status) = &query.status_filter {
-            sql.push_str(&format!(" AND status = ?{}", params.len() + 1));
-            params.push(Box::new(status.as_str().to_string()));
-        }
-        
-        if let Some(index_id) = &query.active_index_id {
-            sql.push_str(&format!(" AND active_index_id = ?{}", params.len() + 1));
-            params.push(Box::new(index_id.to_string()));
-        }
-        
-        if let Some(created_after) = &query.created_after {
-            sql.push_str(&format!(" AND created_at >= ?{}", params.len() + 1));
-            params.push(Box::new(created_after.to_rfc3339()));
-        }
-        
-        if let Some(created_before) = &query.created_before {
-            sql.push_str(&format!(" AND created_at <= ?{}", params.len() + 1));
-            params.push(Box::new(created_before.to_rfc3339()));
+    /// Stores the outcome of indexing a batch of files in one transaction
+    ///
+    /// Each indexed file's symbols and includes replace whatever was stored for it and its
+    /// metadata is created or updated, clearing any error recorded for it;
+    /// failed files are put in the error state with their error. Returns the
+    /// number of symbols stored.
+    pub fn store_file_batch(&self, indexed: Vec<(FileMetadata, Vec<CodeElement>, Vec<String>)>, failed: Vec<(FileMetadata, IndexError)>) -> Result<usize> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let mut stored = 0;
+        let touched: BTreeSet<Uuid> = indexed
+            .iter()
+            .map(|(metadata, _, _)| metadata.index_id)
+            .chain(failed.iter().map(|(metadata, _)| metadata.index_id))
+            .collect();
+        for (metadata, elements, includes) in indexed {
+            let metadata = self.upsert_file_metadata(metadata)?;
+            self.replace_file_includes(&metadata.index_id, &metadata.file_path, &includes)?;
+            stored += self.replace_code_elements(&metadata.index_id, &metadata.file_path, elements)?.len();
+            self.update_file_metadata(&metadata)?;
         }
-        
-        if let Some(min_queries) = &query.min_queries {
-            sql.push_str(&format!(" AND query_count >= ?{}", params.len() + 1));
-            params.push(Box::new(*min_queries));
+        for (metadata, error) in failed {
+            let metadata = self.upsert_file_metadata(metadata)?;
+            if let Some(id) = metadata.id {
+                self.update_file_processing_state(id, FileProcessingState::Error)?;
+            }
+            self.record_index_error(&error)?;
         }
-        
-        if let Some(idle_duration) = &query.idle_longer_than {
-            let cutoff_time = Utc::now() - *idle_duration;
-            sql.push_str(&format!(" AND last_activity <= ?{}", params.len() + 1));
-            params.push(Box::new(cutoff_time.to_rfc3339()));
+        for index_id in &touched {
+            self.touch_code_index(index_id)?;
         }
-        
-        sql.push_str(" ORDER BY last_activity DESC");
-        
-        let mut stmt = self.connection.prepare(&sql)?;
-        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        
-        let sessions = stmt.query_map(&param_refs[..], |row| {
-            Ok(self.row_to_mcp_session(row)?)
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-        
-        Ok(sessions)
+        transaction.commit()?;
+        Ok(stored)
     }
 
-    /// Updates an MCP session
-    pub fn update_mcp_session(&self, session: &McpQuerySession) -> Result<()> {
-        session.validate().map_err(|e| rusqlite::Error::InvalidColumnName(e))?;
-        
-        let rows_affected = self.connection.execute(
-            r#"
-            UPDATE mcp_query_sessions SET 
-                client_name = ?2, active_index_id = ?3, last_activity = ?4,
-                query_count = ?5, status = ?6, client_metadata = ?7
-            WHERE session_id = ?1
-            "#,
-            params![
-                session.session_id.to_string(),
-                session.client_name,
-                session.active_index_id.map(|id| id.to_string()),
-                session.last_activity.to_rfc3339(),
-                session.query_count,
-                session.status.as_str(),
-                session.client_metadata
-            ],
-        )?;
-        
-        if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+    /// Gives file metadata the id of the stored row for its path, creating the row if needed
+    fn upsert_file_metadata(&self, mut metadata: FileMetadata) -> Result<FileMetadata> {
+        match self.get_file_metadata_by_path(&metadata.index_id, &metadata.file_path)? {
+            Some(existing) => {
+                metadata.id = existing.id;
+                Ok(metadata)
+            }
+            None => self.create_file_metadata(metadata),
         }
-        
-        Ok(())
     }
 
-    /// Deletes an MCP session
-    pub fn delete_mcp_session(&self, session_id: &Uuid) -> Result<()> {
+    /// Deletes a code element by ID
+    pub fn delete_code_element(&self, id: i64) -> Result<()> {
         let rows_affected = self.connection.execute(
-            "DELETE FROM mcp_query_sessions WHERE session_id = ?1",
-            [session_id.to_string()],
+            "DELETE FROM code_elements WHERE id = ?1",
+            [id],
         )?;
         
         if rows_affected == 0 {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+            return Err(StorageError::not_found("Code element", id));
         }
         
         Ok(())
     }
 
-    // === Utility Methods ===
-
-    /// Gets statistics for all indices
-    pub fn get_index_statistics(&self) -> Result<HashMap<String, IndexStatistics>> {
+    /// Counts the symbols of an index per memory section
+    pub fn count_code_elements_by_section(&self, index_id: &Uuid) -> Result<BTreeMap<String, u64>> {
         let mut stmt = self.connection.prepare(
             r#"
-            SELECT 
-                ci.id, ci.name, ci.total_files, ci.total_symbols,
-                COUNT(DISTINCT fm.id) as file_count,
-                COUNT(DISTINCT ce.id) as element_count,
-                COUNT(DISTINCT sr.id) as relationship_count
-            FROM code_indices ci
-            LEFT JOIN file_metadata fm ON ci.id = fm.index_id AND fm.processing_state = 'indexed'
-            LEFT JOIN code_elements ce ON ci.id = ce.index_id  
-            LEFT JOIN symbol_relationships sr ON ce.id = sr.from_symbol_id
-            GROUP BY ci.id, ci.name, ci.total_files, ci.total_symbols
+            SELECT memory_section, COUNT(*) FROM code_elements
+            WHERE index_id = ?1 AND memory_section IS NOT NULL
+            GROUP BY memory_section
             "#
         )?;
-        
-        let mut stats_map = HashMap::new();
-        
-        let rows = stmt.query_map([], |row| {
-            let index_id: String = row.get(0)?;
-            let name: String = row.get(1)?;
-            let total_files: u32 = row.get(2)?;
-            let total_symbols: u32 = row.get(3)?;
-            let actual_file_count: i64 = row.get(4)?;
-            let actual_element_count: i64 = row.get(5)?;
-            let relationship_count: i64 = row.get(6)?;
-            
+
+        let counts = stmt.query_map([index_id.to_string()], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        Ok(counts)
+    }
+
+    // === Disk Space ===
+
+    /// Checks free space around this database's file and its page limit; None in memory
+    pub fn disk_space_guard(&self) -> Result<Option<DiskSpaceGuard>> {
+        let Some(path) = self.connection.path().filter(|path| !path.is_empty()) else { return Ok(None) };
+        let max_pages: i64 = self.connection.query_row("PRAGMA max_page_count", [], |row| row.get(0))?;
+        let page_size: i64 = self.connection.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok(Some(DiskSpaceGuard::new(path).with_max_size_bytes((max_pages as u64).saturating_mul(page_size as u64))))
+    }
+
+    // === Symbol Bodies ===
+
+    /// Source text of a symbol, decompressed on demand; None if it wasn't stored
+    pub fn get_symbol_body(&self, symbol_id: i64) -> Result<Option<String>> {
+        let mut stmt = self.connection.prepare_cached("SELECT body FROM symbol_bodies WHERE symbol_id = ?1")?;
+        let mut rows = stmt.query([symbol_id])?;
+        let Some(row) = rows.next()? else { return Ok(None) };
+        decompress(&row.get::<_, Vec<u8>>(0)?, "Body", symbol_id).map(Some)
+    }
+
+    /// Whole signature of a symbol, decompressed if code_elements only keeps its start
+    pub fn get_symbol_signature(&self, symbol_id: i64) -> Result<Option<String>> {
+        let stored = self
+            .connection
+            .prepare_cached("SELECT e.signature, s.signature FROM code_elements e LEFT JOIN symbol_signatures s ON s.symbol_id = e.id WHERE e.id = ?1")?
+            .query_row([symbol_id], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?)))
+            .optional()?;
+        match stored {
+            Some((_, Some(compressed))) => decompress(&compressed, "Signature", symbol_id).map(Some),
+            Some((inline, None)) => Ok(inline),
+            None => Ok(None),
+        }
+    }
+
+    /// Number of stored bodies of an index with their compressed and original sizes
+    pub fn get_symbol_body_stats(&self, index_id: &Uuid) -> Result<SymbolBodyStats> {
+        let stats = self.connection.query_row(
+            r#"
+            SELECT COUNT(*), COALESCE(SUM(LENGTH(b.body)), 0), COALESCE(SUM(b.uncompressed_size), 0)
+            FROM symbol_bodies b
+            JOIN code_elements e ON e.id = b.symbol_id
+            WHERE e.index_id = ?1
+            "#,
+            [index_id.to_string()],
+            |row| {
+                Ok(SymbolBodyStats {
+                    count: row.get::<_, i64>(0)? as u64,
+                    compressed_bytes: row.get::<_, i64>(1)? as u64,
+                    uncompressed_bytes: row.get::<_, i64>(2)? as u64,
+                })
+            },
+        )?;
+        Ok(stats)
+    }
+
+    /// Compresses and stores a symbol's body, replacing an earlier one
+    fn store_symbol_body(&self, symbol_id: i64, body: &str) -> Result<()> {
+        self.connection
+            .prepare_cached("INSERT OR REPLACE INTO symbol_bodies (symbol_id, body, uncompressed_size) VALUES (?1, ?2, ?3)")?
+            .execute(params![symbol_id, compress(body)?, body.len() as i64])?;
+        Ok(())
+    }
+
+    /// Stores the whole of a symbol's signature compressed if code_elements only keeps its start
+    ///
+    /// A signature read back from code_elements, already cut short, leaves
+    /// the stored one alone.
+    fn store_symbol_signature(&self, symbol_id: i64, signature: Option<&str>) -> Result<()> {
+        match signature {
+            Some(signature) if is_inline_signature(signature) => Ok(()),
+            Some(signature) if inline_signature(signature) != signature => {
+                self.connection
+                    .prepare_cached("INSERT OR REPLACE INTO symbol_signatures (symbol_id, signature) VALUES (?1, ?2)")?
+                    .execute(params![symbol_id, compress(signature)?])?;
+                Ok(())
+            }
+            _ => {
+                self.connection.prepare_cached("DELETE FROM symbol_signatures WHERE symbol_id = ?1")?.execute([symbol_id])?;
+                Ok(())
+            }
+        }
+    }
+
+    // === Symbol Popularity ===
+
+    /// Recounts references and call degree for every symbol of an index
+    ///
+    /// Run after an index's relationships are stored; returns the number of
+    /// symbols updated.
+    pub fn refresh_symbol_popularity(&self, index_id: &Uuid) -> Result<usize> {
+        let started = Instant::now();
+        let sql = r#"
+            UPDATE code_elements SET
+                reference_count = (
+                    SELECT COUNT(*) FROM symbol_relationships r
+                    WHERE r.to_symbol_id = code_elements.id AND r.relationship_type NOT IN ('contained_in', 'defines')
+                ),
+                caller_count = (
+                    SELECT COUNT(DISTINCT r.from_symbol_id) FROM symbol_relationships r
+                    WHERE r.to_symbol_id = code_elements.id AND r.relationship_type IN ('calls', 'referenced_as_callback')
+                ),
+                callee_count = (
+                    SELECT COUNT(DISTINCT r.to_symbol_id) FROM symbol_relationships r
+                    WHERE r.from_symbol_id = code_elements.id AND r.relationship_type IN ('calls', 'referenced_as_callback')
+                )
+            WHERE index_id = ?1
+            "#;
+        let updated = self.connection.execute(sql, [index_id.to_string()])?;
+        self.touch_code_index(index_id)?;
+
+        self.record_if_slow(
+            "refresh_symbol_popularity",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id)]),
+            started,
+            updated,
+        );
+        Ok(updated)
+    }
+
+    /// Popularity of the given symbols; ids that don't exist are left out
+    pub fn get_symbol_popularity(&self, symbol_ids: &[i64]) -> Result<HashMap<i64, SymbolPopularity>> {
+        let mut popularity = HashMap::with_capacity(symbol_ids.len());
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT reference_count, caller_count, callee_count FROM code_elements WHERE id = ?1"
+        )?;
+        for &id in symbol_ids {
+            let mut rows = stmt.query([id])?;
+            if let Some(row) = rows.next()? {
+                popularity.insert(id, SymbolPopularity {
+                    reference_count: row.get::<_, i64>(0)? as u64,
+                    caller_count: row.get::<_, i64>(1)? as u64,
+                    callee_count: row.get::<_, i64>(2)? as u64,
+                });
+            }
+        }
+        Ok(popularity)
+    }
+
+    // === Index Insights ===
+
+    /// Hot spots of an index: the `limit` highest of each ranking
+    ///
+    /// Fan-in and fan-out count distinct symbols on the other end of every
+    /// kind of relationship except containment and definition links. Class
+    /// members are counted in the file declaring the class, so out-of-line
+    /// definitions don't count twice.
+    pub fn get_index_insights(&self, index_id: &Uuid, limit: usize) -> Result<IndexInsights> {
+        let started = Instant::now();
+        let columns = "e.id, e.index_id, e.symbol_name, e.symbol_type, e.file_path, e.line_number, \
+                       e.column_number, e.definition_hash, e.scope, e.access_modifier, \
+                       e.is_declaration, e.signature, e.memory_section, e.documentation, e.usr";
+        let degree = |symbol: &str, other: &str| {
+            format!(
+                r#"
+                SELECT {columns}, COUNT(DISTINCT r.{other}) AS degree
+                FROM symbol_relationships r
+                JOIN code_elements e ON e.id = r.{symbol}
+                WHERE e.index_id = ?1
+                  AND r.relationship_type NOT IN ('contained_in', 'defines')
+                  AND r.from_symbol_id <> r.to_symbol_id
+                GROUP BY e.id
+                ORDER BY degree DESC, e.symbol_name, e.id
+                LIMIT ?2
+                "#
+            )
+        };
+        let classes = format!(
+            r#"
+            SELECT {columns}, members.member_count
+            FROM (
+                SELECT namespace_path, file_path, COUNT(*) AS member_count
+                FROM code_elements
+                WHERE index_id = ?1 AND namespace_path <> ''
+                GROUP BY namespace_path, file_path
+            ) members
+            JOIN code_elements e
+              ON e.index_id = ?1 AND e.fully_qualified_name = members.namespace_path AND e.file_path = members.file_path
+            WHERE e.symbol_type IN ('class', 'struct', 'union') AND e.is_declaration = 0
+            ORDER BY members.member_count DESC, e.fully_qualified_name, e.id
+            LIMIT ?2
+            "#
+        );
+        let ranked_symbols = |sql: &str| -> Result<Vec<RankedSymbol>> {
+            let mut stmt = self.connection.prepare(sql)?;
+            let symbols = stmt
+                .query_map(params![index_id.to_string(), limit as i64], |row| {
+                    Ok(RankedSymbol { element: self.row_to_code_element(row)?, count: row.get::<_, i64>(15)? as u64 })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(symbols)
+        };
+        let ranked_files = |sql: &str| -> Result<Vec<RankedFile>> {
+            let mut stmt = self.connection.prepare(sql)?;
+            let files = stmt
+                .query_map(params![index_id.to_string(), limit as i64], |row| {
+                    Ok(RankedFile { file_path: row.get(0)?, count: row.get::<_, i64>(1)? as u64 })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(files)
+        };
+
+        let insights = IndexInsights {
+            fan_in: ranked_symbols(&degree("to_symbol_id", "from_symbol_id"))?,
+            fan_out: ranked_symbols(&degree("from_symbol_id", "to_symbol_id"))?,
+            largest_classes: ranked_symbols(&classes)?,
+            most_included_headers: ranked_files(
+                r#"
+                SELECT resolved_path, COUNT(DISTINCT file_path) AS includers
+                FROM file_includes
+                WHERE index_id = ?1 AND resolved_path IS NOT NULL
+                GROUP BY resolved_path
+                ORDER BY includers DESC, resolved_path
+                LIMIT ?2
+                "#,
+            )?,
+            busiest_files: ranked_files(
+                r#"
+                SELECT file_path, COUNT(*) AS symbols
+                FROM code_elements
+                WHERE index_id = ?1
+                GROUP BY file_path
+                ORDER BY symbols DESC, file_path
+                LIMIT ?2
+                "#,
+            )?,
+        };
+
+        self.record_if_slow(
+            "get_index_insights",
+            &classes,
+            || SlowQuery::summarize_params(&[("index_id", index_id), ("limit", &limit)]),
+            started,
+            limit,
+        );
+        Ok(insights)
+    }
+
+    // === File Dependencies ===
+
+    /// Cross-file references of an index, keyed by (referencing file, referenced file)
+    ///
+    /// Each symbol is attributed to the file it is declared in; references
+    /// within a file are left out. With no `relationship_types`, every kind
+    /// except containment and definition links counts.
+    pub fn get_file_dependencies(
+        &self,
+        index_id: &Uuid,
+        relationship_types: &[RelationshipType],
+    ) -> Result<BTreeMap<(String, String), u64>> {
+        let started = Instant::now();
+        let type_filter = if relationship_types.is_empty() {
+            "r.relationship_type NOT IN ('contained_in', 'defines')".to_string()
+        } else {
+            let types: Vec<String> = relationship_types.iter().map(|t| format!("'{}'", t.as_str())).collect();
+            format!("r.relationship_type IN ({})", types.join(", "))
+        };
+        let sql = format!(
+            r#"
+            SELECT source.file_path, target.file_path, COUNT(*)
+            FROM symbol_relationships r
+            JOIN code_elements source ON source.id = r.from_symbol_id
+            JOIN code_elements target ON target.id = r.to_symbol_id
+            WHERE source.index_id = ?1
+              AND {}
+              AND source.file_path <> target.file_path
+            GROUP BY source.file_path, target.file_path
+            "#,
+            type_filter
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let dependencies = stmt
+            .query_map([index_id.to_string()], |row| {
+                Ok(((row.get(0)?, row.get(1)?), row.get::<_, i64>(2)? as u64))
+            })?
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        self.record_if_slow(
+            "get_file_dependencies",
+            &sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id)]),
+            started,
+            dependencies.len(),
+        );
+        Ok(dependencies)
+    }
+
+    // === File Includes ===
+
+    /// Replaces the includes recorded for a file, leaving them unresolved
+    pub fn replace_file_includes(&self, index_id: &Uuid, file_path: &str, includes: &[String]) -> Result<()> {
+        self.connection.execute(
+            "DELETE FROM file_includes WHERE index_id = ?1 AND file_path = ?2",
+            params![index_id.to_string(), file_path],
+        )?;
+        let mut stmt = self.connection.prepare_cached(
+            "INSERT OR IGNORE INTO file_includes (index_id, file_path, include_path) VALUES (?1, ?2, ?3)"
+        )?;
+        for include in includes {
+            stmt.execute(params![index_id.to_string(), file_path, include])?;
+        }
+        Ok(())
+    }
+
+    /// Resolves every include of an index against its indexed files
+    ///
+    /// Run once an index's files are stored; returns the number of includes
+    /// that refer to an indexed file.
+    pub fn resolve_file_includes(&self, index_id: &Uuid) -> Result<usize> {
+        let started = Instant::now();
+        let files: BTreeSet<String> = self.list_file_metadata(index_id)?.into_iter().map(|metadata| metadata.file_path).collect();
+        let includes: Vec<(String, String)> = self
+            .connection
+            .prepare("SELECT file_path, include_path FROM file_includes WHERE index_id = ?1")?
+            .query_map([index_id.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sql = "UPDATE file_includes SET resolved_path = ?4 WHERE index_id = ?1 AND file_path = ?2 AND include_path = ?3";
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let mut resolved = 0;
+        {
+            let mut stmt = transaction.prepare(sql)?;
+            for (file_path, include_path) in &includes {
+                let target = resolve_include(file_path, include_path, &files);
+                resolved += usize::from(target.is_some());
+                stmt.execute(params![index_id.to_string(), file_path, include_path, target])?;
+            }
+        }
+        transaction.commit()?;
+
+        self.record_if_slow(
+            "resolve_file_includes",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id)]),
+            started,
+            includes.len(),
+        );
+        Ok(resolved)
+    }
+
+    /// Includes of the given files, ordered by file and include path
+    pub fn get_file_includes(&self, index_id: &Uuid, file_paths: &[String]) -> Result<Vec<FileInclude>> {
+        self.query_file_includes("file_path", index_id, file_paths)
+    }
+
+    /// Includes that resolve to the given files, ordered by including file
+    pub fn get_including_files(&self, index_id: &Uuid, resolved_paths: &[String]) -> Result<Vec<FileInclude>> {
+        self.query_file_includes("resolved_path", index_id, resolved_paths)
+    }
+
+    fn query_file_includes(&self, column: &str, index_id: &Uuid, paths: &[String]) -> Result<Vec<FileInclude>> {
+        let mut includes = Vec::new();
+        let mut stmt = self.connection.prepare_cached(&format!(
+            r#"
+            SELECT file_path, include_path, resolved_path FROM file_includes
+            WHERE index_id = ?1 AND {} = ?2
+            ORDER BY file_path, include_path
+            "#,
+            column
+        ))?;
+        for path in paths {
+            let rows = stmt.query_map(params![index_id.to_string(), path], |row| {
+                Ok(FileInclude {
+                    index_id: *index_id,
+                    file_path: row.get(0)?,
+                    include_path: row.get(1)?,
+                    resolved_path: row.get(2)?,
+                })
+            })?;
+            for include in rows {
+                includes.push(include?);
+            }
+        }
+        Ok(includes)
+    }
+
+    // === Symbol Tag Operations ===
+
+    /// Tags a symbol, returning false if it already carried the tag
+    pub fn add_symbol_tag(&self, symbol_id: i64, tag: &str) -> Result<bool> {
+        IndexTag::validate_key(tag).map_err(StorageError::Validation)?;
+
+        let rows_affected = self.connection.execute(
+            "INSERT OR IGNORE INTO symbol_tags (symbol_id, tag, created_at) VALUES (?1, ?2, ?3)",
+            params![symbol_id, tag, Utc::now().to_rfc3339()],
+        )?;
+
+        if rows_affected > 0 {
+            self.touch_symbol_indices([symbol_id])?;
+        }
+        Ok(rows_affected > 0)
+    }
+
+    /// Removes a tag from a symbol, returning whether it existed
+    pub fn remove_symbol_tag(&self, symbol_id: i64, tag: &str) -> Result<bool> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM symbol_tags WHERE symbol_id = ?1 AND tag = ?2",
+            params![symbol_id, tag],
+        )?;
+
+        if rows_affected > 0 {
+            self.touch_symbol_indices([symbol_id])?;
+        }
+        Ok(rows_affected > 0)
+    }
+
+    /// Returns the tags of a symbol in alphabetical order
+    pub fn get_symbol_tags(&self, symbol_id: i64) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT tag FROM symbol_tags WHERE symbol_id = ?1 ORDER BY tag"
+        )?;
+
+        let tags = stmt.query_map([symbol_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(tags)
+    }
+
+    /// Lists the symbols of an index carrying a tag
+    pub fn find_code_elements_by_tag(&self, index_id: &Uuid, tag: &str) -> Result<Vec<CodeElement>> {
+        let started = Instant::now();
+        let sql = r#"
+            SELECT id, index_id, symbol_name, symbol_type, file_path, line_number,
+                   column_number, definition_hash, scope, access_modifier,
+                   is_declaration, signature, memory_section, documentation, usr
+            FROM code_elements
+            WHERE index_id = ?1 AND id IN (SELECT symbol_id FROM symbol_tags WHERE tag = ?2)
+            ORDER BY file_path, line_number, column_number, id
+            "#;
+        let mut stmt = self.connection.prepare(sql)?;
+
+        let elements = stmt.query_map(params![index_id.to_string(), tag], |row| {
+            self.row_to_code_element(row)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        self.record_if_slow(
+            "find_code_elements_by_tag",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id), ("tag", &tag)]),
+            started,
+            elements.len(),
+        );
+        Ok(elements)
+    }
+
+    // === Symbol Annotation Operations ===
+
+    /// Attaches a note or bookmark to a symbol
+    pub fn create_annotation(&self, mut annotation: SymbolAnnotation) -> Result<SymbolAnnotation> {
+        annotation.validate().map_err(StorageError::Validation)?;
+
+        self.connection.execute(
+            r#"
+            INSERT INTO symbol_annotations (index_id, symbol_name, scope, author, kind, text, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+            params![
+                annotation.index_id.to_string(),
+                annotation.symbol_name,
+                annotation.scope.as_deref().unwrap_or(""),
+                annotation.author,
+                annotation.kind.as_str(),
+                annotation.text,
+                annotation.created_at.to_rfc3339()
+            ],
+        )?;
+
+        annotation.id = Some(self.connection.last_insert_rowid());
+        self.touch_code_index(&annotation.index_id)?;
+        Ok(annotation)
+    }
+
+    /// Deletes an annotation, returning whether it existed
+    pub fn delete_annotation(&self, id: i64) -> Result<bool> {
+        self.connection.execute(
+            "UPDATE code_indices SET updated_at = ?2 WHERE id = (SELECT index_id FROM symbol_annotations WHERE id = ?1)",
+            params![id, Utc::now().to_rfc3339()],
+        )?;
+        let rows_affected = self.connection.execute("DELETE FROM symbol_annotations WHERE id = ?1", [id])?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Annotations on a symbol, oldest first; with no scope, annotations in every scope are returned
+    pub fn get_symbol_annotations(&self, index_id: &Uuid, symbol_name: &str, scope: Option<&str>) -> Result<Vec<SymbolAnnotation>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, index_id, symbol_name, scope, author, kind, text, created_at
+            FROM symbol_annotations
+            WHERE index_id = ?1 AND symbol_name = ?2 AND (?3 IS NULL OR scope = ?3)
+            ORDER BY created_at, id
+            "#
+        )?;
+
+        let annotations = stmt.query_map(params![index_id.to_string(), symbol_name, scope], |row| {
+            self.row_to_annotation(row)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(annotations)
+    }
+
+    /// Lists the annotations of an index, optionally only one author's or one kind
+    pub fn list_annotations(&self, index_id: &Uuid, author: Option<&str>, kind: Option<AnnotationKind>) -> Result<Vec<SymbolAnnotation>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, index_id, symbol_name, scope, author, kind, text, created_at
+            FROM symbol_annotations
+            WHERE index_id = ?1 AND (?2 IS NULL OR author = ?2) AND (?3 IS NULL OR kind = ?3)
+            ORDER BY scope, symbol_name, created_at, id
+            "#
+        )?;
+
+        let annotations = stmt.query_map(params![index_id.to_string(), author, kind.map(|k| k.as_str())], |row| {
+            self.row_to_annotation(row)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(annotations)
+    }
+
+    // === Index Error Operations ===
+
+    /// Records why a file failed to index, replacing its previous error
+    pub fn record_index_error(&self, error: &IndexError) -> Result<()> {
+        self.connection.execute(
+            r#"
+            INSERT INTO index_errors (index_id, file_path, kind, message, recorded_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(index_id, file_path) DO UPDATE SET
+                kind = excluded.kind,
+                message = excluded.message,
+                recorded_at = excluded.recorded_at
+            "#,
+            params![
+                error.index_id.to_string(),
+                error.file_path,
+                error.kind.as_str(),
+                error.message,
+                error.recorded_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Forgets the error of a file, returning whether it had one
+    pub fn clear_index_error(&self, index_id: &Uuid, file_path: &str) -> Result<bool> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM index_errors WHERE index_id = ?1 AND file_path = ?2",
+            params![index_id.to_string(), file_path],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Errors of the files an index is missing, by path; optionally only one kind
+    pub fn list_index_errors(&self, index_id: &Uuid, kind: Option<IndexErrorKind>) -> Result<Vec<IndexError>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT index_id, file_path, kind, message, recorded_at
+            FROM index_errors
+            WHERE index_id = ?1 AND (?2 IS NULL OR kind = ?2)
+            ORDER BY file_path
+            "#
+        )?;
+
+        let errors = stmt.query_map(params![index_id.to_string(), kind.map(|k| k.as_str())], |row| {
+            let index_id: String = row.get(0)?;
+            let kind: String = row.get(2)?;
+            let recorded_at: String = row.get(4)?;
+            Ok(IndexError {
+                index_id: Uuid::parse_str(&index_id)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(0, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+                file_path: row.get(1)?,
+                kind: IndexErrorKind::parse(&kind)
+                    .ok_or_else(|| rusqlite::Error::InvalidColumnType(2, "Invalid error kind".to_string(), rusqlite::types::Type::Text))?,
+                message: row.get(3)?,
+                recorded_at: DateTime::parse_from_rfc3339(&recorded_at)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(errors)
+    }
+
+    // === Saved Query Operations ===
+
+    /// Saves a named query, replacing the query and description of an existing one
+    ///
+    /// Re-saving never stops a watch; changing the query text resets the
+    /// watch's baseline result set.
+    pub fn save_query(&self, saved: SavedQuery) -> Result<SavedQuery> {
+        saved.validate().map_err(StorageError::Validation)?;
+
+        self.connection.execute(
+            r#"
+            INSERT INTO saved_queries (index_id, name, query, description, created_at, updated_at, watched)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT(index_id, name) DO UPDATE SET
+                last_result = CASE WHEN saved_queries.query = excluded.query THEN saved_queries.last_result END,
+                query = excluded.query,
+                description = excluded.description,
+                updated_at = excluded.updated_at,
+                watched = saved_queries.watched OR excluded.watched
+            "#,
+            params![
+                saved.index_id.to_string(),
+                saved.name,
+                saved.query,
+                saved.description,
+                saved.created_at.to_rfc3339(),
+                saved.updated_at.to_rfc3339(),
+                saved.watched
+            ],
+        )?;
+
+        self.get_saved_query(&saved.index_id, &saved.name)?
+            .ok_or_else(|| StorageError::NotFound(format!("Saved query {} not found after saving", saved.name)))
+    }
+
+    /// Gets a saved query by name
+    pub fn get_saved_query(&self, index_id: &Uuid, name: &str) -> Result<Option<SavedQuery>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, index_id, name, query, description, created_at, updated_at, watched, last_checked_at
+            FROM saved_queries
+            WHERE index_id = ?1 AND name = ?2
+            "#
+        )?;
+
+        let mut rows = stmt.query_map(params![index_id.to_string(), name], |row| self.row_to_saved_query(row))?;
+        match rows.next() {
+            Some(saved) => Ok(Some(saved?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the saved queries of an index by name
+    pub fn list_saved_queries(&self, index_id: &Uuid) -> Result<Vec<SavedQuery>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, index_id, name, query, description, created_at, updated_at, watched, last_checked_at
+            FROM saved_queries
+            WHERE index_id = ?1
+            ORDER BY name
+            "#
+        )?;
+
+        let queries = stmt.query_map([index_id.to_string()], |row| self.row_to_saved_query(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(queries)
+    }
+
+    /// Deletes a saved query, returning whether it existed
+    pub fn delete_saved_query(&self, index_id: &Uuid, name: &str) -> Result<bool> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM saved_queries WHERE index_id = ?1 AND name = ?2",
+            params![index_id.to_string(), name],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Starts or stops watching a saved query, returning whether it exists
+    ///
+    /// Either way the baseline result set is cleared, so the next evaluation
+    /// records a fresh one.
+    pub fn set_query_watched(&self, index_id: &Uuid, name: &str, watched: bool) -> Result<bool> {
+        let rows_affected = self.connection.execute(
+            r#"
+            UPDATE saved_queries SET watched = ?3, last_result = NULL, last_checked_at = NULL
+            WHERE index_id = ?1 AND name = ?2
+            "#,
+            params![index_id.to_string(), name, watched],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Lists the watched queries of an index by name
+    pub fn list_watched_queries(&self, index_id: &Uuid) -> Result<Vec<SavedQuery>> {
+        Ok(self.list_saved_queries(index_id)?.into_iter().filter(|saved| saved.watched).collect())
+    }
+
+    /// Result set a watched query produced when last evaluated; None before the first evaluation
+    pub fn get_watch_baseline(&self, id: i64) -> Result<Option<Vec<String>>> {
+        let baseline: Option<String> = self
+            .connection
+            .query_row("SELECT last_result FROM saved_queries WHERE id = ?1", [id], |row| row.get(0))?;
+
+        baseline
+            .map(|json| {
+                serde_json::from_str(&json)
+                    .map_err(|e| StorageError::Corruption(format!("Invalid watch baseline for saved query {}: {}", id, e)))
+            })
+            .transpose()
+    }
+
+    /// Records the result set of a watched query
+    pub fn set_watch_baseline(&self, id: i64, keys: &[String]) -> Result<()> {
+        let json = serde_json::to_string(keys)
+            .map_err(|e| StorageError::Validation(format!("Cannot serialize watch result: {}", e)))?;
+        self.connection.execute(
+            "UPDATE saved_queries SET last_result = ?2, last_checked_at = ?3 WHERE id = ?1",
+            params![id, json, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    // === Build Configuration Operations ===
+
+    /// Saves a build configuration, replacing the macros of an existing one
+    ///
+    /// Changing the macros leaves the configuration's exclusions as they
+    /// were until the index is assigned again.
+    pub fn save_build_configuration(&self, configuration: BuildConfiguration) -> Result<BuildConfiguration> {
+        configuration.validate().map_err(StorageError::Validation)?;
+        let defines = serde_json::to_string(&configuration.defines)
+            .map_err(|e| StorageError::Validation(format!("Cannot serialize defines: {}", e)))?;
+        let undefines = serde_json::to_string(&configuration.undefines)
+            .map_err(|e| StorageError::Validation(format!("Cannot serialize undefines: {}", e)))?;
+
+        self.connection.execute(
+            r#"
+            INSERT INTO build_configurations (index_id, name, defines, undefines, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(index_id, name) DO UPDATE SET
+                defines = excluded.defines,
+                undefines = excluded.undefines
+            "#,
+            params![
+                configuration.index_id.to_string(),
+                configuration.name,
+                defines,
+                undefines,
+                configuration.created_at.to_rfc3339()
+            ],
+        )?;
+        self.touch_code_index(&configuration.index_id)?;
+
+        self.get_build_configuration(&configuration.index_id, &configuration.name)?
+            .ok_or_else(|| StorageError::NotFound(format!("Build configuration {} not found after saving", configuration.name)))
+    }
+
+    /// Gets a build configuration by name
+    pub fn get_build_configuration(&self, index_id: &Uuid, name: &str) -> Result<Option<BuildConfiguration>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, index_id, name, defines, undefines, created_at
+            FROM build_configurations
+            WHERE index_id = ?1 AND name = ?2
+            "#
+        )?;
+
+        let mut rows = stmt.query_map(params![index_id.to_string(), name], |row| self.row_to_build_configuration(row))?;
+        match rows.next() {
+            Some(configuration) => Ok(Some(configuration?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the build configurations of an index by name
+    pub fn list_build_configurations(&self, index_id: &Uuid) -> Result<Vec<BuildConfiguration>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, index_id, name, defines, undefines, created_at
+            FROM build_configurations
+            WHERE index_id = ?1
+            ORDER BY name
+            "#
+        )?;
+
+        let configurations = stmt.query_map([index_id.to_string()], |row| self.row_to_build_configuration(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(configurations)
+    }
+
+    /// Deletes a build configuration and its exclusions, returning whether it existed
+    pub fn delete_build_configuration(&self, index_id: &Uuid, name: &str) -> Result<bool> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM build_configurations WHERE index_id = ?1 AND name = ?2",
+            params![index_id.to_string(), name],
+        )?;
+        if rows_affected > 0 {
+            self.touch_code_index(index_id)?;
+        }
+        Ok(rows_affected > 0)
+    }
+
+    /// Replaces the excluded symbols of every configuration of an index
+    ///
+    /// `exclusions` pairs a configuration id with a symbol it compiles out.
+    pub fn replace_configuration_exclusions(&self, index_id: &Uuid, exclusions: &[(i64, i64)]) -> Result<()> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        transaction.execute(
+            r#"
+            DELETE FROM configuration_excluded_symbols
+            WHERE configuration_id IN (SELECT id FROM build_configurations WHERE index_id = ?1)
+            "#,
+            [index_id.to_string()],
+        )?;
+        {
+            let mut stmt = transaction.prepare(
+                "INSERT OR IGNORE INTO configuration_excluded_symbols (configuration_id, symbol_id) VALUES (?1, ?2)"
+            )?;
+            for (configuration_id, symbol_id) in exclusions {
+                stmt.execute(params![configuration_id, symbol_id])?;
+            }
+        }
+        self.touch_code_index(index_id)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Ids of the symbols a configuration compiles out, in ascending order
+    pub fn list_configuration_exclusions(&self, configuration_id: i64) -> Result<Vec<i64>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT symbol_id FROM configuration_excluded_symbols WHERE configuration_id = ?1 ORDER BY symbol_id"
+        )?;
+
+        let ids = stmt.query_map([configuration_id], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+
+        Ok(ids)
+    }
+
+    /// Names of the configurations of an index that compile a symbol
+    pub fn get_symbol_configurations(&self, index_id: &Uuid, symbol_id: i64) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT name FROM build_configurations
+            WHERE index_id = ?1
+              AND id NOT IN (SELECT configuration_id FROM configuration_excluded_symbols WHERE symbol_id = ?2)
+            ORDER BY name
+            "#
+        )?;
+
+        let names = stmt.query_map(params![index_id.to_string(), symbol_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(names)
+    }
+
+    // === Index Revision Operations ===
+
+    /// Records a revision, returning it with its id
+    pub fn create_index_revision(&self, mut revision: IndexRevision) -> Result<IndexRevision> {
+        revision.validate().map_err(StorageError::Validation)?;
+        self.connection
+            .execute(
+                r#"
+                INSERT INTO index_revisions (index_id, name, commit_id, parent_id, is_current, created_at, switched_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+                params![
+                    revision.index_id.to_string(),
+                    revision.name,
+                    revision.commit,
+                    revision.parent_id,
+                    revision.is_current,
+                    revision.created_at.to_rfc3339(),
+                    revision.switched_at.to_rfc3339()
+                ],
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::SqliteFailure(failure, _) if failure.code == rusqlite::ErrorCode::ConstraintViolation => {
+                    StorageError::Conflict(format!("Revision {} already exists", revision.name))
+                }
+                e => e.into(),
+            })?;
+        revision.id = Some(self.connection.last_insert_rowid());
+        Ok(revision)
+    }
+
+    /// Gets a revision of an index by name
+    pub fn get_index_revision(&self, index_id: &Uuid, name: &str) -> Result<Option<IndexRevision>> {
+        Ok(self.select_index_revisions("WHERE index_id = ?1 AND name = ?2", params![index_id.to_string(), name])?.pop())
+    }
+
+    /// The revision the live index holds, if the index was ever switched
+    pub fn get_current_revision(&self, index_id: &Uuid) -> Result<Option<IndexRevision>> {
+        Ok(self.select_index_revisions("WHERE index_id = ?1 AND is_current", params![index_id.to_string()])?.pop())
+    }
+
+    /// Lists the revisions of an index by name
+    pub fn list_index_revisions(&self, index_id: &Uuid) -> Result<Vec<IndexRevision>> {
+        self.select_index_revisions("WHERE index_id = ?1 ORDER BY name", params![index_id.to_string()])
+    }
+
+    /// Makes `revision` the one the live index holds, at `commit`
+    pub fn set_current_revision(&self, revision: &IndexRevision, commit: Option<&str>) -> Result<()> {
+        let id = revision.id.ok_or_else(|| StorageError::Validation("Revision has not been stored".to_string()))?;
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        self.connection.execute(
+            "UPDATE index_revisions SET is_current = 0 WHERE index_id = ?1 AND is_current",
+            [revision.index_id.to_string()],
+        )?;
+        self.connection.execute(
+            "UPDATE index_revisions SET is_current = 1, commit_id = COALESCE(?2, commit_id), switched_at = ?3 WHERE id = ?1",
+            params![id, commit, Utc::now().to_rfc3339()],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Files of a revision with their content hashes, its parents' files included
+    pub fn get_revision_files(&self, revision_id: i64) -> Result<BTreeMap<String, String>> {
+        let mut chain = vec![revision_id];
+        let mut parents = self.connection.prepare_cached("SELECT parent_id FROM index_revisions WHERE id = ?1")?;
+        while let Some(parent) = parents.query_row([chain[chain.len() - 1]], |row| row.get::<_, Option<i64>>(0)).optional()?.flatten() {
+            if chain.contains(&parent) {
+                return Err(StorageError::Corruption(format!("Revision {} is its own ancestor", parent)));
+            }
+            chain.push(parent);
+        }
+
+        let mut files = BTreeMap::new();
+        let mut stmt = self.connection.prepare_cached("SELECT file_path, file_hash FROM revision_files WHERE revision_id = ?1")?;
+        for id in chain.into_iter().rev() {
+            let rows = stmt.query_map([id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))?;
+            for row in rows {
+                match row? {
+                    (path, Some(hash)) => files.insert(path, hash),
+                    (path, None) => files.remove(&path),
+                };
+            }
+        }
+        Ok(files)
+    }
+
+    /// Records the files a revision has now, atomically
+    ///
+    /// Only the difference from the parent revision is stored. Revisions
+    /// branched from this one keep the versions they shared with it: each
+    /// file about to change is copied into those not overriding it already.
+    pub fn record_revision_files(&self, revision: &IndexRevision, files: &BTreeMap<String, String>) -> Result<()> {
+        let id = revision.id.ok_or_else(|| StorageError::Validation("Revision has not been stored".to_string()))?;
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let previous = self.get_revision_files(id)?;
+        let inherited = match revision.parent_id {
+            Some(parent_id) => self.get_revision_files(parent_id)?,
+            None => BTreeMap::new(),
+        };
+        let changed = |base: &BTreeMap<String, String>| -> BTreeSet<String> {
+            base.keys().chain(files.keys()).filter(|path| base.get(*path) != files.get(*path)).cloned().collect()
+        };
+
+        let children: Vec<i64> = self
+            .connection
+            .prepare("SELECT id FROM index_revisions WHERE parent_id = ?1")?
+            .query_map([id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut copy = self
+            .connection
+            .prepare_cached("INSERT OR IGNORE INTO revision_files (revision_id, file_path, file_hash) VALUES (?1, ?2, ?3)")?;
+        for path in changed(&previous) {
+            for child in &children {
+                copy.execute(params![child, path, previous.get(&path)])?;
+            }
+        }
+
+        self.connection.execute("DELETE FROM revision_files WHERE revision_id = ?1", [id])?;
+        for path in changed(&inherited) {
+            copy.execute(params![id, path, files.get(&path)])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Indexed files of the live index with their content hashes
+    pub fn get_live_files(&self, index_id: &Uuid) -> Result<BTreeMap<String, String>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT file_path, file_hash FROM file_metadata WHERE index_id = ?1 AND processing_state = 'indexed'")?;
+        let files = stmt
+            .query_map([index_id.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(files)
+    }
+
+    /// Keeps the live version of a file, symbols, includes and relationships, so it can be restored without parsing
+    ///
+    /// Relationships to and from the file's symbols are kept with their ends
+    /// named, since restored symbols may get new ids. Returns false if that
+    /// version was stashed already.
+    pub fn stash_file(&self, index_id: &Uuid, file_path: &str) -> Result<bool> {
+        let metadata = self
+            .get_file_metadata_by_path(index_id, file_path)?
+            .ok_or_else(|| StorageError::not_found("File", file_path))?;
+        let stashed: bool = self.connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM stashed_files WHERE index_id = ?1 AND file_path = ?2 AND file_hash = ?3)",
+            params![index_id.to_string(), file_path, metadata.file_hash],
+            |row| row.get(0),
+        )?;
+        if stashed {
+            return Ok(false);
+        }
+
+        let mut elements = self.list_code_elements_by_file(index_id, file_path)?;
+        let mut symbols: HashMap<i64, StashedSymbol> =
+            elements.iter().filter_map(|element| Some((element.id?, StashedSymbol::new(element)))).collect();
+        let mut edges = BTreeMap::new();
+        for id in symbols.keys().copied().collect::<Vec<_>>() {
+            let (outgoing, incoming) = self.get_symbol_relationships(id)?;
+            edges.extend(outgoing.into_iter().chain(incoming).filter_map(|relationship| Some((relationship.id?, relationship))));
+        }
+        let mut relationships = Vec::new();
+        for relationship in edges.into_values() {
+            let from = self.stashed_symbol(relationship.from_symbol_id, &mut symbols)?;
+            let to = self.stashed_symbol(relationship.to_symbol_id, &mut symbols)?;
+            if let (Some(from), Some(to)) = (from, to) {
+                relationships.push(StashedRelationship {
+                    from,
+                    to,
+                    relationship_type: relationship.relationship_type,
+                    file_path: relationship.file_path,
+                    line_number: relationship.line_number,
+                });
+            }
+        }
+        for element in &mut elements {
+            if let Some(id) = element.id.take() {
+                element.body = self.get_symbol_body(id)?;
+            }
+        }
+        let includes = self
+            .get_file_includes(index_id, &[file_path.to_string()])?
+            .into_iter()
+            .map(|include| include.include_path)
+            .collect();
+        let file_hash = metadata.file_hash.clone();
+        let stash = StashedFile { metadata: FileMetadata { id: None, ..metadata }, elements, includes, relationships };
+        let json = serde_json::to_vec(&stash).map_err(|e| StorageError::Validation(format!("Cannot serialize stashed file: {}", e)))?;
+        let contents = zstd::stream::encode_all(json.as_slice(), SYMBOL_BODY_COMPRESSION_LEVEL)
+            .map_err(|e| io_error("Failed to compress stashed file", e))?;
+        self.connection.execute(
+            "INSERT INTO stashed_files (index_id, file_path, file_hash, contents, stashed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![index_id.to_string(), file_path, file_hash, contents, Utc::now().to_rfc3339()],
+        )?;
+        Ok(true)
+    }
+
+    /// Replaces the live version of a file with a stashed one, atomically
+    ///
+    /// Returns the number of symbols restored, or None if that version of
+    /// the file was never stashed.
+    pub fn restore_stashed_file(&self, index_id: &Uuid, file_path: &str, file_hash: &str) -> Result<Option<usize>> {
+        let contents: Option<Vec<u8>> = self
+            .connection
+            .query_row(
+                "SELECT contents FROM stashed_files WHERE index_id = ?1 AND file_path = ?2 AND file_hash = ?3",
+                params![index_id.to_string(), file_path, file_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(contents) = contents else {
+            return Ok(None);
+        };
+        let json = zstd::stream::decode_all(contents.as_slice()).map_err(|e| io_error("Failed to decompress stashed file", e))?;
+        let stash: StashedFile = serde_json::from_slice(&json)
+            .map_err(|e| StorageError::Corruption(format!("Stashed file {} is unreadable: {}", file_path, e)))?;
+
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let metadata = self.upsert_file_metadata(stash.metadata)?;
+        self.replace_file_includes(index_id, file_path, &stash.includes)?;
+        let restored = self.replace_code_elements(index_id, file_path, stash.elements)?.len();
+        self.update_file_metadata(&metadata)?;
+        // Relationships whose other end is no longer indexed stay dropped
+        let mut files = HashMap::new();
+        let mut insert = self.connection.prepare_cached(
+            "INSERT OR IGNORE INTO symbol_relationships (from_symbol_id, to_symbol_id, relationship_type, file_path, line_number) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for relationship in &stash.relationships {
+            let from = self.find_stashed_symbol(index_id, &relationship.from, &mut files)?;
+            let to = self.find_stashed_symbol(index_id, &relationship.to, &mut files)?;
+            if let (Some(from), Some(to)) = (from, to) {
+                insert.execute(params![from, to, relationship.relationship_type.as_str(), relationship.file_path, relationship.line_number])?;
+            }
+        }
+        self.touch_code_index(index_id)?;
+        transaction.commit()?;
+        Ok(Some(restored))
+    }
+
+    /// Names a symbol for a stashed relationship, looking each one up once
+    fn stashed_symbol(&self, id: i64, symbols: &mut HashMap<i64, StashedSymbol>) -> Result<Option<StashedSymbol>> {
+        if let Some(symbol) = symbols.get(&id) {
+            return Ok(Some(symbol.clone()));
+        }
+        let symbol = self.get_code_element(id)?.map(|element| StashedSymbol::new(&element));
+        if let Some(symbol) = &symbol {
+            symbols.insert(id, symbol.clone());
+        }
+        Ok(symbol)
+    }
+
+    /// Id of the indexed symbol a stashed relationship end names, preferring one still on its line
+    fn find_stashed_symbol(&self, index_id: &Uuid, symbol: &StashedSymbol, files: &mut HashMap<String, Vec<CodeElement>>) -> Result<Option<i64>> {
+        let elements = match files.entry(symbol.file_path.clone()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert(self.list_code_elements_by_file(index_id, &symbol.file_path)?),
+        };
+        let named: Vec<&CodeElement> = elements.iter().filter(|element| symbol.names(element)).collect();
+        let found = named.iter().find(|element| element.line_number == symbol.line_number).or(named.first());
+        Ok(found.and_then(|element| element.id))
+    }
+
+    /// Drops stashed file versions no revision of the index has any more
+    pub fn prune_stashed_files(&self, index_id: &Uuid) -> Result<usize> {
+        let mut kept = BTreeSet::new();
+        for revision in self.list_index_revisions(index_id)? {
+            kept.extend(self.get_revision_files(revision.id.unwrap_or_default())?);
+        }
+        let stashed: Vec<(String, String)> = self
+            .connection
+            .prepare("SELECT file_path, file_hash FROM stashed_files WHERE index_id = ?1")?
+            .query_map([index_id.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut pruned = 0;
+        for (path, hash) in stashed.into_iter().filter(|file| !kept.contains(file)) {
+            pruned += self.connection.execute(
+                "DELETE FROM stashed_files WHERE index_id = ?1 AND file_path = ?2 AND file_hash = ?3",
+                params![index_id.to_string(), path, hash],
+            )?;
+        }
+        Ok(pruned)
+    }
+
+    fn select_index_revisions(&self, condition: &str, params: impl rusqlite::Params) -> Result<Vec<IndexRevision>> {
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT id, index_id, name, commit_id, parent_id, is_current, created_at, switched_at FROM index_revisions {}",
+            condition
+        ))?;
+        let revisions = stmt.query_map(params, |row| self.row_to_index_revision(row))?.collect::<rusqlite::Result<_>>()?;
+        Ok(revisions)
+    }
+
+    // === Walk Rule Operations ===
+
+    /// Saves which files the directory walk of an index selects, replacing its previous rules
+    pub fn save_walk_rules(&self, rules: &WalkRules) -> Result<()> {
+        rules.validate().map_err(StorageError::Validation)?;
+        let include = serde_json::to_string(&rules.include_patterns)
+            .map_err(|e| StorageError::Validation(format!("Cannot serialize include patterns: {}", e)))?;
+        let exclude = serde_json::to_string(&rules.exclude_patterns)
+            .map_err(|e| StorageError::Validation(format!("Cannot serialize exclude patterns: {}", e)))?;
+
+        self.connection.execute(
+            r#"
+            INSERT INTO index_walk_rules (index_id, include_patterns, exclude_patterns, respect_ignore_files, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(index_id) DO UPDATE SET
+                include_patterns = excluded.include_patterns,
+                exclude_patterns = excluded.exclude_patterns,
+                respect_ignore_files = excluded.respect_ignore_files,
+                updated_at = excluded.updated_at
+            "#,
+            params![
+                rules.index_id.to_string(),
+                include,
+                exclude,
+                rules.respect_ignore_files,
+                rules.updated_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Walk rules of an index; None if it was created before they were stored
+    pub fn get_walk_rules(&self, index_id: &Uuid) -> Result<Option<WalkRules>> {
+        let row: Option<(String, String, bool, String)> = self
+            .connection
+            .query_row(
+                "SELECT include_patterns, exclude_patterns, respect_ignore_files, updated_at FROM index_walk_rules WHERE index_id = ?1",
+                [index_id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+
+        let Some((include, exclude, respect_ignore_files, updated_at)) = row else {
+            return Ok(None);
+        };
+        let patterns = |json: &str| {
+            serde_json::from_str::<Vec<String>>(json)
+                .map_err(|e| StorageError::Corruption(format!("Invalid walk rules of index {}: {}", index_id, e)))
+        };
+        Ok(Some(WalkRules {
+            index_id: *index_id,
+            include_patterns: patterns(&include)?,
+            exclude_patterns: patterns(&exclude)?,
+            respect_ignore_files,
+            updated_at: DateTime::parse_from_rfc3339(&updated_at)
+                .map_err(|e| StorageError::Corruption(format!("Invalid walk rules of index {}: {}", index_id, e)))?
+                .with_timezone(&Utc),
+        }))
+    }
+
+    // === Parse Setting Operations ===
+
+    /// Saves the compilation database and include flags an index is parsed with
+    pub fn save_parse_settings(&self, settings: &ParseSettings) -> Result<()> {
+        let include_flags = serde_json::to_string(&settings.include_flags)
+            .map_err(|e| StorageError::Validation(format!("Cannot serialize include flags: {}", e)))?;
+
+        self.connection.execute(
+            r#"
+            INSERT INTO index_parse_settings (index_id, compile_commands, include_flags, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(index_id) DO UPDATE SET
+                compile_commands = excluded.compile_commands,
+                include_flags = excluded.include_flags,
+                updated_at = excluded.updated_at
+            "#,
+            params![settings.index_id.to_string(), settings.compile_commands, include_flags, settings.updated_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Parse settings of an index; None if it was created before they were stored
+    pub fn get_parse_settings(&self, index_id: &Uuid) -> Result<Option<ParseSettings>> {
+        let row: Option<(Option<String>, String, String)> = self
+            .connection
+            .query_row(
+                "SELECT compile_commands, include_flags, updated_at FROM index_parse_settings WHERE index_id = ?1",
+                [index_id.to_string()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+
+        let Some((compile_commands, include_flags, updated_at)) = row else {
+            return Ok(None);
+        };
+        let corrupt = |e: &dyn std::fmt::Display| StorageError::Corruption(format!("Invalid parse settings of index {}: {}", index_id, e));
+        Ok(Some(ParseSettings {
+            index_id: *index_id,
+            compile_commands,
+            include_flags: serde_json::from_str(&include_flags).map_err(|e| corrupt(&e))?,
+            updated_at: DateTime::parse_from_rfc3339(&updated_at).map_err(|e| corrupt(&e))?.with_timezone(&Utc),
+        }))
+    }
+
+    // === Path Alias Operations ===
+
+    /// Replaces the vendored copy aliases of an index
+    pub fn replace_path_aliases(&self, index_id: &Uuid, aliases: &[PathAlias]) -> Result<()> {
+        for alias in aliases {
+            alias.validate().map_err(StorageError::Validation)?;
+        }
+
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        transaction.execute("DELETE FROM path_aliases WHERE index_id = ?1", [index_id.to_string()])?;
+        {
+            let mut stmt = transaction.prepare(
+                r#"
+                INSERT INTO path_aliases (index_id, alias_path, canonical_path, file_count, tree_hash)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                "#
+            )?;
+            for alias in aliases {
+                stmt.execute(params![
+                    index_id.to_string(),
+                    alias.alias_path,
+                    alias.canonical_path,
+                    alias.file_count as i64,
+                    alias.tree_hash
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Lists the vendored copy aliases of an index by alias path
+    pub fn list_path_aliases(&self, index_id: &Uuid) -> Result<Vec<PathAlias>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT alias_path, canonical_path, file_count, tree_hash
+            FROM path_aliases
+            WHERE index_id = ?1
+            ORDER BY alias_path
+            "#
+        )?;
+
+        let aliases = stmt.query_map([index_id.to_string()], |row| {
+            Ok(PathAlias {
+                index_id: *index_id,
+                alias_path: row.get(0)?,
+                canonical_path: row.get(1)?,
+                file_count: row.get::<_, i64>(2)? as u64,
+                tree_hash: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(aliases)
+    }
+
+    /// Maps a file in a skipped vendored copy to the indexed copy; other paths are returned unchanged
+    pub fn resolve_path_alias(&self, index_id: &Uuid, file_path: &str) -> Result<String> {
+        let mut resolved = file_path.to_string();
+        // Aliases can point into another aliased tree; follow a few hops at most
+        for _ in 0..4 {
+            let alias = self.list_path_aliases(index_id)?
+                .into_iter()
+                .filter_map(|alias| alias.resolve(&resolved).map(|path| (alias.alias_path.len(), path)))
+                .max_by_key(|(length, _)| *length);
+            match alias {
+                Some((_, path)) => resolved = path,
+                None => break,
+            }
+        }
+        Ok(resolved)
+    }
+
+    // === Adaptive Depth Operations ===
+
+    /// Counts one query against each directory on `day`
+    pub fn record_directory_hits(&self, index_id: &Uuid, directories: &BTreeSet<String>, day: NaiveDate) -> Result<()> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        {
+            let mut stmt = transaction.prepare(
+                r#"
+                INSERT INTO directory_query_hits (index_id, directory, day, hits) VALUES (?1, ?2, ?3, 1)
+                ON CONFLICT (index_id, directory, day) DO UPDATE SET hits = hits + 1
+                "#
+            )?;
+            for directory in directories {
+                stmt.execute(params![index_id.to_string(), directory, day.to_string()])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Query hits per directory from `since` on
+    pub fn directory_hits_since(&self, index_id: &Uuid, since: NaiveDate) -> Result<BTreeMap<String, u64>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT directory, SUM(hits) FROM directory_query_hits
+            WHERE index_id = ?1 AND day >= ?2
+            GROUP BY directory
+            "#
+        )?;
+        let hits = stmt
+            .query_map(params![index_id.to_string(), since.to_string()], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))?
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        Ok(hits)
+    }
+
+    /// Drops hit buckets older than `before`, returning how many were removed
+    pub fn prune_directory_hits(&self, index_id: &Uuid, before: NaiveDate) -> Result<usize> {
+        Ok(self.connection.execute(
+            "DELETE FROM directory_query_hits WHERE index_id = ?1 AND day < ?2",
+            params![index_id.to_string(), before.to_string()],
+        )?)
+    }
+
+    /// Replaces the planned indexing depths of an index
+    pub fn replace_directory_depths(&self, index_id: &Uuid, depths: &[DirectoryDepth]) -> Result<()> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        transaction.execute("DELETE FROM directory_depths WHERE index_id = ?1", [index_id.to_string()])?;
+        {
+            let mut stmt = transaction.prepare(
+                r#"
+                INSERT INTO directory_depths (index_id, directory, depth, hits, assigned_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                "#
+            )?;
+            for depth in depths {
+                stmt.execute(params![
+                    index_id.to_string(),
+                    depth.directory,
+                    depth.depth.as_str(),
+                    depth.hits as i64,
+                    depth.assigned_at.to_rfc3339()
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Lists the planned indexing depths of an index by directory
+    pub fn list_directory_depths(&self, index_id: &Uuid) -> Result<Vec<DirectoryDepth>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT directory, depth, hits, assigned_at
+            FROM directory_depths
+            WHERE index_id = ?1
+            ORDER BY directory
+            "#
+        )?;
+
+        let depths = stmt.query_map([index_id.to_string()], |row| {
+            let depth: String = row.get(1)?;
+            let assigned_at: String = row.get(3)?;
+            Ok(DirectoryDepth {
+                index_id: *index_id,
+                directory: row.get(0)?,
+                depth: IndexDepth::parse(&depth)
+                    .ok_or_else(|| rusqlite::Error::InvalidColumnType(1, "Invalid index depth".to_string(), rusqlite::types::Type::Text))?,
+                hits: row.get::<_, i64>(2)? as u64,
+                assigned_at: DateTime::parse_from_rfc3339(&assigned_at)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(3, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(depths)
+    }
+
+    // === Symbol Relationship CRUD Operations ===
+
+    /// Creates a new symbol relationship
+    pub fn create_symbol_relationship(&self, relationship: SymbolRelationship) -> Result<SymbolRelationship> {
+        let mut created = self.insert_symbol_relationships(vec![relationship])?;
+        Ok(created.remove(0))
+    }
+
+    /// Creates many symbol relationships with one prepared statement in one transaction
+    ///
+    /// Every relationship is validated before anything is written; if any
+    /// insert fails none of the relationships are stored.
+    pub fn create_symbol_relationships_batch(&self, relationships: Vec<SymbolRelationship>) -> Result<Vec<SymbolRelationship>> {
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let created = self.insert_symbol_relationships(relationships)?;
+        transaction.commit()?;
+        Ok(created)
+    }
+
+    /// Validates then inserts relationships through one cached statement, inside whatever transaction is open
+    fn insert_symbol_relationships(&self, mut relationships: Vec<SymbolRelationship>) -> Result<Vec<SymbolRelationship>> {
+        for relationship in &relationships {
+            relationship.validate().map_err(StorageError::Validation)?;
+        }
+
+        let mut stmt = self.connection.prepare_cached(
+            r#"
+            INSERT INTO symbol_relationships (
+                from_symbol_id, to_symbol_id, relationship_type, 
+                file_path, line_number
+            ) VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+        )?;
+        for relationship in &mut relationships {
+            stmt.execute(params![
+                relationship.from_symbol_id,
+                relationship.to_symbol_id,
+                relationship.relationship_type.as_str(),
+                relationship.file_path,
+                relationship.line_number
+            ])?;
+            relationship.id = Some(self.connection.last_insert_rowid());
+        }
+        self.touch_symbol_indices(relationships.iter().map(|relationship| relationship.from_symbol_id))?;
+        Ok(relationships)
+    }
+
+    /// Lists every relationship whose source symbol belongs to an index
+    pub fn list_index_relationships(&self, index_id: &Uuid) -> Result<Vec<SymbolRelationship>> {
+        let started = Instant::now();
+        let sql = r#"
+            SELECT r.id, r.from_symbol_id, r.to_symbol_id, r.relationship_type, r.file_path, r.line_number
+            FROM symbol_relationships r
+            JOIN code_elements e ON e.id = r.from_symbol_id
+            WHERE e.index_id = ?1
+            ORDER BY r.id
+            "#;
+        let mut stmt = self.connection.prepare(sql)?;
+        let relationships = stmt
+            .query_map([index_id.to_string()], |row| self.row_to_symbol_relationship(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.record_if_slow(
+            "list_index_relationships",
+            sql,
+            || SlowQuery::summarize_params(&[("index_id", index_id)]),
+            started,
+            relationships.len(),
+        );
+        Ok(relationships)
+    }
+
+    /// Queries symbol relationships using the relationship query builder
+    pub fn query_symbol_relationships(&self, query: &RelationshipQuery) -> Result<Vec<SymbolRelationship>> {
+        let mut typed = SymbolRelationshipQuery::new();
+
+        if let Some(from_id) = query.from_symbol_id {
+            typed = typed.filter(Filter::eq(RelationshipColumn::FromSymbolId, from_id));
+        }
+
+        if let Some(to_id) = query.to_symbol_id {
+            typed = typed.filter(Filter::eq(RelationshipColumn::ToSymbolId, to_id));
+        }
+
+        if !query.relationship_types.is_empty() {
+            typed = typed.filter(Filter::in_list(
+                RelationshipColumn::RelationshipType,
+                query.relationship_types.iter().copied(),
+            ));
+        }
+
+        if let Some(pattern) = &query.file_path_pattern {
+            typed = typed.filter(Filter::contains(RelationshipColumn::FilePath, pattern));
+        }
+
+        let typed = typed
+            .order_by_asc(RelationshipColumn::FromSymbolId)
+            .order_by_asc(RelationshipColumn::ToSymbolId);
+
+        self.select_symbol_relationships("query_symbol_relationships", &typed)
+    }
+
+    /// Runs a typed query over symbol relationships
+    pub fn query_relationships(&self, query: &SymbolRelationshipQuery) -> Result<Vec<SymbolRelationship>> {
+        self.select_symbol_relationships("query_relationships", query)
+    }
+
+    /// Counts the relationships matching a typed query, ignoring its limit and offset
+    pub fn count_relationships(&self, query: &SymbolRelationshipQuery) -> Result<u64> {
+        let started = Instant::now();
+        let (sql, params) = query.to_count_sql("symbol_relationships");
+        let count: i64 = self
+            .connection
+            .query_row(&sql, rusqlite::params_from_iter(params.iter()), |row| row.get(0))?;
+
+        self.record_if_slow("count_relationships", &sql, || describe_params(&params), started, 1);
+        Ok(count as u64)
+    }
+
+    /// Lists all relationships for a symbol (both incoming and outgoing)
+    pub fn get_symbol_relationships(&self, symbol_id: i64) -> Result<(Vec<SymbolRelationship>, Vec<SymbolRelationship>)> {
+        // Outgoing relationships (from this symbol)
+        let outgoing = self.query_symbol_relationships(
+            &RelationshipQuery::new().from_symbol(symbol_id)
+        )?;
+        
+        // Incoming relationships (to this symbol)
+        let incoming = self.query_symbol_relationships(
+            &RelationshipQuery::new().to_symbol(symbol_id)
+        )?;
+        
+        Ok((outgoing, incoming))
+    }
+
+    /// Deletes symbol relationships for a file (used during re-indexing)
+    pub fn delete_symbol_relationships_by_file(&self, file_path: &str) -> Result<()> {
+        self.connection.execute(
+            "DELETE FROM symbol_relationships WHERE file_path = ?1",
+            [file_path],
+        )?;
+        
+        Ok(())
+    }
+
+    /// Deletes a symbol relationship by ID
+    pub fn delete_symbol_relationship(&self, id: i64) -> Result<()> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM symbol_relationships WHERE id = ?1",
+            [id],
+        )?;
+        
+        if rows_affected == 0 {
+            return Err(StorageError::not_found("Symbol relationship", id));
+        }
+        
+        Ok(())
+    }
+
+    // === MCP Query Session CRUD Operations ===
+
+    /// Creates a new MCP query session
+    pub fn create_mcp_session(&self, session: McpQuerySession) -> Result<McpQuerySession> {
+        session.validate().map_err(StorageError::Validation)?;
+        
+        self.connection.execute(
+            r#"
+            INSERT INTO mcp_query_sessions (
+                session_id, client_name, active_index_id, created_at, 
+                last_activity, query_count, status, client_metadata
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+            params![
+                session.session_id.to_string(),
+                session.client_name,
+                session.active_index_id.map(|id| id.to_string()),
+                session.created_at.to_rfc3339(),
+                session.last_activity.to_rfc3339(),
+                session.query_count,
+                session.status.as_str(),
+                session.client_metadata
+            ],
+        )?;
+        
+        Ok(session)
+    }
+
+    /// Retrieves an MCP session by ID
+    pub fn get_mcp_session(&self, session_id: &Uuid) -> Result<Option<McpQuerySession>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT session_id, client_name, active_index_id, created_at, 
+                   last_activity, query_count, status, client_metadata
+            FROM mcp_query_sessions WHERE session_id = ?1
+            "#
+        )?;
+        
+        let mut rows = stmt.query_map([session_id.to_string()], |row| {
+            Ok(self.row_to_mcp_session(row)?)
+        })?;
+        
+        match rows.next() {
+            Some(session) => Ok(Some(session?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Queries MCP sessions using the session query builder
+    pub fn query_mcp_sessions(&self, query: &SessionQuery) -> Result<Vec<McpQuerySession>> {
+        let mut sql = String::from(
+            r#"
+            SELECT session_id, client_name, active_index_id, created_at, 
+                   last_activity, query_count, status, client_metadata
+            FROM mcp_query_sessions WHERE 1=1
+            "#
+        );
+        
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        
+        if let Some(pattern) = &query.client_name_pattern {
+            sql.push_str(&format!(" AND client_name LIKE ?{} ESCAPE '\\'", params.len() + 1));
+            params.push(Box::new(format!("%{}%", escape_like(pattern))));
+        }
+        
+        if let Some(status) = &query.status_filter {
+            sql.push_str(&format!(" AND status = ?{}", params.len() + 1));
+            params.push(Box::new(status.as_str().to_string()));
+        }
+        
+        if let Some(index_id) = &query.active_index_id {
+            sql.push_str(&format!(" AND active_index_id = ?{}", params.len() + 1));
+            params.push(Box::new(index_id.to_string()));
+        }
+        
+        if let Some(created_after) = &query.created_after {
+            sql.push_str(&format!(" AND created_at >= ?{}", params.len() + 1));
+            params.push(Box::new(created_after.to_rfc3339()));
+        }
+        
+        if let Some(created_before) = &query.created_before {
+            sql.push_str(&format!(" AND created_at <= ?{}", params.len() + 1));
+            params.push(Box::new(created_before.to_rfc3339()));
+        }
+        
+        if let Some(min_queries) = &query.min_queries {
+            sql.push_str(&format!(" AND query_count >= ?{}", params.len() + 1));
+            params.push(Box::new(*min_queries));
+        }
+        
+        if let Some(idle_duration) = &query.idle_longer_than {
+            let cutoff_time = Utc::now() - *idle_duration;
+            sql.push_str(&format!(" AND last_activity <= ?{}", params.len() + 1));
+            params.push(Box::new(cutoff_time.to_rfc3339()));
+        }
+        
+        sql.push_str(" ORDER BY last_activity DESC, session_id");
+        
+        let mut stmt = self.connection.prepare(&sql)?;
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        
+        let sessions = stmt.query_map(&param_refs[..], |row| {
+            Ok(self.row_to_mcp_session(row)?)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(sessions)
+    }
+
+    /// Updates an MCP session
+    pub fn update_mcp_session(&self, session: &McpQuerySession) -> Result<()> {
+        session.validate().map_err(StorageError::Validation)?;
+        
+        let rows_affected = self.connection.execute(
+            r#"
+            UPDATE mcp_query_sessions SET 
+                client_name = ?2, active_index_id = ?3, last_activity = ?4,
+                query_count = ?5, status = ?6, client_metadata = ?7
+            WHERE session_id = ?1
+            "#,
+            params![
+                session.session_id.to_string(),
+                session.client_name,
+                session.active_index_id.map(|id| id.to_string()),
+                session.last_activity.to_rfc3339(),
+                session.query_count,
+                session.status.as_str(),
+                session.client_metadata
+            ],
+        )?;
+        
+        if rows_affected == 0 {
+            return Err(StorageError::not_found("MCP session", session.session_id));
+        }
+        
+        Ok(())
+    }
+
+    /// Deletes an MCP session
+    pub fn delete_mcp_session(&self, session_id: &Uuid) -> Result<()> {
+        let rows_affected = self.connection.execute(
+            "DELETE FROM mcp_query_sessions WHERE session_id = ?1",
+            [session_id.to_string()],
+        )?;
+        
+        if rows_affected == 0 {
+            return Err(StorageError::not_found("MCP session", session_id));
+        }
+        
+        Ok(())
+    }
+
+    // === Admin Audit Trail ===
+
+    /// Appends an entry to the audit trail
+    pub fn record_audit(&self, entry: &AuditEntry) -> Result<AuditEntry> {
+        self.connection.execute(
+            r#"
+            INSERT INTO admin_audit (operation, index_id, index_name, actor, session_id, parameters, recorded_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+            params![
+                entry.operation.as_str(),
+                entry.index_id.map(|id| id.to_string()),
+                entry.index_name,
+                entry.actor,
+                entry.session_id,
+                entry.parameters.to_string(),
+                entry.recorded_at.to_rfc3339()
+            ],
+        )?;
+
+        let mut recorded = entry.clone();
+        recorded.id = Some(self.connection.last_insert_rowid());
+        Ok(recorded)
+    }
+
+    /// Lists audit entries, newest first
+    pub fn list_audit_entries(
+        &self,
+        index_name: Option<&str>,
+        operation: Option<AuditOperation>,
+        since: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, operation, index_id, index_name, actor, session_id, parameters, recorded_at
+            FROM admin_audit
+            WHERE (?1 IS NULL OR index_name = ?1)
+              AND (?2 IS NULL OR operation = ?2)
+              AND (?3 IS NULL OR recorded_at >= ?3)
+            ORDER BY id DESC LIMIT ?4
+            "#
+        )?;
+
+        let entries = stmt.query_map(
+            params![index_name, operation.map(|operation| operation.as_str()), since.map(|since| since.to_rfc3339()), limit as i64],
+            |row| self.row_to_audit_entry(row),
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    /// Records an operation if an audit actor is set
+    fn audit(&self, operation: AuditOperation, index: Option<(&Uuid, &str)>, parameters: serde_json::Value) -> Result<()> {
+        let Some(actor) = &self.audit_actor else { return Ok(()) };
+        let mut entry = AuditEntry::new(operation, actor, parameters);
+        if let Some((id, name)) = index {
+            entry = entry.with_index(*id, name);
+        }
+        self.record_audit(&entry)?;
+        Ok(())
+    }
+
+    /// Records an operation on an index known only by id
+    fn audit_index(&self, operation: AuditOperation, index_id: &Uuid, parameters: serde_json::Value) -> Result<()> {
+        if self.audit_actor.is_none() {
+            return Ok(());
+        }
+        let name = self.index_name(index_id)?.unwrap_or_default();
+        self.audit(operation, Some((index_id, &name)), parameters)
+    }
+
+    fn index_name(&self, index_id: &Uuid) -> Result<Option<String>> {
+        let mut stmt = self.connection.prepare("SELECT name FROM code_indices WHERE id = ?1")?;
+        let mut rows = stmt.query([index_id.to_string()])?;
+        Ok(match rows.next()? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+
+    // === Slow Query Log ===
+
+    /// Records a slow query, discarding the oldest entries beyond the log capacity
+    pub fn record_slow_query(&self, query: &SlowQuery) -> Result<()> {
+        self.connection.execute(
+            r#"
+            INSERT INTO slow_queries (recorded_at, operation, sql_text, params_summary, duration_ms, rows_returned)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+            params![
+                query.recorded_at.to_rfc3339(),
+                query.operation,
+                query.sql,
+                query.params_summary,
+                query.duration_ms,
+                query.rows_returned as i64
+            ],
+        )?;
+
+        self.connection.execute(
+            "DELETE FROM slow_queries WHERE id <= (SELECT MAX(id) FROM slow_queries) - ?1",
+            [MAX_SLOW_QUERY_ENTRIES as i64],
+        )?;
+
+        Ok(())
+    }
+
+    /// Lists logged slow queries, slowest first
+    pub fn list_slow_queries(&self, limit: usize) -> Result<Vec<SlowQuery>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT id, recorded_at, operation, sql_text, params_summary, duration_ms, rows_returned
+            FROM slow_queries ORDER BY duration_ms DESC, id LIMIT ?1
+            "#
+        )?;
+
+        let queries = stmt.query_map([limit as i64], |row| {
+            let recorded_at: String = row.get(1)?;
+            let rows_returned: i64 = row.get(6)?;
+            Ok(SlowQuery {
+                id: Some(row.get(0)?),
+                recorded_at: DateTime::parse_from_rfc3339(&recorded_at)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+                operation: row.get(2)?,
+                sql: row.get(3)?,
+                params_summary: row.get(4)?,
+                duration_ms: row.get(5)?,
+                rows_returned: rows_returned as u64,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(queries)
+    }
+
+    /// Removes all slow query log entries
+    pub fn clear_slow_queries(&self) -> Result<usize> {
+        Ok(self.connection.execute("DELETE FROM slow_queries", [])?)
+    }
+
+    // === Utility Methods ===
+
+    /// Gets statistics for all indices
+    pub fn get_index_statistics(&self) -> Result<HashMap<String, IndexStatistics>> {
+        let mut stmt = self.connection.prepare(
+            r#"
+            SELECT 
+                ci.id, ci.name, ci.total_files, ci.total_symbols,
+                COUNT(DISTINCT fm.id) as file_count,
+                COUNT(DISTINCT ce.id) as element_count,
+                COUNT(DISTINCT sr.id) as relationship_count
+            FROM code_indices ci
+            LEFT JOIN file_metadata fm ON ci.id = fm.index_id AND fm.processing_state = 'indexed'
+            LEFT JOIN code_elements ce ON ci.id = ce.index_id  
+            LEFT JOIN symbol_relationships sr ON ce.id = sr.from_symbol_id
+            GROUP BY ci.id, ci.name, ci.total_files, ci.total_symbols
+            "#
+        )?;
+        
+        let mut stats_map = HashMap::new();
+        
+        let rows = stmt.query_map([], |row| {
+            let index_id: String = row.get(0)?;
+            let name: String = row.get(1)?;
+            let total_files: u32 = row.get(2)?;
+            let total_symbols: u32 = row.get(3)?;
+            let actual_file_count: i64 = row.get(4)?;
+            let actual_element_count: i64 = row.get(5)?;
+            let relationship_count: i64 = row.get(6)?;
+            
             Ok((name.clone(), IndexStatistics {
-                index_id: Uuid::parse_str(&index_id).unwrap(),
+                index_id: Uuid::parse_str(&index_id)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(0, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
                 name,
                 reported_files: total_files,
                 reported_symbols: total_symbols,
@@ -816,240 +3431,1331 @@ impl Repository {
             stats_map.insert(name, stats);
         }
         
-        Ok(stats_map)
+        Ok(stats_map)
+    }
+
+    /// Checks an index for inconsistencies and, with `repair`, fixes them in one transaction
+    ///
+    /// Finds symbols of files the index has no metadata for, relationships
+    /// whose symbols are gone, indexed files whose symbol count disagrees with
+    /// their stored symbols, and totals in `code_indices` that drifted from
+    /// the rows. Repairing deletes the first two, recounts the files and
+    /// resets the totals. The returned health describes what was found.
+    pub fn verify_index(&self, index_id: &Uuid, repair: bool) -> Result<IndexHealth> {
+        let id = index_id.to_string();
+        let transaction = Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let count = |sql: &str| -> Result<u64> { Ok(self.connection.query_row(sql, [&id], |row| row.get::<_, i64>(0))? as u64) };
+
+        const ORPHANED_ELEMENTS: &str = "FROM code_elements ce WHERE ce.index_id = ?1 AND NOT EXISTS \
+             (SELECT 1 FROM file_metadata fm WHERE fm.index_id = ce.index_id AND fm.file_path = ce.file_path)";
+        // Either end missing, or the source symbol gone with the target still in this index
+        const DANGLING_RELATIONSHIPS: &str = "FROM symbol_relationships sr WHERE \
+             (sr.from_symbol_id IN (SELECT id FROM code_elements WHERE index_id = ?1) \
+              AND sr.to_symbol_id NOT IN (SELECT id FROM code_elements)) \
+             OR (sr.to_symbol_id IN (SELECT id FROM code_elements WHERE index_id = ?1) \
+              AND sr.from_symbol_id NOT IN (SELECT id FROM code_elements))";
+        const STALE_FILES: &str = "FROM file_metadata fm WHERE fm.index_id = ?1 AND fm.processing_state = 'indexed' \
+             AND fm.symbol_count != (SELECT COUNT(*) FROM code_elements ce WHERE ce.index_id = fm.index_id AND ce.file_path = fm.file_path)";
+
+        let index = self.get_code_index(index_id)?.ok_or_else(|| StorageError::not_found("Code index", index_id))?;
+        let mut health = IndexHealth {
+            orphaned_elements: count(&format!("SELECT COUNT(*) {}", ORPHANED_ELEMENTS))?,
+            dangling_relationships: count(&format!("SELECT COUNT(*) {}", DANGLING_RELATIONSHIPS))?,
+            stale_files: count(&format!("SELECT COUNT(*) {}", STALE_FILES))?,
+            reported_files: index.total_files as u64,
+            reported_symbols: index.total_symbols as u64,
+            actual_files: 0,
+            actual_symbols: 0,
+            repaired: false,
+        };
+
+        if repair && (health.orphaned_elements > 0 || health.dangling_relationships > 0 || health.stale_files > 0) {
+            self.connection.execute(&format!("DELETE FROM code_elements WHERE id IN (SELECT ce.id {})", ORPHANED_ELEMENTS), [&id])?;
+            self.connection.execute(&format!("DELETE FROM symbol_relationships WHERE id IN (SELECT sr.id {})", DANGLING_RELATIONSHIPS), [&id])?;
+            self.connection.execute(
+                &format!(
+                    "UPDATE file_metadata SET symbol_count = (SELECT COUNT(*) FROM code_elements ce \
+                     WHERE ce.index_id = file_metadata.index_id AND ce.file_path = file_metadata.file_path) \
+                     WHERE id IN (SELECT fm.id {})",
+                    STALE_FILES
+                ),
+                [&id],
+            )?;
+        }
+
+        health.actual_files = count("SELECT COUNT(*) FROM file_metadata WHERE index_id = ?1 AND processing_state = 'indexed'")?;
+        health.actual_symbols = count("SELECT COUNT(*) FROM code_elements WHERE index_id = ?1")?;
+        if repair && !health.is_healthy() {
+            self.connection.execute(
+                "UPDATE code_indices SET total_files = ?2, total_symbols = ?3 WHERE id = ?1",
+                params![id, health.actual_files as i64, health.actual_symbols as i64],
+            )?;
+            if health.orphaned_elements > 0 || health.dangling_relationships > 0 {
+                self.refresh_symbol_popularity(index_id)?;
+            }
+            health.repaired = true;
+        }
+        transaction.commit()?;
+        Ok(health)
+    }
+
+    // === Private Helper Methods ===
+
+    fn row_to_audit_entry(&self, row: &Row) -> rusqlite::Result<AuditEntry> {
+        let operation: String = row.get(1)?;
+        let index_id: Option<String> = row.get(2)?;
+        let parameters: String = row.get(6)?;
+        let recorded_at: String = row.get(7)?;
+
+        Ok(AuditEntry {
+            id: Some(row.get(0)?),
+            operation: AuditOperation::parse(&operation)
+                .ok_or_else(|| rusqlite::Error::InvalidColumnType(1, "Invalid audit operation".to_string(), rusqlite::types::Type::Text))?,
+            index_id: index_id
+                .map(|id| Uuid::parse_str(&id))
+                .transpose()
+                .map_err(|_| rusqlite::Error::InvalidColumnType(2, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            index_name: row.get(3)?,
+            actor: row.get(4)?,
+            session_id: row.get(5)?,
+            parameters: serde_json::from_str(&parameters)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(6, "Invalid JSON".to_string(), rusqlite::types::Type::Text))?,
+            recorded_at: DateTime::parse_from_rfc3339(&recorded_at)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    fn select_code_elements(&self, operation: &str, query: &CodeElementQuery) -> Result<Vec<CodeElement>> {
+        let started = Instant::now();
+        let (sql, params) = query.to_sql("code_elements", ElementColumn::ALL);
+
+        let mut stmt = self.connection.prepare(&sql)?;
+        let elements = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            self.row_to_code_element(row)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        self.record_if_slow(operation, &sql, || describe_params(&params), started, elements.len());
+        Ok(elements)
+    }
+
+    fn select_code_elements_page(&self, operation: &str, query: &CodeElementQuery, page: &QueryPage) -> Result<Page<CodeElement>> {
+        let start = page.start().map_err(StorageError::Validation)?;
+        let paged = page.apply(query.clone()).map_err(StorageError::Validation)?;
+        let elements = self.select_code_elements(operation, &paged)?;
+
+        // A short page ends the result set, which spares the count
+        let returned = elements.len() as u64;
+        let total_count = if returned < page.limit && (returned > 0 || start == 0) {
+            start + returned
+        } else {
+            self.count_code_elements(query)?
+        };
+        page.page(elements, total_count).map_err(StorageError::Validation)
+    }
+
+    fn select_symbol_relationships(&self, operation: &str, query: &SymbolRelationshipQuery) -> Result<Vec<SymbolRelationship>> {
+        let started = Instant::now();
+        let (sql, params) = query.to_sql("symbol_relationships", RelationshipColumn::ALL);
+
+        let mut stmt = self.connection.prepare(&sql)?;
+        let relationships = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            self.row_to_symbol_relationship(row)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        self.record_if_slow(operation, &sql, || describe_params(&params), started, relationships.len());
+        Ok(relationships)
+    }
+
+    /// Logs the query if it ran longer than the slow query threshold
+    ///
+    /// Logging failures (e.g. a read-only database) never fail the query itself.
+    fn record_if_slow(&self, operation: &str, sql: &str, params: impl FnOnce() -> String, started: Instant, rows: usize) {
+        let elapsed = started.elapsed();
+        if let Some(timings) = &self.query_timings {
+            timings.record(operation, elapsed);
+        }
+        match self.slow_query_threshold {
+            Some(threshold) if elapsed >= threshold => {}
+            _ => return,
+        }
+
+        let query = SlowQuery::new(operation, sql, params(), elapsed, rows);
+        warn!("Slow query in {}: {:.1} ms, {} rows ({})", operation, query.duration_ms, rows, query.params_summary);
+        if let Err(e) = self.record_slow_query(&query) {
+            warn!("Failed to record slow query: {}", e);
+        }
+    }
+
+    fn row_to_code_index(&self, row: &Row) -> rusqlite::Result<CodeIndex> {
+        let id_str: String = row.get(0)?;
+        let created_at_str: String = row.get(3)?;
+        let updated_at_str: String = row.get(4)?;
+        
+        Ok(CodeIndex {
+            id: Uuid::parse_str(&id_str).map_err(|_| rusqlite::Error::InvalidColumnType(0, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            name: row.get(1)?,
+            base_path: row.get(2)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            total_files: row.get(5)?,
+            total_symbols: row.get(6)?,
+            index_version: row.get(7)?,
+        })
+    }
+
+    fn row_to_file_metadata(&self, row: &Row) -> rusqlite::Result<FileMetadata> {
+        let index_id_str: String = row.get(1)?;
+        let last_modified_str: String = row.get(4)?;
+        let indexed_at_str: String = row.get(7)?;
+        let detail_str: String = row.get(9)?;
+        
+        Ok(FileMetadata {
+            id: Some(row.get(0)?),
+            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            file_path: row.get(2)?,
+            file_hash: row.get(3)?,
+            last_modified: DateTime::parse_from_rfc3339(&last_modified_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            size_bytes: row.get(5)?,
+            symbol_count: row.get(6)?,
+            indexed_at: DateTime::parse_from_rfc3339(&indexed_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            detail: FileDetail::parse(&detail_str)
+                .ok_or_else(|| rusqlite::Error::InvalidColumnType(9, "Invalid file detail".to_string(), rusqlite::types::Type::Text))?,
+        })
+    }
+
+    fn row_to_annotation(&self, row: &Row) -> rusqlite::Result<SymbolAnnotation> {
+        let index_id_str: String = row.get(1)?;
+        let scope: String = row.get(3)?;
+        let kind_str: String = row.get(5)?;
+        let created_at_str: String = row.get(7)?;
+
+        Ok(SymbolAnnotation {
+            id: Some(row.get(0)?),
+            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            symbol_name: row.get(2)?,
+            scope: Some(scope).filter(|scope| !scope.is_empty()),
+            author: row.get(4)?,
+            kind: AnnotationKind::parse(&kind_str)
+                .ok_or_else(|| rusqlite::Error::InvalidColumnType(5, "Invalid annotation kind".to_string(), rusqlite::types::Type::Text))?,
+            text: row.get(6)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    fn row_to_build_configuration(&self, row: &Row) -> rusqlite::Result<BuildConfiguration> {
+        let index_id_str: String = row.get(1)?;
+        let defines_str: String = row.get(3)?;
+        let undefines_str: String = row.get(4)?;
+        let created_at_str: String = row.get(5)?;
+
+        Ok(BuildConfiguration {
+            id: Some(row.get(0)?),
+            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            name: row.get(2)?,
+            defines: serde_json::from_str(&defines_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "Invalid defines".to_string(), rusqlite::types::Type::Text))?,
+            undefines: serde_json::from_str(&undefines_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid undefines".to_string(), rusqlite::types::Type::Text))?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(5, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    fn row_to_index_revision(&self, row: &Row) -> rusqlite::Result<IndexRevision> {
+        let index_id_str: String = row.get(1)?;
+        let created_at_str: String = row.get(6)?;
+        let switched_at_str: String = row.get(7)?;
+
+        Ok(IndexRevision {
+            id: Some(row.get(0)?),
+            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            name: row.get(2)?,
+            commit: row.get(3)?,
+            parent_id: row.get(4)?,
+            is_current: row.get(5)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(6, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            switched_at: DateTime::parse_from_rfc3339(&switched_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    fn row_to_saved_query(&self, row: &Row) -> rusqlite::Result<SavedQuery> {
+        let index_id_str: String = row.get(1)?;
+        let created_at_str: String = row.get(5)?;
+        let updated_at_str: String = row.get(6)?;
+        let last_checked_at_str: Option<String> = row.get(8)?;
+
+        Ok(SavedQuery {
+            id: Some(row.get(0)?),
+            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            name: row.get(2)?,
+            query: row.get(3)?,
+            description: row.get(4)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(5, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(6, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            watched: row.get(7)?,
+            last_checked_at: last_checked_at_str
+                .map(|value| {
+                    DateTime::parse_from_rfc3339(&value)
+                        .map(|dt| dt.with_timezone(&Utc))
+                        .map_err(|_| rusqlite::Error::InvalidColumnType(8, "Invalid datetime".to_string(), rusqlite::types::Type::Text))
+                })
+                .transpose()?,
+        })
+    }
+
+    fn row_to_code_element(&self, row: &Row) -> rusqlite::Result<CodeElement> {
+        let index_id_str: String = row.get(1)?;
+        let symbol_type_str: String = row.get(3)?;
+        let access_modifier_str: Option<String> = row.get(9)?;
+        
+        let symbol_type = match symbol_type_str.as_str() {
+            "function" => SymbolType::Function,
+            "class" => SymbolType::Class,
+            "struct" => SymbolType::Struct,
+            "variable" => SymbolType::Variable,
+            "macro" => SymbolType::Macro,
+            "namespace" => SymbolType::Namespace,
+            "enum" => SymbolType::Enum,
+            "typedef" => SymbolType::Typedef,
+            "union" => SymbolType::Union,
+            "template" => SymbolType::Template,
+            "constructor" => SymbolType::Constructor,
+            "destructor" => SymbolType::Destructor,
+            "operator" => SymbolType::Operator,
+            "field" => SymbolType::Field,
+            "enum_constant" => SymbolType::EnumConstant,
+            "lambda" => SymbolType::Lambda,
+            "unknown" => SymbolType::Unknown,
+            _ => return Err(rusqlite::Error::InvalidColumnType(3, "Invalid symbol type".to_string(), rusqlite::types::Type::Text)),
+        };
+        
+        let access_modifier = access_modifier_str.as_ref().map(|s| match s.as_str() {
+            "public" => Ok(AccessModifier::Public),
+            "private" => Ok(AccessModifier::Private),
+            "protected" => Ok(AccessModifier::Protected),
+            _ => Err(rusqlite::Error::InvalidColumnType(9, "Invalid access modifier".to_string(), rusqlite::types::Type::Text)),
+        }).transpose()?;
+        
+        Ok(CodeElement {
+            id: Some(row.get(0)?),
+            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            symbol_name: row.get(2)?,
+            symbol_type,
+            file_path: row.get(4)?,
+            line_number: row.get(5)?,
+            column_number: row.get(6)?,
+            definition_hash: row.get(7)?,
+            scope: row.get(8)?,
+            access_modifier,
+            is_declaration: row.get(10)?,
+            signature: row.get(11)?,
+            memory_section: row.get(12)?,
+            documentation: row.get(13)?,
+            usr: row.get(14)?,
+            body: None,
+        })
+    }
+
+    fn row_to_symbol_relationship(&self, row: &Row) -> rusqlite::Result<SymbolRelationship> {
+        let relationship_type_str: String = row.get(3)?;
+        
+        let relationship_type = match relationship_type_str.as_str() {
+            "inherits" => RelationshipType::Inherits,
+            "uses" => RelationshipType::Uses,
+            "includes" => RelationshipType::Includes,
+            "calls" => RelationshipType::Calls,
+            "defines" => RelationshipType::Defines,
+            "instantiates" => RelationshipType::Instantiates,
+            "contained_in" => RelationshipType::ContainedIn,
+            "friend" => RelationshipType::Friend,
+            "overrides" => RelationshipType::Overrides,
+            "specializes" => RelationshipType::Specializes,
+            "referenced_as_callback" => RelationshipType::ReferencedAsCallback,
+            _ => return Err(rusqlite::Error::InvalidColumnType(3, "Invalid relationship type".to_string(), rusqlite::types::Type::Text)),
+        };
+        
+        Ok(SymbolRelationship {
+            id: Some(row.get(0)?),
+            from_symbol_id: row.get(1)?,
+            to_symbol_id: row.get(2)?,
+            relationship_type,
+            file_path: row.get(4)?,
+            line_number: row.get(5)?,
+        })
+    }
+
+    fn row_to_mcp_session(&self, row: &Row) -> rusqlite::Result<McpQuerySession> {
+        let session_id_str: String = row.get(0)?;
+        let active_index_id_str: Option<String> = row.get(2)?;
+        let created_at_str: String = row.get(3)?;
+        let last_activity_str: String = row.get(4)?;
+        let status_str: String = row.get(6)?;
+        
+        let status = match status_str.as_str() {
+            "active" => SessionStatus::Active,
+            "inactive" => SessionStatus::Inactive,
+            "terminated" => SessionStatus::Terminated,
+            "error" => SessionStatus::Error,
+            _ => return Err(rusqlite::Error::InvalidColumnType(6, "Invalid session status".to_string(), rusqlite::types::Type::Text)),
+        };
+        
+        let active_index_id = active_index_id_str
+            .map(|s| Uuid::parse_str(&s))
+            .transpose()
+            .map_err(|_| rusqlite::Error::InvalidColumnType(2, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?;
+        
+        Ok(McpQuerySession {
+            session_id: Uuid::parse_str(&session_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(0, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
+            client_name: row.get(1)?,
+            active_index_id,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            last_activity: DateTime::parse_from_rfc3339(&last_activity_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            query_count: row.get(5)?,
+            status,
+            client_metadata: row.get(7)?,
+            tool_calls: BTreeMap::new(),
+        })
+    }
+}
+
+/// Everything stored for an index besides the index row
+///
+/// Symbol tags and configuration exclusions name symbols and
+/// configurations by the ids of the database the contents were read from;
+/// [`Repository::restore_index`] rewrites them to the ids it stores.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct IndexContents {
+    pub tags: BTreeMap<String, String>,
+    pub files: Vec<FileMetadata>,
+    pub elements: Vec<CodeElement>,
+    pub relationships: Vec<SymbolRelationship>,
+    #[serde(default)]
+    pub symbol_tags: Vec<(i64, String)>,
+    #[serde(default)]
+    pub annotations: Vec<SymbolAnnotation>,
+    #[serde(default)]
+    pub configurations: Vec<BuildConfiguration>,
+    /// Configuration id paired with a symbol it compiles out
+    #[serde(default)]
+    pub configuration_exclusions: Vec<(i64, i64)>,
+    #[serde(default)]
+    pub walk_rules: Option<WalkRules>,
+}
+
+/// A code element matched by full-text search
+#[derive(Debug, Clone)]
+pub struct TextSearchHit {
+    pub element: CodeElement,
+    /// Relevance, higher is better (negated BM25)
+    pub score: f64,
+    /// Best-matching column excerpt with matches in [brackets]
+    pub snippet: String,
+}
+
+/// Processing progress of a code index
+///
+/// Files are counted once the indexer has discovered them, so while an index
+/// is being created the totals can still grow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexCoverage {
+    pub state: IndexState,
+    pub indexed_files: u64,
+    /// Queued or being processed
+    pub pending_files: u64,
+    pub failed_files: u64,
+}
+
+impl IndexCoverage {
+    /// Returns true while indexing is in progress and answers may be incomplete
+    pub fn is_partial(&self) -> bool {
+        matches!(self.state, IndexState::Creating | IndexState::Updating)
+    }
+
+    /// Files discovered so far
+    pub fn known_files(&self) -> u64 {
+        self.indexed_files + self.pending_files + self.failed_files
+    }
+
+    /// Share of known files that are indexed, 0-100
+    pub fn percent_indexed(&self) -> f64 {
+        match self.known_files() {
+            0 => 0.0,
+            known => self.indexed_files as f64 * 100.0 / known as f64,
+        }
+    }
+}
+
+/// Stored symbol bodies of an index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SymbolBodyStats {
+    pub count: u64,
+    pub compressed_bytes: u64,
+    pub uncompressed_bytes: u64,
+}
+
+impl SymbolBodyStats {
+    /// Original size over stored size; 1.0 when nothing is stored
+    pub fn compression_ratio(&self) -> f64 {
+        match self.compressed_bytes {
+            0 => 1.0,
+            compressed => self.uncompressed_bytes as f64 / compressed as f64,
+        }
+    }
+}
+
+/// Inconsistencies found in a code index by `Repository::verify_index`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexHealth {
+    /// Symbols of files the index has no metadata for
+    pub orphaned_elements: u64,
+    /// Relationships referring to symbols that no longer exist
+    pub dangling_relationships: u64,
+    /// Indexed files whose symbol count disagrees with their symbols
+    pub stale_files: u64,
+    /// Totals recorded in `code_indices` when the check started
+    pub reported_files: u64,
+    pub reported_symbols: u64,
+    /// Totals counted from the rows, after any repair
+    pub actual_files: u64,
+    pub actual_symbols: u64,
+    /// Whether the problems were repaired
+    pub repaired: bool,
+}
+
+impl IndexHealth {
+    /// Returns true if the recorded totals differ from the counted ones
+    pub fn has_count_drift(&self) -> bool {
+        self.reported_files != self.actual_files || self.reported_symbols != self.actual_symbols
+    }
+
+    /// Returns true if nothing was found
+    pub fn is_healthy(&self) -> bool {
+        self.orphaned_elements == 0 && self.dangling_relationships == 0 && self.stale_files == 0 && !self.has_count_drift()
     }
+}
 
-    // === Private Helper Methods ===
+/// Statistics for a code index
+#[derive(Debug, Clone)]
+pub struct IndexStatistics {
+    pub index_id: Uuid,
+    pub name: String,
+    pub reported_files: u32,
+    pub reported_symbols: u32,
+    pub actual_files: u32,
+    pub actual_elements: u32,
+    pub relationships: u32,
+}
 
-    fn row_to_code_index(&self, row: &Row) -> Result<CodeIndex> {
-        let id_str: String = row.get(0)?;
-        let created_at_str: String = row.get(3)?;
-        let updated_at_str: String = row.get(4)?;
-        let state_str: String = row.get(8)?;
-        
-        Ok(CodeIndex {
-            id: Uuid::parse_str(&id_str).map_err(|_| rusqlite::Error::InvalidColumnType(0, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
-            name: row.get(1)?,
-            base_path: row.get(2)?,
-            created_at: DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc),
-            updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc),
-            total_files: row.get(5)?,
-            total_symbols: row.get(6)?,
-            index_version: row.get(7)?,
-        })
+impl IndexStatistics {
+    /// Returns true if the reported counts match actual counts
+    pub fn is_consistent(&self) -> bool {
+        self.reported_files == self.actual_files && self.reported_symbols == self.actual_elements
+    }
+    
+    /// Returns the difference between reported and actual file counts
+    pub fn file_count_difference(&self) -> i32 {
+        self.actual_files as i32 - self.reported_files as i32
+    }
+    
+    /// Returns the difference between reported and actual symbol counts
+    pub fn symbol_count_difference(&self) -> i32 {
+        self.actual_elements as i32 - self.reported_symbols as i32
     }
+}
 
-    fn row_to_file_metadata(&self, row: &Row) -> Result<FileMetadata> {
-        let index_id_str: String = row.get(1)?;
-        let last_modified_str: String = row.get(4)?;
-        let indexed_at_str: String = row.get(7)?;
-        
-        Ok(FileMetadata {
-            id: Some(row.get(0)?),
-            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
-            file_path: row.get(2)?,
-            file_hash: row.get(3)?,
-            last_modified: DateTime::parse_from_rfc3339(&last_modified_str)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc),
-            size_bytes: row.get(5)?,
-            symbol_count: row.get(6)?,
-            indexed_at: DateTime::parse_from_rfc3339(&indexed_at_str)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc),
-        })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use chrono::TimeZone;
+
+    fn create_test_repository() -> Repository {
+        let config = DatabaseConfig::in_memory();
+        let manager = DatabaseManager::new(config).unwrap();
+        let connection = manager.connect().unwrap();
+        Repository::new(connection)
     }
 
-    fn row_to_code_element(&self, row: &Row) -> Result<CodeElement> {
-        let index_id_str: String = row.get(1)?;
-        let symbol_type_str: String = row.get(3)?;
-        let access_modifier_str: Option<String> = row.get(9)?;
+    #[test]
+    fn test_code_index_crud() {
+        let repo = create_test_repository();
+        let index = CodeIndex::new("Test Index".to_string(), "/test/path".to_string());
+        let index_id = index.id;
         
-        let symbol_type = match symbol_type_str.as_str() {
-            "function" => SymbolType::Function,
-            "class" => SymbolType::Class,
-            "struct" => SymbolType::Struct,
-            "variable" => SymbolType::Variable,
-            "macro" => SymbolType::Macro,
-            "namespace" => SymbolType::Namespace,
-            "enum" => SymbolType::Enum,
-            "typedef" => SymbolType::Typedef,
-            "union" => SymbolType::Union,
-            "template" => SymbolType::Template,
-            "constructor" => SymbolType::Constructor,
-            "destructor" => SymbolType::Destructor,
-            "operator" => SymbolType::Operator,
-            _ => return Err(rusqlite::Error::InvalidColumnType(3, "Invalid symbol type".to_string(), rusqlite::types::Type::Text)),
-        };
+        // Create
+        let created_index = repo.create_code_index(index).unwrap();
+        assert_eq!(created_index.name, "Test Index");
         
-        let access_modifier = access_modifier_str.as_ref().map(|s| match s.as_str() {
-            "public" => Ok(AccessModifier::Public),
-            "private" => Ok(AccessModifier::Private),
-            "protected" => Ok(AccessModifier::Protected),
-            _ => Err(rusqlite::Error::InvalidColumnType(9, "Invalid access modifier".to_string(), rusqlite::types::Type::Text)),
-        }).transpose()?;
+        // Read by ID
+        let retrieved_index = repo.get_code_index(&index_id).unwrap().unwrap();
+        assert_eq!(retrieved_index.name, "Test Index");
+        assert_eq!(retrieved_index.base_path, "/test/path");
         
-        Ok(CodeElement {
-            id: Some(row.get(0)?),
-            index_id: Uuid::parse_str(&index_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(1, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
-            symbol_name: row.get(2)?,
-            symbol_type,
-            file_path: row.get(4)?,
-            line_number: row.get(5)?,
-            column_number: row.get(6)?,
-            definition_hash: row.get(7)?,
-            scope: row.get(8)?,
-            access_modifier,
-            is_declaration: row.get(10)?,
-            signature: row.get(11)?,
-        })
+        // Read by name
+        let retrieved_by_name = repo.get_code_index_by_name("Test Index").unwrap().unwrap();
+        assert_eq!(retrieved_by_name.id, index_id);
+        
+        // Update
+        let mut updated_index = retrieved_index;
+        updated_index.name = "Updated Test Index".to_string();
+        repo.update_code_index(&updated_index).unwrap();
+        
+        let retrieved_updated = repo.get_code_index(&index_id).unwrap().unwrap();
+        assert_eq!(retrieved_updated.name, "Updated Test Index");
+        
+        // List
+        let indices = repo.list_code_indices().unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices[0].name, "Updated Test Index");
+        
+        // Delete
+        repo.delete_code_index(&index_id).unwrap();
+        assert!(repo.get_code_index(&index_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_index_coverage() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Partial".to_string(), "/partial".to_string())).unwrap();
+        for (path, state) in [
+            ("src/a.cpp", FileProcessingState::Indexed),
+            ("src/b.cpp", FileProcessingState::Indexed),
+            ("src/c.cpp", FileProcessingState::Processing),
+            ("src/d.cpp", FileProcessingState::Error),
+        ] {
+            let metadata = repo.create_file_metadata(FileMetadata::new(index.id, path.to_string(), "a".repeat(64), Utc::now(), 10)).unwrap();
+            repo.update_file_processing_state(metadata.id.unwrap(), state).unwrap();
+        }
+
+        let coverage = repo.get_index_coverage(&index.id).unwrap();
+        assert!(coverage.is_partial());
+        assert_eq!((coverage.indexed_files, coverage.pending_files, coverage.failed_files), (2, 1, 1));
+        assert_eq!(coverage.percent_indexed(), 50.0);
+        assert!(repo.is_file_indexed(&index.id, "src/a.cpp").unwrap());
+        assert!(!repo.is_file_indexed(&index.id, "src/c.cpp").unwrap());
+
+        repo.update_code_index_state(&index.id, IndexState::Active).unwrap();
+        assert!(!repo.get_index_coverage(&index.id).unwrap().is_partial());
+        assert!(repo.get_index_coverage(&Uuid::new_v4()).unwrap_err().is_client_error());
+    }
+
+    #[test]
+    fn test_storage_error_kinds() {
+        let repo = create_test_repository();
+
+        let invalid = repo.create_code_index(CodeIndex::new(String::new(), "/test".to_string()));
+        assert!(matches!(invalid, Err(StorageError::Validation(_))));
+
+        let missing = repo.delete_code_index(&Uuid::new_v4());
+        assert!(matches!(missing, Err(StorageError::NotFound(_))));
+
+        repo.create_code_index(CodeIndex::new("dup".to_string(), "/a".to_string())).unwrap();
+        let duplicate = repo.create_code_index(CodeIndex::new("dup".to_string(), "/b".to_string()));
+        assert!(matches!(duplicate, Err(StorageError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_create_or_update_code_element() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let element = CodeElement::new(
+            index.id,
+            "draw".to_string(),
+            SymbolType::Function,
+            "src/shape.cpp".to_string(),
+            10,
+            5,
+            "a".repeat(64),
+        );
+
+        let first = repo.create_or_update_code_element(element.clone()).unwrap();
+        let mut changed = element.clone();
+        changed.definition_hash = "b".repeat(64);
+        changed.signature = Some("void draw()".to_string());
+        let second = repo.create_or_update_code_element(changed).unwrap();
+
+        assert_eq!(first.id, second.id);
+        let elements = repo.list_code_elements_by_file(&index.id, "src/shape.cpp").unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].definition_hash, "b".repeat(64));
+        assert_eq!(elements[0].signature.as_deref(), Some("void draw()"));
+
+        let duplicate = repo.create_code_element(element);
+        assert!(matches!(duplicate, Err(StorageError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_typed_element_query() {
+        use crate::lib::storage::query::SortDirection;
+
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        for line in 1..=5 {
+            repo.create_code_element(CodeElement::new(
+                index.id,
+                format!("fn{}", line),
+                if line % 2 == 0 { SymbolType::Class } else { SymbolType::Function },
+                "src/test.cpp".to_string(),
+                line,
+                1,
+                "a".repeat(64),
+            )).unwrap();
+        }
+
+        let query = CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+            .filter(Filter::eq(ElementColumn::SymbolType, SymbolType::Function))
+            .order_by(ElementColumn::LineNumber, SortDirection::Descending)
+            .limit(2)
+            .offset(1);
+
+        let names: Vec<String> = repo.query_code_elements(&query).unwrap().into_iter().map(|e| e.symbol_name).collect();
+        assert_eq!(names, vec!["fn3", "fn1"]);
+    }
+
+    #[test]
+    fn test_fuzzy_candidates_apply_filters_first() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        for (line, name) in ["Widget", "WidgetA", "WidgetB", "Widget_C"].iter().enumerate() {
+            repo.create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/ui.cpp".to_string(), line as u32 + 1, 1, "a".repeat(64)))
+                .unwrap();
+        }
+        let class = repo
+            .create_code_element(CodeElement::new(index.id, "Widgets".to_string(), SymbolType::Class, "src/ui.h".to_string(), 1, 1, "a".repeat(64)))
+            .unwrap();
+        let all = CodeElementQuery::new().filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()));
+
+        // The type filter applies before the limit rather than after it
+        let classes = all.clone().filter(Filter::eq(ElementColumn::SymbolType, SymbolType::Class));
+        assert_eq!(repo.fuzzy_candidates("Widget", &classes, 1).unwrap(), vec![class.id.unwrap()]);
+        assert_eq!(repo.fuzzy_candidates("Widget", &all, 3).unwrap().len(), 3);
+
+        // Short patterns are matched literally, '_' included
+        assert_eq!(repo.fuzzy_candidates("t_", &all, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_search_match_modes() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        for (line, name) in ["CreateWidget", "CreateButtonWidget", "createWidgetLater", "DestroyWidget"].iter().enumerate() {
+            repo.create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/ui.cpp".to_string(), line as u32 + 1, 1, "a".repeat(64)))
+                .unwrap();
+        }
+        let names = |pattern: &str, mode: MatchMode| -> Vec<String> {
+            repo.search_code_elements(&index.id, pattern, mode, None, &QueryPage::default()).unwrap().items.into_iter().map(|e| e.symbol_name).collect()
+        };
+
+        assert_eq!(names("widget", MatchMode::Substring).len(), 4);
+        assert_eq!(names("create", MatchMode::Prefix), ["CreateButtonWidget", "CreateWidget", "createWidgetLater"]);
+        assert_eq!(names("Create*Widget", MatchMode::Glob), ["CreateButtonWidget", "CreateWidget"]);
+        assert_eq!(names("^Create.*Widget$", MatchMode::Regex), ["CreateButtonWidget", "CreateWidget"]);
+        assert_eq!(names("(?i)widget$", MatchMode::Regex).len(), 3);
+        assert_eq!(names("CreateWidget", MatchMode::Exact), ["CreateWidget"]);
+        assert!(matches!(
+            repo.search_code_elements(&index.id, "Create(", MatchMode::Regex, None, &QueryPage::default()),
+            Err(StorageError::Validation(_))
+        ));
+        assert_eq!(MatchMode::parse("glob"), Some(MatchMode::Glob));
+        assert_eq!(MatchMode::parse("soundex"), None);
+
+        // Typos still find the name, closest first
+        let fuzzy = names("Widgt", MatchMode::Fuzzy);
+        assert_eq!((fuzzy.len(), fuzzy[0].as_str()), (4, "CreateWidget"));
+        assert_eq!(names("DestroyWidgit", MatchMode::Fuzzy)[0], "DestroyWidget");
+        assert_eq!(names("wi", MatchMode::Fuzzy).len(), 4);
+        assert!(names("Reverb", MatchMode::Fuzzy).is_empty());
+
+        let first = repo.search_code_elements(&index.id, "widget", MatchMode::Substring, None, &QueryPage::new(3)).unwrap();
+        assert_eq!((first.items.len(), first.total_count), (3, 4));
+        let rest = QueryPage::new(3).with_cursor(first.next_cursor.unwrap());
+        let rest = repo.search_code_elements(&index.id, "widget", MatchMode::Substring, None, &rest).unwrap();
+        assert_eq!((rest.items.len(), rest.total_count, rest.next_cursor), (1, 4, None));
+
+        let by_file = repo.list_code_elements_by_file_page(&index.id, "src/ui.cpp", &QueryPage::new(2).with_offset(1)).unwrap();
+        let names: Vec<&str> = by_file.items.iter().map(|e| e.symbol_name.as_str()).collect();
+        assert_eq!(names, ["CreateButtonWidget", "createWidgetLater"]);
+        assert_eq!(by_file.total_count, 4);
+        assert!(by_file.has_more());
+    }
+
+    #[test]
+    fn test_slow_query_log() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+
+        // Disabled by default
+        repo.search_code_elements(&index.id, "draw", MatchMode::Substring, None, &QueryPage::default()).unwrap();
+        assert!(repo.list_slow_queries(10).unwrap().is_empty());
+
+        let repo = Repository::new(repo.into_connection()).with_slow_query_threshold(Duration::ZERO);
+        repo.search_code_elements(&index.id, "draw", MatchMode::Substring, None, &QueryPage::default()).unwrap();
+        repo.list_code_elements_by_file(&index.id, "src/main.cpp").unwrap();
+
+        let logged = repo.list_slow_queries(10).unwrap();
+        assert_eq!(logged.len(), 2);
+        let search = logged.iter().find(|q| q.operation == "search_code_elements").unwrap();
+        assert!(search.sql.starts_with("SELECT id, index_id, symbol_name"));
+        assert!(search.params_summary.contains("?2='%draw%'"));
+        assert_eq!(search.rows_returned, 0);
+
+        assert_eq!(repo.clear_slow_queries().unwrap(), 2);
+        assert!(repo.list_slow_queries(10).unwrap().is_empty());
+
+        // Another connection keeps the threshold, timings and actor
+        let dir = tempfile::TempDir::new().unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(dir.path().join("slow.db"))).unwrap();
+        let timings = QueryTimings::default();
+        let repo = Repository::new(manager.connect().unwrap())
+            .with_slow_query_threshold(Duration::ZERO)
+            .with_query_timings(timings.clone())
+            .with_audit_actor(AuditActor::new("mcp:editor"));
+        let reader = repo.on_connection(manager.connect().unwrap());
+        reader.list_code_elements_by_file(&index.id, "src/main.cpp").unwrap();
+        assert_eq!(repo.list_slow_queries(10).unwrap().len(), 1);
+        assert_eq!(reader.audit_actor(), repo.audit_actor());
+        assert!(!timings.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_index_tags() {
+        let repo = create_test_repository();
+
+        let index = CodeIndex::new("Engine".to_string(), "/src/engine".to_string());
+        let index_id = index.id;
+        repo.create_code_index(index).unwrap();
+        repo.create_code_index(CodeIndex::new("Tools".to_string(), "/src/tools".to_string())).unwrap();
+
+        // Set and overwrite
+        repo.set_index_tag(&IndexTag::new(index_id, "team".to_string(), "core".to_string())).unwrap();
+        repo.set_index_tag(&IndexTag::new(index_id, "team".to_string(), "rendering".to_string())).unwrap();
+        repo.set_index_tag(&IndexTag::new(index_id, "branch".to_string(), "main".to_string())).unwrap();
+
+        let tags = repo.get_index_tags(&index_id).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["team"], "rendering");
+
+        // Filter
+        let mut filter = BTreeMap::new();
+        filter.insert("team".to_string(), "rendering".to_string());
+        assert_eq!(repo.list_code_indices_by_tags(&filter).unwrap().len(), 1);
+        filter.insert("branch".to_string(), "release".to_string());
+        assert!(repo.list_code_indices_by_tags(&filter).unwrap().is_empty());
+        assert_eq!(repo.list_code_indices_by_tags(&BTreeMap::new()).unwrap().len(), 2);
+
+        // Remove
+        assert!(repo.remove_index_tag(&index_id, "branch").unwrap());
+        assert!(!repo.remove_index_tag(&index_id, "branch").unwrap());
+
+        // Tags are removed with their index
+        repo.delete_code_index(&index_id).unwrap();
+        assert!(repo.get_index_tags(&index_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_memory_sections() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("fw".to_string(), "/fw".to_string())).unwrap();
+        for (line, section) in [(1, Some(".itcm")), (5, Some(".itcm")), (9, Some(".dtcm")), (12, None)] {
+            let mut element = CodeElement::new(index.id, format!("fn{}", line), SymbolType::Function, "src/dsp.c".to_string(), line, 1, "a".repeat(64));
+            if let Some(section) = section {
+                element = element.with_memory_section(section.to_string());
+            }
+            repo.create_code_element(element).unwrap();
+        }
+
+        let counts = repo.count_code_elements_by_section(&index.id).unwrap();
+        assert_eq!(counts.get(".itcm"), Some(&2));
+        assert_eq!(counts.get(".dtcm"), Some(&1));
+        assert_eq!(counts.len(), 2);
+
+        let placed = repo.query_code_elements(
+            &CodeElementQuery::new().filter(Filter::eq(ElementColumn::MemorySection, ".itcm".to_string()))
+        ).unwrap();
+        assert_eq!(placed.len(), 2);
+        assert!(placed.iter().all(|element| element.memory_section.as_deref() == Some(".itcm")));
+    }
+
+    #[test]
+    fn test_full_text_search() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("audio".to_string(), "/audio".to_string())).unwrap();
+        let other = repo.create_code_index(CodeIndex::new("other".to_string(), "/other".to_string())).unwrap();
+        let element = |index_id, name: &str, symbol_type, line| {
+            CodeElement::new(index_id, name.to_string(), symbol_type, "src/ring.h".to_string(), line, 1, "a".repeat(64))
+        };
+
+        let drain = repo.create_code_element(
+            element(index.id, "drain", SymbolType::Function, 10)
+                .with_scope("audio::RingBuffer".to_string())
+                .with_signature("size_t drain(float* out, size_t frames)".to_string())
+                .with_documentation("Copies buffered samples into out without blocking".to_string()),
+        ).unwrap();
+        repo.create_code_element(element(index.id, "RingBuffer", SymbolType::Class, 3)).unwrap();
+        repo.create_code_element(element(other.id, "RingBuffer", SymbolType::Class, 3)).unwrap();
+
+        let hits = repo.full_text_search(&index.id, "samples block", &[], None, false, &QueryPage::new(10)).unwrap().items;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].element.id, drain.id);
+        assert!(hits[0].snippet.contains("[samples]"));
+
+        // Names outrank scopes, other indices are excluded
+        let hits = repo.full_text_search(&index.id, "ringbuf", &[], None, false, &QueryPage::new(10)).unwrap().items;
+        assert_eq!(hits.iter().map(|hit| hit.element.symbol_name.as_str()).collect::<Vec<_>>(), ["RingBuffer", "drain"]);
+        let classes = repo.full_text_search(&index.id, "ringbuf", &[], Some(&[SymbolType::Class]), false, &QueryPage::new(10)).unwrap().items;
+        assert_eq!(classes.len(), 1);
+        let first = repo.full_text_search(&index.id, "ringbuf", &[], None, false, &QueryPage::new(1)).unwrap();
+        assert_eq!((first.items[0].element.symbol_name.as_str(), first.total_count), ("RingBuffer", 2));
+        let second = QueryPage::new(1).with_cursor(first.next_cursor.unwrap());
+        let second = repo.full_text_search(&index.id, "ringbuf", &[], None, false, &second).unwrap();
+        assert_eq!((second.items[0].element.symbol_name.as_str(), second.next_cursor), ("drain", None));
+        assert!(repo.full_text_search(&index.id, "samples", &[TextColumn::Name], None, false, &QueryPage::new(10)).unwrap().items.is_empty());
+
+        // The index follows updates and deletes
+        let mut updated = drain.clone();
+        updated.documentation = Some("Non-blocking read".to_string());
+        repo.update_code_element(&updated).unwrap();
+        assert!(repo.full_text_search(&index.id, "samples", &[], None, false, &QueryPage::new(10)).unwrap().items.is_empty());
+        repo.delete_code_element(drain.id.unwrap()).unwrap();
+        assert!(repo.full_text_search(&index.id, "read", &[], None, false, &QueryPage::new(10)).unwrap().items.is_empty());
+
+        assert!(repo.full_text_search(&index.id, "ring NEAR(", &[], None, true, &QueryPage::new(10)).unwrap_err().is_client_error());
+        assert!(repo.full_text_search(&index.id, "  ", &[], None, false, &QueryPage::new(10)).is_err());
+    }
+
+    #[test]
+    fn test_symbol_annotations() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let annotate = |name: &str, scope: &str, author: &str, kind, text: &str| {
+            repo.create_annotation(
+                SymbolAnnotation::new(index.id, name.to_string(), author.to_string(), kind, text.to_string()).with_scope(scope.to_string()),
+            )
+            .unwrap()
+        };
+        let entry = annotate("main", "", "dana", AnnotationKind::Note, "Main entry point");
+        annotate("draw", "Widget", "dana", AnnotationKind::Note, "Deprecated, use render");
+        annotate("draw", "Canvas", "assistant", AnnotationKind::Bookmark, "");
+
+        let notes = repo.get_symbol_annotations(&index.id, "main", None).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].text, "Main entry point");
+        assert_eq!(notes[0].scope, None);
+        assert_eq!(repo.get_symbol_annotations(&index.id, "draw", None).unwrap().len(), 2);
+        assert_eq!(repo.get_symbol_annotations(&index.id, "draw", Some("Widget")).unwrap()[0].author, "dana");
+
+        assert_eq!(repo.list_annotations(&index.id, Some("dana"), None).unwrap().len(), 2);
+        assert_eq!(repo.list_annotations(&index.id, None, Some(AnnotationKind::Bookmark)).unwrap().len(), 1);
+
+        assert!(repo.delete_annotation(entry.id.unwrap()).unwrap());
+        assert!(!repo.delete_annotation(entry.id.unwrap()).unwrap());
+
+        // Annotations are removed with their index
+        repo.delete_code_index(&index.id).unwrap();
+        assert!(repo.list_annotations(&index.id, None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_path_aliases() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("mono".to_string(), "/mono".to_string())).unwrap();
+        let alias = |alias: &str, canonical: &str| PathAlias {
+            index_id: index.id,
+            alias_path: alias.to_string(),
+            canonical_path: canonical.to_string(),
+            file_count: 3,
+            tree_hash: "h".to_string(),
+        };
+
+        repo.replace_path_aliases(&index.id, &[alias("external/zlib", "third_party/zlib"), alias("vendor/zlib", "external/zlib")])
+            .unwrap();
+        assert_eq!(repo.list_path_aliases(&index.id).unwrap().len(), 2);
+        assert_eq!(repo.resolve_path_alias(&index.id, "vendor/zlib/inflate.c").unwrap(), "third_party/zlib/inflate.c");
+        assert_eq!(repo.resolve_path_alias(&index.id, "src/main.cpp").unwrap(), "src/main.cpp");
+        assert!(repo.replace_path_aliases(&index.id, &[alias("a", "a")]).is_err());
+
+        // Replacing drops aliases from the previous run
+        repo.replace_path_aliases(&index.id, &[alias("external/zlib", "third_party/zlib")]).unwrap();
+        assert_eq!(repo.resolve_path_alias(&index.id, "vendor/zlib/inflate.c").unwrap(), "vendor/zlib/inflate.c");
+    }
+
+    #[test]
+    fn test_directory_hits_and_depths() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("mono".to_string(), "/mono".to_string())).unwrap();
+        let day = |d: u32| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let directories = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<BTreeSet<_>>();
+
+        repo.record_directory_hits(&index.id, &directories(&["src/audio", "src/ui"]), day(1)).unwrap();
+        repo.record_directory_hits(&index.id, &directories(&["src/audio"]), day(1)).unwrap();
+        repo.record_directory_hits(&index.id, &directories(&["src/audio"]), day(5)).unwrap();
+        let hits = repo.directory_hits_since(&index.id, day(1)).unwrap();
+        assert_eq!(hits, BTreeMap::from([("src/audio".to_string(), 3), ("src/ui".to_string(), 1)]));
+        assert_eq!(repo.directory_hits_since(&index.id, day(2)).unwrap(), BTreeMap::from([("src/audio".to_string(), 1)]));
+
+        assert_eq!(repo.prune_directory_hits(&index.id, day(5)).unwrap(), 2);
+        assert_eq!(repo.directory_hits_since(&index.id, day(1)).unwrap().len(), 1);
+
+        let depth = |directory: &str, depth| DirectoryDepth {
+            index_id: index.id,
+            directory: directory.to_string(),
+            depth,
+            hits: 1,
+            assigned_at: Utc::now(),
+        };
+        repo.replace_directory_depths(&index.id, &[depth("src/ui", IndexDepth::Shallow), depth("src/audio", IndexDepth::Deep)]).unwrap();
+        repo.replace_directory_depths(&index.id, &[depth("src/audio", IndexDepth::Deep), depth("src/dsp", IndexDepth::Standard)]).unwrap();
+        let depths: Vec<(String, IndexDepth)> = repo
+            .list_directory_depths(&index.id)
+            .unwrap()
+            .into_iter()
+            .map(|depth| (depth.directory, depth.depth))
+            .collect();
+        assert_eq!(depths, [("src/audio".to_string(), IndexDepth::Deep), ("src/dsp".to_string(), IndexDepth::Standard)]);
     }
 
-    fn row_to_symbol_relationship(&self, row: &Row) -> Result<SymbolRelationship> {
-        let relationship_type_str: String = row.get(3)?;
-        
-        let relationship_type = match relationship_type_str.as_str() {
-            "inherits" => RelationshipType::Inherits,
-            "uses" => RelationshipType::Uses,
-            "includes" => RelationshipType::Includes,
-            "calls" => RelationshipType::Calls,
-            "defines" => RelationshipType::Defines,
-            "instantiates" => RelationshipType::Instantiates,
-            "contained_in" => RelationshipType::ContainedIn,
-            "friend" => RelationshipType::Friend,
-            "overrides" => RelationshipType::Overrides,
-            "specializes" => RelationshipType::Specializes,
-            _ => return Err(rusqlite::Error::InvalidColumnType(3, "Invalid relationship type".to_string(), rusqlite::types::Type::Text)),
+    #[test]
+    fn test_admin_audit_trail() {
+        let repo = create_test_repository();
+        // Without an actor nothing is recorded
+        let untracked = repo.create_code_index(CodeIndex::new("scratch".to_string(), "/scratch".to_string())).unwrap();
+        assert!(repo.list_audit_entries(None, None, None, 10).unwrap().is_empty());
+
+        let mut repo = repo.with_audit_actor(AuditActor::new("cli:dana"));
+        let index = repo.create_code_index(CodeIndex::new("firmware".to_string(), "/src/fw".to_string())).unwrap();
+        repo.set_index_tag(&IndexTag::new(index.id, "team".to_string(), "audio".to_string())).unwrap();
+        repo.set_audit_actor(AuditActor::new("mcp:desktop").with_session("s-42"));
+        repo.update_code_index_state(&index.id, IndexState::Active).unwrap();
+        repo.delete_code_index(&index.id).unwrap();
+        repo.delete_code_index(&untracked.id).unwrap();
+
+        let entries = repo.list_audit_entries(Some("firmware"), None, None, 10).unwrap();
+        let operations: Vec<_> = entries.iter().map(|entry| entry.operation).collect();
+        assert_eq!(operations, vec![AuditOperation::DeleteIndex, AuditOperation::SetTag, AuditOperation::CreateIndex]);
+        assert_eq!(entries[0].actor, "mcp:desktop");
+        assert_eq!(entries[0].session_id.as_deref(), Some("s-42"));
+        assert_eq!(entries[1].parameters["value"], "audio");
+        assert_eq!(entries[2].parameters["base_path"], "/src/fw");
+
+        let deletes = repo.list_audit_entries(None, Some(AuditOperation::DeleteIndex), None, 10).unwrap();
+        assert_eq!(deletes.len(), 2);
+        assert!(repo.list_audit_entries(None, None, Some(Utc::now() + chrono::Duration::hours(1)), 10).unwrap().is_empty());
+
+        // The trail can't be rewritten
+        assert!(repo.connection().execute("DELETE FROM admin_audit", []).is_err());
+        assert!(repo.connection().execute("UPDATE admin_audit SET actor = 'nobody'", []).is_err());
+    }
+
+    #[test]
+    fn test_build_configurations() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let element = |name: &str, line| {
+            repo.create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "platform.h".to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
         };
-        
-        Ok(SymbolRelationship {
-            id: Some(row.get(0)?),
-            from_symbol_id: row.get(1)?,
-            to_symbol_id: row.get(2)?,
-            relationship_type,
-            file_path: row.get(4)?,
-            line_number: row.get(5)?,
-        })
+        let win_only = element("win_only", 4);
+        let posix_only = element("posix_only", 7);
+
+        let windows = repo.save_build_configuration(BuildConfiguration::new(index.id, "windows".to_string()).with_define("_WIN32", "1")).unwrap();
+        let linux = repo.save_build_configuration(BuildConfiguration::new(index.id, "linux".to_string()).with_define("__linux__", "1")).unwrap();
+        assert!(repo.save_build_configuration(BuildConfiguration::new(index.id, "bad name".to_string())).is_err());
+
+        // Saving under an existing name replaces the macros but keeps its identity
+        let updated = repo
+            .save_build_configuration(BuildConfiguration::new(index.id, "windows".to_string()).with_define("_WIN32", "1").with_undefine("NDEBUG"))
+            .unwrap();
+        assert_eq!(updated.id, windows.id);
+        assert!(updated.undefines.contains("NDEBUG"));
+
+        let (windows, linux) = (windows.id.unwrap(), linux.id.unwrap());
+        repo.replace_configuration_exclusions(&index.id, &[(windows, posix_only), (linux, win_only)]).unwrap();
+        assert_eq!(repo.list_configuration_exclusions(windows).unwrap(), vec![posix_only]);
+        assert_eq!(repo.get_symbol_configurations(&index.id, win_only).unwrap(), vec!["windows"]);
+
+        repo.replace_configuration_exclusions(&index.id, &[(linux, win_only)]).unwrap();
+        assert!(repo.list_configuration_exclusions(windows).unwrap().is_empty());
+
+        assert!(repo.delete_build_configuration(&index.id, "linux").unwrap());
+        assert!(!repo.delete_build_configuration(&index.id, "linux").unwrap());
+        assert!(repo.list_configuration_exclusions(linux).unwrap().is_empty());
+        let names: Vec<_> = repo.list_build_configurations(&index.id).unwrap().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["windows"]);
     }
 
-    fn row_to_mcp_session(&self, row: &Row) -> Result<McpQuerySession> {
-        let session_id_str: String = row.get(0)?;
-        let active_index_id_str: Option<String> = row.get(2)?;
-        let created_at_str: String = row.get(3)?;
-        let last_activity_str: String = row.get(4)?;
-        let status_str: String = row.get(6)?;
-        
-        let status = match status_str.as_str() {
-            "active" => SessionStatus::Active,
-            "inactive" => SessionStatus::Inactive,
-            "terminated" => SessionStatus::Terminated,
-            "error" => SessionStatus::Error,
-            _ => return Err(rusqlite::Error::InvalidColumnType(6, "Invalid session status".to_string(), rusqlite::types::Type::Text)),
+    #[test]
+    fn test_symbol_bodies() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let body = "void mix(float* out) {\n".to_string() + &"    out[i] += in[i] * gain;\n".repeat(50) + "}\n";
+        let element = CodeElement::new(index.id, "mix".to_string(), SymbolType::Function, "mixer.cpp".to_string(), 3, 1, "a".repeat(64))
+            .with_body(body.clone());
+        let id = repo.create_code_element(element).unwrap().id.unwrap();
+        let bare = repo
+            .create_code_element(CodeElement::new(index.id, "clamp".to_string(), SymbolType::Function, "mixer.cpp".to_string(), 60, 1, "b".repeat(64)))
+            .unwrap()
+            .id
+            .unwrap();
+
+        // Bodies are only loaded on request
+        assert!(repo.get_code_element(id).unwrap().unwrap().body.is_none());
+        assert_eq!(repo.get_symbol_body(id).unwrap(), Some(body.clone()));
+        assert_eq!(repo.get_symbol_body(bare).unwrap(), None);
+
+        let stats = repo.get_symbol_body_stats(&index.id).unwrap();
+        assert_eq!((stats.count, stats.uncompressed_bytes), (1, body.len() as u64));
+        assert!(stats.compressed_bytes < stats.uncompressed_bytes);
+        assert!(stats.compression_ratio() > 1.0);
+
+        repo.delete_code_element(id).unwrap();
+        assert_eq!(repo.get_symbol_body(id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_long_signatures_are_compressed() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let long = format!("void dispatch({})", ["std::map<std::string, std::vector<Handler>> handlers"; 8].join(", "));
+        let element = |line, signature: &str| {
+            CodeElement::new(index.id, "dispatch".to_string(), SymbolType::Function, "events.cpp".to_string(), line, 1, "a".repeat(64))
+                .with_signature(signature.to_string())
         };
-        
-        let active_index_id = active_index_id_str
-            .map(|s| Uuid::parse_str(&s))
-            .transpose()
-            .map_err(|_| rusqlite::Error::InvalidColumnType(2, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?;
-        
-        Ok(McpQuerySession {
-            session_id: Uuid::parse_str(&session_id_str).map_err(|_| rusqlite::Error::InvalidColumnType(0, "Invalid UUID".to_string(), rusqlite::types::Type::Text))?,
-            client_name: row.get(1)?,
-            active_index_id,
-            created_at: DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc),
-            last_activity: DateTime::parse_from_rfc3339(&last_activity_str)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(4, "Invalid datetime".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc),
-            query_count: row.get(5)?,
-            status,
-            client_metadata: row.get(7)?,
-        })
+        let id = repo.create_code_element(element(3, &long)).unwrap().id.unwrap();
+        let short = repo.create_code_element(element(9, "void dispatch()")).unwrap().id.unwrap();
+
+        // code_elements keeps the start, which searches see
+        let stored = repo.get_code_element(id).unwrap().unwrap();
+        let inline = stored.signature.clone().unwrap();
+        assert_eq!((inline.chars().count(), inline.ends_with('…')), (SIGNATURE_INLINE_LIMIT + 1, true));
+        assert_eq!(inline_signature(&inline), inline);
+        assert_eq!(repo.full_text_search(&index.id, "handlers", &[TextColumn::Signature], None, false, &QueryPage::new(10)).unwrap().total_count, 1);
+        assert_eq!(repo.get_symbol_signature(id).unwrap().as_deref(), Some(long.as_str()));
+        assert_eq!(repo.get_symbol_signature(short).unwrap().as_deref(), Some("void dispatch()"));
+        assert_eq!(repo.get_symbol_body_stats(&index.id).unwrap().count, 0);
+
+        // Saving what was read back keeps the whole signature; shortening it drops it
+        repo.update_code_element(&stored).unwrap();
+        assert_eq!(repo.get_symbol_signature(id).unwrap().as_deref(), Some(long.as_str()));
+        let mut shortened = stored;
+        shortened.signature = Some("void dispatch(int)".to_string());
+        repo.update_code_element(&shortened).unwrap();
+        assert_eq!(repo.get_symbol_signature(id).unwrap().as_deref(), Some("void dispatch(int)"));
+        let rows: i64 = repo.connection.query_row("SELECT COUNT(*) FROM symbol_signatures", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 0);
+
+        // Re-indexing matches overloads by the stored start, keeping ids
+        repo.update_code_element(&CodeElement { id: Some(id), ..element(3, &long) }).unwrap();
+        let again = repo.replace_code_elements(&index.id, "events.cpp", vec![element(4, &long), element(9, "void dispatch()")]).unwrap();
+        assert_eq!((again[0].id, again[1].id), (Some(id), Some(short)));
+        assert_eq!(repo.get_symbol_signature(id).unwrap().as_deref(), Some(long.as_str()));
     }
-}
 
-/// Statistics for a code index
-#[derive(Debug, Clone)]
-pub struct IndexStatistics {
-    pub index_id: Uuid,
-    pub name: String,
-    pub reported_files: u32,
-    pub reported_symbols: u32,
-    pub actual_files: u32,
-    pub actual_elements: u32,
-    pub relationships: u32,
-}
+    #[test]
+    fn test_saved_queries() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
 
-impl IndexStatistics {
-    /// Returns true if the reported counts match actual counts
-    pub fn is_consistent(&self) -> bool {
-        self.reported_files == self.actual_files && self.reported_symbols == self.actual_elements
+        let saved = repo
+            .save_query(SavedQuery::new(index.id, "hot-functions".to_string(), "type:function section:.iram1".to_string()))
+            .unwrap();
+        assert!(saved.id.is_some());
+        assert!(repo.save_query(SavedQuery::new(index.id, "broken".to_string(), "colour:red".to_string())).is_err());
+
+        // Saving under an existing name replaces the query but keeps its identity
+        let updated = repo
+            .save_query(
+                SavedQuery::new(index.id, "hot-functions".to_string(), "type:function section:.iram1,.itcm".to_string())
+                    .with_description("Functions placed in fast RAM".to_string()),
+            )
+            .unwrap();
+        assert_eq!(updated.id, saved.id);
+        assert_eq!(updated.created_at, saved.created_at);
+        assert_eq!(updated.query, "type:function section:.iram1,.itcm");
+
+        repo.save_query(SavedQuery::new(index.id, "ctors".to_string(), "type:constructor".to_string())).unwrap();
+        let names: Vec<_> = repo.list_saved_queries(&index.id).unwrap().into_iter().map(|q| q.name).collect();
+        assert_eq!(names, vec!["ctors", "hot-functions"]);
+
+        assert!(repo.delete_saved_query(&index.id, "ctors").unwrap());
+        assert!(!repo.delete_saved_query(&index.id, "ctors").unwrap());
+        assert!(repo.get_saved_query(&index.id, "ctors").unwrap().is_none());
     }
-    
-    /// Returns the difference between reported and actual file counts
-    pub fn file_count_difference(&self) -> i32 {
-        self.actual_files as i32 - self.reported_files as i32
+
+    #[test]
+    fn test_symbol_tags() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let element = |name: &str, line| {
+            repo.create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/audio.cpp".to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let process = element("process", 1);
+        let mix = element("mix", 9);
+
+        assert!(repo.add_symbol_tag(process, "realtime").unwrap());
+        assert!(!repo.add_symbol_tag(process, "realtime").unwrap());
+        repo.add_symbol_tag(process, "audio").unwrap();
+        repo.add_symbol_tag(mix, "audio").unwrap();
+        assert!(repo.add_symbol_tag(mix, "not a tag").is_err());
+
+        assert_eq!(repo.get_symbol_tags(process).unwrap(), vec!["audio", "realtime"]);
+        let tagged = repo.find_code_elements_by_tag(&index.id, "realtime").unwrap();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].symbol_name, "process");
+        assert_eq!(repo.find_code_elements_by_tag(&index.id, "audio").unwrap().len(), 2);
+
+        assert!(repo.remove_symbol_tag(process, "realtime").unwrap());
+        assert!(repo.find_code_elements_by_tag(&index.id, "realtime").unwrap().is_empty());
     }
-    
-    /// Returns the difference between reported and actual symbol counts
-    pub fn symbol_count_difference(&self) -> i32 {
-        self.actual_elements as i32 - self.reported_symbols as i32
+
+    #[test]
+    fn test_symbol_popularity() {
+        use crate::lib::storage::query::SortDirection;
+
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let element = |name: &str, line| {
+            repo.create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/app.cpp".to_string(), line, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let init = element("init", 1);
+        let main = element("main", 10);
+        let helper = element("test_helper", 20);
+        let relate = |from, to, kind, line| {
+            repo.create_symbol_relationship(SymbolRelationship::new(from, to, kind, "src/app.cpp".to_string(), line)).unwrap();
+        };
+        relate(main, init, RelationshipType::Calls, 11);
+        relate(main, init, RelationshipType::Calls, 12);
+        relate(helper, init, RelationshipType::ReferencedAsCallback, 21);
+        relate(main, init, RelationshipType::ContainedIn, 13);
+
+        assert_eq!(repo.get_symbol_popularity(&[init]).unwrap()[&init], SymbolPopularity::default());
+        assert_eq!(repo.refresh_symbol_popularity(&index.id).unwrap(), 3);
+
+        let popularity = repo.get_symbol_popularity(&[init, main, 999]).unwrap();
+        assert_eq!(popularity.len(), 2);
+        assert_eq!(popularity[&init], SymbolPopularity { reference_count: 3, caller_count: 2, callee_count: 0 });
+        assert_eq!(popularity[&main].callee_count, 1);
+
+        let ranked = repo
+            .query_code_elements(
+                &CodeElementQuery::new()
+                    .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+                    .order_by(ElementColumn::ReferenceCount, SortDirection::Descending)
+                    .order_by_asc(ElementColumn::SymbolName),
+            )
+            .unwrap();
+        let names: Vec<&str> = ranked.iter().map(|e| e.symbol_name.as_str()).collect();
+        assert_eq!(names, ["init", "main", "test_helper"]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
-    use chrono::TimeZone;
+    #[test]
+    fn test_file_dependencies() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let element = |name: &str, file: &str| {
+            repo.create_code_element(CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64)))
+                .unwrap()
+                .id
+                .unwrap()
+        };
+        let main = element("main", "src/main.cpp");
+        let run = element("run", "src/main.cpp");
+        let init = element("init", "src/core.cpp");
+        let relate = |from, to, kind, line| {
+            repo.create_symbol_relationship(SymbolRelationship::new(from, to, kind, "src/main.cpp".to_string(), line)).unwrap();
+        };
+        relate(main, init, RelationshipType::Calls, 2);
+        relate(run, init, RelationshipType::Calls, 3);
+        relate(main, run, RelationshipType::Calls, 4);
+        relate(init, main, RelationshipType::ContainedIn, 5);
 
-    fn create_test_repository() -> Repository {
-        let config = DatabaseConfig::in_memory();
-        let manager = DatabaseManager::new(config).unwrap();
-        let connection = manager.connect().unwrap();
-        Repository::new(connection)
+        let dependencies = repo.get_file_dependencies(&index.id, &[]).unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert_eq!(dependencies[&("src/main.cpp".to_string(), "src/core.cpp".to_string())], 2);
+        assert!(repo.get_file_dependencies(&index.id, &[RelationshipType::Includes]).unwrap().is_empty());
     }
 
     #[test]
-    fn test_code_index_crud() {
+    fn test_index_insights() {
         let repo = create_test_repository();
-        let index = CodeIndex::new("Test Index".to_string(), "/test/path".to_string());
-        let index_id = index.id;
-        
-        // Create
-        let created_index = repo.create_code_index(index).unwrap();
-        assert_eq!(created_index.name, "Test Index");
-        
-        // Read by ID
-        let retrieved_index = repo.get_code_index(&index_id).unwrap().unwrap();
-        assert_eq!(retrieved_index.name, "Test Index");
-        assert_eq!(retrieved_index.base_path, "/test/path");
-        
-        // Read by name
-        let retrieved_by_name = repo.get_code_index_by_name("Test Index").unwrap().unwrap();
-        assert_eq!(retrieved_by_name.id, index_id);
-        
-        // Update
-        let mut updated_index = retrieved_index;
-        updated_index.name = "Updated Test Index".to_string();
-        repo.update_code_index(&updated_index).unwrap();
-        
-        let retrieved_updated = repo.get_code_index(&index_id).unwrap().unwrap();
-        assert_eq!(retrieved_updated.name, "Updated Test Index");
-        
-        // List
-        let indices = repo.list_code_indices().unwrap();
-        assert_eq!(indices.len(), 1);
-        assert_eq!(indices[0].name, "Updated Test Index");
-        
-        // Delete
-        repo.delete_code_index(&index_id).unwrap();
-        assert!(repo.get_code_index(&index_id).unwrap().is_none());
+        let index = repo.create_code_index(CodeIndex::new("test".to_string(), "/test".to_string())).unwrap();
+        let element = |name: &str, symbol_type, file: &str, line, scope: Option<&str>| {
+            let element = CodeElement::new(index.id, name.to_string(), symbol_type, file.to_string(), line, 1, "a".repeat(64));
+            let element = match scope {
+                Some(scope) => element.with_scope(scope.to_string()),
+                None => element,
+            };
+            repo.create_code_element(element).unwrap().id.unwrap()
+        };
+        let mixer = element("Mixer", SymbolType::Class, "mixer.h", 3, Some("audio"));
+        let mix = element("mix", SymbolType::Function, "mixer.h", 5, Some("audio::Mixer"));
+        element("gain", SymbolType::Field, "mixer.h", 6, Some("audio::Mixer"));
+        element("Voice", SymbolType::Struct, "voice.h", 1, Some("audio"));
+        element("pitch", SymbolType::Field, "voice.h", 2, Some("audio::Voice"));
+        // Out-of-line definitions don't make the class bigger
+        let mix_definition = element("mix", SymbolType::Function, "mixer.cpp", 10, Some("audio::Mixer"));
+        let main = element("main", SymbolType::Function, "main.cpp", 1, None);
+        let relate = |from, to, kind, line| {
+            repo.create_symbol_relationship(SymbolRelationship::new(from, to, kind, "main.cpp".to_string(), line)).unwrap();
+        };
+        relate(main, mix, RelationshipType::Calls, 2);
+        relate(main, mix, RelationshipType::Calls, 3);
+        relate(mix_definition, mix, RelationshipType::Uses, 2);
+        relate(main, mixer, RelationshipType::Uses, 2);
+        relate(mix, mixer, RelationshipType::ContainedIn, 2);
+
+        for file in ["main.cpp", "mixer.cpp", "mixer.h", "voice.h"] {
+            repo.create_file_metadata(FileMetadata::new(index.id, file.to_string(), "b".repeat(64), Utc::now(), 10)).unwrap();
+        }
+        repo.replace_file_includes(&index.id, "main.cpp", &["mixer.h".to_string(), "voice.h".to_string(), "vector".to_string()]).unwrap();
+        repo.replace_file_includes(&index.id, "mixer.cpp", &["mixer.h".to_string()]).unwrap();
+        repo.resolve_file_includes(&index.id).unwrap();
+
+        let insights = repo.get_index_insights(&index.id, 10).unwrap();
+        let symbols = |ranked: &[RankedSymbol]| ranked.iter().map(|r| (r.element.id.unwrap(), r.count)).collect::<Vec<_>>();
+        fn files(ranked: &[RankedFile]) -> Vec<(&str, u64)> {
+            ranked.iter().map(|r| (r.file_path.as_str(), r.count)).collect()
+        }
+        assert_eq!(symbols(&insights.fan_in), [(mix, 2), (mixer, 1)]);
+        assert_eq!(symbols(&insights.fan_out), [(main, 2), (mix_definition, 1)]);
+        assert_eq!(insights.largest_classes.iter().map(|r| (r.element.symbol_name.as_str(), r.count)).collect::<Vec<_>>(), [("Mixer", 2), ("Voice", 1)]);
+        assert_eq!(files(&insights.most_included_headers), [("mixer.h", 2), ("voice.h", 1)]);
+        assert_eq!(files(&insights.busiest_files), [("mixer.h", 3), ("voice.h", 2), ("main.cpp", 1), ("mixer.cpp", 1)]);
+        assert_eq!(repo.get_index_insights(&index.id, 1).unwrap().busiest_files.len(), 1);
     }
 
     #[test]
@@ -1130,7 +4836,7 @@ mod tests {
         assert_eq!(retrieved_element.symbol_name, "testFunction");
         
         // Search by name
-        let search_results = repo.search_code_elements(&index_id, "test", None).unwrap();
+        let search_results = repo.search_code_elements(&index_id, "test", MatchMode::Substring, None, &QueryPage::default()).unwrap().items;
         assert_eq!(search_results.len(), 1);
         assert_eq!(search_results[0].symbol_name, "testFunction");
         
@@ -1138,6 +4844,11 @@ mod tests {
         let file_elements = repo.list_code_elements_by_file(&index_id, "src/test.cpp").unwrap();
         assert_eq!(file_elements.len(), 1);
         
+        // Find by exact name
+        let exact_results = repo.find_code_elements_by_name(&index_id, "testFunction").unwrap();
+        assert_eq!(exact_results.len(), 1);
+        assert!(repo.find_code_elements_by_name(&index_id, "test").unwrap().is_empty());
+        
         // Update
         let mut updated_element = retrieved_element;
         updated_element.symbol_name = "updatedFunction".to_string();
@@ -1218,6 +4929,47 @@ mod tests {
         assert_eq!(empty_relationships.len(), 0);
     }
 
+    #[test]
+    fn test_batch_inserts() {
+        let repo = create_test_repository();
+        let index = CodeIndex::new("Batch Index".to_string(), "/test/path".to_string());
+        let index_id = index.id;
+        repo.create_code_index(index).unwrap();
+
+        let element = |name: &str, line: u32| CodeElement::new(index_id, name.to_string(), SymbolType::Function, "src/batch.cpp".to_string(), line, 1, "a".repeat(64));
+        let created = repo
+            .create_code_elements_batch((1..=100).map(|line| element(&format!("fn{}", line), line)).collect())
+            .unwrap();
+        assert_eq!(created.len(), 100);
+        assert!(created.windows(2).all(|pair| pair[0].id.unwrap() < pair[1].id.unwrap()));
+        assert_eq!(repo.get_code_element(created[41].id.unwrap()).unwrap().unwrap().symbol_name, "fn42");
+
+        // One invalid element keeps the whole batch out
+        let rejected = repo.create_code_elements_batch(vec![element("ok", 200), element("", 201)]);
+        assert!(matches!(rejected, Err(StorageError::Validation(_))));
+        assert_eq!(repo.list_code_elements_by_file(&index_id, "src/batch.cpp").unwrap().len(), 100);
+
+        let ids: Vec<i64> = created.iter().map(|element| element.id.unwrap()).collect();
+        let relationships = repo
+            .create_symbol_relationships_batch(
+                ids.windows(2)
+                    .map(|pair| SymbolRelationship::new(pair[0], pair[1], RelationshipType::Calls, "src/batch.cpp".to_string(), 1))
+                    .collect(),
+            )
+            .unwrap();
+        assert_eq!(relationships.len(), 99);
+        assert!(relationships.iter().all(|relationship| relationship.id.is_some()));
+        let (outgoing, incoming) = repo.get_symbol_relationships(ids[1]).unwrap();
+        assert_eq!((outgoing.len(), incoming.len()), (1, 1));
+
+        let rejected = repo.create_symbol_relationships_batch(vec![
+            SymbolRelationship::new(ids[0], ids[2], RelationshipType::Calls, "src/batch.cpp".to_string(), 2),
+            SymbolRelationship::new(ids[0], ids[0], RelationshipType::Calls, "src/batch.cpp".to_string(), 3),
+        ]);
+        assert!(rejected.is_err());
+        assert_eq!(repo.get_symbol_relationships(ids[0]).unwrap().0.len(), 1);
+    }
+
     #[test]
     fn test_mcp_session_crud() {
         let repo = create_test_repository();
@@ -1269,7 +5021,8 @@ mod tests {
             Utc::now(),
             1024,
         );
-        repo.create_file_metadata(metadata).unwrap();
+        let metadata = repo.create_file_metadata(metadata).unwrap();
+        repo.update_file_processing_state(metadata.id.unwrap(), FileProcessingState::Indexed).unwrap();
         
         let element = CodeElement::new(
             index_id,
@@ -1291,4 +5044,236 @@ mod tests {
         assert_eq!(test_stats.actual_elements, 1);
         assert_eq!(test_stats.relationships, 0);
     }
+
+    #[test]
+    fn test_move_file_keeps_symbol_ids() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Moves".to_string(), "/moves".to_string())).unwrap();
+        repo.create_file_metadata(FileMetadata::new(index.id, "src/mixer.cpp".to_string(), "a".repeat(64), Utc::now(), 10)).unwrap();
+        let element = |name: &str, line: u32| {
+            CodeElement::new(index.id, name.to_string(), SymbolType::Function, "src/mixer.cpp".to_string(), line, 1, "a".repeat(64))
+        };
+        let mix = repo.create_code_element(element("mix", 1)).unwrap();
+        let gain = repo.create_code_element(element("gain", 5)).unwrap();
+        repo.create_code_element(element("legacy", 9)).unwrap();
+        repo.create_symbol_relationship(SymbolRelationship::new(mix.id.unwrap(), gain.id.unwrap(), RelationshipType::Calls, "src/mixer.cpp".to_string(), 2))
+            .unwrap();
+
+        // Moved, with a line added on top, legacy() removed and mute() added
+        let moved = FileMetadata::new(index.id, "src/audio/mixer.cpp".to_string(), "b".repeat(64), Utc::now(), 12);
+        let stored = repo.move_file("src/mixer.cpp", &moved, vec![element("mix", 2), element("gain", 6), element("mute", 10)]).unwrap();
+
+        let ids: Vec<Option<i64>> = stored.iter().take(2).map(|element| element.id).collect();
+        assert_eq!(ids, [mix.id, gain.id]);
+        let elements = repo.list_code_elements_by_file(&index.id, "src/audio/mixer.cpp").unwrap();
+        assert_eq!(elements.len(), 3);
+        assert!(elements.iter().any(|element| element.id == mix.id && element.line_number == 2));
+        assert!(repo.list_code_elements_by_file(&index.id, "src/mixer.cpp").unwrap().is_empty());
+        assert!(repo.get_file_metadata_by_path(&index.id, "src/mixer.cpp").unwrap().is_none());
+        assert_eq!(repo.get_file_metadata_by_path(&index.id, "src/audio/mixer.cpp").unwrap().unwrap().file_hash, "b".repeat(64));
+        let relationships = repo.query_symbol_relationships(&RelationshipQuery::new().from_symbol(mix.id.unwrap())).unwrap();
+        assert_eq!(relationships[0].file_path, "src/audio/mixer.cpp");
+
+        let again = FileMetadata::new(index.id, "src/audio/mixer.cpp".to_string(), "b".repeat(64), Utc::now(), 12);
+        assert!(matches!(repo.move_file("src/mixer.cpp", &again, Vec::new()), Err(StorageError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_replace_file_elements_keeps_incoming_calls() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Replace".to_string(), "/replace".to_string())).unwrap();
+        let element = |name: &str, file: &str, line: u32| {
+            CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64))
+        };
+        let metadata = repo.create_file_metadata(FileMetadata::new(index.id, "src/gain.cpp".to_string(), "a".repeat(64), Utc::now(), 10)).unwrap();
+        let render = repo.create_code_element(element("render", "src/app.cpp", 1)).unwrap().id.unwrap();
+        let gain = repo.create_code_element(element("gain", "src/gain.cpp", 1)).unwrap().id.unwrap();
+        let trim = repo.create_code_element(element("trim", "src/gain.cpp", 5)).unwrap().id.unwrap();
+        let legacy = repo.create_code_element(element("legacy", "src/gain.cpp", 9)).unwrap().id.unwrap();
+        for (to, line) in [(gain, 2), (legacy, 3)] {
+            repo.create_symbol_relationship(SymbolRelationship::new(render, to, RelationshipType::Calls, "src/app.cpp".to_string(), line)).unwrap();
+        }
+        repo.create_symbol_relationship(SymbolRelationship::new(gain, trim, RelationshipType::Calls, "src/gain.cpp".to_string(), 2)).unwrap();
+
+        // gain() and trim() trade lines, legacy() is gone and mute() is new
+        let elements = vec![element("trim", "src/gain.cpp", 1), element("gain", "src/gain.cpp", 5), element("mute", "src/gain.cpp", 9)];
+        let stored = repo.replace_file_elements(&metadata, elements).unwrap();
+        let ids: Vec<Option<i64>> = stored.iter().take(2).map(|element| element.id).collect();
+        assert_eq!(ids, [Some(trim), Some(gain)]);
+        assert_eq!(repo.list_code_elements_by_file(&index.id, "src/gain.cpp").unwrap().len(), 3);
+
+        // The call from the unchanged file survives; the file's own calls are left to be resolved again
+        let calls = repo.query_symbol_relationships(&RelationshipQuery::new().from_symbol(render)).unwrap();
+        assert_eq!(calls.iter().map(|call| call.to_symbol_id).collect::<Vec<_>>(), [gain]);
+        assert!(repo.query_symbol_relationships(&RelationshipQuery::new().from_symbol(gain)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prune_missing_files() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Prune".to_string(), "/prune".to_string())).unwrap();
+        let mut ids = Vec::new();
+        for (file, name) in [("src/mixer.cpp", "mix"), ("src/old.cpp", "legacy"), ("src/gone.h", "ghost")] {
+            let metadata = repo.create_file_metadata(FileMetadata::new(index.id, file.to_string(), "a".repeat(64), Utc::now(), 10)).unwrap();
+            repo.update_file_processing_state(metadata.id.unwrap(), FileProcessingState::Indexed).unwrap();
+            let element = CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64));
+            ids.push(repo.create_code_element(element).unwrap().id.unwrap());
+        }
+        repo.replace_file_includes(&index.id, "src/old.cpp", &["gone.h".to_string()]).unwrap();
+        repo.create_symbol_relationship(SymbolRelationship::new(ids[0], ids[1], RelationshipType::Calls, "src/mixer.cpp".to_string(), 2)).unwrap();
+
+        let present: BTreeSet<String> = ["src/mixer.cpp".to_string()].into();
+        let (pruned, removed) = repo.prune_missing_files(&index.id, &present).unwrap();
+        assert_eq!((pruned, removed), (vec!["src/gone.h".to_string(), "src/old.cpp".to_string()], 2));
+        assert_eq!(repo.list_file_metadata(&index.id).unwrap().len(), 1);
+        assert!(repo.get_file_includes(&index.id, &["src/old.cpp".to_string()]).unwrap().is_empty());
+        assert!(repo.query_symbol_relationships(&RelationshipQuery::new().from_symbol(ids[0])).unwrap().is_empty());
+        assert_eq!(repo.get_code_index(&index.id).unwrap().unwrap().total_files, 1);
+
+        assert_eq!(repo.prune_missing_files(&index.id, &present).unwrap(), (Vec::new(), 0));
+    }
+
+    #[test]
+    fn test_verify_and_repair_index() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Repair".to_string(), "/repair".to_string())).unwrap();
+        let metadata = repo.create_file_metadata(FileMetadata::new(index.id, "src/draw.cpp".to_string(), "a".repeat(64), Utc::now(), 10)).unwrap();
+        repo.update_file_processing_state(metadata.id.unwrap(), FileProcessingState::Indexed).unwrap();
+
+        let element = |name: &str, file: &str| CodeElement::new(index.id, name.to_string(), SymbolType::Function, file.to_string(), 1, 1, "a".repeat(64));
+        let draw = repo.create_code_element(element("draw", "src/draw.cpp")).unwrap();
+        repo.create_code_element(element("ghost", "src/removed.cpp")).unwrap();
+        // A relationship to a symbol that no longer exists, as left by a crash with foreign keys off
+        repo.connection().execute("PRAGMA foreign_keys = OFF", []).unwrap();
+        repo.create_symbol_relationship(SymbolRelationship::new(draw.id.unwrap(), 9999, RelationshipType::Calls, "src/draw.cpp".to_string(), 1)).unwrap();
+
+        let health = repo.verify_index(&index.id, false).unwrap();
+        assert_eq!((health.orphaned_elements, health.dangling_relationships, health.stale_files), (1, 1, 1));
+        assert_eq!((health.actual_files, health.actual_symbols), (1, 2));
+        assert!(!health.repaired && !health.is_healthy());
+
+        let health = repo.verify_index(&index.id, true).unwrap();
+        assert!(health.repaired);
+        assert_eq!((health.actual_files, health.actual_symbols), (1, 1));
+
+        assert!(repo.verify_index(&index.id, false).unwrap().is_healthy());
+        let index = repo.get_code_index(&index.id).unwrap().unwrap();
+        assert_eq!((index.total_files, index.total_symbols), (1, 1));
+        assert_eq!(repo.get_file_metadata_by_path(&index.id, "src/draw.cpp").unwrap().unwrap().symbol_count, 1);
+        assert!(matches!(repo.verify_index(&Uuid::new_v4(), true), Err(StorageError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_index_revisions_and_stashed_files() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Revisions".to_string(), "/revisions".to_string())).unwrap();
+        let files = |entries: &[(&str, &str)]| -> BTreeMap<String, String> {
+            entries.iter().map(|(path, hash)| (path.to_string(), hash.to_string())).collect()
+        };
+
+        // File metadata needs a full-length hash
+        let a2_hash = "a2".repeat(32);
+        let a2 = a2_hash.as_str();
+
+        let main = repo.create_index_revision(IndexRevision::new(index.id, "main".to_string())).unwrap();
+        let feature = repo
+            .create_index_revision(IndexRevision::new(index.id, "feature".to_string()).with_parent(main.id.unwrap()))
+            .unwrap();
+        assert!(matches!(repo.create_index_revision(IndexRevision::new(index.id, "main".to_string())), Err(StorageError::Conflict(_))));
+        repo.record_revision_files(&main, &files(&[("a.cpp", "a1"), ("b.cpp", "b1")])).unwrap();
+        repo.record_revision_files(&feature, &files(&[("a.cpp", a2), ("b.cpp", "b1"), ("c.cpp", "c1")])).unwrap();
+        // Only the difference from main is stored for the feature branch
+        let stored: i64 = repo
+            .connection()
+            .query_row("SELECT COUNT(*) FROM revision_files WHERE revision_id = ?1", [feature.id.unwrap()], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 2);
+
+        // Main moving on leaves the feature branch's files as they were
+        repo.record_revision_files(&main, &files(&[("a.cpp", "a1"), ("b.cpp", "b3"), ("d.cpp", "d1")])).unwrap();
+        assert_eq!(repo.get_revision_files(feature.id.unwrap()).unwrap(), files(&[("a.cpp", a2), ("b.cpp", "b1"), ("c.cpp", "c1")]));
+        assert_eq!(repo.get_revision_files(main.id.unwrap()).unwrap(), files(&[("a.cpp", "a1"), ("b.cpp", "b3"), ("d.cpp", "d1")]));
+
+        assert!(repo.get_current_revision(&index.id).unwrap().is_none());
+        repo.set_current_revision(&main, Some("abc123")).unwrap();
+        repo.set_current_revision(&feature, None).unwrap();
+        let current = repo.get_current_revision(&index.id).unwrap().unwrap();
+        assert_eq!((current.name.as_str(), current.commit), ("feature", None));
+        assert_eq!(repo.get_index_revision(&index.id, "main").unwrap().unwrap().commit.as_deref(), Some("abc123"));
+        let names: Vec<String> = repo.list_index_revisions(&index.id).unwrap().into_iter().map(|revision| revision.name).collect();
+        assert_eq!(names, ["feature", "main"]);
+
+        // A stashed version comes back with its symbols, bodies, includes and relationships
+        let metadata = repo.create_file_metadata(FileMetadata::new(index.id, "a.cpp".to_string(), a2.to_string(), Utc::now(), 10)).unwrap();
+        repo.update_file_processing_state(metadata.id.unwrap(), FileProcessingState::Indexed).unwrap();
+        let element = |name: &str| CodeElement::new(index.id, name.to_string(), SymbolType::Function, "a.cpp".to_string(), 1, 1, "a".repeat(64));
+        let mix = repo.create_code_element(element("mix").with_body("void mix() {}".to_string())).unwrap().id.unwrap();
+        repo.replace_file_includes(&index.id, "a.cpp", &["mixer.h".to_string()]).unwrap();
+        let other = |name: &str, line| CodeElement::new(index.id, name.to_string(), SymbolType::Function, "other.cpp".to_string(), line, 1, "b".repeat(64));
+        let caller = repo.create_code_element(other("play", 1)).unwrap().id.unwrap();
+        let helper = repo.create_code_element(other("clamp", 5)).unwrap().id.unwrap();
+        repo.create_symbol_relationship(SymbolRelationship::new(caller, mix, RelationshipType::Calls, "other.cpp".to_string(), 2)).unwrap();
+        repo.create_symbol_relationship(SymbolRelationship::new(mix, helper, RelationshipType::Calls, "a.cpp".to_string(), 1)).unwrap();
+        assert!(repo.stash_file(&index.id, "a.cpp").unwrap());
+        assert!(!repo.stash_file(&index.id, "a.cpp").unwrap());
+
+        repo.delete_code_elements_by_file(&index.id, "a.cpp").unwrap();
+        repo.create_code_element(element("blend")).unwrap();
+        assert_eq!(repo.restore_stashed_file(&index.id, "a.cpp", a2).unwrap(), Some(1));
+        let restored = repo.list_code_elements_by_file(&index.id, "a.cpp").unwrap();
+        assert_eq!(restored.iter().map(|e| e.symbol_name.as_str()).collect::<Vec<_>>(), ["mix"]);
+        assert_eq!(repo.get_symbol_body(restored[0].id.unwrap()).unwrap().as_deref(), Some("void mix() {}"));
+        assert_eq!(repo.get_file_includes(&index.id, &["a.cpp".to_string()]).unwrap()[0].include_path, "mixer.h");
+        let (outgoing, incoming) = repo.get_symbol_relationships(restored[0].id.unwrap()).unwrap();
+        assert_eq!((outgoing.len(), outgoing[0].to_symbol_id), (1, helper));
+        assert_eq!((incoming.len(), incoming[0].from_symbol_id, incoming[0].line_number), (1, caller, 2));
+        assert_eq!(repo.get_live_files(&index.id).unwrap(), files(&[("a.cpp", a2)]));
+        assert_eq!(repo.restore_stashed_file(&index.id, "a.cpp", "a9").unwrap(), None);
+
+        // Once no revision has that version it is dropped
+        assert_eq!(repo.prune_stashed_files(&index.id).unwrap(), 0);
+        repo.record_revision_files(&feature, &files(&[("a.cpp", "a3")])).unwrap();
+        assert_eq!(repo.prune_stashed_files(&index.id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_walk_rules() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Walk".to_string(), "/walk".to_string())).unwrap();
+        assert_eq!(repo.get_walk_rules(&index.id).unwrap(), None);
+
+        let mut rules = WalkRules::new(index.id, vec!["src/**".to_string()], vec!["**/gen/**".to_string()], true);
+        repo.save_walk_rules(&rules).unwrap();
+        assert_eq!(repo.get_walk_rules(&index.id).unwrap().unwrap().include_patterns, ["src/**"]);
+
+        rules.include_patterns.clear();
+        rules.respect_ignore_files = false;
+        repo.save_walk_rules(&rules).unwrap();
+        let saved = repo.get_walk_rules(&index.id).unwrap().unwrap();
+        assert!(saved.include_patterns.is_empty() && !saved.respect_ignore_files);
+        assert_eq!(saved.exclude_patterns, ["**/gen/**"]);
+
+        rules.exclude_patterns.push(" ".to_string());
+        assert!(matches!(repo.save_walk_rules(&rules), Err(StorageError::Validation(_))));
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("Parse".to_string(), "/parse".to_string())).unwrap();
+        assert_eq!(repo.get_parse_settings(&index.id).unwrap(), None);
+
+        let mut settings = ParseSettings::new(index.id, Some("/parse/build/compile_commands.json".to_string()), vec!["-I/parse/include".to_string()]);
+        repo.save_parse_settings(&settings).unwrap();
+        let saved = repo.get_parse_settings(&index.id).unwrap().unwrap();
+        assert_eq!(saved.compile_commands.as_deref(), Some("/parse/build/compile_commands.json"));
+        assert_eq!(saved.include_flags, ["-I/parse/include"]);
+
+        settings.compile_commands = None;
+        repo.save_parse_settings(&settings).unwrap();
+        assert_eq!(repo.get_parse_settings(&index.id).unwrap().unwrap().compile_commands, None);
+
+        repo.delete_code_index(&index.id).unwrap();
+        assert_eq!(repo.get_parse_settings(&index.id).unwrap(), None);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,612 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::lib::storage::archive::export_index;
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_index::{CodeIndex, IndexState};
+use crate::lib::storage::recovery::io_error;
+use crate::lib::storage::repository::Repository;
+
+/// Tag key whose value overrides the policy's maximum age for one index
+pub const TTL_TAG: &str = "ttl";
+
+/// What happens to an index selected by garbage collection
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    /// Move the index to a compressed archive file, or mark it archived in
+    /// place when the collector has no archive directory
+    Archive,
+    /// Delete the index and all related data
+    Delete,
+}
+
+/// Keep at most `keep` of the most recently updated indices matching `pattern`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionRule {
+    /// Index name glob where '*' matches any run of characters
+    pub pattern: String,
+    pub keep: usize,
+}
+
+/// Why an index was selected for garbage collection
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GcReason {
+    /// Older than the policy's maximum age
+    Expired { age_days: i64 },
+    /// Older than the index's own ttl tag
+    TtlExpired { ttl: String },
+    /// Beyond the number of indices a rule keeps
+    ExceedsKeepLimit { pattern: String, keep: usize },
+    /// Removed to bring the database under its size limit
+    DiskLimit { max_total_bytes: u64 },
+}
+
+/// An index selected for garbage collection
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GcCandidate {
+    pub index_id: Uuid,
+    pub name: String,
+    pub updated_at: DateTime<Utc>,
+    pub reason: GcReason,
+    pub action: RetentionAction,
+    /// Estimated share of the database file used by this index
+    pub estimated_bytes: u64,
+    /// Archive file the index is moved to, for archived indices when the collector has an archive directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_path: Option<PathBuf>,
+}
+
+/// Outcome of a garbage collection run
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GcReport {
+    pub candidates: Vec<GcCandidate>,
+    /// True if nothing was changed
+    pub dry_run: bool,
+}
+
+/// Retention policy for indices
+///
+/// Indices that are being created or updated are never collected. Age limits
+/// and keep rules use the configured action; the disk limit always deletes,
+/// since an archive file takes disk space too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub max_age: Option<Duration>,
+    pub rules: Vec<RetentionRule>,
+    pub max_total_bytes: Option<u64>,
+    pub action: RetentionAction,
+}
+
+/// Applies a retention policy to the indices in one or more repositories
+///
+/// With several, e.g. a database per index, the policy applies to their
+/// indices together: keep rules count across databases and the size limit
+/// is on their total. Databases indices were removed from are vacuumed so
+/// their files shrink.
+pub struct GarbageCollector<'a> {
+    repositories: Vec<&'a Repository>,
+    policy: RetentionPolicy,
+    archive_dir: Option<PathBuf>,
+}
+
+/// An index that may be collected, with the repository holding it
+struct Collectable<'a> {
+    index: CodeIndex,
+    state: IndexState,
+    repository: &'a Repository,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            rules: Vec::new(),
+            max_total_bytes: None,
+            action: RetentionAction::Delete,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Creates an empty policy that collects nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects indices not updated within `max_age`
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Keeps only the newest `keep` indices whose name matches `pattern`
+    pub fn with_rule(mut self, pattern: String, keep: usize) -> Self {
+        self.rules.push(RetentionRule { pattern, keep });
+        self
+    }
+
+    /// Caps the total database size
+    pub fn with_max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+        self.max_total_bytes = Some(max_total_bytes);
+        self
+    }
+
+    /// Sets what happens to indices selected by age or keep rules
+    pub fn with_action(mut self, action: RetentionAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Returns true if the policy has no limits
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_none() && self.rules.is_empty() && self.max_total_bytes.is_none()
+    }
+}
+
+impl RetentionRule {
+    /// Parses a "pattern=N" rule as given on the command line
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let (pattern, keep) = rule
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Retention rule must be in pattern=N form: {}", rule))?;
+        let keep = keep
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid keep count in retention rule: {}", rule))?;
+        if pattern.trim().is_empty() {
+            return Err(format!("Retention rule pattern cannot be empty: {}", rule));
+        }
+        Ok(Self {
+            pattern: pattern.trim().to_string(),
+            keep,
+        })
+    }
+
+    /// Returns true if the index name matches this rule's pattern
+    pub fn matches(&self, name: &str) -> bool {
+        glob_match(&self.pattern, name)
+    }
+}
+
+impl<'a> GarbageCollector<'a> {
+    /// Creates a collector for the given repository and policy
+    pub fn new(repository: &'a Repository, policy: RetentionPolicy) -> Self {
+        Self { repositories: vec![repository], policy, archive_dir: None }
+    }
+
+    /// Moves archived indices out of the database into `.cpidx` files in `archive_dir`
+    pub fn with_archive_dir(mut self, archive_dir: impl Into<PathBuf>) -> Self {
+        self.archive_dir = Some(archive_dir.into());
+        self
+    }
+
+    /// Also collects the indices of another repository
+    pub fn with_repository(mut self, repository: &'a Repository) -> Self {
+        self.repositories.push(repository);
+        self
+    }
+
+    /// Selects the indices the policy would collect
+    ///
+    /// `database_size_bytes` is the current size of the database files,
+    /// used to estimate each index's footprint from its share of stored rows.
+    pub fn plan(&self, database_size_bytes: u64, now: DateTime<Utc>) -> Result<Vec<GcCandidate>> {
+        let indices = self.collectable()?;
+        let footprints = self.estimate_footprints(&indices, database_size_bytes)?;
+        let mut candidates: Vec<GcCandidate> = Vec::new();
+        let mut selected: HashSet<Uuid> = HashSet::new();
+
+        let candidate = |index: &CodeIndex, reason: GcReason, action: RetentionAction| GcCandidate {
+            index_id: index.id,
+            name: index.name.clone(),
+            updated_at: index.updated_at,
+            reason,
+            action,
+            estimated_bytes: footprints.get(&index.id).copied().unwrap_or(0),
+            archive_path: self.archive_path(index, action),
+        };
+
+        for Collectable { index, state, repository } in &indices {
+            if let Some(reason) = self.age_reason(repository, index, now)? {
+                // Archiving an archived index is a no-op
+                if self.policy.action == RetentionAction::Delete || *state != IndexState::Archived {
+                    selected.insert(index.id);
+                    candidates.push(candidate(index, reason, self.policy.action));
+                }
+            }
+        }
+
+        for rule in &self.policy.rules {
+            let matching = indices
+                .iter()
+                .filter(|collectable| rule.matches(&collectable.index.name) && collectable.state != IndexState::Archived);
+            for Collectable { index, .. } in matching.skip(rule.keep) {
+                if selected.insert(index.id) {
+                    let reason = GcReason::ExceedsKeepLimit {
+                        pattern: rule.pattern.clone(),
+                        keep: rule.keep,
+                    };
+                    candidates.push(candidate(index, reason, self.policy.action));
+                }
+            }
+        }
+
+        if let Some(max_total_bytes) = self.policy.max_total_bytes {
+            let freed: u64 = candidates
+                .iter()
+                .filter(|c| c.action == RetentionAction::Delete)
+                .map(|c| c.estimated_bytes)
+                .sum();
+            let mut remaining = database_size_bytes.saturating_sub(freed);
+
+            // Oldest first; archived or selected-for-archive indices go before active ones
+            let mut by_priority: Vec<&Collectable> = indices.iter().rev().collect();
+            by_priority.sort_by_key(|collectable| collectable.state != IndexState::Archived && !selected.contains(&collectable.index.id));
+            for Collectable { index, .. } in by_priority {
+                if remaining <= max_total_bytes {
+                    break;
+                }
+                if let Some(existing) = candidates.iter_mut().find(|c| c.index_id == index.id) {
+                    if existing.action == RetentionAction::Delete {
+                        continue;
+                    }
+                    existing.action = RetentionAction::Delete;
+                    existing.archive_path = None;
+                    existing.reason = GcReason::DiskLimit { max_total_bytes };
+                    remaining = remaining.saturating_sub(existing.estimated_bytes);
+                    continue;
+                }
+                let entry = candidate(index, GcReason::DiskLimit { max_total_bytes }, RetentionAction::Delete);
+                remaining = remaining.saturating_sub(entry.estimated_bytes);
+                candidates.push(entry);
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    /// Plans and, unless `dry_run` is set, applies the policy
+    pub fn run(&self, database_size_bytes: u64, dry_run: bool) -> Result<GcReport> {
+        let candidates = self.plan(database_size_bytes, Utc::now())?;
+
+        if !dry_run {
+            let repositories: std::collections::HashMap<Uuid, &Repository> =
+                self.collectable()?.into_iter().map(|collectable| (collectable.index.id, collectable.repository)).collect();
+            if let Some(archive_dir) = self.archive_dir.as_deref().filter(|_| candidates.iter().any(|c| c.archive_path.is_some())) {
+                std::fs::create_dir_all(archive_dir).map_err(|e| io_error("Failed to create archive directory", e))?;
+            }
+            let mut shrunk: Vec<&Repository> = Vec::new();
+            for candidate in &candidates {
+                let repository = repositories[&candidate.index_id];
+                match (candidate.action, &candidate.archive_path) {
+                    (RetentionAction::Archive, None) => {
+                        repository.update_code_index_state(&candidate.index_id, IndexState::Archived)?;
+                        continue;
+                    }
+                    (RetentionAction::Archive, Some(path)) => {
+                        export_index(repository, &candidate.name, path)?;
+                        repository.delete_code_index(&candidate.index_id)?;
+                    }
+                    (RetentionAction::Delete, _) => repository.delete_code_index(&candidate.index_id)?,
+                }
+                if !shrunk.iter().any(|other| std::ptr::eq(*other, repository)) {
+                    shrunk.push(repository);
+                }
+            }
+            for repository in shrunk {
+                vacuum(repository)?;
+            }
+        }
+
+        Ok(GcReport { candidates, dry_run })
+    }
+
+    /// File an index is archived to: `<name>-<last update>.cpidx` in the archive directory
+    fn archive_path(&self, index: &CodeIndex, action: RetentionAction) -> Option<PathBuf> {
+        let archive_dir = self.archive_dir.as_deref().filter(|_| action == RetentionAction::Archive)?;
+        Some(archive_file(archive_dir, index))
+    }
+
+    /// Indices of every repository that are not being written, newest first
+    fn collectable(&self) -> Result<Vec<Collectable<'a>>> {
+        let mut indices = Vec::new();
+        for &repository in &self.repositories {
+            for index in repository.list_code_indices()? {
+                // Never collect an index that is still being written
+                match repository.get_code_index_state(&index.id)? {
+                    Some(IndexState::Creating) | Some(IndexState::Updating) | None => continue,
+                    Some(state) => indices.push(Collectable { index, state, repository }),
+                }
+            }
+        }
+        // Newest first, so keep rules retain the most recent indices; names break ties
+        indices.sort_by(|a, b| b.index.updated_at.cmp(&a.index.updated_at).then_with(|| a.index.name.cmp(&b.index.name)));
+        Ok(indices)
+    }
+
+    /// Checks the index's ttl tag, then the policy's maximum age
+    fn age_reason(&self, repository: &Repository, index: &CodeIndex, now: DateTime<Utc>) -> Result<Option<GcReason>> {
+        let age = now - index.updated_at;
+
+        if let Some(ttl) = repository.get_index_tags(&index.id)?.get(TTL_TAG) {
+            // An unparseable ttl protects the index rather than deleting it early
+            return Ok(parse_duration(ttl)
+                .filter(|limit| age > *limit)
+                .map(|_| GcReason::TtlExpired { ttl: ttl.clone() }));
+        }
+
+        Ok(self
+            .policy
+            .max_age
+            .filter(|limit| age > *limit)
+            .map(|_| GcReason::Expired { age_days: age.num_days() }))
+    }
+
+    /// Splits the database size across indices by their share of stored rows
+    fn estimate_footprints(
+        &self,
+        indices: &[Collectable<'a>],
+        database_size_bytes: u64,
+    ) -> Result<std::collections::HashMap<Uuid, u64>> {
+        let mut rows: Vec<(Uuid, u64)> = Vec::new();
+        for &repository in &self.repositories {
+            let statistics = repository.get_index_statistics()?;
+            for Collectable { index, .. } in indices.iter().filter(|collectable| std::ptr::eq(collectable.repository, repository)) {
+                let count = statistics.get(&index.name).map_or(0, |s| {
+                    u64::from(s.actual_files) + u64::from(s.actual_elements) + u64::from(s.relationships)
+                });
+                // Every index costs at least its own row
+                rows.push((index.id, count + 1));
+            }
+        }
+        let total_rows: u64 = rows.iter().map(|(_, count)| count).sum();
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, count)| (id, database_size_bytes * count / total_rows.max(1)))
+            .collect())
+    }
+}
+
+/// Archive file of an index in `archive_dir`, with characters that have a meaning in paths replaced
+fn archive_file(archive_dir: &Path, index: &CodeIndex) -> PathBuf {
+    let name: String = index
+        .name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    archive_dir.join(format!("{}-{}.cpidx", name, index.updated_at.format("%Y%m%dT%H%M%S")))
+}
+
+/// Rebuilds the database file without the pages freed by removed indices, then truncates its WAL
+fn vacuum(repository: &Repository) -> Result<()> {
+    let connection = repository.connection();
+    connection.execute_batch("VACUUM")?;
+    // wal_checkpoint reports its outcome as a row
+    connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    Ok(())
+}
+
+/// Parses a duration such as "30m", "12h", "7d" or "2w"
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let unit_at = text.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = text[..unit_at].parse().ok()?;
+
+    match &text[unit_at..] {
+        "m" => Some(Duration::minutes(amount)),
+        "h" => Some(Duration::hours(amount)),
+        "d" => Some(Duration::days(amount)),
+        "w" => Some(Duration::weeks(amount)),
+        _ => None,
+    }
+}
+
+/// Matches `name` against a glob where '*' matches any run of characters
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || name.len() < first.len() + last.len() || !name.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::index_tag::IndexTag;
+
+    fn create_test_repository() -> Repository {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        Repository::new(manager.connect().unwrap())
+    }
+
+    fn add_index(repo: &Repository, name: &str, age_days: i64) -> Uuid {
+        let mut index = CodeIndex::new(name.to_string(), format!("/src/{}", name));
+        index.updated_at = Utc::now() - Duration::days(age_days);
+        let id = index.id;
+        repo.create_code_index(index).unwrap();
+        repo.update_code_index_state(&id, IndexState::Active).unwrap();
+        // update_code_index_state bumps updated_at; restore the intended age
+        let mut index = repo.get_code_index(&id).unwrap().unwrap();
+        index.updated_at = Utc::now() - Duration::days(age_days);
+        repo.update_code_index(&index).unwrap();
+        id
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_duration("12h"), Some(Duration::hours(12)));
+        assert_eq!(parse_duration("7d"), Some(Duration::days(7)));
+        assert_eq!(parse_duration("2w"), Some(Duration::weeks(2)));
+        assert_eq!(parse_duration("7"), None);
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("3y"), None);
+    }
+
+    #[test]
+    fn test_glob_match_and_rule_parse() {
+        assert!(glob_match("ci-*", "ci-1234"));
+        assert!(glob_match("*-pr-*", "engine-pr-42"));
+        assert!(glob_match("main", "main"));
+        assert!(!glob_match("ci-*", "main"));
+        assert!(!glob_match("a*b*c", "acb"));
+
+        let rule = RetentionRule::parse("ci-*=5").unwrap();
+        assert_eq!(rule.pattern, "ci-*");
+        assert_eq!(rule.keep, 5);
+        assert!(RetentionRule::parse("ci-*").is_err());
+        assert!(RetentionRule::parse("=3").is_err());
+    }
+
+    #[test]
+    fn test_max_age_and_ttl() {
+        let repo = create_test_repository();
+        add_index(&repo, "fresh", 1);
+        let old = add_index(&repo, "old", 40);
+        let pinned = add_index(&repo, "pinned", 40);
+        let short = add_index(&repo, "short-lived", 3);
+        repo.set_index_tag(&IndexTag::new(pinned, TTL_TAG.to_string(), "90d".to_string())).unwrap();
+        repo.set_index_tag(&IndexTag::new(short, TTL_TAG.to_string(), "2d".to_string())).unwrap();
+
+        let policy = RetentionPolicy::new().with_max_age(Duration::days(30));
+        let plan = GarbageCollector::new(&repo, policy).plan(0, Utc::now()).unwrap();
+        let ids: Vec<Uuid> = plan.iter().map(|c| c.index_id).collect();
+
+        assert_eq!(plan.len(), 2);
+        assert!(ids.contains(&old));
+        assert!(ids.contains(&short));
+    }
+
+    #[test]
+    fn test_keep_rule_and_run() {
+        let repo = create_test_repository();
+        add_index(&repo, "ci-1", 3);
+        add_index(&repo, "ci-2", 2);
+        add_index(&repo, "ci-3", 1);
+        add_index(&repo, "main", 10);
+
+        let policy = RetentionPolicy::new()
+            .with_rule("ci-*".to_string(), 2)
+            .with_action(RetentionAction::Archive);
+        let collector = GarbageCollector::new(&repo, policy);
+
+        let dry = collector.run(0, true).unwrap();
+        assert_eq!(dry.candidates.len(), 1);
+        assert_eq!(dry.candidates[0].name, "ci-1");
+
+        collector.run(0, false).unwrap();
+        let ci1 = repo.get_code_index_by_name("ci-1").unwrap().unwrap();
+        assert_eq!(repo.get_code_index_state(&ci1.id).unwrap(), Some(IndexState::Archived));
+
+        // Archived indices no longer count against the rule
+        assert!(collector.run(0, false).unwrap().candidates.is_empty());
+    }
+
+    #[test]
+    fn test_archive_moves_index_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = DatabaseManager::new(DatabaseConfig::new(dir.path().join("gc.db"))).unwrap();
+        let repo = Repository::new(manager.connect().unwrap());
+        add_index(&repo, "nightly/old", 40);
+        add_index(&repo, "nightly/new", 1);
+
+        let policy = RetentionPolicy::new().with_max_age(Duration::days(30)).with_action(RetentionAction::Archive);
+        let collector = GarbageCollector::new(&repo, policy).with_archive_dir(dir.path().join("archive"));
+        let report = collector.run(0, false).unwrap();
+
+        assert_eq!(report.candidates.len(), 1);
+        let archive_path = report.candidates[0].archive_path.clone().unwrap();
+        assert!(archive_path.starts_with(dir.path().join("archive")));
+        assert!(archive_path.file_name().unwrap().to_string_lossy().starts_with("nightly_old-"));
+        assert!(repo.get_code_index_by_name("nightly/old").unwrap().is_none());
+
+        let file = std::fs::File::open(&archive_path).unwrap();
+        let archive = crate::lib::storage::archive::IndexArchive::read_from(std::io::BufReader::new(file)).unwrap();
+        assert_eq!(archive.index.name, "nightly/old");
+    }
+
+    #[test]
+    fn test_policy_spans_repositories() {
+        let engine = create_test_repository();
+        let editor = create_test_repository();
+        add_index(&engine, "ci-1", 3);
+        add_index(&editor, "ci-2", 2);
+        add_index(&engine, "ci-3", 1);
+
+        let policy = RetentionPolicy::new().with_rule("ci-*".to_string(), 1);
+        let report = GarbageCollector::new(&engine, policy).with_repository(&editor).run(0, false).unwrap();
+        let names: Vec<&str> = report.candidates.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["ci-2", "ci-1"]);
+        assert!(engine.get_code_index_by_name("ci-1").unwrap().is_none());
+        assert!(editor.get_code_index_by_name("ci-2").unwrap().is_none());
+        assert!(engine.get_code_index_by_name("ci-3").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_disk_limit_deletes_oldest() {
+        let repo = create_test_repository();
+        add_index(&repo, "a", 3);
+        add_index(&repo, "b", 2);
+        add_index(&repo, "c", 1);
+
+        // Three equally sized indices in 3000 bytes; a 2000 byte cap drops the oldest
+        let policy = RetentionPolicy::new().with_max_total_bytes(2000);
+        let plan = GarbageCollector::new(&repo, policy).plan(3000, Utc::now()).unwrap();
+
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].name, "a");
+        assert_eq!(plan[0].action, RetentionAction::Delete);
+    }
+
+    #[test]
+    fn test_disk_limit_deletes_archived_first() {
+        let repo = create_test_repository();
+        add_index(&repo, "a", 4);
+        add_index(&repo, "b", 3);
+        add_index(&repo, "c", 2);
+        let archived = add_index(&repo, "d", 1);
+        repo.update_code_index_state(&archived, IndexState::Archived).unwrap();
+
+        // d is archived and c selected for archiving by the keep rule, so both go before the older a
+        let policy = RetentionPolicy::new()
+            .with_action(RetentionAction::Archive)
+            .with_rule("c".to_string(), 0)
+            .with_max_total_bytes(2000);
+        let plan = GarbageCollector::new(&repo, policy).plan(4000, Utc::now()).unwrap();
+
+        let names: Vec<_> = plan.iter().map(|c| (c.name.as_str(), c.action)).collect();
+        assert_eq!(names, [("c", RetentionAction::Delete), ("d", RetentionAction::Delete)]);
+    }
+
+    #[test]
+    fn test_skips_indices_in_progress() {
+        let repo = create_test_repository();
+        let mut index = CodeIndex::new("building".to_string(), "/src/building".to_string());
+        index.updated_at = Utc::now() - Duration::days(100);
+        repo.create_code_index(index).unwrap();
+
+        let policy = RetentionPolicy::new().with_max_age(Duration::days(1));
+        assert!(GarbageCollector::new(&repo, policy).plan(0, Utc::now()).unwrap().is_empty());
+    }
+}
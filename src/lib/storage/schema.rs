@@ -1,8 +1,40 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
 use std::collections::HashMap;
 
 /// Database schema version - increment when making schema changes
-pub const CURRENT_SCHEMA_VERSION: i32 = 1;
+pub const CURRENT_SCHEMA_VERSION: i32 = 29;
+
+/// Oldest schema version whose binaries can read a database at the current version
+///
+/// Binaries reading the on-disk contract (schema 20 and later) open a
+/// database of a newer schema read-only as long as its recorded minimum is
+/// at or below their own version: their queries name the tables and
+/// columns they know, and newer ones are ignored. Raise this only when a
+/// migration changes existing tables in a way older queries can't read,
+/// such as a rename or a rebuilt table; migrations that add tables,
+/// columns with defaults or indices leave it alone.
+pub const MIN_READER_VERSION: i32 = 28;
+
+/// Schema version that added the `schema_compatibility` table
+const COMPATIBILITY_TABLE_VERSION: i32 = 20;
+
+/// Migrations that rebuild a table other tables reference
+///
+/// Dropping the old table with foreign keys enforced would cascade into
+/// its children, so these run with enforcement off, in their own
+/// transaction, and are checked with `foreign_key_check` before commit.
+const TABLE_REBUILDS: &[i32] = &[28];
+
+/// How this build can use a database, given the schema it was written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaCompatibility {
+    /// The schema is this build's or older, and is migrated on open
+    Current,
+    /// A newer schema older binaries can still read; opened read-only
+    ReadOnly { found: i32 },
+    /// A newer schema this build can't read
+    Incompatible { found: i32 },
+}
 
 /// Schema migration manager for SQLite database
 pub struct SchemaMigrator {
@@ -21,13 +53,83 @@ impl SchemaMigrator {
         let current_version = self.get_current_version()?;
         
         if current_version < CURRENT_SCHEMA_VERSION {
-            self.run_migrations_from(current_version)?;
-            self.set_schema_version(CURRENT_SCHEMA_VERSION)?;
+            self.upgrade(CURRENT_SCHEMA_VERSION)?;
         }
         
         Ok(())
     }
 
+    /// Moves the database to schema version `target`, upgrading or downgrading it
+    ///
+    /// The caller checks that `target` is between 1 and this build's version
+    /// and that [`Self::missing_steps`] is empty for it.
+    pub fn migrate_to(&mut self, target: i32) -> Result<()> {
+        self.ensure_migration_table()?;
+        let current_version = self.get_current_version()?;
+
+        if target > current_version {
+            self.upgrade(target)?;
+        } else if target < current_version {
+            self.downgrade(target)?;
+        }
+        Ok(())
+    }
+
+    /// Versions between the database's and `target` lacking the SQL to cross them
+    ///
+    /// Each version depends on the one before it, so a path is only usable
+    /// if every version on it can be applied, or undone, in turn.
+    pub fn missing_steps(&self, target: i32) -> Result<Vec<i32>> {
+        let current_version = self.get_current_version()?;
+        let missing = if target >= current_version {
+            let migrations = self.get_migrations();
+            ((current_version + 1)..=target).filter(|version| !migrations.contains_key(version)).collect()
+        } else {
+            let downgrades = self.get_downgrades();
+            ((target + 1)..=current_version).rev().filter(|version| !downgrades.contains_key(version)).collect()
+        };
+        Ok(missing)
+    }
+
+    /// Compares the database's schema with this build's
+    pub fn compatibility(&self) -> Result<SchemaCompatibility> {
+        let found = self.get_current_version()?;
+        if found <= CURRENT_SCHEMA_VERSION {
+            return Ok(SchemaCompatibility::Current);
+        }
+
+        // Databases without the table predate the contract, which can't be newer than this build
+        let min_reader: Option<i32> = self
+            .connection
+            .query_row("SELECT min_reader_version FROM schema_compatibility WHERE id = 1", [], |row| row.get(0))
+            .ok();
+        match min_reader {
+            Some(min_reader) if min_reader <= CURRENT_SCHEMA_VERSION => Ok(SchemaCompatibility::ReadOnly { found }),
+            _ => Ok(SchemaCompatibility::Incompatible { found }),
+        }
+    }
+
+    /// Strips the features added after schema version `target`
+    ///
+    /// Tables, columns and indices added by later migrations are dropped, so
+    /// a binary of that version opens the database as its own. The caller
+    /// checks that `target` is between 1 and the database's version, which
+    /// must not be newer than this build's.
+    pub fn downgrade(&mut self, target: i32) -> Result<()> {
+        let current_version = self.get_current_version()?;
+        let downgrades = self.get_downgrades();
+
+        let transaction = self.connection.transaction()?;
+        for version in ((target + 1)..=current_version).rev() {
+            if let Some(downgrade_sql) = downgrades.get(&version) {
+                transaction.execute_batch(downgrade_sql)?;
+            }
+        }
+        transaction.execute("DELETE FROM schema_migrations WHERE version > ?1", [target])?;
+        transaction.execute("INSERT OR REPLACE INTO schema_migrations (version) VALUES (?1)", [target])?;
+        transaction.commit()
+    }
+
     /// Returns the current schema version of the database
     pub fn get_current_version(&self) -> Result<i32> {
         let version: Result<i32> = self.connection.query_row(
@@ -39,6 +141,7 @@ impl SchemaMigrator {
         match version {
             Ok(v) => Ok(v),
             Err(rusqlite::Error::SqliteFailure(_, _)) => Ok(0), // No migrations table yet
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(0), // No migrations applied yet
             Err(e) => Err(e),
         }
     }
@@ -66,19 +169,59 @@ impl SchemaMigrator {
         Ok(())
     }
 
-    /// Runs all migrations starting from the given version
-    fn run_migrations_from(&mut self, from_version: i32) -> Result<()> {
+    /// Runs the migrations after the database's version up to `target` and records it
+    fn upgrade(&mut self, target: i32) -> Result<()> {
+        let current_version = self.get_current_version()?;
+        self.run_migrations(current_version, target)?;
+        self.set_schema_version(target)?;
+        if target >= COMPATIBILITY_TABLE_VERSION {
+            self.connection.execute(
+                "INSERT OR REPLACE INTO schema_compatibility (id, min_reader_version) VALUES (1, ?1)",
+                [MIN_READER_VERSION],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Runs the migrations after `from_version` up to `to_version`
+    fn run_migrations(&mut self, from_version: i32, to_version: i32) -> Result<()> {
         let migrations = self.get_migrations();
         
-        for version in (from_version + 1)..=CURRENT_SCHEMA_VERSION {
-            if let Some(migration_sql) = migrations.get(&version) {
-                self.connection.execute_batch(migration_sql)?;
+        for version in (from_version + 1)..=to_version {
+            match migrations.get(&version) {
+                Some(migration_sql) if TABLE_REBUILDS.contains(&version) => self.rebuild_tables(migration_sql)?,
+                Some(migration_sql) => self.connection.execute_batch(migration_sql)?,
+                None => {}
             }
         }
         
         Ok(())
     }
 
+    /// Runs a table-rebuilding migration with foreign keys off, restoring the previous setting after
+    fn rebuild_tables(&mut self, migration_sql: &str) -> Result<()> {
+        let foreign_keys: bool = self.connection.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        self.connection.execute_batch("PRAGMA foreign_keys = OFF")?;
+        let rebuilt = (|| {
+            let transaction = self.connection.transaction()?;
+            transaction.execute_batch(migration_sql)?;
+            let violation: Option<String> = transaction
+                .query_row("PRAGMA foreign_key_check", [], |row| row.get(0))
+                .optional()?;
+            if let Some(table) = violation {
+                return Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+                    Some(format!("Rebuilding tables left dangling references in {}", table)),
+                ));
+            }
+            transaction.commit()
+        })();
+        if foreign_keys {
+            self.connection.execute_batch("PRAGMA foreign_keys = ON")?;
+        }
+        rebuilt
+    }
+
     /// Returns a map of version -> SQL migration statements
     fn get_migrations(&self) -> HashMap<i32, &'static str> {
         let mut migrations = HashMap::new();
@@ -86,9 +229,135 @@ impl SchemaMigrator {
         // Migration 1: Initial schema
         migrations.insert(1, MIGRATION_V1);
         
+        // Migration 2: Per-index tags
+        migrations.insert(2, MIGRATION_V2);
+        
+        // Migration 3: Slow query log
+        migrations.insert(3, MIGRATION_V3);
+        
+        // Migration 4: Unique code element identity
+        migrations.insert(4, MIGRATION_V4);
+        
+        // Migration 5: Callback references
+        migrations.insert(5, MIGRATION_V5);
+        
+        // Migration 6: Symbol tags
+        migrations.insert(6, MIGRATION_V6);
+        
+        // Migration 7: Memory sections
+        migrations.insert(7, MIGRATION_V7);
+        
+        // Migration 8: Symbol annotations
+        migrations.insert(8, MIGRATION_V8);
+        
+        // Migration 9: Saved queries
+        migrations.insert(9, MIGRATION_V9);
+        
+        // Migration 10: Watched saved queries
+        migrations.insert(10, MIGRATION_V10);
+        
+        // Migration 11: Vendored copy aliases
+        migrations.insert(11, MIGRATION_V11);
+
+        // Migration 12: Append-only audit trail of administrative operations
+        migrations.insert(12, MIGRATION_V12);
+
+        // Migration 13: Full-text search over symbols
+        migrations.insert(13, MIGRATION_V13);
+
+        // Migration 14: Detail level of each file's stored symbols
+        migrations.insert(14, MIGRATION_V14);
+
+        // Migration 15: Query hits per directory and the indexing depth planned from them
+        migrations.insert(15, MIGRATION_V15);
+
+        // Migration 16: Reference counts and call degree per symbol
+        migrations.insert(16, MIGRATION_V16);
+
+        // Migration 17: Build configurations and the symbols each one compiles out
+        migrations.insert(17, MIGRATION_V17);
+
+        // Migration 18: Compressed symbol bodies
+        migrations.insert(18, MIGRATION_V18);
+
+        // Migration 19: Include edges between files
+        migrations.insert(19, MIGRATION_V19);
+
+        // Migration 20: Oldest schema version that can read the database
+        migrations.insert(20, MIGRATION_V20);
+
+        // Migration 21: Qualified names and namespace paths of symbols
+        migrations.insert(21, MIGRATION_V21);
+
+        // Migration 22: Trigram index over symbol names for fuzzy search
+        migrations.insert(22, MIGRATION_V22);
+
+        // Migration 23: Branch revisions of an index and stashed file versions
+        migrations.insert(23, MIGRATION_V23);
+
+        // Migration 24: Include/exclude rules of the directory walk per index
+        migrations.insert(24, MIGRATION_V24);
+
+        // Migration 25: How `index create` set up the parser of an index
+        migrations.insert(25, MIGRATION_V25);
+
+        // Migration 26: Unified Symbol Resolution ids telling overloads apart
+        migrations.insert(26, MIGRATION_V26);
+
+        // Migration 27: Why the files that failed to index failed
+        migrations.insert(27, MIGRATION_V27);
+
+        // Migration 28: Field, enum constant, lambda and unknown symbols
+        migrations.insert(28, MIGRATION_V28);
+
+        // Migration 29: Whole signatures too long for code_elements
+        migrations.insert(29, MIGRATION_V29);
+        
         migrations
     }
 
+    /// Returns a map of version -> SQL undoing that version's migration
+    fn get_downgrades(&self) -> HashMap<i32, &'static str> {
+        HashMap::from([
+            (2, "DROP TABLE index_tags;"),
+            (3, "DROP TABLE slow_queries;"),
+            (4, "DROP INDEX idx_code_elements_identity;"),
+            (5, DOWNGRADE_V5),
+            (6, "DROP TABLE symbol_tags;"),
+            (7, "DROP INDEX idx_code_elements_memory_section; ALTER TABLE code_elements DROP COLUMN memory_section;"),
+            (8, "DROP TABLE symbol_annotations;"),
+            (9, "DROP TABLE saved_queries;"),
+            (
+                10,
+                "ALTER TABLE saved_queries DROP COLUMN watched; ALTER TABLE saved_queries DROP COLUMN last_result; \
+                 ALTER TABLE saved_queries DROP COLUMN last_checked_at;",
+            ),
+            (11, "DROP TABLE path_aliases;"),
+            (12, "DROP TABLE admin_audit;"),
+            (13, DOWNGRADE_V13),
+            (14, "ALTER TABLE file_metadata DROP COLUMN detail;"),
+            (15, "DROP TABLE directory_query_hits; DROP TABLE directory_depths;"),
+            (16, DOWNGRADE_V16),
+            (17, "DROP TABLE configuration_excluded_symbols; DROP TABLE build_configurations;"),
+            (18, "DROP TABLE symbol_bodies;"),
+            (19, "DROP TABLE file_includes;"),
+            (20, "DROP TABLE schema_compatibility;"),
+            (
+                21,
+                "DROP INDEX idx_code_elements_qualified_name; ALTER TABLE code_elements DROP COLUMN namespace_path; \
+                 ALTER TABLE code_elements DROP COLUMN fully_qualified_name;",
+            ),
+            (22, DOWNGRADE_V22),
+            (23, "DROP TABLE stashed_files; DROP TABLE revision_files; DROP TABLE index_revisions;"),
+            (24, "DROP TABLE index_walk_rules;"),
+            (25, "DROP TABLE index_parse_settings;"),
+            (26, "DROP INDEX idx_code_elements_usr; ALTER TABLE code_elements DROP COLUMN usr;"),
+            (27, "DROP TABLE index_errors;"),
+            (28, DOWNGRADE_V28),
+            (29, "DROP TABLE symbol_signatures;"),
+        ])
+    }
+
     /// Returns a reference to the underlying connection
     pub fn connection(&self) -> &Connection {
         &self.connection
@@ -260,7 +529,7 @@ BEGIN
         FROM file_metadata 
         WHERE index_id = NEW.index_id AND processing_state = 'indexed'
     ),
-    updated_at = CURRENT_TIMESTAMP
+    updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
     WHERE id = NEW.index_id;
 END;
 
@@ -279,7 +548,7 @@ BEGIN
         FROM file_metadata 
         WHERE index_id = NEW.index_id AND processing_state = 'indexed'
     ),
-    updated_at = CURRENT_TIMESTAMP
+    updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
     WHERE id = NEW.index_id;
 END;
 
@@ -287,21 +556,702 @@ CREATE TRIGGER update_session_activity_on_query
 AFTER UPDATE OF query_count ON mcp_query_sessions
 BEGIN
     UPDATE mcp_query_sessions 
-    SET last_activity = CURRENT_TIMESTAMP 
+    SET last_activity = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
     WHERE session_id = NEW.session_id;
 END;
 "#;
 
+/// Migration V2: Free-form key/value tags on indices
+const MIGRATION_V2: &str = r#"
+CREATE TABLE index_tags (
+    index_id TEXT NOT NULL,
+    tag_key TEXT NOT NULL,
+    tag_value TEXT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    PRIMARY KEY (index_id, tag_key),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_index_tags_key_value ON index_tags(tag_key, tag_value);
+"#;
+
+/// Migration V3: Repository queries that exceeded the slow query threshold
+const MIGRATION_V3: &str = r#"
+CREATE TABLE slow_queries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    recorded_at DATETIME NOT NULL,
+    operation TEXT NOT NULL,
+    sql_text TEXT NOT NULL,
+    params_summary TEXT NOT NULL,
+    duration_ms REAL NOT NULL CHECK (duration_ms >= 0),
+    rows_returned INTEGER NOT NULL CHECK (rows_returned >= 0)
+);
+
+CREATE INDEX idx_slow_queries_duration ON slow_queries(duration_ms DESC);
+"#;
+
+/// Migration V4: One row per symbol location so re-indexing a file cannot duplicate it
+///
+/// Existing duplicates are collapsed onto the oldest row before the unique index is
+/// created; relationships are moved to the surviving row where that doesn't collide.
+const MIGRATION_V4: &str = r#"
+CREATE TEMP TABLE code_element_duplicates AS
+SELECT ce.id AS duplicate_id, keep.keep_id
+FROM code_elements ce
+JOIN (
+    SELECT MIN(id) AS keep_id, index_id, file_path, line_number, column_number, symbol_name, symbol_type
+    FROM code_elements
+    GROUP BY index_id, file_path, line_number, column_number, symbol_name, symbol_type
+    HAVING COUNT(*) > 1
+) keep USING (index_id, file_path, line_number, column_number, symbol_name, symbol_type)
+WHERE ce.id <> keep.keep_id;
+
+UPDATE OR IGNORE symbol_relationships
+SET from_symbol_id = (SELECT keep_id FROM code_element_duplicates WHERE duplicate_id = from_symbol_id)
+WHERE from_symbol_id IN (SELECT duplicate_id FROM code_element_duplicates);
+
+UPDATE OR IGNORE symbol_relationships
+SET to_symbol_id = (SELECT keep_id FROM code_element_duplicates WHERE duplicate_id = to_symbol_id)
+WHERE to_symbol_id IN (SELECT duplicate_id FROM code_element_duplicates);
+
+DELETE FROM symbol_relationships
+WHERE from_symbol_id IN (SELECT duplicate_id FROM code_element_duplicates)
+   OR to_symbol_id IN (SELECT duplicate_id FROM code_element_duplicates);
+
+DELETE FROM code_elements WHERE id IN (SELECT duplicate_id FROM code_element_duplicates);
+
+DROP TABLE code_element_duplicates;
+
+CREATE UNIQUE INDEX idx_code_elements_identity
+ON code_elements(index_id, file_path, line_number, column_number, symbol_name, symbol_type);
+"#;
+
+/// Migration V5: Allow 'referenced_as_callback' relationships
+///
+/// SQLite cannot alter a CHECK constraint, so the table is rebuilt with the
+/// extended type list and its rows and indices copied over.
+const MIGRATION_V5: &str = r#"
+CREATE TABLE symbol_relationships_v5 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_symbol_id INTEGER NOT NULL,
+    to_symbol_id INTEGER NOT NULL,
+    relationship_type TEXT NOT NULL CHECK (relationship_type IN ('inherits', 'uses', 'includes', 'calls', 'defines', 'instantiates', 'contained_in', 'friend', 'overrides', 'specializes', 'referenced_as_callback')),
+    file_path TEXT NOT NULL,
+    line_number INTEGER NOT NULL,
+    FOREIGN KEY (from_symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE,
+    FOREIGN KEY (to_symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE,
+    UNIQUE(from_symbol_id, to_symbol_id, relationship_type, line_number)
+);
+
+INSERT INTO symbol_relationships_v5 (id, from_symbol_id, to_symbol_id, relationship_type, file_path, line_number)
+SELECT id, from_symbol_id, to_symbol_id, relationship_type, file_path, line_number FROM symbol_relationships;
+
+DROP TABLE symbol_relationships;
+ALTER TABLE symbol_relationships_v5 RENAME TO symbol_relationships;
+
+CREATE INDEX idx_symbol_relationships_from ON symbol_relationships(from_symbol_id);
+CREATE INDEX idx_symbol_relationships_to ON symbol_relationships(to_symbol_id);
+CREATE INDEX idx_symbol_relationships_type ON symbol_relationships(relationship_type);
+CREATE INDEX idx_symbol_relationships_file_path ON symbol_relationships(file_path);
+"#;
+
+/// Migration V6: Tags on individual symbols, such as hot-path roots
+const MIGRATION_V6: &str = r#"
+CREATE TABLE symbol_tags (
+    symbol_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (symbol_id, tag),
+    FOREIGN KEY (symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_symbol_tags_tag ON symbol_tags(tag);
+"#;
+
+/// Migration V7: Linker section of each symbol, from section attributes and placement macros
+const MIGRATION_V7: &str = r#"
+ALTER TABLE code_elements ADD COLUMN memory_section TEXT;
+
+CREATE INDEX idx_code_elements_memory_section ON code_elements(index_id, memory_section)
+WHERE memory_section IS NOT NULL;
+"#;
+
+/// Migration V8: Notes and bookmarks on symbols, keyed by scope and name to survive re-indexing
+const MIGRATION_V8: &str = r#"
+CREATE TABLE symbol_annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    index_id TEXT NOT NULL,
+    symbol_name TEXT NOT NULL,
+    scope TEXT NOT NULL DEFAULT '',
+    author TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'bookmark')),
+    text TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_symbol_annotations_symbol ON symbol_annotations(index_id, symbol_name, scope);
+CREATE INDEX idx_symbol_annotations_author ON symbol_annotations(index_id, author);
+"#;
+
+/// Migration V9: Named filter expressions per index
+const MIGRATION_V9: &str = r#"
+CREATE TABLE saved_queries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    index_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    description TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE,
+    UNIQUE(index_id, name)
+);
+"#;
+
+/// Migration V10: Standing queries with the result set they last produced
+const MIGRATION_V10: &str = r#"
+ALTER TABLE saved_queries ADD COLUMN watched INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE saved_queries ADD COLUMN last_result TEXT;
+ALTER TABLE saved_queries ADD COLUMN last_checked_at DATETIME;
+"#;
+
+/// Migration V11: Directories skipped as byte-identical copies of an indexed directory
+const MIGRATION_V11: &str = r#"
+CREATE TABLE path_aliases (
+    index_id TEXT NOT NULL,
+    alias_path TEXT NOT NULL,
+    canonical_path TEXT NOT NULL,
+    file_count INTEGER NOT NULL,
+    tree_hash TEXT NOT NULL,
+    PRIMARY KEY (index_id, alias_path),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V12: Administrative audit trail
+///
+/// No foreign key to code_indices: entries outlive the indices they describe.
+/// Triggers reject updates and deletes so the trail is append-only.
+const MIGRATION_V12: &str = r#"
+CREATE TABLE admin_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation TEXT NOT NULL,
+    index_id TEXT,
+    index_name TEXT,
+    actor TEXT NOT NULL,
+    session_id TEXT,
+    parameters TEXT NOT NULL DEFAULT '{}',
+    recorded_at DATETIME NOT NULL
+);
+
+CREATE INDEX idx_admin_audit_index_name ON admin_audit(index_name);
+CREATE INDEX idx_admin_audit_recorded_at ON admin_audit(recorded_at);
+
+CREATE TRIGGER admin_audit_no_update BEFORE UPDATE ON admin_audit
+BEGIN
+    SELECT RAISE(ABORT, 'admin_audit is append-only');
+END;
+
+CREATE TRIGGER admin_audit_no_delete BEFORE DELETE ON admin_audit
+BEGIN
+    SELECT RAISE(ABORT, 'admin_audit is append-only');
+END;
+"#;
+
+/// Migration V13: Symbol documentation and an FTS5 index over code elements
+///
+/// The FTS table stores no content of its own; triggers keep it in step with
+/// code_elements, and the final statement indexes the rows already present.
+const MIGRATION_V13: &str = r#"
+ALTER TABLE code_elements ADD COLUMN documentation TEXT;
+
+CREATE VIRTUAL TABLE code_elements_fts USING fts5(
+    symbol_name,
+    signature,
+    scope,
+    documentation,
+    content = 'code_elements',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE TRIGGER code_elements_fts_insert AFTER INSERT ON code_elements
+BEGIN
+    INSERT INTO code_elements_fts (rowid, symbol_name, signature, scope, documentation)
+    VALUES (new.id, new.symbol_name, new.signature, new.scope, new.documentation);
+END;
+
+CREATE TRIGGER code_elements_fts_delete AFTER DELETE ON code_elements
+BEGIN
+    INSERT INTO code_elements_fts (code_elements_fts, rowid, symbol_name, signature, scope, documentation)
+    VALUES ('delete', old.id, old.symbol_name, old.signature, old.scope, old.documentation);
+END;
+
+CREATE TRIGGER code_elements_fts_update AFTER UPDATE OF symbol_name, signature, scope, documentation ON code_elements
+BEGIN
+    INSERT INTO code_elements_fts (code_elements_fts, rowid, symbol_name, signature, scope, documentation)
+    VALUES ('delete', old.id, old.symbol_name, old.signature, old.scope, old.documentation);
+    INSERT INTO code_elements_fts (rowid, symbol_name, signature, scope, documentation)
+    VALUES (new.id, new.symbol_name, new.signature, new.scope, new.documentation);
+END;
+
+INSERT INTO code_elements_fts (code_elements_fts) VALUES ('rebuild');
+"#;
+
+/// Migration V14: Detail level of each file's stored symbols
+///
+/// 'reduced' marks files indexed under a tiered detail policy, which can be
+/// promoted to 'full' by re-indexing them.
+const MIGRATION_V14: &str = r#"
+ALTER TABLE file_metadata ADD COLUMN detail TEXT NOT NULL DEFAULT 'full';
+"#;
+
+/// Migration V15: Query hits per directory and the indexing depth planned from them
+///
+/// Hits are bucketed by day so planning can look at a recent window and old
+/// buckets can be dropped.
+const MIGRATION_V15: &str = r#"
+CREATE TABLE directory_query_hits (
+    index_id TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    day TEXT NOT NULL,  -- YYYY-MM-DD, UTC
+    hits INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (index_id, directory, day),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+
+CREATE TABLE directory_depths (
+    index_id TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    depth TEXT NOT NULL CHECK (depth IN ('deep', 'standard', 'shallow')),
+    hits INTEGER NOT NULL DEFAULT 0,
+    assigned_at DATETIME NOT NULL,
+    PRIMARY KEY (index_id, directory),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V16: Reference counts and call degree per symbol
+///
+/// Derived from symbol_relationships when an index is built; re-inserted
+/// rows start at zero until the next refresh.
+const MIGRATION_V16: &str = r#"
+ALTER TABLE code_elements ADD COLUMN reference_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE code_elements ADD COLUMN caller_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE code_elements ADD COLUMN callee_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_code_elements_popularity ON code_elements(index_id, reference_count DESC);
+"#;
+
+/// Migration V17: Build configurations and the symbols each one compiles out
+///
+/// Exclusions rather than memberships are stored: most symbols are outside
+/// any `#if` and belong to every configuration.
+const MIGRATION_V17: &str = r#"
+CREATE TABLE build_configurations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    index_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    defines TEXT NOT NULL,
+    undefines TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE,
+    UNIQUE(index_id, name)
+);
+
+CREATE TABLE configuration_excluded_symbols (
+    configuration_id INTEGER NOT NULL,
+    symbol_id INTEGER NOT NULL,
+    PRIMARY KEY (configuration_id, symbol_id),
+    FOREIGN KEY (configuration_id) REFERENCES build_configurations(id) ON DELETE CASCADE,
+    FOREIGN KEY (symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_configuration_excluded_symbols_symbol ON configuration_excluded_symbols(symbol_id);
+"#;
+
+/// Migration V18: Compressed symbol bodies
+///
+/// Kept out of code_elements so scans over symbols never touch the blobs;
+/// only stored for indices built with bodies enabled.
+const MIGRATION_V18: &str = r#"
+CREATE TABLE symbol_bodies (
+    symbol_id INTEGER PRIMARY KEY,
+    body BLOB NOT NULL,
+    uncompressed_size INTEGER NOT NULL,
+    FOREIGN KEY (symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V19: Include edges between files
+///
+/// Includes are stored as written and resolved to indexed files once a whole
+/// index is stored, so a header indexed after its includer still resolves.
+const MIGRATION_V19: &str = r#"
+CREATE TABLE file_includes (
+    index_id TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    include_path TEXT NOT NULL,
+    resolved_path TEXT,  -- NULL for system headers and files outside the index
+    PRIMARY KEY (index_id, file_path, include_path),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_file_includes_resolved ON file_includes(index_id, resolved_path);
+"#;
+
+/// Migration V20: Oldest schema version that can read the database
+///
+/// One row, rewritten with `MIN_READER_VERSION` whenever a build migrates
+/// the database, so older builds can tell whether they may read it.
+const MIGRATION_V20: &str = r#"
+CREATE TABLE schema_compatibility (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    min_reader_version INTEGER NOT NULL
+);
+"#;
+
+/// Migration V21: Qualified names and namespace paths of symbols
+///
+/// Both are derived from the scope and name when an element is written, so
+/// qualified searches compare columns instead of concatenating per row.
+/// `namespace_path` is the scope, or '' at global scope, so a query anchored
+/// at the global scope can match it too.
+const MIGRATION_V21: &str = r#"
+ALTER TABLE code_elements ADD COLUMN fully_qualified_name TEXT NOT NULL DEFAULT '';
+ALTER TABLE code_elements ADD COLUMN namespace_path TEXT NOT NULL DEFAULT '';
+
+UPDATE code_elements SET
+    namespace_path = COALESCE(scope, ''),
+    fully_qualified_name = CASE WHEN COALESCE(scope, '') = '' THEN symbol_name ELSE scope || '::' || symbol_name END;
+
+CREATE INDEX idx_code_elements_qualified_name ON code_elements(index_id, fully_qualified_name);
+"#;
+
+/// Migration V22: Trigram index over symbol names for fuzzy search
+///
+/// Kept apart from code_elements_fts, whose word tokens can't find a name
+/// from a misspelling; like it, it stores no content and follows
+/// code_elements through triggers.
+const MIGRATION_V22: &str = r#"
+CREATE VIRTUAL TABLE code_elements_trigrams USING fts5(
+    symbol_name,
+    content = 'code_elements',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+
+CREATE TRIGGER code_elements_trigrams_insert AFTER INSERT ON code_elements
+BEGIN
+    INSERT INTO code_elements_trigrams (rowid, symbol_name) VALUES (new.id, new.symbol_name);
+END;
+
+CREATE TRIGGER code_elements_trigrams_delete AFTER DELETE ON code_elements
+BEGIN
+    INSERT INTO code_elements_trigrams (code_elements_trigrams, rowid, symbol_name) VALUES ('delete', old.id, old.symbol_name);
+END;
+
+CREATE TRIGGER code_elements_trigrams_update AFTER UPDATE OF symbol_name ON code_elements
+BEGIN
+    INSERT INTO code_elements_trigrams (code_elements_trigrams, rowid, symbol_name) VALUES ('delete', old.id, old.symbol_name);
+    INSERT INTO code_elements_trigrams (rowid, symbol_name) VALUES (new.id, new.symbol_name);
+END;
+
+INSERT INTO code_elements_trigrams (code_elements_trigrams) VALUES ('rebuild');
+"#;
+
+/// Migration V23: Branch revisions of an index and stashed file versions
+///
+/// A revision's files are stored as its difference from its parent, with a
+/// NULL hash for a file the parent has and the revision doesn't. Stashed
+/// files are keyed by content hash, so every revision with the same version
+/// of a file shares one copy of its symbols.
+const MIGRATION_V23: &str = r#"
+CREATE TABLE index_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    index_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    commit_id TEXT,
+    parent_id INTEGER,
+    is_current BOOLEAN NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL,
+    switched_at DATETIME NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE,
+    FOREIGN KEY (parent_id) REFERENCES index_revisions(id),
+    UNIQUE(index_id, name)
+);
+
+CREATE UNIQUE INDEX idx_index_revisions_current ON index_revisions(index_id) WHERE is_current;
+
+CREATE TABLE revision_files (
+    revision_id INTEGER NOT NULL,
+    file_path TEXT NOT NULL,
+    file_hash TEXT,
+    PRIMARY KEY (revision_id, file_path),
+    FOREIGN KEY (revision_id) REFERENCES index_revisions(id) ON DELETE CASCADE
+);
+
+CREATE TABLE stashed_files (
+    index_id TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    file_hash TEXT NOT NULL,
+    contents BLOB NOT NULL,  -- zstd-compressed JSON of a StashedFile
+    stashed_at DATETIME NOT NULL,
+    PRIMARY KEY (index_id, file_path, file_hash),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V24: Include/exclude rules of the directory walk per index
+///
+/// Patterns are stored as JSON arrays of globs.
+const MIGRATION_V24: &str = r#"
+CREATE TABLE index_walk_rules (
+    index_id TEXT PRIMARY KEY,
+    include_patterns TEXT NOT NULL DEFAULT '[]',
+    exclude_patterns TEXT NOT NULL DEFAULT '[]',
+    respect_ignore_files BOOLEAN NOT NULL DEFAULT 1,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V25: The compilation database and inferred include flags of each index
+///
+/// The defines and preset are stored as the index's default build
+/// configuration and preset tag; these are what's left to parse its files
+/// again the way `index create` did. Include flags are a JSON array.
+const MIGRATION_V25: &str = r#"
+CREATE TABLE index_parse_settings (
+    index_id TEXT PRIMARY KEY,
+    compile_commands TEXT,
+    include_flags TEXT NOT NULL DEFAULT '[]',
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V26: Unified Symbol Resolution ids of symbols
+///
+/// libclang gives every declaration and the definition of a symbol the same
+/// USR, and distinct ones to overloads like `mix(int)` and `mix(double)`.
+/// NULL for symbols parsed without libclang.
+const MIGRATION_V26: &str = r#"
+ALTER TABLE code_elements ADD COLUMN usr TEXT;
+
+CREATE INDEX idx_code_elements_usr ON code_elements(index_id, usr) WHERE usr IS NOT NULL;
+"#;
+
+/// Migration V27: The error of each file the last indexing run couldn't index
+const MIGRATION_V27: &str = r#"
+CREATE TABLE index_errors (
+    index_id TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('read', 'parse', 'timeout', 'panic', 'crash')),
+    message TEXT NOT NULL,
+    recorded_at DATETIME NOT NULL,
+    PRIMARY KEY (index_id, file_path),
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+"#;
+
+/// Migration V28: Allow field, enum constant, lambda and unknown symbols
+///
+/// Rebuilds code_elements like V5 rebuilt the relationships, keeping ids so
+/// the rows referencing them stay valid. Foreign keys are off for the
+/// rebuild, as dropping the old table would otherwise cascade into them;
+/// the triggers and view naming the table are recreated around it. Older
+/// binaries can't read the new types, hence `MIN_READER_VERSION`.
+const MIGRATION_V28: &str = r#"
+DROP VIEW symbol_details_view;
+
+CREATE TABLE code_elements_v28 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    index_id TEXT NOT NULL,
+    symbol_name TEXT NOT NULL,
+    symbol_type TEXT NOT NULL CHECK (symbol_type IN ('function', 'class', 'struct', 'variable', 'macro', 'namespace', 'enum', 'typedef', 'union', 'template', 'constructor', 'destructor', 'operator', 'field', 'enum_constant', 'lambda', 'unknown')),
+    file_path TEXT NOT NULL,
+    line_number INTEGER NOT NULL,
+    column_number INTEGER NOT NULL,
+    definition_hash TEXT NOT NULL,
+    scope TEXT,
+    access_modifier TEXT CHECK (access_modifier IN ('public', 'private', 'protected')),
+    is_declaration BOOLEAN NOT NULL DEFAULT 0,
+    signature TEXT,
+    memory_section TEXT,
+    documentation TEXT,
+    reference_count INTEGER NOT NULL DEFAULT 0,
+    caller_count INTEGER NOT NULL DEFAULT 0,
+    callee_count INTEGER NOT NULL DEFAULT 0,
+    fully_qualified_name TEXT NOT NULL DEFAULT '',
+    namespace_path TEXT NOT NULL DEFAULT '',
+    usr TEXT,
+    FOREIGN KEY (index_id) REFERENCES code_indices(id) ON DELETE CASCADE
+);
+
+INSERT INTO code_elements_v28 (
+    id, index_id, symbol_name, symbol_type, file_path, line_number, column_number, definition_hash, scope,
+    access_modifier, is_declaration, signature, memory_section, documentation, reference_count, caller_count,
+    callee_count, fully_qualified_name, namespace_path, usr
+)
+SELECT
+    id, index_id, symbol_name, symbol_type, file_path, line_number, column_number, definition_hash, scope,
+    access_modifier, is_declaration, signature, memory_section, documentation, reference_count, caller_count,
+    callee_count, fully_qualified_name, namespace_path, usr
+FROM code_elements;
+
+DROP TABLE code_elements;
+ALTER TABLE code_elements_v28 RENAME TO code_elements;
+
+CREATE INDEX idx_code_elements_index_id ON code_elements(index_id);
+CREATE INDEX idx_code_elements_symbol_name ON code_elements(symbol_name);
+CREATE INDEX idx_code_elements_symbol_type ON code_elements(symbol_type);
+CREATE INDEX idx_code_elements_file_path ON code_elements(file_path);
+CREATE INDEX idx_code_elements_scope ON code_elements(scope);
+CREATE INDEX idx_code_elements_definition_hash ON code_elements(definition_hash);
+CREATE INDEX idx_code_elements_composite ON code_elements(index_id, symbol_name, symbol_type);
+CREATE UNIQUE INDEX idx_code_elements_identity
+ON code_elements(index_id, file_path, line_number, column_number, symbol_name, symbol_type);
+CREATE INDEX idx_code_elements_memory_section ON code_elements(index_id, memory_section)
+WHERE memory_section IS NOT NULL;
+CREATE INDEX idx_code_elements_popularity ON code_elements(index_id, reference_count DESC);
+CREATE INDEX idx_code_elements_qualified_name ON code_elements(index_id, fully_qualified_name);
+CREATE INDEX idx_code_elements_usr ON code_elements(index_id, usr) WHERE usr IS NOT NULL;
+
+CREATE TRIGGER code_elements_fts_insert AFTER INSERT ON code_elements
+BEGIN
+    INSERT INTO code_elements_fts (rowid, symbol_name, signature, scope, documentation)
+    VALUES (new.id, new.symbol_name, new.signature, new.scope, new.documentation);
+END;
+
+CREATE TRIGGER code_elements_fts_delete AFTER DELETE ON code_elements
+BEGIN
+    INSERT INTO code_elements_fts (code_elements_fts, rowid, symbol_name, signature, scope, documentation)
+    VALUES ('delete', old.id, old.symbol_name, old.signature, old.scope, old.documentation);
+END;
+
+CREATE TRIGGER code_elements_fts_update AFTER UPDATE OF symbol_name, signature, scope, documentation ON code_elements
+BEGIN
+    INSERT INTO code_elements_fts (code_elements_fts, rowid, symbol_name, signature, scope, documentation)
+    VALUES ('delete', old.id, old.symbol_name, old.signature, old.scope, old.documentation);
+    INSERT INTO code_elements_fts (rowid, symbol_name, signature, scope, documentation)
+    VALUES (new.id, new.symbol_name, new.signature, new.scope, new.documentation);
+END;
+
+CREATE TRIGGER code_elements_trigrams_insert AFTER INSERT ON code_elements
+BEGIN
+    INSERT INTO code_elements_trigrams (rowid, symbol_name) VALUES (new.id, new.symbol_name);
+END;
+
+CREATE TRIGGER code_elements_trigrams_delete AFTER DELETE ON code_elements
+BEGIN
+    INSERT INTO code_elements_trigrams (code_elements_trigrams, rowid, symbol_name) VALUES ('delete', old.id, old.symbol_name);
+END;
+
+CREATE TRIGGER code_elements_trigrams_update AFTER UPDATE OF symbol_name ON code_elements
+BEGIN
+    INSERT INTO code_elements_trigrams (code_elements_trigrams, rowid, symbol_name) VALUES ('delete', old.id, old.symbol_name);
+    INSERT INTO code_elements_trigrams (rowid, symbol_name) VALUES (new.id, new.symbol_name);
+END;
+
+CREATE VIEW symbol_details_view AS
+SELECT 
+    ce.id,
+    ce.symbol_name,
+    ce.symbol_type,
+    ce.file_path,
+    ce.line_number,
+    ce.column_number,
+    ce.scope,
+    ce.access_modifier,
+    ce.is_declaration,
+    ce.signature,
+    ci.name as index_name,
+    fm.last_modified as file_last_modified
+FROM code_elements ce
+JOIN code_indices ci ON ce.index_id = ci.id
+LEFT JOIN file_metadata fm ON ce.index_id = fm.index_id AND ce.file_path = fm.file_path
+WHERE ci.state = 'active';
+"#;
+
+/// Migration V29: The whole of each signature code_elements keeps only the start of, compressed
+const MIGRATION_V29: &str = r#"
+CREATE TABLE symbol_signatures (
+    symbol_id INTEGER PRIMARY KEY,
+    signature BLOB NOT NULL,
+    FOREIGN KEY (symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE
+);
+"#;
+
+/// Undoes V5: rebuilds relationships with the original type list, dropping callback references
+const DOWNGRADE_V5: &str = r#"
+CREATE TABLE symbol_relationships_v4 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_symbol_id INTEGER NOT NULL,
+    to_symbol_id INTEGER NOT NULL,
+    relationship_type TEXT NOT NULL CHECK (relationship_type IN ('inherits', 'uses', 'includes', 'calls', 'defines', 'instantiates', 'contained_in', 'friend', 'overrides', 'specializes')),
+    file_path TEXT NOT NULL,
+    line_number INTEGER NOT NULL,
+    FOREIGN KEY (from_symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE,
+    FOREIGN KEY (to_symbol_id) REFERENCES code_elements(id) ON DELETE CASCADE,
+    UNIQUE(from_symbol_id, to_symbol_id, relationship_type, line_number)
+);
+
+INSERT INTO symbol_relationships_v4 (id, from_symbol_id, to_symbol_id, relationship_type, file_path, line_number)
+SELECT id, from_symbol_id, to_symbol_id, relationship_type, file_path, line_number FROM symbol_relationships
+WHERE relationship_type <> 'referenced_as_callback';
+
+DROP TABLE symbol_relationships;
+ALTER TABLE symbol_relationships_v4 RENAME TO symbol_relationships;
+
+CREATE INDEX idx_symbol_relationships_from ON symbol_relationships(from_symbol_id);
+CREATE INDEX idx_symbol_relationships_to ON symbol_relationships(to_symbol_id);
+CREATE INDEX idx_symbol_relationships_type ON symbol_relationships(relationship_type);
+CREATE INDEX idx_symbol_relationships_file_path ON symbol_relationships(file_path);
+"#;
+
+/// Undoes V13: the triggers go first, since SQLite won't drop a column a trigger names
+const DOWNGRADE_V13: &str = r#"
+DROP TRIGGER code_elements_fts_insert;
+DROP TRIGGER code_elements_fts_delete;
+DROP TRIGGER code_elements_fts_update;
+DROP TABLE code_elements_fts;
+ALTER TABLE code_elements DROP COLUMN documentation;
+"#;
+
+/// Undoes V16
+const DOWNGRADE_V16: &str = r#"
+DROP INDEX idx_code_elements_popularity;
+ALTER TABLE code_elements DROP COLUMN reference_count;
+ALTER TABLE code_elements DROP COLUMN caller_count;
+ALTER TABLE code_elements DROP COLUMN callee_count;
+"#;
+
+/// Undoes V22
+const DOWNGRADE_V22: &str = r#"
+DROP TRIGGER code_elements_trigrams_insert;
+DROP TRIGGER code_elements_trigrams_delete;
+DROP TRIGGER code_elements_trigrams_update;
+DROP TABLE code_elements_trigrams;
+"#;
+
+/// Undoes V28 by deleting the symbols of the new types
+///
+/// Downgrades run in a transaction, where foreign keys can't be turned off
+/// for a rebuild, so the wider CHECK stays; older binaries never write the
+/// new types.
+const DOWNGRADE_V28: &str = r#"
+DELETE FROM code_elements WHERE symbol_type IN ('field', 'enum_constant', 'lambda', 'unknown');
+"#;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::tempdir;
-    use std::path::Path;
 
     fn create_test_db() -> Result<Connection> {
-        let dir = tempdir().unwrap();
-        let db_path = dir.path().join("test.db");
-        Connection::open(db_path)
+        Connection::open_in_memory()
     }
 
     #[test]
@@ -320,7 +1270,7 @@ mod tests {
     }
 
     #[test]
-    fn test_tables_created() {
+    fn test_tables_created() -> Result<()> {
         let conn = create_test_db().unwrap();
         let mut migrator = SchemaMigrator::new(conn);
         migrator.migrate().unwrap();
@@ -334,12 +1284,28 @@ mod tests {
             .collect::<Result<Vec<_>, _>>()?;
         
         let expected_tables = vec![
+            "admin_audit",
+            "build_configurations",
             "code_elements",
+            "code_elements_fts",
             "code_indices", 
+            "configuration_excluded_symbols",
+            "directory_depths",
+            "directory_query_hits",
+            "file_includes",
             "file_metadata",
+            "index_tags",
             "mcp_query_sessions",
+            "path_aliases",
+            "saved_queries",
+            "schema_compatibility",
             "schema_migrations",
+            "slow_queries",
+            "symbol_annotations",
+            "symbol_bodies",
             "symbol_relationships",
+            "symbol_signatures",
+            "symbol_tags",
         ];
         
         for expected_table in expected_tables {
@@ -350,7 +1316,7 @@ mod tests {
     }
 
     #[test]
-    fn test_indices_created() {
+    fn test_indices_created() -> Result<()> {
         let conn = create_test_db().unwrap();
         let mut migrator = SchemaMigrator::new(conn);
         migrator.migrate().unwrap();
@@ -373,7 +1339,7 @@ mod tests {
     }
 
     #[test]
-    fn test_views_created() {
+    fn test_views_created() -> Result<()> {
         let conn = create_test_db().unwrap();
         let mut migrator = SchemaMigrator::new(conn);
         migrator.migrate().unwrap();
@@ -393,7 +1359,7 @@ mod tests {
     }
 
     #[test]
-    fn test_foreign_keys_enabled() {
+    fn test_foreign_keys_enabled() -> Result<()> {
         let conn = create_test_db().unwrap();
         let mut migrator = SchemaMigrator::new(conn);
         migrator.migrate().unwrap();
@@ -407,6 +1373,79 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_callback_migration_preserves_relationships() -> Result<()> {
+        let conn = create_test_db()?;
+        let migrator = SchemaMigrator::new(conn);
+        let migrations = migrator.get_migrations();
+        for version in 1..=4 {
+            migrator.connection().execute_batch(migrations[&version])?;
+        }
+
+        let conn = migrator.connection();
+        conn.execute_batch(
+            r#"
+            INSERT INTO code_indices (id, name, base_path, created_at, updated_at, index_version, state)
+            VALUES ('i', 'test', '/test', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z', 1, 'active');
+            INSERT INTO code_elements (id, index_id, symbol_name, symbol_type, file_path, line_number, column_number, definition_hash)
+            VALUES (1, 'i', 'main', 'function', 'main.cpp', 1, 1, 'h'), (2, 'i', 'on_tick', 'function', 'main.cpp', 9, 1, 'h');
+            INSERT INTO symbol_relationships (from_symbol_id, to_symbol_id, relationship_type, file_path, line_number)
+            VALUES (1, 2, 'calls', 'main.cpp', 3);
+            "#,
+        )?;
+        assert!(conn
+            .execute("INSERT INTO symbol_relationships (from_symbol_id, to_symbol_id, relationship_type, file_path, line_number) VALUES (1, 2, 'referenced_as_callback', 'main.cpp', 4)", [])
+            .is_err());
+
+        conn.execute_batch(MIGRATION_V5)?;
+        let kept: String = conn.query_row("SELECT relationship_type FROM symbol_relationships WHERE id = 1", [], |row| row.get(0))?;
+        assert_eq!(kept, "calls");
+        conn.execute("INSERT INTO symbol_relationships (from_symbol_id, to_symbol_id, relationship_type, file_path, line_number) VALUES (1, 2, 'referenced_as_callback', 'main.cpp', 4)", [])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symbol_type_migration_keeps_references() -> Result<()> {
+        let mut migrator = SchemaMigrator::new(create_test_db()?);
+        migrator.migrate_to(27)?;
+        let conn = migrator.connection();
+        conn.execute_batch(
+            r#"
+            INSERT INTO code_indices (id, name, base_path, created_at, updated_at, index_version, state)
+            VALUES ('i', 'test', '/test', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z', 1, 'active');
+            INSERT INTO code_elements (id, index_id, symbol_name, symbol_type, file_path, line_number, column_number, definition_hash, usr)
+            VALUES (1, 'i', 'main', 'function', 'main.cpp', 1, 1, 'h', 'c:@F@main'), (2, 'i', 'on_tick', 'function', 'main.cpp', 9, 1, 'h', NULL);
+            INSERT INTO symbol_relationships (from_symbol_id, to_symbol_id, relationship_type, file_path, line_number)
+            VALUES (1, 2, 'calls', 'main.cpp', 3);
+            INSERT INTO symbol_tags (symbol_id, tag, created_at) VALUES (2, 'hot', '2024-01-01T00:00:00Z');
+            "#,
+        )?;
+        let insert_lambda = "INSERT INTO code_elements (index_id, symbol_name, symbol_type, file_path, line_number, column_number, definition_hash) \
+                             VALUES ('i', '(lambda at main.cpp:4:15)', 'lambda', 'main.cpp', 4, 15, 'h')";
+        assert!(conn.execute(insert_lambda, []).is_err());
+
+        migrator.migrate()?;
+        let conn = migrator.connection();
+        let kept: (i64, i64, String) = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM symbol_relationships), (SELECT COUNT(*) FROM symbol_tags), usr FROM code_elements WHERE id = 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!(kept, (1, 1, "c:@F@main".to_string()));
+        conn.execute(insert_lambda, [])?;
+        let found: i64 = conn.query_row("SELECT COUNT(*) FROM code_elements_fts WHERE code_elements_fts MATCH 'lambda'", [], |row| row.get(0))?;
+        assert_eq!(found, 1);
+        let foreign_keys: i32 = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        assert_eq!(foreign_keys, 1);
+
+        migrator.downgrade(27)?;
+        let remaining: i64 = migrator.connection().query_row("SELECT COUNT(*) FROM code_elements", [], |row| row.get(0))?;
+        assert_eq!(remaining, 2);
+
+        Ok(())
+    }
+
     #[test]
     fn test_migration_idempotent() {
         let conn = create_test_db().unwrap();
@@ -419,4 +1458,59 @@ mod tests {
         // Version should still be current
         assert_eq!(migrator.get_current_version().unwrap(), CURRENT_SCHEMA_VERSION);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_compatibility_and_downgrade() -> Result<()> {
+        let mut migrator = SchemaMigrator::new(create_test_db()?);
+        migrator.migrate()?;
+        assert_eq!(migrator.compatibility()?, SchemaCompatibility::Current);
+
+        let conn = migrator.connection();
+        conn.execute("INSERT INTO schema_migrations (version) VALUES (?1)", [CURRENT_SCHEMA_VERSION + 1])?;
+        assert_eq!(migrator.compatibility()?, SchemaCompatibility::ReadOnly { found: CURRENT_SCHEMA_VERSION + 1 });
+        conn.execute("UPDATE schema_compatibility SET min_reader_version = ?1", [CURRENT_SCHEMA_VERSION + 1])?;
+        assert_eq!(migrator.compatibility()?, SchemaCompatibility::Incompatible { found: CURRENT_SCHEMA_VERSION + 1 });
+        conn.execute("DELETE FROM schema_migrations WHERE version > ?1", [CURRENT_SCHEMA_VERSION])?;
+
+        // Every migration can be undone, and redone on the downgraded database
+        migrator.downgrade(1)?;
+        assert_eq!(migrator.get_current_version()?, 1);
+        let tables: i64 = migrator.connection().query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('index_tags', 'file_includes', 'code_elements_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(tables, 0);
+        migrator.migrate()?;
+        assert_eq!(migrator.get_current_version()?, CURRENT_SCHEMA_VERSION);
+        assert_eq!(migrator.compatibility()?, SchemaCompatibility::Current);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_to() -> Result<()> {
+        let mut migrator = SchemaMigrator::new(create_test_db()?);
+        assert!(migrator.missing_steps(CURRENT_SCHEMA_VERSION)?.is_empty());
+        let has_table = |migrator: &SchemaMigrator, name: &str| -> Result<bool> {
+            migrator.connection().query_row("SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = ?1", [name], |row| row.get(0))
+        };
+
+        migrator.migrate_to(12)?;
+        assert_eq!(migrator.get_current_version()?, 12);
+        assert!(has_table(&migrator, "admin_audit")? && !has_table(&migrator, "code_elements_fts")?);
+        assert!(!has_table(&migrator, "schema_compatibility")?);
+
+        migrator.migrate_to(20)?;
+        let min_reader: i32 = migrator.connection().query_row("SELECT min_reader_version FROM schema_compatibility", [], |row| row.get(0))?;
+        assert_eq!(min_reader, MIN_READER_VERSION);
+
+        migrator.migrate_to(CURRENT_SCHEMA_VERSION)?;
+        assert!(migrator.missing_steps(1)?.is_empty());
+        migrator.migrate_to(18)?;
+        assert_eq!(migrator.get_current_version()?, 18);
+        assert!(has_table(&migrator, "symbol_bodies")? && !has_table(&migrator, "file_includes")?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use serde::Serialize;
+
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::symbol_popularity::SymbolPopularity;
+use crate::lib::storage::query::{full_text_query, MatchMode, TextColumn};
+use crate::lib::storage::repository::TextSearchHit;
+
+/// FTS5 operators that are not search terms when written in capitals
+const OPERATORS: &[&str] = &["AND", "OR", "NOT", "NEAR"];
+
+/// A word or phrase of a full-text query, as the tokenizer sees it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryTerm {
+    /// Term as written in the query, without quotes
+    pub text: String,
+    /// Lowercased tokens; a phrase has several
+    pub tokens: Vec<String>,
+    /// The last token also matches longer tokens
+    pub prefix: bool,
+}
+
+/// How a term matched a field
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchType {
+    /// The whole field is the term
+    Exact,
+    /// The term appears as whole tokens
+    Token,
+    /// The term is the start of a longer token
+    Prefix,
+    /// The name contains the pattern
+    Substring,
+    /// The name matches the wildcards of the pattern
+    Glob,
+    /// The regular expression matches the name
+    Regex,
+    /// The name is close to the pattern
+    Fuzzy,
+}
+
+/// One term found in one field of a hit
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FieldMatch {
+    pub field: &'static str,
+    pub term: String,
+    pub match_type: MatchType,
+    pub occurrences: usize,
+    /// Ranking weight of the field; None where fields aren't weighted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+}
+
+/// One part of a hit's score
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ScoreComponent {
+    pub name: String,
+    pub value: f64,
+    pub detail: String,
+}
+
+/// Why a search hit matched and how it was scored
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HitExplanation {
+    /// Matches ordered by field weight, then term
+    pub matches: Vec<FieldMatch>,
+    /// What hits are ranked by; None when they are ranked by name alone
+    pub score: Option<f64>,
+    /// The parts of the ranking, first the one that decides most
+    pub components: Vec<ScoreComponent>,
+}
+
+/// Terms of a search as sent to `full_text_search`
+///
+/// Free text is turned into its MATCH expression first so both paths see
+/// the same terms. Raw expressions are read best-effort: operators, column
+/// filters and NEAR distances are skipped.
+pub fn query_terms(text: &str, raw: bool) -> Vec<QueryTerm> {
+    let expression = if raw {
+        text.to_string()
+    } else {
+        full_text_query(text, &[]).unwrap_or_default()
+    };
+
+    let mut terms = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        let (word, quoted) = match c {
+            '"' => {
+                let mut phrase = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        // A doubled quote is a literal quote inside the phrase
+                        if chars.next_if_eq(&'"').is_none() {
+                            break;
+                        }
+                    }
+                    phrase.push(c);
+                }
+                (phrase, true)
+            }
+            '{' => {
+                chars.by_ref().find(|c| *c == '}');
+                continue;
+            }
+            ',' => {
+                // NEAR(a b, 5)
+                while chars.peek().is_some_and(|c| c.is_whitespace() || c.is_ascii_digit()) {
+                    chars.next();
+                }
+                continue;
+            }
+            c if is_bareword_char(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek().copied().filter(|c| is_bareword_char(*c)) {
+                    word.push(c);
+                    chars.next();
+                }
+                (word, false)
+            }
+            _ => continue,
+        };
+
+        while chars.peek().is_some_and(|c| *c == ' ') {
+            chars.next();
+        }
+        let prefix = chars.peek() == Some(&'*');
+        if !quoted && (chars.peek() == Some(&':') || OPERATORS.contains(&word.as_str())) {
+            continue;
+        }
+        let tokens = tokenize(&word);
+        if !tokens.is_empty() {
+            terms.push(QueryTerm { text: word, tokens, prefix });
+        }
+    }
+    terms
+}
+
+/// Explains a hit of a search for `terms` over `columns` (empty means all)
+///
+/// Hits are ranked by BM25; equally relevant ones go to the more
+/// referenced symbol, so its `popularity` is a component too.
+pub fn explain_hit(hit: &TextSearchHit, terms: &[QueryTerm], columns: &[TextColumn], popularity: &SymbolPopularity) -> HitExplanation {
+    let columns = if columns.is_empty() { TextColumn::ALL } else { columns };
+    let mut matches = Vec::new();
+    for column in columns {
+        let Some(value) = column_value(&hit.element, *column) else {
+            continue;
+        };
+        let tokens = tokenize(value);
+        for term in terms {
+            if let Some((match_type, occurrences)) = match_term(&tokens, term) {
+                matches.push(FieldMatch {
+                    field: column.name(),
+                    term: term.text.clone(),
+                    match_type,
+                    occurrences,
+                    weight: Some(column.weight()),
+                });
+            }
+        }
+    }
+    matches.sort_by(|a, b| b.weight.unwrap_or_default().total_cmp(&a.weight.unwrap_or_default()).then_with(|| a.term.cmp(&b.term)));
+
+    let weights: Vec<String> = columns.iter().map(|column| format!("{}={}", column.name(), column.weight())).collect();
+    HitExplanation {
+        matches,
+        score: Some(hit.score),
+        components: vec![
+            ScoreComponent {
+                name: "bm25".to_string(),
+                value: hit.score,
+                detail: format!("BM25 relevance with field weights {}", weights.join(", ")),
+            },
+            popularity_component(popularity, "breaks ties between equally relevant hits"),
+        ],
+    }
+}
+
+/// Explains a hit of a symbol name search for `pattern`
+///
+/// `fuzzy_score` is the hit's closeness when `mode` is fuzzy, which then
+/// ranks the hits alone; otherwise they are ranked by popularity if
+/// `by_popularity`, then by name.
+pub fn explain_symbol(
+    element: &CodeElement,
+    pattern: &str,
+    mode: MatchMode,
+    qualified: bool,
+    fuzzy_score: Option<f64>,
+    popularity: &SymbolPopularity,
+    by_popularity: bool,
+) -> HitExplanation {
+    let match_type = match mode {
+        MatchMode::Exact => MatchType::Exact,
+        MatchMode::Substring => MatchType::Substring,
+        MatchMode::Prefix => MatchType::Prefix,
+        MatchMode::Glob => MatchType::Glob,
+        MatchMode::Regex => MatchType::Regex,
+        MatchMode::Fuzzy => MatchType::Fuzzy,
+    };
+    let matches = vec![FieldMatch {
+        field: if qualified { "qualified_name" } else { "name" },
+        term: pattern.to_string(),
+        match_type,
+        occurrences: 1,
+        weight: None,
+    }];
+    let name_order = ScoreComponent {
+        name: "name".to_string(),
+        value: 0.0,
+        detail: format!("ranked by name '{}', then file and line", element.symbol_name),
+    };
+    let (score, components) = match (fuzzy_score, by_popularity) {
+        (Some(score), _) => (
+            Some(score),
+            vec![ScoreComponent {
+                name: "fuzzy".to_string(),
+                value: score,
+                detail: format!("closeness of '{}' to '{}'", element.symbol_name, pattern),
+            }],
+        ),
+        (None, true) => (
+            Some(popularity.reference_count as f64),
+            vec![popularity_component(popularity, "ranks the hits, most referenced first"), name_order],
+        ),
+        (None, false) => (None, vec![name_order]),
+    };
+    HitExplanation { matches, score, components }
+}
+
+/// Explains a hit of an embedding search, ranked by `similarity` alone
+pub fn explain_similarity(similarity: f64, backend: &str) -> HitExplanation {
+    HitExplanation {
+        matches: Vec::new(),
+        score: Some(similarity),
+        components: vec![ScoreComponent {
+            name: "similarity".to_string(),
+            value: similarity,
+            detail: format!("cosine similarity of the query and symbol embeddings from {}", backend),
+        }],
+    }
+}
+
+fn popularity_component(popularity: &SymbolPopularity, role: &str) -> ScoreComponent {
+    ScoreComponent {
+        name: "popularity".to_string(),
+        value: popularity.reference_count as f64,
+        detail: format!("{}; {}", popularity.summary(), role),
+    }
+}
+
+fn column_value(element: &CodeElement, column: TextColumn) -> Option<&str> {
+    match column {
+        TextColumn::Name => Some(element.symbol_name.as_str()),
+        TextColumn::Signature => element.signature.as_deref(),
+        TextColumn::Scope => element.scope.as_deref(),
+        TextColumn::Documentation => element.documentation.as_deref(),
+    }
+}
+
+/// Best match of a term in a field's tokens and how often it occurs
+fn match_term(tokens: &[String], term: &QueryTerm) -> Option<(MatchType, usize)> {
+    let (last, rest) = term.tokens.split_last()?;
+    let mut best = None;
+    let mut occurrences = 0;
+    for window in tokens.windows(term.tokens.len()) {
+        let (candidate, leading) = window.split_last()?;
+        if leading != rest {
+            continue;
+        }
+        let match_type = if candidate == last {
+            MatchType::Token
+        } else if term.prefix && candidate.starts_with(last.as_str()) {
+            MatchType::Prefix
+        } else {
+            continue;
+        };
+        occurrences += 1;
+        best = Some(best.map_or(match_type, |best: MatchType| best.min(match_type)));
+    }
+    let match_type = if tokens == term.tokens.as_slice() { MatchType::Exact } else { best? };
+    Some((match_type, occurrences))
+}
+
+/// Splits text like the index's unicode61 tokenizer: alphanumeric runs, lowercased
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn is_bareword_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '"' | '(' | ')' | '{' | '}' | ':' | '*' | '^' | '+' | ',')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::SymbolType;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_query_terms_and_explanations() {
+        let terms = query_terms("ring buf", false);
+        assert_eq!(terms.iter().map(|t| (t.text.as_str(), t.prefix)).collect::<Vec<_>>(), [("ring", false), ("buf", true)]);
+
+        let raw = query_terms("{symbol_name scope} : (\"ring buffer\" OR drain*) NOT documentation:legacy NEAR(a b, 5)", true);
+        assert_eq!(
+            raw.iter().map(|t| (t.text.as_str(), t.prefix)).collect::<Vec<_>>(),
+            [("ring buffer", false), ("drain", true), ("legacy", false), ("a", false), ("b", false)]
+        );
+        assert_eq!(raw[0].tokens, ["ring", "buffer"]);
+        assert_eq!(query_terms("\"say \"\"hi\"\"\"", true)[0].text, "say \"hi\"");
+
+        let element = CodeElement::new(Uuid::new_v4(), "ring_buffer".to_string(), SymbolType::Class, "src/ring.h".to_string(), 1, 1, "a".repeat(64))
+            .with_scope("audio::ring".to_string())
+            .with_documentation("A ring buffer. Drains the ring on overflow".to_string());
+        let hit = TextSearchHit { element, score: 3.5, snippet: String::new() };
+
+        let popularity = SymbolPopularity { reference_count: 7, caller_count: 3, callee_count: 0 };
+        let explanation = explain_hit(&hit, &query_terms("ring buf", false), &[], &popularity);
+        let matches: Vec<(&str, &str, MatchType, usize)> =
+            explanation.matches.iter().map(|m| (m.field, m.term.as_str(), m.match_type, m.occurrences)).collect();
+        assert_eq!(
+            matches,
+            [
+                ("name", "buf", MatchType::Prefix, 1),
+                ("name", "ring", MatchType::Token, 1),
+                ("scope", "ring", MatchType::Token, 1),
+                ("documentation", "buf", MatchType::Prefix, 1),
+                ("documentation", "ring", MatchType::Token, 2),
+            ]
+        );
+        assert_eq!(explanation.components[0].value, 3.5);
+        assert_eq!((explanation.components[1].name.as_str(), explanation.components[1].value), ("popularity", 7.0));
+
+        let exact = explain_hit(&hit, &query_terms("\"ring buffer\"", true), &[TextColumn::Name], &SymbolPopularity::default());
+        assert_eq!(exact.matches.len(), 1);
+        assert_eq!(exact.matches[0].match_type, MatchType::Exact);
+    }
+
+    #[test]
+    fn test_symbol_explanations_name_the_ranking() {
+        let element = CodeElement::new(Uuid::new_v4(), "mix".to_string(), SymbolType::Function, "src/mixer.cpp".to_string(), 1, 1, "a".repeat(64));
+        let popularity = SymbolPopularity { reference_count: 12, caller_count: 4, callee_count: 1 };
+
+        let ranked = explain_symbol(&element, "mi", MatchMode::Prefix, false, None, &popularity, true);
+        assert_eq!((ranked.matches[0].field, ranked.matches[0].match_type), ("name", MatchType::Prefix));
+        assert_eq!(ranked.score, Some(12.0));
+        let names: Vec<&str> = ranked.components.iter().map(|component| component.name.as_str()).collect();
+        assert_eq!(names, ["popularity", "name"]);
+        assert!(ranked.components[0].detail.starts_with("used 12 times by 4 callers"));
+
+        let by_name = explain_symbol(&element, "audio::mix", MatchMode::Exact, true, None, &popularity, false);
+        assert_eq!(by_name.matches[0].field, "qualified_name");
+        assert_eq!((by_name.score, by_name.components.len()), (None, 1));
+
+        let fuzzy = explain_symbol(&element, "mxi", MatchMode::Fuzzy, false, Some(0.8), &popularity, true);
+        assert_eq!((fuzzy.score, fuzzy.components[0].name.as_str()), (Some(0.8), "fuzzy"));
+    }
+}
@@ -0,0 +1,282 @@
+// Index snapshots
+//
+// A mass re-index with the wrong compile flags can replace a good index with
+// garbage. Taking a snapshot first makes that a cheap experiment: a snapshot
+// is a copy of the database written with SQLite's online backup API, which
+// stays consistent while other connections keep reading and writing, and
+// restoring copies it back over the database the same way. A snapshot is
+// taken for one index; since restoring rolls back the whole database, it is
+// refused while other indices have changed since, unless forced.
+
+use chrono::{DateTime, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+use crate::lib::storage::encryption::apply_key;
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::recovery::io_error;
+use crate::lib::storage::repository::Repository;
+use crate::lib::storage::schema::CURRENT_SCHEMA_VERSION;
+
+/// Pages copied per step of the backup API; other connections may write between steps
+const PAGES_PER_STEP: i32 = 1024;
+
+/// A checkpoint of the database, taken for one of its indices
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSnapshot {
+    /// Creation time as `20240131T120000.000Z`, which sorts chronologically
+    pub id: String,
+    pub index_name: String,
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub schema_version: i32,
+    pub total_files: u32,
+    pub total_symbols: u32,
+    /// Database copy; not part of the manifest
+    #[serde(skip)]
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub size_bytes: u64,
+}
+
+/// What restoring a snapshot did
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotRestore {
+    pub snapshot: IndexSnapshot,
+    /// Other indices that changed since the snapshot and were rolled back with it
+    pub rolled_back: Vec<String>,
+    /// Audit entries recorded since the snapshot, carried over to the restored database
+    pub audit_entries_kept: usize,
+}
+
+/// Snapshots of a database, kept in a directory beside it
+pub struct SnapshotStore {
+    config: DatabaseConfig,
+}
+
+impl SnapshotStore {
+    /// Creates a store for snapshots of the configured database
+    pub fn new(config: DatabaseConfig) -> Self {
+        Self { config }
+    }
+
+    /// Directory holding the snapshots of this database
+    pub fn snapshot_dir(&self) -> PathBuf {
+        let mut dir = self.config.database_path.clone().into_os_string();
+        dir.push(".snapshots");
+        PathBuf::from(dir)
+    }
+
+    /// Copies the database through `connection` into a new snapshot of the named index
+    pub fn create(&self, connection: &Connection, index_name: &str, label: Option<&str>) -> Result<IndexSnapshot> {
+        if self.config.is_in_memory() {
+            return Err(StorageError::Validation("Snapshots need a file database".to_string()));
+        }
+        let (total_files, total_symbols): (u32, u32) = connection
+            .query_row("SELECT total_files, total_symbols FROM code_indices WHERE name = ?1", [index_name], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => StorageError::not_found("Index", index_name),
+                e => e.into(),
+            })?;
+
+        let dir = self.snapshot_dir();
+        fs::create_dir_all(&dir).map_err(|e| io_error("Failed to create snapshot directory", e))?;
+        let created_at = Utc::now();
+        let id = created_at.format("%Y%m%dT%H%M%S%.3fZ").to_string();
+        let path = dir.join(format!("{}.db", id));
+
+        let mut target = Connection::open(&path)?;
+        if let Some(key) = &self.config.encryption_key {
+            apply_key(&target, key)?;
+        }
+        Backup::new(connection, &mut target)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)?;
+        // The copy keeps the database's WAL mode; a rollback journal leaves it a single file
+        target.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+        drop(target);
+
+        let snapshot = IndexSnapshot {
+            id,
+            index_name: index_name.to_string(),
+            label: label.map(str::to_string),
+            created_at,
+            schema_version: CURRENT_SCHEMA_VERSION,
+            total_files,
+            total_symbols,
+            size_bytes: file_size(&path),
+            path,
+        };
+        let manifest = serde_json::to_vec_pretty(&snapshot).map_err(|e| io_error("Failed to write snapshot manifest", e.into()))?;
+        fs::write(snapshot.path.with_extension("json"), manifest).map_err(|e| io_error("Failed to write snapshot manifest", e))?;
+        Ok(snapshot)
+    }
+
+    /// Snapshots oldest first, only those of `index_name` if given
+    ///
+    /// Manifests without their database copy are skipped.
+    pub fn list(&self, index_name: Option<&str>) -> Result<Vec<IndexSnapshot>> {
+        let entries = match fs::read_dir(self.snapshot_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error("Failed to read snapshot directory", e).into()),
+        };
+
+        let mut snapshots = Vec::new();
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let manifest = fs::read(&path).map_err(|e| io_error("Failed to read snapshot manifest", e))?;
+            let mut snapshot: IndexSnapshot = serde_json::from_slice(&manifest)
+                .map_err(|e| StorageError::Corruption(format!("Unreadable snapshot manifest {}: {}", path.display(), e)))?;
+            snapshot.path = path.with_extension("db");
+            if !snapshot.path.exists() || index_name.is_some_and(|name| name != snapshot.index_name) {
+                continue;
+            }
+            snapshot.size_bytes = file_size(&snapshot.path);
+            snapshots.push(snapshot);
+        }
+        snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(snapshots)
+    }
+
+    /// The snapshot of `index_name` with the given id, or its newest one
+    pub fn find(&self, index_name: &str, id: Option<&str>) -> Result<IndexSnapshot> {
+        let snapshots = self.list(Some(index_name))?;
+        let found = match id {
+            Some(id) => snapshots.into_iter().find(|snapshot| snapshot.id == id),
+            None => snapshots.into_iter().last(),
+        };
+        found.ok_or_else(|| match id {
+            Some(id) => StorageError::not_found("Snapshot", id),
+            None => StorageError::not_found("Snapshot of index", index_name),
+        })
+    }
+
+    /// Replaces the database with a snapshot
+    ///
+    /// Refused while indices other than the snapshot's have changed since
+    /// it was taken, unless `force` rolls them back too. The audit trail is
+    /// append-only, so entries recorded since the snapshot are written again
+    /// after the copy rather than lost with it.
+    pub fn restore(&self, snapshot: &IndexSnapshot, force: bool) -> Result<SnapshotRestore> {
+        let source = Connection::open_with_flags(&snapshot.path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        if let Some(key) = &self.config.encryption_key {
+            apply_key(&source, key)?;
+        }
+        let manager = DatabaseManager::new(self.config.clone())?;
+        let repository = Repository::new(manager.connect()?);
+
+        let rolled_back = changed_indices(repository.connection(), &source, &snapshot.index_name)?;
+        if !rolled_back.is_empty() && !force {
+            return Err(StorageError::Conflict(format!(
+                "Restoring snapshot {} would also roll back {}, changed since it was taken; use --force to restore anyway",
+                snapshot.id,
+                rolled_back.join(", ")
+            )));
+        }
+        let mut audit_entries = repository.list_audit_entries(None, None, Some(snapshot.created_at), i64::MAX as usize)?;
+
+        let mut connection = repository.into_connection();
+        Backup::new(&source, &mut connection)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)?;
+        drop(connection);
+
+        // Reopening brings a snapshot taken by an older release up to this schema
+        let repository = Repository::new(manager.connect()?);
+        audit_entries.reverse();
+        for entry in &audit_entries {
+            repository.record_audit(entry)?;
+        }
+
+        Ok(SnapshotRestore {
+            snapshot: snapshot.clone(),
+            rolled_back,
+            audit_entries_kept: audit_entries.len(),
+        })
+    }
+}
+
+/// Names of the indices other than `index_name` that differ between two databases
+fn changed_indices(current: &Connection, snapshot: &Connection, index_name: &str) -> Result<Vec<String>> {
+    let mut current = index_states(current)?;
+    let snapshot = index_states(snapshot)?;
+    for (name, state) in snapshot {
+        if current.get(&name) == Some(&state) {
+            current.remove(&name);
+        } else {
+            current.insert(name, state);
+        }
+    }
+    current.remove(index_name);
+    Ok(current.into_keys().collect())
+}
+
+/// Id, last update and totals of each index, by name
+fn index_states(connection: &Connection) -> Result<BTreeMap<String, (String, String, i64, i64)>> {
+    let mut stmt = connection.prepare("SELECT name, id, CAST(updated_at AS TEXT), total_files, total_symbols FROM code_indices")?;
+    let states = stmt
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(states)
+}
+
+fn file_size(path: &std::path::Path) -> u64 {
+    fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::admin_audit::{AuditActor, AuditEntry, AuditOperation};
+    use crate::lib::storage::models::code_index::CodeIndex;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::new(dir.path().join("index.db"));
+        let open = || Repository::new(DatabaseManager::new(config.clone()).unwrap().connect().unwrap());
+        let repository = open();
+        let mut audio = repository.create_code_index(CodeIndex::new("audio".to_string(), "/src/audio".to_string())).unwrap();
+        let mut video = repository.create_code_index(CodeIndex::new("video".to_string(), "/src/video".to_string())).unwrap();
+        audio.update_stats(10, 200);
+        repository.update_code_index(&audio).unwrap();
+
+        let store = SnapshotStore::new(config.clone());
+        let snapshot = store.create(repository.connection(), "audio", Some("before -DNDEBUG")).unwrap();
+        assert_eq!((snapshot.total_files, snapshot.total_symbols), (10, 200));
+        assert!(matches!(store.create(repository.connection(), "missing", None), Err(StorageError::NotFound(_))));
+
+        // The re-index goes wrong
+        audio.update_stats(10, 3);
+        repository.update_code_index(&audio).unwrap();
+        repository.record_audit(&AuditEntry::new(AuditOperation::UpdateIndex, &AuditActor::new("cli:dana"), json!({}))).unwrap();
+        drop(repository);
+
+        assert_eq!(store.list(Some("audio")).unwrap(), std::slice::from_ref(&snapshot));
+        assert!(store.list(Some("video")).unwrap().is_empty());
+        assert_eq!(store.find("audio", None).unwrap().id, snapshot.id);
+
+        let restored = store.restore(&snapshot, false).unwrap();
+        assert_eq!((restored.rolled_back.len(), restored.audit_entries_kept), (0, 1));
+        let repository = open();
+        assert_eq!(repository.get_code_index_by_name("audio").unwrap().unwrap().total_symbols, 200);
+        assert_eq!(repository.list_audit_entries(None, Some(AuditOperation::UpdateIndex), None, 10).unwrap().len(), 1);
+
+        // Changes to other indices are only rolled back when forced
+        video.update_stats(4, 40);
+        repository.update_code_index(&video).unwrap();
+        drop(repository);
+        assert!(matches!(store.restore(&snapshot, false), Err(StorageError::Conflict(_))));
+        assert_eq!(store.restore(&snapshot, true).unwrap().rolled_back, ["video"]);
+        assert_eq!(open().get_code_index_by_name("video").unwrap().unwrap().total_files, 0);
+    }
+}
@@ -0,0 +1,299 @@
+// Tags file export
+//
+// vim and emacs jump to definitions through a tags file, usually written by
+// running ctags or etags over the tree. The same tags are written here from
+// the stored symbols, so an indexed codebase needs no second tagging pass:
+// the extended ctags format for vim, sorted so lookups can binary search
+// it, and the etags format for emacs, one section per file.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+
+/// Formats a tags file can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagsFormat {
+    Ctags,
+    Etags,
+}
+
+/// A tags file and what went into it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagsFile {
+    pub contents: String,
+    pub tag_count: usize,
+    pub file_count: usize,
+    /// Files whose source couldn't be read; their tags address lines by number
+    pub unreadable_files: Vec<String>,
+}
+
+impl TagsFormat {
+    /// Parses "ctags" or "etags"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ctags" => Some(Self::Ctags),
+            "etags" => Some(Self::Etags),
+            _ => None,
+        }
+    }
+
+    /// Format conventionally written to a file name: `TAGS` for etags, `tags` for ctags
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.file_name()?.to_str()? {
+            "TAGS" => Some(Self::Etags),
+            "tags" => Some(Self::Ctags),
+            _ => None,
+        }
+    }
+
+    /// File name the editors look for by default
+    pub fn default_file_name(&self) -> &'static str {
+        match self {
+            Self::Ctags => "tags",
+            Self::Etags => "TAGS",
+        }
+    }
+}
+
+impl TagsFile {
+    /// Writes a tags file of `elements`
+    ///
+    /// `path_of` maps a stored relative path to the one written, which the
+    /// editors resolve against the directory of the tags file. `read_source`
+    /// returns a file's text; tags of files it can't read fall back to line
+    /// numbers, which go stale as soon as the file is edited.
+    pub fn build(
+        format: TagsFormat,
+        elements: &[CodeElement],
+        path_of: impl Fn(&str) -> String,
+        mut read_source: impl FnMut(&str) -> Option<String>,
+    ) -> Self {
+        let scopes: HashMap<String, SymbolType> = elements
+            .iter()
+            .filter(|element| scope_field(element.symbol_type).is_some())
+            .map(|element| (element.fully_qualified_name(), element.symbol_type))
+            .collect();
+        let mut by_file: BTreeMap<&str, Vec<&CodeElement>> = BTreeMap::new();
+        for element in elements.iter().filter(|element| kind_letter(element).is_some()) {
+            by_file.entry(element.file_path.as_str()).or_default().push(element);
+        }
+
+        let mut ctags_lines = Vec::new();
+        let mut contents = String::new();
+        let mut tag_count = 0;
+        let file_count = by_file.len();
+        let mut unreadable_files = Vec::new();
+        for (file_path, mut file_elements) in by_file {
+            file_elements.sort_by_key(|element| (element.line_number, element.column_number));
+            let source = read_source(file_path);
+            if source.is_none() {
+                unreadable_files.push(file_path.to_string());
+            }
+            let lines = source.as_deref().map(SourceFile::new);
+            let path = path_of(file_path);
+            tag_count += file_elements.len();
+            match format {
+                TagsFormat::Ctags => {
+                    ctags_lines.extend(file_elements.iter().map(|element| ctags_line(element, &path, lines.as_ref(), &scopes)));
+                }
+                TagsFormat::Etags => {
+                    let section: String = file_elements.iter().map(|element| etags_line(element, lines.as_ref())).collect();
+                    contents.push_str(&format!("\x0c\n{},{}\n{}", path, section.len(), section));
+                }
+            }
+        }
+
+        if format == TagsFormat::Ctags {
+            ctags_lines.sort();
+            contents = ctags_header();
+            for line in ctags_lines {
+                contents.push_str(&line);
+                contents.push('\n');
+            }
+        }
+        Self {
+            contents,
+            tag_count,
+            file_count,
+            unreadable_files,
+        }
+    }
+}
+
+/// Lines of a source file with the byte offset each starts at
+struct SourceFile<'a> {
+    lines: Vec<(usize, &'a str)>,
+}
+
+impl<'a> SourceFile<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut offset = 0;
+        let lines = text
+            .split('\n')
+            .map(|line| {
+                let start = offset;
+                offset += line.len() + 1;
+                (start, line.strip_suffix('\r').unwrap_or(line))
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /// Byte offset and text of a 1-based line
+    fn line(&self, line_number: u32) -> Option<(usize, &'a str)> {
+        self.lines.get((line_number as usize).checked_sub(1)?).copied()
+    }
+}
+
+fn ctags_header() -> String {
+    [
+        "!_TAG_FILE_FORMAT\t2\t/extended format; --format=1 will not append ;\" to lines/",
+        "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/",
+        &format!("!_TAG_PROGRAM_NAME\t{}\t//", crate::NAME),
+        &format!("!_TAG_PROGRAM_VERSION\t{}\t//", crate::VERSION),
+    ]
+    .iter()
+    .map(|line| format!("{}\n", line))
+    .collect()
+}
+
+/// `name<TAB>file<TAB>address;"<TAB>kind<TAB>fields`, searching for the line where it can be read
+fn ctags_line(element: &CodeElement, path: &str, source: Option<&SourceFile>, scopes: &HashMap<String, SymbolType>) -> String {
+    let address = match source.and_then(|source| source.line(element.line_number)) {
+        // vim searches tag patterns with 'nomagic', where only these two are special
+        Some((_, line)) => format!("/^{}$/", line.replace('\\', "\\\\").replace('/', "\\/")),
+        None => element.line_number.to_string(),
+    };
+    let mut line = format!(
+        "{}\t{}\t{};\"\t{}\tline:{}",
+        element.symbol_name,
+        path,
+        address,
+        kind_letter(element).unwrap_or('v'),
+        element.line_number
+    );
+    if let Some(scope) = element.scope.as_deref().filter(|scope| !scope.is_empty()) {
+        if let Some(field) = scopes.get(scope).and_then(|symbol_type| scope_field(*symbol_type)) {
+            line.push_str(&format!("\t{}:{}", field, scope));
+        }
+    }
+    if let Some(access) = element.access_modifier {
+        line.push_str(&format!("\taccess:{}", access.as_str()));
+    }
+    if let Some(signature) = element.signature.as_deref().filter(|_| is_function(element.symbol_type)) {
+        if let Some(parameters) = signature.find('(').map(|start| &signature[start..]) {
+            line.push_str(&format!("\tsignature:{}", parameters.replace('\t', " ")));
+        }
+    }
+    line
+}
+
+/// `text<DEL>name<SOH>line,offset`, where text is the line up to the end of the name
+fn etags_line(element: &CodeElement, source: Option<&SourceFile>) -> String {
+    match source.and_then(|source| source.line(element.line_number)) {
+        Some((offset, line)) => {
+            let end = (element.column_number.saturating_sub(1) as usize + element.symbol_name.len()).min(line.len());
+            let text = match line.get(..end).filter(|text| text.ends_with(element.symbol_name.as_str())) {
+                Some(text) => text,
+                None => line,
+            };
+            format!("{}\x7f{}\x01{},{}\n", text, element.symbol_name, element.line_number, offset)
+        }
+        None => format!("{}\x7f{}\x01{},\n", element.symbol_name, element.symbol_name, element.line_number),
+    }
+}
+
+/// Kind letter universal-ctags gives the symbol in C++; None for symbols left out
+fn kind_letter(element: &CodeElement) -> Option<char> {
+    let has_scope = element.scope.as_deref().is_some_and(|scope| !scope.is_empty());
+    let letter = match element.symbol_type {
+        SymbolType::Class | SymbolType::Template => 'c',
+        SymbolType::Struct => 's',
+        SymbolType::Union => 'u',
+        SymbolType::Enum => 'g',
+        SymbolType::EnumConstant => 'e',
+        SymbolType::Namespace => 'n',
+        SymbolType::Typedef => 't',
+        SymbolType::Macro => 'd',
+        SymbolType::Field => 'm',
+        SymbolType::Variable if has_scope && element.access_modifier.is_some() => 'm',
+        SymbolType::Variable => 'v',
+        SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor | SymbolType::Operator => {
+            if element.is_declaration {
+                'p'
+            } else {
+                'f'
+            }
+        }
+        SymbolType::Lambda | SymbolType::Unknown => return None,
+    };
+    Some(letter)
+}
+
+/// Extension field naming a member's scope when the scope is of this type
+fn scope_field(symbol_type: SymbolType) -> Option<&'static str> {
+    match symbol_type {
+        SymbolType::Namespace => Some("namespace"),
+        SymbolType::Class | SymbolType::Template => Some("class"),
+        SymbolType::Struct => Some("struct"),
+        SymbolType::Union => Some("union"),
+        SymbolType::Enum => Some("enum"),
+        _ => None,
+    }
+}
+
+fn is_function(symbol_type: SymbolType) -> bool {
+    matches!(symbol_type, SymbolType::Function | SymbolType::Constructor | SymbolType::Destructor | SymbolType::Operator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::models::code_element::AccessModifier;
+    use uuid::Uuid;
+
+    const MIXER: &str = "namespace audio {\nclass Mixer {\npublic:\n    void mix(float* out);  // a/b\n};\n}\n";
+
+    #[test]
+    fn test_tags_export() {
+        let element = |name: &str, symbol_type, file: &str, line, column, scope: Option<&str>| {
+            let element = CodeElement::new(Uuid::nil(), name.to_string(), symbol_type, file.to_string(), line, column, "h".to_string());
+            match scope {
+                Some(scope) => element.with_scope(scope.to_string()),
+                None => element,
+            }
+        };
+        let elements = vec![
+            element("audio", SymbolType::Namespace, "mixer.h", 1, 11, None),
+            element("Mixer", SymbolType::Class, "mixer.h", 2, 7, Some("audio")),
+            element("mix", SymbolType::Function, "mixer.h", 4, 10, Some("audio::Mixer"))
+                .with_declaration(true)
+                .with_access_modifier(AccessModifier::Public)
+                .with_signature("void mix(float* out)".to_string()),
+            element("mix", SymbolType::Function, "mixer.cpp", 3, 20, Some("audio::Mixer")),
+        ];
+        let read = |file: &str| (file == "mixer.h").then(|| MIXER.to_string());
+
+        let ctags = TagsFile::build(TagsFormat::Ctags, &elements, |path| format!("src/{}", path), read);
+        assert_eq!((ctags.tag_count, ctags.file_count, ctags.unreadable_files.as_slice()), (4, 2, ["mixer.cpp".to_string()].as_slice()));
+        let tags: Vec<&str> = ctags.contents.lines().filter(|line| !line.starts_with("!_")).collect();
+        assert_eq!(
+            tags,
+            [
+                "Mixer\tsrc/mixer.h\t/^class Mixer {$/;\"\tc\tline:2\tnamespace:audio",
+                "audio\tsrc/mixer.h\t/^namespace audio {$/;\"\tn\tline:1",
+                "mix\tsrc/mixer.cpp\t3;\"\tf\tline:3\tclass:audio::Mixer",
+                "mix\tsrc/mixer.h\t/^    void mix(float* out);  \\/\\/ a\\/b$/;\"\tp\tline:4\tclass:audio::Mixer\taccess:public\tsignature:(float* out)",
+            ]
+        );
+        assert!(ctags.contents.starts_with("!_TAG_FILE_FORMAT\t2\t"));
+
+        let etags = TagsFile::build(TagsFormat::Etags, &elements, str::to_string, read);
+        let header = "\x0c\nmixer.h,";
+        let section = "namespace audio\x7faudio\x011,0\nclass Mixer\x7fMixer\x012,18\n    void mix\x7fmix\x014,40\n";
+        assert!(etags.contents.starts_with("\x0c\nmixer.cpp,11\nmix\x7fmix\x013,\n"));
+        assert!(etags.contents.ends_with(&format!("{}{}\n{}", header, section.len(), section)));
+        assert_eq!(TagsFormat::from_path(Path::new("/src/TAGS")), Some(TagsFormat::Etags));
+    }
+}
@@ -0,0 +1,137 @@
+// Query timings
+//
+// Every repository query timed for the slow query log is also counted here
+// by operation when a `QueryTimings` is attached, so the server can report
+// how long the database takes per kind of query, not just its outliers.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets; slower samples only count towards the total
+pub const LATENCY_BUCKETS: [Duration; 12] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// Count, total, maximum and histogram of a series of durations
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+    /// Samples per bucket of `LATENCY_BUCKETS`, not cumulative
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+/// Calls of one tool: how many, which failed and how long they took
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Failed calls per error kind (e.g. "validation", "cancelled")
+    pub error_kinds: BTreeMap<String, u64>,
+    pub latency: LatencyStats,
+}
+
+/// Latency of repository queries by operation, shared by every repository it is attached to
+#[derive(Debug, Clone, Default)]
+pub struct QueryTimings {
+    operations: Arc<Mutex<BTreeMap<String, LatencyStats>>>,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| elapsed <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+    }
+
+    /// Average duration in milliseconds, None before the first sample
+    pub fn mean_ms(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total.as_secs_f64() * 1000.0 / self.count as f64)
+    }
+
+    /// Samples at or below each bucket bound, as Prometheus histograms count them
+    pub fn cumulative_buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        let mut total = 0;
+        LATENCY_BUCKETS.iter().zip(&self.buckets).map(move |(bound, count)| {
+            total += count;
+            (*bound, total)
+        })
+    }
+}
+
+impl ToolStats {
+    /// Counts a call that took `elapsed` and, if it failed, its error kind
+    pub fn record(&mut self, elapsed: Duration, error_kind: Option<&str>) {
+        self.calls += 1;
+        self.latency.record(elapsed);
+        if let Some(kind) = error_kind {
+            self.errors += 1;
+            *self.error_kinds.entry(kind.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Share of calls that failed, from 0.0 to 1.0
+    pub fn error_rate(&self) -> f64 {
+        if self.calls > 0 {
+            self.errors as f64 / self.calls as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+impl QueryTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one run of `operation` that took `elapsed`
+    pub fn record(&self, operation: &str, elapsed: Duration) {
+        if let Ok(mut operations) = self.operations.lock() {
+            operations.entry(operation.to_string()).or_default().record(elapsed);
+        }
+    }
+
+    /// Latency of each operation recorded so far
+    pub fn snapshot(&self) -> BTreeMap<String, LatencyStats> {
+        self.operations.lock().map(|operations| operations.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_timings() {
+        let timings = QueryTimings::new();
+        let shared = timings.clone();
+        timings.record("search_code_elements", Duration::from_millis(3));
+        shared.record("search_code_elements", Duration::from_millis(40));
+        shared.record("search_code_elements", Duration::from_secs(30));
+
+        let stats = &timings.snapshot()["search_code_elements"];
+        assert_eq!((stats.count, stats.max), (3, Duration::from_secs(30)));
+        assert!((stats.mean_ms().unwrap() - 10014.333).abs() < 0.01);
+        let cumulative: Vec<u64> = stats.cumulative_buckets().map(|(_, count)| count).collect();
+        // 3 ms falls in the 5 ms bucket, 40 ms in the 50 ms one; 30 s is beyond the last
+        assert_eq!(cumulative, [0, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2]);
+        assert_eq!(LatencyStats::default().mean_ms(), None);
+    }
+}
@@ -0,0 +1,325 @@
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use crate::lib::storage::error::Result;
+use crate::lib::storage::models::code_element::{CodeElement, SymbolType};
+use crate::lib::storage::models::symbol_relationships::RelationshipType;
+use crate::lib::storage::query::{CodeElementQuery, ElementColumn, Filter, RelationshipColumn, SymbolRelationshipQuery};
+use crate::lib::storage::repository::Repository;
+
+/// Default number of inheritance levels followed from the class
+pub const DEFAULT_MAX_DEPTH: u32 = 10;
+
+/// Default cap on the number of classes in one hierarchy
+pub const DEFAULT_MAX_NODES: usize = 1000;
+
+/// Symbol types that can have bases and derived classes
+pub const CLASS_TYPES: &[SymbolType] = &[SymbolType::Class, SymbolType::Struct, SymbolType::Union, SymbolType::Template];
+
+/// Symbol types whose members can override one another
+const METHOD_TYPES: &[SymbolType] = &[SymbolType::Function, SymbolType::Destructor, SymbolType::Operator];
+
+/// Which way inheritance is followed from the class
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HierarchyDirection {
+    /// Classes the class derives from, up to the root bases
+    Bases,
+    /// Classes deriving from the class, down to the leaves
+    Derived,
+}
+
+/// Traversal settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HierarchyOptions {
+    pub direction: HierarchyDirection,
+    /// Inheritance levels followed from the class
+    pub max_depth: u32,
+    /// Traversal stops once this many classes are in the hierarchy
+    pub max_nodes: usize,
+}
+
+/// An Inherits relationship: `derived_id` lists `base_id` as a base
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
+pub struct InheritanceEdge {
+    pub derived_id: i64,
+    pub base_id: i64,
+    pub file_path: String,
+    pub line_number: u32,
+}
+
+/// A class in the hierarchy and its distance from the starting class
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HierarchyNode {
+    pub element: CodeElement,
+    pub depth: u32,
+}
+
+/// Result of a hierarchy traversal in one direction
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TypeHierarchy {
+    pub root: i64,
+    /// Classes in breadth-first order, the starting class first
+    pub nodes: Vec<HierarchyNode>,
+    pub edges: Vec<InheritanceEdge>,
+    /// True if `max_nodes` stopped the traversal early
+    pub truncated: bool,
+}
+
+/// A method overriding a method of another class in the hierarchy
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct OverrideEdge {
+    pub method: CodeElement,
+    pub overridden: CodeElement,
+}
+
+/// Walks Inherits relationships breadth-first from a class
+///
+/// Multiple inheritance makes the hierarchy a graph: a class reached along
+/// two paths (a diamond) appears once, at its shortest depth, with an edge
+/// for every path.
+pub struct TypeHierarchyWalker<'a> {
+    repository: &'a Repository,
+    options: HierarchyOptions,
+}
+
+impl Default for HierarchyOptions {
+    fn default() -> Self {
+        Self {
+            direction: HierarchyDirection::Bases,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+impl HierarchyOptions {
+    /// Follows inheritance in the given direction
+    pub fn with_direction(mut self, direction: HierarchyDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the number of inheritance levels followed
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Caps the number of classes in the hierarchy
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes.max(1);
+        self
+    }
+}
+
+impl<'a> TypeHierarchyWalker<'a> {
+    /// Creates a walker over the repository's relationships
+    pub fn new(repository: &'a Repository, options: HierarchyOptions) -> Self {
+        Self { repository, options }
+    }
+
+    /// Builds the hierarchy reachable from `root`
+    pub fn walk(&self, root: i64) -> Result<TypeHierarchy> {
+        // Inherits relationships point from the derived class to its base
+        let from = match self.options.direction {
+            HierarchyDirection::Bases => RelationshipColumn::FromSymbolId,
+            HierarchyDirection::Derived => RelationshipColumn::ToSymbolId,
+        };
+
+        let mut depths = HashMap::from([(root, 0)]);
+        let mut order = vec![root];
+        let mut edges = Vec::new();
+        let mut seen_edges = HashSet::new();
+        let mut truncated = false;
+        let mut frontier = vec![root];
+        let mut depth = 0;
+
+        while !frontier.is_empty() && depth < self.options.max_depth && !truncated {
+            let inherits = self.repository.query_relationships(
+                &SymbolRelationshipQuery::new()
+                    .filter(Filter::eq(RelationshipColumn::RelationshipType, RelationshipType::Inherits))
+                    .filter(Filter::in_list(from, frontier.iter().copied()))
+                    .order_by_asc(RelationshipColumn::Id),
+            )?;
+
+            let mut next = Vec::new();
+            for relationship in inherits {
+                let neighbor = match self.options.direction {
+                    HierarchyDirection::Bases => relationship.to_symbol_id,
+                    HierarchyDirection::Derived => relationship.from_symbol_id,
+                };
+                if let Entry::Vacant(entry) = depths.entry(neighbor) {
+                    if order.len() >= self.options.max_nodes {
+                        truncated = true;
+                        break;
+                    }
+                    entry.insert(depth + 1);
+                    order.push(neighbor);
+                    next.push(neighbor);
+                }
+                let edge = InheritanceEdge {
+                    derived_id: relationship.from_symbol_id,
+                    base_id: relationship.to_symbol_id,
+                    file_path: relationship.file_path,
+                    line_number: relationship.line_number,
+                };
+                if seen_edges.insert(edge.clone()) {
+                    edges.push(edge);
+                }
+            }
+
+            frontier = next;
+            depth += 1;
+        }
+
+        let mut elements = self.elements(&order)?;
+        let nodes = order
+            .iter()
+            .filter_map(|id| elements.remove(id).map(|element| HierarchyNode { element, depth: depths[id] }))
+            .collect();
+
+        Ok(TypeHierarchy { root, nodes, edges, truncated })
+    }
+
+    /// Overrides between methods of the given classes
+    ///
+    /// A method belongs to a class when its scope is the class's qualified
+    /// name. Only overrides whose overridden method is also a member of one
+    /// of the classes are returned.
+    pub fn overrides(&self, classes: &[&CodeElement]) -> Result<Vec<OverrideEdge>> {
+        let Some(first) = classes.first() else {
+            return Ok(Vec::new());
+        };
+        let methods = self.repository.query_code_elements(
+            &CodeElementQuery::new()
+                .filter(Filter::eq(ElementColumn::IndexId, first.index_id.to_string()))
+                .filter(Filter::in_list(ElementColumn::Scope, classes.iter().map(|class| class.fully_qualified_name())))
+                .filter(Filter::in_list(ElementColumn::SymbolType, METHOD_TYPES.iter().copied())),
+        )?;
+        let methods: HashMap<i64, CodeElement> = methods
+            .into_iter()
+            .filter_map(|method| method.id.map(|id| (id, method)))
+            .collect();
+        let mut ids: Vec<i64> = methods.keys().copied().collect();
+        ids.sort_unstable();
+
+        let relationships = self.repository.query_relationships(
+            &SymbolRelationshipQuery::new()
+                .filter(Filter::eq(RelationshipColumn::RelationshipType, RelationshipType::Overrides))
+                .filter(Filter::in_list(RelationshipColumn::FromSymbolId, ids.iter().copied()))
+                .order_by_asc(RelationshipColumn::Id),
+        )?;
+
+        let mut edges = Vec::new();
+        for relationship in relationships {
+            // Overrides point from the overrider to the method it overrides
+            let (Some(method), Some(overridden)) = (
+                methods.get(&relationship.from_symbol_id).cloned(),
+                methods.get(&relationship.to_symbol_id).cloned(),
+            ) else {
+                continue;
+            };
+            edges.push(OverrideEdge { method, overridden });
+        }
+        Ok(edges)
+    }
+
+    fn elements(&self, ids: &[i64]) -> Result<HashMap<i64, CodeElement>> {
+        let query = CodeElementQuery::new().filter(Filter::in_list(ElementColumn::Id, ids.iter().copied()));
+        Ok(self
+            .repository
+            .query_code_elements(&query)?
+            .into_iter()
+            .filter_map(|element| element.id.map(|id| (id, element)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_index::CodeIndex;
+    use crate::lib::storage::models::symbol_relationships::SymbolRelationship;
+
+    /// Widget <- Button <- Toggle, Widget <- Label; Toggle also derives from
+    /// Clickable. Button::paint and Toggle::paint override Widget::paint.
+    fn widgets() -> (Repository, HashMap<&'static str, i64>) {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        let repo = Repository::new(manager.connect().unwrap());
+        let index = repo.create_code_index(CodeIndex::new("ui".to_string(), "/ui".to_string())).unwrap();
+
+        let mut ids = HashMap::new();
+        let elements = [
+            ("Widget", "Widget", SymbolType::Class, "ui"),
+            ("Button", "Button", SymbolType::Class, "ui"),
+            ("Toggle", "Toggle", SymbolType::Class, "ui"),
+            ("Label", "Label", SymbolType::Class, "ui"),
+            ("Clickable", "Clickable", SymbolType::Struct, "ui"),
+            ("Widget::paint", "paint", SymbolType::Function, "ui::Widget"),
+            ("Button::paint", "paint", SymbolType::Function, "ui::Button"),
+            ("Toggle::paint", "paint", SymbolType::Function, "ui::Toggle"),
+        ];
+        for (line, (key, name, symbol_type, scope)) in elements.into_iter().enumerate() {
+            let element = CodeElement::new(index.id, name.to_string(), symbol_type, "src/ui.h".to_string(), line as u32 + 1, 1, "a".repeat(64))
+                .with_scope(scope.to_string());
+            ids.insert(key, repo.create_code_element(element).unwrap().id.unwrap());
+        }
+
+        let relate = |from: &str, to: &str, relationship_type| {
+            repo.create_symbol_relationship(SymbolRelationship::new(ids[from], ids[to], relationship_type, "src/ui.h".to_string(), 20)).unwrap();
+        };
+        relate("Button", "Widget", RelationshipType::Inherits);
+        relate("Toggle", "Button", RelationshipType::Inherits);
+        relate("Toggle", "Clickable", RelationshipType::Inherits);
+        relate("Label", "Widget", RelationshipType::Inherits);
+        relate("Button::paint", "Widget::paint", RelationshipType::Overrides);
+        relate("Toggle::paint", "Button::paint", RelationshipType::Overrides);
+
+        (repo, ids)
+    }
+
+    fn depths(hierarchy: &TypeHierarchy) -> Vec<(i64, u32)> {
+        hierarchy.nodes.iter().map(|node| (node.element.id.unwrap(), node.depth)).collect()
+    }
+
+    #[test]
+    fn test_bases_and_derived() {
+        let (repo, ids) = widgets();
+
+        let bases = TypeHierarchyWalker::new(&repo, HierarchyOptions::default()).walk(ids["Toggle"]).unwrap();
+        assert_eq!(depths(&bases), [(ids["Toggle"], 0), (ids["Button"], 1), (ids["Clickable"], 1), (ids["Widget"], 2)]);
+        assert_eq!(bases.edges.len(), 3);
+
+        let options = HierarchyOptions::default().with_direction(HierarchyDirection::Derived);
+        let derived = TypeHierarchyWalker::new(&repo, options).walk(ids["Widget"]).unwrap();
+        assert_eq!(depths(&derived), [(ids["Widget"], 0), (ids["Button"], 1), (ids["Label"], 1), (ids["Toggle"], 2)]);
+        assert!(derived.edges.iter().any(|edge| edge.derived_id == ids["Toggle"] && edge.base_id == ids["Button"]));
+
+        let shallow = TypeHierarchyWalker::new(&repo, options.with_max_depth(1)).walk(ids["Widget"]).unwrap();
+        assert_eq!(shallow.nodes.len(), 3);
+        assert!(!shallow.truncated);
+
+        let capped = TypeHierarchyWalker::new(&repo, options.with_max_nodes(2)).walk(ids["Widget"]).unwrap();
+        assert_eq!(capped.nodes.len(), 2);
+        assert!(capped.truncated);
+    }
+
+    #[test]
+    fn test_overrides_within_hierarchy() {
+        let (repo, ids) = widgets();
+        let walker = TypeHierarchyWalker::new(&repo, HierarchyOptions::default());
+        let bases = walker.walk(ids["Toggle"]).unwrap();
+        let classes: Vec<&CodeElement> = bases.nodes.iter().map(|node| &node.element).collect();
+
+        let overrides = walker.overrides(&classes).unwrap();
+        let pairs: Vec<(i64, i64)> = overrides.iter().map(|edge| (edge.method.id.unwrap(), edge.overridden.id.unwrap())).collect();
+        assert_eq!(pairs, [(ids["Button::paint"], ids["Widget::paint"]), (ids["Toggle::paint"], ids["Button::paint"])]);
+
+        // Widget alone has no override within its hierarchy
+        let widget = walker.walk(ids["Widget"]).unwrap();
+        assert!(walker.overrides(&[&widget.nodes[0].element]).unwrap().is_empty());
+    }
+}
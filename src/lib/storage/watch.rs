@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+use crate::lib::storage::error::{Result, StorageError};
+use crate::lib::storage::models::code_element::CodeElement;
+use crate::lib::storage::models::saved_query::SavedQuery;
+use crate::lib::storage::repository::Repository;
+
+/// Largest result set tracked per watched query
+pub const MAX_WATCH_RESULTS: u64 = 10_000;
+
+/// How a watched query's result set changed since it was last evaluated
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct WatchChange {
+    /// Saved query name
+    pub name: String,
+    pub query: String,
+    /// Symbols that now match, as `file: qualified::name (type)`
+    pub added: Vec<String>,
+    /// Symbols that no longer match
+    pub removed: Vec<String>,
+    /// Size of the new result set
+    pub total_count: usize,
+}
+
+/// Re-runs the watched queries of an index and reports changed result sets
+///
+/// Symbols are compared by file, qualified name and type, not position, so
+/// edits that only move a symbol don't trigger a watch. The first evaluation
+/// of a watch records its baseline and reports nothing.
+pub struct WatchEvaluator<'a> {
+    repository: &'a Repository,
+    max_results: u64,
+}
+
+impl<'a> WatchEvaluator<'a> {
+    /// Creates an evaluator tracking up to MAX_WATCH_RESULTS symbols per query
+    pub fn new(repository: &'a Repository) -> Self {
+        Self {
+            repository,
+            max_results: MAX_WATCH_RESULTS,
+        }
+    }
+
+    /// Caps the result set tracked per query; symbols beyond it are ignored
+    pub fn with_max_results(mut self, max_results: u64) -> Self {
+        self.max_results = max_results.max(1);
+        self
+    }
+
+    /// Evaluates every watched query of the index, updating their baselines
+    pub fn run(&self, index_id: &Uuid) -> Result<Vec<WatchChange>> {
+        let mut changes = Vec::new();
+        for saved in self.repository.list_watched_queries(index_id)? {
+            if let Some(change) = self.evaluate(&saved)? {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Evaluates one watched query, updating its baseline
+    pub fn evaluate(&self, saved: &SavedQuery) -> Result<Option<WatchChange>> {
+        let id = saved.id.ok_or_else(|| StorageError::Validation(format!("Saved query {} has no id", saved.name)))?;
+        let query = saved.to_element_query().map_err(StorageError::Validation)?.limit(self.max_results);
+
+        let current: BTreeSet<String> = self.repository.query_code_elements(&query)?.iter().map(result_key).collect();
+        let keys: Vec<String> = current.iter().cloned().collect();
+        let baseline = self.repository.get_watch_baseline(id)?;
+        self.repository.set_watch_baseline(id, &keys)?;
+
+        let Some(baseline) = baseline else { return Ok(None) };
+        let previous: BTreeSet<String> = baseline.into_iter().collect();
+        if previous == current {
+            return Ok(None);
+        }
+
+        Ok(Some(WatchChange {
+            name: saved.name.clone(),
+            query: saved.query.clone(),
+            added: current.difference(&previous).cloned().collect(),
+            removed: previous.difference(&current).cloned().collect(),
+            total_count: current.len(),
+        }))
+    }
+}
+
+/// Identity of a symbol within a watch result set
+fn result_key(element: &CodeElement) -> String {
+    format!("{}: {} ({})", element.file_path, element.fully_qualified_name(), element.symbol_type.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::connection::{DatabaseConfig, DatabaseManager};
+    use crate::lib::storage::models::code_element::SymbolType;
+    use crate::lib::storage::models::code_index::CodeIndex;
+
+    fn create_test_repository() -> Repository {
+        let manager = DatabaseManager::new(DatabaseConfig::in_memory()).unwrap();
+        Repository::new(manager.connect().unwrap())
+    }
+
+    fn add_function(repo: &Repository, index_id: Uuid, name: &str, file: &str, line: u32) -> CodeElement {
+        repo.create_code_element(CodeElement::new(index_id, name.to_string(), SymbolType::Function, file.to_string(), line, 1, "a".repeat(64)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_watch_reports_result_set_changes() {
+        let repo = create_test_repository();
+        let index = repo.create_code_index(CodeIndex::new("net".to_string(), "/net".to_string())).unwrap();
+        let send = add_function(&repo, index.id, "send_packet", "src/net/tx.cpp", 10);
+        repo.save_query(SavedQuery::new(index.id, "net-functions".to_string(), "type:function file:src/net/*".to_string()).with_watch(true))
+            .unwrap();
+        repo.save_query(SavedQuery::new(index.id, "unwatched".to_string(), "type:function".to_string())).unwrap();
+
+        // The first run records the baseline
+        let evaluator = WatchEvaluator::new(&repo);
+        assert!(evaluator.run(&index.id).unwrap().is_empty());
+
+        // Moving a symbol is not a change; adding or removing one is
+        let mut moved = send.clone();
+        moved.line_number = 40;
+        repo.update_code_element(&moved).unwrap();
+        add_function(&repo, index.id, "recv_packet", "src/net/rx.cpp", 5);
+        add_function(&repo, index.id, "draw", "src/ui/draw.cpp", 5);
+        let changes = evaluator.run(&index.id).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].name, "net-functions");
+        assert_eq!(changes[0].added, vec!["src/net/rx.cpp: recv_packet (function)"]);
+        assert!(changes[0].removed.is_empty());
+        assert_eq!(changes[0].total_count, 2);
+
+        repo.delete_code_element(send.id.unwrap()).unwrap();
+        let changes = evaluator.run(&index.id).unwrap();
+        assert_eq!(changes[0].removed, vec!["src/net/tx.cpp: send_packet (function)"]);
+        assert!(evaluator.run(&index.id).unwrap().is_empty());
+
+        // Unwatching drops the baseline
+        assert!(repo.set_query_watched(&index.id, "net-functions", false).unwrap());
+        assert!(repo.list_watched_queries(&index.id).unwrap().is_empty());
+    }
+}
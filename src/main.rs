@@ -1,8 +1,70 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use tracing::info;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
 
-mod config;
+use cpp_index_mcp::lib::cli_interface::self_update::{self, ReleaseChannel};
+use cpp_index_mcp::lib::cpp_indexer::adaptive_depth::DepthPlanner;
+use cpp_index_mcp::lib::cpp_indexer::build_capture::{record_invocation, CapturedInvocation, CAPTURE_LOG_ENV};
+#[cfg(unix)]
+use cpp_index_mcp::lib::cpp_indexer::build_capture::{
+    compile_commands, find_executable, install_shim, read_invocations, write_compile_commands, SHIMMED_COMPILERS,
+};
+use cpp_index_mcp::lib::cpp_indexer::changelog::ChangeReport;
+use cpp_index_mcp::lib::cpp_indexer::clang_parser::{ClangParser, HeaderCache};
+use cpp_index_mcp::lib::cpp_indexer::clangd_index::{ClangdIndex, CLANGD_INDEX_DIR};
+use cpp_index_mcp::lib::cpp_indexer::conditionals::{assign_configurations, MacroConfiguration};
+use cpp_index_mcp::lib::cpp_indexer::index_settings::IndexSettings;
+use cpp_index_mcp::lib::cpp_indexer::include_roots::{infer_include_dirs, ClangProbe};
+use cpp_index_mcp::lib::cpp_indexer::incremental::IncrementalIndexer;
+use cpp_index_mcp::lib::cpp_indexer::multi_build::{BuildManifest, IndexSpec, MultiBuild};
+use cpp_index_mcp::lib::cpp_indexer::parse_worker;
+use cpp_index_mcp::lib::cpp_indexer::pipeline::{IndexingPipeline, ParserWorker, PipelineConfig, PipelineReport};
+use cpp_index_mcp::lib::cpp_indexer::presets::{self, IndexPreset, PRESET_TAG};
+use cpp_index_mcp::lib::cpp_indexer::symbol_extractor::SymbolExtractor;
+use cpp_index_mcp::lib::cpp_indexer::vendored::skip_vendored_copies;
+use cpp_index_mcp::lib::cpp_indexer::walk_filter::{default_rules, WalkFilter};
+use cpp_index_mcp::lib::cpp_indexer::watcher::{apply_changes, FileWatcher, WatchBatch, DEFAULT_DEBOUNCE};
+use cpp_index_mcp::lib::mcp_server::failover::Failover;
+use cpp_index_mcp::lib::mcp_server::freshness::{check_file, detect_moves, files_to_keep, missing_files, store_moved, Freshness};
+use cpp_index_mcp::lib::mcp_server::references::{find_word, SourceLines};
+use cpp_index_mcp::lib::mcp_server::registry::RepositoryRegistry;
+use cpp_index_mcp::lib::mcp_server::review::parse_unified_diff;
+use cpp_index_mcp::lib::mcp_server::risk::RiskReport;
+use cpp_index_mcp::lib::mcp_server::scheduler::Scheduler;
+use cpp_index_mcp::lib::mcp_server::server::McpServer;
+use cpp_index_mcp::lib::mcp_server::telemetry::{read_spool, send_spool, Telemetry};
+use cpp_index_mcp::lib::mcp_server::tool_handlers::ReadOnlyMode;
+use cpp_index_mcp::lib::storage::archive::{export_index, IndexArchive};
+use cpp_index_mcp::lib::storage::batch_writer::PriorityGate;
+use cpp_index_mcp::lib::storage::code_intel::{CodeIntelFormat, CodeIntelIndex};
+use cpp_index_mcp::lib::storage::connection::{CheckpointMode, CheckpointScheduler, DatabaseConfig, DatabaseManager};
+use cpp_index_mcp::lib::storage::coupling::{CouplingGranularity, CouplingReport, ExportFormat};
+use cpp_index_mcp::lib::storage::dsm::{DependencyMatrix, DEFAULT_DSM_LEVEL};
+use cpp_index_mcp::lib::storage::element_listing::{render_rows, ElementRow, ListingFormat};
+use cpp_index_mcp::lib::storage::embeddings::EmbeddingStore;
+use cpp_index_mcp::lib::storage::encryption::{export_encrypted, EncryptionKey, KEY_ENV_VAR};
+use cpp_index_mcp::lib::storage::error::StorageError;
+use cpp_index_mcp::lib::storage::models::admin_audit::{AuditActor, AuditEntry, AuditOperation};
+use cpp_index_mcp::lib::storage::models::build_configuration::{BuildConfiguration, DEFAULT_CONFIGURATION_NAME};
+use cpp_index_mcp::lib::storage::models::parse_settings::ParseSettings;
+use cpp_index_mcp::lib::storage::models::code_index::{CodeIndex, IndexState};
+use cpp_index_mcp::lib::storage::models::index_insights::{RankedFile, RankedSymbol};
+use cpp_index_mcp::lib::storage::models::index_tag::IndexTag;
+use cpp_index_mcp::lib::storage::models::saved_query::SavedQuery;
+use cpp_index_mcp::lib::storage::query::{CodeElementQuery, ElementColumn, Filter};
+use cpp_index_mcp::lib::storage::tags::{TagsFile, TagsFormat};
+use cpp_index_mcp::lib::storage::query_dsl::term_filter;
+use cpp_index_mcp::lib::storage::recovery::{DatabaseHealth, DatabaseRecovery, RecoveryStrategy, ServerStorage};
+use cpp_index_mcp::lib::storage::repository::Repository;
+use cpp_index_mcp::lib::storage::snapshot::SnapshotStore;
+use cpp_index_mcp::lib::storage::watch::{WatchChange, WatchEvaluator};
+use cpp_index_mcp::lib::storage::retention::{
+    parse_duration, GarbageCollector, RetentionAction, RetentionPolicy, RetentionRule,
+};
+
+use cpp_index_mcp::config;
 
 // Library modules will be implemented later
 // mod lib {
@@ -15,6 +77,9 @@ mod config;
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Database to use instead of the one the storage location gives an index
+    #[arg(long, global = true, value_name = "PATH")]
+    db_path: Option<std::path::PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -33,18 +98,283 @@ enum Commands {
         /// Use STDIO transport
         #[arg(long)]
         stdio: bool,
-        /// Index name to serve
+        /// Serve an index from a database of its own (repeatable); tools name the index they query
+        #[arg(long = "index-database", value_name = "NAME=PATH")]
+        index_databases: Vec<String>,
+        /// Re-index files under the base path of each served index as they change
+        #[arg(long)]
+        watch: bool,
+        /// Write a failover heartbeat so a replica can serve while this server rebuilds
+        #[arg(long, conflicts_with = "replica")]
+        primary: bool,
+        /// Serve read-only, only while the primary is rebuilding or stops sending heartbeats
+        #[arg(long)]
+        replica: bool,
+        /// Open the databases read-only and reject tools that modify them, so several servers can share them with one writer
+        #[arg(long, conflicts_with = "primary")]
+        read_only: bool,
+    },
+    /// Keep an index up to date by re-indexing files as they change
+    Watch {
+        /// Index name
         #[arg(long)]
         index: String,
+        /// Quiet period after a file's last change before re-indexing it, in milliseconds
+        #[arg(long, default_value_t = DEFAULT_DEBOUNCE.as_millis() as u64)]
+        debounce_ms: u64,
     },
     /// Query symbols
     Query {
         /// Index name
         #[arg(long)]
         index: String,
-        /// Symbol to search for
+        /// Symbol name to search for; '*' and '?' are wildcards
+        #[arg(long)]
+        symbol: Option<String>,
+        /// Symbol types, comma-separated (e.g. function,class)
+        #[arg(long = "type", value_name = "TYPES")]
+        symbol_type: Option<String>,
+        /// File path or glob the symbols are in
+        #[arg(long)]
+        file: Option<String>,
+        /// Maximum number of symbols to print
+        #[arg(long, default_value_t = 100)]
+        limit: u64,
+        /// Output format: table, json or csv
+        #[arg(long, default_value = "table")]
+        format: String,
+        #[command(subcommand)]
+        action: Option<QueryActions>,
+    },
+    /// Inspect or send opt-in usage statistics
+    Telemetry {
+        #[command(subcommand)]
+        action: TelemetryActions,
+    },
+    /// Maintain the database file itself
+    Db {
+        #[command(subcommand)]
+        action: DbActions,
+    },
+    /// Change settings in the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigActions,
+    },
+    /// Install the newest release of the configured channel over this binary
+    SelfUpdate {
+        /// Release channel, overriding update_channel: stable or nightly
+        #[arg(long)]
+        channel: Option<String>,
+        /// Only report whether an update is available
+        #[arg(long)]
+        check: bool,
+    },
+    /// Compute metrics over an index for external tools
+    Analyze {
+        #[command(subcommand)]
+        action: AnalyzeActions,
+    },
+    /// Summarize source history
+    Report {
+        #[command(subcommand)]
+        action: ReportActions,
+    },
+    /// Run a build with compiler shims and write the compile_commands.json it implies
+    WrapBuild {
+        /// Where to write the compilation database
+        #[arg(long, value_name = "PATH", default_value = "compile_commands.json")]
+        out: std::path::PathBuf,
+        /// Build command, after `--` (e.g. -- make -j8)
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Record one compiler invocation; run by the shims of wrap-build
+    #[command(hide = true)]
+    RecordCompile {
+        /// Real compiler followed by its arguments
+        #[arg(last = true)]
+        arguments: Vec<String>,
+    },
+    /// Parse the files named on stdin; run by indexing to isolate libclang
+    #[command(hide = true)]
+    ParseWorker,
+}
+
+#[derive(Subcommand)]
+enum DbActions {
+    /// Write a copy of the database that an older release can open, without the newer features
+    Downgrade {
+        /// Schema version of the older release
+        #[arg(long)]
+        to: i32,
+        /// Where to write the copy
+        #[arg(long, value_name = "PATH")]
+        out: std::path::PathBuf,
+        /// Copy the database of this index (default: the shared database)
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReportActions {
+    /// Draft an API changelog of public symbols added, removed or modified between two git revisions
+    Changes {
+        /// Older revision (tag, branch or commit)
+        #[arg(long)]
+        from: String,
+        /// Newer revision
+        #[arg(long, default_value = "HEAD")]
+        to: String,
+        /// Git repository to read
+        #[arg(long, value_name = "PATH", default_value = ".")]
+        repo: std::path::PathBuf,
+        /// Write the report here instead of printing it
+        #[arg(long, value_name = "PATH")]
+        out: Option<std::path::PathBuf>,
+        /// Print JSON instead of Markdown
+        #[arg(long)]
+        json: bool,
+    },
+    /// Score the risk of a diff from the index: callers, test references, size and CODEOWNERS spread
+    Risk {
+        /// Index name
+        #[arg(long)]
+        index: String,
+        /// Unified diff to score; reads stdin if omitted or "-"
+        #[arg(long, value_name = "PATH")]
+        diff: Option<std::path::PathBuf>,
+        /// Write the JSON report here instead of printing it
+        #[arg(long, value_name = "PATH")]
+        out: Option<std::path::PathBuf>,
+        /// Exit with status 1 if the score reaches this (0-100)
+        #[arg(long, value_name = "SCORE")]
+        fail_at: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AnalyzeActions {
+    /// Export fan-in/fan-out per file or directory, computed from cross-file references
+    Coupling {
+        /// Index name
+        #[arg(long)]
+        index: String,
+        /// Output file; the format follows its extension unless --format is given
+        #[arg(long, value_name = "PATH")]
+        out: std::path::PathBuf,
+        /// Aggregate by file or directory
+        #[arg(long, default_value = "file")]
+        by: String,
+        /// csv or json
+        #[arg(long)]
+        format: Option<String>,
+        /// Write one CSV row per coupled pair (the heatmap cells) instead of per unit
+        #[arg(long)]
+        edges: bool,
+    },
+    /// Export a directory-to-directory dependency structure matrix with cycles highlighted
+    Dsm {
+        /// Index name
+        #[arg(long)]
+        index: String,
+        /// Number of leading path components that make up a directory
+        #[arg(long, default_value_t = DEFAULT_DSM_LEVEL)]
+        level: usize,
+        /// Output file; the format follows its extension unless --format is given
+        #[arg(long, value_name = "PATH")]
+        out: std::path::PathBuf,
+        /// json or html
+        #[arg(long)]
+        format: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TelemetryActions {
+    /// Show whether telemetry is enabled and what is waiting to be sent
+    Status,
+    /// Post spooled reports to the configured endpoint
+    Send,
+}
+
+#[derive(Subcommand)]
+enum QueryActions {
+    /// Save a named filter expression (e.g. "type:function section:.iram1")
+    Save {
+        /// Query name
+        name: String,
+        /// Filter expression
+        query: String,
+        /// What the query is for
+        #[arg(long)]
+        description: Option<String>,
+        /// Watch the query for changes to its result set
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Run a saved query
+    RunSaved {
+        /// Query name
+        name: String,
+        /// Maximum number of symbols to print
+        #[arg(long, default_value_t = 100)]
+        limit: u64,
+    },
+    /// List saved queries
+    ListSaved,
+    /// Delete a saved query
+    DeleteSaved {
+        /// Query name
+        name: String,
+    },
+    /// Watch a saved query, recording its current results as the baseline
+    Watch {
+        /// Query name
+        name: String,
+    },
+    /// Stop watching a saved query
+    Unwatch {
+        /// Query name
+        name: String,
+    },
+    /// Re-run watched queries and print what changed since the last check
+    CheckWatches {
+        /// Exit with status 1 if any watched query changed
+        #[arg(long)]
+        fail_on_change: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotActions {
+    /// Copy the database into a new snapshot of an index
+    Create {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// What the snapshot is taken before (e.g. "switch to clang 17 flags")
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// List snapshots, oldest first
+    List {
+        /// Only snapshots of this index
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Replace the database with a snapshot of an index
+    Restore {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Snapshot id (default: the newest snapshot of the index)
         #[arg(long)]
-        symbol: String,
+        snapshot: Option<String>,
+        /// Restore even if other indices changed since the snapshot, rolling them back too
+        #[arg(long)]
+        force: bool,
     },
 }
 
@@ -58,6 +388,65 @@ enum IndexActions {
         /// Path to C++ codebase
         #[arg(long)]
         path: String,
+        /// compile_commands.json providing per-file compiler flags
+        #[arg(long, value_name = "PATH")]
+        compile_commands: Option<String>,
+        /// Files parsed at once (default: number of CPUs)
+        #[arg(long, short = 'j', value_name = "N")]
+        jobs: Option<usize>,
+        /// Macro to define for the default build configuration (repeatable)
+        #[arg(long = "define", short = 'D', value_name = "NAME[=VALUE]")]
+        define: Vec<String>,
+        /// Macro to undefine for the default build configuration (repeatable)
+        #[arg(long = "undefine", short = 'U', value_name = "NAME")]
+        undefine: Vec<String>,
+        /// Store the source text of each symbol, compressed
+        #[arg(long)]
+        store_bodies: bool,
+        /// Without --compile-commands, find the tree's include directories by probing for unresolved includes
+        #[arg(long, conflicts_with = "compile_commands")]
+        infer_includes: bool,
+        /// Settings for a common kind of codebase (see `index presets`)
+        #[arg(long, value_name = "NAME")]
+        preset: Option<String>,
+    },
+    /// Create the indices listed in a manifest concurrently
+    CreateAll {
+        /// TOML manifest with an [[index]] table per index
+        #[arg(long, value_name = "PATH")]
+        manifest: std::path::PathBuf,
+        /// Files parsed at once across all indices (default: the manifest's jobs, or number of CPUs)
+        #[arg(long, short = 'j', value_name = "N")]
+        jobs: Option<usize>,
+    },
+    /// Re-index the files of an index that changed on disk
+    Update {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Also remove files deleted from disk, with their symbols and relationships
+        #[arg(long)]
+        prune: bool,
+        /// Files parsed at once (default: number of CPUs)
+        #[arg(long, short = 'j', value_name = "N")]
+        jobs: Option<usize>,
+    },
+    /// List the presets of index create
+    Presets,
+    /// Create an index from clangd's background index, parsing only what changed since
+    ImportClangd {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Path to C++ codebase
+        #[arg(long)]
+        path: String,
+        /// clangd index directory (default: <path>/.cache/clangd/index)
+        #[arg(long, value_name = "DIR")]
+        index_dir: Option<String>,
+        /// Files parsed at once (default: number of CPUs)
+        #[arg(long, short = 'j', value_name = "N")]
+        jobs: Option<usize>,
     },
     /// List existing indices
     List,
@@ -67,26 +456,232 @@ enum IndexActions {
         #[arg(long)]
         name: String,
     },
+    /// Set or remove tags on an index
+    Tag {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Tag to set as key=value (repeatable)
+        #[arg(long = "set", value_name = "KEY=VALUE")]
+        set: Vec<String>,
+        /// Tag key to remove (repeatable)
+        #[arg(long = "remove", value_name = "KEY")]
+        remove: Vec<String>,
+    },
+    /// Add, replace or remove a build configuration of an index
+    Configure {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Build configuration name (e.g. linux-debug)
+        #[arg(long)]
+        configuration: String,
+        /// Macro to define (repeatable)
+        #[arg(long = "define", short = 'D', value_name = "NAME[=VALUE]")]
+        define: Vec<String>,
+        /// Macro to undefine (repeatable)
+        #[arg(long = "undefine", short = 'U', value_name = "NAME")]
+        undefine: Vec<String>,
+        /// Remove the configuration instead
+        #[arg(long, conflicts_with_all = ["define", "undefine"])]
+        remove: bool,
+    },
+    /// Show database and per-index statistics of every database, or of the one holding --name
+    Stats {
+        /// Checkpoint and truncate the write-ahead log first
+        #[arg(long)]
+        checkpoint: bool,
+        /// Show the slowest logged repository queries
+        #[arg(long)]
+        slow_queries: bool,
+        /// Number of slow queries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Also show the hot spots of this index: fan-in, fan-out, largest classes, most included headers and busiest files
+        #[arg(long)]
+        name: Option<String>,
+        /// Entries of each hot spot ranking
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Check an index for orphaned symbols, dangling relationships and drifted counts
+    Verify {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Fix what is found, in one transaction
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Archive or delete indices according to a retention policy
+    Gc {
+        /// Collect indices not updated within this duration (e.g. 30d, 12h, 2w)
+        #[arg(long, value_name = "DURATION")]
+        max_age: Option<String>,
+        /// Keep only the newest N indices matching a name pattern (repeatable)
+        #[arg(long = "keep", value_name = "PATTERN=N")]
+        keep: Vec<String>,
+        /// Delete the oldest indices until the databases together fit in this many MB
+        #[arg(long, value_name = "MB")]
+        max_total_mb: Option<u64>,
+        /// Archive expired indices instead of deleting them
+        #[arg(long)]
+        archive: bool,
+        /// Directory archived indices are written to (default: `archive` in the storage location)
+        #[arg(long, value_name = "DIR", requires = "archive")]
+        archive_dir: Option<std::path::PathBuf>,
+        /// Show what would be collected without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write an index to a portable archive
+    Export {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// Archive to write (e.g. index.cpidx)
+        #[arg(long, value_name = "PATH")]
+        out: std::path::PathBuf,
+    },
+    /// Write an index as SCIP or LSIF for Sourcegraph and other code intelligence tools
+    ExportScip {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// File to write (e.g. index.scip or dump.lsif)
+        #[arg(long, value_name = "PATH")]
+        out: std::path::PathBuf,
+        /// scip or lsif (default: from the extension of --out, else scip)
+        #[arg(long)]
+        format: Option<String>,
+    },
+    /// Write a tags file for vim (ctags) or emacs (etags) from the stored symbols
+    ExportTags {
+        /// Index name
+        #[arg(long)]
+        name: String,
+        /// ctags or etags (default: etags for a file named TAGS, else ctags)
+        #[arg(long)]
+        format: Option<String>,
+        /// File to write (default: tags or TAGS in the indexed codebase's root)
+        #[arg(long, value_name = "PATH")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Create an index from an archive written by export
+    Import {
+        /// Archive to read
+        #[arg(value_name = "PATH")]
+        archive: std::path::PathBuf,
+        /// Name for the imported index (default: the archived name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Root of the codebase on this machine (default: the archived path)
+        #[arg(long, value_name = "PATH")]
+        base_path: Option<String>,
+    },
+    /// Write a consistent copy of every database to the backup directory
+    Backup,
+    /// Checkpoint an index before a risky re-index and roll back to it
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotActions,
+    },
+    /// Compute the symbol embeddings semantic_search ranks by, reusing those of unchanged symbols
+    Embed {
+        /// Index name
+        #[arg(long)]
+        name: String,
+    },
+    /// Upgrade or downgrade every database in place to a schema version, backing each up first
+    Migrate {
+        /// Schema version to migrate to
+        #[arg(long)]
+        to: i32,
+    },
+    /// Encrypt the existing plaintext databases in place with the configured key
+    Encrypt,
+    /// Show the audit trail of administrative operations, newest first
+    Audit {
+        /// Only operations on this index
+        #[arg(long)]
+        name: Option<String>,
+        /// Only this operation (e.g. delete_index, set_tag, restore)
+        #[arg(long)]
+        operation: Option<String>,
+        /// Only operations within this duration (e.g. 30d, 12h)
+        #[arg(long, value_name = "DURATION")]
+        since: Option<String>,
+        /// Number of entries to show
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigActions {
+    /// Set an option, e.g. `config set storage.path /mnt/big/indices`
+    Set {
+        /// Option name, with tables separated by dots
+        key: String,
+        /// TOML value, or a string
+        value: String,
+    },
 }
 
 fn main() -> Result<()> {
-    // Initialize logging
+    // Logs go to stderr: stdout carries command output and the MCP stdio transport
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
         .json()
         .init();
 
     info!("Starting C++ Index MCP Server");
 
-    let cli = Cli::parse();
+    if let Err(e) = run(Cli::parse()) {
+        // Bad input gets a plain message and exit code 2; real failures keep the full report
+        let storage_error = e.chain().find_map(|cause| cause.downcast_ref::<StorageError>());
+        if storage_error.is_some_and(StorageError::is_client_error) {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
 
+/// Runs the parsed command
+fn run(cli: Cli) -> Result<()> {
+    let db_path = cli.db_path.as_deref();
     match cli.command {
         Commands::Index { action } => {
             match action {
-                IndexActions::Create { name, path } => {
+                IndexActions::Create { name, path, compile_commands, jobs, define, undefine, store_bodies, infer_includes, preset } => {
                     info!("Creating index '{}' for path '{}'", name, path);
-                    // TODO: Implement index creation
-                    println!("Index creation not yet implemented");
+                    let spec = IndexSpec { name, path, compile_commands, define, undefine, store_bodies, infer_includes, preset };
+                    create_index_from_spec(&spec, jobs, None, db_path)?;
+                }
+                IndexActions::CreateAll { manifest, jobs } => {
+                    info!("Creating the indices of {}", manifest.display());
+                    create_all_indices(&manifest, jobs, db_path)?;
+                }
+                IndexActions::Update { name, prune, jobs } => {
+                    info!("Updating index '{}' (prune={})", name, prune);
+                    update_index(&load_config(db_path, Some(&name))?, &name, prune, jobs)?;
+                }
+                IndexActions::ImportClangd { name, path, index_dir, jobs } => {
+                    info!("Importing clangd index of '{}' as '{}'", path, name);
+                    let mut pipeline_config = PipelineConfig::default();
+                    if let Some(jobs) = jobs {
+                        pipeline_config = pipeline_config.with_jobs(jobs);
+                    }
+                    import_clangd(&load_config(db_path, Some(&name))?, &name, &path, index_dir.as_deref(), pipeline_config)?;
+                }
+                IndexActions::Presets => {
+                    for preset in presets::presets() {
+                        println!("{:<16} {}", preset.name, preset.description);
+                    }
                 }
                 IndexActions::List => {
                     info!("Listing indices");
@@ -98,6 +693,120 @@ fn main() -> Result<()> {
                     // TODO: Implement index deletion
                     println!("Index deletion not yet implemented");
                 }
+                IndexActions::Tag { name, set, remove } => {
+                    info!("Updating tags of index '{}'", name);
+                    tag_index(&load_config(db_path, Some(&name))?, &name, &set, &remove)?;
+                }
+                IndexActions::Configure { name, configuration, define, undefine, remove } => {
+                    info!("Configuring build configuration '{}' of index '{}'", configuration, name);
+                    configure_index(&load_config(db_path, Some(&name))?, &name, &configuration, &define, &undefine, remove)?;
+                }
+                IndexActions::Stats { checkpoint, slow_queries, limit, name, top } => {
+                    info!("Showing index statistics");
+                    let insights = name.as_deref().map(|name| (name, top));
+                    let databases = match &name {
+                        Some(name) => vec![load_config(db_path, Some(name))?],
+                        None => every_database(db_path)?,
+                    };
+                    for (position, config) in databases.iter().enumerate() {
+                        if position > 0 {
+                            println!();
+                        }
+                        show_stats(config, checkpoint, slow_queries.then_some(limit), insights)?;
+                    }
+                }
+                IndexActions::Verify { name, repair } => {
+                    info!("Verifying index '{}' (repair={})", name, repair);
+                    verify_index(&load_config(db_path, Some(&name))?, &name, repair)?;
+                }
+                IndexActions::Gc { max_age, keep, max_total_mb, archive, archive_dir, dry_run } => {
+                    info!("Collecting stale indices (dry_run={})", dry_run);
+                    let archive_dir = match archive_dir {
+                        Some(directory) => Some(directory),
+                        None => archive.then(|| load_config(db_path, None).map(|config| config.storage_path().join("archive"))).transpose()?,
+                    };
+                    collect_indices(&every_database(db_path)?, max_age.as_deref(), &keep, max_total_mb, archive_dir.as_deref(), dry_run)?;
+                }
+                IndexActions::Export { name, out } => {
+                    info!("Exporting index '{}' to {}", name, out.display());
+                    let repository = open_repository(&load_config(db_path, Some(&name))?)?;
+                    let archive = export_index(&repository, &name, &out)?;
+                    println!(
+                        "Exported '{}' ({} files, {} symbols, {} relationships) to {}",
+                        name,
+                        archive.contents.files.len(),
+                        archive.contents.elements.len(),
+                        archive.contents.relationships.len(),
+                        out.display()
+                    );
+                }
+                IndexActions::ExportScip { name, out, format } => {
+                    info!("Exporting index '{}' for code intelligence tools to {}", name, out.display());
+                    export_code_intel(&load_config(db_path, Some(&name))?, &name, &out, format.as_deref())?;
+                }
+                IndexActions::ExportTags { name, format, out } => {
+                    info!("Exporting index '{}' as a tags file", name);
+                    export_tags(&load_config(db_path, Some(&name))?, &name, format.as_deref(), out.as_deref())?;
+                }
+                IndexActions::Import { archive, name, base_path } => {
+                    info!("Importing index from {}", archive.display());
+                    let file = std::fs::File::open(&archive).map_err(|e| anyhow::anyhow!("Failed to open {}: {}", archive.display(), e))?;
+                    let contents = IndexArchive::read_from(std::io::BufReader::new(file))?;
+                    // The index goes to the database of the name it will have
+                    let name = name.unwrap_or_else(|| contents.index.name.clone());
+                    let repository = open_repository(&load_config(db_path, Some(&name))?)?;
+                    let index = contents.restore(&repository, Some(&name), base_path.as_deref())?;
+                    println!("Imported '{}' ({} files, {} symbols) rooted at {}", index.name, index.total_files, index.total_symbols, index.base_path);
+                }
+                IndexActions::Backup => {
+                    info!("Backing up database");
+                    for config in every_database(db_path)? {
+                        backup_database(&config)?;
+                    }
+                }
+                IndexActions::Snapshot { action } => {
+                    let name = match &action {
+                        SnapshotActions::Create { name, .. } | SnapshotActions::Restore { name, .. } => Some(name.as_str()),
+                        SnapshotActions::List { name } => name.as_deref(),
+                    };
+                    snapshot_index(&load_config(db_path, name)?, action)?;
+                }
+                IndexActions::Embed { name } => {
+                    info!("Embedding symbols of index '{}'", name);
+                    embed_index(&load_config(db_path, Some(&name))?, &name)?;
+                }
+                IndexActions::Migrate { to } => {
+                    info!("Migrating databases to schema version {}", to);
+                    for config in every_database(db_path)? {
+                        let migration = DatabaseManager::new(database_config(&config)?)?.migrate_in_place(to)?;
+                        match &migration.backup_path {
+                            Some(backup) => println!(
+                                "Migrated {} from schema version {} to {}; backed up to {}",
+                                config.database_path().display(),
+                                migration.from,
+                                migration.to,
+                                backup.display()
+                            ),
+                            None => println!("{} is already at schema version {}", config.database_path().display(), migration.to),
+                        }
+                    }
+                }
+                IndexActions::Encrypt => {
+                    info!("Encrypting databases");
+                    for config in every_database(db_path)? {
+                        encrypt_database(&config)?;
+                    }
+                }
+                IndexActions::Audit { name, operation, since, limit } => {
+                    info!("Showing audit trail");
+                    let databases = match &name {
+                        Some(name) => vec![load_config(db_path, Some(name))?],
+                        None => every_database(db_path)?,
+                    };
+                    for config in databases {
+                        show_audit(&config, name.as_deref(), operation.as_deref(), since.as_deref(), limit)?;
+                    }
+                }
             }
         }
         Commands::Menu => {
@@ -105,17 +814,1808 @@ fn main() -> Result<()> {
             // TODO: Implement interactive menu
             println!("Interactive menu not yet implemented");
         }
-        Commands::Server { stdio, index } => {
-            info!("Starting MCP server for index '{}' with stdio={}", index, stdio);
-            // TODO: Implement MCP server
-            println!("MCP server not yet implemented");
+        Commands::Server { stdio, index_databases, watch, primary, replica, read_only } => {
+            if read_only && watch {
+                return Err(StorageError::Validation("--watch re-indexes files, which a --read-only server can't".to_string()).into());
+            }
+            let config = load_config(db_path, None)?;
+            let databases = index_databases_of(&config, &index_databases)?;
+            // A replica shares the databases with the primary, which writes them
+            let registry = index_registry(&config, &databases, read_only || replica)?;
+            // Checked at startup so a typo in a cron expression doesn't wait for the task to come due
+            let scheduler = Scheduler::new(&config.schedule, chrono::Utc::now()).map_err(|e| anyhow::anyhow!("Invalid schedule: {}", e))?;
+            let timeout = Duration::from_secs(config.failover_timeout_seconds);
+            let failover = match (primary, replica) {
+                (true, _) => Some(Failover::primary(config.heartbeat_path(), timeout)),
+                (_, true) => Some(Failover::replica(config.heartbeat_path(), timeout)),
+                _ => None,
+            };
+            info!(
+                "Starting MCP server with stdio={} watch={} read_only={}; {} indices in databases of their own; {} scheduled tasks, next at {:?}; failover {}",
+                stdio,
+                watch,
+                read_only,
+                registry.index_names().len(),
+                config.schedule.len(),
+                scheduler.next_run(),
+                failover.as_ref().map_or("off", |failover| failover.role().as_str())
+            );
+            // Tool calls hold back the watchers' writes while they run
+            let priority = PriorityGate::new();
+            let mut server = build_server(&config, registry, scheduler, failover, read_only || replica)?.with_priority_gate(priority.clone());
+            if watch {
+                let (alerts, inbox) = tokio::sync::mpsc::unbounded_channel();
+                watch_indices(&config, &databases, &priority, &alerts)?;
+                server = server.with_watch_alerts(inbox);
+            }
+            tokio::runtime::Runtime::new()?.block_on(server.start())?;
+        }
+        Commands::Watch { index, debounce_ms } => {
+            info!("Watching index '{}'", index);
+            watch_index(&load_config(db_path, Some(&index))?, &index, Duration::from_millis(debounce_ms))?;
+        }
+        Commands::Query { index, symbol, symbol_type, file, limit, format, action } => match action {
+            Some(action) => {
+                info!("Managing saved queries of index '{}'", index);
+                saved_queries(&load_config(db_path, Some(&index))?, &index, action)?;
+            }
+            None => {
+                let terms: Vec<(&str, String)> = [("name", symbol), ("type", symbol_type), ("file", file)]
+                    .into_iter()
+                    .filter_map(|(field, value)| value.map(|value| (field, value)))
+                    .collect();
+                if terms.is_empty() {
+                    anyhow::bail!("Nothing to do: pass --symbol, --type, --file or a saved query command");
+                }
+                let format = ListingFormat::parse(&format)
+                    .ok_or_else(|| StorageError::Validation(format!("Unknown format '{}' (expected table, json or csv)", format)))?;
+                info!("Querying index '{}'", index);
+                query_symbols(&load_config(db_path, Some(&index))?, &index, &terms, limit, format)?;
+            }
+        },
+        Commands::Telemetry { action } => {
+            let config = load_config(db_path, None)?;
+            match action {
+                TelemetryActions::Status => telemetry_status(&config)?,
+                TelemetryActions::Send => {
+                    let Some(endpoint) = config.telemetry_endpoint.as_deref().filter(|_| config.telemetry_enabled) else {
+                        anyhow::bail!("Telemetry is disabled; set telemetry_enabled and telemetry_endpoint to send reports");
+                    };
+                    let sent = send_spool(config.telemetry_spool_path(), endpoint)?;
+                    println!("Sent {} report(s) to {}", sent, endpoint);
+                }
+            }
+        }
+        Commands::Db { action } => match action {
+            DbActions::Downgrade { to, out, name } => {
+                info!("Downgrading a copy of the database to schema version {}", to);
+                let config = load_config(db_path, name.as_deref())?;
+                DatabaseManager::new(database_config(&config)?)?.export_downgraded(&out, to)?;
+                println!("Wrote schema version {} copy to {}", to, out.display());
+            }
+        },
+        Commands::Config { action } => match action {
+            ConfigActions::Set { key, value } => {
+                let path = config::Config::file_path();
+                config::Config::set_in_file(&path, &key, &value)?;
+                println!("Set {} in {}", key, path.display());
+                let config = config::Config::load_from(&path)?;
+                if key.starts_with("storage") {
+                    println!("Indices are stored in {}", config.storage_path().display());
+                }
+            }
+        },
+        Commands::SelfUpdate { channel, check } => {
+            let config = load_config(db_path, None)?;
+            let channel = match channel {
+                Some(name) => ReleaseChannel::parse(&name)
+                    .ok_or_else(|| StorageError::Validation(format!("Unknown release channel '{}'; use stable or nightly", name)))?,
+                None => config.update_channel,
+            };
+            update_binary(&config, channel, check)?;
+        }
+        Commands::Report { action } => match action {
+            ReportActions::Changes { from, to, repo, out, json } => {
+                info!("Reporting symbol changes from {} to {} in {}", from, to, repo.display());
+                report_changes(&repo, &from, &to, out.as_deref(), json)?;
+            }
+            ReportActions::Risk { index, diff, out, fail_at } => {
+                info!("Scoring diff risk against index '{}'", index);
+                let score = report_risk(&load_config(db_path, Some(&index))?, &index, diff.as_deref(), out.as_deref())?;
+                if fail_at.is_some_and(|fail_at| score >= fail_at) {
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::WrapBuild { out, command } => {
+            info!("Capturing compiler invocations of `{}`", command.join(" "));
+            wrap_build(&out, &command)?;
+        }
+        Commands::RecordCompile { arguments } => record_compile(arguments),
+        Commands::ParseWorker => parse_worker::serve(std::io::stdin().lock(), std::io::stdout().lock())?,
+        Commands::Analyze { action } => match action {
+            AnalyzeActions::Coupling { index, out, by, format, edges } => {
+                info!("Exporting coupling of index '{}' by {}", index, by);
+                export_coupling(&load_config(db_path, Some(&index))?, &index, &out, &by, format.as_deref(), edges)?;
+            }
+            AnalyzeActions::Dsm { index, level, out, format } => {
+                info!("Exporting dependency matrix of index '{}' at level {}", index, level);
+                export_dsm(&load_config(db_path, Some(&index))?, &index, level, &out, format.as_deref())?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Runs a build with compiler shims ahead of the real compilers, then writes what they recorded
+#[cfg(unix)]
+fn wrap_build(out: &std::path::Path, command: &[String]) -> Result<()> {
+    // A fresh private directory, so no other user can plant shims in it
+    let shim_dir = tempfile::Builder::new().prefix("cpp-index-wrap-").tempdir()?;
+    run_wrapped_build(shim_dir.path(), out, command)
+}
+
+#[cfg(not(unix))]
+fn wrap_build(_out: &std::path::Path, _command: &[String]) -> Result<()> {
+    anyhow::bail!("wrap-build needs a Unix shell for its compiler shims")
+}
+
+#[cfg(unix)]
+fn run_wrapped_build(shim_dir: &std::path::Path, out: &std::path::Path, command: &[String]) -> Result<()> {
+    let recorder = std::env::current_exe()?;
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let log = shim_dir.join("invocations.jsonl");
+
+    let mut shimmed = 0;
+    for name in SHIMMED_COMPILERS {
+        if let Some(compiler) = find_executable(name, &path, shim_dir) {
+            install_shim(shim_dir, name, &recorder, &compiler)?;
+            shimmed += 1;
+        }
+    }
+    // CC and CXX may name compilers off PATH or under other names
+    let mut environment = Vec::new();
+    for (variable, shim_name) in [("CC", "cc-wrapped"), ("CXX", "cxx-wrapped")] {
+        let Some(compiler) = std::env::var_os(variable).filter(|compiler| !compiler.is_empty()) else {
+            let default = if variable == "CC" { "cc" } else { "c++" };
+            if shim_dir.join(default).is_file() {
+                environment.push((variable, shim_dir.join(default)));
+            }
+            continue;
+        };
+        let compiler = std::path::PathBuf::from(compiler);
+        let compiler = if compiler.components().count() > 1 {
+            compiler
+        } else {
+            find_executable(&compiler.to_string_lossy(), &path, shim_dir)
+                .ok_or_else(|| anyhow::anyhow!("{} names {}, which is not on PATH", variable, compiler.display()))?
+        };
+        environment.push((variable, install_shim(shim_dir, shim_name, &recorder, &compiler)?));
+        shimmed += 1;
+    }
+    if shimmed == 0 {
+        anyhow::bail!("No compiler found on PATH to wrap (looked for {})", SHIMMED_COMPILERS.join(", "));
+    }
+
+    let mut search_path = vec![shim_dir.to_path_buf()];
+    search_path.extend(std::env::split_paths(&path));
+    let status = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .envs(environment)
+        .env("PATH", std::env::join_paths(search_path)?)
+        .env(CAPTURE_LOG_ENV, &log)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", command[0], e))?;
+
+    let invocations = if log.exists() { read_invocations(&log)? } else { Vec::new() };
+    let entries = compile_commands(&invocations);
+    write_compile_commands(out, &entries)?;
+    println!(
+        "Recorded {} compiler invocations; wrote {} entries to {}",
+        invocations.len(),
+        entries.len(),
+        out.display()
+    );
+    if !status.success() {
+        anyhow::bail!("Build failed ({}); {} holds the files compiled before the failure", status, out.display());
+    }
+    println!("Index with: index create --compile-commands {}", out.display());
+    Ok(())
+}
+
+/// Appends an invocation to the log of the wrapped build; never fails the build
+fn record_compile(arguments: Vec<String>) {
+    let Some(log) = std::env::var_os(CAPTURE_LOG_ENV) else {
+        return;
+    };
+    let recorded = std::env::current_dir().and_then(|directory| {
+        record_invocation(std::path::Path::new(&log), &CapturedInvocation { directory, arguments })
+    });
+    if let Err(e) = recorded {
+        eprintln!("cpp-index-mcp: failed to record compiler invocation: {}", e);
+    }
+}
+
+/// Re-indexes files of an index as they change, until interrupted
+fn watch_index(config: &config::Config, name: &str, debounce: Duration) -> Result<()> {
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    println!("Watching {} (Ctrl-C to stop)", index.base_path);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let report = |batch: &WatchBatch, changes: &[WatchChange]| {
+            for result in &batch.results {
+                println!("{:?} {} ({} symbols)", result.action, result.file_path.display(), result.symbols_extracted);
+            }
+            for (path, error) in &batch.failures {
+                eprintln!("Failed {}: {}", path.display(), error);
+            }
+            changes.iter().for_each(print_watch_change);
+        };
+        tokio::select! {
+            result = keep_index_current(config, repository, index, debounce, PriorityGate::new(), report) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        }
+    })
+}
+
+/// Re-indexes files of `index` as they change, handing each applied batch to `report`
+///
+/// Changes are stored as they are indexed, so the index stays current,
+/// and the WAL is checkpointed once it outgrows its limit or interval.
+/// Each file waits while queries on `priority` are in flight. After a
+/// batch stored anything the watched queries are checked, and `report`
+/// also gets the ones whose results changed. Returns when the watcher
+/// stops.
+async fn keep_index_current(
+    config: &config::Config,
+    repository: Repository,
+    index: CodeIndex,
+    debounce: Duration,
+    priority: PriorityGate,
+    mut report: impl FnMut(&WatchBatch, &[WatchChange]),
+) -> Result<()> {
+    let settings = IndexSettings::load(&repository, &index)?;
+    let mut watcher = FileWatcher::new(&index.base_path, debounce)?.with_selection(settings.selection.clone());
+    let parse_worker = settings.subprocess_worker(&std::env::current_exe()?);
+    let database_config = database_config(config)?;
+    let mut scheduler = CheckpointScheduler::new(&database_config);
+    let manager = DatabaseManager::new(database_config)?;
+    let repository = Arc::new(Mutex::new(repository));
+    let index_id = index.id;
+    let mut indexer = IncrementalIndexer::new(None)
+        .and_then(|indexer| indexer.with_memory_budget(config.memory_limit_mb * 1024 * 1024, &config.spill_path()))
+        .and_then(|indexer| indexer.with_repository(Arc::clone(&repository), index, settings))
+        .map(|indexer| indexer.with_parse_worker(parse_worker).with_priority_gate(priority))
+        .map_err(|e| anyhow::anyhow!("Failed to start indexer: {}", e))?;
+    while let Some(changes) = watcher.next_changes().await {
+        let batch = apply_changes(&mut indexer, &changes).await;
+        let repository = repository.lock().map_err(|_| anyhow::anyhow!("Repository lock poisoned"))?;
+        let watch_changes = if batch.results.is_empty() {
+            Vec::new()
+        } else {
+            WatchEvaluator::new(&repository).run(&index_id).unwrap_or_else(|e| {
+                warn!("Checking watched queries failed: {}", e);
+                Vec::new()
+            })
+        };
+        report(&batch, &watch_changes);
+        if let Err(e) = scheduler.maybe_checkpoint(&manager, repository.connection()) {
+            warn!("Checkpoint failed: {}", e);
         }
-        Commands::Query { index, symbol } => {
-            info!("Querying symbol '{}' in index '{}'", symbol, index);
-            // TODO: Implement symbol query
-            println!("Symbol query not yet implemented");
+    }
+    Ok(())
+}
+
+/// Keeps every served index current on threads of their own, alongside the server
+///
+/// Indices in databases of their own are watched through those, the rest
+/// through the default database. Watchers log instead of printing, since
+/// stdout carries the MCP transport, give way to the queries on `priority`
+/// and send changed watched queries to `alerts`.
+fn watch_indices(
+    config: &config::Config,
+    databases: &std::collections::BTreeMap<String, std::path::PathBuf>,
+    priority: &PriorityGate,
+    alerts: &tokio::sync::mpsc::UnboundedSender<WatchChange>,
+) -> Result<()> {
+    let default = Repository::new(DatabaseManager::new(database_config(config)?)?.connect()?);
+    let mut watched: Vec<(String, config::Config)> = Vec::new();
+    for index in default.list_code_indices()? {
+        if !databases.contains_key(&index.name) {
+            watched.push((index.name, config.clone()));
         }
     }
+    for (name, path) in databases {
+        let mut index_config = config.clone();
+        index_config.database_path = Some(path.clone());
+        watched.push((name.clone(), index_config));
+    }
 
+    for (name, config) in watched {
+        let priority = priority.clone();
+        let alerts = alerts.clone();
+        std::thread::spawn(move || {
+            let watch = || -> Result<()> {
+                let repository = Repository::new(DatabaseManager::new(database_config(&config)?)?.connect()?);
+                let repository = with_slow_query_log(&config, repository.with_audit_actor(AuditActor::new("watcher")));
+                let Some(index) = repository.get_code_index_by_name(&name)? else { return Ok(()) };
+                info!("Watching '{}' at {}", name, index.base_path);
+                let report = |batch: &WatchBatch, changes: &[WatchChange]| {
+                    for result in &batch.results {
+                        info!("Watcher {:?} {} in '{}' ({} symbols)", result.action, result.file_path.display(), name, result.symbols_extracted);
+                    }
+                    for (path, error) in &batch.failures {
+                        warn!("Watcher failed {} in '{}': {}", path.display(), name, error);
+                    }
+                    for change in changes {
+                        // Nobody is listening once the server stopped
+                        let _ = alerts.send(change.clone());
+                    }
+                };
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                runtime.block_on(keep_index_current(&config, repository, index, DEFAULT_DEBOUNCE, priority, report))
+            };
+            if let Err(e) = watch() {
+                warn!("Stopped watching '{}': {}", name, e);
+            }
+        });
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Builds the MCP server over the default database and the indices in `registry`
+///
+/// The default database is served through a connection pool: queries on
+/// reader connections, index updates on the writer. With `read_only` it is
+/// opened read-only next to a writer elsewhere; one failing its integrity
+/// check is served read-only as it is.
+fn build_server(
+    config: &config::Config,
+    registry: RepositoryRegistry,
+    scheduler: Scheduler,
+    failover: Option<Failover>,
+    read_only: bool,
+) -> Result<McpServer> {
+    let database_config = database_config(config)?;
+    let mut server = McpServer::new()?.with_registry(registry).with_scheduler(scheduler);
+    if let Some(failover) = failover {
+        server = server.with_failover(failover);
+    }
+    if read_only {
+        let manager = DatabaseManager::new(database_config.with_read_only(true))?;
+        let repository = with_slow_query_log(config, Repository::new(manager.connect()?));
+        server = server
+            .with_repository(repository)
+            .with_database_manager(Arc::new(manager))
+            .with_read_only_mode(ReadOnlyMode::Shared);
+    } else {
+        match DatabaseRecovery::new(database_config.clone()).open_for_server()? {
+            ServerStorage::Degraded(repository) => {
+                server = server.with_repository(with_slow_query_log(config, repository)).with_read_only_mode(ReadOnlyMode::Degraded);
+            }
+            ServerStorage::Pooled(pool) => {
+                let repository = with_slow_query_log(config, Repository::new(pool.writer()?));
+                server = server
+                    .with_repository(repository)
+                    .with_connection_pool(pool)
+                    .with_database_manager(Arc::new(DatabaseManager::new(database_config)?));
+            }
+        }
+    }
+    if config.telemetry_enabled {
+        server = server.with_telemetry(Telemetry::new(config.telemetry_spool_path()));
+    }
+    if let Some(backend) = config.embeddings.backend() {
+        server = server.with_embeddings(open_embedding_store(config)?, backend);
+    }
+    if config.adaptive_depth {
+        let planner = DepthPlanner::new()
+            .with_promote_hits(config.adaptive_depth_promote_hits)
+            .with_window_days(config.adaptive_depth_window_days);
+        server = server.with_adaptive_depth(planner);
+    }
+    if let Some(dedup) = config.vendored_dedup() {
+        server = server.with_vendored_dedup(dedup);
+    }
+    Ok(server
+        .with_stale_check(config.stale_check)
+        .with_stale_reindex_limit(config.stale_reindex_max_kb.saturating_mul(1024))
+        .with_parse_worker(std::env::current_exe()?))
+}
+
+/// Creates the index a spec describes, with its project file, preset and compilation database
+///
+/// With `shared`, the build parses within a multi-index build's worker
+/// budget and reports its progress there instead of printing it.
+fn create_index_from_spec(spec: &IndexSpec, jobs: Option<usize>, shared: Option<(&MultiBuild, usize)>, db_path: Option<&std::path::Path>) -> Result<PipelineReport> {
+    let mut config = config::Config::load_for_project(std::path::Path::new(&spec.path))?.for_index(&spec.name);
+    if let Some(path) = db_path {
+        config.database_path = Some(path.to_path_buf());
+    }
+    if config.project.is_some() {
+        println!("Using {}", std::path::Path::new(&spec.path).join(config::PROJECT_CONFIG_FILE).display());
+    }
+    // The command line wins over the project file
+    let compile_commands = spec
+        .compile_commands
+        .as_ref()
+        .map(std::path::PathBuf::from)
+        .or_else(|| config.project.as_ref().and_then(|project| project.compile_commands(std::path::Path::new(&spec.path))));
+    let preset = match &spec.preset {
+        Some(preset) => Some(presets::preset(preset).ok_or_else(|| {
+            let names: Vec<&str> = presets::presets().iter().map(|preset| preset.name).collect();
+            StorageError::Validation(format!("Unknown preset '{}' (expected one of {})", preset, names.join(", ")))
+        })?),
+        None => None,
+    };
+    let mut pipeline_config = PipelineConfig::default();
+    if let Some(jobs) = jobs {
+        pipeline_config = pipeline_config.with_jobs(jobs);
+    }
+    let detail_policy = preset.as_ref().map(|preset| preset.detail_policy).unwrap_or_default();
+    pipeline_config = pipeline_config.with_detail_policy(detail_policy.with_bodies(detail_policy.bodies || spec.store_bodies));
+    // Definitions on the command line come last, overriding the preset's
+    let define = preset.iter().flat_map(|preset| preset.defines()).chain(spec.define.iter().cloned()).collect();
+    let options = CreateOptions { define, undefine: spec.undefine.clone(), infer_includes: spec.infer_includes, preset, compile_commands };
+    create_index(&config, &spec.name, &spec.path, pipeline_config, &options, shared)
+}
+
+/// Creates the indices of a manifest at once, sharing parse workers and header parses
+fn create_all_indices(manifest_path: &std::path::Path, jobs: Option<usize>, db_path: Option<&std::path::Path>) -> Result<()> {
+    let manifest = BuildManifest::load(manifest_path).map_err(|e| anyhow::anyhow!("Invalid manifest: {}", e))?;
+    let workers = jobs.or(manifest.jobs).unwrap_or_else(|| PipelineConfig::default().jobs());
+    let build = MultiBuild::new(workers, manifest.indices.iter().map(|spec| spec.name.clone()));
+    println!("Creating {} indices with {} jobs", manifest.indices.len(), workers);
+
+    let started = std::time::Instant::now();
+    let (finished, progress_stop) = std::sync::mpsc::channel::<()>();
+    let results: Vec<Result<PipelineReport>> = std::thread::scope(|scope| {
+        let build = &build;
+        scope.spawn(move || {
+            // A line every few seconds, while any build is still running
+            while let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = progress_stop.recv_timeout(Duration::from_secs(5)) {
+                eprintln!("{}", build.status_line());
+            }
+        });
+        let handles: Vec<_> = manifest
+            .indices
+            .iter()
+            .enumerate()
+            // Every index may use the whole budget; the budget, not the pipelines, limits parsing
+            .map(|(slot, spec)| {
+                scope.spawn(move || {
+                    let result = create_index_from_spec(spec, Some(workers), Some((build, slot)), db_path);
+                    build.finish(slot, result.is_ok());
+                    result
+                })
+            })
+            .collect();
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow::anyhow!("Index build panicked"))))
+            .collect();
+        drop(finished);
+        results
+    });
+
+    println!("{:<24} {:>8} {:>10} {:>8} {:>9}  STATUS", "INDEX", "FILES", "SYMBOLS", "FAILED", "SECONDS");
+    let mut failed = 0;
+    for (spec, result) in manifest.indices.iter().zip(&results) {
+        match result {
+            Ok(report) => println!(
+                "{:<24} {:>8} {:>10} {:>8} {:>9.1}  ok",
+                spec.name,
+                report.files_indexed,
+                report.symbols_stored,
+                report.failures.len(),
+                report.elapsed.as_secs_f64()
+            ),
+            Err(e) => {
+                failed += 1;
+                println!("{:<24} {:>8} {:>10} {:>8} {:>9}  {}", spec.name, "-", "-", "-", "-", e);
+            }
+        }
+    }
+    let (reused, parsed) = build.header_stats();
+    println!("Parsed {} headers once and reused them {} times; finished in {:.1}s", parsed, reused, started.elapsed().as_secs_f64());
+    if failed > 0 {
+        anyhow::bail!("{} of {} indices failed", failed, manifest.indices.len());
+    }
+    Ok(())
+}
+
+/// Options of `index create` beyond the pipeline settings
+struct CreateOptions {
+    define: Vec<String>,
+    undefine: Vec<String>,
+    infer_includes: bool,
+    preset: Option<IndexPreset>,
+    /// compile_commands.json to parse with
+    compile_commands: Option<std::path::PathBuf>,
+}
+
+/// Creates an index and parses the codebase at `path` into it on a pool of parser threads
+///
+/// Files are walked with the default walk rules. The rules and how files
+/// are parsed are saved with the index, so `index update` and the server
+/// list and parse them the same way.
+fn create_index(
+    config: &config::Config,
+    name: &str,
+    path: &str,
+    pipeline_config: PipelineConfig,
+    options: &CreateOptions,
+    shared: Option<(&MultiBuild, usize)>,
+) -> Result<PipelineReport> {
+    let base_path = std::fs::canonicalize(path)?;
+    if !base_path.is_dir() {
+        anyhow::bail!("Not a directory: {}", path);
+    }
+    let repository = open_repository(config)?;
+    if repository.get_code_index_by_name(name)?.is_some() {
+        anyhow::bail!("Index '{}' already exists", name);
+    }
+
+    let mut index = CodeIndex::new(name.to_string(), base_path.to_string_lossy().to_string());
+    // Validated before the index exists, so a typo doesn't leave an empty index behind
+    let configuration = if options.define.is_empty() && options.undefine.is_empty() {
+        None
+    } else {
+        Some(build_configuration(&index, DEFAULT_CONFIGURATION_NAME, &options.define, &options.undefine)?)
+    };
+    let macro_flags: Vec<String> = configuration.iter().flat_map(|configuration| configuration.compile_flags()).collect();
+    // Saved absolute, as later updates may run from anywhere
+    let compile_commands = match &options.compile_commands {
+        Some(path) => Some(std::fs::canonicalize(path).map_err(|e| anyhow::anyhow!("Invalid compilation database {}: {}", path.display(), e))?),
+        None => None,
+    };
+    let project = config.project.clone().unwrap_or_default();
+    let mut rules = default_rules(index.id);
+    let walk_filter = WalkFilter::from_rules(&rules);
+    let mut settings = IndexSettings::new(&base_path, project, options.preset.clone(), walk_filter, macro_flags, compile_commands.clone())?;
+    if let (Some(database), Some(compile_commands)) = (&settings.database, &compile_commands) {
+        println!("Loaded {} compile commands from {}", database.len(), compile_commands.display());
+    }
+    let files = settings.source_files(&base_path)?;
+    let include_flags = if options.infer_includes && settings.database.is_none() {
+        let probe_flags = settings.compile_flags.clone().unwrap_or_else(ClangParser::default_flags);
+        let mut probe = ClangProbe::new(probe_flags);
+        let inference = infer_include_dirs(&base_path, &files, &mut probe);
+        for dir in &inference.include_dirs {
+            println!("Inferred include directory {}", dir.display());
+        }
+        println!(
+            "Inferred {} include directories in {} rounds; {} includes unresolved",
+            inference.include_dirs.len(),
+            inference.rounds,
+            inference.unresolved.len()
+        );
+        inference.flags()
+    } else {
+        Vec::new()
+    };
+    settings = settings.with_include_flags(include_flags.clone());
+    index = repository.create_code_index(index)?;
+    rules.index_id = index.id;
+    repository.save_walk_rules(&rules)?;
+    if let Some(preset) = &options.preset {
+        repository.set_index_tag(&IndexTag::new(index.id, PRESET_TAG.to_string(), preset.name.to_string()))?;
+    }
+    if let Some(configuration) = configuration {
+        repository.save_build_configuration(configuration)?;
+    }
+    let compile_commands = compile_commands.map(|path| path.to_string_lossy().to_string());
+    repository.save_parse_settings(&ParseSettings::new(index.id, compile_commands, include_flags))?;
+    let files = skip_vendored(config, &repository, &index, files)?;
+
+    let store_bodies = pipeline_config.detail_policy().bodies;
+    let headers = HeaderCache::new();
+    let program = std::env::current_exe()?;
+    let new_worker = || settings.worker(&headers, Some(&program));
+    let pipeline = IndexingPipeline::new(pipeline_config);
+    let run = match shared {
+        Some((build, slot)) => {
+            build.start(slot, files.len());
+            let fingerprint = settings.fingerprint();
+            pipeline.run(&repository, &index, files, || new_worker().map(|worker| build.extractor(slot, &fingerprint, worker)))
+        }
+        None => {
+            println!("Indexing {} files with {} jobs", files.len(), pipeline_config.jobs());
+            pipeline.run(&repository, &index, files, new_worker)
+        }
+    };
+    let report = match run {
+        Ok(report) => report,
+        Err(e) => {
+            repository.update_code_index_state(&index.id, IndexState::Failed)?;
+            return Err(e.into());
+        }
+    };
+
+    index.update_stats(report.files_indexed as u32, report.symbols_stored as u32);
+    repository.update_code_index(&index)?;
+    repository.update_code_index_state(&index.id, IndexState::Active)?;
+    for (file, error) in &report.failures {
+        eprintln!("Failed {}: {}", file, error);
+    }
+    if shared.is_none() {
+        println!(
+            "Indexed {} files ({} symbols, {} calls, {} callback references) in {:.1}s; {} failed",
+            report.files_indexed,
+            report.symbols_stored,
+            report.calls_stored,
+            report.callbacks_stored,
+            report.elapsed.as_secs_f64(),
+            report.failures.len()
+        );
+    }
+    if store_bodies {
+        let bodies = repository.get_symbol_body_stats(&index.id)?;
+        println!(
+            "Stored {} symbol bodies: {} bytes compressed to {} ({:.1}x)",
+            bodies.count,
+            bodies.uncompressed_bytes,
+            bodies.compressed_bytes,
+            bodies.compression_ratio()
+        );
+    }
+    Ok(report)
+}
+
+/// Leaves out the files of vendored copies when the configuration dedupes them, naming each copy skipped
+fn skip_vendored(config: &config::Config, repository: &Repository, index: &CodeIndex, files: Vec<String>) -> Result<Vec<String>> {
+    let Some(dedup) = config.vendored_dedup() else { return Ok(files) };
+    let (files, duplicates) = skip_vendored_copies(repository, index, files, &dedup)?;
+    for duplicate in &duplicates {
+        for alias in &duplicate.aliases {
+            println!("Skipping {}: copy of {} ({} files)", alias.display(), duplicate.canonical.display(), duplicate.file_count);
+        }
+    }
+    Ok(files)
+}
+
+/// Re-indexes the new and changed files of an index and, with `prune`, drops the deleted ones
+///
+/// Files are listed and parsed with the settings saved for the index, the
+/// way `index create` listed and parsed them. Pruning removes every file
+/// gone from disk in one transaction; without it they are only counted.
+/// Indexed files the walk no longer selects are kept.
+fn update_index(config: &config::Config, name: &str, prune: bool, jobs: Option<usize>) -> Result<()> {
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let base_path = std::path::Path::new(&index.base_path);
+    let settings = IndexSettings::load(&repository, &index)?;
+
+    // Moved files keep their symbols' ids and notes instead of being pruned and added again
+    let (missing, indexed) = missing_files(&repository, &index)?;
+    if !missing.is_empty() {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let policy = settings.selection.detail_policy();
+        for detected in runtime.block_on(detect_moves(&index, &settings, &missing, &indexed, &policy))? {
+            println!("Moved {} to {}", detected.file_move.from, detected.file_move.to);
+            store_moved(&repository, &index, detected)?;
+        }
+    }
+
+    let files = skip_vendored(config, &repository, &index, settings.source_files(base_path)?)?;
+    let present = files_to_keep(&repository, &index, &files)?;
+
+    if prune {
+        let (pruned, removed) = repository.prune_missing_files(&index.id, &present)?;
+        for file in &pruned {
+            println!("Pruned {}", file);
+        }
+        if !pruned.is_empty() {
+            repository.record_audit(
+                &AuditEntry::new(
+                    AuditOperation::UpdateIndex,
+                    &AuditActor::local_user(),
+                    serde_json::json!({"pruned_files": pruned.len(), "pruned_symbols": removed}),
+                )
+                .with_index(index.id, name),
+            )?;
+        }
+        println!("Pruned {} files deleted from disk ({} symbols)", pruned.len(), removed);
+    } else {
+        let missing = repository.list_file_metadata(&index.id)?.iter().filter(|metadata| !present.contains(&metadata.file_path)).count();
+        if missing > 0 {
+            println!("{} indexed files are no longer on disk; run with --prune to remove them", missing);
+        }
+    }
+
+    let mut changed = Vec::new();
+    for file in files {
+        if check_file(&repository, &index, &file)?.status != Freshness::Fresh {
+            changed.push(file);
+        }
+    }
+
+    // Depths a server learned with adaptive_depth apply to updates from the command line too
+    let mut pipeline_config = PipelineConfig::default().with_adaptive_depth(config.adaptive_depth);
+    if let Some(jobs) = jobs {
+        pipeline_config = pipeline_config.with_jobs(jobs);
+    }
+    let headers = HeaderCache::new();
+    let program = std::env::current_exe()?;
+    let new_worker = || settings.worker(&headers, Some(&program));
+
+    println!("Re-indexing {} new or changed files with {} jobs", changed.len(), pipeline_config.jobs());
+    repository.update_code_index_state(&index.id, IndexState::Updating)?;
+    let report = match IndexingPipeline::new(pipeline_config).run(&repository, &index, changed, new_worker) {
+        Ok(report) => report,
+        Err(e) => {
+            repository.update_code_index_state(&index.id, IndexState::Failed)?;
+            return Err(e.into());
+        }
+    };
+    repository.recount_index_totals(&index.id)?;
+    repository.update_code_index_state(&index.id, IndexState::Active)?;
+    for (file, error) in &report.failures {
+        eprintln!("Failed {}: {}", file, error);
+    }
+    let index = repository.get_code_index(&index.id)?.ok_or_else(|| StorageError::not_found("Index", name))?;
+    println!(
+        "Indexed {} files ({} symbols) in {:.1}s; {} failed. Index '{}' now has {} files, {} symbols",
+        report.files_indexed,
+        report.symbols_stored,
+        report.elapsed.as_secs_f64(),
+        report.failures.len(),
+        name,
+        index.total_files,
+        index.total_symbols
+    );
+    Ok(())
+}
+
+/// Creates an index from clangd's background index shards, then parses the files they don't cover
+fn import_clangd(config: &config::Config, name: &str, path: &str, index_dir: Option<&str>, pipeline_config: PipelineConfig) -> Result<()> {
+    let base_path = std::fs::canonicalize(path)?;
+    if !base_path.is_dir() {
+        anyhow::bail!("Not a directory: {}", path);
+    }
+    let index_dir = match index_dir {
+        Some(index_dir) => std::path::PathBuf::from(index_dir),
+        None => base_path.join(CLANGD_INDEX_DIR),
+    };
+    let repository = open_repository(config)?;
+
+    let clangd = ClangdIndex::load_dir(&index_dir)?;
+    let import = clangd.import(&repository, name, &base_path)?;
+    println!(
+        "Imported {} symbols and {} relationships of {} files from {} clangd shards; skipped {} symbols",
+        import.symbols,
+        import.relationships,
+        import.files_imported,
+        clangd.shard_count(),
+        import.skipped_symbols
+    );
+
+    let mut index = import.index;
+    if import.delta_files.is_empty() {
+        return Ok(());
+    }
+    println!("Indexing {} changed or unindexed files with {} jobs", import.delta_files.len(), pipeline_config.jobs());
+    let report = IndexingPipeline::new(pipeline_config).run(&repository, &index, import.delta_files, || ParserWorker::new(None, None))?;
+    index.update_stats(
+        (import.files_imported + report.files_indexed) as u32,
+        (import.symbols + report.symbols_stored) as u32,
+    );
+    repository.update_code_index(&index)?;
+    for (file, error) in &report.failures {
+        eprintln!("Failed {}: {}", file, error);
+    }
+    println!(
+        "Indexed {} files ({} symbols) in {:.1}s; {} failed",
+        report.files_indexed,
+        report.symbols_stored,
+        report.elapsed.as_secs_f64(),
+        report.failures.len()
+    );
+    Ok(())
+}
+
+/// Format given by --format, or else implied by the output file, if it is one of `supported`
+fn export_format(format: Option<&str>, out: &std::path::Path, supported: &[ExportFormat]) -> Result<ExportFormat> {
+    let expected = supported.iter().map(|format| format.as_str()).collect::<Vec<_>>().join(" or ");
+    let format = match format {
+        Some(format) => ExportFormat::parse(format)
+            .filter(|format| supported.contains(format))
+            .ok_or_else(|| StorageError::Validation(format!("Unknown format '{}' (expected {})", format, expected)))?,
+        None => ExportFormat::from_path(out).filter(|format| supported.contains(format)).ok_or_else(|| {
+            StorageError::Validation(format!("Cannot tell the format of {}; pass --format {}", out.display(), expected))
+        })?,
+    };
+    Ok(format)
+}
+
+/// Writes the coupling report of an index as CSV or JSON
+fn export_coupling(config: &config::Config, name: &str, out: &std::path::Path, by: &str, format: Option<&str>, edges: bool) -> Result<()> {
+    let granularity = CouplingGranularity::parse(by)
+        .ok_or_else(|| StorageError::Validation(format!("Unknown granularity '{}' (expected file or directory)", by)))?;
+    let format = export_format(format, out, &[ExportFormat::Csv, ExportFormat::Json])?;
+
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let report = CouplingReport::analyze(&repository, &index.id, granularity)?;
+
+    let contents = match (format, edges) {
+        (ExportFormat::Json, _) => report.to_json(),
+        (ExportFormat::Csv, false) => report.units_csv(),
+        (ExportFormat::Csv, true) => report.edges_csv(),
+        (ExportFormat::Html, _) => return Err(StorageError::Validation("Coupling reports have no HTML form".to_string()).into()),
+    };
+    std::fs::write(out, contents)?;
+    println!("Wrote {} units and {} coupled pairs to {}", report.units.len(), report.edges.len(), out.display());
+    Ok(())
+}
+
+/// Writes an index's symbols, definitions, references and hover text as SCIP or LSIF
+///
+/// Reference columns are read from the source under the index's base path;
+/// references in files that aren't checked out there are left out.
+fn export_code_intel(config: &config::Config, name: &str, out: &std::path::Path, format: Option<&str>) -> Result<()> {
+    let format = match format {
+        Some(format) => CodeIntelFormat::parse(format)
+            .ok_or_else(|| StorageError::Validation(format!("Unknown format '{}' (expected scip or lsif)", format)))?,
+        None => CodeIntelFormat::from_path(out).unwrap_or(CodeIntelFormat::Scip),
+    };
+
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let elements = repository.query_code_elements(
+        &CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+            .order_by_asc(ElementColumn::Id),
+    )?;
+    let relationships = repository.list_index_relationships(&index.id)?;
+
+    let mut source = SourceLines::new(&index.base_path);
+    let export = CodeIntelIndex::build(&index, &elements, &relationships, |file_path, line_number, symbol_name| {
+        find_word(source.line(file_path, line_number)?, symbol_name).map(|column| column as u32)
+    });
+    match format {
+        CodeIntelFormat::Scip => std::fs::write(out, export.to_scip(cpp_index_mcp::NAME, cpp_index_mcp::VERSION))?,
+        CodeIntelFormat::Lsif => std::fs::write(
+            out,
+            export.to_lsif(cpp_index_mcp::NAME, cpp_index_mcp::VERSION, |file_path, line_number| {
+                source.line(file_path, line_number).map(str::to_string)
+            }),
+        )?,
+    }
+
+    println!("Wrote {} documents and {} symbols to {}", export.documents.len(), export.symbol_count(), out.display());
+    if export.unlocated_references > 0 {
+        eprintln!("{} references left out: their source lines under {} couldn't be read", export.unlocated_references, index.base_path);
+    }
+    Ok(())
+}
+
+/// Writes a ctags or etags file of an index's symbols
+///
+/// Paths in the file are relative when it is written to the codebase's
+/// root, where editors look for it, and absolute otherwise.
+fn export_tags(config: &config::Config, name: &str, format: Option<&str>, out: Option<&std::path::Path>) -> Result<()> {
+    let format = match format {
+        Some(format) => TagsFormat::parse(format)
+            .ok_or_else(|| StorageError::Validation(format!("Unknown format '{}' (expected ctags or etags)", format)))?,
+        None => out.and_then(TagsFormat::from_path).unwrap_or(TagsFormat::Ctags),
+    };
+
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let elements = repository.query_code_elements(
+        &CodeElementQuery::new()
+            .filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()))
+            .order_by_asc(ElementColumn::Id),
+    )?;
+
+    let root = std::path::Path::new(&index.base_path);
+    let out = out.map_or_else(|| root.join(format.default_file_name()), std::path::Path::to_path_buf);
+    let directory = out.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+    let in_root = matches!((directory.canonicalize(), root.canonicalize()), (Ok(directory), Ok(root)) if directory == root);
+    let tags = TagsFile::build(
+        format,
+        &elements,
+        |file_path| if in_root { file_path.to_string() } else { root.join(file_path).to_string_lossy().to_string() },
+        |file_path| std::fs::read_to_string(root.join(file_path)).ok(),
+    );
+    std::fs::write(&out, &tags.contents)?;
+
+    println!("Wrote {} tags of {} files to {}", tags.tag_count, tags.file_count, out.display());
+    if !tags.unreadable_files.is_empty() {
+        eprintln!(
+            "{} files under {} couldn't be read; their tags point at line numbers",
+            tags.unreadable_files.len(),
+            index.base_path
+        );
+    }
+    Ok(())
+}
+
+/// Prints or writes the public symbol changes between two revisions
+fn report_changes(repository: &std::path::Path, from: &str, to: &str, out: Option<&std::path::Path>, json: bool) -> Result<()> {
+    let mut extractor = SymbolExtractor::new(None).map_err(|e| anyhow::anyhow!("Failed to start parser: {}", e))?;
+    let report = ChangeReport::between_revisions(repository, from, to, &mut extractor)?;
+    for (file, error) in &report.failures {
+        eprintln!("Failed {}: {}", file, error);
+    }
+
+    let contents = if json { report.to_json() } else { report.to_markdown() };
+    match out {
+        Some(out) => {
+            std::fs::write(out, contents)?;
+            println!("Wrote {} changes in {} modules to {}", report.len(), report.modules.len(), out.display());
+        }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+/// Prints or writes the risk report of a diff, returning its score
+fn report_risk(config: &config::Config, name: &str, diff: Option<&std::path::Path>, out: Option<&std::path::Path>) -> Result<u32> {
+    let diff = match diff {
+        Some(path) if path != std::path::Path::new("-") => std::fs::read_to_string(path)?,
+        _ => std::io::read_to_string(std::io::stdin())?,
+    };
+    let hunks = parse_unified_diff(&diff);
+    if hunks.is_empty() {
+        anyhow::bail!("No hunks found in diff");
+    }
+
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let report = RiskReport::analyze(&repository, &index, &hunks)?;
+
+    match out {
+        Some(out) => {
+            std::fs::write(out, report.to_json())?;
+            println!("Risk {} ({:?}) for {} symbols in {} files; wrote {}", report.score, report.level, report.symbols.len(), report.files.len(), out.display());
+            for reason in &report.reasons {
+                println!("  - {}", reason);
+            }
+        }
+        None => println!("{}", report.to_json()),
+    }
+    Ok(report.score)
+}
+
+/// Writes the directory dependency matrix of an index as JSON or HTML
+fn export_dsm(config: &config::Config, name: &str, level: usize, out: &std::path::Path, format: Option<&str>) -> Result<()> {
+    if level == 0 {
+        return Err(StorageError::Validation("--level must be at least 1".to_string()).into());
+    }
+    let format = export_format(format, out, &[ExportFormat::Json, ExportFormat::Html])?;
+
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let matrix = DependencyMatrix::analyze(&repository, &index.id, level)?;
+
+    let contents = match format {
+        ExportFormat::Json => matrix.to_json(),
+        ExportFormat::Html => matrix.to_html(),
+        ExportFormat::Csv => return Err(StorageError::Validation("The matrix has no CSV form".to_string()).into()),
+    };
+    std::fs::write(out, contents)?;
+    println!("Wrote {} directories to {}", matrix.directories.len(), out.display());
+    for cycle in &matrix.cycles {
+        println!("Cycle: {}", cycle.join(" <-> "));
+    }
+    Ok(())
+}
+
+/// Checks the release channel for a newer version and, unless only checking, installs it
+fn update_binary(config: &config::Config, channel: ReleaseChannel, check: bool) -> Result<()> {
+    let asset_name = self_update::platform_asset_name();
+    let releases = self_update::fetch_releases(self_update::RELEASE_REPOSITORY)?;
+    let Some(release) = self_update::select_update(&releases, channel, cpp_index_mcp::VERSION, &asset_name) else {
+        println!("cpp-index-mcp {} is the newest {} release", cpp_index_mcp::VERSION, channel.as_str());
+        return Ok(());
+    };
+    println!("Update available: {} -> {} ({})", cpp_index_mcp::VERSION, release.tag_name, channel.as_str());
+    if check {
+        return Ok(());
+    }
+
+    let Some(public_key) = config.update_public_key.as_deref() else {
+        anyhow::bail!("No release signing key configured; set update_public_key to the project's minisign public key");
+    };
+    let executable = std::env::current_exe()?.canonicalize()?;
+    self_update::install_release(release, public_key, &executable)?;
+    println!("Installed {} at {}", release.tag_name, executable.display());
+    Ok(())
+}
+
+/// Prints the telemetry settings and totals of the reports waiting in the spool
+fn telemetry_status(config: &config::Config) -> Result<()> {
+    let spool_path = config.telemetry_spool_path();
+    if config.telemetry_enabled {
+        println!("Telemetry: enabled");
+    } else {
+        println!("Telemetry: disabled (opt in with telemetry_enabled = true)");
+    }
+    println!("Endpoint: {}", config.telemetry_endpoint.as_deref().unwrap_or("(none)"));
+    println!("Spool: {}", spool_path.display());
+
+    let reports = read_spool(&spool_path)?;
+    println!("Pending reports: {}", reports.len());
+    let mut tool_calls: std::collections::BTreeMap<&str, u64> = std::collections::BTreeMap::new();
+    let mut error_kinds: std::collections::BTreeMap<&str, u64> = std::collections::BTreeMap::new();
+    for report in &reports {
+        for (tool, count) in &report.tool_calls {
+            *tool_calls.entry(tool).or_default() += count;
+        }
+        for (kind, count) in &report.error_kinds {
+            *error_kinds.entry(kind).or_default() += count;
+        }
+    }
+    for (tool, count) in tool_calls {
+        println!("  {:<32} {}", tool, count);
+    }
+    if !error_kinds.is_empty() {
+        println!("Errors:");
+        for (kind, count) in error_kinds {
+            println!("  {:<32} {}", kind, count);
+        }
+    }
+    Ok(())
+}
+
+/// Configuration for a command on the index `name`, or on the shared database without one
+///
+/// `--db-path` wins over both.
+fn load_config(db_path: Option<&std::path::Path>, name: Option<&str>) -> Result<config::Config> {
+    let mut config = config::Config::load()?;
+    if let Some(name) = name {
+        config = config.for_index(name);
+    }
+    if let Some(path) = db_path {
+        config.database_path = Some(path.to_path_buf());
+    }
+    Ok(config)
+}
+
+/// Configurations of every database for commands that name no index, failing if there is none
+fn every_database(db_path: Option<&std::path::Path>) -> Result<Vec<config::Config>> {
+    let config = load_config(db_path, None)?;
+    let databases = config.databases()?;
+    if databases.is_empty() {
+        anyhow::bail!("No index databases in {}", config.storage_path().display());
+    }
+    Ok(databases)
+}
+
+/// Builds the storage configuration from the application configuration
+fn database_config(config: &config::Config) -> Result<DatabaseConfig> {
+    let database_config = DatabaseConfig::new(config.database_path())
+        .with_wal_checkpoint_interval(config.wal_checkpoint_interval_seconds)
+        .with_wal_size_limit_mb(config.wal_size_limit_mb);
+
+    if !config.encrypt_database {
+        return Ok(database_config);
+    }
+    Ok(database_config.with_encryption_key(encryption_key(config)?))
+}
+
+/// Indices served from databases of their own: those in the storage location, the configured ones, then `NAME=PATH` overrides
+fn index_databases_of(config: &config::Config, index_databases: &[String]) -> Result<std::collections::BTreeMap<String, std::path::PathBuf>> {
+    let mut databases = match config.database_path {
+        // Every index is in the configured database
+        Some(_) => std::collections::BTreeMap::new(),
+        None => config.stored_index_databases()?,
+    };
+    databases.extend(config.index_databases.clone());
+    for assignment in index_databases {
+        let (name, path) = assignment
+            .split_once('=')
+            .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid index database {}: expected NAME=PATH", assignment))?;
+        databases.insert(name.to_string(), path.into());
+    }
+    Ok(databases)
+}
+
+/// Registry serving `databases`, each opened with the settings of the default one, read-only with `read_only`
+fn index_registry(config: &config::Config, databases: &std::collections::BTreeMap<String, std::path::PathBuf>, read_only: bool) -> Result<RepositoryRegistry> {
+    let database_config = database_config(config)?.with_read_only(read_only);
+    let mut registry = RepositoryRegistry::new();
+    for (name, path) in databases {
+        let mut index_config = database_config.clone();
+        index_config.database_path = path.clone();
+        registry.register(name.clone(), index_config);
+    }
+    Ok(registry)
+}
+
+/// Reads the database key from CPP_INDEX_DB_KEY or the configured key command
+fn encryption_key(config: &config::Config) -> Result<EncryptionKey> {
+    if let Some(key) = EncryptionKey::from_env().map_err(anyhow::Error::msg)? {
+        return Ok(key);
+    }
+    match &config.encryption_key_command {
+        Some(command) => EncryptionKey::from_command(command).map_err(anyhow::Error::msg),
+        None => anyhow::bail!("Database encryption is enabled but no key is set: export {} or configure encryption_key_command", KEY_ENV_VAR),
+    }
+}
+
+/// Writes a backup that recovery can restore, recording it in the audit trail
+fn backup_database(config: &config::Config) -> Result<()> {
+    let repository = open_repository(config)?;
+    let path = DatabaseRecovery::new(database_config(config)?).create_backup(repository.connection())?;
+    repository.record_audit(&AuditEntry::new(
+        AuditOperation::Backup,
+        &AuditActor::local_user(),
+        serde_json::json!({"backup_path": path}),
+    ))?;
+
+    println!("Backed up to {}", path.display());
+    Ok(())
+}
+
+/// Embeds the symbols of an index into the sidecar semantic_search reads
+fn embed_index(config: &config::Config, name: &str) -> Result<()> {
+    let backend = config.embeddings.backend().ok_or_else(|| {
+        StorageError::Validation("Semantic search is disabled; configure an embeddings backend first".to_string())
+    })?;
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let mut store = open_embedding_store(config)?;
+    let refresh = store.refresh(&repository, &index, backend.as_ref())?;
+    println!(
+        "Embedded '{}' with {}: {} symbols embedded, {} unchanged, {} stale vectors dropped",
+        name,
+        backend.name(),
+        refresh.embedded,
+        refresh.reused,
+        refresh.removed
+    );
+    Ok(())
+}
+
+/// Opens the embeddings sidecar of the configured database, with sqlite-vss if configured
+fn open_embedding_store(config: &config::Config) -> Result<EmbeddingStore> {
+    let store = EmbeddingStore::open(&database_config(config)?)?;
+    Ok(match &config.embeddings_vss_path {
+        Some(directory) => store.with_vss(directory)?,
+        None => store,
+    })
+}
+
+/// Creates, lists or restores snapshots of an index
+fn snapshot_index(config: &config::Config, action: SnapshotActions) -> Result<()> {
+    let store = SnapshotStore::new(database_config(config)?);
+    match action {
+        SnapshotActions::Create { name, label } => {
+            info!("Snapshotting index '{}'", name);
+            let repository = open_repository(config)?;
+            let index = repository
+                .get_code_index_by_name(&name)?
+                .ok_or_else(|| StorageError::not_found("Index", &name))?;
+            let snapshot = store.create(repository.connection(), &name, label.as_deref())?;
+            repository.record_audit(
+                &AuditEntry::new(
+                    AuditOperation::Backup,
+                    &AuditActor::local_user(),
+                    serde_json::json!({"snapshot": snapshot.id, "snapshot_path": snapshot.path}),
+                )
+                .with_index(index.id, &name),
+            )?;
+            println!(
+                "Snapshot {} of '{}' ({} files, {} symbols, {:.1} MB)",
+                snapshot.id,
+                name,
+                snapshot.total_files,
+                snapshot.total_symbols,
+                snapshot.size_bytes as f64 / (1024.0 * 1024.0)
+            );
+        }
+        SnapshotActions::List { name } => {
+            let snapshots = store.list(name.as_deref())?;
+            if snapshots.is_empty() {
+                println!("No snapshots");
+                return Ok(());
+            }
+            println!("{:<22} {:<20} {:>7} {:>9} {:>9}  Label", "Id", "Index", "Files", "Symbols", "MB");
+            for snapshot in snapshots {
+                println!(
+                    "{:<22} {:<20} {:>7} {:>9} {:>9.1}  {}",
+                    snapshot.id,
+                    snapshot.index_name,
+                    snapshot.total_files,
+                    snapshot.total_symbols,
+                    snapshot.size_bytes as f64 / (1024.0 * 1024.0),
+                    snapshot.label.as_deref().unwrap_or("")
+                );
+            }
+        }
+        SnapshotActions::Restore { name, snapshot, force } => {
+            let snapshot = store.find(&name, snapshot.as_deref())?;
+            info!("Restoring snapshot {} of '{}'", snapshot.id, name);
+            let restored = store.restore(&snapshot, force)?;
+            let repository = open_repository(config)?;
+            let mut entry = AuditEntry::new(
+                AuditOperation::Restore,
+                &AuditActor::local_user(),
+                serde_json::json!({"snapshot": snapshot.id, "rolled_back": restored.rolled_back}),
+            );
+            if let Some(index) = repository.get_code_index_by_name(&name)? {
+                entry = entry.with_index(index.id, &name);
+            }
+            repository.record_audit(&entry)?;
+            println!(
+                "Restored '{}' to snapshot {} ({} files, {} symbols)",
+                name, snapshot.id, snapshot.total_files, snapshot.total_symbols
+            );
+            if !restored.rolled_back.is_empty() {
+                println!("Also rolled back: {}", restored.rolled_back.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the plaintext database with an encrypted copy
+///
+/// The copy is written next to the database and renamed over it, so the
+/// plaintext file never coexists with a half-written replacement.
+fn encrypt_database(config: &config::Config) -> Result<()> {
+    let key = encryption_key(config)?;
+    let database_path = config.database_path();
+    let manager = DatabaseManager::new(DatabaseConfig::new(&database_path))?;
+    if !manager.database_exists() {
+        anyhow::bail!("Database {} does not exist", database_path.display());
+    }
+
+    let mut staging = database_path.clone().into_os_string();
+    staging.push(".encrypting");
+    let staging = std::path::PathBuf::from(staging);
+    {
+        // Recorded first so the entry is part of the encrypted copy
+        let repository = Repository::new(manager.connect()?);
+        repository.record_audit(&AuditEntry::new(
+            AuditOperation::Encrypt,
+            &AuditActor::local_user(),
+            serde_json::json!({"database_path": database_path}),
+        ))?;
+        manager.checkpoint(repository.connection(), CheckpointMode::Truncate)?;
+        export_encrypted(repository.connection(), &staging, &key)?;
+    }
+    std::fs::rename(&staging, &database_path)?;
+    for suffix in ["-wal", "-shm"] {
+        let mut path = database_path.clone().into_os_string();
+        path.push(suffix);
+        let _ = std::fs::remove_file(path);
+    }
+
+    println!("Encrypted {}; set encrypt_database = true to open it", database_path.display());
+    Ok(())
+}
+
+/// Prints the audit trail, newest first
+fn show_audit(config: &config::Config, index_name: Option<&str>, operation: Option<&str>, since: Option<&str>, limit: usize) -> Result<()> {
+    let operation = operation
+        .map(|name| {
+            AuditOperation::parse(name).ok_or_else(|| {
+                let known: Vec<&str> = AuditOperation::all().iter().map(AuditOperation::as_str).collect();
+                StorageError::Validation(format!("Unknown operation '{}' (expected one of: {})", name, known.join(", ")))
+            })
+        })
+        .transpose()?;
+    let since = since
+        .map(|duration| {
+            parse_duration(duration)
+                .map(|age| chrono::Utc::now() - age)
+                .ok_or_else(|| anyhow::anyhow!("Invalid duration: {} (use e.g. 30m, 12h, 7d, 2w)", duration))
+        })
+        .transpose()?;
+
+    let repository = open_repository(config)?;
+    let entries = repository.list_audit_entries(index_name, operation, since, limit)?;
+    if entries.is_empty() {
+        println!("No audited operations");
+        return Ok(());
+    }
+
+    for entry in entries {
+        let session = entry.session_id.map(|session| format!(" [{}]", session)).unwrap_or_default();
+        println!(
+            "{}  {:<14} {:<20} {}{}  {}",
+            entry.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+            entry.operation.as_str(),
+            entry.index_name.as_deref().unwrap_or("-"),
+            entry.actor,
+            session,
+            entry.parameters
+        );
+    }
+    Ok(())
+}
+
+/// Opens the index database, offering to recover it if it fails its integrity check
+fn open_repository(config: &config::Config) -> Result<Repository> {
+    let database_config = database_config(config)?;
+    let recovery = DatabaseRecovery::new(database_config.clone());
+
+    let recovered = if let DatabaseHealth::Corrupt { problems } = recovery.check() {
+        eprintln!("Database {} is damaged:", config.database_path().display());
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        eprint!("Attempt recovery? The damaged file is kept alongside the database. [y/N] ");
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            anyhow::bail!("Database is damaged; recovery declined");
+        }
+
+        let report = recovery.recover()?;
+        match report.strategy {
+            RecoveryStrategy::Salvage => {
+                for (table, rows) in &report.tables_recovered {
+                    println!("Recovered {} rows from {}", rows, table);
+                }
+                for table in &report.tables_lost {
+                    println!("Could not recover {}", table);
+                }
+            }
+            RecoveryStrategy::RestoreBackup => {
+                if let Some(backup) = &report.backup_path {
+                    println!("Restored from backup {}", backup.display());
+                }
+            }
+            RecoveryStrategy::Reset => {
+                println!("Nothing could be salvaged and no backup exists; indices must be recreated");
+            }
+        }
+        println!("Damaged database moved to {}", report.quarantined_path.display());
+        Some(report)
+    } else {
+        None
+    };
+
+    let manager = DatabaseManager::new(database_config)?;
+    let repository = Repository::new(manager.connect()?).with_audit_actor(AuditActor::local_user());
+    if let Some(report) = &recovered {
+        let strategy = match report.strategy {
+            RecoveryStrategy::Salvage => "salvage",
+            RecoveryStrategy::RestoreBackup => "restore_backup",
+            RecoveryStrategy::Reset => "reset",
+        };
+        repository.record_audit(&AuditEntry::new(
+            AuditOperation::Restore,
+            &AuditActor::local_user(),
+            serde_json::json!({
+                "strategy": strategy,
+                "backup_path": report.backup_path,
+                "quarantined_path": report.quarantined_path,
+                "tables_lost": report.tables_lost
+            }),
+        ))?;
+    }
+    Ok(with_slow_query_log(config, repository))
+}
+
+/// Logs queries slower than the configured threshold, unless it is 0
+fn with_slow_query_log(config: &config::Config, repository: Repository) -> Repository {
+    match config.slow_query_threshold_ms {
+        0 => repository,
+        threshold => repository.with_slow_query_threshold(Duration::from_millis(threshold)),
+    }
+}
+
+/// Applies tag changes to an index and prints the resulting tags
+fn tag_index(config: &config::Config, name: &str, set: &[String], remove: &[String]) -> Result<()> {
+    if set.is_empty() && remove.is_empty() {
+        anyhow::bail!("Nothing to do: pass --set key=value or --remove key");
+    }
+
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+
+    for assignment in set {
+        let (key, value) = IndexTag::parse_assignment(assignment).map_err(StorageError::Validation)?;
+        let tag = IndexTag::new(index.id, key, value);
+        repository.set_index_tag(&tag)?;
+    }
+    for key in remove {
+        if !repository.remove_index_tag(&index.id, key)? {
+            println!("Tag '{}' was not set on index '{}'", key, name);
+        }
+    }
+
+    for (key, value) in repository.get_index_tags(&index.id)? {
+        println!("{}={}", key, value);
+    }
+
+    Ok(())
+}
+
+/// A build configuration of an index from `NAME[=VALUE]` defines and `NAME` undefines
+fn build_configuration(index: &CodeIndex, name: &str, define: &[String], undefine: &[String]) -> Result<BuildConfiguration> {
+    let mut configuration = BuildConfiguration::new(index.id, name.to_string());
+    for definition in define {
+        let (name, value) = MacroConfiguration::parse_define(definition).map_err(StorageError::Validation)?;
+        configuration = configuration.with_define(name, value);
+    }
+    for name in undefine {
+        let name = name.trim();
+        configuration = configuration.with_undefine(name.strip_prefix("-U").unwrap_or(name));
+    }
+    configuration.validate().map_err(StorageError::Validation)?;
+    Ok(configuration)
+}
+
+/// Saves or removes a build configuration, then reassigns the index's symbols to its configurations
+fn configure_index(config: &config::Config, name: &str, configuration: &str, define: &[String], undefine: &[String], remove: bool) -> Result<()> {
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+
+    if remove {
+        if !repository.delete_build_configuration(&index.id, configuration)? {
+            return Err(StorageError::not_found("Build configuration", configuration).into());
+        }
+    } else {
+        repository.save_build_configuration(build_configuration(&index, configuration, define, undefine)?)?;
+    }
+    assign_configurations(&repository, &index)?;
+
+    for configuration in repository.list_build_configurations(&index.id)? {
+        let excluded = repository.list_configuration_exclusions(configuration.id.unwrap_or_default())?.len();
+        println!("{}: {} ({} symbols compiled out)", configuration.name, configuration.compile_flags().join(" "), excluded);
+    }
+    Ok(())
+}
+
+/// Prints the symbols of an index matching every `(field, value)` term of the query syntax
+fn query_symbols(config: &config::Config, name: &str, terms: &[(&str, String)], limit: u64, format: ListingFormat) -> Result<()> {
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+
+    let mut query = CodeElementQuery::new().filter(Filter::eq(ElementColumn::IndexId, index.id.to_string()));
+    for (field, value) in terms {
+        query = query.filter(term_filter(field, value).map_err(StorageError::Validation)?);
+    }
+    let query = query.order_by_asc(ElementColumn::FilePath).order_by_asc(ElementColumn::LineNumber);
+    let total = repository.count_code_elements(&query)?;
+
+    let rows: Vec<ElementRow> = repository.query_code_elements(&query.limit(limit))?.iter().map(ElementRow::from).collect();
+    print!("{}", render_rows(&rows, format));
+    if total > limit {
+        // On stderr so JSON and CSV output stays parseable
+        eprintln!("... {} more (raise --limit to see them)", total - limit);
+    }
+    Ok(())
+}
+
+/// Saves, lists, runs, deletes or watches the saved queries of an index
+fn saved_queries(config: &config::Config, name: &str, action: QueryActions) -> Result<()> {
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+
+    match action {
+        QueryActions::Save { name, query, description, watch } => {
+            let mut saved = SavedQuery::new(index.id, name, query).with_watch(watch);
+            if let Some(description) = description {
+                saved = saved.with_description(description);
+            }
+            let saved = repository.save_query(saved)?;
+            println!("Saved '{}': {}", saved.name, saved.query);
+        }
+        QueryActions::ListSaved => {
+            let queries = repository.list_saved_queries(&index.id)?;
+            if queries.is_empty() {
+                println!("No saved queries for index '{}'", index.name);
+            }
+            for saved in queries {
+                let watched = if saved.watched { " [watched]" } else { "" };
+                match saved.description {
+                    Some(description) => println!("{}{}: {}  # {}", saved.name, watched, saved.query, description),
+                    None => println!("{}{}: {}", saved.name, watched, saved.query),
+                }
+            }
+        }
+        QueryActions::RunSaved { name, limit } => {
+            let saved = repository
+                .get_saved_query(&index.id, &name)?
+                .ok_or_else(|| StorageError::not_found("Saved query", &name))?;
+            let query = saved.to_element_query().map_err(StorageError::Validation)?;
+            let total = repository.count_code_elements(&query)?;
+
+            for element in repository.query_code_elements(&query.limit(limit))? {
+                println!(
+                    "{}:{}:{}  {}  {}",
+                    element.file_path,
+                    element.line_number,
+                    element.column_number,
+                    element.symbol_type.as_str(),
+                    element.fully_qualified_name()
+                );
+            }
+            if total > limit {
+                println!("... {} more (raise --limit to see them)", total - limit);
+            }
+        }
+        QueryActions::DeleteSaved { name } => {
+            if !repository.delete_saved_query(&index.id, &name)? {
+                return Err(StorageError::not_found("Saved query", &name).into());
+            }
+            println!("Deleted saved query '{}'", name);
+        }
+        QueryActions::Watch { name } => {
+            if !repository.set_query_watched(&index.id, &name, true)? {
+                return Err(StorageError::not_found("Saved query", &name).into());
+            }
+            let saved = repository
+                .get_saved_query(&index.id, &name)?
+                .ok_or_else(|| StorageError::not_found("Saved query", &name))?;
+            WatchEvaluator::new(&repository).evaluate(&saved)?;
+            println!("Watching '{}'", name);
+        }
+        QueryActions::Unwatch { name } => {
+            if !repository.set_query_watched(&index.id, &name, false)? {
+                return Err(StorageError::not_found("Saved query", &name).into());
+            }
+            println!("Stopped watching '{}'", name);
+        }
+        QueryActions::CheckWatches { fail_on_change } => {
+            let changes = WatchEvaluator::new(&repository).run(&index.id)?;
+            if changes.is_empty() {
+                println!("No watched query changed");
+            }
+            changes.iter().for_each(print_watch_change);
+            if fail_on_change && !changes.is_empty() {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints an alert for a watched query whose results changed, with the symbols added and removed
+fn print_watch_change(change: &WatchChange) {
+    println!(
+        "ALERT {}: {} added, {} removed ({} matching)",
+        change.name,
+        change.added.len(),
+        change.removed.len(),
+        change.total_count
+    );
+    for key in &change.added {
+        println!("  + {}", key);
+    }
+    for key in &change.removed {
+        println!("  - {}", key);
+    }
+}
+
+/// Prints database size, WAL size and per-index counts, optionally followed by slow queries
+fn show_stats(config: &config::Config, checkpoint: bool, slow_queries: Option<usize>, insights: Option<(&str, usize)>) -> Result<()> {
+    let repository = open_repository(config)?;
+    let manager = DatabaseManager::new(database_config(config)?)?;
+
+    if checkpoint {
+        let result = manager.checkpoint(repository.connection(), CheckpointMode::Truncate)?;
+        if result.busy {
+            println!("Checkpoint incomplete: the database is in use by another process");
+        }
+        println!(
+            "Checkpointed WAL: {} KB -> {} KB",
+            result.wal_size_before / 1024,
+            result.wal_size_after / 1024
+        );
+    }
+
+    let info = manager.get_database_info()?;
+    println!("Database: {}", info.database_path.display());
+    println!("Schema version: {}", info.schema_version);
+    println!("Size: {}", info.file_size_human_readable());
+    println!("WAL size: {} KB ({} mode)", info.wal_size_bytes / 1024, info.journal_mode);
+
+    let mut statistics: Vec<_> = repository.get_index_statistics()?.into_values().collect();
+    statistics.sort_by(|a, b| a.name.cmp(&b.name));
+    for stats in statistics {
+        println!(
+            "{}: {} files, {} symbols, {} relationships",
+            stats.name, stats.actual_files, stats.actual_elements, stats.relationships
+        );
+    }
+
+    if let Some(limit) = slow_queries {
+        let queries = repository.list_slow_queries(limit)?;
+        println!();
+        if queries.is_empty() {
+            println!("No slow queries logged (threshold {} ms)", config.slow_query_threshold_ms);
+        }
+        for query in queries {
+            println!(
+                "{:>9.1} ms  {:>6} rows  {}  {}",
+                query.duration_ms,
+                query.rows_returned,
+                query.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+                query.operation
+            );
+            println!("    params: {}", query.params_summary);
+            println!("    sql: {}", query.sql);
+        }
+    }
+
+    if let Some((name, top)) = insights {
+        let index = repository
+            .get_code_index_by_name(name)?
+            .ok_or_else(|| StorageError::not_found("Index", name))?;
+        let insights = repository.get_index_insights(&index.id, top)?;
+        let symbols = |title: &str, ranked: &[RankedSymbol]| {
+            println!();
+            println!("{}:", title);
+            for entry in ranked {
+                let element = &entry.element;
+                println!("  {:>6}  {}  {}:{}", entry.count, element.fully_qualified_name(), element.file_path, element.line_number);
+            }
+        };
+        let files = |title: &str, ranked: &[RankedFile]| {
+            println!();
+            println!("{}:", title);
+            for entry in ranked {
+                println!("  {:>6}  {}", entry.count, entry.file_path);
+            }
+        };
+        symbols("Highest fan-in (distinct referencing symbols)", &insights.fan_in);
+        symbols("Highest fan-out (distinct referenced symbols)", &insights.fan_out);
+        symbols("Largest classes (members)", &insights.largest_classes);
+        files("Most included headers (including files)", &insights.most_included_headers);
+        files("Busiest files (symbols)", &insights.busiest_files);
+    }
+
+    Ok(())
+}
+
+/// Prints what `Repository::verify_index` found, failing if problems remain
+fn verify_index(config: &config::Config, name: &str, repair: bool) -> Result<()> {
+    let repository = open_repository(config)?;
+    let index = repository
+        .get_code_index_by_name(name)?
+        .ok_or_else(|| StorageError::not_found("Index", name))?;
+    let health = repository.verify_index(&index.id, repair)?;
+
+    if health.is_healthy() {
+        println!("Index '{}' is consistent: {} files, {} symbols", name, health.actual_files, health.actual_symbols);
+        return Ok(());
+    }
+    println!("Orphaned symbols:       {}", health.orphaned_elements);
+    println!("Dangling relationships: {}", health.dangling_relationships);
+    println!("Stale file metadata:    {}", health.stale_files);
+    println!(
+        "Recorded totals:        {} files, {} symbols (counted {} files, {} symbols)",
+        health.reported_files, health.reported_symbols, health.actual_files, health.actual_symbols
+    );
+
+    if !health.repaired {
+        anyhow::bail!("Index '{}' is inconsistent; run with --repair to fix it", name);
+    }
+    repository.record_audit(
+        &AuditEntry::new(
+            AuditOperation::RepairIndex,
+            &AuditActor::local_user(),
+            serde_json::json!({
+                "orphaned_elements": health.orphaned_elements,
+                "dangling_relationships": health.dangling_relationships,
+                "stale_files": health.stale_files,
+            }),
+        )
+        .with_index(index.id, name),
+    )?;
+    println!("Repaired index '{}'", name);
+    Ok(())
+}
+
+/// Applies a retention policy built from the command line to the indices of `databases` and prints what was collected
+fn collect_indices(
+    databases: &[config::Config],
+    max_age: Option<&str>,
+    keep: &[String],
+    max_total_mb: Option<u64>,
+    archive_dir: Option<&std::path::Path>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut policy = RetentionPolicy::new();
+    if let Some(max_age) = max_age {
+        let max_age = parse_duration(max_age)
+            .ok_or_else(|| anyhow::anyhow!("Invalid duration: {} (use e.g. 30m, 12h, 7d, 2w)", max_age))?;
+        policy = policy.with_max_age(max_age);
+    }
+    for rule in keep {
+        let rule = RetentionRule::parse(rule).map_err(|e| anyhow::anyhow!(e))?;
+        policy = policy.with_rule(rule.pattern, rule.keep);
+    }
+    if let Some(max_total_mb) = max_total_mb {
+        policy = policy.with_max_total_bytes(max_total_mb * 1024 * 1024);
+    }
+    if archive_dir.is_some() {
+        policy = policy.with_action(RetentionAction::Archive);
+    }
+    if policy.is_empty() {
+        anyhow::bail!("Nothing to do: pass --max-age, --keep or --max-total-mb");
+    }
+
+    let mut repositories = Vec::new();
+    let mut database_size = 0;
+    for config in databases {
+        repositories.push(open_repository(config)?);
+        let manager = DatabaseManager::new(database_config(config)?)?;
+        database_size += manager.get_database_info()?.file_size_bytes.max(0) as u64;
+    }
+    let Some((first, others)) = repositories.split_first() else {
+        return Ok(());
+    };
+    let mut collector = others.iter().fold(GarbageCollector::new(first, policy), |collector, repository| collector.with_repository(repository));
+    if let Some(archive_dir) = archive_dir {
+        collector = collector.with_archive_dir(archive_dir);
+    }
+    let report = collector.run(database_size, dry_run)?;
+    let deleted: Vec<_> = report
+        .candidates
+        .iter()
+        .filter(|candidate| !dry_run && (candidate.action == RetentionAction::Delete || candidate.archive_path.is_some()))
+        .collect();
+    for config in databases.iter().filter(|config| EmbeddingStore::sidecar_path(&config.database_path()).exists()) {
+        let store = open_embedding_store(config)?;
+        for candidate in &deleted {
+            store.remove(&candidate.index_id)?;
+        }
+    }
+
+    if report.candidates.is_empty() {
+        println!("No indices to collect");
+        return Ok(());
+    }
+
+    for candidate in &report.candidates {
+        let action = match (candidate.action, dry_run) {
+            (RetentionAction::Archive, true) => "Would archive",
+            (RetentionAction::Archive, false) => "Archived",
+            (RetentionAction::Delete, true) => "Would delete",
+            (RetentionAction::Delete, false) => "Deleted",
+        };
+        println!(
+            "{} '{}' (last updated {}, ~{} KB): {:?}",
+            action,
+            candidate.name,
+            candidate.updated_at.format("%Y-%m-%d"),
+            candidate.estimated_bytes / 1024,
+            candidate.reason
+        );
+        if let Some(path) = &candidate.archive_path {
+            println!("  -> {}", path.display());
+        }
+    }
+
+    Ok(())
+}